@@ -0,0 +1,137 @@
+//! Bloom filters over single columns, which tell an equality lookup on a
+//! column without an index that no row can match, without a scan.
+//!
+//! A filter never misses a value the column holds, but may claim a value
+//! it does not hold, more often as it fills. It is rebuilt twice as large
+//! once it holds as many values as it was sized for. The values of rows
+//! deleted or changed stay in it until then, or until VACUUM rebuilds it.
+//!
+//! The bits are not saved with the table: the hash behind them may change
+//! from one Rust release to the next, so a filter read back is rebuilt from
+//! the rows, and until then lets every lookup through.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{EngineError, Row, Table, Value};
+
+/// Bits set aside per value, which with [`HASHES`] hash functions gives
+/// about one false positive in a hundred lookups at full capacity.
+const BITS_PER_VALUE: usize = 10;
+const HASHES: u64 = 7;
+/// Capacity of a filter on an empty table.
+const MIN_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BloomFilter {
+    /// Empty in a filter read back and not yet rebuilt.
+    #[serde(skip)]
+    bits: Vec<u64>,
+    /// Number of values the filter was sized for.
+    capacity: usize,
+    /// Number of values added, counting repeats.
+    len: usize,
+}
+
+impl BloomFilter {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        Self {
+            bits: vec![0; (capacity * BITS_PER_VALUE).div_ceil(64)],
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// The bits a value sets, by double hashing.
+    fn positions(&self, value: &Value) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let h1 = hasher.finish();
+        hasher.write_u8(0xff);
+        let h2 = hasher.finish() | 1;
+        let bits = self.bits.len() as u64 * 64;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    fn insert(&mut self, value: &Value) {
+        for pos in self.positions(value) {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+        self.len += 1;
+    }
+
+    /// Whether the column may hold `value`; `false` means it does not.
+    pub fn may_contain(&self, value: &Value) -> bool {
+        self.bits.is_empty()
+            || self
+                .positions(value)
+                .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// A filter holding the values of the column at `col_idx` in `table`,
+    /// with room for at least as many again.
+    fn build(table: &Table, col_idx: usize, capacity: usize) -> Self {
+        let mut filter = Self::new(capacity.max(table.row_count() * 2));
+        for value in table.column_values(col_idx) {
+            filter.add(value);
+        }
+        filter
+    }
+
+    /// Adds a stored value. NULLs are left out, since no lookup is for one.
+    fn add(&mut self, value: &Value) {
+        if !value.is_null() {
+            self.insert(value);
+        }
+    }
+}
+
+impl Table {
+    /// Keeps a bloom filter on `column`, replacing any it had. Fails if the
+    /// column does not exist.
+    pub fn add_bloom_filter(&mut self, column: &str) -> Result<(), EngineError> {
+        let col_idx = self.column_positions(&[column])?[0];
+        let filter = BloomFilter::build(self, col_idx, 0);
+        self.bloom_filters.insert(column.to_string(), filter);
+        Ok(())
+    }
+
+    /// Stops keeping the bloom filter on `column`, returning whether there
+    /// was one.
+    pub fn drop_bloom_filter(&mut self, column: &str) -> bool {
+        self.bloom_filters.remove(column).is_some()
+    }
+
+    /// Adds a row about to be appended to the bloom filters, growing any
+    /// that are full.
+    pub(crate) fn add_to_bloom_filters(&mut self, row: &Row) {
+        let mut bloom_filters = std::mem::take(&mut self.bloom_filters);
+        for (column, filter) in bloom_filters.iter_mut() {
+            let Some(col_idx) = self.columns.iter().position(|c| &c.name == column) else {
+                continue;
+            };
+            if filter.len >= filter.capacity {
+                *filter = BloomFilter::build(self, col_idx, filter.capacity * 2);
+            } else if filter.bits.is_empty() {
+                *filter = BloomFilter::build(self, col_idx, filter.capacity);
+            }
+            filter.add(&row[col_idx]);
+        }
+        self.bloom_filters = bloom_filters;
+    }
+
+    /// Rebuilds the bloom filters from the rows, after rows were removed or
+    /// changed in place.
+    pub(crate) fn rebuild_bloom_filters(&mut self) {
+        let mut bloom_filters = std::mem::take(&mut self.bloom_filters);
+        for (column, filter) in bloom_filters.iter_mut() {
+            if let Some(col_idx) = self.columns.iter().position(|c| &c.name == column) {
+                *filter = BloomFilter::build(self, col_idx, filter.capacity);
+            }
+        }
+        self.bloom_filters = bloom_filters;
+    }
+}
@@ -0,0 +1,332 @@
+//! A compact binary encoding for values and rows, independent of serde, for
+//! use wherever rows are written to disk or sent over the wire.
+//!
+//! An encoded row is a format version byte, the number of values as a
+//! varint, then each value as a one-byte tag followed by its payload.
+//! Integers are zigzag LEB128 varints, text and JSON are length-prefixed
+//! UTF-8, and floats are 8 little-endian bytes.
+
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::custom;
+use crate::decimal::Decimal;
+use crate::engine::{EnumValue, Row, Value};
+use crate::temporal::Interval;
+
+/// Version written at the start of every encoding produced by this module.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Error returned when bytes are not a valid encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// The input was written by an unknown version of the format.
+    UnsupportedVersion(u8),
+    /// A value started with an unknown tag byte.
+    InvalidTag(u8),
+    /// A payload is malformed, e.g. text that is not UTF-8 or an integer
+    /// out of range for its type.
+    InvalidData(&'static str),
+    /// Bytes were left over after the last value.
+    TrailingBytes,
+}
+
+const NULL: u8 = 0;
+const INT: u8 = 1;
+const BIG_INT: u8 = 2;
+const FLOAT: u8 = 3;
+const DECIMAL: u8 = 4;
+const TEXT: u8 = 5;
+const FALSE: u8 = 6;
+const TRUE: u8 = 7;
+const DATE: u8 = 8;
+const TIME: u8 = 9;
+const TIMESTAMP: u8 = 10;
+const INTERVAL: u8 = 11;
+const UUID: u8 = 12;
+const JSON: u8 = 13;
+const LIST: u8 = 14;
+const ENUM: u8 = 15;
+const CUSTOM: u8 = 16;
+
+fn write_varint(out: &mut Vec<u8>, mut n: u128) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, n: i128) {
+    write_varint(out, ((n << 1) ^ (n >> 127)) as u128);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u128);
+    out.extend_from_slice(bytes);
+}
+
+/// Appends the tagged encoding of `value`, without a version byte.
+pub(crate) fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        // A NULL's column type is not kept; every NULL decodes as untyped.
+        Value::Null | Value::TypedNull(_) => out.push(NULL),
+        Value::Int(n) => {
+            out.push(INT);
+            write_signed(out, *n as i128);
+        }
+        Value::BigInt(n) => {
+            out.push(BIG_INT);
+            write_signed(out, *n);
+        }
+        Value::Float(f) => {
+            out.push(FLOAT);
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::Decimal(d) => {
+            out.push(DECIMAL);
+            write_signed(out, d.mantissa());
+            out.push(d.scale());
+        }
+        Value::Text(s) => {
+            out.push(TEXT);
+            write_bytes(out, s.as_bytes());
+        }
+        Value::Bool(b) => out.push(if *b { TRUE } else { FALSE }),
+        Value::Date(d) => {
+            out.push(DATE);
+            write_signed(out, *d as i128);
+        }
+        Value::Time(t) => {
+            out.push(TIME);
+            write_signed(out, *t as i128);
+        }
+        Value::Timestamp(ts) => {
+            out.push(TIMESTAMP);
+            write_signed(out, *ts as i128);
+        }
+        Value::Interval(iv) => {
+            out.push(INTERVAL);
+            write_signed(out, iv.months as i128);
+            write_signed(out, iv.micros as i128);
+        }
+        Value::Uuid(u) => {
+            out.push(UUID);
+            out.extend_from_slice(u.as_bytes());
+        }
+        Value::Json(j) => {
+            out.push(JSON);
+            write_bytes(out, j.to_string().as_bytes());
+        }
+        Value::List(items) => {
+            out.push(LIST);
+            write_varint(out, items.len() as u128);
+            for item in items {
+                write_value(out, item);
+            }
+        }
+        // Enum values carry their labels so that they decode on their own.
+        Value::Enum(e) => {
+            out.push(ENUM);
+            write_varint(out, e.index() as u128);
+            write_varint(out, e.labels().len() as u128);
+            for label in e.labels().iter() {
+                write_bytes(out, label.as_bytes());
+            }
+        }
+        // Custom values are their type name and JSON form; decoding them
+        // requires the type to be registered.
+        Value::Custom(c) => {
+            out.push(CUSTOM);
+            write_bytes(out, c.type_name().as_bytes());
+            let json = c.to_json().unwrap_or_default();
+            write_bytes(out, json.to_string().as_bytes());
+        }
+    }
+}
+
+/// Reads encoded values from the front of a byte slice.
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.input.len() < n {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (head, tail) = self.input.split_at(n);
+        self.input = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u128, DecodeError> {
+        let mut n = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u128) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(DecodeError::InvalidData("varint is too long"))
+    }
+
+    fn signed(&mut self) -> Result<i128, DecodeError> {
+        let n = self.varint()?;
+        Ok((n >> 1) as i128 ^ -((n & 1) as i128))
+    }
+
+    fn int<T: TryFrom<i128>>(&mut self) -> Result<T, DecodeError> {
+        T::try_from(self.signed()?).map_err(|_| DecodeError::InvalidData("integer out of range"))
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        let n = self.varint()?;
+        // A length can never exceed the bytes that are left.
+        usize::try_from(n)
+            .ok()
+            .filter(|&n| n <= self.input.len())
+            .ok_or(DecodeError::UnexpectedEnd)
+    }
+
+    fn text(&mut self) -> Result<String, DecodeError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| DecodeError::InvalidData("text is not UTF-8"))
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        let tag = self.byte()?;
+        Ok(match tag {
+            NULL => Value::Null,
+            INT => Value::Int(self.int()?),
+            BIG_INT => Value::BigInt(self.signed()?),
+            FLOAT => {
+                let bytes = self.take(8)?.try_into().unwrap_or_default();
+                Value::Float(f64::from_le_bytes(bytes))
+            }
+            DECIMAL => {
+                let mantissa = self.signed()?;
+                let scale = self.byte()?;
+                Value::Decimal(
+                    Decimal::new(mantissa, scale)
+                        .ok_or(DecodeError::InvalidData("decimal out of range"))?,
+                )
+            }
+            TEXT => Value::Text(self.text()?),
+            FALSE => Value::Bool(false),
+            TRUE => Value::Bool(true),
+            DATE => Value::Date(self.int()?),
+            TIME => Value::Time(self.int()?),
+            TIMESTAMP => Value::Timestamp(self.int()?),
+            INTERVAL => Value::Interval(Interval::new(self.int()?, self.int()?)),
+            UUID => Value::Uuid(Uuid::from_slice(self.take(16)?).unwrap_or_default()),
+            JSON => Value::Json(
+                serde_json::from_str(&self.text()?)
+                    .map_err(|_| DecodeError::InvalidData("invalid JSON document"))?,
+            ),
+            LIST => {
+                let count = self.len()?;
+                let mut items = Vec::with_capacity(count);
+                for _ in 0..count {
+                    items.push(self.value()?);
+                }
+                Value::List(items)
+            }
+            ENUM => {
+                let index = usize::try_from(self.varint()?)
+                    .map_err(|_| DecodeError::InvalidData("enum index out of range"))?;
+                let count = self.len()?;
+                let labels = (0..count)
+                    .map(|_| self.text())
+                    .collect::<Result<Arc<[String]>, _>>()?;
+                let label = labels
+                    .get(index)
+                    .ok_or(DecodeError::InvalidData("enum index out of range"))?
+                    .clone();
+                Value::Enum(
+                    EnumValue::new(&labels, &label)
+                        .ok_or(DecodeError::InvalidData("invalid enum labels"))?,
+                )
+            }
+            CUSTOM => {
+                let name = self.text()?;
+                let json = serde_json::from_str(&self.text()?)
+                    .map_err(|_| DecodeError::InvalidData("invalid JSON document"))?;
+                Value::Custom(
+                    custom::decode(&name, json)
+                        .ok_or(DecodeError::InvalidData("unknown or invalid custom type"))?,
+                )
+            }
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        })
+    }
+
+    fn version(&mut self) -> Result<(), DecodeError> {
+        match self.byte()? {
+            FORMAT_VERSION => Ok(()),
+            version => Err(DecodeError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn finish(&self) -> Result<(), DecodeError> {
+        if self.input.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingBytes)
+        }
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, the checksum every file format of
+/// the crate stores beside its data.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn encode_value(value: &Value) -> Vec<u8> {
+    let mut out = vec![FORMAT_VERSION];
+    write_value(&mut out, value);
+    out
+}
+
+/// Decodes bytes produced by [`encode_value`].
+pub fn decode_value(bytes: &[u8]) -> Result<Value, DecodeError> {
+    let mut reader = Reader { input: bytes };
+    reader.version()?;
+    let value = reader.value()?;
+    reader.finish()?;
+    Ok(value)
+}
+
+pub fn encode_row(row: &Row) -> Vec<u8> {
+    let mut out = vec![FORMAT_VERSION];
+    write_varint(&mut out, row.len() as u128);
+    for value in row {
+        write_value(&mut out, value);
+    }
+    out
+}
+
+/// Decodes bytes produced by [`encode_row`].
+pub fn decode_row(bytes: &[u8]) -> Result<Row, DecodeError> {
+    let mut reader = Reader { input: bytes };
+    reader.version()?;
+    let count = reader.len()?;
+    let mut row = Vec::with_capacity(count);
+    for _ in 0..count {
+        row.push(reader.value()?);
+    }
+    reader.finish()?;
+    Ok(row)
+}
@@ -0,0 +1,731 @@
+//! Tables laid out by column rather than by row, chosen with `CREATE TABLE
+//! ... WITH (layout = 'column')`.
+//!
+//! A column table keeps one vector of values per column instead of one per
+//! row, so that a row costs no allocation of its own, and a scan builds
+//! rows holding only the columns its query reads. The rest of the engine
+//! still sees whole rows, built when asked for; changing a row rewrites its
+//! entry in each column.
+//!
+//! A compressed column table, `WITH (layout = 'column', compression =
+//! 'on')`, keeps each distinct text of a text column once, and every row
+//! only the position of its text among them.
+//!
+//! A table can keep its long texts out of line, `WITH (overflow = 'n')`: a
+//! text of more than `n` bytes goes to an overflow area, which clones of
+//! the table share and which holds each such text once, and the row keeps
+//! only a handle to it. A column table keeps an area per column; a row
+//! table keeps one for all its columns, with NULL in each row where a text
+//! went out. A scan leaves those texts out of the rows it builds until it
+//! knows which rows match, unless its condition reads them, and an index
+//! over such a column keeps only the first `n` characters of each key;
+//! see [`Table::set_overflow`].
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::mem;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::fnv1a;
+use crate::engine::{Column, Engine, EngineError, Row, Table, Value, ValueType};
+use crate::index::value_size;
+use crate::storage::TableStorage;
+
+/// How a table stores its rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Layout {
+    /// A vector per row.
+    #[default]
+    Row,
+    /// A vector per column.
+    Column,
+}
+
+impl Layout {
+    /// The name `WITH (layout = ...)` takes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Row => "row",
+            Layout::Column => "column",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name.to_ascii_lowercase().as_str() {
+            "row" => Some(Layout::Row),
+            "column" => Some(Layout::Column),
+            _ => None,
+        }
+    }
+}
+
+/// The values of one column of a column table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Values {
+    Plain(Vec<Value>),
+    /// Each distinct text once, with the position of each row's value
+    /// among them.
+    Dictionary(Dictionary),
+    /// Long texts in an overflow area, with a handle to them in each row
+    /// holding one.
+    Overflow(Overflow),
+}
+
+/// Texts no row holds any more stay among the words until the column is
+/// next rebuilt, as a DELETE does.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Dictionary {
+    words: Vec<Value>,
+    codes: Vec<u32>,
+    /// Position of each text in `words`; rebuilt when empty, as after
+    /// loading.
+    #[serde(skip)]
+    lookup: HashMap<String, u32>,
+}
+
+impl Dictionary {
+    /// The code for `value`, adding it to the words if it is new. Values
+    /// other than text are not shared.
+    fn code(&mut self, value: Value) -> u32 {
+        if self.lookup.is_empty() && !self.words.is_empty() {
+            for (code, word) in self.words.iter().enumerate() {
+                if let Value::Text(text) = word {
+                    self.lookup.entry(text.clone()).or_insert(code as u32);
+                }
+            }
+        }
+        if let Value::Text(text) = &value {
+            if let Some(&code) = self.lookup.get(text) {
+                return code;
+            }
+            self.lookup.insert(text.clone(), self.words.len() as u32);
+        }
+        self.words.push(value);
+        self.words.len() as u32 - 1
+    }
+}
+
+/// A value of an overflow column, as the row holds it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Cell {
+    Inline(Value),
+    /// Position of the text in the overflow area.
+    Handle(u32),
+}
+
+/// Texts no row holds any more stay in the area until the column is next
+/// rebuilt, as a DELETE does.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Overflow {
+    /// Texts of more bytes than this go to the area.
+    threshold: usize,
+    cells: Vec<Cell>,
+    area: Vec<Arc<Value>>,
+    /// Handles of the texts in the area by their hash; rebuilt when empty,
+    /// as after loading.
+    #[serde(skip)]
+    lookup: HashMap<u64, Vec<u32>>,
+}
+
+impl Overflow {
+    /// The cell for `value`, moving a long text to the area unless it
+    /// holds the same text already.
+    fn cell(&mut self, value: Value) -> Cell {
+        let text = match &value {
+            Value::Text(text) if text.len() > self.threshold => text,
+            _ => return Cell::Inline(value),
+        };
+        if self.lookup.is_empty() && !self.area.is_empty() {
+            for (handle, stored) in self.area.iter().enumerate() {
+                if let Value::Text(stored) = &**stored {
+                    let hash = fnv1a(stored.as_bytes());
+                    self.lookup.entry(hash).or_default().push(handle as u32);
+                }
+            }
+        }
+        let hash = fnv1a(text.as_bytes());
+        let handles = self.lookup.entry(hash).or_default();
+        if let Some(&handle) = handles.iter().find(|&&h| *self.area[h as usize] == value) {
+            return Cell::Handle(handle);
+        }
+        handles.push(self.area.len() as u32);
+        self.area.push(Arc::new(value));
+        Cell::Handle(self.area.len() as u32 - 1)
+    }
+
+    fn value<'a>(&'a self, cell: &'a Cell) -> &'a Value {
+        match cell {
+            Cell::Inline(value) => value,
+            Cell::Handle(handle) => &self.area[*handle as usize],
+        }
+    }
+}
+
+/// The long texts of a row table with an overflow threshold, kept out of
+/// its rows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Spilled {
+    /// For each row, the positions of its columns whose texts are in the
+    /// area, with their handles.
+    handles: Vec<Vec<(u32, u32)>>,
+    /// The area, kept as that of an overflow column; its cells stay empty.
+    texts: Overflow,
+}
+
+impl Spilled {
+    /// Moves the long texts in the text columns of `row` to the area,
+    /// leaving NULL in their place, and returns their columns and handles.
+    fn split(&mut self, columns: &[Column], row: &mut Row) -> Vec<(u32, u32)> {
+        let mut handles = Vec::new();
+        for (col_idx, value) in row.iter_mut().enumerate() {
+            if !columns
+                .get(col_idx)
+                .is_some_and(|c| holds_text(&c.col_type))
+            {
+                continue;
+            }
+            match self.texts.cell(mem::replace(value, Value::Null)) {
+                Cell::Inline(inline) => *value = inline,
+                Cell::Handle(handle) => handles.push((col_idx as u32, handle)),
+            }
+        }
+        handles
+    }
+
+    /// The text out of line at `col_idx` of the row at `row_idx`, if any.
+    fn get(&self, row_idx: usize, col_idx: usize) -> Option<&Value> {
+        let handles = self.handles.get(row_idx)?;
+        let &(_, handle) = handles.iter().find(|&&(c, _)| c as usize == col_idx)?;
+        Some(&self.texts.area[handle as usize])
+    }
+
+    /// Puts the texts out of line of the row at `row_idx` back in `row`,
+    /// in the columns `reads` takes.
+    fn fill(&self, row_idx: usize, row: &mut Row, reads: &dyn Fn(usize) -> bool) {
+        for &(col_idx, handle) in self.handles.get(row_idx).into_iter().flatten() {
+            if reads(col_idx as usize) {
+                row[col_idx as usize] = (*self.texts.area[handle as usize]).clone();
+            }
+        }
+    }
+
+    /// Rough number of bytes the handles and the area take up.
+    fn memory(&self) -> usize {
+        let handles = self.handles.iter().map(|h| mem::size_of_val(h.as_slice()));
+        handles.sum::<usize>() + self.texts.area.iter().map(|v| value_size(v)).sum::<usize>()
+    }
+}
+
+/// Whether a column of `col_type` holds texts that can go out of line.
+fn holds_text(col_type: &ValueType) -> bool {
+    matches!(col_type, ValueType::Text | ValueType::Varchar(_))
+}
+
+impl Values {
+    fn new(compressed: bool, overflow: Option<usize>, col_type: &ValueType) -> Self {
+        let text = holds_text(col_type);
+        if compressed && (text || matches!(col_type, ValueType::Enum(_))) {
+            Values::Dictionary(Dictionary::default())
+        } else if let (Some(threshold), true) = (overflow, text) {
+            Values::Overflow(Overflow {
+                threshold,
+                ..Overflow::default()
+            })
+        } else {
+            Values::Plain(Vec::new())
+        }
+    }
+
+    fn get(&self, row_idx: usize) -> &Value {
+        match self {
+            Values::Plain(values) => &values[row_idx],
+            Values::Dictionary(d) => &d.words[d.codes[row_idx] as usize],
+            Values::Overflow(o) => o.value(&o.cells[row_idx]),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Value> + '_> {
+        match self {
+            Values::Plain(values) => Box::new(values.iter()),
+            Values::Dictionary(d) => Box::new(d.codes.iter().map(|&c| &d.words[c as usize])),
+            Values::Overflow(o) => Box::new(o.cells.iter().map(|cell| o.value(cell))),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        match self {
+            Values::Plain(values) => values.push(value),
+            Values::Dictionary(d) => {
+                let code = d.code(value);
+                d.codes.push(code);
+            }
+            Values::Overflow(o) => {
+                let cell = o.cell(value);
+                o.cells.push(cell);
+            }
+        }
+    }
+
+    fn replace(&mut self, row_idx: usize, value: Value) -> Value {
+        match self {
+            Values::Plain(values) => std::mem::replace(&mut values[row_idx], value),
+            Values::Dictionary(d) => {
+                let old = d.words[d.codes[row_idx] as usize].clone();
+                d.codes[row_idx] = d.code(value);
+                old
+            }
+            Values::Overflow(o) => {
+                let cell = o.cell(value);
+                match std::mem::replace(&mut o.cells[row_idx], cell) {
+                    Cell::Inline(old) => old,
+                    Cell::Handle(handle) => (*o.area[handle as usize]).clone(),
+                }
+            }
+        }
+    }
+
+    /// Rough number of bytes the values take up.
+    fn memory(&self) -> usize {
+        match self {
+            Values::Plain(values) => values.iter().map(value_size).sum(),
+            Values::Dictionary(d) => {
+                d.words.iter().map(value_size).sum::<usize>()
+                    + d.codes.len() * mem::size_of::<u32>()
+            }
+            Values::Overflow(o) => {
+                let cells = o.cells.iter().map(|cell| match cell {
+                    Cell::Inline(value) => value_size(value),
+                    Cell::Handle(_) => mem::size_of::<Cell>(),
+                });
+                cells.sum::<usize>() + o.area.iter().map(|v| value_size(v)).sum::<usize>()
+            }
+        }
+    }
+
+    fn into_values(self) -> Vec<Value> {
+        match self {
+            Values::Plain(values) => values,
+            Values::Dictionary(d) => d
+                .codes
+                .iter()
+                .map(|&c| d.words[c as usize].clone())
+                .collect(),
+            Values::Overflow(o) => o.cells.iter().map(|cell| o.value(cell).clone()).collect(),
+        }
+    }
+}
+
+/// The rows of a column table, one vector per column. Text columns of a
+/// compressed table are dictionary encoded, and those of a table with an
+/// overflow threshold keep their long texts out of line.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ColumnStore {
+    columns: Vec<Values>,
+    len: usize,
+}
+
+impl ColumnStore {
+    fn from_rows(
+        columns: &[Column],
+        compressed: bool,
+        overflow: Option<usize>,
+        rows: Vec<Row>,
+    ) -> Self {
+        let mut store = Self {
+            columns: columns
+                .iter()
+                .map(|c| Values::new(compressed, overflow, &c.col_type))
+                .collect(),
+            len: 0,
+        };
+        rows.into_iter().for_each(|row| store.push(row));
+        store
+    }
+
+    fn push(&mut self, row: Row) {
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.push(value);
+        }
+        self.len += 1;
+    }
+
+    fn row(&self, row_idx: usize) -> Row {
+        self.columns
+            .iter()
+            .map(|c| c.get(row_idx).clone())
+            .collect()
+    }
+
+    fn replace(&mut self, row_idx: usize, row: Row) -> Row {
+        self.columns
+            .iter_mut()
+            .zip(row)
+            .map(|(column, value)| column.replace(row_idx, value))
+            .collect()
+    }
+
+    fn into_rows(self) -> Vec<Row> {
+        let mut rows = vec![Vec::with_capacity(self.columns.len()); self.len];
+        for column in self.columns {
+            for (row, value) in rows.iter_mut().zip(column.into_values()) {
+                row.push(value);
+            }
+        }
+        rows
+    }
+}
+
+impl Table {
+    /// Stores the rows in `layout`, moving them if it is not the table's.
+    /// A table moved to the row layout is no longer compressed.
+    pub fn set_layout(&mut self, layout: Layout) {
+        if layout == self.layout {
+            return;
+        }
+        let rows = self.take_rows();
+        self.layout = layout;
+        self.compressed &= layout == Layout::Column;
+        self.set_rows(rows);
+    }
+
+    /// Turns dictionary encoding of the text columns on or off. Fails for
+    /// a table with the row layout, whose rows are kept as they are.
+    pub fn set_compressed(&mut self, compressed: bool) -> Result<(), EngineError> {
+        if compressed && self.layout == Layout::Row {
+            return Err(EngineError::InvalidOperation(
+                "only a table with the column layout can be compressed".to_string(),
+            ));
+        }
+        if compressed != self.compressed {
+            let rows = self.take_rows();
+            self.compressed = compressed;
+            self.set_rows(rows);
+        }
+        Ok(())
+    }
+
+    /// Keeps the texts of more than `threshold` bytes in the text columns
+    /// out of line, or, for `None`, every text inline; see the [module
+    /// docs](self). The text columns of a compressed table keep each text
+    /// once in their dictionary instead.
+    ///
+    /// Indexes made by CREATE INDEX afterwards over just one such column,
+    /// and not unique, keep the first `threshold` characters of each key,
+    /// as `column(threshold)` would.
+    pub fn set_overflow(&mut self, threshold: Option<usize>) -> Result<(), EngineError> {
+        if threshold != self.overflow {
+            let rows = self.take_rows();
+            self.overflow = threshold;
+            self.set_rows(rows);
+        }
+        Ok(())
+    }
+
+    /// Whether the column at `col_idx` keeps its long texts out of line.
+    pub(crate) fn out_of_line(&self, col_idx: usize) -> bool {
+        match self.layout {
+            Layout::Row => {
+                self.overflow.is_some()
+                    && self
+                        .columns
+                        .get(col_idx)
+                        .is_some_and(|c| holds_text(&c.col_type))
+            }
+            Layout::Column => {
+                matches!(self.store.columns.get(col_idx), Some(Values::Overflow(_)))
+            }
+        }
+    }
+
+    /// Rough number of bytes the rows take up, counting what their values
+    /// own.
+    pub fn row_memory(&self) -> usize {
+        match self.layout {
+            Layout::Row => {
+                self.storage
+                    .rows()
+                    .iter()
+                    .map(|row| mem::size_of::<Row>() + row.iter().map(value_size).sum::<usize>())
+                    .sum::<usize>()
+                    + self.spilled.memory()
+            }
+            Layout::Column => self.store.columns.iter().map(Values::memory).sum(),
+        }
+    }
+
+    /// Rough number of bytes the rows and index entries take up.
+    pub(crate) fn memory(&self) -> usize {
+        self.row_memory()
+            + self
+                .indices
+                .values()
+                .map(|index| index.stats("").memory)
+                .sum::<usize>()
+    }
+
+    /// Number of rows in the table.
+    pub fn row_count(&self) -> usize {
+        match self.layout {
+            Layout::Row => self.storage.rows().len(),
+            Layout::Column => self.store.len,
+        }
+    }
+
+    /// The row at `row_idx`, borrowed from a row table or built from the
+    /// columns of a column table.
+    pub fn row(&self, row_idx: usize) -> Cow<'_, Row> {
+        match self.layout {
+            Layout::Row => {
+                let row = &self.storage.rows()[row_idx];
+                match self.spilled.handles.get(row_idx) {
+                    Some(handles) if !handles.is_empty() => {
+                        let mut row = row.clone();
+                        self.spilled.fill(row_idx, &mut row, &|_| true);
+                        Cow::Owned(row)
+                    }
+                    _ => Cow::Borrowed(row),
+                }
+            }
+            Layout::Column => Cow::Owned(self.store.row(row_idx)),
+        }
+    }
+
+    /// Every row, in table order.
+    pub fn all_rows(&self) -> Cow<'_, [Row]> {
+        match self.layout {
+            Layout::Row => self.stored_rows(&|_| true),
+            Layout::Column => Cow::Owned((0..self.store.len).map(|i| self.store.row(i)).collect()),
+        }
+    }
+
+    /// The rows of a row table, borrowed unless it holds texts out of line,
+    /// which are put back in the columns `reads` takes.
+    fn stored_rows(&self, reads: &dyn Fn(usize) -> bool) -> Cow<'_, [Row]> {
+        let rows = self.storage.rows();
+        if self.spilled.handles.iter().all(Vec::is_empty) {
+            return Cow::Borrowed(rows);
+        }
+        let mut rows = rows.to_vec();
+        for (row_idx, row) in rows.iter_mut().enumerate() {
+            self.spilled.fill(row_idx, row, reads);
+        }
+        Cow::Owned(rows)
+    }
+
+    /// Every row, with the values of the columns `reads` refuses left NULL
+    /// in a column table, which then copies only the columns read, and the
+    /// texts a row table keeps out of line left NULL in those columns.
+    pub(crate) fn rows_reading(&self, reads: &dyn Fn(&str) -> bool) -> Cow<'_, [Row]> {
+        if self.layout == Layout::Row {
+            return self.stored_rows(&|col_idx| reads(&self.columns[col_idx].name));
+        }
+        let mut rows = vec![Vec::with_capacity(self.columns.len()); self.store.len];
+        for (column, values) in self.columns.iter().zip(&self.store.columns) {
+            if reads(&column.name) {
+                for (row, value) in rows.iter_mut().zip(values.iter()) {
+                    row.push(value.clone());
+                }
+            } else {
+                rows.iter_mut().for_each(|row| row.push(Value::Null));
+            }
+        }
+        Cow::Owned(rows)
+    }
+
+    /// Sets the values of the columns `reads` takes in the rows at `ids`,
+    /// as [`Table::rows_reading`] built them, for reading the texts of
+    /// overflow columns only once a scan knows the rows it keeps.
+    pub(crate) fn read_columns(
+        &self,
+        rows: &mut [Row],
+        ids: &[usize],
+        reads: &dyn Fn(&str) -> bool,
+    ) {
+        if self.layout == Layout::Row {
+            let reads = |col_idx: usize| reads(&self.columns[col_idx].name);
+            for &row_idx in ids {
+                self.spilled.fill(row_idx, &mut rows[row_idx], &reads);
+            }
+            return;
+        }
+        for (col_idx, column) in self.columns.iter().enumerate() {
+            if !reads(&column.name) {
+                continue;
+            }
+            let values = &self.store.columns[col_idx];
+            for &row_idx in ids {
+                rows[row_idx][col_idx] = values.get(row_idx).clone();
+            }
+        }
+    }
+
+    /// The values of the column at `col_idx`, in table order.
+    pub(crate) fn column_values(&self, col_idx: usize) -> Box<dyn Iterator<Item = &Value> + '_> {
+        match self.layout {
+            Layout::Row => Box::new(self.storage.rows().iter().enumerate().map(
+                move |(row_idx, row)| self.spilled.get(row_idx, col_idx).unwrap_or(&row[col_idx]),
+            )),
+            Layout::Column => Box::new(self.store.columns[col_idx].iter()),
+        }
+    }
+
+    /// Appends a row without any check; see [`Table::insert`].
+    pub(crate) fn push_row(&mut self, mut row: Row) {
+        self.segment_push(&row);
+        self.note_inserted();
+        match self.layout {
+            Layout::Row => {
+                if self.overflow.is_some() {
+                    let spilled = Arc::make_mut(&mut self.spilled);
+                    let handles = spilled.split(&self.columns, &mut row);
+                    spilled.handles.push(handles);
+                }
+                self.storage.insert(row)
+            }
+            Layout::Column => Arc::make_mut(&mut self.store).push(row),
+        }
+    }
+
+    /// Puts `row` at `row_idx` without any check, returning the row that
+    /// was there.
+    pub(crate) fn replace_row(&mut self, row_idx: usize, mut row: Row) -> Row {
+        let old = match self.layout {
+            Layout::Row if self.overflow.is_some() => {
+                let spilled = Arc::make_mut(&mut self.spilled);
+                let handles = spilled.split(&self.columns, &mut row);
+                let mut old = self.storage.replace(row_idx, row);
+                spilled.fill(row_idx, &mut old, &|_| true);
+                spilled.handles[row_idx] = handles;
+                old
+            }
+            Layout::Row => self.storage.replace(row_idx, row),
+            Layout::Column => Arc::make_mut(&mut self.store).replace(row_idx, row),
+        };
+        self.segment_replace(row_idx, &old);
+        old
+    }
+
+    /// Deletes the rows at `doomed` and returns them, in table order,
+    /// leaving the indexes as they are.
+    pub(crate) fn remove_rows(&mut self, doomed: &BTreeSet<usize>) -> Vec<Row> {
+        self.forget_inserted(doomed);
+        let removed = match self.layout {
+            Layout::Row => {
+                let mut removed = self.storage.delete(doomed);
+                if self.overflow.is_some() {
+                    let spilled = Arc::make_mut(&mut self.spilled);
+                    for (row, &row_idx) in removed.iter_mut().zip(doomed) {
+                        spilled.fill(row_idx, row, &|_| true);
+                    }
+                    let mut row_idx = 0;
+                    spilled.handles.retain(|_| {
+                        row_idx += 1;
+                        !doomed.contains(&(row_idx - 1))
+                    });
+                }
+                removed
+            }
+            Layout::Column => {
+                let mut removed = Vec::with_capacity(doomed.len());
+                let mut kept = Vec::with_capacity(self.store.len - doomed.len());
+                let rows = Arc::unwrap_or_clone(mem::take(&mut self.store)).into_rows();
+                for (row_idx, row) in rows.into_iter().enumerate() {
+                    if doomed.contains(&row_idx) {
+                        removed.push(row);
+                    } else {
+                        kept.push(row);
+                    }
+                }
+                self.store = Arc::new(ColumnStore::from_rows(
+                    &self.columns,
+                    self.compressed,
+                    self.overflow,
+                    kept,
+                ));
+                removed
+            }
+        };
+        self.rebuild_segments();
+        removed
+    }
+
+    /// Removes every row and returns them, leaving the indexes as they are.
+    pub(crate) fn take_rows(&mut self) -> Vec<Row> {
+        self.segments.iter_mut().for_each(Vec::clear);
+        match self.layout {
+            Layout::Row => {
+                let mut rows = self.storage.take();
+                let spilled = mem::take(&mut self.spilled);
+                for (row_idx, row) in rows.iter_mut().enumerate() {
+                    spilled.fill(row_idx, row, &|_| true);
+                }
+                rows
+            }
+            Layout::Column => Arc::unwrap_or_clone(mem::take(&mut self.store)).into_rows(),
+        }
+    }
+
+    /// Replaces every row without any check, leaving the indexes as they
+    /// are.
+    pub(crate) fn set_rows(&mut self, mut rows: Vec<Row>) {
+        match self.layout {
+            Layout::Row => {
+                let mut spilled = Spilled::default();
+                if let Some(threshold) = self.overflow {
+                    spilled.texts.threshold = threshold;
+                    let handles = rows
+                        .iter_mut()
+                        .map(|row| spilled.split(&self.columns, row))
+                        .collect();
+                    spilled.handles = handles;
+                }
+                self.spilled = Arc::new(spilled);
+                self.storage = TableStorage::from_rows(rows);
+            }
+            Layout::Column => {
+                self.store = Arc::new(ColumnStore::from_rows(
+                    &self.columns,
+                    self.compressed,
+                    self.overflow,
+                    rows,
+                ))
+            }
+        }
+        self.rebuild_segments();
+        self.sync_inserted();
+    }
+}
+
+impl Engine {
+    /// Stores the rows of `table` in `layout` from now on. Fails on an
+    /// engine opened read only or inside a transaction, as statements that
+    /// change a table do.
+    pub fn set_table_layout(&mut self, table: &str, layout: Layout) -> Result<(), EngineError> {
+        self.change_table(table, |t| {
+            t.set_layout(layout);
+            Ok(())
+        })
+    }
+
+    /// Turns compression of `table` on or off; see [`Table::set_compressed`]
+    /// and [`Engine::set_table_layout`].
+    pub fn set_table_compression(
+        &mut self,
+        table: &str,
+        compressed: bool,
+    ) -> Result<(), EngineError> {
+        self.change_table(table, |t| t.set_compressed(compressed))
+    }
+
+    /// Sets the overflow threshold of `table`; see [`Table::set_overflow`]
+    /// and [`Engine::set_table_layout`].
+    pub fn set_table_overflow(
+        &mut self,
+        table: &str,
+        threshold: Option<usize>,
+    ) -> Result<(), EngineError> {
+        self.change_table(table, |t| t.set_overflow(threshold))
+    }
+}
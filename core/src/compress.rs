@@ -0,0 +1,26 @@
+//! LZ4 block compression for files the engine writes, through
+//! [`lz4_flex`].
+//!
+//! A block is a raw LZ4 block, without the frame around it, so the length
+//! it decompresses to is kept next to it by whoever writes it. LZ4 never
+//! copies more than [`MAX_RATIO`] bytes for a byte of the block, so a
+//! length beyond that is taken for damage before anything is allocated for
+//! it.
+
+/// The most bytes a byte of a block decompresses to.
+const MAX_RATIO: usize = 255;
+
+/// Compresses `input` into a block.
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    lz4_flex::block::compress(input)
+}
+
+/// Decompresses a block made by [`compress`], or `None` if it is damaged
+/// or would not decompress to exactly `len` bytes.
+pub(crate) fn decompress(block: &[u8], len: usize) -> Option<Vec<u8>> {
+    if len > block.len().saturating_mul(MAX_RATIO) {
+        return None;
+    }
+    let out = lz4_flex::block::decompress(block, len).ok()?;
+    (out.len() == len).then_some(out)
+}
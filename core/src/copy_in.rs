@@ -0,0 +1,314 @@
+//! Loading many rows into a table at once through [`Engine::copy_in`].
+//!
+//! A load checks each row as it arrives against what the row alone
+//! decides: its column types, the NOT NULL of the primary key and the
+//! partitions. Rows are stored [`COPY_BATCH_ROWS`] at a time, with their
+//! index entries added in the same pass and no lookups. The checks that
+//! need the other rows, UNIQUE constraints and foreign keys, wait for
+//! [`CopyIn::finish`], which takes back every row of the load if one fails.
+
+use std::io::{self, Write};
+
+use crate::csv::{records, CsvOptions};
+use crate::engine::{AutoIncrement, Engine, EngineError, Row, Value};
+
+/// Number of rows a load gathers before storing them.
+pub const COPY_BATCH_ROWS: usize = 4096;
+
+/// A load into one table, made by [`Engine::copy_in`]. Rows go in through
+/// [`CopyIn::write_row`], and CSV through [`Write`]; both may be mixed.
+/// Dropping the load without [`CopyIn::finish`] takes its rows back out.
+pub struct CopyIn<'a> {
+    engine: &'a mut Engine,
+    table: String,
+    /// Rows the table held before the load.
+    start: usize,
+    /// The table's AUTO_INCREMENT before the load.
+    auto_increment: Option<AutoIncrement>,
+    last_id: Option<i128>,
+    /// Checked rows with their index keys, in index name order, not yet
+    /// stored.
+    pending: Vec<(Row, Vec<Value>)>,
+    csv: CsvOptions,
+    /// The column names of the CSV header, once read.
+    header: Option<Vec<String>>,
+    /// CSV bytes after the last complete record.
+    text: Vec<u8>,
+    /// How far `text` was searched for the end of a record, and whether a
+    /// quoted field is open there.
+    scanned: usize,
+    quoted: bool,
+    /// Lines of CSV read before `text`.
+    lines: usize,
+    /// The first CSV record that failed, which fails the load.
+    failed: Option<EngineError>,
+    done: bool,
+}
+
+impl CopyIn<'_> {
+    /// Name of the table being loaded.
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Number of rows taken so far.
+    pub fn rows(&self) -> usize {
+        self.engine.tables[&self.table].row_count() - self.start + self.pending.len()
+    }
+
+    /// Sets how CSV written to the load is read, before any is written.
+    /// The default is [`CsvOptions::default`], with a header naming the
+    /// columns.
+    pub fn set_csv_options(&mut self, options: CsvOptions) {
+        self.csv = options;
+    }
+
+    /// Takes a row holding a value for every column, in column order.
+    /// Fails, leaving the row out, if it holds the wrong number of values,
+    /// a value its column does not accept, a NULL in the primary key, a
+    /// value no partition takes, or one an indexed expression fails on.
+    pub fn write_row(&mut self, values: Row) -> Result<(), EngineError> {
+        let table = &self.engine.tables[&self.table];
+        if values.len() != table.columns.len() {
+            return Err(EngineError::ValueCountMismatch);
+        }
+        let row = values
+            .into_iter()
+            .enumerate()
+            .map(|(idx, value)| table.accept(idx, value))
+            .collect::<Result<Row, _>>()?;
+        self.take(row)
+    }
+
+    fn take(&mut self, mut row: Row) -> Result<(), EngineError> {
+        let table = &self.engine.tables[&self.table];
+        let id = table.fill_auto_increment(&mut row)?;
+        table.check_primary_key(&row)?;
+        table.check_partition(&row)?;
+        let mut names = table.indices.keys().collect::<Vec<_>>();
+        names.sort();
+        let keys = names
+            .into_iter()
+            .map(|name| table.indices[name].key(&row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let table = self
+            .engine
+            .tables
+            .get_mut(&self.table)
+            .ok_or_else(|| EngineError::TableNotFound(self.table.clone()))?;
+        table.note_auto_increment(&row);
+        self.last_id = id.or(self.last_id);
+        self.pending.push((row, keys));
+        if self.pending.len() >= COPY_BATCH_ROWS {
+            self.store();
+        }
+        Ok(())
+    }
+
+    /// Appends the pending rows to the table and adds their index entries.
+    fn store(&mut self) {
+        let Some(table) = self.engine.tables.get_mut(&self.table) else {
+            return;
+        };
+        let mut names = table.indices.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        for (row, keys) in self.pending.drain(..) {
+            let row_idx = table.row_count();
+            for (name, key) in names.iter().zip(keys) {
+                if let Some(index) = table.indices.get_mut(name) {
+                    index.insert(key, row_idx);
+                }
+            }
+            table.add_to_bloom_filters(&row);
+            table.push_row(row);
+        }
+    }
+
+    /// Reads the complete CSV records in `text`, keeping a record that is
+    /// cut off for the next write, or reading it too if `all` is set.
+    fn read_csv(&mut self, all: bool) -> Result<(), EngineError> {
+        let mut end = 0;
+        for (pos, &byte) in self.text.iter().enumerate().skip(self.scanned) {
+            match byte {
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => end = pos + 1,
+                _ => {}
+            }
+        }
+        self.scanned = self.text.len();
+        if all {
+            end = self.text.len();
+        }
+        if end == 0 {
+            return Ok(());
+        }
+        let rest = self.text.split_off(end);
+        let chunk = std::mem::replace(&mut self.text, rest);
+        self.scanned -= end;
+        let text = String::from_utf8(chunk).map_err(|_| EngineError::InvalidInput {
+            line: self.lines + 1,
+            reason: "CSV is not UTF-8".to_string(),
+        })?;
+        let mut found = records(&text, self.csv.delimiter)?;
+        for record in &mut found {
+            record.line += self.lines;
+        }
+        self.lines += text.matches('\n').count();
+        let mut found = found.into_iter();
+        if self.csv.header && self.header.is_none() {
+            let Some(record) = found.next() else {
+                return Ok(());
+            };
+            self.header = Some(record.fields.into_iter().map(|f| f.text).collect());
+        }
+        let found = found.collect::<Vec<_>>();
+        let null = self.csv.null.clone();
+        let rows = self
+            .engine
+            .csv_rows(&self.table, self.header.clone(), &found, &|field| {
+                !field.quoted && field.text == null
+            })?;
+        rows.into_iter().try_for_each(|row| self.take(row))
+    }
+
+    /// Stores the rest of the rows, checks the UNIQUE constraints and
+    /// foreign keys over every row of the load, and returns how many rows
+    /// were added. If CSV written to the load failed, or a check does, the
+    /// table is left as it was before the load.
+    pub fn finish(mut self) -> Result<usize, EngineError> {
+        let result = self.complete();
+        self.done = true;
+        if result.is_err() {
+            self.roll_back();
+        }
+        result
+    }
+
+    fn complete(&mut self) -> Result<usize, EngineError> {
+        if let Some(e) = self.failed.take() {
+            return Err(e);
+        }
+        self.read_csv(true)?;
+        self.store();
+        let table = &self.engine.tables[&self.table];
+        let added = (self.start..table.row_count())
+            .map(|row_idx| table.row(row_idx).into_owned())
+            .collect::<Vec<_>>();
+        let mut names = table.indices.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let index = &table.indices[name];
+            for row in &added {
+                let key = index.key(row)?;
+                if index.duplicated(&key) {
+                    return Err(EngineError::UniqueViolation {
+                        constraint: name.clone(),
+                        value: key,
+                    });
+                }
+            }
+        }
+        self.engine.check_references(&self.table, &added)?;
+        if self.last_id.is_some() {
+            self.engine.last_insert_id = self.last_id;
+        }
+        self.engine.table_changed(&self.table);
+        if !self.engine.in_transaction() {
+            self.engine.log_inserts(&self.table, &added)?;
+        }
+        self.engine.enforce_memory_limit()?;
+        Ok(added.len())
+    }
+
+    /// Removes the rows of the load and their index entries.
+    fn roll_back(&mut self) {
+        self.pending.clear();
+        let Some(table) = self.engine.tables.get_mut(&self.table) else {
+            return;
+        };
+        let mut rows = table.take_rows();
+        rows.truncate(self.start);
+        table.set_rows(rows);
+        table.auto_increment = self.auto_increment.take();
+        let _ = self.engine.rebuild_table_indexes(&self.table, None);
+    }
+}
+
+impl Write for CopyIn<'_> {
+    /// Takes CSV text, reading each record once it is complete. A record
+    /// that fails fails the write and every later one, and then
+    /// [`CopyIn::finish`].
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(e) = &self.failed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?}", e),
+            ));
+        }
+        self.text.extend_from_slice(buf);
+        if let Err(e) = self.read_csv(false) {
+            let error = io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e));
+            self.failed = Some(e);
+            return Err(error);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for CopyIn<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.roll_back();
+        }
+    }
+}
+
+impl Engine {
+    /// Starts a load of many rows into `table`; see [`CopyIn`] and the
+    /// [module docs](self). Fails if the table is not one of the
+    /// engine's own, if the engine is read only, or if an index must be
+    /// rebuilt before rows are added.
+    ///
+    /// The load borrows the engine until it is finished or dropped.
+    pub fn copy_in(&mut self, table: &str) -> Result<CopyIn<'_>, EngineError> {
+        self.writable()?;
+        if self.attached.contains_key(table) {
+            return Err(EngineError::InvalidOperation(format!(
+                "table {} is attached storage, which takes rows through INSERT",
+                table
+            )));
+        }
+        self.load_table(table)?;
+        self.save_for_rollback(table);
+        let loaded = self
+            .tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?;
+        if let Some((name, _)) = loaded.indices.iter().find(|(_, i)| !i.is_ready()) {
+            return Err(EngineError::InvalidOperation(format!(
+                "index {} must be rebuilt before rows are added",
+                name
+            )));
+        }
+        Ok(CopyIn {
+            start: loaded.row_count(),
+            auto_increment: loaded.auto_increment.clone(),
+            engine: self,
+            table: table.to_string(),
+            last_id: None,
+            pending: Vec::new(),
+            csv: CsvOptions::default(),
+            header: None,
+            text: Vec::new(),
+            scanned: 0,
+            quoted: false,
+            lines: 0,
+            failed: None,
+            done: false,
+        })
+    }
+}
@@ -0,0 +1,384 @@
+//! Moving rows in and out of the engine as CSV, following RFC 4180; see
+//! [`Engine::import_csv`] and [`Engine::export_csv`].
+//!
+//! A field holding the delimiter, a quote or a line break is quoted, with
+//! quotes inside doubled. An unquoted field equal to the NULL marker is
+//! NULL; a quoted one is text, so that the empty string and NULL stay apart
+//! under the default empty marker.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+
+use crate::engine::{Engine, EngineError, QueryResult, Row, Value, ValueType};
+use crate::parser::{parse_select, CopyQuery};
+
+/// How CSV is read and written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    /// Whether the first record names the columns. On import the names
+    /// pick the column each field goes to; without them fields go to the
+    /// columns in order.
+    pub header: bool,
+    /// The field text standing for NULL.
+    pub null: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: true,
+            null: String::new(),
+        }
+    }
+}
+
+pub(crate) struct Field {
+    pub(crate) text: String,
+    pub(crate) quoted: bool,
+}
+
+/// A record with the line it starts on, counting from 1.
+pub(crate) struct Record {
+    pub(crate) line: usize,
+    pub(crate) fields: Vec<Field>,
+}
+
+/// Types tried, in order, for a column of a table created by an import;
+/// the first that every field of the column casts to is taken.
+const INFERRED: [ValueType; 6] = [
+    ValueType::Int,
+    ValueType::Float,
+    ValueType::Bool,
+    ValueType::Date,
+    ValueType::Timestamp,
+    ValueType::Text,
+];
+
+fn io_error(e: std::io::Error) -> EngineError {
+    EngineError::Io(e.to_string())
+}
+
+/// Splits `text` into records. Blank lines are skipped.
+pub(crate) fn records(text: &str, delimiter: char) -> Result<Vec<Record>, EngineError> {
+    let mut out = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        loop {
+            let mut field = Field {
+                text: String::new(),
+                quoted: false,
+            };
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                field.quoted = true;
+                loop {
+                    match chars.next() {
+                        None => {
+                            return Err(EngineError::InvalidInput {
+                                line: start,
+                                reason: "unterminated quoted field".to_string(),
+                            })
+                        }
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.text.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.text.push(c);
+                        }
+                    }
+                }
+            }
+            while let Some(&c) = chars.peek() {
+                if c == delimiter || c == '\n' || c == '\r' {
+                    break;
+                }
+                if field.quoted || c == '"' {
+                    return Err(EngineError::InvalidInput {
+                        line,
+                        reason: "quote in the middle of a field".to_string(),
+                    });
+                }
+                field.text.push(c);
+                chars.next();
+            }
+            fields.push(field);
+            match chars.next() {
+                Some(c) if c == delimiter => continue,
+                Some('\r') => {
+                    chars.next_if_eq(&'\n');
+                }
+                _ => {}
+            }
+            line += 1;
+            break;
+        }
+        let blank = matches!(&fields[..], [f] if f.text.is_empty() && !f.quoted);
+        if !blank {
+            out.push(Record {
+                line: start,
+                fields,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Writes one record, quoting the fields that need it.
+fn write_record<'a>(
+    out: &mut impl Write,
+    fields: impl Iterator<Item = Option<&'a str>>,
+    options: &CsvOptions,
+) -> Result<(), EngineError> {
+    let mut line = String::new();
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            line.push(options.delimiter);
+        }
+        match field {
+            None => line.push_str(&options.null),
+            Some(text) => {
+                let quote =
+                    text == options.null || text.contains([options.delimiter, '"', '\n', '\r']);
+                if quote {
+                    line.push('"');
+                    line.push_str(&text.replace('"', "\"\""));
+                    line.push('"');
+                } else {
+                    line.push_str(text);
+                }
+            }
+        }
+    }
+    line.push_str("\r\n");
+    out.write_all(line.as_bytes()).map_err(io_error)
+}
+
+/// A value as CSV field text: its text form without SQL quoting, or
+/// `None` for NULL.
+fn field_text(value: &Value) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    Some(match value.cast(&ValueType::Text) {
+        Ok(Value::Text(text)) => text,
+        _ => value.to_string(),
+    })
+}
+
+impl Engine {
+    /// Reads CSV from `reader` into `table`, returning the number of rows
+    /// added. Each field is cast from text to its column's type; columns
+    /// missing from the header are NULL, or take their next AUTO_INCREMENT
+    /// value. A table that does not exist is created, named after the
+    /// header, with each column of the first type in INT, FLOAT, BOOL,
+    /// DATE, TIMESTAMP and TEXT that all of its fields cast to.
+    ///
+    /// Rows are checked as INSERT checks them, and the import adds all of
+    /// them or none.
+    pub fn import_csv(
+        &mut self,
+        table: &str,
+        mut reader: impl Read,
+        options: &CsvOptions,
+    ) -> Result<usize, EngineError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).map_err(io_error)?;
+        let mut records = records(&text, options.delimiter)?.into_iter();
+        let header = if options.header {
+            let record = records.next().ok_or_else(|| EngineError::InvalidInput {
+                line: 1,
+                reason: "no header".to_string(),
+            })?;
+            Some(
+                record
+                    .fields
+                    .into_iter()
+                    .map(|f| f.text)
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            None
+        };
+        let records = records.collect::<Vec<_>>();
+        let is_null = |field: &Field| !field.quoted && field.text == options.null;
+
+        let created = !self.tables.contains_key(table);
+        if created {
+            let header = header.clone().ok_or_else(|| {
+                EngineError::InvalidQuery(format!(
+                    "table {} does not exist and the CSV has no header to create it from",
+                    table
+                ))
+            })?;
+            let columns = header
+                .into_iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    let fields = records
+                        .iter()
+                        .filter_map(|r| r.fields.get(idx))
+                        .filter(|f| !is_null(f));
+                    let col_type = INFERRED
+                        .iter()
+                        .find(|t| {
+                            fields
+                                .clone()
+                                .all(|f| Value::Text(f.text.clone()).cast(t).is_ok())
+                        })
+                        .cloned()
+                        .unwrap_or(ValueType::Text);
+                    (name, col_type)
+                })
+                .collect();
+            self.create_table(table, columns)?;
+        }
+        let result = self.csv_rows(table, header, &records, &is_null);
+        let rows = match result {
+            Ok(rows) => rows,
+            Err(e) => {
+                if created {
+                    self.tables.remove(table);
+                }
+                return Err(e);
+            }
+        };
+        let result = self.insert_rows(table, rows);
+        if result.is_err() && created {
+            self.tables.remove(table);
+        }
+        result
+    }
+
+    /// The rows `records` hold for `table`, cast to its column types.
+    pub(crate) fn csv_rows(
+        &self,
+        name: &str,
+        header: Option<Vec<String>>,
+        records: &[Record],
+        is_null: &dyn Fn(&Field) -> bool,
+    ) -> Result<Vec<Row>, EngineError> {
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let positions = match header {
+            Some(names) => names
+                .iter()
+                .map(|n| {
+                    table
+                        .columns
+                        .iter()
+                        .position(|c| c.name == *n)
+                        .ok_or_else(|| EngineError::ColumnNotFound(n.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => (0..table.columns.len()).collect(),
+        };
+        records
+            .iter()
+            .map(|record| {
+                if record.fields.len() != positions.len() {
+                    return Err(EngineError::InvalidInput {
+                        line: record.line,
+                        reason: format!(
+                            "{} fields where {} were expected",
+                            record.fields.len(),
+                            positions.len()
+                        ),
+                    });
+                }
+                let mut row = table
+                    .columns
+                    .iter()
+                    .map(|c| Value::TypedNull(c.col_type.clone()))
+                    .collect::<Row>();
+                for (&idx, field) in positions.iter().zip(&record.fields) {
+                    if is_null(field) {
+                        continue;
+                    }
+                    let text = Value::Text(field.text.clone());
+                    let value = text.cast(&table.columns[idx].col_type).unwrap_or(text);
+                    row[idx] = table.accept(idx, value)?;
+                }
+                Ok(row)
+            })
+            .collect()
+    }
+
+    /// Writes the rows of `source` to `writer` as CSV, returning how many
+    /// were written. `source` is the name of a table or view, or a SELECT.
+    /// With a header, the first record holds the result's column names.
+    pub fn export_csv(
+        &self,
+        source: &str,
+        mut writer: impl Write,
+        options: &CsvOptions,
+    ) -> Result<usize, EngineError> {
+        let result = self.export_source(source)?;
+        if options.header {
+            let names = result.column_names();
+            write_record(&mut writer, names.into_iter().map(Some), options)?;
+        }
+        for row in &result.rows {
+            let fields = row.iter().map(field_text).collect::<Vec<_>>();
+            write_record(&mut writer, fields.iter().map(Option::as_deref), options)?;
+        }
+        writer.flush().map_err(io_error)?;
+        Ok(result.rows.len())
+    }
+
+    /// The rows an export of `source` writes: every row of the table or
+    /// view it names, or the result of the SELECT it holds.
+    pub(crate) fn export_source(&self, source: &str) -> Result<QueryResult, EngineError> {
+        let query = match parse_select(source.trim()) {
+            Ok(("", q)) => q,
+            _ => parse_select(&format!("SELECT * FROM {}", source.trim()))
+                .ok()
+                .filter(|(rest, _)| rest.is_empty())
+                .map(|(_, q)| q)
+                .ok_or_else(|| {
+                    EngineError::InvalidQuery(format!(
+                        "{} is neither a table name nor a SELECT",
+                        source
+                    ))
+                })?,
+        };
+        self.select_result(&query)
+    }
+
+    /// Runs `COPY`: reads a CSV file into a table, or writes a table, view
+    /// or query to one.
+    pub(crate) fn copy(&mut self, q: &CopyQuery) -> Result<(), EngineError> {
+        let open_error =
+            |path: &str, e: std::io::Error| EngineError::Io(format!("{}: {}", path, e));
+        match q {
+            CopyQuery::From {
+                table,
+                path,
+                options,
+            } => {
+                let file = File::open(path).map_err(|e| open_error(path, e))?;
+                self.import_csv(table, file, options)?;
+            }
+            CopyQuery::To {
+                source,
+                path,
+                options,
+            } => {
+                let file = File::create(path).map_err(|e| open_error(path, e))?;
+                self.export_csv(source, BufWriter::new(file), options)?;
+            }
+        }
+        Ok(())
+    }
+}
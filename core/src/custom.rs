@@ -0,0 +1,145 @@
+//! User-defined value types stored in [`Value::Custom`], so that
+//! applications can keep domain values such as IP addresses or points in
+//! tables without a dedicated variant.
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{OnceLock, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::engine::Value;
+
+/// A type that can be stored in a [`Value::Custom`]. Values compare, hash
+/// and order with others of the same type; values of different custom
+/// types are never equal.
+///
+/// Implement this and call [`register_custom_type`] before deserializing
+/// data that contains the type.
+pub trait CustomType:
+    Any
+    + Clone
+    + fmt::Debug
+    + fmt::Display
+    + Eq
+    + Hash
+    + Ord
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+{
+    /// Name identifying the type in serialized data, e.g. `"inet"`.
+    const NAME: &'static str;
+}
+
+/// Object-safe view of a [`CustomType`], implemented for every such type.
+pub trait CustomValue: fmt::Debug + fmt::Display + Send + Sync {
+    fn type_name(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
+    fn clone_box(&self) -> Box<dyn CustomValue>;
+    fn eq_dyn(&self, other: &dyn CustomValue) -> bool;
+    /// `None` when `other` has a different type.
+    fn cmp_dyn(&self, other: &dyn CustomValue) -> Option<Ordering>;
+    fn hash_dyn(&self, state: &mut dyn Hasher);
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>;
+}
+
+impl<T: CustomType> CustomValue for T {
+    fn type_name(&self) -> &'static str {
+        T::NAME
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn CustomValue> {
+        Box::new(self.clone())
+    }
+
+    fn eq_dyn(&self, other: &dyn CustomValue) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+
+    fn cmp_dyn(&self, other: &dyn CustomValue) -> Option<Ordering> {
+        other.as_any().downcast_ref::<T>().map(|o| self.cmp(o))
+    }
+
+    fn hash_dyn(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state);
+    }
+
+    fn to_json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+}
+
+impl Clone for Box<dyn CustomValue> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+type Decoder = fn(serde_json::Value) -> serde_json::Result<Box<dyn CustomValue>>;
+
+fn registry() -> &'static RwLock<HashMap<&'static str, Decoder>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<&'static str, Decoder>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Makes `T` known to deserialization and to the binary row encoding, which
+/// find a custom value's type by its [`CustomType::NAME`].
+pub fn register_custom_type<T: CustomType>() {
+    let decode: Decoder =
+        |json| serde_json::from_value::<T>(json).map(|v| Box::new(v) as Box<dyn CustomValue>);
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(T::NAME, decode);
+}
+
+/// Rebuilds a custom value from its type name and JSON form; `None` when
+/// the type is not registered or the JSON does not match it.
+pub(crate) fn decode(type_name: &str, json: serde_json::Value) -> Option<Box<dyn CustomValue>> {
+    let decode = *registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(type_name)?;
+    decode(json).ok()
+}
+
+// Custom values are serialized as a `(type name, value)` pair.
+impl Serialize for Box<dyn CustomValue> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let json = self.to_json().map_err(serde::ser::Error::custom)?;
+        (self.type_name(), json).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Box<dyn CustomValue> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (name, json) = <(String, serde_json::Value)>::deserialize(deserializer)?;
+        decode(&name, json).ok_or_else(|| {
+            serde::de::Error::custom(format!("unknown or invalid custom type {}", name))
+        })
+    }
+}
+
+impl Value {
+    pub fn custom<T: CustomType>(value: T) -> Value {
+        Value::Custom(Box::new(value))
+    }
+
+    /// The custom value held, if it has type `T`.
+    pub fn as_custom<T: CustomType>(&self) -> Option<&T> {
+        match self {
+            Value::Custom(c) => c.as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+}
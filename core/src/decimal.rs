@@ -0,0 +1,291 @@
+//! Exact fixed-point numbers backing the DECIMAL type.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Largest number of significant digits a decimal can hold; also the
+/// largest scale.
+pub const MAX_PRECISION: u8 = 38;
+
+/// Minimum number of fractional digits kept by division.
+const DIV_SCALE: u8 = 6;
+
+/// A decimal number stored as `mantissa * 10^-scale`, e.g. `12.30` is
+/// mantissa 1230 at scale 2. Values that differ only in trailing zeros
+/// compare and hash equal.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u8,
+}
+
+fn pow10(exp: u8) -> i128 {
+    10i128.pow(exp as u32)
+}
+
+/// Divides rounding half away from zero.
+fn div_round(n: i128, d: i128) -> i128 {
+    let (q, r) = (n / d, n % d);
+    if r.unsigned_abs() >= d.unsigned_abs() - r.unsigned_abs() {
+        if (n < 0) == (d < 0) {
+            q + 1
+        } else {
+            q - 1
+        }
+    } else {
+        q
+    }
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    /// Builds `mantissa * 10^-scale`, or `None` if it exceeds
+    /// [`MAX_PRECISION`] digits.
+    pub fn new(mantissa: i128, scale: u8) -> Option<Self> {
+        let d = Decimal { mantissa, scale };
+        (scale <= MAX_PRECISION && d.digits() <= MAX_PRECISION as u32).then_some(d)
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// Number of significant digits in the mantissa.
+    pub fn digits(&self) -> u32 {
+        self.mantissa
+            .unsigned_abs()
+            .checked_ilog10()
+            .map_or(1, |d| d + 1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// Changes the number of fractional digits, rounding half away from
+    /// zero when digits are dropped.
+    pub fn rescale(&self, scale: u8) -> Option<Self> {
+        let mantissa = match scale.cmp(&self.scale) {
+            Ordering::Equal => self.mantissa,
+            Ordering::Greater => self.mantissa.checked_mul(pow10(scale - self.scale))?,
+            Ordering::Less => div_round(self.mantissa, pow10(self.scale - scale)),
+        };
+        Self::new(mantissa, scale)
+    }
+
+    /// Rescales to `scale` and checks that the result has at most
+    /// `precision` digits, as a `DECIMAL(precision, scale)` column requires.
+    pub fn fit(&self, precision: u8, scale: u8) -> Option<Self> {
+        self.rescale(scale)
+            .filter(|d| d.digits() <= precision as u32)
+    }
+
+    /// Both operands at their common scale.
+    fn aligned(&self, other: &Self) -> Option<(i128, i128, u8)> {
+        let scale = self.scale.max(other.scale);
+        Some((
+            self.rescale(scale)?.mantissa,
+            other.rescale(scale)?.mantissa,
+            scale,
+        ))
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+        Self::new(a.checked_add(b)?, scale)
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+        Self::new(a.checked_sub(b)?, scale)
+    }
+
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        let scale = self.scale + other.scale;
+        if scale > MAX_PRECISION {
+            let excess = scale - MAX_PRECISION;
+            return Self::new(div_round(mantissa, pow10(excess)), MAX_PRECISION);
+        }
+        Self::new(mantissa, scale)
+    }
+
+    /// Divides keeping at least [`DIV_SCALE`] fractional digits. Returns
+    /// `None` when `other` is zero or the result does not fit.
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let scale = self
+            .scale
+            .max(other.scale)
+            .saturating_add(DIV_SCALE)
+            .min(MAX_PRECISION);
+        // (a / 10^sa) / (b / 10^sb) = a * 10^(scale + sb - sa) / b / 10^scale,
+        // and scale >= sa.
+        let shift = (scale - self.scale) as u32 + other.scale as u32;
+        let num = self.mantissa.checked_mul(10i128.checked_pow(shift)?)?;
+        Self::new(div_round(num, other.mantissa), scale)
+    }
+
+    pub fn checked_rem(&self, other: &Self) -> Option<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+        Self::new(a.checked_rem(b)?, scale)
+    }
+
+    /// The integer part.
+    pub fn trunc_to_i128(&self) -> i128 {
+        self.mantissa / pow10(self.scale)
+    }
+
+    /// The integer part, if it fits in an `i64`.
+    pub fn trunc_to_i64(&self) -> Option<i64> {
+        i64::try_from(self.trunc_to_i128()).ok()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Going through the decimal text gives the closest f64.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The shortest decimal that round-trips to `f`, or `None` for NaN,
+    /// infinities and values beyond [`MAX_PRECISION`] digits.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        // `{:?}` may use exponent notation; `{}` never does.
+        format!("{}", f).parse().ok()
+    }
+
+    /// Mantissa and scale with trailing fractional zeros removed.
+    fn normalized(&self) -> (i128, u8) {
+        let (mut m, mut s) = (self.mantissa, self.scale);
+        while s > 0 && m % 10 == 0 {
+            m /= 10;
+            s -= 1;
+        }
+        (m, s)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(n: i64) -> Self {
+        Decimal {
+            mantissa: n as i128,
+            scale: 0,
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized().hash(state);
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compare integer parts first, then the fractional parts brought to
+        // a common scale; this cannot overflow, unlike aligning mantissas.
+        let split = |d: &Decimal| {
+            let unit = pow10(d.scale);
+            (d.mantissa.div_euclid(unit), d.mantissa.rem_euclid(unit))
+        };
+        let ((ai, af), (bi, bf)) = (split(self), split(other));
+        let scale = self.scale.max(other.scale);
+        ai.cmp(&bi)
+            .then_with(|| (af * pow10(scale - self.scale)).cmp(&(bf * pow10(scale - other.scale))))
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+/// Error returned when text is not a valid decimal number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseDecimalError;
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    /// Parses `[+-]digits[.digits]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (int, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let all_digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+        if (int.is_empty() && frac.is_empty()) || !all_digits(int) || !all_digits(frac) {
+            return Err(ParseDecimalError);
+        }
+        let scale = u8::try_from(frac.len()).map_err(|_| ParseDecimalError)?;
+        let digits = format!("{}{}", int, frac);
+        let digits = digits.trim_start_matches('0');
+        if digits.len() > MAX_PRECISION as usize {
+            return Err(ParseDecimalError);
+        }
+        let magnitude: i128 = if digits.is_empty() {
+            0
+        } else {
+            digits.parse().map_err(|_| ParseDecimalError)?
+        };
+        let mantissa = if negative { -magnitude } else { magnitude };
+        Decimal::new(mantissa, scale).ok_or(ParseDecimalError)
+    }
+}
+
+// Decimals travel as strings so that JSON consumers do not round them
+// through a float.
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| serde::de::Error::custom(format!("invalid decimal: {}", s)))
+    }
+}
@@ -0,0 +1,640 @@
+//! DELETE and UPDATE, together with the foreign key checks and referential
+//! actions they share with INSERT.
+//!
+//! A statement may touch several tables through cascades. Each change is
+//! noted with the rows it replaced or deleted, and all of them are undone
+//! if any step fails, so a statement applies completely or not at all.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::mem;
+
+use crate::engine::{
+    row_key, AutoIncrement, Engine, EngineError, ForeignKey, ReferentialAction, Row, Scope, Table,
+    Value, ValueType,
+};
+use crate::expr::{Binder, Relation};
+use crate::index::IndexKind;
+use crate::parser::{Condition, Expr};
+
+/// The changes the running statement made, by table.
+#[derive(Default)]
+struct Undo {
+    tables: BTreeMap<String, Changes>,
+    /// Whether the changes go to the log once the statement succeeds.
+    logged: bool,
+}
+
+/// The changes a statement made to one table, oldest first, with what the
+/// table held before them that the steps do not record.
+struct Changes {
+    steps: Vec<Step>,
+    auto_increment: Option<AutoIncrement>,
+    inserted: Vec<i64>,
+    /// The rows stored or changed, kept only when the changes are logged.
+    added: Option<Vec<Row>>,
+}
+
+enum Step {
+    /// A row appended.
+    Inserted,
+    /// A row changed in place, by position, with the row it replaced.
+    Replaced(usize, Row),
+    /// Rows deleted, by their positions before the delete, in table order.
+    Deleted(Vec<(usize, Row)>),
+}
+
+impl Changes {
+    fn new(table: &Table, logged: bool) -> Self {
+        Self {
+            steps: Vec::new(),
+            auto_increment: table.auto_increment.clone(),
+            inserted: table.inserted.clone(),
+            added: logged.then(Vec::new),
+        }
+    }
+
+    /// Notes the row just appended to `table`.
+    fn inserted(&mut self, table: &Table) {
+        self.steps.push(Step::Inserted);
+        if let Some(added) = &mut self.added {
+            added.push(table.row(table.row_count() - 1).into_owned());
+        }
+    }
+
+    /// Notes the row just put at `row_idx` in `table` in place of `old`.
+    fn replaced(&mut self, table: &Table, row_idx: usize, old: Row) {
+        self.steps.push(Step::Replaced(row_idx, old));
+        if let Some(added) = &mut self.added {
+            added.push(table.row(row_idx).into_owned());
+        }
+    }
+
+    /// Notes the rows `removed`, just deleted from the positions `doomed`.
+    fn deleted(&mut self, doomed: &BTreeSet<usize>, removed: &[Row]) {
+        let deleted = doomed.iter().copied().zip(removed.iter().cloned());
+        self.steps.push(Step::Deleted(deleted.collect()));
+    }
+
+    /// The rows the changes took out of the table and those they put in.
+    fn logged_rows(self) -> (Vec<Row>, Vec<Row>) {
+        let mut removed = Vec::new();
+        for step in self.steps {
+            match step {
+                Step::Inserted => {}
+                Step::Replaced(_, old) => removed.push(old),
+                Step::Deleted(deleted) => removed.extend(deleted.into_iter().map(|(_, row)| row)),
+            }
+        }
+        (removed, self.added.unwrap_or_default())
+    }
+
+    /// Turns `rows`, those of the table after the changes, into those it
+    /// held before them.
+    fn undo_rows(steps: Vec<Step>, rows: &mut Vec<Row>) {
+        for step in steps.into_iter().rev() {
+            match step {
+                Step::Inserted => {
+                    rows.pop();
+                }
+                Step::Replaced(row_idx, old) => rows[row_idx] = old,
+                Step::Deleted(deleted) => {
+                    let mut kept = mem::take(rows).into_iter();
+                    for (row_idx, row) in deleted {
+                        let before = row_idx - rows.len();
+                        rows.extend(kept.by_ref().take(before));
+                        rows.push(row);
+                    }
+                    rows.extend(kept);
+                }
+            }
+        }
+    }
+
+    /// Puts `table` back as it was before the changes, rebuilding its
+    /// indexes and bloom filters.
+    fn undo(self, table: &mut Table) {
+        let mut rows = table.take_rows();
+        Self::undo_rows(self.steps, &mut rows);
+        table.set_rows(rows);
+        table.auto_increment = self.auto_increment;
+        table.inserted = self.inserted;
+        table.rebuild_bloom_filters();
+        let mut ready = table
+            .indices
+            .iter()
+            .filter(|(_, index)| index.is_ready())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        ready.sort();
+        for name in ready {
+            // Every index held these rows before, so none can fail now.
+            let _ = table.rebuild_index(&name);
+        }
+    }
+}
+
+impl Engine {
+    /// Adds a foreign key from `table` to `fk.ref_table`, failing if either
+    /// table or any column is missing, if the referenced columns have no
+    /// UNIQUE constraint, or if existing rows already break the key.
+    pub fn add_foreign_key(&mut self, table: &str, fk: ForeignKey) -> Result<(), EngineError> {
+        self.change_schema(|engine| engine.add_foreign_key_now(table, fk))
+    }
+
+    fn add_foreign_key_now(&mut self, table: &str, fk: ForeignKey) -> Result<(), EngineError> {
+        let child = self
+            .tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?;
+        let parent = self
+            .tables
+            .get(&fk.ref_table)
+            .ok_or_else(|| EngineError::TableNotFound(fk.ref_table.clone()))?;
+        Self::check_foreign_key(&fk, child, parent)?;
+        Self::check_key(&fk, child, parent, &child.all_rows())?;
+        if let Some(child) = self.tables.get_mut(table) {
+            child.foreign_keys.push(fk);
+        }
+        Ok(())
+    }
+
+    /// Fails if a column of `fk` is missing from `child` or `parent`, if
+    /// it has more columns than it references or fewer, if a column's type
+    /// holds values of another kind than the column it references, or if
+    /// the referenced columns have no UNIQUE constraint.
+    pub(crate) fn check_foreign_key(
+        fk: &ForeignKey,
+        child: &Table,
+        parent: &Table,
+    ) -> Result<(), EngineError> {
+        let columns = child.column_positions(&fk.columns)?;
+        let ref_columns = parent.column_positions(&fk.ref_columns)?;
+        if fk.columns.len() != fk.ref_columns.len() {
+            return Err(EngineError::InvalidQuery(format!(
+                "foreign key {} has {} columns but references {}",
+                fk.name,
+                fk.columns.len(),
+                fk.ref_columns.len()
+            )));
+        }
+        for (idx, ref_idx) in columns.into_iter().zip(ref_columns) {
+            let column = &child.columns[idx];
+            let referenced = &parent.columns[ref_idx];
+            if !same_keys(&column.col_type, &referenced.col_type) {
+                return Err(EngineError::TypeMismatch {
+                    column: column.name.clone(),
+                    expected: referenced.col_type.clone(),
+                    found: column.col_type.clone(),
+                });
+            }
+        }
+        if parent.unique_on(&fk.ref_columns).is_none() {
+            return Err(EngineError::InvalidQuery(format!(
+                "foreign key {} references columns of {} without a UNIQUE constraint",
+                fk.name, fk.ref_table
+            )));
+        }
+        Ok(())
+    }
+
+    /// The row an INSERT's values work out to; they may call functions but
+    /// refer to no column.
+    pub(crate) fn insert_values(&self, values: &[Expr]) -> Result<Row, EngineError> {
+        let scope = Scope::new();
+        let rel = Relation::default();
+        let binder = Binder::new(self, &scope, &rel);
+        values
+            .iter()
+            .map(|value| binder.expr(value)?.eval(&Row::new()))
+            .collect()
+    }
+
+    /// Deletes the rows of `table` matching `cond`, or every row without
+    /// one, applying the ON DELETE action of each foreign key that
+    /// references them. Returns the number of rows deleted from `table`.
+    pub fn delete(&mut self, table: &str, cond: Option<&Condition>) -> Result<usize, EngineError> {
+        self.writable()?;
+        if self.attached.contains_key(table) {
+            return self.delete_attached(table, cond);
+        }
+        let doomed = self.matching_rows(table, cond)?;
+        self.delete_positions(table, doomed)
+    }
+
+    /// Deletes the rows of `table` at positions `doomed` as DELETE does,
+    /// returning how many were deleted.
+    pub(crate) fn delete_positions(
+        &mut self,
+        table: &str,
+        doomed: BTreeSet<usize>,
+    ) -> Result<usize, EngineError> {
+        let count = doomed.len();
+        self.undoable(|engine, undo| engine.delete_rows(table, doomed, undo))?;
+        Ok(count)
+    }
+
+    /// Sets columns of the rows of `table` matching `cond`, applying the ON
+    /// UPDATE action of each foreign key that references a changed key.
+    /// Returns the number of rows updated in `table`.
+    pub fn update(
+        &mut self,
+        table: &str,
+        assignments: &[(String, Expr)],
+        cond: Option<&Condition>,
+    ) -> Result<usize, EngineError> {
+        self.writable()?;
+        if self.attached.contains_key(table) {
+            return self.update_attached(table, assignments, cond);
+        }
+        let targets = self.matching_rows(table, cond)?;
+        let scope = Scope::new();
+        let current = self
+            .tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?;
+        let rel = Relation::from_table(table, current);
+        let binder = Binder::new(self, &scope, &rel);
+        let assignments = assignments
+            .iter()
+            .map(|(column, expr)| Ok((rel.resolve(column)?, binder.expr(expr)?)))
+            .collect::<Result<Vec<_>, EngineError>>()?;
+        let mut changes = Vec::with_capacity(targets.len());
+        for row_idx in targets {
+            let old = current.row(row_idx);
+            let mut new = old.to_vec();
+            for (col_idx, expr) in &assignments {
+                new[*col_idx] = current.accept(*col_idx, expr.eval(&old)?)?;
+            }
+            changes.push((row_idx, new));
+        }
+        let count = changes.len();
+        self.undoable(|engine, undo| engine.update_rows(table, changes, undo))?;
+        Ok(count)
+    }
+
+    /// Adds `rows`, already holding values their columns accept, to
+    /// `table`, filling AUTO_INCREMENT columns and checking foreign keys as
+    /// INSERT does. Adds every row or none; returns how many were added.
+    pub(crate) fn insert_rows(
+        &mut self,
+        table: &str,
+        rows: Vec<Row>,
+    ) -> Result<usize, EngineError> {
+        let count = rows.len();
+        self.undoable(|engine, undo| {
+            engine.table_for_write(table, undo)?;
+            for mut row in rows {
+                engine.tables[table].fill_auto_increment(&mut row)?;
+                engine.check_references(table, std::slice::from_ref(&row))?;
+                let (table, noted) = engine.table_for_write(table, undo)?;
+                table.insert(row)?;
+                noted.inserted(table);
+            }
+            Ok(count)
+        })
+    }
+
+    /// Checks that `rows`, about to be stored in `table`, reference existing
+    /// keys through each of the table's foreign keys.
+    pub(crate) fn check_references(&self, table: &str, rows: &[Row]) -> Result<(), EngineError> {
+        let child = self
+            .tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?;
+        for fk in &child.foreign_keys {
+            let parent = self
+                .tables
+                .get(&fk.ref_table)
+                .ok_or_else(|| EngineError::TableNotFound(fk.ref_table.clone()))?;
+            Self::check_key(fk, child, parent, rows)?;
+        }
+        Ok(())
+    }
+
+    fn check_key(
+        fk: &ForeignKey,
+        child: &Table,
+        parent: &Table,
+        rows: &[Row],
+    ) -> Result<(), EngineError> {
+        let positions = child.column_positions(&fk.columns)?;
+        let unique = parent.unique_on(&fk.ref_columns).ok_or_else(|| {
+            EngineError::InvalidQuery(format!("foreign key {} lost its UNIQUE key", fk.name))
+        })?;
+        for row in rows {
+            if let Some(key) = row_key(&positions, row).filter(|k| !unique.contains(k)) {
+                return Err(EngineError::ForeignKeyViolation {
+                    constraint: fk.name.clone(),
+                    value: key,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Positions of the rows of `table` matching `cond`, found through an
+    /// index when the planner picks one.
+    fn matching_rows(
+        &self,
+        table: &str,
+        cond: Option<&Condition>,
+    ) -> Result<BTreeSet<usize>, EngineError> {
+        let current = self
+            .tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?;
+        // Expired rows are left for Engine::sweep_expired.
+        let scope = Scope::new();
+        let live = |row_idx: &usize| !current.expired(*row_idx, scope.now);
+        let Some(cond) = cond else {
+            return Ok((0..current.row_count()).filter(live).collect());
+        };
+        let rel = Relation::from_table(table, current);
+        let filter = Binder::new(self, &scope, &rel).condition(cond)?;
+        let found = self.index_lookup(current, &rel, cond, None);
+        if let Some(found) = &found {
+            found.count_hit(current);
+        }
+        let candidates = match found {
+            Some(found) if found.exact => return Ok(found.rows.into_iter().filter(live).collect()),
+            Some(found) => found.rows,
+            None => (0..current.row_count()).collect(),
+        };
+        let mut matched = BTreeSet::new();
+        for row_idx in candidates.into_iter().filter(live) {
+            if filter.matches(&current.row(row_idx))? {
+                matched.insert(row_idx);
+            }
+        }
+        Ok(matched)
+    }
+
+    /// Runs `f`, undoing its changes to every table if it fails.
+    fn undoable<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self, &mut Undo) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        self.writable()?;
+        let mut undo = Undo {
+            logged: self.logs.is_some() && !self.in_transaction(),
+            ..Undo::default()
+        };
+        let result = f(self, &mut undo);
+        if result.is_err() {
+            for (name, changes) in undo.tables {
+                if let Some(table) = self.tables.get_mut(&name) {
+                    changes.undo(table);
+                }
+            }
+            return result;
+        }
+        for name in undo.tables.keys() {
+            self.table_changed(name);
+        }
+        if !undo.logged {
+            return result;
+        }
+        for (name, changes) in undo.tables {
+            let (removed, added) = changes.logged_rows();
+            self.log_rows(&name, &removed, &added)?;
+        }
+        result
+    }
+
+    /// The table for changing, with its changes so far in `undo`. Inside a
+    /// transaction, the table is first kept for ROLLBACK.
+    fn table_for_write<'a>(
+        &'a mut self,
+        name: &str,
+        undo: &'a mut Undo,
+    ) -> Result<(&'a mut Table, &'a mut Changes), EngineError> {
+        if !undo.tables.contains_key(name) {
+            self.save_for_rollback(name);
+        }
+        let table = self
+            .tables
+            .get_mut(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let changes = undo
+            .tables
+            .entry(name.to_string())
+            .or_insert_with(|| Changes::new(table, undo.logged));
+        Ok((table, changes))
+    }
+
+    /// Every foreign key that references `table`, with the table it is on,
+    /// in table name order.
+    fn references_to(&self, table: &str) -> Vec<(String, ForeignKey)> {
+        let mut found = self
+            .tables
+            .iter()
+            .flat_map(|(name, t)| {
+                t.foreign_keys
+                    .iter()
+                    .filter(|fk| fk.ref_table == table)
+                    .map(move |fk| (name.clone(), fk.clone()))
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| (&a.0, &a.1.name).cmp(&(&b.0, &b.1.name)));
+        found
+    }
+
+    /// Rows of `table` whose key under `fk` is one of `keys`, in table
+    /// order, looked up in an index over just the key's columns if the
+    /// table has one.
+    fn referencing_rows(
+        &self,
+        table: &str,
+        fk: &ForeignKey,
+        keys: &HashSet<Value>,
+    ) -> Result<Vec<usize>, EngineError> {
+        let child = &self.tables[table];
+        let positions = child.column_positions(&fk.columns)?;
+        let index = child.indices.values().find(|index| {
+            index.is_ready()
+                && index.is_plain()
+                && index.kind() != IndexKind::Trigram
+                && index.columns == fk.columns
+        });
+        if let Some(index) = index {
+            let mut hits = keys
+                .iter()
+                .flat_map(|key| index.get(key))
+                .copied()
+                .collect::<Vec<_>>();
+            hits.sort_unstable();
+            return Ok(hits);
+        }
+        Ok(child
+            .all_rows()
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row_key(&positions, row).is_some_and(|k| keys.contains(&k)))
+            .map(|(row_idx, _)| row_idx)
+            .collect())
+    }
+
+    fn delete_rows(
+        &mut self,
+        name: &str,
+        doomed: BTreeSet<usize>,
+        undo: &mut Undo,
+    ) -> Result<(), EngineError> {
+        if doomed.is_empty() {
+            return Ok(());
+        }
+        let (table, noted) = self.table_for_write(name, undo)?;
+        let removed = table.remove_rows(&doomed);
+        noted.deleted(&doomed, &removed);
+        table.unindex_rows(&doomed, &removed)?;
+
+        for (child, fk) in self.references_to(name) {
+            let positions = self.tables[name].column_positions(&fk.ref_columns)?;
+            let keys = removed
+                .iter()
+                .filter_map(|row| row_key(&positions, row))
+                .collect::<HashSet<_>>();
+            let hits = self.referencing_rows(&child, &fk, &keys)?;
+            if hits.is_empty() {
+                continue;
+            }
+            match fk.on_delete {
+                ReferentialAction::Restrict => {
+                    let positions = self.tables[&child].column_positions(&fk.columns)?;
+                    return Err(EngineError::ForeignKeyViolation {
+                        value: row_key(&positions, &self.tables[&child].row(hits[0]))
+                            .unwrap_or(Value::Null),
+                        constraint: fk.name,
+                    });
+                }
+                ReferentialAction::Cascade => {
+                    self.delete_rows(&child, hits.into_iter().collect(), undo)?
+                }
+                ReferentialAction::SetNull => {
+                    let changes = self.nulled(&child, &fk, hits)?;
+                    self.update_rows(&child, changes, undo)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn update_rows(
+        &mut self,
+        name: &str,
+        changes: Vec<(usize, Row)>,
+        undo: &mut Undo,
+    ) -> Result<(), EngineError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let (table, noted) = self.table_for_write(name, undo)?;
+        for (_, row) in &changes {
+            table.check_primary_key(row)?;
+            table.check_partition(row)?;
+        }
+        let mut replaced = Vec::with_capacity(changes.len());
+        for (row_idx, row) in &changes {
+            let old = table.replace_row(*row_idx, row.clone());
+            noted.replaced(table, *row_idx, old.clone());
+            replaced.push((*row_idx, old));
+            table.add_to_bloom_filters(row);
+        }
+        table.reindex_rows(&replaced)?;
+        let new_rows = changes.into_iter().map(|(_, row)| row).collect::<Vec<_>>();
+        self.check_references(name, &new_rows)?;
+
+        for (child, fk) in self.references_to(name) {
+            let positions = self.tables[name].column_positions(&fk.ref_columns)?;
+            // Old key to new key, for each row whose key changed.
+            let moved = replaced
+                .iter()
+                .zip(&new_rows)
+                .filter_map(|((_, old), new)| {
+                    let old = row_key(&positions, old)?;
+                    let new = row_key(&positions, new);
+                    (new.as_ref() != Some(&old)).then_some((old, new))
+                })
+                .collect::<HashMap<_, _>>();
+            let keys = moved.keys().cloned().collect::<HashSet<_>>();
+            let hits = self.referencing_rows(&child, &fk, &keys)?;
+            if hits.is_empty() {
+                continue;
+            }
+            let changes = match fk.on_update {
+                ReferentialAction::Restrict => {
+                    let positions = self.tables[&child].column_positions(&fk.columns)?;
+                    return Err(EngineError::ForeignKeyViolation {
+                        value: row_key(&positions, &self.tables[&child].row(hits[0]))
+                            .unwrap_or(Value::Null),
+                        constraint: fk.name,
+                    });
+                }
+                ReferentialAction::Cascade => {
+                    let child_table = &self.tables[&child];
+                    let child_positions = child_table.column_positions(&fk.columns)?;
+                    let mut changes = Vec::with_capacity(hits.len());
+                    for row_idx in hits {
+                        let mut row = child_table.row(row_idx).into_owned();
+                        let old = row_key(&child_positions, &row).unwrap_or(Value::Null);
+                        match moved.get(&old).cloned().flatten() {
+                            Some(new) => {
+                                let parts = match new {
+                                    Value::List(parts) if child_positions.len() > 1 => parts,
+                                    single => vec![single],
+                                };
+                                for (&col_idx, part) in child_positions.iter().zip(parts) {
+                                    row[col_idx] = child_table.accept(col_idx, part)?;
+                                }
+                            }
+                            // The new key has a NULL in it, so set the
+                            // referencing columns to NULL as well.
+                            None => {
+                                for &col_idx in &child_positions {
+                                    row[col_idx] = child_table.accept(col_idx, Value::Null)?;
+                                }
+                            }
+                        }
+                        changes.push((row_idx, row));
+                    }
+                    changes
+                }
+                ReferentialAction::SetNull => self.nulled(&child, &fk, hits)?,
+            };
+            self.update_rows(&child, changes, undo)?;
+        }
+        Ok(())
+    }
+
+    /// The rows at `hits` in `table` with the columns of `fk` set to NULL.
+    fn nulled(
+        &self,
+        table: &str,
+        fk: &ForeignKey,
+        hits: Vec<usize>,
+    ) -> Result<Vec<(usize, Row)>, EngineError> {
+        let child = &self.tables[table];
+        let positions = child.column_positions(&fk.columns)?;
+        let mut changes = Vec::with_capacity(hits.len());
+        for row_idx in hits {
+            let mut row = child.row(row_idx).into_owned();
+            for &col_idx in &positions {
+                row[col_idx] = child.accept(col_idx, Value::Null)?;
+            }
+            changes.push((row_idx, row));
+        }
+        Ok(changes)
+    }
+}
+
+/// Whether columns of types `a` and `b` hold the same kind of value, so that
+/// a key in one can match a key in the other: the same type, text of any
+/// length, or decimals of any precision.
+fn same_keys(a: &ValueType, b: &ValueType) -> bool {
+    use ValueType::{Decimal, Text, Varchar};
+    a == b
+        || matches!(
+            (a, b),
+            (Text | Varchar(_), Text | Varchar(_)) | (Decimal { .. }, Decimal { .. })
+        )
+}
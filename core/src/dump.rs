@@ -0,0 +1,250 @@
+//! The engine as a SQL script that rebuilds it, like sqlite3's `.dump`;
+//! see [`Engine::dump`] and [`Engine::restore_from_sql`].
+
+use std::io::{Read, Write};
+
+use crate::columnar::Layout;
+use crate::engine::{Engine, EngineError, Typing};
+use crate::index::IndexKind;
+use crate::parser::{parse_script, statement_error};
+use crate::schema::COMMENT_KEY;
+
+fn io_error(e: std::io::Error) -> EngineError {
+    EngineError::Io(e.to_string())
+}
+
+/// A string as a SQL literal.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+impl Engine {
+    /// Writes statements that rebuild the engine's schemas, tables, rows,
+    /// indexes, foreign keys, views and settings, one per line, for
+    /// [`Engine::restore_from_sql`] or any client to run. Tables and views
+    /// come in name order, each view after the views it reads. Foreign keys
+    /// are added with ALTER TABLE once every table has its rows, since a
+    /// table may come before the one it references.
+    ///
+    /// Bloom filters are left out, having no SQL form yet; [`Engine::save`]
+    /// keeps everything. An AUTO_INCREMENT column counts on from the
+    /// highest value restored.
+    pub fn dump(&self, mut writer: impl Write) -> Result<(), EngineError> {
+        let mut out = Vec::new();
+        if self.default_typing != Typing::default() {
+            out.push(format!("PRAGMA typing = {}", self.default_typing.name()));
+        }
+        if self.bool_ints {
+            out.push("PRAGMA bool_ints = ON".to_string());
+        }
+        for schema in &self.schemas {
+            out.push(format!("CREATE SCHEMA {}", schema));
+        }
+
+        let mut names = self.tables.keys().collect::<Vec<_>>();
+        names.sort();
+        for &name in &names {
+            let restored = self.spill.restored(name, &self.tables[name])?;
+            let table = restored.as_ref().unwrap_or(&self.tables[name]);
+            let auto_increment = table.auto_increment.as_ref().map(|a| a.column.as_str());
+            let mut columns = table
+                .columns
+                .iter()
+                .map(|c| match auto_increment {
+                    Some(column) if column == c.name => {
+                        format!("{} {} AUTO_INCREMENT", c.name, c.col_type)
+                    }
+                    _ => format!("{} {}", c.name, c.col_type),
+                })
+                .collect::<Vec<_>>();
+            if let Some(key) = &table.primary_key {
+                columns.push(format!("PRIMARY KEY ({})", key.join(", ")));
+            }
+            for unique in table.uniques.iter().filter(|u| u.name != "PRIMARY") {
+                columns.push(format!(
+                    "CONSTRAINT {} UNIQUE ({})",
+                    unique.name,
+                    unique.columns.join(", ")
+                ));
+            }
+            let mut options = Vec::new();
+            if table.layout != Layout::Row {
+                options.push(format!("layout = '{}'", table.layout.name()));
+            }
+            if table.compressed {
+                options.push("compression = 'on'".to_string());
+            }
+            if let Some(threshold) = table.overflow {
+                options.push(format!("overflow = '{}'", threshold));
+            }
+            if let Some(ttl) = &table.ttl {
+                options.push(format!("ttl = {}", quote(&ttl.after.to_string())));
+                if let Some(column) = &ttl.column {
+                    options.push(format!("ttl_column = {}", quote(column)));
+                }
+            }
+            let options = if options.is_empty() {
+                String::new()
+            } else {
+                format!(" WITH ({})", options.join(", "))
+            };
+            let partitioning = table
+                .partitioning
+                .as_ref()
+                .map_or(String::new(), |p| format!(" {}", p));
+            out.push(format!(
+                "CREATE TABLE {} ({}){}{}",
+                name,
+                columns.join(", "),
+                options,
+                partitioning
+            ));
+            if table.typing != self.default_typing {
+                out.push(format!("PRAGMA typing({}) = {}", name, table.typing.name()));
+            }
+            if let Some(comment) = table.metadata.get(COMMENT_KEY) {
+                out.push(format!("COMMENT ON TABLE {} IS {}", name, quote(comment)));
+            }
+            for column in &table.columns {
+                if let Some(comment) = column.metadata.get(COMMENT_KEY) {
+                    out.push(format!(
+                        "COMMENT ON COLUMN {}.{} IS {}",
+                        name,
+                        column.name,
+                        quote(comment)
+                    ));
+                }
+            }
+            for row in table.all_rows().iter() {
+                let values = row.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                out.push(format!(
+                    "INSERT INTO {} VALUES ({})",
+                    name,
+                    values.join(", ")
+                ));
+            }
+            let mut indexes = table.indices.iter().collect::<Vec<_>>();
+            indexes.sort_by_key(|(index_name, _)| *index_name);
+            let first = table.columns.first().map(|c| c.name.as_str());
+            for (index_name, index) in indexes {
+                // CREATE TABLE indexes the first column itself.
+                let automatic = Some(index_name.as_str()) == first
+                    && index.columns == [index_name.as_str()]
+                    && index.kind() == IndexKind::Hash
+                    && !index.unique;
+                // So do its constraints.
+                if automatic || table.uniques.iter().any(|u| &u.name == index_name) {
+                    continue;
+                }
+                let kind = match index.kind() {
+                    IndexKind::Ordered => "BTREE",
+                    IndexKind::Hash => "HASH",
+                    IndexKind::Trigram => "TRIGRAM",
+                };
+                out.push(format!(
+                    "CREATE {}INDEX {} ON {} ({}) USING {}",
+                    if index.unique { "UNIQUE " } else { "" },
+                    index_name,
+                    name,
+                    index.columns.join(", "),
+                    kind
+                ));
+            }
+        }
+
+        for name in names {
+            for fk in &self.tables[name].foreign_keys {
+                let ref_columns = if fk.ref_columns.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", fk.ref_columns.join(", "))
+                };
+                out.push(format!(
+                    "ALTER TABLE {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {}{} \
+                     ON DELETE {} ON UPDATE {}",
+                    name,
+                    fk.name,
+                    fk.columns.join(", "),
+                    fk.ref_table,
+                    ref_columns,
+                    fk.on_delete.name(),
+                    fk.on_update.name()
+                ));
+            }
+        }
+
+        // Views, each once the views it reads are defined.
+        let mut pending = self
+            .views
+            .iter()
+            .map(|(name, query)| (name, query, false))
+            .chain(
+                self.materialized
+                    .iter()
+                    .map(|(name, view)| (name, &view.query, true)),
+            )
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|(name, _, _)| *name);
+        while !pending.is_empty() {
+            let ready = pending
+                .iter()
+                .position(|(_, query, _)| {
+                    query
+                        .table_names()
+                        .iter()
+                        .all(|t| !pending.iter().any(|(name, _, _)| *name == t))
+                })
+                .unwrap_or(0);
+            let (name, query, materialized) = pending.remove(ready);
+            if materialized {
+                out.push(format!("CREATE MATERIALIZED VIEW {} AS {}", name, query));
+                if self.materialized[name].auto_refresh {
+                    out.push(format!("PRAGMA auto_refresh({}) = ON", name));
+                }
+            } else {
+                out.push(format!("CREATE VIEW {} AS {}", name, query));
+            }
+        }
+
+        for statement in out {
+            writeln!(writer, "{};", statement).map_err(io_error)?;
+        }
+        writer.flush().map_err(io_error)
+    }
+
+    /// Runs the statements of a SQL script read from `reader`, such as one
+    /// written by [`Engine::dump`], returning how many ran. Statements are
+    /// separated by semicolons. The script runs completely or not at all:
+    /// if a statement fails, the tables, views and schemas are put back as
+    /// they were.
+    pub fn restore_from_sql(&mut self, mut reader: impl Read) -> Result<usize, EngineError> {
+        let mut script = String::new();
+        reader.read_to_string(&mut script).map_err(io_error)?;
+        let statements = match parse_script(&script) {
+            Ok((_, statements)) => statements,
+            Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge => {
+                return Err(statement_error(&script, nom::Err::Failure(e)));
+            }
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                let line = script[..script.len() - e.input.len()].matches('\n').count() + 1;
+                return Err(EngineError::InvalidQuery(format!(
+                    "cannot parse the statement at line {}",
+                    line
+                )));
+            }
+            Err(nom::Err::Incomplete(_)) => {
+                return Err(EngineError::InvalidQuery("incomplete script".to_string()))
+            }
+        };
+        let count = statements.len();
+        let state = self.save_state()?;
+        for statement in statements {
+            if let Err(e) = self.execute(statement) {
+                self.restore_state(state);
+                self.compact()?;
+                return Err(e);
+            }
+        }
+        Ok(count)
+    }
+}
@@ -1,40 +1,738 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::parser::{Condition, Operator, Query, SelectQuery};
+use crate::bloom::BloomFilter;
+use crate::columnar::{ColumnStore, Layout, Spilled};
+use crate::custom::CustomValue;
+use crate::decimal::{Decimal, MAX_PRECISION};
+use crate::expr::{infer_type, Binder, BoundExpr, Relation, ScalarFunc, UserFunction};
+use crate::index::{check_hint, AccessPath, Index, IndexKind};
+use crate::join;
+use crate::json;
+use crate::migrate::AppliedMigration;
+use crate::parser::{
+    AlterTableAction, AlterTableQuery, Condition, CreateTableQuery, Expr, Operator, PragmaQuery,
+    SelectItem, SelectQuery, TableRef,
+};
+use crate::partition::Partitioning;
+use crate::plan_cache::PlanCache;
+use crate::schema::COMMENT_KEY;
+use crate::sort::SortCounters;
+use crate::stats::Analysis;
+use crate::storage::{Attached, TableStorage};
+use crate::table_log::TableLogs;
+use crate::temporal::{self, Interval, MICROS_PER_DAY, MICROS_PER_SECOND};
+use crate::ttl::Ttl;
+use crate::view::MaterializedView;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Value {
     Int(i64),
+    /// A 128-bit integer, for IDs and counters beyond the range of `Int`,
+    /// including every unsigned 64-bit value.
+    BigInt(i128),
+    Float(#[serde(with = "float")] f64),
+    Decimal(Decimal),
     Text(String),
     Bool(bool),
+    /// Days since 1970-01-01.
+    Date(i32),
+    /// Microseconds since midnight.
+    Time(i64),
+    /// Microseconds since 1970-01-01 00:00:00 UTC.
+    Timestamp(i64),
+    Interval(Interval),
+    Uuid(Uuid),
+    Json(serde_json::Value),
+    List(Vec<Value>),
+    Enum(EnumValue),
+    /// A value of an application-defined type; see [`crate::CustomType`].
+    Custom(Box<dyn CustomValue>),
     Null,
+    /// A NULL stored in, or cast to, a column of the given type. It equals
+    /// every other NULL and reports the type from [`Value::value_type`].
+    TypedNull(ValueType),
+}
+
+/// Serializes floats as numbers, and NaN and the infinities, which JSON has
+/// no number for, as the strings `NaN`, `inf` and `-inf`.
+mod float {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(f: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if f.is_finite() {
+            serializer.serialize_f64(*f)
+        } else {
+            serializer.collect_str(f)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(f64),
+            Text(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Number(f) => Ok(f),
+            Repr::Text(s) => s
+                .parse()
+                .map_err(|_| de::Error::custom(format!("invalid float: {}", s))),
+        }
+    }
+}
+
+/// A value of an ENUM column, stored as the position of its label in the
+/// column's label list; the list itself is shared with the column type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnumValue {
+    labels: Arc<[String]>,
+    index: u16,
+}
+
+impl EnumValue {
+    /// The value spelled `label`, or `None` if it is not one of `labels`.
+    pub fn new(labels: &Arc<[String]>, label: &str) -> Option<Self> {
+        let index = labels.iter().position(|l| l == label)?;
+        Some(EnumValue {
+            labels: labels.clone(),
+            index: u16::try_from(index).ok()?,
+        })
+    }
+
+    pub fn label(&self) -> &str {
+        &self.labels[self.index as usize]
+    }
+
+    /// Position of the label in declaration order, starting at 0.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn labels(&self) -> &Arc<[String]> {
+        &self.labels
+    }
+
+    /// Position of `label` among this value's labels.
+    fn position_of(&self, label: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .position(|l| l == label)
+            .map(|i| i as u16)
+    }
+}
+
+impl PartialEq for EnumValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+            && (Arc::ptr_eq(&self.labels, &other.labels) || self.labels == other.labels)
+    }
+}
+
+impl Eq for EnumValue {}
+
+impl Hash for EnumValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+/// Canonical bit pattern of a float, so that `-0.0` equals `0.0` and all
+/// NaNs equal each other; this keeps `Value` usable as an index key.
+fn float_bits(f: f64) -> u64 {
+    if f == 0.0 {
+        0
+    } else if f.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        f.to_bits()
+    }
+}
+
+/// Total order on floats consistent with `float_bits`: NaN sorts after
+/// every other value.
+pub(crate) fn cmp_float(a: f64, b: f64) -> Ordering {
+    f64::from_bits(float_bits(a)).total_cmp(&f64::from_bits(float_bits(b)))
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => float_bits(*a) == float_bits(*b),
+            (Value::Decimal(a), Value::Decimal(b)) => a == b,
+            (Value::Text(a), Value::Text(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Date(a), Value::Date(b)) => a == b,
+            (Value::Time(a), Value::Time(b)) => a == b,
+            (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+            (Value::Interval(a), Value::Interval(b)) => a == b,
+            (Value::Uuid(a), Value::Uuid(b)) => a == b,
+            (Value::Json(a), Value::Json(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Enum(a), Value::Enum(b)) => a == b,
+            (Value::Custom(a), Value::Custom(b)) => a.eq_dyn(b.as_ref()),
+            (a, b) if a.is_null() => b.is_null(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Typed and untyped NULLs are equal, so neither hashes its variant.
+        if self.is_null() {
+            return;
+        }
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Int(n) => n.hash(state),
+            Value::BigInt(n) => n.hash(state),
+            Value::Float(f) => float_bits(*f).hash(state),
+            Value::Decimal(d) => d.hash(state),
+            Value::Text(s) => s.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Date(d) => d.hash(state),
+            Value::Time(t) | Value::Timestamp(t) => t.hash(state),
+            Value::Interval(i) => i.hash(state),
+            Value::Uuid(u) => u.hash(state),
+            // Object keys are kept sorted, so equal documents print the same.
+            Value::Json(j) => j.to_string().hash(state),
+            Value::List(items) => items.hash(state),
+            Value::Enum(e) => e.hash(state),
+            Value::Custom(c) => {
+                c.type_name().hash(state);
+                c.hash_dyn(state);
+            }
+            Value::Null | Value::TypedNull(_) => {}
+        }
+    }
+}
+
+impl Value {
+    /// Where the value's type sorts: its kind, then its type within the kind.
+    pub(crate) fn type_rank(&self) -> (u8, u8) {
+        match self {
+            Value::Null | Value::TypedNull(_) => (0, 0),
+            Value::Bool(_) => (1, 0),
+            Value::Int(_) => (2, 0),
+            Value::BigInt(_) => (2, 1),
+            Value::Decimal(_) => (2, 2),
+            Value::Float(_) => (2, 3),
+            Value::Text(_) => (3, 0),
+            Value::Date(_) => (4, 0),
+            Value::Timestamp(_) => (4, 1),
+            Value::Time(_) => (5, 0),
+            Value::Interval(_) => (6, 0),
+            Value::Uuid(_) => (7, 0),
+            Value::Enum(_) => (8, 0),
+            Value::Json(_) => (9, 0),
+            Value::List(_) => (10, 0),
+            Value::Custom(_) => (11, 0),
+        }
+    }
+}
+
+/// The order used by ORDER BY, MIN and MAX. Values of different kinds sort
+/// as NULL, booleans, numbers, text, dates and timestamps, times,
+/// intervals, UUIDs, enums, JSON, lists, then custom values. Numbers of any
+/// type order by value with NaN after every other number, and a date sorts
+/// as midnight among timestamps. Values of different types that are equal
+/// by value, such as `1` and `1.0`, sort narrower type first so that the
+/// order agrees with `==`.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (kind_a, type_a) = self.type_rank();
+        let (kind_b, type_b) = other.type_rank();
+        kind_a
+            .cmp(&kind_b)
+            .then_with(|| match (self, other) {
+                (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+                (Value::Text(a), Value::Text(b)) => a.cmp(b),
+                (Value::Interval(a), Value::Interval(b)) => a.cmp(b),
+                (Value::Uuid(a), Value::Uuid(b)) => a.cmp(b),
+                (Value::Enum(a), Value::Enum(b)) => {
+                    a.labels.cmp(&b.labels).then(a.index.cmp(&b.index))
+                }
+                (Value::Json(a), Value::Json(b)) => a.to_string().cmp(&b.to_string()),
+                (Value::List(a), Value::List(b)) => a.cmp(b),
+                (Value::Custom(a), Value::Custom(b)) => a
+                    .type_name()
+                    .cmp(b.type_name())
+                    .then_with(|| a.cmp_dyn(b.as_ref()).unwrap_or(Ordering::Equal)),
+                _ => Engine::cmp_numeric(self, other)
+                    .or_else(|| Engine::cmp_temporal(self, other))
+                    .unwrap_or(Ordering::Equal),
+            })
+            .then(type_a.cmp(&type_b))
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Renders the value as a SQL literal, e.g. `'it''s'`, `DATE '2024-01-31'`
+/// or `NULL`. Parsing the text back with [`FromStr`] gives an equal value,
+/// except that enum and custom values come back as their text.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        crate::parser::fmt_literal(f, self)
+    }
+}
+
+/// Error returned when text is not a single SQL literal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseValueError;
+
+impl FromStr for Value {
+    type Err = ParseValueError;
+
+    /// Parses one SQL literal in any of the forms accepted in queries.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::parser::parse_literal(s).ok_or(ParseValueError)
+    }
+}
+
+macro_rules! value_from {
+    ($($source:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$source> for Value {
+                fn from(v: $source) -> Self {
+                    Value::$variant(v.into())
+                }
+            }
+        )*
+    };
+}
+
+value_from! {
+    i32 => Int,
+    i64 => Int,
+    u64 => BigInt,
+    i128 => BigInt,
+    f64 => Float,
+    bool => Bool,
+    &str => Text,
+    String => Text,
+    Decimal => Decimal,
+    Uuid => Uuid,
+    Interval => Interval,
+    serde_json::Value => Json,
+}
+
+/// `None` becomes NULL.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+// Conversions out of a `Value` fail with `InvalidCast` unless the value has
+// the matching type; integers also convert between widths when they fit, and
+// any number converts to `f64`. NULL converts only to `Option`.
+
+impl TryFrom<Value> for i64 {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Int(n) => Ok(n),
+            Value::BigInt(n) if i64::try_from(n).is_ok() => Ok(n as i64),
+            v => Err(v.conversion_error(ValueType::Int)),
+        }
+    }
+}
+
+impl TryFrom<Value> for i128 {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Int(n) => Ok(n as i128),
+            Value::BigInt(n) => Ok(n),
+            v => Err(v.conversion_error(ValueType::BigInt)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Float(f) => Ok(f),
+            Value::Int(n) => Ok(n as f64),
+            Value::BigInt(n) => Ok(n as f64),
+            Value::Decimal(d) => Ok(d.to_f64()),
+            v => Err(v.conversion_error(ValueType::Float)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Bool(b) => Ok(b),
+            v => Err(v.conversion_error(ValueType::Bool)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Text(s) => Ok(s),
+            v => Err(v.conversion_error(ValueType::Text)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Decimal {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Decimal(d) => Ok(d),
+            v => Err(v.conversion_error(ValueType::Decimal {
+                precision: MAX_PRECISION,
+                scale: 0,
+            })),
+        }
+    }
+}
+
+impl TryFrom<Value> for Uuid {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Uuid(u) => Ok(u),
+            v => Err(v.conversion_error(ValueType::Uuid)),
+        }
+    }
+}
+
+impl<T: TryFrom<Value, Error = EngineError>> TryFrom<Value> for Option<T> {
+    type Error = EngineError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Null | Value::TypedNull(_) => Ok(None),
+            v => T::try_from(v).map(Some),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ValueType {
+    /// A 64-bit integer, spelled INT, INTEGER or BIGINT as in standard SQL.
     Int,
+    /// A 128-bit integer, spelled HUGEINT or INT128. BIGINT is not this
+    /// type: it is 64 bits wide elsewhere, and so [`ValueType::Int`] here.
+    BigInt,
+    Float,
+    /// Exact number with at most `precision` digits, `scale` of them after
+    /// the decimal point.
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
     Text,
+    /// Text of at most this many characters.
+    Varchar(u32),
     Bool,
+    Date,
+    Time,
+    Timestamp,
+    Interval,
+    Uuid,
+    Json,
+    List,
+    /// One of a fixed list of text labels, e.g. `ENUM('new', 'done')`.
+    Enum(Arc<[String]>),
+    /// An application-defined type, by its [`crate::CustomType::NAME`].
+    Custom(String),
     Null,
 }
 
+/// Formats a type as CREATE TABLE spells it.
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Int => f.write_str("INT"),
+            ValueType::BigInt => f.write_str("HUGEINT"),
+            ValueType::Float => f.write_str("FLOAT"),
+            ValueType::Decimal { precision, scale } => {
+                write!(f, "DECIMAL({}, {})", precision, scale)
+            }
+            ValueType::Text => f.write_str("TEXT"),
+            ValueType::Varchar(max) => write!(f, "VARCHAR({})", max),
+            ValueType::Bool => f.write_str("BOOL"),
+            ValueType::Date => f.write_str("DATE"),
+            ValueType::Time => f.write_str("TIME"),
+            ValueType::Timestamp => f.write_str("TIMESTAMP"),
+            ValueType::Interval => f.write_str("INTERVAL"),
+            ValueType::Uuid => f.write_str("UUID"),
+            ValueType::Json => f.write_str("JSON"),
+            ValueType::List => f.write_str("LIST"),
+            ValueType::Enum(labels) => {
+                f.write_str("ENUM(")?;
+                for (i, label) in labels.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "'{}'", label.replace('\'', "''"))?;
+                }
+                f.write_str(")")
+            }
+            ValueType::Custom(name) => f.write_str(name),
+            ValueType::Null => f.write_str("NULL"),
+        }
+    }
+}
+
 impl Value {
     pub fn value_type(&self) -> ValueType {
         match self {
             Value::Int(_) => ValueType::Int,
+            Value::BigInt(_) => ValueType::BigInt,
+            Value::Float(_) => ValueType::Float,
+            Value::Decimal(d) => ValueType::Decimal {
+                precision: MAX_PRECISION,
+                scale: d.scale(),
+            },
             Value::Text(_) => ValueType::Text,
             Value::Bool(_) => ValueType::Bool,
+            Value::Date(_) => ValueType::Date,
+            Value::Time(_) => ValueType::Time,
+            Value::Timestamp(_) => ValueType::Timestamp,
+            Value::Interval(_) => ValueType::Interval,
+            Value::Uuid(_) => ValueType::Uuid,
+            Value::Json(_) => ValueType::Json,
+            Value::List(_) => ValueType::List,
+            Value::Enum(e) => ValueType::Enum(e.labels.clone()),
+            Value::Custom(c) => ValueType::Custom(c.type_name().to_string()),
             Value::Null => ValueType::Null,
+            Value::TypedNull(t) => t.clone(),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null | Value::TypedNull(_))
+    }
+
+    fn conversion_error(self, target: ValueType) -> EngineError {
+        EngineError::InvalidCast {
+            value: self,
+            target,
+        }
+    }
+
+    /// The value as a float if it is a number of any type.
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::BigInt(n) => Some(*n as f64),
+            Value::Float(f) => Some(*f),
+            Value::Decimal(d) => Some(d.to_f64()),
+            _ => None,
         }
     }
+
+    /// Converts the value to `target`. NULL casts to NULL of any type; text
+    /// converts to a number or Bool only when it spells a valid literal, and
+    /// floats and decimals convert to Int or BigInt by truncation when the
+    /// result fits. Numbers converted to DECIMAL are rounded to its scale
+    /// and fail with `NumericOverflow` when they exceed its precision.
+    /// Temporal values convert to and from their ISO 8601 text form, and
+    /// timestamps to and from Int as seconds since the epoch, and intervals
+    /// to and from text such as `1 day 02:00:00`. UUIDs convert to and from
+    /// their hyphenated text form, and JSON documents to and from their
+    /// text.
+    /// Casting to VARCHAR(n) converts to text and cuts it to n characters.
+    /// Enum values convert to their label, or to Int as their 1-based
+    /// position, and text converts to an enum when it is one of its labels.
+    pub fn cast(&self, target: &ValueType) -> Result<Value, EngineError> {
+        let invalid = || EngineError::InvalidCast {
+            value: self.clone(),
+            target: target.clone(),
+        };
+        Ok(match (self, target) {
+            (v, t) if v.is_null() => Value::TypedNull(t.clone()),
+            (v, t) if v.value_type() == *t => v.clone(),
+            (Value::Int(n), ValueType::Text) => Value::Text(n.to_string()),
+            (Value::Int(n), ValueType::Bool) => Value::Bool(*n != 0),
+            (Value::Int(n), ValueType::Float) => Value::Float(*n as f64),
+            (Value::Float(f), ValueType::Int) => {
+                let t = f.trunc();
+                if !(-9.223_372_036_854_776e18..9.223_372_036_854_776e18).contains(&t) {
+                    return Err(invalid());
+                }
+                Value::Int(t as i64)
+            }
+            (Value::Float(f), ValueType::Text) => Value::Text(f.to_string()),
+            (Value::Int(n), ValueType::BigInt) => Value::BigInt(*n as i128),
+            (Value::BigInt(n), ValueType::Int) => {
+                Value::Int(i64::try_from(*n).map_err(|_| invalid())?)
+            }
+            (Value::BigInt(n), ValueType::Float) => Value::Float(*n as f64),
+            (Value::BigInt(n), ValueType::Text) => Value::Text(n.to_string()),
+            (Value::Float(f), ValueType::BigInt) => {
+                let t = f.trunc();
+                if !(-1.701_411_834_604_692_3e38..1.701_411_834_604_692_3e38).contains(&t) {
+                    return Err(invalid());
+                }
+                Value::BigInt(t as i128)
+            }
+            (Value::Decimal(d), ValueType::Int) => {
+                Value::Int(d.trunc_to_i64().ok_or_else(invalid)?)
+            }
+            (Value::Decimal(d), ValueType::BigInt) => Value::BigInt(d.trunc_to_i128()),
+            (Value::Decimal(d), ValueType::Float) => Value::Float(d.to_f64()),
+            (Value::Decimal(d), ValueType::Text) => Value::Text(d.to_string()),
+            (v, ValueType::Varchar(max)) => match v.cast(&ValueType::Text)? {
+                Value::Text(s) => Value::Text(s.chars().take(*max as usize).collect()),
+                other => other,
+            },
+            (v, ValueType::Decimal { precision, scale }) => {
+                let d = match v {
+                    Value::Int(n) => Decimal::from(*n),
+                    Value::BigInt(n) => Decimal::new(*n, 0).ok_or(EngineError::NumericOverflow)?,
+                    Value::Float(f) => Decimal::from_f64(*f).ok_or_else(invalid)?,
+                    Value::Decimal(d) => *d,
+                    Value::Text(s) => s.trim().parse().map_err(|_| invalid())?,
+                    _ => return Err(invalid()),
+                };
+                Value::Decimal(
+                    d.fit(*precision, *scale)
+                        .ok_or(EngineError::NumericOverflow)?,
+                )
+            }
+            (Value::Bool(b), ValueType::Int) => Value::Int(*b as i64),
+            (Value::Bool(b), ValueType::Text) => {
+                Value::Text(if *b { "TRUE" } else { "FALSE" }.to_string())
+            }
+            (Value::Text(s), ValueType::Int) => {
+                Value::Int(s.trim().parse().map_err(|_| invalid())?)
+            }
+            (Value::Text(s), ValueType::BigInt) => {
+                Value::BigInt(s.trim().parse().map_err(|_| invalid())?)
+            }
+            (Value::Text(s), ValueType::Float) => {
+                Value::Float(s.trim().parse().map_err(|_| invalid())?)
+            }
+            (Value::Text(s), ValueType::Date) => {
+                Value::Date(temporal::parse_date(s.trim()).ok_or_else(invalid)?)
+            }
+            (Value::Text(s), ValueType::Time) => {
+                Value::Time(temporal::parse_time(s.trim()).ok_or_else(invalid)?)
+            }
+            (Value::Text(s), ValueType::Timestamp) => {
+                Value::Timestamp(temporal::parse_timestamp(s.trim()).ok_or_else(invalid)?)
+            }
+            (Value::Date(d), ValueType::Text) => Value::Text(temporal::format_date(*d)),
+            (Value::Time(t), ValueType::Text) => Value::Text(temporal::format_time(*t)),
+            (Value::Timestamp(ts), ValueType::Text) => Value::Text(temporal::format_timestamp(*ts)),
+            (Value::Text(s), ValueType::Interval) => {
+                Value::Interval(s.parse().map_err(|_| invalid())?)
+            }
+            (Value::Interval(i), ValueType::Text) => Value::Text(i.to_string()),
+            (Value::Date(d), ValueType::Timestamp) => Value::Timestamp(*d as i64 * MICROS_PER_DAY),
+            (Value::Timestamp(ts), ValueType::Date) => {
+                Value::Date(temporal::timestamp_date(*ts).ok_or_else(invalid)?)
+            }
+            (Value::Timestamp(ts), ValueType::Time) => Value::Time(ts.rem_euclid(MICROS_PER_DAY)),
+            (Value::Timestamp(ts), ValueType::Int) => Value::Int(ts.div_euclid(MICROS_PER_SECOND)),
+            (Value::Int(n), ValueType::Timestamp) => Value::Timestamp(
+                n.checked_mul(MICROS_PER_SECOND)
+                    .ok_or(EngineError::NumericOverflow)?,
+            ),
+            (Value::Text(s), ValueType::Uuid) => {
+                Value::Uuid(Uuid::parse_str(s.trim()).map_err(|_| invalid())?)
+            }
+            (Value::Uuid(u), ValueType::Text) => Value::Text(u.to_string()),
+            (Value::Text(s), ValueType::Json) => {
+                Value::Json(serde_json::from_str(s).map_err(|_| invalid())?)
+            }
+            (Value::Json(j), ValueType::Text) => Value::Text(j.to_string()),
+            (Value::Enum(e), ValueType::Text) => Value::Text(e.label().to_string()),
+            (Value::Enum(e), ValueType::Int) => Value::Int(e.index as i64 + 1),
+            (Value::Text(s), ValueType::Enum(labels)) => {
+                Value::Enum(EnumValue::new(labels, s).ok_or_else(invalid)?)
+            }
+            (Value::Enum(e), ValueType::Enum(labels)) => {
+                Value::Enum(EnumValue::new(labels, e.label()).ok_or_else(invalid)?)
+            }
+            (Value::Text(s), ValueType::Bool) => match s.trim().to_ascii_uppercase().as_str() {
+                "TRUE" | "1" => Value::Bool(true),
+                "FALSE" | "0" => Value::Bool(false),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        })
+    }
 }
 
 pub type Row = Vec<Value>;
 
+/// Describes one column of a query result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultColumn {
+    /// The name the column is reported under: its alias if it has one,
+    /// otherwise the referenced column's name or the expression's text.
+    pub name: String,
+    /// The `(table, column)` the values were read from when the column is a
+    /// plain column reference; `table` is the alias used in FROM, if any.
+    /// `None` for computed columns.
+    pub source: Option<(String, String)>,
+}
+
+/// Rows returned by a SELECT along with a description of their columns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub columns: Vec<ResultColumn>,
+    pub rows: Vec<Row>,
+}
+
+impl QueryResult {
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EngineError {
     TableNotFound(String),
+    /// A table or view with the name already exists.
+    TableExists(String),
+    ViewNotFound(String),
+    SchemaNotFound(String),
+    SchemaExists(String),
     ColumnNotFound(String),
     ValueCountMismatch,
     TypeMismatch {
@@ -42,77 +740,877 @@ pub enum EngineError {
         expected: ValueType,
         found: ValueType,
     },
+    AmbiguousColumn(String),
+    InvalidQuery(String),
+    UnknownFunction(String),
+    InvalidCast {
+        value: Value,
+        target: ValueType,
+    },
+    InvalidOperation(String),
+    InvalidPattern(String),
+    DivisionByZero,
+    NumericOverflow,
+    /// Text is longer than its VARCHAR column allows; lengths count
+    /// characters.
+    ValueTooLong {
+        column: String,
+        max: usize,
+        actual: usize,
+    },
+    /// Text stored in an ENUM column is not one of its labels.
+    InvalidEnumValue {
+        column: String,
+        value: String,
+    },
+    /// A row would reference a key missing from the referenced table, or a
+    /// referenced row would be deleted or changed while the foreign key
+    /// restricts it. `value` is the key involved.
+    ForeignKeyViolation {
+        constraint: String,
+        value: Value,
+    },
+    /// A primary key column would hold NULL.
+    NotNullViolation {
+        column: String,
+    },
+    /// A row would repeat the key of another under a UNIQUE constraint.
+    /// `value` is the key: the column's value, or a list of values for a
+    /// constraint over several columns.
+    UniqueViolation {
+        constraint: String,
+        value: Value,
+    },
+    /// A statement of migration `version` failed with `error`; the
+    /// migration was undone.
+    MigrationFailed {
+        version: u32,
+        error: Box<EngineError>,
+    },
+    /// Reading or writing a file failed; holds the system's message.
+    Io(String),
+    /// A snapshot or table log is not one this version can read.
+    InvalidSnapshot(String),
+    /// Data read back from a file does not match the checksum written with
+    /// it, so the file was damaged after it was written. `location` names
+    /// the file and, where there is one, the page, record or entry.
+    Corruption {
+        location: String,
+    },
+    /// A page of a [`PagedTable`](crate::PagedTable) file does not hold
+    /// what it should: the file was damaged, or is not a paged table.
+    InvalidPage(u64),
+    /// Data being imported is malformed at `line`, counting from 1.
+    InvalidInput {
+        line: usize,
+        reason: String,
+    },
+    /// An integer literal in a statement does not fit the integer type it
+    /// must have; holds the literal as written.
+    LiteralOutOfRange(String),
+    /// A statement would change an engine opened with
+    /// [`Engine::open_read_only`].
+    ReadOnly,
+    /// A thread panicked while it held a [`SharedEngine`](crate::SharedEngine),
+    /// or one of its tables, to itself, and may have left it half changed.
+    Poisoned,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: String,
     pub col_type: ValueType,
+    /// Free-form key/value notes, such as the `comment` set by COMMENT ON.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Column {
+    /// Checks a value being stored in this column. NULL is always accepted
+    /// and numbers convert between numeric types: DECIMAL columns round
+    /// them to the column's scale, FLOAT columns take the nearest float, and
+    /// integer columns take only numbers with no fractional part, failing
+    /// with `NumericOverflow` when out of range. VARCHAR columns take text
+    /// up to their length, ENUM columns take text naming one of their
+    /// labels and UUID columns take a UUID written as text.
+    pub(crate) fn accept(&self, value: Value) -> Result<Value, EngineError> {
+        match (&self.col_type, value) {
+            (t, v) if v.is_null() => Ok(Value::TypedNull(t.clone())),
+            (t, v) if *t == v.value_type() => Ok(v),
+            (ValueType::Decimal { .. } | ValueType::Float, v) if v.as_f64().is_some() => {
+                v.cast(&self.col_type)
+            }
+            (ValueType::Int | ValueType::BigInt, v) if v.as_f64().is_some() => {
+                let n = v
+                    .cast(&self.col_type)
+                    .map_err(|_| EngineError::NumericOverflow)?;
+                if Engine::compare(&n, &Operator::Eq, &v) != Some(true) {
+                    return Err(EngineError::TypeMismatch {
+                        column: self.name.clone(),
+                        expected: self.col_type.clone(),
+                        found: v.value_type(),
+                    });
+                }
+                Ok(n)
+            }
+            (ValueType::Varchar(max), Value::Text(s)) => {
+                let actual = s.chars().count();
+                if actual > *max as usize {
+                    return Err(EngineError::ValueTooLong {
+                        column: self.name.clone(),
+                        max: *max as usize,
+                        actual,
+                    });
+                }
+                Ok(Value::Text(s))
+            }
+            (ValueType::Uuid, Value::Text(s)) => match Uuid::parse_str(s.trim()) {
+                Ok(u) => Ok(Value::Uuid(u)),
+                Err(_) => Err(EngineError::TypeMismatch {
+                    column: self.name.clone(),
+                    expected: ValueType::Uuid,
+                    found: ValueType::Text,
+                }),
+            },
+            (ValueType::Enum(labels), Value::Text(s)) => EnumValue::new(labels, &s)
+                .map(Value::Enum)
+                .ok_or_else(|| EngineError::InvalidEnumValue {
+                    column: self.name.clone(),
+                    value: s,
+                }),
+            (t, v) => Err(EngineError::TypeMismatch {
+                column: self.name.clone(),
+                expected: t.clone(),
+                found: v.value_type(),
+            }),
+        }
+    }
+}
+
+/// How strictly a table checks the types of the values stored in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Typing {
+    /// Values must have the column's type, apart from conversions between
+    /// numeric types and from text to enum labels.
+    #[default]
+    Strict,
+    /// Any value can be stored in any column, SQLite style: text is
+    /// converted to the column's type when it can be, and values that do
+    /// not fit are stored as they are.
+    Flexible,
+}
+
+impl Typing {
+    /// Parses the mode's name as used by `PRAGMA typing`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Typing> {
+        match name.to_ascii_uppercase().as_str() {
+            "STRICT" => Some(Typing::Strict),
+            "FLEXIBLE" => Some(Typing::Flexible),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Typing::Strict => "STRICT",
+            Typing::Flexible => "FLEXIBLE",
+        }
+    }
+}
+
+/// A UNIQUE constraint over one or more columns of a table. It is enforced
+/// by the unique index of the same name; rows with a NULL in any of the
+/// columns are exempt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UniqueConstraint {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+/// The values of a row at `positions` as a single key: the value itself for
+/// one column, or a list for several. `None` if any of them is NULL.
+pub(crate) fn row_key(positions: &[usize], row: &Row) -> Option<Value> {
+    let mut values = positions.iter().map(|&i| &row[i]);
+    if positions.len() == 1 {
+        return values.next().filter(|v| !v.is_null()).cloned();
+    }
+    values
+        .map(|v| (!v.is_null()).then(|| v.clone()))
+        .collect::<Option<Vec<_>>>()
+        .map(Value::List)
+}
+
+/// What happens to referencing rows when the row they reference is deleted
+/// or has its key changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReferentialAction {
+    /// Fail the statement.
+    #[default]
+    Restrict,
+    /// Delete the referencing rows, or change their key to match.
+    Cascade,
+    /// Set the referencing columns to NULL.
+    SetNull,
+}
+
+impl ReferentialAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReferentialAction::Restrict => "RESTRICT",
+            ReferentialAction::Cascade => "CASCADE",
+            ReferentialAction::SetNull => "SET NULL",
+        }
+    }
+}
+
+/// A FOREIGN KEY constraint: every row's `columns` must match the
+/// `ref_columns` of some row in `ref_table`, unless one of them is NULL.
+/// The referenced columns must carry a UNIQUE constraint, whose index is
+/// used for the checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub ref_table: String,
+    pub ref_columns: Vec<String>,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+}
+
+impl ForeignKey {
+    /// A foreign key that restricts both deletes and updates.
+    pub fn new(name: &str, columns: &[&str], ref_table: &str, ref_columns: &[&str]) -> Self {
+        let owned = |cs: &[&str]| cs.iter().map(|c| c.to_string()).collect();
+        ForeignKey {
+            name: name.to_string(),
+            columns: owned(columns),
+            ref_table: ref_table.to_string(),
+            ref_columns: owned(ref_columns),
+            on_delete: ReferentialAction::Restrict,
+            on_update: ReferentialAction::Restrict,
+        }
+    }
+
+    pub fn on_delete(mut self, action: ReferentialAction) -> Self {
+        self.on_delete = action;
+        self
+    }
+
+    pub fn on_update(mut self, action: ReferentialAction) -> Self {
+        self.on_update = action;
+        self
+    }
+}
+
+/// An integer column that is given the next number in sequence whenever a
+/// row is inserted with it NULL or left out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoIncrement {
+    pub column: String,
+    /// The highest value generated or inserted so far, wide enough for
+    /// every value of a HUGEINT column.
+    pub last: i128,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Table {
     pub columns: Vec<Column>,
-    pub rows: Vec<Row>,
-    pub indices: HashMap<String, HashMap<Value, Vec<usize>>>,
+    /// The rows of a table with the row layout, in a [`MemoryStorage`]; a
+    /// column table keeps them elsewhere, and [`Table::all_rows`] reads
+    /// either.
+    ///
+    /// [`MemoryStorage`]: crate::storage::MemoryStorage
+    #[serde(rename = "rows")]
+    pub(crate) storage: TableStorage,
+    #[serde(default)]
+    pub layout: Layout,
+    /// Whether the text columns are dictionary encoded; see
+    /// [`Table::set_compressed`].
+    #[serde(default)]
+    pub compressed: bool,
+    /// Texts of more bytes than this are kept out of line; see
+    /// [`Table::set_overflow`].
+    #[serde(default)]
+    pub overflow: Option<usize>,
+    /// The rows of a table with the column layout, shared by copies of the
+    /// table until one of them changes.
+    #[serde(default)]
+    pub(crate) store: Arc<ColumnStore>,
+    /// The texts a table with the row layout keeps out of line; see
+    /// [`Table::set_overflow`].
+    #[serde(default)]
+    pub(crate) spilled: Arc<Spilled>,
+    /// Secondary indexes by name; see [`Table::add_index`].
+    pub indices: HashMap<String, Index>,
+    #[serde(default)]
+    pub typing: Typing,
+    #[serde(default)]
+    pub uniques: Vec<UniqueConstraint>,
+    /// Foreign keys from this table to others; see
+    /// [`Engine::add_foreign_key`].
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    #[serde(default)]
+    pub auto_increment: Option<AutoIncrement>,
+    /// Columns of the primary key, whose UNIQUE constraint is named
+    /// `PRIMARY`; see [`Table::set_primary_key`].
+    #[serde(default)]
+    pub primary_key: Option<Vec<String>>,
+    /// Free-form key/value notes, such as the `comment` set by COMMENT ON.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    /// What the last ANALYZE found; see [`Engine::analyze`].
+    #[serde(default)]
+    pub stats: Option<Analysis>,
+    /// Bloom filters by column name; see [`Table::add_bloom_filter`].
+    #[serde(default)]
+    pub bloom_filters: HashMap<String, BloomFilter>,
+    /// How the rows are split into partitions; see
+    /// [`Table::set_partitioning`].
+    #[serde(default)]
+    pub partitioning: Option<Partitioning>,
+    /// Positions of the rows of each partition, in table order; worked
+    /// out again when the table is read back.
+    #[serde(skip)]
+    pub(crate) segments: Vec<Vec<usize>>,
+    /// How long the rows live; see [`Table::set_ttl`].
+    #[serde(default)]
+    pub ttl: Option<Ttl>,
+    /// When each row was inserted, in microseconds since the Unix epoch,
+    /// kept while the TTL counts from it.
+    #[serde(default)]
+    pub(crate) inserted: Vec<i64>,
 }
 
 impl Table {
     pub fn new(columns: Vec<(String, ValueType)>) -> Self {
         let cols = columns
             .into_iter()
-            .map(|(name, col_type)| Column { name, col_type })
+            .map(|(name, col_type)| Column {
+                name,
+                col_type,
+                metadata: BTreeMap::new(),
+            })
             .collect::<Vec<_>>();
         Self {
             columns: cols,
-            rows: Vec::new(),
+            storage: TableStorage::default(),
+            layout: Layout::Row,
+            compressed: false,
+            overflow: None,
+            store: Arc::default(),
+            spilled: Arc::default(),
             indices: HashMap::new(),
+            typing: Typing::Strict,
+            uniques: Vec::new(),
+            foreign_keys: Vec::new(),
+            auto_increment: None,
+            primary_key: None,
+            metadata: BTreeMap::new(),
+            stats: None,
+            bloom_filters: HashMap::new(),
+            partitioning: None,
+            segments: Vec::new(),
+            ttl: None,
+            inserted: Vec::new(),
         }
     }
 
-    pub fn create_index(&mut self, column: &str) {
-        if let Some(pos) = self.columns.iter().position(|c| c.name == column) {
-            let mut map: HashMap<Value, Vec<usize>> = HashMap::new();
-            for (idx, row) in self.rows.iter().enumerate() {
-                if let Some(val) = row.get(pos) {
-                    map.entry(val.clone()).or_default().push(idx);
-                }
-            }
-            self.indices.insert(column.to_string(), map);
-        }
+    /// Positions of the named columns.
+    pub(crate) fn column_positions<S: AsRef<str>>(
+        &self,
+        names: &[S],
+    ) -> Result<Vec<usize>, EngineError> {
+        names
+            .iter()
+            .map(|name| {
+                let name = name.as_ref();
+                self.columns
+                    .iter()
+                    .position(|c| c.name == name)
+                    .ok_or_else(|| EngineError::ColumnNotFound(name.to_string()))
+            })
+            .collect()
     }
 
-    pub fn insert(&mut self, values: Row) {
-        let row_idx = self.rows.len();
-        for (col_idx, value) in values.iter().enumerate() {
-            if let Some(col) = self.columns.get(col_idx) {
-                if let Some(index) = self.indices.get_mut(&col.name) {
-                    index.entry(value.clone()).or_default().push(row_idx);
-                }
-            }
+    /// The metadata of the named column, or of the table itself for `None`.
+    pub fn metadata_mut(
+        &mut self,
+        column: Option<&str>,
+    ) -> Result<&mut BTreeMap<String, String>, EngineError> {
+        match column {
+            None => Ok(&mut self.metadata),
+            Some(name) => self
+                .columns
+                .iter_mut()
+                .find(|c| c.name == name)
+                .map(|c| &mut c.metadata)
+                .ok_or_else(|| EngineError::ColumnNotFound(name.to_string())),
         }
-        self.rows.push(values);
     }
-}
+
+    /// A unique index over exactly `columns`, in that order.
+    pub(crate) fn unique_on(&self, columns: &[String]) -> Option<&Index> {
+        self.indices.values().find(|index| {
+            index.unique && index.is_plain() && index.is_ready() && index.columns == columns
+        })
+    }
+
+    /// Checks a value being stored in column `col_idx` under the table's
+    /// typing mode, returning the value to store.
+    pub(crate) fn accept(&self, col_idx: usize, value: Value) -> Result<Value, EngineError> {
+        let column = &self.columns[col_idx];
+        match self.typing {
+            Typing::Strict => column.accept(value),
+            Typing::Flexible => Ok(match column.accept(value.clone()) {
+                Ok(v) => v,
+                Err(_) => match value {
+                    Value::Text(_) => value.cast(&column.col_type).unwrap_or(value),
+                    _ => value,
+                },
+            }),
+        }
+    }
+
+    /// Adds a UNIQUE constraint named `name` over `columns`, along with the
+    /// unique index that enforces it. Fails if a column does not exist, if
+    /// the table has an index of the name, or if the rows already break the
+    /// constraint.
+    pub fn add_unique(&mut self, name: &str, columns: &[&str]) -> Result<(), EngineError> {
+        if self.indices.contains_key(name) {
+            return Err(EngineError::InvalidQuery(format!(
+                "index {} already exists",
+                name
+            )));
+        }
+        self.build_index(name, columns, IndexKind::Hash, true)?;
+        self.uniques.push(UniqueConstraint {
+            name: name.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+        });
+        Ok(())
+    }
+
+    /// Adds the primary key, UNIQUE constraints and AUTO_INCREMENT column
+    /// declared by a CREATE TABLE.
+    fn add_constraints(&mut self, q: &CreateTableQuery) -> Result<(), EngineError> {
+        fn names(columns: &[String]) -> Vec<&str> {
+            columns.iter().map(String::as_str).collect()
+        }
+        if let Some(columns) = &q.primary_key {
+            self.set_primary_key(&names(columns))?;
+        }
+        for unique in &q.uniques {
+            self.add_unique(&unique.name, &names(&unique.columns))?;
+        }
+        if let Some(column) = &q.auto_increment {
+            self.set_auto_increment(column)?;
+        }
+        Ok(())
+    }
+
+    /// Makes `columns` the primary key: together they must be unique, as
+    /// enforced by a UNIQUE constraint named `PRIMARY`, and none may be
+    /// NULL. A key of several columns constrains their combination, not
+    /// each column on its own.
+    pub fn set_primary_key(&mut self, columns: &[&str]) -> Result<(), EngineError> {
+        if self.primary_key.is_some() {
+            return Err(EngineError::InvalidQuery(
+                "table already has a primary key".to_string(),
+            ));
+        }
+        let positions = self.column_positions(columns)?;
+        for row in self.all_rows().iter() {
+            self.check_not_null(&positions, row)?;
+        }
+        self.add_unique("PRIMARY", columns)?;
+        self.primary_key = Some(columns.iter().map(|c| c.to_string()).collect());
+        Ok(())
+    }
+
+    fn check_not_null(&self, positions: &[usize], row: &Row) -> Result<(), EngineError> {
+        match positions.iter().find(|&&i| row[i].is_null()) {
+            Some(&i) => Err(EngineError::NotNullViolation {
+                column: self.columns[i].name.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the row against the primary key's NOT NULL rule.
+    pub(crate) fn check_primary_key(&self, row: &Row) -> Result<(), EngineError> {
+        match &self.primary_key {
+            Some(columns) => self.check_not_null(&self.column_positions(columns)?, row),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes `column`, which must be INT or HUGEINT, auto-incrementing.
+    /// Numbering continues after the largest value already in the column.
+    pub fn set_auto_increment(&mut self, column: &str) -> Result<(), EngineError> {
+        let pos = self.column_positions(&[column])?[0];
+        if !matches!(
+            self.columns[pos].col_type,
+            ValueType::Int | ValueType::BigInt
+        ) {
+            return Err(EngineError::InvalidQuery(format!(
+                "AUTO_INCREMENT column {} must be an integer",
+                column
+            )));
+        }
+        let last = self
+            .column_values(pos)
+            .filter_map(|value| match *value {
+                Value::Int(n) => Some(n as i128),
+                Value::BigInt(n) => Some(n),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        self.auto_increment = Some(AutoIncrement {
+            column: column.to_string(),
+            last,
+        });
+        Ok(())
+    }
+
+    /// Gives the row the next auto-increment id if its auto-increment
+    /// column is NULL, returning the id. Fails with `NumericOverflow` once
+    /// the column's type has no next value.
+    pub(crate) fn fill_auto_increment(&self, row: &mut Row) -> Result<Option<i128>, EngineError> {
+        let Some(auto) = &self.auto_increment else {
+            return Ok(None);
+        };
+        let pos = self.column_positions(&[&auto.column])?[0];
+        if !row[pos].is_null() {
+            return Ok(None);
+        }
+        let id = auto
+            .last
+            .checked_add(1)
+            .ok_or(EngineError::NumericOverflow)?;
+        row[pos] = match self.columns[pos].col_type {
+            ValueType::BigInt => Value::BigInt(id),
+            _ => Value::Int(i64::try_from(id).map_err(|_| EngineError::NumericOverflow)?),
+        };
+        Ok(Some(id))
+    }
+
+    /// Appends a row, failing without changes if it breaks the primary key
+    /// or a UNIQUE constraint, if an indexed expression fails on it, or if
+    /// an index is not ready for it.
+    pub fn insert(&mut self, values: Row) -> Result<(), EngineError> {
+        self.check_primary_key(&values)?;
+        self.check_partition(&values)?;
+        let row_idx = self.row_count();
+        let mut names = self.indices.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let mut keys = Vec::with_capacity(names.len());
+        for name in names {
+            let index = &self.indices[&name];
+            if !index.is_ready() {
+                return Err(EngineError::InvalidOperation(format!(
+                    "index {} must be rebuilt before rows are added",
+                    name
+                )));
+            }
+            let key = index.key(&values)?;
+            if index.conflict(&key) {
+                return Err(EngineError::UniqueViolation {
+                    constraint: name,
+                    value: key,
+                });
+            }
+            keys.push((name, key));
+        }
+        for (name, key) in keys {
+            if let Some(index) = self.indices.get_mut(&name) {
+                index.insert(key, row_idx);
+            }
+        }
+        self.add_to_bloom_filters(&values);
+        self.note_auto_increment(&values);
+        self.push_row(values);
+        Ok(())
+    }
+
+    /// Raises the last AUTO_INCREMENT value to the row's, if it is higher.
+    pub(crate) fn note_auto_increment(&mut self, row: &Row) {
+        if let Some(auto) = &mut self.auto_increment {
+            if let Some(pos) = self.columns.iter().position(|c| c.name == auto.column) {
+                let n = match row[pos] {
+                    Value::Int(n) => Some(n as i128),
+                    Value::BigInt(n) => Some(n),
+                    _ => None,
+                };
+                auto.last = auto.last.max(n.unwrap_or(auto.last));
+            }
+        }
+    }
+
+    /// Rebuilds every index after rows were removed or changed in place,
+    /// failing if the rows now break the primary key or a unique index.
+    pub(crate) fn reindex(&mut self) -> Result<(), EngineError> {
+        for row in self.all_rows().iter() {
+            self.check_primary_key(row)?;
+        }
+        self.rebuild_bloom_filters();
+        self.rebuild_indexes()
+    }
+}
+
+/// The rows a scan reads, with the positions of those it keeps.
+type Scan<'a> = (Relation, Cow<'a, [Row]>, Vec<usize>);
+
+/// State shared by everything executed as part of one statement.
+#[derive(Debug, Clone)]
+pub(crate) struct Scope {
+    /// Temporary tables defined by the statement's WITH clauses, keyed by
+    /// name.
+    pub ctes: HashMap<String, Table>,
+    /// Tables of a [`SharedEngine`](crate::SharedEngine), locked for the
+    /// statement to read; the engine's own map of tables is empty then.
+    pub shared: HashMap<String, Arc<Table>>,
+    /// The statement's start time in microseconds since the Unix epoch, so
+    /// that every `NOW()` in the statement sees the same value.
+    pub now: i64,
+}
+
+impl Scope {
+    pub(crate) fn new() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as i64);
+        Self {
+            ctes: HashMap::new(),
+            shared: HashMap::new(),
+            now,
+        }
+    }
+}
+
+/// A scalar function implemented in Rust and callable from SQL; see
+/// [`Engine::register_function`].
+pub type ScalarFn = dyn Fn(&[Value]) -> Result<Value, EngineError> + Send + Sync;
 
 #[derive(Default)]
 pub struct Engine {
     pub tables: HashMap<String, Table>,
+    /// User-defined scalar functions keyed by upper-cased name.
+    pub(crate) functions: HashMap<String, Arc<UserFunction>>,
+    /// Typing mode of tables created by [`Engine::create_table`].
+    pub default_typing: Typing,
+    /// Whether booleans act as the integers 1 and 0 in comparisons and in
+    /// SUM and AVG, so that `active = 1` matches true and `SUM(active)`
+    /// counts trues. Off by default; set with `PRAGMA bool_ints = ON`.
+    pub bool_ints: bool,
+    pub(crate) last_insert_id: Option<i128>,
+    /// Views by name, expanded each time a query reads them.
+    pub(crate) views: HashMap<String, SelectQuery>,
+    pub(crate) materialized: HashMap<String, MaterializedView>,
+    /// Schemas created with CREATE SCHEMA. A table or view named
+    /// `schema.name` belongs to `schema`; unqualified names belong to none.
+    pub(crate) schemas: BTreeSet<String>,
+    /// Migrations applied by [`Engine::migrate`], oldest first.
+    pub(crate) migrations: Vec<AppliedMigration>,
+    /// Statements parsed by [`Engine::execute_sql`], by shape.
+    pub(crate) plan_cache: PlanCache,
+    /// Where tables are logged, once [`Engine::enable_log`] is called.
+    pub(crate) logs: Option<TableLogs>,
+    /// Bytes of sort keys an ORDER BY may hold, or `None` for
+    /// [`crate::DEFAULT_SORT_MEMORY`].
+    pub(crate) sort_memory: Option<usize>,
+    pub(crate) sort_counters: SortCounters,
+    /// Bytes logged after a checkpoint before the next, or `None` for
+    /// [`crate::DEFAULT_CHECKPOINT_SIZE`].
+    pub(crate) checkpoint_size: Option<u64>,
+    /// Checkpoints kept before the latest, with their logs.
+    pub(crate) history: usize,
+    /// Tables kept by storage attached with [`Engine::attach`].
+    pub(crate) attached: HashMap<String, Attached>,
+    /// Whether statements that change anything are refused; see
+    /// [`Engine::open_read_only`].
+    pub(crate) read_only: bool,
+    /// The memory limit and the tables spilled to disk to keep under it;
+    /// see [`Engine::set_memory_limit`].
+    pub(crate) spill: crate::spill::Spill,
+    /// The open transaction, if any; see [`Engine::begin`].
+    pub(crate) transaction: Option<crate::transaction::Transaction>,
+    /// Whether a change run by [`Engine::change_schema`] is under way, so
+    /// that the changes it makes in turn are part of it.
+    pub(crate) changing: bool,
+}
+
+/// The list of `UNNEST(list)`, when `expr` is that call.
+fn unnested(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::Function { name, args } if name.eq_ignore_ascii_case("UNNEST") => {
+            match args.as_slice() {
+                [list] => Some(list),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
-        Self {
-            tables: HashMap::new(),
+        Self::default()
+    }
+
+    /// Registers a scalar function taking exactly `arity` arguments so that
+    /// SQL can call it by `name` (matched case-insensitively). Registering
+    /// the same name again replaces the previous function; built-in
+    /// function names cannot be reused.
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&[Value]) -> Result<Value, EngineError> + Send + Sync + 'static,
+    ) -> Result<(), EngineError> {
+        if ScalarFunc::builtin(name).is_some() {
+            return Err(EngineError::InvalidOperation(format!(
+                "cannot redefine built-in function {}",
+                name
+            )));
         }
+        let func = UserFunction {
+            arity,
+            func: Arc::new(func),
+        };
+        self.functions
+            .insert(name.to_ascii_uppercase(), Arc::new(func));
+        Ok(())
+    }
+
+    /// Creates a table. Fails if the name is taken by a table or view, or
+    /// if it is qualified with a schema that does not exist.
+    pub fn create_table(
+        &mut self,
+        name: &str,
+        columns: Vec<(String, ValueType)>,
+    ) -> Result<(), EngineError> {
+        self.create_table_with_typing(name, columns, self.default_typing)
+    }
+
+    /// Creates a table with typing mode `typing`. Fails if the name is
+    /// taken, and on an engine opened read only or inside a transaction.
+    pub fn create_table_with_typing(
+        &mut self,
+        name: &str,
+        columns: Vec<(String, ValueType)>,
+        typing: Typing,
+    ) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            if engine.name_taken(name) {
+                return Err(EngineError::TableExists(name.to_string()));
+            }
+            engine.check_schema(name)?;
+            engine
+                .tables
+                .insert(name.to_string(), Self::new_table(columns, typing));
+            Ok(())
+        })
+    }
+
+    /// Creates a table unless one of the same name exists, returning
+    /// whether it did. The existing table is left as it is, even if its
+    /// columns differ.
+    pub fn create_table_if_not_exists(
+        &mut self,
+        name: &str,
+        columns: Vec<(String, ValueType)>,
+    ) -> Result<bool, EngineError> {
+        if self.tables.contains_key(name) {
+            return Ok(false);
+        }
+        self.create_table(name, columns)?;
+        Ok(true)
+    }
+
+    /// Creates a table, replacing any table of the same name along with its
+    /// rows. Fails if the name belongs to a view, or if another table has a
+    /// foreign key to the one being replaced, and on an engine opened read
+    /// only or inside a transaction, which could not bring the rows back.
+    pub fn replace_table(
+        &mut self,
+        name: &str,
+        columns: Vec<(String, ValueType)>,
+    ) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            if !engine.tables.contains_key(name) {
+                return engine.create_table(name, columns);
+            }
+            if let Some((child, fk)) = engine.tables.iter().find_map(|(child, table)| {
+                table
+                    .foreign_keys
+                    .iter()
+                    .find(|fk| fk.ref_table == name && child != name)
+                    .map(|fk| (child, fk))
+            }) {
+                return Err(EngineError::InvalidQuery(format!(
+                    "foreign key {} of {} references {}",
+                    fk.name, child, name
+                )));
+            }
+            engine.forget_spilled_table(name);
+            let table = Self::new_table(columns, engine.default_typing);
+            engine.tables.insert(name.to_string(), table);
+            engine.table_changed(name);
+            Ok(())
+        })
     }
 
-    pub fn create_table(&mut self, name: &str, columns: Vec<(String, ValueType)>) {
+    fn new_table(columns: Vec<(String, ValueType)>, typing: Typing) -> Table {
         let mut table = Table::new(columns);
-        if let Some(first_col) = table.columns.get(0) {
-            table.create_index(&first_col.name);
+        table.typing = typing;
+        if let Some(first_col) = table.columns.first().map(|c| c.name.clone()) {
+            table.create_index(&first_col);
+        }
+        table
+    }
+
+    /// Renames a table, updating the foreign keys and views that reference
+    /// it, as `ALTER TABLE from RENAME TO to` does. Fails if `from` does not
+    /// exist, is attached storage or `to` is taken, and on an engine opened
+    /// read only or inside a transaction.
+    pub fn rename_table(&mut self, from: &str, to: &str) -> Result<(), EngineError> {
+        self.execute(crate::parser::Query::AlterTable(AlterTableQuery {
+            table: from.to_string(),
+            action: AlterTableAction::RenameTo(to.to_string()),
+        }))?;
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), EngineError> {
+        if self.attached.contains_key(from) {
+            return Err(EngineError::InvalidOperation(format!(
+                "table {} is attached storage, which cannot be renamed",
+                from
+            )));
+        }
+        if self.name_taken(to) {
+            return Err(EngineError::TableExists(to.to_string()));
+        }
+        self.check_schema(to)?;
+        let table = self
+            .tables
+            .remove(from)
+            .ok_or_else(|| EngineError::TableNotFound(from.to_string()))?;
+        self.tables.insert(to.to_string(), table);
+        for fk in self.tables.values_mut().flat_map(|t| &mut t.foreign_keys) {
+            if fk.ref_table == from {
+                fk.ref_table = to.to_string();
+            }
         }
-        self.tables.insert(name.to_string(), table);
+        for view in self.views.values_mut() {
+            view.rename_table(from, to);
+        }
+        for view in self.materialized.values_mut() {
+            view.query.rename_table(from, to);
+        }
+        Ok(())
     }
 
     pub fn insert_into(
@@ -121,139 +1619,651 @@ impl Engine {
         values: Row,
         columns: Option<Vec<String>>,
     ) -> Result<(), EngineError> {
-        match self.tables.get_mut(name) {
-            Some(table) => {
-                if let Some(cols) = columns {
-                    if cols.len() != values.len() {
-                        return Err(EngineError::ValueCountMismatch);
-                    }
-                    let mut row = vec![Value::Null; table.columns.len()];
-                    for (col_name, val) in cols.iter().zip(values.iter()) {
-                        let idx = table
-                            .columns
-                            .iter()
-                            .position(|c| c.name == *col_name)
-                            .ok_or_else(|| EngineError::ColumnNotFound(col_name.clone()))?;
-                        let col_def = &table.columns[idx];
-                        if col_def.col_type != val.value_type() {
-                            return Err(EngineError::TypeMismatch {
-                                column: col_def.name.clone(),
-                                expected: col_def.col_type.clone(),
-                                found: val.value_type(),
-                            });
-                        }
-                        row[idx] = val.clone();
-                    }
-                    table.insert(row);
-                    Ok(())
-                } else {
-                    if table.columns.len() != values.len() {
-                        return Err(EngineError::ValueCountMismatch);
-                    }
-                    for (col, val) in table.columns.iter().zip(values.iter()) {
-                        if col.col_type != val.value_type() {
-                            return Err(EngineError::TypeMismatch {
-                                column: col.name.clone(),
-                                expected: col.col_type.clone(),
-                                found: val.value_type(),
-                            });
-                        }
-                    }
-                    table.insert(values);
-                    Ok(())
-                }
+        self.writable()?;
+        if self.attached.contains_key(name) {
+            return self.insert_attached(name, values, columns);
+        }
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let mut row = if let Some(cols) = columns {
+            if cols.len() != values.len() {
+                return Err(EngineError::ValueCountMismatch);
+            }
+            let mut row = table
+                .columns
+                .iter()
+                .map(|c| Value::TypedNull(c.col_type.clone()))
+                .collect::<Row>();
+            for (col_name, val) in cols.iter().zip(values) {
+                let idx = table
+                    .columns
+                    .iter()
+                    .position(|c| c.name == *col_name)
+                    .ok_or_else(|| EngineError::ColumnNotFound(col_name.clone()))?;
+                row[idx] = table.accept(idx, val)?;
+            }
+            row
+        } else {
+            if table.columns.len() != values.len() {
+                return Err(EngineError::ValueCountMismatch);
             }
-            None => Err(EngineError::TableNotFound(name.to_string())),
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(idx, val)| table.accept(idx, val))
+                .collect::<Result<Row, _>>()?
+        };
+        let id = table.fill_auto_increment(&mut row)?;
+        self.check_references(name, std::slice::from_ref(&row))?;
+        self.save_for_rollback(name);
+        match self.tables.get_mut(name) {
+            Some(table) => table.insert(row.clone())?,
+            None => return Err(EngineError::TableNotFound(name.to_string())),
+        }
+        if id.is_some() {
+            self.last_insert_id = id;
         }
+        self.table_changed(name);
+        if self.in_transaction() {
+            return Ok(());
+        }
+        self.log_inserts(name, std::slice::from_ref(&row))
     }
 
-    fn get_column_idx(table: &Table, name: &str) -> Result<usize, EngineError> {
-        table
-            .columns
-            .iter()
-            .position(|c| c.name == name)
-            .ok_or_else(|| EngineError::ColumnNotFound(name.to_string()))
+    /// The id most recently generated for an AUTO_INCREMENT column by an
+    /// INSERT on this engine, if any.
+    pub fn last_insert_id(&self) -> Option<i128> {
+        self.last_insert_id
     }
 
-    fn compare(a: &Value, op: &Operator, b: &Value) -> bool {
+    fn ordering_matches(ord: Ordering, op: &Operator) -> bool {
+        match op {
+            Operator::Eq => ord == Ordering::Equal,
+            Operator::Ne => ord != Ordering::Equal,
+            Operator::Lt => ord == Ordering::Less,
+            Operator::Le => ord != Ordering::Greater,
+            Operator::Gt => ord == Ordering::Greater,
+            Operator::Ge => ord != Ordering::Less,
+            Operator::Like
+            | Operator::NotLike
+            | Operator::Regexp
+            | Operator::NotRegexp
+            | Operator::Contains
+            | Operator::ContainedBy => false,
+        }
+    }
+
+    /// Matches `text` against a LIKE pattern where `%` matches any run of
+    /// characters and `_` matches exactly one.
+    fn like_match(text: &str, pattern: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        let (mut t, mut p) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None;
+        while t < text.len() {
+            match pattern.get(p) {
+                Some('%') => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                }
+                Some(&c) if c == '_' || c == text[t] => {
+                    t += 1;
+                    p += 1;
+                }
+                _ => match backtrack {
+                    Some((bp, bt)) => {
+                        backtrack = Some((bp, bt + 1));
+                        p = bp + 1;
+                        t = bt + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|&c| c == '%')
+    }
+
+    /// Compares two values under SQL semantics: any comparison involving
+    /// NULL is UNKNOWN (`None`), numbers of different types compare by
+    /// value, and other values of different types never match. Enum values
+    /// order by declaration and compare with text through their labels.
+    /// A JSON scalar, such as JSON_EXTRACT and `->` return, compares with a
+    /// value that is not JSON as the SQL value it holds.
+    pub(crate) fn compare(a: &Value, op: &Operator, b: &Value) -> Option<bool> {
+        if a.is_null() || b.is_null() {
+            return None;
+        }
+        let scalar = |j: &serde_json::Value| !j.is_array() && !j.is_object();
         match (a, b) {
-            (Value::Int(x), Value::Int(y)) => match op {
-                Operator::Eq => x == y,
-                Operator::Ne => x != y,
-                Operator::Lt => x < y,
-                Operator::Le => x <= y,
-                Operator::Gt => x > y,
-                Operator::Ge => x >= y,
+            (Value::Json(_), Value::Json(_)) => {}
+            (Value::Json(x), _) if scalar(x) => return Self::compare(&json::unquote(x), op, b),
+            (_, Value::Json(y)) if scalar(y) => return Self::compare(a, op, &json::unquote(y)),
+            _ => {}
+        }
+        if let Some(ord) = Self::cmp_numeric(a, b) {
+            return Some(Self::ordering_matches(ord, op));
+        }
+        Some(match (a, b) {
+            (Value::Text(x), Value::Text(y)) if *op == Operator::Like => Self::like_match(x, y),
+            (Value::Text(x), Value::Text(y)) if *op == Operator::NotLike => !Self::like_match(x, y),
+            (Value::Text(x), Value::Text(y)) => Self::ordering_matches(x.cmp(y), op),
+            (Value::Uuid(x), Value::Uuid(y)) => Self::ordering_matches(x.cmp(y), op),
+            (Value::Interval(x), Value::Interval(y)) => Self::ordering_matches(x.cmp(y), op),
+            (Value::Custom(x), Value::Custom(y)) => x
+                .cmp_dyn(y.as_ref())
+                .is_some_and(|ord| Self::ordering_matches(ord, op)),
+            (Value::Enum(x), Value::Enum(y)) if x.labels == y.labels => {
+                Self::ordering_matches(x.index.cmp(&y.index), op)
+            }
+            (Value::Enum(x), Value::Text(y)) if *op == Operator::Like => {
+                Self::like_match(x.label(), y)
+            }
+            (Value::Enum(x), Value::Text(y)) if *op == Operator::NotLike => {
+                !Self::like_match(x.label(), y)
+            }
+            (Value::Enum(x), Value::Text(y)) => match x.position_of(y) {
+                Some(pos) => Self::ordering_matches(x.index.cmp(&pos), op),
+                None => *op == Operator::Ne,
             },
-            (Value::Text(x), Value::Text(y)) => match op {
-                Operator::Eq => x == y,
-                Operator::Ne => x != y,
-                Operator::Lt => x < y,
-                Operator::Le => x <= y,
-                Operator::Gt => x > y,
-                Operator::Ge => x >= y,
+            (Value::Text(x), Value::Enum(y)) => match y.position_of(x) {
+                Some(pos) => Self::ordering_matches(pos.cmp(&y.index), op),
+                None => *op == Operator::Ne,
             },
-            (Value::Bool(x), Value::Bool(y)) => match op {
-                Operator::Eq => x == y,
-                Operator::Ne => x != y,
+            (Value::Date(_) | Value::Time(_) | Value::Timestamp(_), _) => {
+                Self::cmp_temporal(a, b).is_some_and(|ord| Self::ordering_matches(ord, op))
+            }
+            (Value::List(x), Value::List(y)) if *op == Operator::Contains => {
+                y.iter().all(|v| x.contains(v))
+            }
+            (Value::List(x), Value::List(y)) if *op == Operator::ContainedBy => {
+                x.iter().all(|v| y.contains(v))
+            }
+            (Value::Bool(_), Value::Bool(_))
+            | (Value::Json(_), Value::Json(_))
+            | (Value::List(_), Value::List(_)) => match op {
+                Operator::Eq => a == b,
+                Operator::Ne => a != b,
                 _ => false,
             },
             _ => false,
+        })
+    }
+
+    /// Orders two numbers of any numeric types: integers and decimals
+    /// compare exactly, while a comparison involving a float is done in
+    /// floating point. `None` unless both values are numbers.
+    pub(crate) fn cmp_numeric(a: &Value, b: &Value) -> Option<Ordering> {
+        let integral = |v: &Value| matches!(v, Value::Int(_) | Value::BigInt(_));
+        Some(match (a, b) {
+            (Value::Int(x), Value::Int(y)) => x.cmp(y),
+            _ if integral(a) && integral(b) => Self::wide_int(a).cmp(&Self::wide_int(b)),
+            (Value::Decimal(x), Value::Decimal(y)) => x.cmp(y),
+            (Value::Decimal(x), _) if integral(b) => Self::cmp_decimal_int(x, Self::wide_int(b)),
+            (_, Value::Decimal(y)) if integral(a) => {
+                Self::cmp_decimal_int(y, Self::wide_int(a)).reverse()
+            }
+            _ => cmp_float(a.as_f64()?, b.as_f64()?),
+        })
+    }
+
+    /// The value of an Int or BigInt as an `i128`; 0 for anything else.
+    fn wide_int(v: &Value) -> i128 {
+        match v {
+            Value::Int(n) => *n as i128,
+            Value::BigInt(n) => *n,
+            _ => 0,
         }
     }
 
-    pub fn select(&self, q: &SelectQuery) -> Result<Vec<Row>, EngineError> {
-        let table = self
-            .tables
-            .get(&q.table)
-            .ok_or_else(|| EngineError::TableNotFound(q.table.clone()))?;
-
-        let mut rows: Vec<Row> = if let Some(cond) = &q.condition {
-            let col_idx = Self::get_column_idx(table, &cond.column)?;
-            if let Operator::Eq = cond.op {
-                if let Some(index) = table.indices.get(&cond.column) {
-                    if let Some(row_indices) = index.get(&cond.value) {
-                        row_indices.iter().map(|&i| table.rows[i].clone()).collect()
-                    } else {
-                        Vec::new()
+    /// Orders a decimal against an integer exactly; integers too wide for a
+    /// decimal lie beyond every decimal.
+    fn cmp_decimal_int(d: &Decimal, n: i128) -> Ordering {
+        match Decimal::new(n, 0) {
+            Some(n) => d.cmp(&n),
+            None if n > 0 => Ordering::Less,
+            None => Ordering::Greater,
+        }
+    }
+
+    /// Orders two temporal values of the same kind; a date compares with a
+    /// timestamp as midnight of that day. `None` for any other pairing.
+    fn cmp_temporal(a: &Value, b: &Value) -> Option<Ordering> {
+        let midnight = |d: &i32| *d as i64 * MICROS_PER_DAY;
+        Some(match (a, b) {
+            (Value::Date(x), Value::Date(y)) => x.cmp(y),
+            (Value::Time(x), Value::Time(y)) => x.cmp(y),
+            (Value::Timestamp(x), Value::Timestamp(y)) => x.cmp(y),
+            (Value::Date(x), Value::Timestamp(y)) => midnight(x).cmp(y),
+            (Value::Timestamp(x), Value::Date(y)) => x.cmp(&midnight(y)),
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn get_table<'a>(
+        &'a self,
+        scope: &'a Scope,
+        name: &str,
+    ) -> Result<&'a Table, EngineError> {
+        scope
+            .ctes
+            .get(name)
+            .or_else(|| self.stored_table(scope, name))
+            .or_else(|| self.materialized.get(name).map(|view| &view.table))
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))
+    }
+
+    /// The table `name` of the engine, or of the shared engine `scope`
+    /// reads.
+    fn stored_table<'a>(&'a self, scope: &'a Scope, name: &str) -> Option<&'a Table> {
+        scope
+            .shared
+            .get(name)
+            .map(|table| &**table)
+            .or_else(|| self.tables.get(name))
+    }
+
+    /// Scans a single table, using an index for the condition when one can
+    /// answer it. Returns the table's rows and the positions of those that
+    /// match, in table order; no row of a row table is copied, and only
+    /// the columns `q` reads of a column table. Texts kept out of line
+    /// are copied only into the rows that match, unless the condition
+    /// reads them.
+    fn scan_table<'a>(
+        &'a self,
+        scope: &'a Scope,
+        table_ref: &TableRef,
+        q: &SelectQuery,
+    ) -> Result<Scan<'a>, EngineError> {
+        let table = self.get_table(scope, &table_ref.name)?;
+        let hint = table_ref.hint.as_ref();
+        check_hint(table, &table_ref.name, hint)?;
+        let rel = Relation::from_table(table_ref.qualifier(), table);
+        let names = |names: &[String], column: &str| {
+            names
+                .iter()
+                .any(|name| name.rsplit('.').next() == Some(column))
+        };
+        let mut deferred = Vec::new();
+        let mut rows = match table.layout {
+            layout
+                if !q.columns.is_empty()
+                    && (layout == Layout::Column || table.overflow.is_some()) =>
+            {
+                let read = q.column_names();
+                let tested = q
+                    .condition
+                    .as_ref()
+                    .map_or_else(Vec::new, |c| c.column_names());
+                for (col_idx, column) in table.columns.iter().enumerate() {
+                    if table.out_of_line(col_idx)
+                        && names(&read, &column.name)
+                        && !names(&tested, &column.name)
+                    {
+                        deferred.push(column.name.clone());
+                    }
+                }
+                table.rows_reading(&|column| names(&read, column) && !names(&deferred, column))
+            }
+            _ => table.all_rows(),
+        };
+        let ids = match &q.condition {
+            None => (0..rows.len()).collect(),
+            Some(cond) => {
+                let found = self.index_lookup(table, &rel, cond, hint);
+                if let Some(found) = &found {
+                    found.count_hit(table);
+                }
+                match found {
+                    Some(found) if found.exact => found.rows,
+                    found => {
+                        let candidates = match found {
+                            Some(found) => found.rows,
+                            None => (0..rows.len()).collect(),
+                        };
+                        let filter = Binder::new(self, scope, &rel).condition(cond)?;
+                        let mut ids = Vec::with_capacity(candidates.len());
+                        for row_idx in candidates {
+                            if filter.matches(&rows[row_idx])? {
+                                ids.push(row_idx);
+                            }
+                        }
+                        ids
                     }
-                } else {
-                    table
-                        .rows
-                        .iter()
-                        .cloned()
-                        .filter(|r| Self::compare(&r[col_idx], &cond.op, &cond.value))
-                        .collect()
                 }
+            }
+        };
+        if !deferred.is_empty() {
+            table.read_columns(rows.to_mut(), &ids, &|column| names(&deferred, column));
+        }
+        Ok((rel, rows, ids))
+    }
+
+    /// Joins every table in the FROM list onto the ones before it, and
+    /// returns the combined rows with the positions of those that pass the
+    /// WHERE condition.
+    fn scan_product(
+        &self,
+        scope: &Scope,
+        tables: &[TableRef],
+        cond: Option<&Condition>,
+    ) -> Result<(Relation, Vec<Row>, Vec<usize>), EngineError> {
+        let mut rel = Relation::default();
+        let mut rows: Vec<Row> = vec![Vec::new()];
+        for table_ref in tables {
+            let table = self.get_table(scope, &table_ref.name)?;
+            let table_rel = Relation::from_table(table_ref.qualifier(), table);
+            rows = join::join(&rows, &rel, &table.all_rows(), &table_rel, cond);
+            rel.columns.extend(table_rel.columns);
+        }
+        let ids = match cond {
+            Some(cond) => {
+                let filter = Binder::new(self, scope, &rel).condition(cond)?;
+                let mut ids = Vec::with_capacity(rows.len());
+                for (row_idx, row) in rows.iter().enumerate() {
+                    if filter.matches(row)? {
+                        ids.push(row_idx);
+                    }
+                }
+                ids
+            }
+            None => (0..rows.len()).collect(),
+        };
+        Ok((rel, rows, ids))
+    }
+
+    /// Binds an ORDER BY column against the input relation, falling back
+    /// to an output alias from the select list.
+    fn bind_order_column(
+        binder: &Binder,
+        q: &SelectQuery,
+        name: &str,
+    ) -> Result<BoundExpr, EngineError> {
+        match binder.rel.resolve(name) {
+            Ok(idx) => Ok(BoundExpr::Column(idx)),
+            Err(EngineError::ColumnNotFound(_)) => {
+                match q.columns.iter().find(|c| c.alias.as_deref() == Some(name)) {
+                    Some(item) => binder.expr(&item.expr),
+                    None => Err(EngineError::ColumnNotFound(name.to_string())),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn output_name(item: &SelectItem) -> String {
+        if let Some(alias) = &item.alias {
+            return alias.clone();
+        }
+        match &item.expr {
+            Expr::Column(name) => name
+                .rsplit_once('.')
+                .map_or(name.as_str(), |(_, c)| c)
+                .to_string(),
+            expr => expr.to_string(),
+        }
+    }
+
+    /// Describes a select-list item: its output name and, for a plain
+    /// column reference, the column it reads.
+    fn result_column(rel: &Relation, item: &SelectItem) -> Result<ResultColumn, EngineError> {
+        let source = match &item.expr {
+            Expr::Column(name) => Some(rel.columns[rel.resolve(name)?].clone()),
+            _ => None,
+        };
+        Ok(ResultColumn {
+            name: Self::output_name(item),
+            source,
+        })
+    }
+
+    pub fn select(&self, q: &SelectQuery) -> Result<Vec<Row>, EngineError> {
+        self.select_result(q).map(|result| result.rows)
+    }
+
+    /// Runs a SELECT and returns its rows together with the result column
+    /// metadata.
+    pub fn select_result(&self, q: &SelectQuery) -> Result<QueryResult, EngineError> {
+        self.run_select(q, &Scope::new())
+    }
+
+    /// Runs EXPLAIN on a SELECT: one row per table in FROM, holding the
+    /// name it goes by in the query, how it is read, as an [`AccessPath`]
+    /// shown as text, and its index hint, or NULL. Tables joined to others
+    /// are always scanned.
+    pub(crate) fn explain(&self, q: &SelectQuery, outer: &Scope) -> Result<Vec<Row>, EngineError> {
+        let scoped;
+        let scope =
+            if q.with.is_empty() && !q.tables.iter().any(|t| self.views.contains_key(&t.name)) {
+                outer
             } else {
-                table
-                    .rows
-                    .iter()
-                    .cloned()
-                    .filter(|r| Self::compare(&r[col_idx], &cond.op, &cond.value))
-                    .collect()
+                scoped = self.materialize(q, outer)?;
+                &scoped
+            };
+        let single = q.tables.len() == 1;
+        let mut rows = Vec::with_capacity(q.tables.len());
+        for table_ref in &q.tables {
+            let table = self.get_table(scope, &table_ref.name)?;
+            let hint = table_ref.hint.as_ref();
+            check_hint(table, &table_ref.name, hint)?;
+            let rel = Relation::from_table(table_ref.qualifier(), table);
+            let path = match &q.condition {
+                Some(cond) if single => self
+                    .index_lookup(table, &rel, cond, hint)
+                    .map_or(AccessPath::Scan, |found| found.path),
+                _ => AccessPath::Scan,
+            };
+            rows.push(vec![
+                Value::Text(table_ref.qualifier().to_string()),
+                Value::Text(path.to_string()),
+                hint.map_or(Value::Null, |hint| Value::Text(hint.to_string())),
+            ]);
+        }
+        Ok(rows)
+    }
+
+    /// Runs a query into a temporary table, typing each column by its
+    /// values.
+    pub(crate) fn temporary_table(
+        &self,
+        q: &SelectQuery,
+        scope: &Scope,
+    ) -> Result<Table, EngineError> {
+        let result = self.run_select(q, scope)?;
+        let columns = result
+            .columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| (c.name, infer_type(&result.rows, i)));
+        let mut table = Table::new(columns.collect());
+        table.set_rows(result.rows);
+        Ok(table)
+    }
+
+    /// Materializes the query's common table expressions, and the views it
+    /// reads, as temporary tables that are visible only for the duration of
+    /// the statement.
+    pub(crate) fn materialize(&self, q: &SelectQuery, outer: &Scope) -> Result<Scope, EngineError> {
+        let mut scope = outer.clone();
+        for cte in &q.with {
+            let table = self.temporary_table(&cte.query, &scope)?;
+            scope.ctes.insert(cte.name.clone(), table);
+        }
+        for table_ref in &q.tables {
+            if scope.ctes.contains_key(&table_ref.name) {
+                continue;
             }
+            if let Some(view) = self.views.get(&table_ref.name) {
+                // A view sees tables and other views, but not the CTEs of
+                // the query reading it.
+                let view_scope = Scope {
+                    ctes: HashMap::new(),
+                    shared: scope.shared.clone(),
+                    now: scope.now,
+                };
+                let table = self.temporary_table(view, &view_scope)?;
+                scope.ctes.insert(table_ref.name.clone(), table);
+            } else if let Some(table) = self.attached_table(&table_ref.name, q, &scope)? {
+                scope.ctes.insert(table_ref.name.clone(), table);
+            } else if let Some(table) = self
+                .stored_table(&scope, &table_ref.name)
+                .filter(|t| t.ttl.is_some())
+            {
+                // Expired rows are hidden until swept.
+                if let Some(unexpired) = table.unexpired(scope.now)? {
+                    scope.ctes.insert(table_ref.name.clone(), unexpired);
+                }
+            }
+        }
+        Ok(scope)
+    }
+
+    pub(crate) fn run_select(
+        &self,
+        q: &SelectQuery,
+        outer: &Scope,
+    ) -> Result<QueryResult, EngineError> {
+        let scoped;
+        let scope = if q.with.is_empty()
+            && !q.tables.iter().any(|t| {
+                self.views.contains_key(&t.name)
+                    || self.attached.contains_key(&t.name)
+                    || self
+                        .stored_table(outer, &t.name)
+                        .is_some_and(|t| t.ttl.is_some())
+            }) {
+            outer
         } else {
-            table.rows.clone()
+            scoped = self.materialize(q, outer)?;
+            &scoped
+        };
+        // Rows are kept as positions in `source` through filtering, sorting
+        // and pagination, and only the rows returned are copied.
+        let (rel, source, mut ids) = match q.tables.as_slice() {
+            [table_ref] => self.scan_table(scope, table_ref, q)?,
+            tables => {
+                let (rel, rows, ids) = self.scan_product(scope, tables, q.condition.as_ref())?;
+                (rel, Cow::Owned(rows), ids)
+            }
         };
 
-        if let Some((ref col, asc)) = q.order_by {
-            let idx = Self::get_column_idx(table, col)?;
-            rows.sort_by(|a, b| {
-                let va = &a[idx];
-                let vb = &b[idx];
-                match (va, vb) {
-                    (Value::Int(x), Value::Int(y)) => x.cmp(y),
-                    (Value::Text(x), Value::Text(y)) => x.cmp(y),
-                    (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
-                    _ => std::cmp::Ordering::Equal,
+        let binder = Binder::new(self, scope, &rel);
+        let columns = if q.columns.is_empty() {
+            rel.columns
+                .iter()
+                .map(|(t, c)| ResultColumn {
+                    name: c.clone(),
+                    source: Some((t.clone(), c.clone())),
+                })
+                .collect()
+        } else {
+            q.columns
+                .iter()
+                .map(|item| Self::result_column(&rel, item))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if q.columns.iter().any(|c| c.expr.contains_aggregate()) {
+            let rows = ids.iter().map(|&i| &source[i]).collect::<Vec<_>>();
+            let mut row = Vec::with_capacity(q.columns.len());
+            for item in &q.columns {
+                if let Some(column) = item.expr.column_outside_aggregate() {
+                    return Err(EngineError::InvalidQuery(format!(
+                        "column {} must appear in an aggregate function",
+                        column
+                    )));
                 }
-            });
+                row.push(binder.expr(&item.expr)?.eval_aggregate(&rows)?);
+            }
+            let rows = Self::paginate(q, vec![row]);
+            return Ok(QueryResult { columns, rows });
+        }
+
+        if let Some((ref col, asc)) = q.order_by {
+            let key = Self::bind_order_column(&binder, q, col)?;
+            let keyed = ids
+                .into_iter()
+                .map(|row_idx| Ok((key.eval(&source[row_idx])?, row_idx)));
+            ids = self.sort_positions(keyed)?;
             if !asc {
-                rows.reverse();
+                ids.reverse();
             }
         }
+        // Rows that UNNEST expands are counted for OFFSET and LIMIT once
+        // expanded.
+        let unnests = q
+            .columns
+            .iter()
+            .map(|c| unnested(&c.expr).is_some())
+            .collect::<Vec<_>>();
+        let expands = unnests.contains(&true);
+        let ids = if expands { ids } else { Self::paginate(q, ids) };
 
+        if q.columns.is_empty() {
+            let rows = ids.into_iter().map(|i| source[i].clone()).collect();
+            return Ok(QueryResult { columns, rows });
+        }
+        let exprs = q
+            .columns
+            .iter()
+            .map(|c| binder.expr(unnested(&c.expr).unwrap_or(&c.expr)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut projected = Vec::with_capacity(ids.len());
+        for row_idx in ids {
+            let row = exprs
+                .iter()
+                .map(|e| e.eval(&source[row_idx]))
+                .collect::<Result<Row, _>>()?;
+            if expands {
+                projected.extend(Self::unnest_row(&unnests, row)?);
+            } else {
+                projected.push(row);
+            }
+        }
+        if expands {
+            projected = Self::paginate(q, projected);
+        }
+        Ok(QueryResult {
+            columns,
+            rows: projected,
+        })
+    }
+
+    /// The rows a projected `row` expands to when the columns marked in
+    /// `unnests` are UNNEST: one for each element of the longest of their
+    /// lists, with the other lists padded with NULL and every other column
+    /// repeated. A NULL or empty list has no elements, so a row whose lists
+    /// all have none expands to no rows.
+    fn unnest_row(unnests: &[bool], row: Row) -> Result<Vec<Row>, EngineError> {
+        let lists = row
+            .iter()
+            .zip(unnests)
+            .map(|(value, &unnest)| match value {
+                _ if !unnest => Ok(None),
+                Value::List(items) => Ok(Some(items.as_slice())),
+                v if v.is_null() => Ok(Some(&[][..])),
+                other => Err(EngineError::InvalidOperation(format!(
+                    "cannot apply UNNEST to {:?}",
+                    other.value_type()
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = lists.iter().flatten().map(|l| l.len()).max().unwrap_or(0);
+        Ok((0..count)
+            .map(|n| {
+                row.iter()
+                    .zip(&lists)
+                    .map(|(value, list)| match list {
+                        Some(items) => items.get(n).cloned().unwrap_or(Value::Null),
+                        None => value.clone(),
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Applies OFFSET and LIMIT.
+    fn paginate<T>(q: &SelectQuery, rows: Vec<T>) -> Vec<T> {
         let start = q.offset.unwrap_or(0);
         let mut rows = if start >= rows.len() {
             Vec::new()
@@ -265,28 +2275,346 @@ impl Engine {
                 rows.truncate(limit);
             }
         }
+        rows
+    }
 
-        let result = if q.columns.is_empty() {
-            rows
+    pub fn execute(&mut self, query: crate::parser::Query) -> Result<Vec<Row>, EngineError> {
+        if query.writes() {
+            self.writable()?;
+        }
+        self.check_transaction(&query)?;
+        self.load_for(&query)?;
+        // Rows reach the logs as they change, COPY's included, and the logs
+        // are checkpointed once they grow past the checkpoint size; the
+        // schema and the engine's settings reach them only through a
+        // checkpoint.
+        let changes_schema = query.writes()
+            && !query.is_dml()
+            && !query.is_transaction_control()
+            && !matches!(query, crate::parser::Query::Copy(_));
+        let rows = if !changes_schema {
+            self.execute_statement(query)
         } else {
-            let indices: Result<Vec<usize>, EngineError> = q
-                .columns
-                .iter()
-                .map(|c| Self::get_column_idx(table, c))
-                .collect();
-            let indices = indices?;
-            rows.into_iter()
-                .map(|r| indices.iter().map(|&i| r[i].clone()).collect())
-                .collect()
+            self.change_schema(|engine| engine.execute_statement(query))
         };
-        Ok(result)
+        self.enforce_memory_limit()?;
+        rows
     }
 
-    pub fn execute(&mut self, query: crate::parser::Query) -> Result<Vec<Row>, EngineError> {
+    /// Fails with [`EngineError::ReadOnly`] on an engine opened read only.
+    /// Whatever changes the engine checks here first: changes to rows where
+    /// they are made, and every other change through
+    /// [`Engine::change_schema`].
+    pub(crate) fn writable(&self) -> Result<(), EngineError> {
+        if self.read_only {
+            return Err(EngineError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Runs `f`, which changes the engine other than by changing rows, as a
+    /// statement that does: refused on an engine opened read only or inside
+    /// a transaction, and followed by a checkpoint, which is how such a
+    /// change reaches the logs. A change made while another runs is part of
+    /// it, and waits for its checkpoint.
+    pub(crate) fn change_schema<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        if self.changing {
+            return f(self);
+        }
+        self.writable()?;
+        if self.in_transaction() {
+            return Err(EngineError::InvalidOperation(
+                "the schema cannot be changed inside a transaction".to_string(),
+            ));
+        }
+        self.changing = true;
+        let result = f(self);
+        self.changing = false;
+        self.plan_cache.clear();
+        let changed = result?;
+        self.checkpoint()?;
+        Ok(changed)
+    }
+
+    /// Changes the definition of `table` through `f`; see
+    /// [`Engine::change_schema`].
+    pub(crate) fn change_table<T>(
+        &mut self,
+        table: &str,
+        f: impl FnOnce(&mut Table) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        self.change_schema(|engine| {
+            engine.load_table(table)?;
+            let changed = f(engine
+                .tables
+                .get_mut(table)
+                .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?)?;
+            engine.table_changed(table);
+            Ok(changed)
+        })
+    }
+
+    fn execute_statement(&mut self, query: crate::parser::Query) -> Result<Vec<Row>, EngineError> {
         match query {
             crate::parser::Query::Select(q) => self.select(&q),
+            crate::parser::Query::Explain(q) => self.explain(&q, &Scope::new()),
             crate::parser::Query::Insert(q) => {
-                self.insert_into(&q.table, q.values, q.columns)?;
+                let values = self.insert_values(&q.values)?;
+                self.insert_into(&q.table, values, q.columns)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Delete(q) => {
+                self.delete(&q.table, q.condition.as_ref())?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Update(q) => {
+                self.update(&q.table, &q.assignments, q.condition.as_ref())?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::AlterTable(q) => {
+                match q.action {
+                    AlterTableAction::RenameTo(new_name) => self.rename(&q.table, &new_name)?,
+                    AlterTableAction::AddForeignKey(fk) => self.add_foreign_key(&q.table, fk)?,
+                }
+                Ok(Vec::new())
+            }
+            crate::parser::Query::CreateView(q) if q.materialized => {
+                self.create_materialized_view(&q.name, q.query)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::CreateView(q) => {
+                self.create_view(&q.name, q.query)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::RefreshView(name) => {
+                self.refresh_materialized_view(&name)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::CreateTable(mut q) => {
+                if q.compression && q.layout == Layout::Row {
+                    return Err(EngineError::InvalidQuery(
+                        "compression needs layout = 'column'".into(),
+                    ));
+                }
+                if let Some(partitioning) = &q.partitioning {
+                    Table::new(q.columns.clone()).set_partitioning(Some(partitioning.clone()))?;
+                }
+                if q.ttl.is_some() {
+                    Table::new(q.columns.clone()).set_ttl(q.ttl.clone())?;
+                }
+                // Constraints are tried on a table of the same columns
+                // first, so that a bad one leaves no table behind.
+                let mut scratch = Self::new_table(q.columns.clone(), self.default_typing);
+                scratch.add_constraints(&q)?;
+                for fk in &mut q.foreign_keys {
+                    let parent = if fk.ref_table == q.name {
+                        &scratch
+                    } else {
+                        self.tables
+                            .get(&fk.ref_table)
+                            .ok_or_else(|| EngineError::TableNotFound(fk.ref_table.clone()))?
+                    };
+                    if fk.ref_columns.is_empty() {
+                        fk.ref_columns = parent.primary_key.clone().ok_or_else(|| {
+                            EngineError::InvalidQuery(format!(
+                                "foreign key {} references {}, which has no primary key",
+                                fk.name, fk.ref_table
+                            ))
+                        })?;
+                    }
+                    Self::check_foreign_key(fk, &scratch, parent)?;
+                }
+                match (q.if_not_exists, q.or_replace) {
+                    (true, true) => {
+                        return Err(EngineError::InvalidQuery(
+                            "OR REPLACE cannot be combined with IF NOT EXISTS".into(),
+                        ))
+                    }
+                    (true, false) => {
+                        if !self.create_table_if_not_exists(&q.name, q.columns.clone())? {
+                            return Ok(Vec::new());
+                        }
+                    }
+                    (false, true) => self.replace_table(&q.name, q.columns.clone())?,
+                    (false, false) => self.create_table(&q.name, q.columns.clone())?,
+                }
+                let table = self
+                    .tables
+                    .get_mut(&q.name)
+                    .ok_or_else(|| EngineError::TableNotFound(q.name.clone()))?;
+                table.set_layout(q.layout);
+                table.set_compressed(q.compression)?;
+                table.set_overflow(q.overflow)?;
+                table.set_partitioning(q.partitioning.clone())?;
+                table.set_ttl(q.ttl.clone())?;
+                table.add_constraints(&q)?;
+                for fk in q.foreign_keys {
+                    self.add_foreign_key(&q.name, fk)?;
+                }
+                Ok(Vec::new())
+            }
+            crate::parser::Query::CreateIndex(q) => {
+                self.create_index(&q)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::CreateSchema(name) => {
+                self.create_schema(&name)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::DropSchema { name, cascade } => {
+                self.drop_schema(&name, cascade)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Comment(q) => {
+                self.set_metadata(&q.table, q.column.as_deref(), COMMENT_KEY, q.text)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::DropView(name) => {
+                self.drop_view(&name)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Analyze(table) => {
+                self.analyze_statement(table.as_deref())?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Reindex(name) => {
+                self.reindex(name.as_deref())?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Vacuum(table) => {
+                let stats = self.vacuum(table.as_deref())?;
+                Ok(vec![vec![
+                    Value::Int(stats.expired as i64),
+                    Value::Int(stats.memory as i64),
+                    Value::Int(stats.disk as i64),
+                ]])
+            }
+            crate::parser::Query::Pragma(q) => self.pragma(&q),
+            crate::parser::Query::Copy(q) => {
+                self.copy(&q)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Begin => {
+                self.begin()?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Commit => {
+                self.commit()?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Rollback => {
+                self.rollback()?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Savepoint(name) => {
+                self.savepoint(&name)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::RollbackTo(name) => {
+                self.rollback_to(&name)?;
+                Ok(Vec::new())
+            }
+            crate::parser::Query::Release(name) => {
+                self.release(&name)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Runs a PRAGMA statement. `PRAGMA typing(t)` reads or sets the typing
+    /// mode of table `t`; without a table it applies to the default for new
+    /// tables. Reading returns a single row holding the setting.
+    fn pragma(&mut self, q: &PragmaQuery) -> Result<Vec<Row>, EngineError> {
+        if q.name.eq_ignore_ascii_case("typing") {
+            self.typing_pragma(q)
+        } else if q.name.eq_ignore_ascii_case("bool_ints") && q.table.is_none() {
+            match &q.value {
+                None => Ok(vec![vec![Value::Bool(self.bool_ints)]]),
+                Some(value) => {
+                    self.bool_ints = Self::pragma_flag(&q.name, value)?;
+                    Ok(Vec::new())
+                }
+            }
+        } else if q.name.eq_ignore_ascii_case("sort_memory") && q.table.is_none() {
+            match &q.value {
+                None => Ok(vec![vec![Value::Int(self.sort_memory() as i64)]]),
+                Some(Value::Int(bytes)) if *bytes >= 0 => {
+                    self.set_sort_memory(*bytes as usize);
+                    Ok(Vec::new())
+                }
+                Some(value) => Err(EngineError::InvalidQuery(format!(
+                    "invalid sort_memory value {:?}",
+                    value
+                ))),
+            }
+        } else if q.name.eq_ignore_ascii_case("checkpoint_size") && q.table.is_none() {
+            match &q.value {
+                None => Ok(vec![vec![Value::Int(self.checkpoint_size() as i64)]]),
+                Some(Value::Int(bytes)) if *bytes >= 0 => {
+                    self.set_checkpoint_size(*bytes as u64);
+                    Ok(Vec::new())
+                }
+                Some(value) => Err(EngineError::InvalidQuery(format!(
+                    "invalid checkpoint_size value {:?}",
+                    value
+                ))),
+            }
+        } else if q.name.eq_ignore_ascii_case("auto_refresh") && q.table.is_some() {
+            let name = q.table.as_deref().unwrap_or_default();
+            match &q.value {
+                None => Ok(vec![vec![Value::Bool(self.auto_refresh(name)?)]]),
+                Some(value) => {
+                    self.set_auto_refresh(name, Self::pragma_flag(&q.name, value)?)?;
+                    Ok(Vec::new())
+                }
+            }
+        } else {
+            Err(EngineError::InvalidQuery(format!(
+                "unknown pragma {}",
+                q.name
+            )))
+        }
+    }
+
+    /// Reads an on/off pragma setting: ON, OFF, TRUE, FALSE, 1 or 0.
+    fn pragma_flag(name: &str, value: &Value) -> Result<bool, EngineError> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            Value::Int(n @ (0 | 1)) => Some(*n == 1),
+            Value::Text(word) => match word.to_ascii_uppercase().as_str() {
+                "ON" | "TRUE" => Some(true),
+                "OFF" | "FALSE" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+        .ok_or_else(|| EngineError::InvalidQuery(format!("invalid {} value {:?}", name, value)))
+    }
+
+    fn typing_pragma(&mut self, q: &PragmaQuery) -> Result<Vec<Row>, EngineError> {
+        let typing = match &q.table {
+            Some(name) => {
+                &mut self
+                    .tables
+                    .get_mut(name)
+                    .ok_or_else(|| EngineError::TableNotFound(name.clone()))?
+                    .typing
+            }
+            None => &mut self.default_typing,
+        };
+        match &q.value {
+            None => Ok(vec![vec![Value::Text(typing.name().to_string())]]),
+            Some(value) => {
+                *typing = match value {
+                    Value::Text(name) => Typing::from_name(name),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    EngineError::InvalidQuery(format!("invalid typing mode {:?}", value))
+                })?;
                 Ok(Vec::new())
             }
         }
@@ -0,0 +1,952 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::decimal::Decimal;
+use crate::engine::{Engine, EngineError, Row, ScalarFn, Scope, Table, Value, ValueType};
+use crate::json;
+use crate::parser::{AggregateFunc, BinaryOp, Condition, Expr, Operator};
+use crate::temporal::{Interval, MICROS_PER_DAY, MICROS_PER_SECOND};
+
+/// Column layout of an intermediate result, used to resolve plain (`col`)
+/// and qualified (`table.col`) column references to row positions.
+#[derive(Debug, Default)]
+pub(crate) struct Relation {
+    pub columns: Vec<(String, String)>,
+}
+
+impl Relation {
+    pub fn from_table(name: &str, table: &Table) -> Self {
+        Self {
+            columns: table
+                .columns
+                .iter()
+                .map(|c| (name.to_string(), c.name.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> Result<usize, EngineError> {
+        let (qualifier, column) = match name.rsplit_once('.') {
+            Some((t, c)) => (Some(t), c),
+            None => (None, name),
+        };
+        // A table in a schema can also be qualified by its name alone.
+        let qualifies =
+            |q: &str, t: &str| q == t || t.rsplit_once('.').is_some_and(|(_, base)| base == q);
+        let mut found = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, (t, c))| c == column && qualifier.is_none_or(|q| qualifies(q, t)))
+            .map(|(i, _)| i);
+        let idx = found
+            .next()
+            .ok_or_else(|| EngineError::ColumnNotFound(name.to_string()))?;
+        if found.next().is_some() {
+            return Err(EngineError::AmbiguousColumn(name.to_string()));
+        }
+        Ok(idx)
+    }
+}
+
+/// Resolves column references in expressions and conditions against a
+/// relation, materializing any subqueries along the way.
+pub(crate) struct Binder<'a> {
+    engine: &'a Engine,
+    scope: &'a Scope,
+    pub rel: &'a Relation,
+}
+
+impl<'a> Binder<'a> {
+    pub fn new(engine: &'a Engine, scope: &'a Scope, rel: &'a Relation) -> Self {
+        Self { engine, scope, rel }
+    }
+
+    pub fn expr(&self, expr: &Expr) -> Result<BoundExpr, EngineError> {
+        Ok(match expr {
+            Expr::Literal(v) => BoundExpr::Literal(v.clone()),
+            Expr::Column(name) => BoundExpr::Column(self.rel.resolve(name)?),
+            Expr::Binary { op, left, right } => BoundExpr::Binary {
+                op: *op,
+                left: Box::new(self.expr(left)?),
+                right: Box::new(self.expr(right)?),
+            },
+            Expr::Aggregate {
+                func,
+                arg,
+                distinct,
+            } => BoundExpr::Aggregate {
+                func: *func,
+                arg: match arg {
+                    Some(arg) if matches!(func, AggregateFunc::Sum | AggregateFunc::Avg) => {
+                        Some(Box::new(self.operand(arg)?))
+                    }
+                    Some(arg) => Some(Box::new(self.expr(arg)?)),
+                    None => None,
+                },
+                distinct: *distinct,
+            },
+            Expr::Function { name, args } => {
+                let func = ScalarFunc::lookup(self.engine, name)
+                    .ok_or_else(|| EngineError::UnknownFunction(name.clone()))?;
+                func.check_arity(name, args.len())?;
+                if let ScalarFunc::Now = func {
+                    return Ok(BoundExpr::Literal(Value::Timestamp(self.scope.now)));
+                }
+                if let ScalarFunc::LastInsertId = func {
+                    let id = self
+                        .engine
+                        .last_insert_id()
+                        .map(|id| match i64::try_from(id) {
+                            Ok(n) => Value::Int(n),
+                            Err(_) => Value::BigInt(id),
+                        });
+                    return Ok(BoundExpr::Literal(id.into()));
+                }
+                BoundExpr::Function {
+                    func,
+                    args: args
+                        .iter()
+                        .map(|a| self.expr(a))
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            Expr::Cast { expr, target } => BoundExpr::Cast {
+                expr: Box::new(self.expr(expr)?),
+                target: target.clone(),
+            },
+            Expr::Case {
+                branches,
+                otherwise,
+            } => BoundExpr::Case {
+                branches: branches
+                    .iter()
+                    .map(|(c, e)| Ok((self.condition(c)?, self.expr(e)?)))
+                    .collect::<Result<_, EngineError>>()?,
+                otherwise: match otherwise {
+                    Some(e) => Some(Box::new(self.expr(e)?)),
+                    None => None,
+                },
+            },
+        })
+    }
+
+    /// Binds an operand of a comparison or of SUM or AVG, reading booleans
+    /// as 1 and 0 when the engine's `bool_ints` option is on.
+    fn operand(&self, expr: &Expr) -> Result<BoundExpr, EngineError> {
+        let bound = self.expr(expr)?;
+        Ok(match bound {
+            _ if !self.engine.bool_ints => bound,
+            BoundExpr::Literal(v) => BoundExpr::Literal(bool_as_int(v)),
+            bound => BoundExpr::BoolAsInt(Box::new(bound)),
+        })
+    }
+
+    pub fn condition(&self, cond: &Condition) -> Result<Filter, EngineError> {
+        Ok(match cond {
+            Condition::Compare {
+                left,
+                op: op @ (Operator::Regexp | Operator::NotRegexp),
+                right,
+            } => Filter::Regexp {
+                expr: self.expr(left)?,
+                pattern: self.expr(right)?,
+                negated: *op == Operator::NotRegexp,
+                cache: RegexCache::default(),
+            },
+            Condition::Compare { left, op, right } => Filter::Compare {
+                left: self.operand(left)?,
+                op: op.clone(),
+                right: self.operand(right)?,
+            },
+            Condition::Between { expr, low, high } => Filter::Between {
+                expr: self.operand(expr)?,
+                low: self.operand(low)?,
+                high: self.operand(high)?,
+            },
+            Condition::InList { expr, values } => {
+                let expr = self.operand(expr)?;
+                let values = values
+                    .iter()
+                    .map(|v| self.operand(v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let constants = values
+                    .iter()
+                    .map(|v| match v {
+                        BoundExpr::Literal(v) => Some(v.clone()),
+                        _ => None,
+                    })
+                    .collect::<Option<HashSet<_>>>();
+                match constants {
+                    Some(values) => Filter::In { expr, values },
+                    None => Filter::InList { expr, values },
+                }
+            }
+            Condition::InSubquery { expr, subquery } => {
+                let expr = self.operand(expr)?;
+                let result = self.engine.run_select(subquery, self.scope)?;
+                if result.columns.len() != 1 {
+                    return Err(EngineError::InvalidQuery(
+                        "IN subquery must return exactly one column".to_string(),
+                    ));
+                }
+                let values = result
+                    .rows
+                    .into_iter()
+                    .filter_map(|mut r| r.pop())
+                    .map(|v| {
+                        if self.engine.bool_ints {
+                            bool_as_int(v)
+                        } else {
+                            v
+                        }
+                    })
+                    .collect();
+                Filter::In { expr, values }
+            }
+            Condition::Any { expr, op, list } => Filter::Any {
+                expr: self.operand(expr)?,
+                op: op.clone(),
+                list: self.expr(list)?,
+            },
+            Condition::IsNull(expr) => Filter::IsNull(self.expr(expr)?),
+            Condition::Not(c) => Filter::Not(Box::new(self.condition(c)?)),
+            Condition::And(a, b) => {
+                Filter::And(Box::new(self.condition(a)?), Box::new(self.condition(b)?))
+            }
+            Condition::Or(a, b) => {
+                Filter::Or(Box::new(self.condition(a)?), Box::new(self.condition(b)?))
+            }
+        })
+    }
+}
+
+/// An expression with its column references resolved to row positions.
+#[derive(Debug, Clone)]
+pub(crate) enum BoundExpr {
+    Literal(Value),
+    Column(usize),
+    Binary {
+        op: BinaryOp,
+        left: Box<BoundExpr>,
+        right: Box<BoundExpr>,
+    },
+    Aggregate {
+        func: AggregateFunc,
+        arg: Option<Box<BoundExpr>>,
+        distinct: bool,
+    },
+    Function {
+        func: ScalarFunc,
+        args: Vec<BoundExpr>,
+    },
+    Cast {
+        expr: Box<BoundExpr>,
+        target: ValueType,
+    },
+    Case {
+        branches: Vec<(Filter, BoundExpr)>,
+        otherwise: Option<Box<BoundExpr>>,
+    },
+    /// Reads a boolean result as 1 or 0; see `Engine::bool_ints`.
+    BoolAsInt(Box<BoundExpr>),
+}
+
+fn bool_as_int(v: Value) -> Value {
+    match v {
+        Value::Bool(b) => Value::Int(b as i64),
+        v => v,
+    }
+}
+
+/// A function registered through `Engine::register_function`.
+pub(crate) struct UserFunction {
+    pub arity: usize,
+    pub func: Arc<ScalarFn>,
+}
+
+impl fmt::Debug for UserFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserFunction")
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Scalar functions callable from SQL: the built-ins plus any registered
+/// with the engine.
+#[derive(Debug, Clone)]
+pub(crate) enum ScalarFunc {
+    Coalesce,
+    IfNull,
+    Now,
+    LastInsertId,
+    Date,
+    Uuid,
+    JsonExtract,
+    JsonUnquote,
+    Cardinality,
+    ElementAt,
+    Lower,
+    Upper,
+    Unnest,
+    User(Arc<UserFunction>),
+}
+
+impl ScalarFunc {
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "COALESCE" => Some(ScalarFunc::Coalesce),
+            "IFNULL" => Some(ScalarFunc::IfNull),
+            "NOW" => Some(ScalarFunc::Now),
+            "LAST_INSERT_ID" => Some(ScalarFunc::LastInsertId),
+            "DATE" => Some(ScalarFunc::Date),
+            "UUID" => Some(ScalarFunc::Uuid),
+            "JSON_EXTRACT" => Some(ScalarFunc::JsonExtract),
+            "JSON_UNQUOTE" => Some(ScalarFunc::JsonUnquote),
+            "CARDINALITY" => Some(ScalarFunc::Cardinality),
+            "ELEMENT_AT" => Some(ScalarFunc::ElementAt),
+            "LOWER" => Some(ScalarFunc::Lower),
+            "UPPER" => Some(ScalarFunc::Upper),
+            "UNNEST" => Some(ScalarFunc::Unnest),
+            _ => None,
+        }
+    }
+
+    fn lookup(engine: &Engine, name: &str) -> Option<Self> {
+        Self::builtin(name).or_else(|| {
+            engine
+                .functions
+                .get(&name.to_ascii_uppercase())
+                .map(|f| ScalarFunc::User(f.clone()))
+        })
+    }
+
+    fn check_arity(&self, name: &str, count: usize) -> Result<(), EngineError> {
+        let ok = match self {
+            ScalarFunc::Coalesce => count >= 1,
+            ScalarFunc::IfNull => count == 2,
+            ScalarFunc::Now | ScalarFunc::LastInsertId => count == 0,
+            ScalarFunc::Date => count == 1,
+            ScalarFunc::Uuid => count == 0,
+            ScalarFunc::JsonExtract => count == 2,
+            ScalarFunc::JsonUnquote => count == 1,
+            ScalarFunc::Cardinality => count == 1,
+            ScalarFunc::ElementAt => count == 2,
+            ScalarFunc::Lower | ScalarFunc::Upper | ScalarFunc::Unnest => count == 1,
+            ScalarFunc::User(f) => count == f.arity,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(EngineError::InvalidQuery(format!(
+                "wrong number of arguments to {}: {}",
+                name, count
+            )))
+        }
+    }
+
+    /// Calls the function, evaluating arguments lazily through `arg`.
+    fn call(
+        &self,
+        count: usize,
+        mut arg: impl FnMut(usize) -> Result<Value, EngineError>,
+    ) -> Result<Value, EngineError> {
+        match self {
+            ScalarFunc::Coalesce | ScalarFunc::IfNull => {
+                for i in 0..count {
+                    let v = arg(i)?;
+                    if v != Value::Null {
+                        return Ok(v);
+                    }
+                }
+                Ok(Value::Null)
+            }
+            // NOW() is replaced by the statement timestamp when binding, and
+            // LAST_INSERT_ID() by the engine's last generated id.
+            ScalarFunc::Now | ScalarFunc::LastInsertId => unreachable!(),
+            // DATE() takes the day part of a timestamp, or parses text.
+            ScalarFunc::Date => match arg(0)? {
+                v @ (Value::Timestamp(_) | Value::Date(_) | Value::Null | Value::TypedNull(_)) => {
+                    v.cast(&ValueType::Date)
+                }
+                Value::Text(s) => Value::Text(s)
+                    .cast(&ValueType::Timestamp)?
+                    .cast(&ValueType::Date),
+                other => Err(EngineError::InvalidOperation(format!(
+                    "cannot apply DATE to {:?}",
+                    other.value_type()
+                ))),
+            },
+            // A fresh random (version 4) UUID on every call.
+            ScalarFunc::Uuid => Ok(Value::Uuid(Uuid::new_v4())),
+            // JSON_EXTRACT(doc, path) returns the JSON found at `path`, or
+            // NULL; JSON_UNQUOTE turns a JSON scalar into a plain SQL value.
+            ScalarFunc::JsonExtract => {
+                let doc = match arg(0)? {
+                    Value::Null | Value::TypedNull(_) => return Ok(Value::Null),
+                    v => v.cast(&ValueType::Json)?,
+                };
+                let Value::Json(doc) = doc else {
+                    unreachable!()
+                };
+                match arg(1)? {
+                    Value::Null | Value::TypedNull(_) => Ok(Value::Null),
+                    path => Ok(json::extract(&doc, &path)?
+                        .map_or(Value::Null, |found| Value::Json(found.clone()))),
+                }
+            }
+            ScalarFunc::JsonUnquote => match arg(0)? {
+                Value::Json(j) => Ok(json::unquote(&j)),
+                v @ (Value::Text(_) | Value::Null | Value::TypedNull(_)) => Ok(v),
+                other => Err(EngineError::InvalidOperation(format!(
+                    "cannot apply JSON_UNQUOTE to {:?}",
+                    other.value_type()
+                ))),
+            },
+            ScalarFunc::Cardinality => match arg(0)? {
+                Value::List(items) => Ok(Value::Int(items.len() as i64)),
+                Value::Null | Value::TypedNull(_) => Ok(Value::Null),
+                other => Err(EngineError::InvalidOperation(format!(
+                    "cannot apply CARDINALITY to {:?}",
+                    other.value_type()
+                ))),
+            },
+            // ELEMENT_AT(list, n) counts from 1; out-of-range positions give
+            // NULL.
+            ScalarFunc::ElementAt => match (arg(0)?, arg(1)?) {
+                (Value::List(items), Value::Int(n)) => Ok(n
+                    .checked_sub(1)
+                    .and_then(|idx| usize::try_from(idx).ok())
+                    .and_then(|idx| items.into_iter().nth(idx))
+                    .unwrap_or(Value::Null)),
+                (x, y) if x.is_null() || y.is_null() => Ok(Value::Null),
+                (list, idx) => Err(EngineError::InvalidOperation(format!(
+                    "cannot index {:?} by {:?}",
+                    list.value_type(),
+                    idx.value_type()
+                ))),
+            },
+            ScalarFunc::Lower => change_case("LOWER", arg(0)?, str::to_lowercase),
+            ScalarFunc::Upper => change_case("UPPER", arg(0)?, str::to_uppercase),
+            // UNNEST turns one row into several, which only a column of
+            // SELECT can do; see `Engine::run_select`.
+            ScalarFunc::Unnest => Err(EngineError::InvalidQuery(
+                "UNNEST is only allowed as a column of SELECT".to_string(),
+            )),
+            ScalarFunc::User(f) => {
+                let args = (0..count).map(arg).collect::<Result<Vec<_>, _>>()?;
+                (f.func)(&args)
+            }
+        }
+    }
+}
+
+/// LOWER or UPPER, called `name`, of `value`, changing text through `f`.
+fn change_case(name: &str, value: Value, f: fn(&str) -> String) -> Result<Value, EngineError> {
+    match value {
+        Value::Text(s) => Ok(Value::Text(f(&s))),
+        v @ (Value::Null | Value::TypedNull(_)) => Ok(v),
+        other => Err(EngineError::InvalidOperation(format!(
+            "cannot apply {} to {:?}",
+            name,
+            other.value_type()
+        ))),
+    }
+}
+
+impl BoundExpr {
+    /// Evaluates the expression against a single row.
+    pub fn eval(&self, row: &Row) -> Result<Value, EngineError> {
+        match self {
+            BoundExpr::Literal(v) => Ok(v.clone()),
+            BoundExpr::Column(idx) => Ok(row[*idx].clone()),
+            BoundExpr::Binary { op, left, right } => {
+                arithmetic(*op, &left.eval(row)?, &right.eval(row)?)
+            }
+            BoundExpr::Aggregate { func, .. } => Err(EngineError::InvalidQuery(format!(
+                "aggregate {} is not allowed here",
+                func
+            ))),
+            BoundExpr::Function { func, args } => func.call(args.len(), |i| args[i].eval(row)),
+            BoundExpr::Cast { expr, target } => expr.eval(row)?.cast(target),
+            BoundExpr::Case {
+                branches,
+                otherwise,
+            } => {
+                for (cond, result) in branches {
+                    if cond.matches(row)? {
+                        return result.eval(row);
+                    }
+                }
+                match otherwise {
+                    Some(e) => e.eval(row),
+                    None => Ok(Value::Null),
+                }
+            }
+            BoundExpr::BoolAsInt(expr) => Ok(bool_as_int(expr.eval(row)?)),
+        }
+    }
+
+    /// Evaluates the expression once over a whole group of rows; column
+    /// references are only allowed inside aggregate calls.
+    pub fn eval_aggregate<R: Borrow<Row>>(&self, rows: &[R]) -> Result<Value, EngineError> {
+        match self {
+            BoundExpr::Literal(v) => Ok(v.clone()),
+            BoundExpr::Column(_) => Err(EngineError::InvalidQuery(
+                "column must appear in an aggregate function".to_string(),
+            )),
+            BoundExpr::Binary { op, left, right } => arithmetic(
+                *op,
+                &left.eval_aggregate(rows)?,
+                &right.eval_aggregate(rows)?,
+            ),
+            BoundExpr::Aggregate {
+                func,
+                arg,
+                distinct,
+            } => aggregate(*func, arg.as_deref(), *distinct, rows),
+            BoundExpr::Function { func, args } => {
+                func.call(args.len(), |i| args[i].eval_aggregate(rows))
+            }
+            BoundExpr::Cast { expr, target } => expr.eval_aggregate(rows)?.cast(target),
+            BoundExpr::Case { .. } => Err(EngineError::InvalidQuery(
+                "CASE around aggregates is not supported".to_string(),
+            )),
+            BoundExpr::BoolAsInt(expr) => Ok(bool_as_int(expr.eval_aggregate(rows)?)),
+        }
+    }
+}
+
+fn arithmetic(op: BinaryOp, a: &Value, b: &Value) -> Result<Value, EngineError> {
+    let invalid = || {
+        EngineError::InvalidOperation(format!(
+            "cannot apply {} to {:?} and {:?}",
+            op,
+            a.value_type(),
+            b.value_type()
+        ))
+    };
+    match (a, b) {
+        (x, y) if x.is_null() || y.is_null() => Ok(Value::Null),
+        (Value::Int(x), Value::Int(y)) => int_arithmetic(op, *x, *y),
+        (Value::Float(x), Value::Float(y)) => float_arithmetic(op, *x, *y),
+        (Value::Decimal(x), Value::Decimal(y)) => decimal_arithmetic(op, x, y),
+        (Value::BigInt(x), Value::BigInt(y)) => bigint_arithmetic(op, *x, *y),
+        (Value::BigInt(x), Value::Int(y)) => bigint_arithmetic(op, *x, *y as i128),
+        (Value::Int(x), Value::BigInt(y)) => bigint_arithmetic(op, *x as i128, *y),
+        (Value::Decimal(x), Value::Int(y)) => decimal_arithmetic(op, x, &Decimal::from(*y)),
+        (Value::Int(x), Value::Decimal(y)) => decimal_arithmetic(op, &Decimal::from(*x), y),
+        (Value::Decimal(x), Value::BigInt(y)) => {
+            let y = Decimal::new(*y, 0).ok_or(EngineError::NumericOverflow)?;
+            decimal_arithmetic(op, x, &y)
+        }
+        (Value::BigInt(x), Value::Decimal(y)) => {
+            let x = Decimal::new(*x, 0).ok_or(EngineError::NumericOverflow)?;
+            decimal_arithmetic(op, &x, y)
+        }
+        // A float with any other number is computed in floating point.
+        (Value::Float(_), _) | (_, Value::Float(_)) => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => float_arithmetic(op, x, y),
+            _ => Err(invalid()),
+        },
+        _ => temporal_arithmetic(op, a, b).unwrap_or_else(|| Err(invalid())),
+    }
+}
+
+/// Date and time arithmetic: timestamps and times shift by an Int number
+/// of seconds and dates by an Int number of days, while subtracting two
+/// timestamps or two dates gives the seconds or days between them. Adding
+/// an interval to a date gives a date when the interval is a whole number
+/// of days and a timestamp otherwise; intervals also add to each other and
+/// scale by integers. `None` when the operation is not defined for the
+/// operands.
+fn temporal_arithmetic(op: BinaryOp, a: &Value, b: &Value) -> Option<Result<Value, EngineError>> {
+    let shift = |base: i64, amount: i64, unit: i64| {
+        let delta = amount.checked_mul(unit)?;
+        match op {
+            BinaryOp::Add => base.checked_add(delta),
+            _ => base.checked_sub(delta),
+        }
+    };
+    let additive = matches!(op, BinaryOp::Add | BinaryOp::Sub);
+    // The interval to add, negated when subtracting.
+    let signed = |iv: &Interval| match op {
+        BinaryOp::Sub => iv.checked_neg(),
+        _ => Some(*iv),
+    };
+    let shift_date = |d: i32, iv: Interval| match iv.add_to_date(d) {
+        Some(d) => Some(Value::Date(d)),
+        None => iv
+            .add_to_timestamp(d as i64 * MICROS_PER_DAY)
+            .map(Value::Timestamp),
+    };
+    let result = match (a, b) {
+        (Value::Timestamp(ts), Value::Interval(iv)) if additive => signed(iv)
+            .and_then(|iv| iv.add_to_timestamp(*ts))
+            .map(Value::Timestamp),
+        (Value::Interval(iv), Value::Timestamp(ts)) if op == BinaryOp::Add => {
+            iv.add_to_timestamp(*ts).map(Value::Timestamp)
+        }
+        (Value::Date(d), Value::Interval(iv)) if additive => {
+            signed(iv).and_then(|iv| shift_date(*d, iv))
+        }
+        (Value::Interval(iv), Value::Date(d)) if op == BinaryOp::Add => shift_date(*d, *iv),
+        (Value::Time(t), Value::Interval(iv)) if additive => signed(iv)
+            .and_then(|iv| t.checked_add(iv.micros % MICROS_PER_DAY))
+            .map(|t| Value::Time(t.rem_euclid(MICROS_PER_DAY))),
+        (Value::Interval(x), Value::Interval(y)) if additive => signed(y)
+            .and_then(|y| x.checked_add(&y))
+            .map(Value::Interval),
+        (Value::Interval(iv), Value::Int(n)) | (Value::Int(n), Value::Interval(iv))
+            if op == BinaryOp::Mul =>
+        {
+            iv.checked_mul(*n).map(Value::Interval)
+        }
+        (Value::Interval(_), Value::Int(0)) if op == BinaryOp::Div => {
+            return Some(Err(EngineError::DivisionByZero))
+        }
+        (Value::Interval(iv), Value::Int(n)) if op == BinaryOp::Div => {
+            iv.checked_div(*n).map(Value::Interval)
+        }
+        (Value::Timestamp(ts), Value::Int(secs)) if additive => {
+            shift(*ts, *secs, MICROS_PER_SECOND).map(Value::Timestamp)
+        }
+        (Value::Int(secs), Value::Timestamp(ts)) if op == BinaryOp::Add => {
+            shift(*ts, *secs, MICROS_PER_SECOND).map(Value::Timestamp)
+        }
+        (Value::Timestamp(x), Value::Timestamp(y)) if op == BinaryOp::Sub => x
+            .checked_sub(*y)
+            .map(|d| Value::Int(d.div_euclid(MICROS_PER_SECOND))),
+        (Value::Time(t), Value::Int(secs)) if additive => {
+            shift(*t, secs.rem_euclid(86_400), MICROS_PER_SECOND)
+                .map(|t| Value::Time(t.rem_euclid(MICROS_PER_DAY)))
+        }
+        (Value::Date(d), Value::Int(days)) if additive => shift(*d as i64, *days, 1)
+            .and_then(|d| i32::try_from(d).ok())
+            .map(Value::Date),
+        (Value::Int(days), Value::Date(d)) if op == BinaryOp::Add => shift(*d as i64, *days, 1)
+            .and_then(|d| i32::try_from(d).ok())
+            .map(Value::Date),
+        (Value::Date(x), Value::Date(y)) if op == BinaryOp::Sub => {
+            Some(Value::Int(*x as i64 - *y as i64))
+        }
+        _ => return None,
+    };
+    Some(result.ok_or(EngineError::NumericOverflow))
+}
+
+fn int_arithmetic(op: BinaryOp, x: i64, y: i64) -> Result<Value, EngineError> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && y == 0 {
+        return Err(EngineError::DivisionByZero);
+    }
+    let result = match op {
+        BinaryOp::Add => x.checked_add(y),
+        BinaryOp::Sub => x.checked_sub(y),
+        BinaryOp::Mul => x.checked_mul(y),
+        BinaryOp::Div => x.checked_div(y),
+        BinaryOp::Mod => x.checked_rem(y),
+    };
+    result.map(Value::Int).ok_or(EngineError::NumericOverflow)
+}
+
+fn bigint_arithmetic(op: BinaryOp, x: i128, y: i128) -> Result<Value, EngineError> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && y == 0 {
+        return Err(EngineError::DivisionByZero);
+    }
+    let result = match op {
+        BinaryOp::Add => x.checked_add(y),
+        BinaryOp::Sub => x.checked_sub(y),
+        BinaryOp::Mul => x.checked_mul(y),
+        BinaryOp::Div => x.checked_div(y),
+        BinaryOp::Mod => x.checked_rem(y),
+    };
+    result
+        .map(Value::BigInt)
+        .ok_or(EngineError::NumericOverflow)
+}
+
+fn decimal_arithmetic(op: BinaryOp, x: &Decimal, y: &Decimal) -> Result<Value, EngineError> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && y.is_zero() {
+        return Err(EngineError::DivisionByZero);
+    }
+    let result = match op {
+        BinaryOp::Add => x.checked_add(y),
+        BinaryOp::Sub => x.checked_sub(y),
+        BinaryOp::Mul => x.checked_mul(y),
+        BinaryOp::Div => x.checked_div(y),
+        BinaryOp::Mod => x.checked_rem(y),
+    };
+    result
+        .map(Value::Decimal)
+        .ok_or(EngineError::NumericOverflow)
+}
+
+fn float_arithmetic(op: BinaryOp, x: f64, y: f64) -> Result<Value, EngineError> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && y == 0.0 {
+        return Err(EngineError::DivisionByZero);
+    }
+    let result = match op {
+        BinaryOp::Add => x + y,
+        BinaryOp::Sub => x - y,
+        BinaryOp::Mul => x * y,
+        BinaryOp::Div => x / y,
+        BinaryOp::Mod => x % y,
+    };
+    if result.is_infinite() && x.is_finite() && y.is_finite() {
+        return Err(EngineError::NumericOverflow);
+    }
+    Ok(Value::Float(result))
+}
+
+fn aggregate<R: Borrow<Row>>(
+    func: AggregateFunc,
+    arg: Option<&BoundExpr>,
+    distinct: bool,
+    rows: &[R],
+) -> Result<Value, EngineError> {
+    let arg = match arg {
+        Some(arg) => arg,
+        None if func == AggregateFunc::Count => return Ok(Value::Int(rows.len() as i64)),
+        None => {
+            return Err(EngineError::InvalidQuery(format!(
+                "{}(*) is not supported",
+                func
+            )))
+        }
+    };
+    let mut values = Vec::with_capacity(rows.len());
+    let mut seen = HashSet::new();
+    for row in rows {
+        let v = arg.eval(row.borrow())?;
+        if v != Value::Null && (!distinct || seen.insert(v.clone())) {
+            values.push(v);
+        }
+    }
+    match func {
+        AggregateFunc::Count => Ok(Value::Int(values.len() as i64)),
+        AggregateFunc::Sum | AggregateFunc::Avg => {
+            let mut sum = match values.first() {
+                Some(Value::Int(_)) => Value::Int(0),
+                Some(Value::BigInt(_)) => Value::BigInt(0),
+                Some(Value::Interval(_)) => Value::Interval(Interval::default()),
+                Some(Value::Float(_)) => Value::Float(0.0),
+                Some(Value::Decimal(_)) => Value::Decimal(Decimal::ZERO),
+                Some(other) => {
+                    return Err(EngineError::InvalidOperation(format!(
+                        "cannot apply {} to {:?}",
+                        func,
+                        other.value_type()
+                    )))
+                }
+                None => return Ok(Value::Null),
+            };
+            for v in &values {
+                sum = arithmetic(BinaryOp::Add, &sum, v)?;
+            }
+            if func == AggregateFunc::Avg {
+                let count = values.len() as i64;
+                sum = match sum {
+                    Value::Float(f) => Value::Float(f / count as f64),
+                    sum => arithmetic(BinaryOp::Div, &sum, &Value::Int(count))?,
+                };
+            }
+            Ok(sum)
+        }
+        AggregateFunc::Min => Ok(values.into_iter().min().unwrap_or(Value::Null)),
+        AggregateFunc::Max => Ok(values.into_iter().max().unwrap_or(Value::Null)),
+    }
+}
+
+/// Compiled patterns by their text. Filters are shared by the threads
+/// reading a table, so the cache is locked.
+#[derive(Debug, Default)]
+pub(crate) struct RegexCache(Mutex<HashMap<String, Regex>>);
+
+impl Clone for RegexCache {
+    fn clone(&self) -> Self {
+        let cache = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        Self(Mutex::new(cache.clone()))
+    }
+}
+
+/// A condition resolved against a relation, ready to test rows.
+#[derive(Debug, Clone)]
+pub(crate) enum Filter {
+    Compare {
+        left: BoundExpr,
+        op: Operator,
+        right: BoundExpr,
+    },
+    Between {
+        expr: BoundExpr,
+        low: BoundExpr,
+        high: BoundExpr,
+    },
+    /// Regular-expression match on text; each distinct pattern is compiled
+    /// once per statement.
+    Regexp {
+        expr: BoundExpr,
+        pattern: BoundExpr,
+        negated: bool,
+        cache: RegexCache,
+    },
+    /// Membership in a set of constants.
+    In {
+        expr: BoundExpr,
+        values: HashSet<Value>,
+    },
+    /// Membership in a list of non-constant expressions.
+    InList {
+        expr: BoundExpr,
+        values: Vec<BoundExpr>,
+    },
+    /// Comparison against each element of a list value.
+    Any {
+        expr: BoundExpr,
+        op: Operator,
+        list: BoundExpr,
+    },
+    IsNull(BoundExpr),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    /// Whether the row satisfies the filter; UNKNOWN counts as a mismatch,
+    /// as in a WHERE clause.
+    pub fn matches(&self, row: &Row) -> Result<bool, EngineError> {
+        Ok(self.eval(row)? == Some(true))
+    }
+
+    /// Evaluates the filter under SQL three-valued logic, with `None`
+    /// standing for UNKNOWN.
+    pub fn eval(&self, row: &Row) -> Result<Option<bool>, EngineError> {
+        Ok(match self {
+            Filter::Compare { left, op, right } => {
+                Engine::compare(&left.eval(row)?, op, &right.eval(row)?)
+            }
+            Filter::Between { expr, low, high } => {
+                let v = expr.eval(row)?;
+                and(
+                    Engine::compare(&v, &Operator::Ge, &low.eval(row)?),
+                    Engine::compare(&v, &Operator::Le, &high.eval(row)?),
+                )
+            }
+            Filter::Regexp {
+                expr,
+                pattern,
+                negated,
+                cache,
+            } => match (expr.eval(row)?, pattern.eval(row)?) {
+                (Value::Text(text), Value::Text(pattern)) => {
+                    let mut cache = cache.0.lock().unwrap_or_else(|e| e.into_inner());
+                    let regex = match cache.entry(pattern) {
+                        Entry::Occupied(e) => e.into_mut(),
+                        Entry::Vacant(e) => {
+                            let regex = Regex::new(e.key())
+                                .map_err(|err| EngineError::InvalidPattern(err.to_string()))?;
+                            e.insert(regex)
+                        }
+                    };
+                    Some(regex.is_match(&text) != *negated)
+                }
+                (x, y) if x.is_null() || y.is_null() => None,
+                _ => Some(false),
+            },
+            Filter::In { expr, values } => match expr.eval(row)? {
+                v if v.is_null() => None,
+                v if values.contains(&v) => Some(true),
+                // Values of another type can still compare equal, such as an
+                // Int and a Float.
+                v if values.iter().any(|c| {
+                    std::mem::discriminant(c) != std::mem::discriminant(&v)
+                        && Engine::compare(&v, &Operator::Eq, c) == Some(true)
+                }) =>
+                {
+                    Some(true)
+                }
+                _ if values.contains(&Value::Null) => None,
+                _ => Some(false),
+            },
+            Filter::InList { expr, values } => {
+                let v = expr.eval(row)?;
+                let mut found = Some(false);
+                for candidate in values {
+                    found = or(
+                        found,
+                        Engine::compare(&v, &Operator::Eq, &candidate.eval(row)?),
+                    );
+                    if found == Some(true) {
+                        break;
+                    }
+                }
+                found
+            }
+            Filter::Any { expr, op, list } => match list.eval(row)? {
+                Value::List(items) => {
+                    let v = expr.eval(row)?;
+                    let mut found = Some(false);
+                    for item in &items {
+                        found = or(found, Engine::compare(&v, op, item));
+                        if found == Some(true) {
+                            break;
+                        }
+                    }
+                    found
+                }
+                v if v.is_null() => None,
+                other => {
+                    return Err(EngineError::InvalidOperation(format!(
+                        "ANY expects a list, found {:?}",
+                        other.value_type()
+                    )))
+                }
+            },
+            Filter::IsNull(expr) => Some(expr.eval(row)?.is_null()),
+            Filter::Not(f) => f.eval(row)?.map(|b| !b),
+            Filter::And(a, b) => match a.eval(row)? {
+                Some(false) => Some(false),
+                left => and(left, b.eval(row)?),
+            },
+            Filter::Or(a, b) => match a.eval(row)? {
+                Some(true) => Some(true),
+                left => or(left, b.eval(row)?),
+            },
+        })
+    }
+}
+
+/// Three-valued AND: FALSE wins over UNKNOWN.
+fn and(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+/// Three-valued OR: TRUE wins over UNKNOWN.
+fn or(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Infers the type of a computed column from its first value of known
+/// type; a NULL read from a column carries that column's type.
+pub(crate) fn infer_type(rows: &[Row], idx: usize) -> ValueType {
+    rows.iter()
+        .map(|r| r[idx].value_type())
+        .find(|t| *t != ValueType::Null)
+        .unwrap_or(ValueType::Null)
+}
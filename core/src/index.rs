@@ -0,0 +1,1662 @@
+//! Secondary indexes over one or more columns, and the lookups that answer
+//! a WHERE condition from them instead of testing every row.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::mem;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Row, Scope, Table, Value, ValueType};
+use crate::expr::{Binder, BoundExpr, Relation, ScalarFunc};
+use crate::parser::{parse_expr, Condition, CreateIndexQuery, Expr, IndexHint, Operator};
+use crate::partition::partition_scan;
+
+/// How an index stores its keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexKind {
+    /// Hashed keys, for equality and IN-list lookups.
+    #[default]
+    Hash,
+    /// Sorted keys, which also serve `<`, `<=`, `>`, `>=` and BETWEEN, and
+    /// lookups on the leading columns of a multi-column index.
+    Ordered,
+    /// Every run of three characters in a text column, for LIKE patterns
+    /// holding such runs, as in `LIKE '%needle%'`. The rows found hold
+    /// every run of the pattern, and are tested against it again.
+    Trigram,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Entries {
+    Hash(#[serde(with = "pairs")] HashMap<Value, Vec<usize>>),
+    Ordered(#[serde(with = "pairs")] BTreeMap<Value, Vec<usize>>),
+    /// Rows by each trigram of their key, as text.
+    Trigram(#[serde(with = "pairs")] HashMap<Value, Vec<usize>>),
+}
+
+/// Serializes entries as a list of key and rows pairs, since formats such
+/// as JSON only take strings as map keys.
+mod pairs {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::engine::Value;
+
+    pub fn serialize<'a, M, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a M: IntoIterator<Item = (&'a Value, &'a Vec<usize>)>,
+        S: Serializer,
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, M, D>(deserializer: D) -> Result<M, D::Error>
+    where
+        M: FromIterator<(Value, Vec<usize>)>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(Value, Vec<usize>)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// Number of queries that read rows through an index. Queries only borrow
+/// the table, so the count is atomic.
+#[derive(Debug, Default)]
+struct Hits(AtomicU64);
+
+impl Clone for Hits {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(AtomicOrdering::Relaxed)))
+    }
+}
+
+/// One part of an index key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredKeyPart", into = "StoredKeyPart")]
+enum KeyPart {
+    /// The value of the column at this position.
+    Column(usize),
+    /// The value of an expression over the row's columns, given as written
+    /// with column names resolved, and bound for evaluation. Bound
+    /// expressions can call registered functions, so only the SQL is
+    /// serialized, to be bound again by [`Engine::rebuild_indexes`].
+    Expr(Expr, Box<BoundExpr>),
+    /// An expression read back as SQL and not yet bound.
+    Unbound(String),
+    /// The first characters of the text column at this position, so that
+    /// long values take up less of the index. Rows found through it are
+    /// tested against the whole value.
+    Prefix(usize, usize),
+}
+
+/// A [`KeyPart`] as serialized.
+#[derive(Serialize, Deserialize)]
+enum StoredKeyPart {
+    Column(usize),
+    Expr(String),
+    Prefix(usize, usize),
+}
+
+impl From<StoredKeyPart> for KeyPart {
+    fn from(part: StoredKeyPart) -> Self {
+        match part {
+            StoredKeyPart::Column(pos) => KeyPart::Column(pos),
+            StoredKeyPart::Expr(sql) => KeyPart::Unbound(sql),
+            StoredKeyPart::Prefix(pos, chars) => KeyPart::Prefix(pos, chars),
+        }
+    }
+}
+
+impl From<KeyPart> for StoredKeyPart {
+    fn from(part: KeyPart) -> Self {
+        match part {
+            KeyPart::Column(pos) => StoredKeyPart::Column(pos),
+            KeyPart::Expr(expr, _) => StoredKeyPart::Expr(expr.to_string()),
+            KeyPart::Unbound(sql) => StoredKeyPart::Expr(sql),
+            KeyPart::Prefix(pos, chars) => StoredKeyPart::Prefix(pos, chars),
+        }
+    }
+}
+
+/// Maps the values of some columns, or of expressions over them, to the
+/// positions of the rows holding them. The key of a row is its value for
+/// an index of one column or expression, and the list of its values, in
+/// index order, otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Index {
+    /// The indexed columns, with an expression shown as SQL.
+    pub columns: Vec<String>,
+    /// Whether no two rows may share a key. Keys with a NULL are exempt.
+    #[serde(default)]
+    pub unique: bool,
+    parts: Vec<KeyPart>,
+    /// Shared by copies of the table until one of them changes.
+    entries: Arc<Entries>,
+    /// The [`Value::type_rank`] of every non-NULL key stored.
+    #[serde(default)]
+    key_types: BTreeSet<(u8, u8)>,
+    /// Whether the entries hold every row: false once they were dropped,
+    /// as by [`Table::drop_index_entries`], until the index is rebuilt.
+    #[serde(default)]
+    filled: bool,
+    /// Counted since the index was created or the table read back.
+    #[serde(skip)]
+    hits: Hits,
+}
+
+/// Size and use of one index, as returned by [`Engine::index_stats`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexStats {
+    pub name: String,
+    pub columns: Vec<String>,
+    /// Number of rows stored.
+    pub entries: usize,
+    /// Number of distinct keys stored.
+    pub keys: usize,
+    /// Rough number of bytes the entries take up.
+    pub memory: usize,
+    /// Number of queries that read rows through the index.
+    pub hits: u64,
+}
+
+impl Index {
+    fn new(columns: Vec<String>, parts: Vec<KeyPart>, kind: IndexKind, unique: bool) -> Self {
+        let entries = match kind {
+            IndexKind::Hash => Entries::Hash(HashMap::new()),
+            IndexKind::Ordered => Entries::Ordered(BTreeMap::new()),
+            IndexKind::Trigram => Entries::Trigram(HashMap::new()),
+        };
+        Self {
+            columns,
+            unique,
+            parts,
+            entries: Arc::new(entries),
+            key_types: BTreeSet::new(),
+            filled: false,
+            hits: Hits::default(),
+        }
+    }
+
+    /// The index with no entries, covering the same key and keeping its
+    /// hit count.
+    fn emptied(&mut self) -> Self {
+        Self {
+            hits: mem::take(&mut self.hits),
+            ..Self::new(
+                mem::take(&mut self.columns),
+                mem::take(&mut self.parts),
+                self.kind(),
+                self.unique,
+            )
+        }
+    }
+
+    /// Frees the room the entries hold for rows not yet added.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let positions: Box<dyn Iterator<Item = &mut Vec<usize>>> =
+            match Arc::make_mut(&mut self.entries) {
+                Entries::Hash(map) | Entries::Trigram(map) => {
+                    map.shrink_to_fit();
+                    Box::new(map.values_mut())
+                }
+                Entries::Ordered(map) => Box::new(map.values_mut()),
+            };
+        positions.for_each(Vec::shrink_to_fit);
+    }
+
+    pub(crate) fn stats(&self, name: &str) -> IndexStats {
+        let (keys, entries, memory) = match &*self.entries {
+            Entries::Hash(map) => count_entries(map),
+            Entries::Ordered(map) => count_entries(map),
+            Entries::Trigram(map) => count_entries(map),
+        };
+        IndexStats {
+            name: name.to_string(),
+            columns: self.columns.clone(),
+            entries,
+            keys,
+            memory,
+            hits: self.hits.0.load(AtomicOrdering::Relaxed),
+        }
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        match *self.entries {
+            Entries::Hash(_) => IndexKind::Hash,
+            Entries::Ordered(_) => IndexKind::Ordered,
+            Entries::Trigram(_) => IndexKind::Trigram,
+        }
+    }
+
+    /// Whether the index holds every row and can compute the key of a new
+    /// one. An index read back without its entries, or over expressions,
+    /// is not ready until [`Engine::rebuild_indexes`] rebuilds it, and
+    /// lookups pass it over.
+    pub fn is_ready(&self) -> bool {
+        self.filled && !self.parts.iter().any(|p| matches!(p, KeyPart::Unbound(_)))
+    }
+
+    /// Whether every part of the key is a plain column.
+    pub(crate) fn is_plain(&self) -> bool {
+        self.parts.iter().all(|p| matches!(p, KeyPart::Column(_)))
+    }
+
+    /// The key `row` is stored under. Fails if an indexed expression does.
+    pub(crate) fn key(&self, row: &Row) -> Result<Value, EngineError> {
+        let part = |part: &KeyPart| match part {
+            KeyPart::Column(pos) => Ok(row[*pos].clone()),
+            KeyPart::Expr(_, bound) => bound.eval(row),
+            KeyPart::Prefix(pos, chars) => Ok(truncate(&row[*pos], *chars)),
+            KeyPart::Unbound(sql) => Err(EngineError::InvalidOperation(format!(
+                "indexed expression {} must be bound again by rebuilding the indexes",
+                sql
+            ))),
+        };
+        match self.parts.as_slice() {
+            [single] => part(single),
+            parts => parts
+                .iter()
+                .map(part)
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+        }
+    }
+
+    /// Whether `key` is taken in a unique index. Keys with a NULL never
+    /// conflict.
+    pub(crate) fn conflict(&self, key: &Value) -> bool {
+        self.unique && !self.has_null(key) && self.contains(key)
+    }
+
+    /// Whether more than one row holds `key` in a unique index, as after
+    /// rows were added without checking; see [`Engine::copy_in`].
+    pub(crate) fn duplicated(&self, key: &Value) -> bool {
+        self.unique && !self.has_null(key) && self.get(key).len() > 1
+    }
+
+    fn has_null(&self, key: &Value) -> bool {
+        match (key, self.parts.len()) {
+            (Value::List(values), n) if n > 1 => values.iter().any(Value::is_null),
+            (key, _) => key.is_null(),
+        }
+    }
+
+    pub(crate) fn contains(&self, key: &Value) -> bool {
+        !self.get(key).is_empty()
+    }
+
+    /// Adds every row, failing on an expression error or, in a unique
+    /// index, on the first duplicate.
+    fn fill(&mut self, name: &str, rows: &[Row]) -> Result<(), EngineError> {
+        for (row_idx, row) in rows.iter().enumerate() {
+            let key = self.key(row)?;
+            if self.conflict(&key) {
+                return Err(EngineError::UniqueViolation {
+                    constraint: name.to_string(),
+                    value: key,
+                });
+            }
+            self.insert(key, row_idx);
+        }
+        self.filled = true;
+        Ok(())
+    }
+
+    /// Stores row `row_idx` under `key`, keeping the rows of every key in
+    /// table order.
+    pub(crate) fn insert(&mut self, key: Value, row_idx: usize) {
+        if !key.is_null() {
+            self.key_types.insert(key.type_rank());
+        }
+        // A row repeating a trigram is stored under it once.
+        let add = |rows: &mut Vec<usize>| {
+            if let Err(at) = rows.binary_search(&row_idx) {
+                rows.insert(at, row_idx);
+            }
+        };
+        match Arc::make_mut(&mut self.entries) {
+            Entries::Hash(map) => add(map.entry(key).or_default()),
+            Entries::Ordered(map) => add(map.entry(key).or_default()),
+            Entries::Trigram(map) => {
+                let Value::Text(text) = &key else {
+                    return;
+                };
+                for trigram in trigrams(text) {
+                    add(map.entry(trigram).or_default());
+                }
+            }
+        }
+    }
+
+    /// Stops storing row `row_idx` under `key`, dropping keys left with no
+    /// rows.
+    pub(crate) fn remove(&mut self, key: &Value, row_idx: usize) {
+        let drop_row = |rows: &mut Vec<usize>| {
+            if let Ok(at) = rows.binary_search(&row_idx) {
+                rows.remove(at);
+            }
+            rows.is_empty()
+        };
+        match Arc::make_mut(&mut self.entries) {
+            Entries::Hash(map) => {
+                if map.get_mut(key).is_some_and(drop_row) {
+                    map.remove(key);
+                }
+            }
+            Entries::Ordered(map) => {
+                if map.get_mut(key).is_some_and(drop_row) {
+                    map.remove(key);
+                }
+            }
+            Entries::Trigram(map) => {
+                let Value::Text(text) = key else {
+                    return;
+                };
+                for trigram in trigrams(text) {
+                    if map.get_mut(&trigram).is_some_and(drop_row) {
+                        map.remove(&trigram);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves the rows stored after those at `doomed`, just deleted and
+    /// removed from the index, back into their places.
+    fn close_gaps(&mut self, doomed: &[usize]) {
+        let Some(&first) = doomed.first() else {
+            return;
+        };
+        let positions: Box<dyn Iterator<Item = &mut Vec<usize>>> =
+            match Arc::make_mut(&mut self.entries) {
+                Entries::Hash(map) | Entries::Trigram(map) => Box::new(map.values_mut()),
+                Entries::Ordered(map) => Box::new(map.values_mut()),
+            };
+        for rows in positions {
+            for row_idx in rows.iter_mut().filter(|row_idx| **row_idx > first) {
+                *row_idx -= doomed.partition_point(|d| d < row_idx);
+            }
+        }
+    }
+
+    /// Whether the index can look up `probe`.
+    fn answers(&self, probe: &Probe) -> bool {
+        match self.kind() {
+            IndexKind::Hash => matches!(probe, Probe::Keys(_)),
+            IndexKind::Ordered => true,
+            IndexKind::Trigram => false,
+        }
+    }
+
+    /// Positions of the rows of a trigram index holding every one of
+    /// `trigrams`, in table order.
+    fn trigram_rows(&self, trigrams: &[Value]) -> Vec<usize> {
+        let Entries::Trigram(map) = &*self.entries else {
+            return Vec::new();
+        };
+        let mut lists = trigrams
+            .iter()
+            .map(|t| map.get(t).map_or(&[][..], Vec::as_slice))
+            .collect::<Vec<_>>();
+        lists.sort_by_key(|rows| rows.len());
+        let Some((shortest, rest)) = lists.split_first() else {
+            return Vec::new();
+        };
+        // Rows are added in table order, so every list is sorted.
+        let mut rows = shortest.to_vec();
+        rows.retain(|row| rest.iter().all(|list| list.binary_search(row).is_ok()));
+        rows
+    }
+
+    /// Positions of the rows stored under `key`; in a trigram index, of
+    /// the rows holding the trigram `key`.
+    pub fn get(&self, key: &Value) -> &[usize] {
+        let rows = match &*self.entries {
+            Entries::Hash(map) | Entries::Trigram(map) => map.get(key),
+            Entries::Ordered(map) => map.get(key),
+        };
+        rows.map_or(&[], Vec::as_slice)
+    }
+
+    /// Positions of the rows whose key lies between the bounds, or `None`
+    /// for an index that does not keep its keys in order. NULLs are never
+    /// in range.
+    pub fn range(&self, low: Bound<&Value>, high: Bound<&Value>) -> Option<Vec<usize>> {
+        let Entries::Ordered(map) = &*self.entries else {
+            return None;
+        };
+        // NULL sorts before every other value, so an open lower end starts
+        // just after it.
+        let low = match low {
+            Bound::Unbounded => Bound::Excluded(&Value::Null),
+            bound => bound,
+        };
+        if let (Bound::Included(l) | Bound::Excluded(l), Bound::Included(h) | Bound::Excluded(h)) =
+            (low, high)
+        {
+            // BTreeMap::range panics on inverted bounds; such a range is
+            // simply empty.
+            if l > h || (l == h && !matches!((low, high), (Bound::Included(_), Bound::Included(_))))
+            {
+                return Some(Vec::new());
+            }
+        }
+        Some(
+            map.range::<Value, _>((low, high))
+                .flat_map(|(_, rows)| rows.iter().copied())
+                .collect(),
+        )
+    }
+
+    /// Positions of the rows of a multi-column index whose leading values
+    /// are `prefix`, or `None` for an index that does not keep its keys in
+    /// order. Lists sort element by element, so those rows are the keys
+    /// from `prefix` onwards for as long as they start with it.
+    pub fn prefix(&self, prefix: &[Value]) -> Option<Vec<usize>> {
+        let Entries::Ordered(map) = &*self.entries else {
+            return None;
+        };
+        let start = Value::List(prefix.to_vec());
+        Some(
+            map.range::<Value, _>((Bound::Included(&start), Bound::Unbounded))
+                .take_while(
+                    |(key, _)| matches!(key, Value::List(values) if values.starts_with(prefix)),
+                )
+                .flat_map(|(_, rows)| rows.iter().copied())
+                .collect(),
+        )
+    }
+}
+
+/// The distinct runs of three characters in `text`.
+fn trigrams(text: &str) -> BTreeSet<Value> {
+    let chars = text.chars().collect::<Vec<_>>();
+    chars
+        .windows(3)
+        .map(|w| Value::Text(w.iter().collect()))
+        .collect()
+}
+
+/// The trigrams every text matching a LIKE pattern holds: those of its
+/// runs of plain characters between wildcards.
+fn pattern_trigrams(pattern: &str) -> Vec<Value> {
+    let mut all = BTreeSet::new();
+    for run in pattern.split(['%', '_']) {
+        all.extend(trigrams(run));
+    }
+    all.into_iter().collect()
+}
+
+/// The first `chars` characters of a text value; other values are kept
+/// whole.
+fn truncate(value: &Value, chars: usize) -> Value {
+    match value {
+        Value::Text(s) => Value::Text(s.chars().take(chars).collect()),
+        value => value.clone(),
+    }
+}
+
+/// The number of keys and rows in a map of entries, and roughly how many
+/// bytes they take up.
+fn count_entries<'a>(
+    map: impl IntoIterator<Item = (&'a Value, &'a Vec<usize>)>,
+) -> (usize, usize, usize) {
+    let (mut keys, mut rows, mut memory) = (0, 0, 0);
+    for (key, positions) in map {
+        keys += 1;
+        rows += positions.len();
+        memory += value_size(key)
+            + mem::size_of::<Vec<usize>>()
+            + positions.capacity() * mem::size_of::<usize>();
+    }
+    (keys, rows, memory)
+}
+
+/// Rough number of bytes a value takes up, counting what it owns.
+pub(crate) fn value_size(value: &Value) -> usize {
+    mem::size_of::<Value>()
+        + match value {
+            Value::Text(s) => s.capacity(),
+            Value::List(values) => values.iter().map(value_size).sum(),
+            Value::Json(json) => json.to_string().len(),
+            _ => 0,
+        }
+}
+
+impl Table {
+    /// Indexes `column` under its own name, replacing any index of that
+    /// name.
+    pub fn create_index(&mut self, column: &str) {
+        self.create_index_with_kind(column, IndexKind::Hash);
+    }
+
+    pub fn create_index_with_kind(&mut self, column: &str, kind: IndexKind) {
+        // Only a missing column can fail, and then there is nothing to index.
+        let _ = self.add_index(column, &[column], kind);
+    }
+
+    /// Adds an index named `name` over `columns`, replacing any index of
+    /// that name. Fails if a column does not exist.
+    pub fn add_index(
+        &mut self,
+        name: &str,
+        columns: &[&str],
+        kind: IndexKind,
+    ) -> Result<(), EngineError> {
+        self.build_index(name, columns, kind, false)
+    }
+
+    /// Adds an index like [`Table::add_index`] that also rejects rows
+    /// repeating the key of another, failing if existing rows already do.
+    pub fn add_unique_index(
+        &mut self,
+        name: &str,
+        columns: &[&str],
+        kind: IndexKind,
+    ) -> Result<(), EngineError> {
+        self.build_index(name, columns, kind, true)
+    }
+
+    pub(crate) fn build_index(
+        &mut self,
+        name: &str,
+        columns: &[&str],
+        kind: IndexKind,
+        unique: bool,
+    ) -> Result<(), EngineError> {
+        let parts = self
+            .column_positions(columns)?
+            .into_iter()
+            .map(KeyPart::Column)
+            .collect();
+        let columns = columns.iter().map(|c| c.to_string()).collect();
+        self.put_index(name, Index::new(columns, parts, kind, unique))
+    }
+
+    /// Fills `index` from the rows and adds it under `name`.
+    fn put_index(&mut self, name: &str, mut index: Index) -> Result<(), EngineError> {
+        if self.uniques.iter().any(|u| u.name == name) {
+            return Err(EngineError::InvalidQuery(format!(
+                "index {} enforces a UNIQUE constraint",
+                name
+            )));
+        }
+        if index.kind() == IndexKind::Trigram {
+            let text = match index.parts.as_slice() {
+                [KeyPart::Column(pos)] => matches!(
+                    self.columns[*pos].col_type,
+                    ValueType::Text | ValueType::Varchar(_)
+                ),
+                _ => false,
+            };
+            if !text || index.unique {
+                return Err(EngineError::InvalidQuery(format!(
+                    "trigram index {} must be over a single text column, and cannot be unique",
+                    name
+                )));
+            }
+        }
+        index.fill(name, &self.all_rows())?;
+        self.indices.insert(name.to_string(), index);
+        Ok(())
+    }
+
+    /// Rebuilds every index from the rows, after rows were removed or
+    /// changed in place, failing if a unique index finds a duplicate or an
+    /// indexed expression fails.
+    pub(crate) fn rebuild_indexes(&mut self) -> Result<(), EngineError> {
+        let mut names = self.indices.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names.iter().try_for_each(|name| self.rebuild_index(name))
+    }
+
+    /// Rebuilds the index `name` from the rows, if there is one.
+    pub(crate) fn rebuild_index(&mut self, name: &str) -> Result<(), EngineError> {
+        let Some(mut index) = self.indices.remove(name) else {
+            return Ok(());
+        };
+        index = index.emptied();
+        let filled = index.fill(name, &self.all_rows());
+        self.indices.insert(name.to_string(), index);
+        filled
+    }
+
+    /// Updates every index for the rows at the positions in `replaced`,
+    /// just changed in place from the rows paired with them, failing if a
+    /// unique index finds a duplicate or an indexed expression fails. An
+    /// index not ready for new rows is rebuilt instead.
+    pub(crate) fn reindex_rows(&mut self, replaced: &[(usize, Row)]) -> Result<(), EngineError> {
+        let mut names = self.indices.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let Some(mut index) = self.indices.remove(&name) else {
+                continue;
+            };
+            if !index.is_ready() {
+                self.indices.insert(name.clone(), index);
+                self.rebuild_index(&name)?;
+                continue;
+            }
+            let updated = self.update_index(&name, &mut index, replaced);
+            self.indices.insert(name, index);
+            updated?;
+        }
+        Ok(())
+    }
+
+    fn update_index(
+        &self,
+        name: &str,
+        index: &mut Index,
+        replaced: &[(usize, Row)],
+    ) -> Result<(), EngineError> {
+        // Take every old key out first, so that rows may trade keys.
+        for (row_idx, old) in replaced {
+            index.remove(&index.key(old)?, *row_idx);
+        }
+        for (row_idx, _) in replaced {
+            let key = index.key(&self.row(*row_idx))?;
+            if index.conflict(&key) {
+                return Err(EngineError::UniqueViolation {
+                    constraint: name.to_string(),
+                    value: key,
+                });
+            }
+            index.insert(key, *row_idx);
+        }
+        Ok(())
+    }
+
+    /// Takes the rows `removed`, just deleted from the positions `doomed`,
+    /// out of every index and moves the rows after them back into their
+    /// places. An index not ready for new rows is rebuilt instead.
+    pub(crate) fn unindex_rows(
+        &mut self,
+        doomed: &BTreeSet<usize>,
+        removed: &[Row],
+    ) -> Result<(), EngineError> {
+        let doomed = doomed.iter().copied().collect::<Vec<_>>();
+        let mut names = self.indices.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let Some(index) = self.indices.get_mut(&name) else {
+                continue;
+            };
+            if !index.is_ready() {
+                self.rebuild_index(&name)?;
+                continue;
+            }
+            for (row_idx, row) in doomed.iter().zip(removed) {
+                index.remove(&index.key(row)?, *row_idx);
+            }
+            index.close_gaps(&doomed);
+        }
+        Ok(())
+    }
+
+    /// Empties every index, keeping what it covers, so that the table
+    /// serializes smaller. Until [`Engine::rebuild_indexes`] rebuilds
+    /// them, queries scan instead of using the indexes, and rows cannot be
+    /// added.
+    pub fn drop_index_entries(&mut self) {
+        for index in self.indices.values_mut() {
+            *index = index.emptied();
+        }
+    }
+}
+
+/// Cost of reading a row through an index, relative to reading it during
+/// a scan: index lookups visit rows out of order, and collect and sort
+/// their positions first.
+const INDEX_ROW_COST: f64 = 4.0;
+
+/// How a table is read for a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessPath {
+    /// Every row is read and tested.
+    Scan,
+    /// The rows stored under some keys of the named index.
+    IndexLookup(String),
+    /// The rows in a range of keys of the named ordered index.
+    IndexRange(String),
+    /// No row at all, as the bloom filter on the named column shows that
+    /// none holds the value compared with.
+    BloomFilter(String),
+    /// The rows of the named partitions, the only ones that can hold rows
+    /// matching; see [`crate::Partitioning`].
+    Partitions(Vec<String>),
+}
+
+impl fmt::Display for AccessPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessPath::Scan => write!(f, "SCAN"),
+            AccessPath::IndexLookup(name) => write!(f, "INDEX LOOKUP {}", name),
+            AccessPath::IndexRange(name) => write!(f, "INDEX RANGE {}", name),
+            AccessPath::BloomFilter(column) => write!(f, "BLOOM FILTER {}", column),
+            AccessPath::Partitions(names) => write!(f, "PARTITIONS {}", names.join(", ")),
+        }
+    }
+}
+
+/// Rows found through an index.
+pub(crate) struct IndexScan {
+    pub(crate) path: AccessPath,
+    /// Positions of the rows, in table order.
+    pub(crate) rows: Vec<usize>,
+    /// Whether every row found is known to match, so that the rows need
+    /// not be tested again.
+    pub(crate) exact: bool,
+}
+
+impl IndexScan {
+    fn in_table_order(mut self) -> Self {
+        self.rows.sort_unstable();
+        self.rows.dedup();
+        self
+    }
+
+    /// Counts a query reading rows through the index, if one was used.
+    pub(crate) fn count_hit(&self, table: &Table) {
+        let (AccessPath::IndexLookup(name) | AccessPath::IndexRange(name)) = &self.path else {
+            return;
+        };
+        if let Some(index) = table.indices.get(name) {
+            index.hits.0.fetch_add(1, AtomicOrdering::Relaxed);
+        }
+    }
+}
+
+/// A way to find the rows matching part of a condition through an index.
+enum Candidate<'a> {
+    /// A comparison, or several bounding the same column, in an index over
+    /// the column or expression.
+    Probe(&'a Expr, Probe<'a>),
+    /// Constants for the leading columns of a multi-column index.
+    Prefix(&'a str, &'a Index, Vec<Value>),
+    /// The trigrams of a LIKE pattern, in a trigram index over the column.
+    Trigram(&'a str, &'a Index, Vec<Value>),
+}
+
+/// What part of a column or expression a condition selects, when an index
+/// can tell.
+pub(crate) enum Probe<'a> {
+    /// Rows equal to any of the values.
+    Keys(Vec<&'a Value>),
+    /// Rows between two bounds.
+    Range(Bound<&'a Value>, Bound<&'a Value>),
+}
+
+/// Finds the column or expression a condition constrains and how, when
+/// the condition is an equality, IN-list, comparison or BETWEEN test of it
+/// against constants. NULL constants are left out of key lists since they
+/// never compare equal.
+pub(crate) fn probe(cond: &Condition) -> Option<(&Expr, Probe<'_>)> {
+    match cond {
+        Condition::Compare { left, op, right } => {
+            let (expr, op, value) = match (left, right) {
+                (Expr::Literal(_), Expr::Literal(_)) => return None,
+                (expr, Expr::Literal(v)) => (expr, op.clone(), v),
+                (Expr::Literal(v), expr) => (expr, flip(op)?, v),
+                _ => return None,
+            };
+            let probe = match op {
+                Operator::Eq => {
+                    Probe::Keys(vec![value].into_iter().filter(|v| !v.is_null()).collect())
+                }
+                Operator::Lt => Probe::Range(Bound::Unbounded, Bound::Excluded(value)),
+                Operator::Le => Probe::Range(Bound::Unbounded, Bound::Included(value)),
+                Operator::Gt => Probe::Range(Bound::Excluded(value), Bound::Unbounded),
+                Operator::Ge => Probe::Range(Bound::Included(value), Bound::Unbounded),
+                _ => return None,
+            };
+            Some((expr, probe))
+        }
+        Condition::Between {
+            expr,
+            low: Expr::Literal(low),
+            high: Expr::Literal(high),
+        } => Some((
+            expr,
+            Probe::Range(Bound::Included(low), Bound::Included(high)),
+        )),
+        Condition::InList { expr, values } => values
+            .iter()
+            .map(|v| match v {
+                Expr::Literal(v) => Some(v),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|vs| {
+                (
+                    expr,
+                    Probe::Keys(vs.into_iter().filter(|v| !v.is_null()).collect()),
+                )
+            }),
+        _ => None,
+    }
+}
+
+/// Whichever of two bounds on the same side of a range admits less: the
+/// greater value for a lower bound, the lesser for an upper one, excluding
+/// the value itself when either bound does.
+fn tighter<'a>(a: Bound<&'a Value>, b: Bound<&'a Value>, keep: Ordering) -> Bound<&'a Value> {
+    match (a, b) {
+        (Bound::Unbounded, bound) | (bound, Bound::Unbounded) => bound,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            match x.cmp(y) {
+                Ordering::Equal if matches!(a, Bound::Excluded(_)) => a,
+                Ordering::Equal => b,
+                order if order == keep => a,
+                _ => b,
+            }
+        }
+    }
+}
+
+/// The operator that gives the same result with its operands swapped.
+fn flip(op: &Operator) -> Option<Operator> {
+    Some(match op {
+        Operator::Eq => Operator::Eq,
+        Operator::Lt => Operator::Gt,
+        Operator::Le => Operator::Ge,
+        Operator::Gt => Operator::Lt,
+        Operator::Ge => Operator::Le,
+        _ => return None,
+    })
+}
+
+/// `expr` with its column references replaced by the names of the columns
+/// of `table` they resolve to in `rel`, and function names upper-cased, so
+/// that an expression compares equal however it was written. Fails for
+/// what an index cannot hold: aggregates, subqueries, and functions whose
+/// result changes from call to call.
+fn normalize(expr: &Expr, table: &Table, rel: &Relation) -> Result<Expr, EngineError> {
+    let boxed = |e: &Expr| normalize(e, table, rel).map(Box::new);
+    Ok(match expr {
+        Expr::Literal(_) => expr.clone(),
+        Expr::Column(name) => Expr::Column(table.columns[rel.resolve(name)?].name.clone()),
+        Expr::Binary { op, left, right } => Expr::Binary {
+            op: *op,
+            left: boxed(left)?,
+            right: boxed(right)?,
+        },
+        Expr::Aggregate { func, .. } => {
+            return Err(EngineError::InvalidQuery(format!(
+                "aggregate {} cannot be used in an index",
+                func
+            )))
+        }
+        Expr::Function { name, args } => {
+            if let Some(ScalarFunc::Now | ScalarFunc::Uuid | ScalarFunc::LastInsertId) =
+                ScalarFunc::builtin(name)
+            {
+                return Err(EngineError::InvalidQuery(format!(
+                    "function {} cannot be used in an index",
+                    name
+                )));
+            }
+            Expr::Function {
+                name: name.to_ascii_uppercase(),
+                args: args
+                    .iter()
+                    .map(|a| normalize(a, table, rel))
+                    .collect::<Result<_, _>>()?,
+            }
+        }
+        Expr::Cast { expr, target } => Expr::Cast {
+            expr: boxed(expr)?,
+            target: target.clone(),
+        },
+        Expr::Case {
+            branches,
+            otherwise,
+        } => Expr::Case {
+            branches: branches
+                .iter()
+                .map(|(c, e)| {
+                    Ok((
+                        normalize_condition(c, table, rel)?,
+                        normalize(e, table, rel)?,
+                    ))
+                })
+                .collect::<Result<_, EngineError>>()?,
+            otherwise: otherwise.as_deref().map(boxed).transpose()?,
+        },
+    })
+}
+
+fn normalize_condition(
+    cond: &Condition,
+    table: &Table,
+    rel: &Relation,
+) -> Result<Condition, EngineError> {
+    let expr = |e: &Expr| normalize(e, table, rel);
+    let boxed = |c: &Condition| normalize_condition(c, table, rel).map(Box::new);
+    Ok(match cond {
+        Condition::Compare { left, op, right } => Condition::Compare {
+            left: expr(left)?,
+            op: op.clone(),
+            right: expr(right)?,
+        },
+        Condition::Between { expr: e, low, high } => Condition::Between {
+            expr: expr(e)?,
+            low: expr(low)?,
+            high: expr(high)?,
+        },
+        Condition::InList { expr: e, values } => Condition::InList {
+            expr: expr(e)?,
+            values: values.iter().map(expr).collect::<Result<_, _>>()?,
+        },
+        Condition::InSubquery { .. } => {
+            return Err(EngineError::InvalidQuery(
+                "subqueries cannot be used in an index".to_string(),
+            ))
+        }
+        Condition::Any { expr: e, op, list } => Condition::Any {
+            expr: expr(e)?,
+            op: op.clone(),
+            list: expr(list)?,
+        },
+        Condition::IsNull(e) => Condition::IsNull(expr(e)?),
+        Condition::Not(c) => Condition::Not(boxed(c)?),
+        Condition::And(a, b) => Condition::And(boxed(a)?, boxed(b)?),
+        Condition::Or(a, b) => Condition::Or(boxed(a)?, boxed(b)?),
+    })
+}
+
+/// Estimated number of rows whose first `len` columns in `index` equal
+/// given constants, taking the columns to be independent.
+fn prefix_estimate(table: &Table, index: &Index, len: usize) -> Option<f64> {
+    let rows = table.row_count() as f64;
+    index.columns[..len]
+        .iter()
+        .try_fold(rows, |estimate, column| {
+            let matching = table.estimate_equal(column, 1)?;
+            Some(if rows > 0.0 {
+                estimate * matching / rows
+            } else {
+                0.0
+            })
+        })
+}
+
+/// Reads `column(n)` in CREATE INDEX as the first `n` characters of a text
+/// column, returning its position and `n`. Fails for a column that does
+/// not hold text or a length of zero.
+fn prefix_part(
+    table: &Table,
+    rel: &Relation,
+    expr: &Expr,
+) -> Result<Option<(usize, usize)>, EngineError> {
+    let Expr::Function { name, args } = expr else {
+        return Ok(None);
+    };
+    let (Ok(col_idx), [Expr::Literal(Value::Int(chars))]) = (rel.resolve(name), args.as_slice())
+    else {
+        return Ok(None);
+    };
+    let column = &table.columns[col_idx];
+    if !matches!(column.col_type, ValueType::Text | ValueType::Varchar(_)) {
+        return Err(EngineError::InvalidQuery(format!(
+            "column {} does not hold text, so cannot be indexed by prefix",
+            column.name
+        )));
+    }
+    match usize::try_from(*chars) {
+        Ok(chars) if chars > 0 => Ok(Some((col_idx, chars))),
+        _ => Err(EngineError::InvalidQuery(format!(
+            "prefix length of {} must be positive",
+            column.name
+        ))),
+    }
+}
+
+/// The table's ready indexes that `hint` allows, in name order, so that
+/// the choice between equally good ones does not change from run to run.
+fn sorted_indexes<'a>(
+    table: &'a Table,
+    hint: Option<&IndexHint>,
+) -> impl Iterator<Item = (&'a str, &'a Index)> {
+    let mut indexes = table
+        .indices
+        .iter()
+        .filter(|(name, index)| index.is_ready() && hint.is_none_or(|h| h.allows(name)))
+        .map(|(name, index)| (name.as_str(), index))
+        .collect::<Vec<_>>();
+    indexes.sort_by_key(|(name, _)| *name);
+    indexes.into_iter()
+}
+
+/// The trigram index over the column `cond` matches against a LIKE
+/// pattern, and the pattern's trigrams; `None` without such an index, or
+/// for a pattern without three plain characters in a row.
+fn trigram_plan<'a>(
+    table: &'a Table,
+    rel: &Relation,
+    cond: &Condition,
+    hint: Option<&IndexHint>,
+) -> Option<(&'a str, &'a Index, Vec<Value>)> {
+    let Condition::Compare {
+        left: Expr::Column(column),
+        op: Operator::Like,
+        right: Expr::Literal(Value::Text(pattern)),
+    } = cond
+    else {
+        return None;
+    };
+    let col_idx = rel.resolve(column).ok()?;
+    let trigrams = pattern_trigrams(pattern);
+    if trigrams.is_empty() {
+        return None;
+    }
+    let (name, index) = sorted_indexes(table, hint).find(|(_, i)| {
+        i.kind() == IndexKind::Trigram
+            && matches!(i.parts.as_slice(), [KeyPart::Column(pos)] if *pos == col_idx)
+    })?;
+    Some((name, index, trigrams))
+}
+
+/// Fails if `hint` names an index that `table`, called `name` in the
+/// query, does not have.
+pub(crate) fn check_hint(
+    table: &Table,
+    name: &str,
+    hint: Option<&IndexHint>,
+) -> Result<(), EngineError> {
+    match hint
+        .into_iter()
+        .flat_map(IndexHint::indexes)
+        .find(|index| !table.indices.contains_key(*index))
+    {
+        Some(index) => Err(EngineError::InvalidQuery(format!(
+            "index {} does not exist on {}",
+            index, name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The conditions that must all hold for `cond` to hold.
+pub(crate) fn conjuncts(cond: &Condition) -> Vec<&Condition> {
+    match cond {
+        Condition::And(left, right) => {
+            let mut all = conjuncts(left);
+            all.extend(conjuncts(right));
+            all
+        }
+        cond => vec![cond],
+    }
+}
+
+impl Engine {
+    /// Runs CREATE INDEX, failing if the table already has an index of the
+    /// name. Indexed expressions are evaluated for every row written, and a
+    /// write fails if one of them does.
+    pub(crate) fn create_index(&mut self, q: &CreateIndexQuery) -> Result<(), EngineError> {
+        let table = self
+            .tables
+            .get(&q.table)
+            .ok_or_else(|| EngineError::TableNotFound(q.table.clone()))?;
+        let name = q.name.clone().unwrap_or_else(|| {
+            let base = q.table.rsplit('.').next().unwrap_or(&q.table);
+            let parts = q
+                .columns
+                .iter()
+                .map(|expr| match expr {
+                    Expr::Column(c) => c.clone(),
+                    Expr::Function { name, .. } => name.to_ascii_lowercase(),
+                    _ => "expr".to_string(),
+                })
+                .collect::<Vec<_>>();
+            format!("{}_{}_idx", base, parts.join("_"))
+        });
+        if table.indices.contains_key(&name) {
+            return Err(EngineError::InvalidQuery(format!(
+                "index {} already exists on {}",
+                name, q.table
+            )));
+        }
+        let rel = Relation::from_table(&q.table, table);
+        let scope = Scope::new();
+        let binder = Binder::new(self, &scope, &rel);
+        let mut columns = Vec::new();
+        let mut parts = Vec::new();
+        for expr in &q.columns {
+            if let Some((col_idx, chars)) = prefix_part(table, &rel, expr)? {
+                columns.push(format!("{}({})", table.columns[col_idx].name, chars));
+                parts.push(KeyPart::Prefix(col_idx, chars));
+                continue;
+            }
+            match normalize(expr, table, &rel)? {
+                Expr::Column(column) => {
+                    let col_idx = rel.resolve(&column)?;
+                    match table.overflow {
+                        // Keys over long texts kept out of line keep only
+                        // their start; see `Table::set_overflow`.
+                        Some(chars)
+                            if chars > 0
+                                && table.out_of_line(col_idx)
+                                && q.columns.len() == 1
+                                && !q.unique
+                                && q.kind != IndexKind::Trigram =>
+                        {
+                            columns.push(format!("{}({})", column, chars));
+                            parts.push(KeyPart::Prefix(col_idx, chars));
+                        }
+                        _ => {
+                            parts.push(KeyPart::Column(col_idx));
+                            columns.push(column);
+                        }
+                    }
+                }
+                expr => {
+                    let bound = binder.expr(&expr)?;
+                    columns.push(expr.to_string());
+                    parts.push(KeyPart::Expr(expr, Box::new(bound)));
+                }
+            }
+        }
+        let index = Index::new(columns, parts, q.kind, q.unique);
+        match self.tables.get_mut(&q.table) {
+            Some(table) => table.put_index(&name, index),
+            None => Err(EngineError::TableNotFound(q.table.clone())),
+        }
+    }
+
+    /// Rebuilds the indexes of every table from its rows, binding indexed
+    /// expressions again, for tables read back without their index entries
+    /// or with indexes over expressions. Serializing the entries makes for
+    /// a larger file but leaves only expression indexes to rebuild; see
+    /// [`Table::drop_index_entries`]. Fails if an expression no longer
+    /// binds or a unique index finds a duplicate.
+    pub fn rebuild_indexes(&mut self) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            let mut names = engine.tables.keys().cloned().collect::<Vec<_>>();
+            names.sort();
+            names
+                .iter()
+                .try_for_each(|name| engine.rebuild_table_indexes(name, None))
+        })
+    }
+
+    /// Runs REINDEX on every index, on those of the table `name`, or on
+    /// the indexes called `name` in whichever tables have one.
+    pub(crate) fn reindex(&mut self, name: Option<&str>) -> Result<(), EngineError> {
+        let Some(name) = name else {
+            return self.rebuild_indexes();
+        };
+        if self.tables.contains_key(name) {
+            return self.rebuild_table_indexes(name, None);
+        }
+        let mut tables = self
+            .tables
+            .iter()
+            .filter(|(_, table)| table.indices.contains_key(name))
+            .map(|(table, _)| table.clone())
+            .collect::<Vec<_>>();
+        if tables.is_empty() {
+            return Err(EngineError::InvalidQuery(format!(
+                "no table or index named {}",
+                name
+            )));
+        }
+        tables.sort();
+        tables
+            .iter()
+            .try_for_each(|table| self.rebuild_table_indexes(table, Some(name)))
+    }
+
+    /// Binds the indexed expressions of `table` again and rebuilds its
+    /// indexes, or only the one called `only`.
+    pub(crate) fn rebuild_table_indexes(
+        &mut self,
+        name: &str,
+        only: Option<&str>,
+    ) -> Result<(), EngineError> {
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let rel = Relation::from_table(name, table);
+        let scope = Scope::new();
+        let binder = Binder::new(self, &scope, &rel);
+        let mut bound = Vec::new();
+        for (index_name, index) in &table.indices {
+            if only.is_some_and(|only| only != index_name) {
+                continue;
+            }
+            let mut parts = Vec::with_capacity(index.parts.len());
+            for part in &index.parts {
+                parts.push(match part {
+                    KeyPart::Unbound(sql) => {
+                        let expr = match parse_expr(sql) {
+                            Ok(("", expr)) => normalize(&expr, table, &rel)?,
+                            _ => {
+                                return Err(EngineError::InvalidQuery(format!(
+                                    "cannot read indexed expression {} of {}",
+                                    sql, index_name
+                                )))
+                            }
+                        };
+                        let expr_bound = binder.expr(&expr)?;
+                        KeyPart::Expr(expr, Box::new(expr_bound))
+                    }
+                    part => part.clone(),
+                });
+            }
+            bound.push((index_name.clone(), parts));
+        }
+        bound.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let Some(table) = self.tables.get_mut(name) else {
+            return Ok(());
+        };
+        for (index_name, parts) in bound {
+            if let Some(index) = table.indices.get_mut(&index_name) {
+                index.parts = parts;
+            }
+            table.rebuild_index(&index_name)?;
+        }
+        Ok(())
+    }
+
+    /// Size and use of every index on `table`, in name order, to tell
+    /// which are worth keeping.
+    pub fn index_stats(&self, table: &str) -> Result<Vec<IndexStats>, EngineError> {
+        let scope = Scope::new();
+        let mut stats = self
+            .get_table(&scope, table)?
+            .indices
+            .iter()
+            .map(|(name, index)| index.stats(name))
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(stats)
+    }
+
+    /// Index keys are stored values, so a constant is converted to the
+    /// column's type first. `None` when it does not convert to a key equal
+    /// to itself, in which case the index cannot be trusted to find it.
+    fn index_key(&self, table: &Table, col_idx: usize, value: &Value) -> Option<Value> {
+        table
+            .accept(col_idx, value.clone())
+            .ok()
+            .filter(|k| Self::compare(k, &Operator::Eq, value) == Some(true))
+            // Under `bool_ints` a boolean also matches 1 or 0, which a
+            // lookup would miss.
+            .filter(|k| !(self.bool_ints && matches!(k, Value::Bool(_))))
+    }
+
+    /// The rows of `table` that may match `cond`, when the planner finds
+    /// an index worth reading them from; `None` when every row is to be
+    /// tested instead.
+    ///
+    /// Each index that can answer part of the condition is a candidate: an
+    /// index over the column or expression of a comparison, or the
+    /// multi-column index whose leading columns the most equalities fix to
+    /// a constant, or a trigram index over a column matched against a LIKE
+    /// pattern. Comparisons that bound the same column from either side,
+    /// as in `a > 1 AND a < 5`, are read as a single range. Once the table
+    /// has been analyzed, candidates are tried from the fewest rows
+    /// estimated to match, and one is only used if reading those rows costs
+    /// less than a scan. Candidates without an estimate come last and are
+    /// always used. When the condition has several parts, the rows found
+    /// are tested against the whole of it. Only indexes that `hint` allows
+    /// are candidates. Without one, a partitioned table reads only the
+    /// partitions that can hold matching rows.
+    pub(crate) fn index_lookup(
+        &self,
+        table: &Table,
+        rel: &Relation,
+        cond: &Condition,
+        hint: Option<&IndexHint>,
+    ) -> Option<IndexScan> {
+        let parts = conjuncts(cond);
+        if let Some(column) = parts
+            .iter()
+            .find_map(|part| self.bloom_miss(table, rel, part))
+        {
+            return Some(IndexScan {
+                path: AccessPath::BloomFilter(column.to_string()),
+                rows: Vec::new(),
+                exact: true,
+            });
+        }
+        let single = parts.len() == 1;
+        let mut probes = Vec::new();
+        let mut ranges: Vec<(usize, &Expr, Bound<&Value>, Bound<&Value>)> = Vec::new();
+        for (expr, probe) in parts.iter().filter_map(|part| probe(part)) {
+            let column = match expr {
+                Expr::Column(c) => rel.resolve(c).ok(),
+                _ => None,
+            };
+            match (column, probe) {
+                (Some(col_idx), Probe::Range(low, high)) => {
+                    match ranges.iter_mut().find(|(c, ..)| *c == col_idx) {
+                        Some((_, _, l, h)) => {
+                            *l = tighter(*l, low, Ordering::Greater);
+                            *h = tighter(*h, high, Ordering::Less);
+                        }
+                        None => ranges.push((col_idx, expr, low, high)),
+                    }
+                }
+                (_, probe) => probes.push((expr, probe)),
+            }
+        }
+        probes.extend(
+            ranges
+                .into_iter()
+                .map(|(_, expr, low, high)| (expr, Probe::Range(low, high))),
+        );
+        let mut candidates = probes
+            .into_iter()
+            .map(|(expr, probe)| {
+                (
+                    self.estimate(table, rel, expr, &probe),
+                    Candidate::Probe(expr, probe),
+                )
+            })
+            .collect::<Vec<_>>();
+        if let Some((name, index, prefix)) = self.prefix_plan(table, rel, &parts, hint) {
+            let estimate = prefix_estimate(table, index, prefix.len());
+            // With nothing known, the multi-column index is tried first
+            // for a condition of several parts.
+            let at = if single { candidates.len() } else { 0 };
+            candidates.insert(at, (estimate, Candidate::Prefix(name, index, prefix)));
+        }
+        for part in &parts {
+            if let Some((name, index, trigrams)) = trigram_plan(table, rel, part, hint) {
+                // No row holds more of a pattern's trigrams than the rarest.
+                let estimate = trigrams
+                    .iter()
+                    .map(|t| index.get(t).len() as f64)
+                    .reduce(f64::min);
+                candidates.push((estimate, Candidate::Trigram(name, index, trigrams)));
+            }
+        }
+        candidates.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) => a.total_cmp(b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        let scan_cost = table.row_count() as f64;
+        candidates
+            .into_iter()
+            .filter(|(estimate, _)| estimate.is_none_or(|rows| rows * INDEX_ROW_COST < scan_cost))
+            .find_map(|(_, candidate)| match candidate {
+                Candidate::Probe(expr, probe) => {
+                    let found = self.probe_lookup(table, rel, expr, probe, hint)?;
+                    Some(IndexScan {
+                        exact: found.exact && single,
+                        ..found
+                    })
+                }
+                Candidate::Prefix(name, index, prefix) => {
+                    let full = prefix.len() == index.columns.len();
+                    let mut rows = if full {
+                        index.get(&Value::List(prefix)).to_vec()
+                    } else {
+                        index.prefix(&prefix)?
+                    };
+                    rows.sort_unstable();
+                    let name = name.to_string();
+                    Some(IndexScan {
+                        path: if full {
+                            AccessPath::IndexLookup(name)
+                        } else {
+                            AccessPath::IndexRange(name)
+                        },
+                        rows,
+                        exact: false,
+                    })
+                }
+                Candidate::Trigram(name, index, trigrams) => Some(IndexScan {
+                    path: AccessPath::IndexLookup(name.to_string()),
+                    rows: index.trigram_rows(&trigrams),
+                    exact: false,
+                }),
+            })
+            .or_else(|| partition_scan(table, rel, &parts))
+    }
+
+    /// The column whose bloom filter shows that no row matches `cond`, an
+    /// equality or IN-list test of the column against constants.
+    fn bloom_miss<'a>(
+        &self,
+        table: &'a Table,
+        rel: &Relation,
+        cond: &Condition,
+    ) -> Option<&'a str> {
+        let (Expr::Column(column), Probe::Keys(values)) = probe(cond)? else {
+            return None;
+        };
+        let col_idx = rel.resolve(column).ok()?;
+        let (name, filter) = table
+            .bloom_filters
+            .get_key_value(&table.columns[col_idx].name)?;
+        let keys = values
+            .into_iter()
+            .map(|v| self.index_key(table, col_idx, v))
+            .collect::<Option<Vec<_>>>()?;
+        (!keys.iter().any(|k| filter.may_contain(k))).then_some(name.as_str())
+    }
+
+    /// Estimated number of rows matching a probe of a column, or `None`
+    /// without statistics on it.
+    fn estimate(&self, table: &Table, rel: &Relation, expr: &Expr, probe: &Probe) -> Option<f64> {
+        let Expr::Column(column) = expr else {
+            return None;
+        };
+        let name = &table.columns[rel.resolve(column).ok()?].name;
+        match probe {
+            Probe::Keys(values) => table.estimate_equal(name, values.len()),
+            Probe::Range(low, high) => table.estimate_range(name, *low, *high),
+        }
+    }
+
+    /// How the planner reads `table` for a query whose WHERE condition is
+    /// `cond`: by testing every row, or through which index.
+    pub fn access_path(
+        &self,
+        table: &str,
+        cond: Option<&Condition>,
+    ) -> Result<AccessPath, EngineError> {
+        let scope = Scope::new();
+        let found = self.get_table(&scope, table)?;
+        let rel = Relation::from_table(table, found);
+        Ok(cond
+            .and_then(|cond| self.index_lookup(found, &rel, cond, None))
+            .map_or(AccessPath::Scan, |found| found.path))
+    }
+
+    /// Looks up a probe in an index over exactly its column or expression.
+    fn probe_lookup(
+        &self,
+        table: &Table,
+        rel: &Relation,
+        expr: &Expr,
+        probe: Probe,
+        hint: Option<&IndexHint>,
+    ) -> Option<IndexScan> {
+        let Expr::Column(column) = expr else {
+            return self.expression_lookup(table, rel, expr, probe, hint);
+        };
+        let col_idx = rel.resolve(column).ok()?;
+        let name = &table.columns[col_idx].name;
+        let Some((index_name, index)) = sorted_indexes(table, hint).find(|(_, i)| {
+            i.is_plain() && i.columns.len() == 1 && &i.columns[0] == name && i.answers(&probe)
+        }) else {
+            return self.prefix_lookup(table, col_idx, probe, hint);
+        };
+        let index_name = index_name.to_string();
+        let found = match probe {
+            Probe::Keys(values) => {
+                let keys = values
+                    .into_iter()
+                    .map(|v| self.index_key(table, col_idx, v))
+                    .collect::<Option<Vec<_>>>()?;
+                IndexScan {
+                    path: AccessPath::IndexLookup(index_name),
+                    rows: keys.iter().flat_map(|k| index.get(k)).copied().collect(),
+                    exact: true,
+                }
+            }
+            // Range ends that do not convert exactly would move the bounds,
+            // so those also fall back to a scan. The rows found are tested
+            // again, since values of other types stored under flexible
+            // typing can sort inside the range without comparing as in it.
+            Probe::Range(low, high) => {
+                let key = |bound: Bound<&Value>| match bound {
+                    Bound::Included(v) => self.index_key(table, col_idx, v).map(Bound::Included),
+                    Bound::Excluded(v) => self.index_key(table, col_idx, v).map(Bound::Excluded),
+                    Bound::Unbounded => Some(Bound::Unbounded),
+                };
+                let (low, high) = (key(low)?, key(high)?);
+                IndexScan {
+                    path: AccessPath::IndexRange(index_name),
+                    rows: index.range(low.as_ref(), high.as_ref())?,
+                    exact: false,
+                }
+            }
+        };
+        Some(found.in_table_order())
+    }
+
+    /// Looks up a probe of a text column in an index over its first
+    /// characters. Constants are cut to the same length, and a range takes
+    /// in both of its ends, since a value outside it can share the prefix
+    /// of one. The rows found are tested again.
+    fn prefix_lookup(
+        &self,
+        table: &Table,
+        col_idx: usize,
+        probe: Probe,
+        hint: Option<&IndexHint>,
+    ) -> Option<IndexScan> {
+        let (name, index, chars) =
+            sorted_indexes(table, hint).find_map(|(name, i)| match i.parts.as_slice() {
+                [KeyPart::Prefix(pos, chars)] if *pos == col_idx && i.answers(&probe) => {
+                    Some((name, i, *chars))
+                }
+                _ => None,
+            })?;
+        let key = |v: &Value| Some(truncate(&self.index_key(table, col_idx, v)?, chars));
+        let found = match probe {
+            Probe::Keys(values) => {
+                let keys = values.into_iter().map(key).collect::<Option<Vec<_>>>()?;
+                IndexScan {
+                    path: AccessPath::IndexLookup(name.to_string()),
+                    rows: keys.iter().flat_map(|k| index.get(k)).copied().collect(),
+                    exact: false,
+                }
+            }
+            Probe::Range(low, high) => {
+                let bound = |bound: Bound<&Value>| match bound {
+                    Bound::Included(v) | Bound::Excluded(v) => key(v).map(Bound::Included),
+                    Bound::Unbounded => Some(Bound::Unbounded),
+                };
+                let (low, high) = (bound(low)?, bound(high)?);
+                IndexScan {
+                    path: AccessPath::IndexRange(name.to_string()),
+                    rows: index.range(low.as_ref(), high.as_ref())?,
+                    exact: false,
+                }
+            }
+        };
+        Some(found.in_table_order())
+    }
+
+    /// Looks up one comparison of an expression in an index over exactly
+    /// that expression. Its keys are computed, not stored in a column of
+    /// known type, so constants are looked up as they are, and only if
+    /// every key has the same type as them: a key of another type could
+    /// compare equal to a constant without being stored under it. The rows
+    /// found are tested again.
+    fn expression_lookup(
+        &self,
+        table: &Table,
+        rel: &Relation,
+        expr: &Expr,
+        probe: Probe,
+        hint: Option<&IndexHint>,
+    ) -> Option<IndexScan> {
+        let expr = normalize(expr, table, rel).ok()?;
+        let (name, index) = sorted_indexes(table, hint).find(|(_, i)| {
+            matches!(i.parts.as_slice(), [KeyPart::Expr(indexed, _)] if *indexed == expr)
+                && i.answers(&probe)
+        })?;
+        let usable = |bound: &Bound<&Value>| match bound {
+            Bound::Included(v) | Bound::Excluded(v) => {
+                index.key_types.iter().all(|t| *t == v.type_rank())
+            }
+            Bound::Unbounded => true,
+        };
+        let found = match probe {
+            Probe::Keys(values) => {
+                if !values.iter().all(|v| usable(&Bound::Included(v))) {
+                    return None;
+                }
+                IndexScan {
+                    path: AccessPath::IndexLookup(name.to_string()),
+                    rows: values.iter().flat_map(|v| index.get(v)).copied().collect(),
+                    exact: false,
+                }
+            }
+            Probe::Range(low, high) => {
+                if !usable(&low) || !usable(&high) {
+                    return None;
+                }
+                IndexScan {
+                    path: AccessPath::IndexRange(name.to_string()),
+                    rows: index.range(low, high)?,
+                    exact: false,
+                }
+            }
+        };
+        Some(found.in_table_order())
+    }
+
+    /// Finds the constants that `parts` compare columns equal to, and the
+    /// multi-column index whose leading columns they cover the most of:
+    /// all of its columns, or for an ordered index any number of them.
+    fn prefix_plan<'a>(
+        &self,
+        table: &'a Table,
+        rel: &Relation,
+        parts: &[&Condition],
+        hint: Option<&IndexHint>,
+    ) -> Option<(&'a str, &'a Index, Vec<Value>)> {
+        let mut fixed: HashMap<&str, Value> = HashMap::new();
+        for part in parts {
+            let Some((Expr::Column(column), Probe::Keys(values))) = probe(part) else {
+                continue;
+            };
+            let Ok(col_idx) = rel.resolve(column) else {
+                continue;
+            };
+            if let [value] = values.as_slice() {
+                if let Some(key) = self.index_key(table, col_idx, value) {
+                    fixed.insert(&table.columns[col_idx].name, key);
+                }
+            }
+        }
+        sorted_indexes(table, hint)
+            .filter(|(_, index)| index.columns.len() > 1 && index.is_plain())
+            .filter_map(|(name, index)| {
+                let prefix = index
+                    .columns
+                    .iter()
+                    .map_while(|c| fixed.get(c.as_str()).cloned())
+                    .collect::<Vec<_>>();
+                let usable = prefix.len() == index.columns.len()
+                    || (!prefix.is_empty() && index.kind() == IndexKind::Ordered);
+                usable.then_some((name, index, prefix))
+            })
+            .max_by_key(|(_, _, prefix)| prefix.len())
+    }
+}
@@ -0,0 +1,118 @@
+//! Joining the rows of one more table onto the rows read so far, by a
+//! nested loop or, for equality conditions between the two, a hash join.
+
+use std::collections::HashMap;
+
+use crate::engine::{row_key, Row};
+use crate::expr::Relation;
+use crate::index::conjuncts;
+use crate::parser::{Condition, Expr, Operator};
+
+/// Cost of adding a row to or looking a row up in a hash join's table,
+/// relative to pairing two rows in a nested loop.
+const HASH_ROW_COST: f64 = 2.0;
+
+/// Pairs every row of `left` with every row of `right` that `cond` may
+/// accept, with the columns of `left` first. Rows come out in the order a
+/// nested loop over `left`, then `right`, would give; the caller still
+/// tests them against `cond`.
+///
+/// When `cond` compares columns of the two sides for equality, a hash join
+/// pairs only rows whose columns are equal, if the inputs are large enough
+/// for that to cost less than pairing them all.
+pub(crate) fn join(
+    left: &[Row],
+    left_rel: &Relation,
+    right: &[Row],
+    right_rel: &Relation,
+    cond: Option<&Condition>,
+) -> Vec<Row> {
+    let keys = cond.map_or_else(Vec::new, |cond| equi_keys(left_rel, right_rel, cond));
+    let (l, r) = (left.len() as f64, right.len() as f64);
+    if !keys.is_empty() && (l + r) * HASH_ROW_COST < l * r {
+        if let Some(rows) = hash_join(left, right, &keys) {
+            return rows;
+        }
+    }
+    let mut product = Vec::with_capacity(left.len() * right.len());
+    for l in left {
+        for r in right {
+            product.push(concat(l, r));
+        }
+    }
+    product
+}
+
+fn concat(left: &Row, right: &Row) -> Row {
+    let mut row = Vec::with_capacity(left.len() + right.len());
+    row.extend(left.iter().cloned());
+    row.extend(right.iter().cloned());
+    row
+}
+
+/// Positions of the columns, one from each side, that a part of `cond`
+/// requires to be equal.
+pub(crate) fn equi_keys(
+    left: &Relation,
+    right: &Relation,
+    cond: &Condition,
+) -> Vec<(usize, usize)> {
+    // A name found on both sides is ambiguous, which binding the whole
+    // condition reports.
+    let side = |name: &str| match (left.resolve(name), right.resolve(name)) {
+        (Ok(l), Err(_)) => Some((Some(l), None)),
+        (Err(_), Ok(r)) => Some((None, Some(r))),
+        _ => None,
+    };
+    conjuncts(cond)
+        .into_iter()
+        .filter_map(|part| match part {
+            Condition::Compare {
+                left: Expr::Column(a),
+                op: Operator::Eq,
+                right: Expr::Column(b),
+            } => match (side(a)?, side(b)?) {
+                ((Some(l), None), (None, Some(r))) | ((None, Some(r)), (Some(l), None)) => {
+                    Some((l, r))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Joins on equal keys, or `None` if the keys of a column are not all of
+/// one type: values of different types can compare equal without being
+/// equal as hash keys, so only a nested loop finds every match.
+fn hash_join(left: &[Row], right: &[Row], keys: &[(usize, usize)]) -> Option<Vec<Row>> {
+    let (left_keys, right_keys): (Vec<usize>, Vec<usize>) = keys.iter().copied().unzip();
+    let mut types = vec![None; keys.len()];
+    let mut same_types = |row: &Row, positions: &[usize]| {
+        positions.iter().zip(types.iter_mut()).all(|(&pos, seen)| {
+            let value = &row[pos];
+            value.is_null() || *seen.get_or_insert(value.type_rank()) == value.type_rank()
+        })
+    };
+    let mut table: HashMap<_, Vec<&Row>> = HashMap::new();
+    for row in right {
+        if !same_types(row, &right_keys) {
+            return None;
+        }
+        // NULL keys never compare equal, so such rows join with nothing.
+        if let Some(key) = row_key(&right_keys, row) {
+            table.entry(key).or_default().push(row);
+        }
+    }
+    let mut product = Vec::new();
+    for l in left {
+        if !same_types(l, &left_keys) {
+            return None;
+        }
+        let matches = row_key(&left_keys, l).and_then(|key| table.get(&key));
+        for r in matches.into_iter().flatten() {
+            product.push(concat(l, r));
+        }
+    }
+    Some(product)
+}
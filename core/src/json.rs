@@ -0,0 +1,116 @@
+//! Path lookups into JSON documents for `JSON_EXTRACT` and the `->` /
+//! `->>` operators.
+
+use serde_json::Value as Json;
+
+use crate::engine::{EngineError, Value};
+
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Parses a path such as `$.items[0]."first name"`: `$` is the whole
+/// document, `.key` or `."key"` selects an object member and `[n]` an
+/// array element.
+fn parse_path(path: &str) -> Option<Vec<Step>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let (key, tail) = match after.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"')?;
+                    (&quoted[..end], &quoted[end + 1..])
+                }
+                None => {
+                    let end = after.find(['.', '[']).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            if key.is_empty() {
+                return None;
+            }
+            steps.push(Step::Key(key.to_string()));
+            rest = tail;
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            steps.push(Step::Index(after[..end].trim().parse().ok()?));
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(steps)
+}
+
+/// Looks up `selector` in `doc`. The selector is a `$` path, a plain
+/// object key, or an integer array index; `None` when nothing matches.
+pub fn extract<'a>(doc: &'a Json, selector: &Value) -> Result<Option<&'a Json>, EngineError> {
+    let steps = match selector {
+        Value::Text(path) if path.starts_with('$') => parse_path(path)
+            .ok_or_else(|| EngineError::InvalidPattern(format!("invalid JSON path: {}", path)))?,
+        Value::Text(key) => vec![Step::Key(key.clone())],
+        Value::Int(idx) => match usize::try_from(*idx) {
+            Ok(idx) => vec![Step::Index(idx)],
+            Err(_) => return Ok(None),
+        },
+        other => {
+            return Err(EngineError::InvalidOperation(format!(
+                "cannot use {:?} as a JSON path",
+                other.value_type()
+            )))
+        }
+    };
+    let mut current = doc;
+    for step in &steps {
+        let next = match step {
+            Step::Key(key) => current.get(key.as_str()),
+            Step::Index(idx) => current.get(*idx),
+        };
+        match next {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Converts a JSON scalar to the matching SQL value; objects and arrays
+/// become their JSON text.
+pub fn unquote(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Bool(*b),
+        Json::Number(n) => match n.as_i64() {
+            Some(n) => Value::Int(n),
+            None => n.as_f64().map_or(Value::Null, Value::Float),
+        },
+        Json::String(s) => Value::Text(s.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+/// Converts a SQL value to plain JSON: numbers, text, booleans, lists and
+/// JSON values as themselves, NULL as `null`, and anything else, such as
+/// dates and decimals, as its text. A `BIGINT` beyond the range of JSON
+/// integers becomes text too.
+pub(crate) fn to_json(value: &Value) -> Json {
+    match value {
+        v if v.is_null() => Json::Null,
+        Value::Int(n) => Json::from(*n),
+        Value::BigInt(n) => i64::try_from(*n)
+            .map(Json::from)
+            .or_else(|_| u64::try_from(*n).map(Json::from))
+            .unwrap_or_else(|_| Json::String(n.to_string())),
+        Value::Float(f) => Json::from(*f),
+        Value::Bool(b) => Json::Bool(*b),
+        Value::Text(s) => Json::String(s.clone()),
+        Value::Json(json) => json.clone(),
+        Value::List(items) => Json::Array(items.iter().map(to_json).collect()),
+        other => match other.cast(&crate::engine::ValueType::Text) {
+            Ok(Value::Text(text)) => Json::String(text),
+            _ => Json::String(other.to_string()),
+        },
+    }
+}
@@ -1,7 +1,80 @@
+mod bloom;
+pub mod codec;
+mod columnar;
+mod compress;
+mod copy_in;
+mod csv;
+mod custom;
+mod decimal;
+mod dml;
+mod dump;
 pub mod engine;
+mod expr;
+mod index;
+mod join;
+mod json;
+mod lsm;
+mod migrate;
+mod ndjson;
+mod paged;
+#[cfg(feature = "parquet")]
+mod parquet_file;
 pub mod parser;
+mod partition;
+mod plan;
+mod plan_cache;
+mod schema;
+mod shared;
+mod snapshot;
+mod sort;
+mod spill;
+#[cfg(feature = "sqlite")]
+mod sqlite_file;
+mod stats;
+mod storage;
+mod table_log;
+mod temporal;
+mod transaction;
+mod ttl;
+mod vacuum;
+mod verify;
+mod view;
 
-pub use engine::{Engine, EngineError, Row, Table, Value, ValueType};
+pub use bloom::BloomFilter;
+pub use columnar::Layout;
+pub use copy_in::{CopyIn, COPY_BATCH_ROWS};
+pub use csv::CsvOptions;
+pub use custom::{register_custom_type, CustomType, CustomValue};
+pub use decimal::{Decimal, ParseDecimalError};
+pub use engine::{
+    AutoIncrement, Column, Engine, EngineError, EnumValue, ForeignKey, ParseValueError,
+    QueryResult, ReferentialAction, ResultColumn, Row, ScalarFn, Table, Typing, UniqueConstraint,
+    Value, ValueType,
+};
+pub use index::{AccessPath, Index, IndexKind, IndexStats};
+pub use lsm::{LsmStorage, DEFAULT_MEMTABLE_ROWS};
+pub use migrate::{AppliedMigration, Migration};
+pub use ndjson::JsonFormat;
+pub use paged::{BufferPoolStats, PagedTable, RowId, DEFAULT_POOL_PAGES, MAX_ROW_LEN, PAGE_SIZE};
 pub use parser::{
-    parse_insert, parse_query, parse_select, Condition, InsertQuery, Operator, Query, SelectQuery,
+    parse_alter_table, parse_comment, parse_create_index, parse_create_table, parse_create_view,
+    parse_delete, parse_expr, parse_insert, parse_pragma, parse_query, parse_script, parse_select,
+    parse_statement, parse_type, parse_update, AggregateFunc, AlterTableAction, AlterTableQuery,
+    BinaryOp, CommentQuery, Condition, CopyQuery, CreateIndexQuery, CreateTableQuery,
+    CreateViewQuery, Cte, DeleteQuery, Expr, IndexHint, InsertQuery, Operator, PragmaQuery, Query,
+    SelectItem, SelectQuery, TableRef, UpdateQuery,
 };
+pub use partition::{PartitionScheme, Partitioning};
+pub use plan::Plan;
+pub use plan_cache::{PlanCacheStats, DEFAULT_PLAN_CACHE_SIZE};
+pub use schema::{Constraint, EngineStats, TableSchema, TableStats, TableUsage, COMMENT_KEY};
+pub use shared::SharedEngine;
+pub use snapshot::{BackupJob, SaveOptions, SNAPSHOT_VERSION};
+pub use sort::{SortStats, DEFAULT_SORT_MEMORY};
+pub use stats::{Analysis, ColumnStats, HISTOGRAM_BUCKETS};
+pub use storage::{MemoryStorage, StorageEngine};
+pub use table_log::{PointInTime, DEFAULT_CHECKPOINT_SIZE};
+pub use temporal::{Interval, ParseIntervalError};
+pub use ttl::Ttl;
+pub use uuid::Uuid;
+pub use vacuum::VacuumStats;
@@ -0,0 +1,540 @@
+//! A log-structured merge tree: storage for attached tables that take far
+//! more writes than reads. Writes never touch rows already on disk, so
+//! inserting and deleting cost the same however large the table grows.
+//!
+//! New rows, and tombstones for deleted ones, go to a memtable in memory,
+//! ordered by row id. Once it holds its limit of entries, or on a flush,
+//! the memtable is written out as a sorted run in level 0. Four runs in
+//! level 0 are merged with the run of level 1; each later level holds a
+//! single run of up to ten times the entries of the level above, and is
+//! merged into the next level once it outgrows that. A row is read from
+//! the memtable or the newest run holding its id, and tombstones are
+//! dropped once merged into the last level.
+//!
+//! The tree is a directory. A run file `<n>.run` holds the bytes
+//! `MSQLLSM2`, then each entry in id order: the id as a little-endian
+//! `u64`, the length of the row's [`crate::codec`] encoding as a
+//! little-endian `u32`, or `u32::MAX` for a tombstone, and the encoding
+//! followed by its FNV-1a checksum as a `u64`. Then comes the index: the
+//! number of entries as a `u64`, and the id, offset and length of each as
+//! a `u64`, `u64` and `u32`; and last the checksum of the index and its
+//! offset as `u64`s. An entry or index failing its checksum fails with
+//! [`EngineError::Corruption`]. The `MANIFEST` file names the runs of
+//! each level in JSON, along with the columns and the next row id. It is
+//! replaced by a rename after runs are written, and only then are merged
+//! runs deleted, so a crash leaves the tree as it was before or after.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::{decode_row, encode_row, fnv1a};
+use crate::engine::{Column, EngineError, Row};
+use crate::storage::accepted;
+
+const MAGIC: &[u8; 8] = b"MSQLLSM2";
+const TOMBSTONE: u32 = u32::MAX;
+const MANIFEST: &str = "MANIFEST";
+/// Runs level 0 collects before they are merged into level 1.
+const LEVEL0_RUNS: usize = 4;
+/// How many times the entries of the level above a level may hold.
+const LEVEL_RATIO: usize = 10;
+
+/// Entries the memtable holds before it is written out, unless changed
+/// with [`LsmStorage::set_memtable_rows`].
+pub const DEFAULT_MEMTABLE_ROWS: usize = 4096;
+
+fn io(e: std::io::Error) -> EngineError {
+    EngineError::Io(e.to_string())
+}
+
+/// The error for the run file at `path` damaged at `place`.
+fn damaged(path: &Path, place: &str) -> EngineError {
+    EngineError::Corruption {
+        location: format!("{}: {}", path.display(), place),
+    }
+}
+
+fn run_path(dir: &Path, number: u64) -> PathBuf {
+    dir.join(format!("{}.run", number))
+}
+
+/// Where the encoding of an entry is in its run file, as its offset and
+/// length, or `None` for a tombstone.
+type Slot = Option<(u64, u32)>;
+
+/// A sorted run, with its index held in memory.
+struct Run {
+    number: u64,
+    path: PathBuf,
+    file: File,
+    /// The entries in id order.
+    keys: Vec<(u64, Slot)>,
+}
+
+impl Run {
+    fn open(dir: &Path, number: u64) -> Result<Run, EngineError> {
+        let path = run_path(dir, number);
+        let mut file = File::open(&path).map_err(io)?;
+        let len = file.metadata().map_err(io)?.len();
+        let mut head = [0; 8];
+        file.read_exact(&mut head).map_err(io)?;
+        if &head != MAGIC || len < 32 {
+            return Err(damaged(&path, "header"));
+        }
+        let mut footer = [0; 16];
+        file.seek(SeekFrom::End(-16)).map_err(io)?;
+        file.read_exact(&mut footer).map_err(io)?;
+        let checksum = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let index_at = u64::from_le_bytes(footer[8..].try_into().unwrap());
+        if index_at < 8 || index_at > len - 24 {
+            return Err(damaged(&path, "footer"));
+        }
+        file.seek(SeekFrom::Start(index_at)).map_err(io)?;
+        let mut bytes = vec![0; (len - 16 - index_at) as usize];
+        file.read_exact(&mut bytes).map_err(io)?;
+        let count = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
+        if fnv1a(&bytes) != checksum || bytes.len() != 8 + count * 20 {
+            return Err(damaged(&path, "index"));
+        }
+        let keys = bytes[8..]
+            .chunks_exact(20)
+            .map(|entry| {
+                let id = u64::from_le_bytes(entry[..8].try_into().unwrap());
+                let offset = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+                let len = u32::from_le_bytes(entry[16..].try_into().unwrap());
+                (id, (len != TOMBSTONE).then_some((offset, len)))
+            })
+            .collect();
+        Ok(Run {
+            number,
+            path,
+            file,
+            keys,
+        })
+    }
+
+    /// Writes the entries, in id order, as run `number` in `dir`.
+    fn write(
+        dir: &Path,
+        number: u64,
+        entries: impl Iterator<Item = Result<(u64, Option<Vec<u8>>), EngineError>>,
+    ) -> Result<Run, EngineError> {
+        let path = run_path(dir, number);
+        let mut out = BufWriter::new(File::create(&path).map_err(io)?);
+        let mut index = Vec::new();
+        let mut offset = MAGIC.len() as u64;
+        out.write_all(MAGIC).map_err(io)?;
+        for entry in entries {
+            let (id, bytes) = entry?;
+            let len = bytes.as_ref().map_or(TOMBSTONE, |b| b.len() as u32);
+            out.write_all(&id.to_le_bytes()).map_err(io)?;
+            out.write_all(&len.to_le_bytes()).map_err(io)?;
+            offset += 12;
+            index.push((id, bytes.is_some().then_some((offset, len))));
+            if let Some(bytes) = bytes {
+                out.write_all(&bytes).map_err(io)?;
+                out.write_all(&fnv1a(&bytes).to_le_bytes()).map_err(io)?;
+                offset += bytes.len() as u64 + 8;
+            }
+        }
+        let mut table = (index.len() as u64).to_le_bytes().to_vec();
+        for (id, slot) in &index {
+            let (at, len) = slot.unwrap_or((0, TOMBSTONE));
+            table.extend_from_slice(&id.to_le_bytes());
+            table.extend_from_slice(&at.to_le_bytes());
+            table.extend_from_slice(&len.to_le_bytes());
+        }
+        out.write_all(&table).map_err(io)?;
+        out.write_all(&fnv1a(&table).to_le_bytes()).map_err(io)?;
+        out.write_all(&offset.to_le_bytes()).map_err(io)?;
+        out.into_inner()
+            .map_err(|e| io(e.into_error()))?
+            .sync_all()
+            .map_err(io)?;
+        Ok(Run {
+            number,
+            file: File::open(&path).map_err(io)?,
+            path,
+            keys: index,
+        })
+    }
+
+    fn find(&self, id: u64) -> Option<Slot> {
+        self.keys
+            .binary_search_by_key(&id, |(key, _)| *key)
+            .ok()
+            .map(|at| self.keys[at].1)
+    }
+
+    /// The encoding at `slot`, checked against its checksum, or `None` for
+    /// a tombstone.
+    fn bytes(&self, slot: Slot) -> Result<Option<Vec<u8>>, EngineError> {
+        let Some((offset, len)) = slot else {
+            return Ok(None);
+        };
+        let mut bytes = vec![0; len as usize + 8];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset)).map_err(io)?;
+        file.read_exact(&mut bytes).map_err(io)?;
+        let checksum = bytes.split_off(len as usize);
+        if fnv1a(&bytes).to_le_bytes()[..] != checksum[..] {
+            return Err(damaged(&self.path, &format!("entry at byte {}", offset)));
+        }
+        Ok(Some(bytes))
+    }
+
+    fn row(&self, slot: Slot) -> Result<Option<Row>, EngineError> {
+        match (slot, self.bytes(slot)?) {
+            (Some((offset, _)), Some(bytes)) => decode_row(&bytes)
+                .map(Some)
+                .map_err(|_| damaged(&self.path, &format!("entry at byte {}", offset))),
+            _ => Ok(None),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.file.metadata().map_or(0, |m| m.len())
+    }
+}
+
+/// The entries of several runs in id order, each id taken from the first
+/// run, the newest, that holds it.
+struct Merge<'a> {
+    runs: Vec<&'a Run>,
+    positions: Vec<usize>,
+    /// Whether tombstones are left out rather than passed on.
+    drop_tombstones: bool,
+}
+
+impl<'a> Merge<'a> {
+    fn new(runs: Vec<&'a Run>, drop_tombstones: bool) -> Self {
+        Self {
+            positions: vec![0; runs.len()],
+            runs,
+            drop_tombstones,
+        }
+    }
+}
+
+impl<'a> Iterator for Merge<'a> {
+    type Item = (u64, &'a Run, Slot);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let id = self
+                .runs
+                .iter()
+                .zip(&self.positions)
+                .filter_map(|(run, &at)| run.keys.get(at).map(|(id, _)| *id))
+                .min()?;
+            let mut newest = None;
+            for (run, at) in self.runs.iter().zip(&mut self.positions) {
+                if let Some(&(key, slot)) = run.keys.get(*at) {
+                    if key == id {
+                        newest.get_or_insert((*run, slot));
+                        *at += 1;
+                    }
+                }
+            }
+            let (run, slot) = newest?;
+            if slot.is_some() || !self.drop_tombstones {
+                return Some((id, run, slot));
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    columns: Vec<Column>,
+    next_id: u64,
+    next_run: u64,
+    levels: Vec<Vec<u64>>,
+}
+
+/// A table stored as an LSM tree in a directory; see the
+/// [module docs](self). Row ids count up from 0 and are not reused while
+/// the tree holds a tombstone for them.
+pub struct LsmStorage {
+    dir: PathBuf,
+    columns: Vec<Column>,
+    /// New rows and tombstones not yet written to a run.
+    memtable: BTreeMap<u64, Option<Row>>,
+    memtable_rows: usize,
+    /// The runs of each level. Level 0 lists its runs oldest first; every
+    /// other level has at most one.
+    levels: Vec<Vec<Run>>,
+    next_id: u64,
+    next_run: u64,
+}
+
+impl LsmStorage {
+    /// Creates an empty tree in `dir`, creating the directory if needed.
+    /// Fails if it already holds a tree.
+    pub fn create(dir: impl AsRef<Path>, columns: Vec<Column>) -> Result<Self, EngineError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(io)?;
+        if dir.join(MANIFEST).exists() {
+            return Err(EngineError::Io(format!(
+                "{} already holds an LSM tree",
+                dir.display()
+            )));
+        }
+        let storage = Self {
+            dir: dir.to_path_buf(),
+            columns,
+            memtable: BTreeMap::new(),
+            memtable_rows: DEFAULT_MEMTABLE_ROWS,
+            levels: vec![Vec::new()],
+            next_id: 0,
+            next_run: 0,
+        };
+        storage.write_manifest()?;
+        Ok(storage)
+    }
+
+    /// Opens a tree made by [`LsmStorage::create`], deleting any run file
+    /// a crash left behind.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let dir = dir.as_ref();
+        let manifest: Manifest = serde_json::from_slice(&fs::read(dir.join(MANIFEST)).map_err(io)?)
+            .map_err(|e| EngineError::Io(format!("{}: {}", dir.display(), e)))?;
+        let levels = manifest
+            .levels
+            .iter()
+            .map(|runs| runs.iter().map(|&n| Run::open(dir, n)).collect())
+            .collect::<Result<Vec<Vec<_>>, _>>()?;
+        for entry in fs::read_dir(dir).map_err(io)? {
+            let path = entry.map_err(io)?.path();
+            let number = path
+                .file_name()
+                .and_then(|name| name.to_str()?.strip_suffix(".run")?.parse::<u64>().ok());
+            if number.is_some_and(|n| !manifest.levels.iter().flatten().any(|&m| m == n)) {
+                fs::remove_file(&path).map_err(io)?;
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            columns: manifest.columns,
+            memtable: BTreeMap::new(),
+            memtable_rows: DEFAULT_MEMTABLE_ROWS,
+            levels,
+            next_id: manifest.next_id,
+            next_run: manifest.next_run,
+        })
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Writes the memtable out once it holds `rows` entries, at least one.
+    pub fn set_memtable_rows(&mut self, rows: usize) {
+        self.memtable_rows = rows.max(1);
+    }
+
+    /// The number of entries, rows and tombstones, in the runs of each
+    /// level, level 0 first.
+    pub fn level_sizes(&self) -> Vec<usize> {
+        self.levels
+            .iter()
+            .map(|runs| runs.iter().map(|run| run.keys.len()).sum())
+            .collect()
+    }
+
+    /// The runs from the newest to the oldest.
+    fn runs(&self) -> Vec<&Run> {
+        self.levels
+            .iter()
+            .flat_map(|runs| runs.iter().rev())
+            .collect()
+    }
+
+    /// The row with `id`, or `None` if there is none.
+    pub fn get(&self, id: u64) -> Result<Option<Row>, EngineError> {
+        if let Some(row) = self.memtable.get(&id) {
+            return Ok(row.clone());
+        }
+        for run in self.runs() {
+            if let Some(slot) = run.find(id) {
+                return run.row(slot);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks `row` against the columns, as [`crate::Table::insert`] does,
+    /// and stores it, returning its id.
+    pub fn insert(&mut self, row: Row) -> Result<u64, EngineError> {
+        let row = accepted(&self.columns, row)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.memtable.insert(id, Some(row));
+        self.flush_if_full()?;
+        Ok(id)
+    }
+
+    /// Deletes the row with `id`, returning whether there was one.
+    pub fn delete(&mut self, id: u64) -> Result<bool, EngineError> {
+        if self.get(id)?.is_none() {
+            return Ok(false);
+        }
+        self.memtable.insert(id, None);
+        self.flush_if_full()?;
+        Ok(true)
+    }
+
+    /// Calls `f` on every row in id order, and stops at the first error `f`
+    /// returns.
+    pub fn for_each(
+        &self,
+        mut f: impl FnMut(u64, Row) -> Result<(), EngineError>,
+    ) -> Result<(), EngineError> {
+        let mut stored = Merge::new(self.runs(), false).peekable();
+        let mut memtable = self.memtable.iter().peekable();
+        loop {
+            let (id, row) = match (stored.peek(), memtable.peek()) {
+                (None, None) => return Ok(()),
+                (Some(&(id, run, slot)), next) if next.is_none_or(|(&newer, _)| id < newer) => {
+                    stored.next();
+                    (id, run.row(slot)?)
+                }
+                (next, _) => {
+                    let (&id, row) = memtable.next().expect("a memtable entry is next");
+                    if next.is_some_and(|&(stored, _, _)| stored == id) {
+                        stored.next();
+                    }
+                    (id, row.clone())
+                }
+            };
+            if let Some(row) = row {
+                f(id, row)?;
+            }
+        }
+    }
+
+    /// Writes the memtable out as a run and compacts, making every change
+    /// so far durable.
+    pub fn flush(&mut self) -> Result<(), EngineError> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let entries = self
+            .memtable
+            .iter()
+            .map(|(&id, row)| Ok((id, row.as_ref().map(encode_row))));
+        let run = Run::write(&self.dir, self.next_run, entries)?;
+        self.memtable.clear();
+        self.next_run += 1;
+        self.levels[0].push(run);
+        let mut merged = Vec::new();
+        if self.levels[0].len() >= LEVEL0_RUNS {
+            merged.extend(self.merge_levels(0, 1)?);
+        }
+        let mut level = 1;
+        while level < self.levels.len() {
+            let limit = self
+                .memtable_rows
+                .saturating_mul(LEVEL_RATIO.saturating_pow(level as u32));
+            if self.level_sizes()[level] > limit {
+                merged.extend(self.merge_levels(level, level + 1)?);
+            }
+            level += 1;
+        }
+        self.write_manifest()?;
+        for run in merged {
+            fs::remove_file(&run.path).map_err(io)?;
+        }
+        Ok(())
+    }
+
+    fn flush_if_full(&mut self) -> Result<(), EngineError> {
+        if self.memtable.len() >= self.memtable_rows {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the runs of levels `from` through `to` into one run in level
+    /// `to`, returning the runs it replaced. Their files are left for the
+    /// caller to delete once the manifest no longer names them.
+    fn merge_levels(&mut self, from: usize, to: usize) -> Result<Vec<Run>, EngineError> {
+        if self.levels.len() <= to {
+            self.levels.resize_with(to + 1, Vec::new);
+        }
+        let last = self.levels[to + 1..].iter().all(Vec::is_empty);
+        let inputs = self.levels[from..=to]
+            .iter()
+            .flat_map(|runs| runs.iter().rev())
+            .collect();
+        let entries = Merge::new(inputs, last).map(|(id, run, slot)| Ok((id, run.bytes(slot)?)));
+        let run = Run::write(&self.dir, self.next_run, entries)?;
+        self.next_run += 1;
+        let mut replaced = Vec::new();
+        for runs in &mut self.levels[from..=to] {
+            replaced.append(runs);
+        }
+        self.levels[to].push(run);
+        Ok(replaced)
+    }
+
+    /// Merges every run into one, dropping tombstones and the rows they
+    /// hide, and returns how many bytes smaller the run files got.
+    pub fn vacuum(&mut self) -> Result<u64, EngineError> {
+        self.flush()?;
+        let before = self.runs().iter().map(|run| run.size()).sum::<u64>();
+        if before == 0 {
+            return Ok(0);
+        }
+        let merged = self.merge_levels(0, self.levels.len().max(2) - 1)?;
+        self.write_manifest()?;
+        for run in merged {
+            fs::remove_file(&run.path).map_err(io)?;
+        }
+        let after = self.runs().iter().map(|run| run.size()).sum::<u64>();
+        Ok(before.saturating_sub(after))
+    }
+
+    /// Writes the memtable out, then reads every run back, failing with
+    /// [`EngineError::Corruption`] at the first index or entry that does
+    /// not match its checksum.
+    pub fn verify(&mut self) -> Result<(), EngineError> {
+        self.flush()?;
+        for run in self.runs() {
+            let read = Run::open(&self.dir, run.number)?;
+            for (_, slot) in &read.keys {
+                read.bytes(*slot)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_manifest(&self) -> Result<(), EngineError> {
+        let manifest = Manifest {
+            columns: self.columns.clone(),
+            next_id: self.next_id,
+            next_run: self.next_run,
+            levels: self
+                .levels
+                .iter()
+                .map(|runs| runs.iter().map(|run| run.number).collect())
+                .collect(),
+        };
+        let temp = self.dir.join(format!("{}.tmp", MANIFEST));
+        let json = serde_json::to_vec(&manifest).map_err(|e| EngineError::Io(e.to_string()))?;
+        let mut file = File::create(&temp).map_err(io)?;
+        file.write_all(&json).map_err(io)?;
+        file.sync_all().map_err(io)?;
+        fs::rename(&temp, self.dir.join(MANIFEST)).map_err(io)
+    }
+}
+
+impl Drop for LsmStorage {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
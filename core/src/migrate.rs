@@ -0,0 +1,144 @@
+//! Schema migrations: numbered groups of statements that applications run
+//! in order to evolve their schema. Each migration applies completely or
+//! not at all, and the engine records which ones it has applied.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Scope, Table};
+use crate::parser::{parse_statement, statement_error, SelectQuery};
+use crate::view::MaterializedView;
+
+/// One step of schema evolution, as SQL statements run in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    /// Position of the migration; later migrations have higher versions.
+    pub version: u32,
+    pub name: String,
+    pub steps: Vec<String>,
+}
+
+impl Migration {
+    pub fn new(version: u32, name: &str) -> Self {
+        Self {
+            version,
+            name: name.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Adds a statement to run after the ones already added.
+    pub fn step(mut self, sql: &str) -> Self {
+        self.steps.push(sql.to_string());
+        self
+    }
+}
+
+/// A migration the engine has applied, as listed by
+/// [`Engine::applied_migrations`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    /// When it was applied, in microseconds since the Unix epoch.
+    pub applied_at: i64,
+}
+
+/// The schema and data as they were before a migration or script started.
+pub(crate) struct Snapshot {
+    tables: HashMap<String, Table>,
+    views: HashMap<String, SelectQuery>,
+    materialized: HashMap<String, MaterializedView>,
+    schemas: BTreeSet<String>,
+}
+
+impl Engine {
+    /// The version of the last migration applied, or 0 if none has been.
+    pub fn schema_version(&self) -> u32 {
+        self.migrations.last().map_or(0, |m| m.version)
+    }
+
+    pub fn applied_migrations(&self) -> &[AppliedMigration] {
+        &self.migrations
+    }
+
+    /// Applies, in order, the migrations newer than the schema version and
+    /// returns how many it applied. `migrations` must be sorted by
+    /// increasing version, and those already applied must keep their
+    /// names.
+    ///
+    /// If a statement fails, everything its migration changed is undone
+    /// and `MigrationFailed` is returned; earlier migrations stay applied.
+    pub fn migrate(&mut self, migrations: &[Migration]) -> Result<usize, EngineError> {
+        if let Some(pair) = migrations.windows(2).find(|w| w[0].version >= w[1].version) {
+            return Err(EngineError::InvalidQuery(format!(
+                "migration {} is listed after migration {}",
+                pair[1].version, pair[0].version
+            )));
+        }
+        for migration in migrations {
+            if let Some(applied) = self
+                .migrations
+                .iter()
+                .find(|a| a.version == migration.version && a.name != migration.name)
+            {
+                return Err(EngineError::InvalidQuery(format!(
+                    "migration {} was applied as {}, not {}",
+                    applied.version, applied.name, migration.name
+                )));
+            }
+        }
+        let current = self.schema_version();
+        let pending = migrations.iter().filter(|m| m.version > current);
+        let mut count = 0;
+        for migration in pending {
+            let state = self.save_state()?;
+            if let Err(error) = self.run_steps(&migration.steps) {
+                self.restore_state(state);
+                // Table logs may hold what the failed steps did.
+                self.compact()?;
+                return Err(EngineError::MigrationFailed {
+                    version: migration.version,
+                    error: Box::new(error),
+                });
+            }
+            self.migrations.push(AppliedMigration {
+                version: migration.version,
+                name: migration.name.clone(),
+                applied_at: Scope::new().now,
+            });
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn run_steps(&mut self, steps: &[String]) -> Result<(), EngineError> {
+        for sql in steps {
+            let (_, query) = parse_statement(sql).map_err(|e| statement_error(sql, e))?;
+            self.execute(query)?;
+        }
+        Ok(())
+    }
+
+    /// The schema and data as they are now, for [`Engine::restore_state`].
+    /// Spilled tables are read back first, so the snapshot holds them.
+    pub(crate) fn save_state(&mut self) -> Result<Snapshot, EngineError> {
+        self.load_spilled()?;
+        Ok(Snapshot {
+            tables: self.tables.clone(),
+            views: self.views.clone(),
+            materialized: self.materialized.clone(),
+            schemas: self.schemas.clone(),
+        })
+    }
+
+    pub(crate) fn restore_state(&mut self, snapshot: Snapshot) {
+        // The snapshot holds every row, so tables spilled since are not.
+        self.forget_spilled();
+        self.tables = snapshot.tables;
+        self.views = snapshot.views;
+        self.materialized = snapshot.materialized;
+        self.schemas = snapshot.schemas;
+    }
+}
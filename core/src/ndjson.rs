@@ -0,0 +1,127 @@
+//! Moving rows in and out of the engine as JSON: objects one per line
+//! (NDJSON) on import, and either that or a single array of objects on
+//! export; see [`Engine::import_ndjson`] and [`Engine::export_json`].
+
+use std::io::{BufRead, Write};
+
+use serde_json::Value as Json;
+
+use crate::engine::{Engine, EngineError, Row, Value, ValueType};
+use crate::json::{to_json, unquote};
+
+/// How [`Engine::export_json`] lays out the rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// One array holding an object per row.
+    Array,
+    /// One object per line.
+    Lines,
+}
+
+fn io_error(e: std::io::Error) -> EngineError {
+    EngineError::Io(e.to_string())
+}
+
+impl Engine {
+    /// Reads newline-delimited JSON objects from `reader` into `table`,
+    /// returning the number of rows added. Each key names a column; columns
+    /// without a key are NULL, or take their next AUTO_INCREMENT value.
+    /// Strings are cast to their column's type, and objects and arrays are
+    /// kept whole in JSON columns. Blank lines are skipped.
+    ///
+    /// Rows are checked as INSERT checks them, and the import adds all of
+    /// them or none.
+    pub fn import_ndjson(
+        &mut self,
+        table: &str,
+        reader: impl BufRead,
+    ) -> Result<usize, EngineError> {
+        let target = self
+            .tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_string()))?;
+        let mut rows = Vec::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line.map_err(io_error)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| EngineError::InvalidInput {
+                line: idx + 1,
+                reason,
+            };
+            let object = match serde_json::from_str(&line) {
+                Ok(Json::Object(object)) => object,
+                Ok(_) => return Err(invalid("not a JSON object".to_string())),
+                Err(e) => return Err(invalid(e.to_string())),
+            };
+            let mut row = target
+                .columns
+                .iter()
+                .map(|c| Value::TypedNull(c.col_type.clone()))
+                .collect::<Row>();
+            for (key, json) in object {
+                let col_idx = target
+                    .columns
+                    .iter()
+                    .position(|c| c.name == key)
+                    .ok_or(EngineError::ColumnNotFound(key))?;
+                let col_type = &target.columns[col_idx].col_type;
+                let value = match (col_type, unquote(&json)) {
+                    (_, Value::Null) => continue,
+                    (ValueType::Json, _) => Value::Json(json),
+                    (_, text @ Value::Text(_)) => text.cast(col_type).unwrap_or(text),
+                    (_, value) => value,
+                };
+                row[col_idx] = target.accept(col_idx, value)?;
+            }
+            rows.push(row);
+        }
+        self.insert_rows(table, rows)
+    }
+
+    /// Writes the rows of `source` to `writer` as JSON objects keyed by the
+    /// result's column names, in column order, returning how many were
+    /// written. `source` is the name of a table or view, or a SELECT.
+    pub fn export_json(
+        &self,
+        source: &str,
+        mut writer: impl Write,
+        format: JsonFormat,
+    ) -> Result<usize, EngineError> {
+        let result = self.export_source(source)?;
+        let keys = result
+            .column_names()
+            .into_iter()
+            .map(|name| Json::from(name).to_string())
+            .collect::<Vec<_>>();
+        let mut out = String::new();
+        if format == JsonFormat::Array {
+            out.push('[');
+        }
+        for (row_idx, row) in result.rows.iter().enumerate() {
+            if format == JsonFormat::Array && row_idx > 0 {
+                out.push(',');
+            }
+            out.push('{');
+            for (col_idx, (key, value)) in keys.iter().zip(row).enumerate() {
+                if col_idx > 0 {
+                    out.push(',');
+                }
+                out.push_str(key);
+                out.push(':');
+                out.push_str(&to_json(value).to_string());
+            }
+            out.push('}');
+            if format == JsonFormat::Lines {
+                out.push('\n');
+            }
+        }
+        if format == JsonFormat::Array {
+            out.push_str("]\n");
+        }
+        writer.write_all(out.as_bytes()).map_err(io_error)?;
+        writer.flush().map_err(io_error)?;
+        Ok(result.rows.len())
+    }
+}
@@ -0,0 +1,656 @@
+//! Tables kept in a file of fixed-size pages rather than in memory, read
+//! through a buffer pool that holds a bounded number of pages, so that a
+//! table can be larger than the memory given to it.
+//!
+//! Every page ends with an FNV-1a checksum of the rest of it, as a
+//! little-endian `u64`, written when the page is and checked when it is
+//! read back; a page failing it fails with [`EngineError::Corruption`].
+//!
+//! Page 0 holds the header: the bytes `MSQLPAG2`, the page size as a
+//! little-endian `u32`, the number of pages and the first free page as
+//! little-endian `u64`s, then the columns as JSON behind their length as a
+//! `u32`, then a byte that is 1 if new rows are compressed. Every other
+//! page is a row page or a free page. A row page is a slotted page: a type
+//! byte, the number of slots and the start of the row data as
+//! little-endian `u16`s, then one slot per row holding the offset and
+//! length of its encoding, while the data fills the page from its checksum
+//! backwards. The top bit of the length marks a compressed row: its length
+//! before compression as a little-endian `u32`, then a [`crate::compress`]
+//! block.
+//! A free page holds the number of the next free page at byte 8, so that
+//! free pages form a list that new pages are taken from.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::codec::{decode_row, encode_row, fnv1a};
+use crate::compress::{compress, decompress};
+use crate::engine::{Column, Engine, EngineError, QueryResult, Row};
+use crate::parser::SelectQuery;
+
+/// Size of every page, in bytes.
+pub const PAGE_SIZE: usize = 4096;
+/// Pages a table keeps in memory unless told otherwise.
+pub const DEFAULT_POOL_PAGES: usize = 256;
+
+const MAGIC: &[u8; 8] = b"MSQLPAG2";
+const FREE_PAGE: u8 = 0;
+const ROW_PAGE: u8 = 1;
+/// Type byte, slot count and data start.
+const ROW_HEADER: usize = 5;
+const SLOT: usize = 4;
+/// Where the checksum at the end of every page starts.
+const DATA_END: usize = PAGE_SIZE - 8;
+/// Set in a slot's length when the row is compressed.
+const PACKED: usize = 0x8000;
+/// Longest row encoding a page holds.
+pub const MAX_ROW_LEN: usize = DATA_END - ROW_HEADER - SLOT;
+
+type Page = Box<[u8; PAGE_SIZE]>;
+
+/// Where a row of a [`PagedTable`] is stored. The position of a deleted
+/// row may be given to a row inserted later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RowId {
+    pub page: u64,
+    pub slot: u16,
+}
+
+/// How the buffer pool of a [`PagedTable`] has been used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Pages held in memory now.
+    pub pages: usize,
+    /// Page reads served from memory.
+    pub hits: u64,
+    /// Page reads that went to the file.
+    pub misses: u64,
+}
+
+fn u16_at(page: &[u8], at: usize) -> usize {
+    u16::from_le_bytes([page[at], page[at + 1]]) as usize
+}
+
+fn set_u16(page: &mut [u8], at: usize, n: usize) {
+    page[at..at + 2].copy_from_slice(&(n as u16).to_le_bytes());
+}
+
+fn u64_at(page: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(page[at..at + 8].try_into().unwrap())
+}
+
+/// Fails if `page`, page `id` of the file at `path`, does not match its
+/// checksum.
+fn check_page(path: &Path, id: u64, page: &[u8]) -> Result<(), EngineError> {
+    if fnv1a(&page[..DATA_END]) == u64_at(page, DATA_END) {
+        Ok(())
+    } else {
+        Err(EngineError::Corruption {
+            location: format!("{}: page {}", path.display(), id),
+        })
+    }
+}
+
+struct Frame {
+    page: Page,
+    dirty: bool,
+    /// When the page was last read or written, in accesses.
+    used: u64,
+}
+
+/// The file of pages, read and written through a pool of at most
+/// `capacity` pages that drops the least recently used page when full.
+struct Pager {
+    file: File,
+    path: PathBuf,
+    page_count: u64,
+    /// First page of the free list, or 0 for none.
+    free_head: u64,
+    frames: HashMap<u64, Frame>,
+    capacity: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Pager {
+    fn io(e: std::io::Error) -> EngineError {
+        EngineError::Io(e.to_string())
+    }
+
+    /// Makes room for one more page, writing out the least recently used
+    /// page if it changed.
+    fn evict(&mut self) -> Result<(), EngineError> {
+        if self.frames.len() < self.capacity {
+            return Ok(());
+        }
+        let oldest = self
+            .frames
+            .iter()
+            .min_by_key(|(_, frame)| frame.used)
+            .map(|(&id, _)| id);
+        if let Some(id) = oldest {
+            let frame = self.frames.remove(&id).unwrap();
+            if frame.dirty {
+                self.write_out(id, &frame.page[..])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes page `id` with its checksum.
+    fn write_out(&mut self, id: u64, page: &[u8]) -> Result<(), EngineError> {
+        let data = &page[..DATA_END];
+        self.file
+            .seek(SeekFrom::Start(id * PAGE_SIZE as u64))
+            .and_then(|_| self.file.write_all(data))
+            .and_then(|_| self.file.write_all(&fnv1a(data).to_le_bytes()))
+            .map_err(Self::io)
+    }
+
+    /// Reads page `id` from the file, checking its checksum.
+    fn read_in(&mut self, id: u64) -> Result<Page, EngineError> {
+        let mut page: Page = Box::new([0; PAGE_SIZE]);
+        self.file
+            .seek(SeekFrom::Start(id * PAGE_SIZE as u64))
+            .and_then(|_| self.file.read_exact(&mut page[..]))
+            .map_err(Self::io)?;
+        check_page(&self.path, id, &page[..])?;
+        Ok(page)
+    }
+
+    fn frame(&mut self, id: u64) -> Result<&mut Frame, EngineError> {
+        if id == 0 || id >= self.page_count {
+            return Err(EngineError::InvalidPage(id));
+        }
+        self.clock += 1;
+        if self.frames.contains_key(&id) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            self.evict()?;
+            let page = self.read_in(id)?;
+            let frame = Frame {
+                page,
+                dirty: false,
+                used: 0,
+            };
+            self.frames.insert(id, frame);
+        }
+        let frame = self.frames.get_mut(&id).unwrap();
+        frame.used = self.clock;
+        Ok(frame)
+    }
+
+    fn page(&mut self, id: u64) -> Result<&[u8; PAGE_SIZE], EngineError> {
+        Ok(&self.frame(id)?.page)
+    }
+
+    fn page_mut(&mut self, id: u64) -> Result<&mut [u8; PAGE_SIZE], EngineError> {
+        let frame = self.frame(id)?;
+        frame.dirty = true;
+        Ok(&mut frame.page)
+    }
+
+    /// A new empty row page, taken from the free list when it has one.
+    fn allocate(&mut self) -> Result<u64, EngineError> {
+        let id = if self.free_head != 0 {
+            let id = self.free_head;
+            self.free_head = u64_at(self.page(id)?, 8);
+            id
+        } else {
+            self.evict()?;
+            let id = self.page_count;
+            self.page_count += 1;
+            let frame = Frame {
+                page: Box::new([0; PAGE_SIZE]),
+                dirty: true,
+                used: self.clock,
+            };
+            self.frames.insert(id, frame);
+            id
+        };
+        let page = self.page_mut(id)?;
+        page.fill(0);
+        page[0] = ROW_PAGE;
+        set_u16(page, 3, DATA_END);
+        Ok(id)
+    }
+
+    /// Puts a page on the free list.
+    fn free(&mut self, id: u64) -> Result<(), EngineError> {
+        let head = self.free_head;
+        let page = self.page_mut(id)?;
+        page.fill(0);
+        page[0] = FREE_PAGE;
+        page[8..16].copy_from_slice(&head.to_le_bytes());
+        self.free_head = id;
+        Ok(())
+    }
+
+    fn header(&self, columns: &[Column], compressed: bool) -> Result<Vec<u8>, EngineError> {
+        let schema = serde_json::to_vec(columns).map_err(|e| EngineError::Io(e.to_string()))?;
+        if 33 + schema.len() > DATA_END {
+            return Err(EngineError::InvalidOperation(
+                "the columns do not fit in the header page".to_string(),
+            ));
+        }
+        let mut header = vec![0; PAGE_SIZE];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        header[12..20].copy_from_slice(&self.page_count.to_le_bytes());
+        header[20..28].copy_from_slice(&self.free_head.to_le_bytes());
+        header[28..32].copy_from_slice(&(schema.len() as u32).to_le_bytes());
+        header[32..32 + schema.len()].copy_from_slice(&schema);
+        header[32 + schema.len()] = u8::from(compressed);
+        Ok(header)
+    }
+
+    /// Writes every changed page, then the header.
+    fn flush(&mut self, columns: &[Column], compressed: bool) -> Result<(), EngineError> {
+        let mut dirty = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| frame.dirty)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        dirty.sort_unstable();
+        for id in dirty {
+            let page = self.frames[&id].page.clone();
+            self.write_out(id, &page[..])?;
+            self.frames.get_mut(&id).unwrap().dirty = false;
+        }
+        let header = self.header(columns, compressed)?;
+        self.write_out(0, &header)?;
+        self.file.sync_data().map_err(Self::io)
+    }
+}
+
+/// A slotted row page.
+struct RowPage<'a>(&'a [u8; PAGE_SIZE]);
+
+impl RowPage<'_> {
+    fn slots(&self) -> usize {
+        u16_at(self.0, 1)
+    }
+
+    /// The bytes stored in `slot`, or `None` if its row was deleted.
+    fn row(&self, slot: usize) -> Option<&[u8]> {
+        let at = ROW_HEADER + slot * SLOT;
+        let (offset, len) = (u16_at(self.0, at), u16_at(self.0, at + 2) & !PACKED);
+        (len > 0).then(|| &self.0[offset..offset + len])
+    }
+
+    /// The row in `slot`, or `None` if it was deleted; `Some(Err)` if its
+    /// bytes do not decode.
+    fn decoded(&self, slot: usize) -> Option<Result<Row, ()>> {
+        let bytes = self.row(slot)?;
+        if u16_at(self.0, ROW_HEADER + slot * SLOT + 2) & PACKED == 0 {
+            return Some(decode_row(bytes).map_err(|_| ()));
+        }
+        let len = bytes
+            .get(..4)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+        Some(
+            len.and_then(|len| decompress(&bytes[4..], len))
+                .ok_or(())
+                .and_then(|raw| decode_row(&raw).map_err(|_| ())),
+        )
+    }
+
+    /// Bytes free between the slots and the row data.
+    fn gap(&self) -> usize {
+        u16_at(self.0, 3) - ROW_HEADER - self.slots() * SLOT
+    }
+
+    /// Bytes free once deleted rows are squeezed out.
+    fn room(&self) -> usize {
+        let live: usize = (0..self.slots())
+            .filter_map(|s| self.row(s))
+            .map(<[u8]>::len)
+            .sum();
+        DATA_END - ROW_HEADER - self.slots() * SLOT - live
+    }
+
+    fn dead_slot(&self) -> Option<usize> {
+        (0..self.slots()).find(|&s| self.row(s).is_none())
+    }
+}
+
+/// Adds `row` to a row page, reusing a deleted row's slot and squeezing
+/// out deleted rows as needed, or returns `None` if it does not fit.
+/// `packed` marks the bytes as a compressed row.
+fn place(page: &mut [u8; PAGE_SIZE], row: &[u8], packed: bool) -> Option<usize> {
+    let view = RowPage(page);
+    let slot = view.dead_slot();
+    let needed = row.len() + if slot.is_some() { 0 } else { SLOT };
+    if view.room() < needed {
+        return None;
+    }
+    if view.gap() < needed {
+        compact(page);
+    }
+    let slots = RowPage(page).slots();
+    let slot = slot.unwrap_or_else(|| {
+        set_u16(page, 1, slots + 1);
+        slots
+    });
+    let start = u16_at(page, 3) - row.len();
+    page[start..start + row.len()].copy_from_slice(row);
+    set_u16(page, 3, start);
+    set_u16(page, ROW_HEADER + slot * SLOT, start);
+    let flag = if packed { PACKED } else { 0 };
+    set_u16(page, ROW_HEADER + slot * SLOT + 2, row.len() | flag);
+    Some(slot)
+}
+
+/// Moves the live rows of a page together at its end.
+fn compact(page: &mut [u8; PAGE_SIZE]) {
+    let view = RowPage(page);
+    let rows = (0..view.slots())
+        .map(|s| view.row(s).map(<[u8]>::to_vec))
+        .collect::<Vec<_>>();
+    let mut end = DATA_END;
+    for (slot, row) in rows.iter().enumerate() {
+        if let Some(row) = row {
+            end -= row.len();
+            page[end..end + row.len()].copy_from_slice(row);
+            set_u16(page, ROW_HEADER + slot * SLOT, end);
+        }
+    }
+    set_u16(page, 3, end);
+}
+
+/// A table stored in a file of pages; see the [module docs](self).
+pub struct PagedTable {
+    pager: Pager,
+    columns: Vec<Column>,
+    /// The page rows were last added to, tried first for the next row.
+    last_page: Option<u64>,
+    /// Whether rows added are compressed.
+    compressed: bool,
+}
+
+impl PagedTable {
+    /// Creates a file at `path`, which must not exist, for an empty table
+    /// with `columns`, keeping up to `pool_pages` pages in memory.
+    pub fn create(
+        path: impl AsRef<Path>,
+        columns: Vec<Column>,
+        pool_pages: usize,
+    ) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(Pager::io)?;
+        let mut table = Self::with_pager(file, path, columns, 1, 0, pool_pages);
+        table.flush()?;
+        Ok(table)
+    }
+
+    /// Opens a table created by [`PagedTable::create`], keeping up to
+    /// `pool_pages` pages in memory.
+    pub fn open(path: impl AsRef<Path>, pool_pages: usize) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(Pager::io)?;
+        let mut header = vec![0; PAGE_SIZE];
+        file.read_exact(&mut header).map_err(Pager::io)?;
+        let page_size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        if &header[..8] != MAGIC || page_size != PAGE_SIZE {
+            return Err(EngineError::InvalidPage(0));
+        }
+        check_page(path, 0, &header)?;
+        let schema_len = u32::from_le_bytes(header[28..32].try_into().unwrap()) as usize;
+        let columns = header
+            .get(32..32 + schema_len)
+            .and_then(|schema| serde_json::from_slice(schema).ok())
+            .ok_or(EngineError::InvalidPage(0))?;
+        let (page_count, free_head) = (u64_at(&header, 12), u64_at(&header, 20));
+        let mut table = Self::with_pager(file, path, columns, page_count, free_head, pool_pages);
+        table.compressed = header.get(32 + schema_len) == Some(&1);
+        Ok(table)
+    }
+
+    fn with_pager(
+        file: File,
+        path: &Path,
+        columns: Vec<Column>,
+        page_count: u64,
+        free_head: u64,
+        pool_pages: usize,
+    ) -> Self {
+        Self {
+            pager: Pager {
+                file,
+                path: path.to_path_buf(),
+                page_count,
+                free_head,
+                frames: HashMap::new(),
+                capacity: pool_pages.max(1),
+                clock: 0,
+                hits: 0,
+                misses: 0,
+            },
+            columns,
+            last_page: None,
+            compressed: false,
+        }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Whether rows added are compressed.
+    pub fn compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Compresses the rows added from now on, when that makes them
+    /// smaller, or stops doing so. Rows already stored are read either
+    /// way. The setting is kept in the file.
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+    }
+
+    /// Checks `row` against the columns, as [`Table::insert`] does, and
+    /// stores it. Fails if its encoding, compressed if the table is, is
+    /// longer than [`MAX_ROW_LEN`].
+    pub fn insert(&mut self, row: Row) -> Result<RowId, EngineError> {
+        if row.len() != self.columns.len() {
+            return Err(EngineError::ValueCountMismatch);
+        }
+        let row = self
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| column.accept(value))
+            .collect::<Result<Row, _>>()?;
+        let mut bytes = encode_row(&row);
+        let mut packed = false;
+        if self.compressed {
+            let block = compress(&bytes);
+            if 4 + block.len() < bytes.len() {
+                let mut stored = (bytes.len() as u32).to_le_bytes().to_vec();
+                stored.extend_from_slice(&block);
+                bytes = stored;
+                packed = true;
+            }
+        }
+        if bytes.len() > MAX_ROW_LEN {
+            return Err(EngineError::InvalidOperation(format!(
+                "a row of {} bytes does not fit in a page",
+                bytes.len()
+            )));
+        }
+        if let Some(page) = self.last_page {
+            if let Some(slot) = place(self.pager.page_mut(page)?, &bytes, packed) {
+                return Ok(RowId {
+                    page,
+                    slot: slot as u16,
+                });
+            }
+        }
+        let page = self.pager.allocate()?;
+        self.last_page = Some(page);
+        let slot =
+            place(self.pager.page_mut(page)?, &bytes, packed).expect("an empty page holds a row");
+        Ok(RowId {
+            page,
+            slot: slot as u16,
+        })
+    }
+
+    fn row_page(&mut self, page: u64) -> Result<RowPage<'_>, EngineError> {
+        let data = self.pager.page(page)?;
+        if data[0] != ROW_PAGE {
+            return Err(EngineError::InvalidPage(page));
+        }
+        Ok(RowPage(data))
+    }
+
+    /// The row at `id`, or `None` if it was deleted or never stored.
+    pub fn get(&mut self, id: RowId) -> Result<Option<Row>, EngineError> {
+        if id.page == 0
+            || id.page >= self.pager.page_count
+            || self.pager.page(id.page)?[0] == FREE_PAGE
+        {
+            return Ok(None);
+        }
+        let page = self.row_page(id.page)?;
+        let slot = id.slot as usize;
+        match (slot < page.slots()).then(|| page.decoded(slot)).flatten() {
+            Some(row) => row.map(Some).map_err(|_| EngineError::InvalidPage(id.page)),
+            None => Ok(None),
+        }
+    }
+
+    /// Deletes the row at `id`, returning whether there was one. A page
+    /// left without rows goes back on the free list.
+    pub fn delete(&mut self, id: RowId) -> Result<bool, EngineError> {
+        if self.get(id)?.is_none() {
+            return Ok(false);
+        }
+        let page = self.pager.page_mut(id.page)?;
+        set_u16(page, ROW_HEADER + id.slot as usize * SLOT + 2, 0);
+        let view = RowPage(page);
+        if (0..view.slots()).all(|s| view.row(s).is_none()) {
+            self.pager.free(id.page)?;
+            if self.last_page == Some(id.page) {
+                self.last_page = None;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Calls `f` on every row in storage order, reading one page at a
+    /// time, and stops at the first error `f` returns.
+    pub fn for_each(
+        &mut self,
+        mut f: impl FnMut(RowId, Row) -> Result<(), EngineError>,
+    ) -> Result<(), EngineError> {
+        for page_id in 1..self.pager.page_count {
+            let data = self.pager.page(page_id)?;
+            if data[0] == FREE_PAGE {
+                continue;
+            }
+            let page = self.row_page(page_id)?;
+            let rows = (0..page.slots())
+                .filter_map(|slot| page.decoded(slot).map(|row| (slot, row)))
+                .collect::<Vec<_>>();
+            for (slot, row) in rows {
+                let row = row.map_err(|_| EngineError::InvalidPage(page_id))?;
+                let id = RowId {
+                    page: page_id,
+                    slot: slot as u16,
+                };
+                f(id, row)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every changed page and the header to the file.
+    pub fn flush(&mut self) -> Result<(), EngineError> {
+        self.pager.flush(&self.columns, self.compressed)
+    }
+
+    /// Rewrites the file with the rows packed into as few pages as they
+    /// fit, dropping free pages and the space of deleted rows, and returns
+    /// how many bytes smaller it got. Rows get new ids. The new file is
+    /// written beside the old one and then renamed over it, so a failed
+    /// vacuum leaves the old file whole.
+    pub fn vacuum(&mut self) -> Result<u64, EngineError> {
+        self.flush()?;
+        let mut temp = self.pager.path.as_os_str().to_owned();
+        temp.push(".vacuum");
+        let temp = PathBuf::from(temp);
+        let _ = fs::remove_file(&temp);
+        let mut packed = PagedTable::create(&temp, self.columns.clone(), self.pager.capacity)?;
+        packed.compressed = self.compressed;
+        let copied = self
+            .for_each(|_, row| packed.insert(row).map(|_| ()))
+            .and_then(|_| packed.flush())
+            .and_then(|_| fs::rename(&temp, &self.pager.path).map_err(Pager::io));
+        if let Err(e) = copied {
+            drop(packed);
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+        let before = self.pager.page_count;
+        packed.pager.path = self.pager.path.clone();
+        // The old pages were flushed above and nothing changed since, so
+        // dropping them writes nothing.
+        *self = packed;
+        Ok(before.saturating_sub(self.pager.page_count) * PAGE_SIZE as u64)
+    }
+
+    /// Writes every changed page, then reads every page of the file back,
+    /// failing with [`EngineError::Corruption`] at the first that does not
+    /// match its checksum.
+    pub fn verify(&mut self) -> Result<(), EngineError> {
+        self.flush()?;
+        for id in 0..self.pager.page_count {
+            self.pager.read_in(id)?;
+        }
+        Ok(())
+    }
+
+    pub fn pool_stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            pages: self.pager.frames.len(),
+            hits: self.pager.hits,
+            misses: self.pager.misses,
+        }
+    }
+}
+
+impl Drop for PagedTable {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Engine {
+    /// Runs `q`, which reads `table` under the name `name`, alongside the
+    /// engine's own tables. When the query reads only that table, its rows
+    /// are streamed through the WHERE condition and only those matching it
+    /// are held in memory; otherwise every row is.
+    pub fn select_paged(
+        &self,
+        name: &str,
+        table: &mut PagedTable,
+        q: &SelectQuery,
+    ) -> Result<QueryResult, EngineError> {
+        self.select_stored(name, table, q)
+    }
+}
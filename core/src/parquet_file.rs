@@ -0,0 +1,309 @@
+//! Reading and writing Parquet files, with the `parquet` feature; see
+//! [`Engine::import_parquet`] and [`Engine::export_parquet`].
+//!
+//! Exported columns are all optional, typed from their first non-NULL
+//! value: integers as `INT64`, floats as `DOUBLE`, booleans, dates as
+//! `INT32 (DATE)`, timestamps as `INT64 (TIMESTAMP(MICROS))`, and anything
+//! else as `UTF8` text.
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::column::writer::ColumnWriterImpl;
+use parquet::data_type::{
+    BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int32Type, Int64Type,
+};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::format::MicroSeconds;
+use parquet::record::Field;
+use parquet::schema::types::Type;
+
+use crate::engine::{Engine, EngineError, Row, Value, ValueType};
+use crate::expr::infer_type;
+
+fn parquet_error(path: &Path, e: impl std::fmt::Display) -> EngineError {
+    EngineError::Io(format!("{}: {}", path.display(), e))
+}
+
+/// The SQL value of a Parquet field, or `None` for a nested group or map.
+fn to_value(field: &Field) -> Option<Value> {
+    Some(match field {
+        Field::Null => Value::Null,
+        Field::Bool(b) => Value::Bool(*b),
+        Field::Byte(n) => Value::Int(i64::from(*n)),
+        Field::Short(n) => Value::Int(i64::from(*n)),
+        Field::Int(n) => Value::Int(i64::from(*n)),
+        Field::Long(n) => Value::Int(*n),
+        Field::UByte(n) => Value::Int(i64::from(*n)),
+        Field::UShort(n) => Value::Int(i64::from(*n)),
+        Field::UInt(n) => Value::Int(i64::from(*n)),
+        Field::ULong(n) => Value::from(*n),
+        Field::Float16(f) => Value::Float(f.to_f64()),
+        Field::Float(f) => Value::Float(f64::from(*f)),
+        Field::Double(f) => Value::Float(*f),
+        Field::Decimal(_) => Value::Decimal(field.to_string().parse().ok()?),
+        Field::Str(s) => Value::Text(s.clone()),
+        Field::Bytes(bytes) => Value::Text(bytes.as_utf8().ok()?.to_string()),
+        Field::Date(days) => Value::Date(*days),
+        Field::TimestampMillis(ms) => Value::Timestamp(ms.checked_mul(1000)?),
+        Field::TimestampMicros(us) => Value::Timestamp(*us),
+        Field::ListInternal(list) => Value::List(
+            list.elements()
+                .iter()
+                .map(to_value)
+                .collect::<Option<_>>()?,
+        ),
+        Field::Group(_) | Field::MapInternal(_) => return None,
+    })
+}
+
+/// The Parquet column written for values of type `col_type`.
+fn column_type(name: &str, col_type: &ValueType) -> Result<Type, parquet::errors::ParquetError> {
+    let (physical, logical) = match col_type {
+        ValueType::Int => (PhysicalType::INT64, None),
+        ValueType::Float => (PhysicalType::DOUBLE, None),
+        ValueType::Bool => (PhysicalType::BOOLEAN, None),
+        ValueType::Date => (PhysicalType::INT32, Some(LogicalType::Date)),
+        ValueType::Timestamp => (
+            PhysicalType::INT64,
+            Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: true,
+                unit: TimeUnit::MICROS(MicroSeconds {}),
+            }),
+        ),
+        _ => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+    };
+    Type::primitive_type_builder(name, physical)
+        .with_repetition(Repetition::OPTIONAL)
+        .with_logical_type(logical)
+        .build()
+}
+
+/// Writes the non-NULL values of a column with their definition levels.
+fn write_column<T: DataType>(
+    writer: &mut ColumnWriterImpl<'_, T>,
+    values: impl Iterator<Item = Option<T::T>>,
+) -> Result<(), parquet::errors::ParquetError> {
+    let mut present = Vec::new();
+    let mut levels = Vec::new();
+    for value in values {
+        levels.push(i16::from(value.is_some()));
+        present.extend(value);
+    }
+    writer.write_batch(&present, Some(&levels), None)?;
+    Ok(())
+}
+
+impl Engine {
+    /// Reads the rows of the Parquet file at `path` into `table`, returning
+    /// the number of rows added. Each top-level column of the file goes to
+    /// the column of the same name, cast to its type; a table that does not
+    /// exist is created with the types of the file's first non-NULL
+    /// values. Lists become lists; nested groups and maps are refused.
+    ///
+    /// Rows are checked as INSERT checks them, and the import adds all of
+    /// them or none.
+    pub fn import_parquet(
+        &mut self,
+        table: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<usize, EngineError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| parquet_error(path, e))?;
+        let reader = SerializedFileReader::new(file).map_err(|e| parquet_error(path, e))?;
+        let names = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema()
+            .get_fields()
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect::<Vec<_>>();
+        let records = reader
+            .get_row_iter(None)
+            .map_err(|e| parquet_error(path, e))?
+            .map(|row| {
+                let row = row.map_err(|e| parquet_error(path, e))?;
+                row.get_column_iter()
+                    .map(|(name, field)| {
+                        to_value(field).ok_or_else(|| {
+                            EngineError::InvalidOperation(format!(
+                                "nested Parquet column {} cannot be imported",
+                                name
+                            ))
+                        })
+                    })
+                    .collect::<Result<Row, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let created = !self.tables.contains_key(table);
+        if created {
+            let columns = names
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    let col_type = match infer_type(&records, idx) {
+                        ValueType::Null => ValueType::Text,
+                        t => t,
+                    };
+                    (name.clone(), col_type)
+                })
+                .collect();
+            self.create_table(table, columns)?;
+        }
+        let result = self
+            .parquet_rows(table, &names, records)
+            .and_then(|rows| self.insert_rows(table, rows));
+        if result.is_err() && created {
+            self.tables.remove(table);
+        }
+        result
+    }
+
+    /// The rows of `records`, whose values are in the order of `names`,
+    /// as rows of `table`.
+    fn parquet_rows(
+        &self,
+        name: &str,
+        names: &[String],
+        records: Vec<Row>,
+    ) -> Result<Vec<Row>, EngineError> {
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let positions = table.column_positions(names)?;
+        records
+            .into_iter()
+            .map(|record| {
+                let mut row = table
+                    .columns
+                    .iter()
+                    .map(|c| Value::TypedNull(c.col_type.clone()))
+                    .collect::<Row>();
+                for (&idx, value) in positions.iter().zip(record) {
+                    let value = match value {
+                        text @ Value::Text(_) => {
+                            text.cast(&table.columns[idx].col_type).unwrap_or(text)
+                        }
+                        value => value,
+                    };
+                    row[idx] = table.accept(idx, value)?;
+                }
+                Ok(row)
+            })
+            .collect()
+    }
+
+    /// Writes the rows of `source` to a Parquet file at `path`, replacing
+    /// any file there, and returns how many were written. `source` is the
+    /// name of a table or view, or a SELECT; the file's columns are named
+    /// after the result's.
+    pub fn export_parquet(
+        &self,
+        source: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<usize, EngineError> {
+        let path = path.as_ref();
+        let result = self.export_source(source)?;
+        let types = (0..result.columns.len())
+            .map(|idx| infer_type(&result.rows, idx))
+            .collect::<Vec<_>>();
+        let fields = result
+            .column_names()
+            .into_iter()
+            .zip(&types)
+            .map(|(name, col_type)| column_type(name, col_type).map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| parquet_error(path, e))?;
+        let schema = Type::group_type_builder("schema")
+            .with_fields(fields)
+            .build()
+            .map_err(|e| parquet_error(path, e))?;
+
+        // Cast every value first, so that a value of another type fails
+        // the export before the file is touched.
+        let columns = types
+            .iter()
+            .enumerate()
+            .map(|(idx, col_type)| {
+                result
+                    .rows
+                    .iter()
+                    .map(|row| match &row[idx] {
+                        v if v.is_null() => Ok(None),
+                        v => match col_type {
+                            ValueType::Int
+                            | ValueType::Float
+                            | ValueType::Bool
+                            | ValueType::Date
+                            | ValueType::Timestamp => v.cast(col_type).map(Some),
+                            _ => Ok(Some(match v.cast(&ValueType::Text) {
+                                Ok(text) => text,
+                                Err(_) => Value::Text(v.to_string()),
+                            })),
+                        },
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let file = File::create(path).map_err(|e| parquet_error(path, e))?;
+        let write = || -> Result<(), parquet::errors::ParquetError> {
+            let mut writer = SerializedFileWriter::new(file, Arc::new(schema), Default::default())?;
+            let mut group = writer.next_row_group()?;
+            for (col_type, values) in types.iter().zip(&columns) {
+                let mut column = group.next_column()?.expect("a writer for each column");
+                let values = values.iter();
+                match col_type {
+                    ValueType::Int | ValueType::Timestamp => write_column(
+                        column.typed::<Int64Type>(),
+                        values.map(|v| match v {
+                            Some(Value::Int(n) | Value::Timestamp(n)) => Some(*n),
+                            _ => None,
+                        }),
+                    )?,
+                    ValueType::Date => write_column(
+                        column.typed::<Int32Type>(),
+                        values.map(|v| match v {
+                            Some(Value::Date(d)) => Some(*d),
+                            _ => None,
+                        }),
+                    )?,
+                    ValueType::Float => write_column(
+                        column.typed::<DoubleType>(),
+                        values.map(|v| match v {
+                            Some(Value::Float(f)) => Some(*f),
+                            _ => None,
+                        }),
+                    )?,
+                    ValueType::Bool => write_column(
+                        column.typed::<BoolType>(),
+                        values.map(|v| match v {
+                            Some(Value::Bool(b)) => Some(*b),
+                            _ => None,
+                        }),
+                    )?,
+                    _ => write_column(
+                        column.typed::<ByteArrayType>(),
+                        values.map(|v| match v {
+                            Some(Value::Text(s)) => Some(ByteArray::from(s.as_str())),
+                            _ => None,
+                        }),
+                    )?,
+                }
+                column.close()?;
+            }
+            group.close()?;
+            writer.close()?;
+            Ok(())
+        };
+        write().map_err(|e| parquet_error(path, e))?;
+        Ok(result.rows.len())
+    }
+}
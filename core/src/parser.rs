@@ -1,16 +1,30 @@
+use std::fmt;
+
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case, take_while1},
-    character::complete::{char, digit1, multispace0, multispace1},
-    combinator::{map, map_res, opt},
-    multi::{separated_list0, separated_list1},
-    sequence::{delimited, preceded, separated_pair, tuple},
+    bytes::complete::{tag, tag_no_case, take_while, take_while1},
+    character::complete::{char, digit1, multispace0, multispace1, satisfy},
+    combinator::{all_consuming, map, map_opt, map_res, not, opt, peek, recognize, verify},
+    error::{Error, ErrorKind},
+    multi::{fold_many0, many0, many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::engine::Value;
+use crate::columnar::Layout;
+use crate::csv::CsvOptions;
+use crate::decimal::MAX_PRECISION;
+use crate::engine::{
+    EngineError, ForeignKey, ReferentialAction, UniqueConstraint, Value, ValueType,
+};
+use crate::index::IndexKind;
+use crate::partition::Partitioning;
+use crate::temporal::{self, Interval};
+use crate::ttl::Ttl;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Operator {
     Eq,
     Ne,
@@ -18,72 +32,1198 @@ pub enum Operator {
     Le,
     Gt,
     Ge,
+    Like,
+    NotLike,
+    Regexp,
+    NotRegexp,
+    /// `@>`: the left list contains every element of the right one.
+    Contains,
+    /// `<@`: every element of the left list is in the right one.
+    ContainedBy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// An aggregate call; `arg` is `None` for `COUNT(*)`, and `distinct`
+    /// is set for `COUNT(DISTINCT x)` and friends. Only valid in the select
+    /// list.
+    Aggregate {
+        func: AggregateFunc,
+        arg: Option<Box<Expr>>,
+        distinct: bool,
+    },
+    /// A scalar function call such as `COALESCE(a, b)`; the name is
+    /// resolved case-insensitively when the query is executed.
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// `CAST(expr AS type)`.
+    Cast {
+        expr: Box<Expr>,
+        target: ValueType,
+    },
+    /// `CASE WHEN cond THEN expr ... [ELSE expr] END`; a missing ELSE yields
+    /// NULL.
+    Case {
+        branches: Vec<(Condition, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+}
+
+impl Expr {
+    pub fn contains_aggregate(&self) -> bool {
+        match self {
+            Expr::Aggregate { .. } => true,
+            Expr::Binary { left, right, .. } => {
+                left.contains_aggregate() || right.contains_aggregate()
+            }
+            Expr::Function { args, .. } => args.iter().any(|a| a.contains_aggregate()),
+            Expr::Cast { expr, .. } => expr.contains_aggregate(),
+            Expr::Case {
+                branches,
+                otherwise,
+            } => {
+                branches.iter().any(|(_, e)| e.contains_aggregate())
+                    || otherwise.as_ref().is_some_and(|e| e.contains_aggregate())
+            }
+            Expr::Literal(_) | Expr::Column(_) => false,
+        }
+    }
+
+    /// The first column read outside an aggregate call, as written.
+    pub(crate) fn column_outside_aggregate(&self) -> Option<&str> {
+        match self {
+            Expr::Column(name) => Some(name),
+            Expr::Literal(_) | Expr::Aggregate { .. } => None,
+            Expr::Binary { left, right, .. } => left
+                .column_outside_aggregate()
+                .or_else(|| right.column_outside_aggregate()),
+            Expr::Function { args, .. } => args.iter().find_map(Expr::column_outside_aggregate),
+            Expr::Cast { expr, .. } => expr.column_outside_aggregate(),
+            Expr::Case {
+                branches,
+                otherwise,
+            } => branches
+                .iter()
+                .find_map(|(_, e)| e.column_outside_aggregate())
+                .or_else(|| otherwise.as_ref()?.column_outside_aggregate()),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+        };
+        f.write_str(s)
+    }
+}
+
+impl BinaryOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Sub => 1,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 2,
+        }
+    }
+}
+
+impl fmt::Display for AggregateFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AggregateFunc::Count => "COUNT",
+            AggregateFunc::Sum => "SUM",
+            AggregateFunc::Min => "MIN",
+            AggregateFunc::Max => "MAX",
+            AggregateFunc::Avg => "AVG",
+        };
+        f.write_str(s)
+    }
+}
+
+pub(crate) fn fmt_literal(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::Int(n) => write!(f, "{}", n),
+        // Small values are marked so that they do not read back as Int.
+        Value::BigInt(n) if i64::try_from(*n).is_ok() => write!(f, "HUGEINT '{}'", n),
+        Value::BigInt(n) => write!(f, "{}", n),
+        Value::Float(x) if x.is_finite() => write!(f, "{:?}", x),
+        Value::Float(x) => write!(f, "FLOAT '{}'", x),
+        Value::Decimal(d) => write!(f, "DECIMAL '{}'", d),
+        Value::Date(d) => write!(f, "DATE '{}'", temporal::format_date(*d)),
+        Value::Time(t) => write!(f, "TIME '{}'", temporal::format_time(*t)),
+        Value::Timestamp(ts) => write!(f, "TIMESTAMP '{}'", temporal::format_timestamp(*ts)),
+        Value::Interval(iv) => write!(f, "INTERVAL '{}'", iv),
+        Value::Uuid(u) => write!(f, "UUID '{}'", u),
+        Value::Json(j) => write!(f, "JSON '{}'", j.to_string().replace('\'', "''")),
+        Value::List(items) => {
+            f.write_str("ARRAY[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                fmt_literal(f, item)?;
+            }
+            f.write_str("]")
+        }
+        Value::Text(s) => write!(f, "'{}'", s.replace('\'', "''")),
+        Value::Enum(e) => write!(f, "'{}'", e.label().replace('\'', "''")),
+        Value::Custom(c) => write!(f, "'{}'", c.to_string().replace('\'', "''")),
+        Value::Bool(b) => f.write_str(if *b { "TRUE" } else { "FALSE" }),
+        Value::Null | Value::TypedNull(_) => f.write_str("NULL"),
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Operator::Eq => "=",
+            Operator::Ne => "<>",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Like => "LIKE",
+            Operator::NotLike => "NOT LIKE",
+            Operator::Regexp => "REGEXP",
+            Operator::Contains => "@>",
+            Operator::ContainedBy => "<@",
+            Operator::NotRegexp => "NOT REGEXP",
+        };
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Compare { left, op, right } => write!(f, "{} {} {}", left, op, right),
+            Condition::Between { expr, low, high } => {
+                write!(f, "{} BETWEEN {} AND {}", expr, low, high)
+            }
+            Condition::InList { expr, values } => {
+                write!(f, "{} IN (", expr)?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                f.write_str(")")
+            }
+            Condition::InSubquery { expr, subquery } => write!(f, "{} IN ({})", expr, subquery),
+            Condition::Any { expr, op, list } => write!(f, "{} {} ANY ({})", expr, op, list),
+            Condition::IsNull(expr) => write!(f, "{} IS NULL", expr),
+            Condition::Not(c) => write!(f, "NOT ({})", c),
+            Condition::And(a, b) => write!(f, "({} AND {})", a, b),
+            Condition::Or(a, b) => write!(f, "({} OR {})", a, b),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(value) => fmt_literal(f, value),
+            Expr::Column(name) => f.write_str(name),
+            Expr::Binary { op, left, right } => {
+                // Operands that bind more loosely than `op` are wrapped,
+                // so that the text reads back as the same tree.
+                let wrap = |e: &Expr, right_side: bool| match e {
+                    Expr::Binary { op: inner, .. } => {
+                        inner.precedence() < op.precedence()
+                            || (right_side && inner.precedence() == op.precedence())
+                    }
+                    _ => false,
+                };
+                for (e, right_side) in [(left, false), (right, true)] {
+                    if right_side {
+                        write!(f, " {} ", op)?;
+                    }
+                    if wrap(e, right_side) {
+                        write!(f, "({})", e)?;
+                    } else {
+                        write!(f, "{}", e)?;
+                    }
+                }
+                Ok(())
+            }
+            Expr::Aggregate {
+                func, arg: None, ..
+            } => write!(f, "{}(*)", func),
+            Expr::Aggregate {
+                func,
+                arg: Some(arg),
+                distinct,
+            } => {
+                let distinct = if *distinct { "DISTINCT " } else { "" };
+                write!(f, "{}({}{})", func, distinct, arg)
+            }
+            Expr::Function { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                f.write_str(")")
+            }
+            Expr::Cast { expr, target } => write!(f, "CAST({} AS {})", expr, target),
+            Expr::Case {
+                branches,
+                otherwise,
+            } => {
+                f.write_str("CASE")?;
+                for (cond, result) in branches {
+                    write!(f, " WHEN {} THEN {}", cond, result)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, " ELSE {}", otherwise)?;
+                }
+                f.write_str(" END")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    Compare {
+        left: Expr,
+        op: Operator,
+        right: Expr,
+    },
+    /// `expr BETWEEN low AND high`, inclusive on both ends.
+    Between {
+        expr: Expr,
+        low: Expr,
+        high: Expr,
+    },
+    /// `expr IN (e1, e2, ...)`.
+    InList {
+        expr: Expr,
+        values: Vec<Expr>,
+    },
+    /// `expr IN (SELECT ...)`; the subquery must return a single column.
+    InSubquery {
+        expr: Expr,
+        subquery: Box<SelectQuery>,
+    },
+    /// `expr op ANY (list)`: true when the comparison holds for some
+    /// element of the list.
+    Any {
+        expr: Expr,
+        op: Operator,
+        list: Expr,
+    },
+    /// `expr IS NULL`, which is never UNKNOWN; `expr IS NOT NULL` is its
+    /// negation.
+    IsNull(Expr),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AggregateFunc {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectItem {
+    pub expr: Expr,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableRef {
+    pub name: String,
+    pub alias: Option<String>,
+    pub hint: Option<IndexHint>,
+}
+
+/// Which indexes the planner may read a table through, overriding its own
+/// choice: `USE INDEX (name, ...)` or `IGNORE INDEX (name, ...)` after the
+/// table in FROM.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IndexHint {
+    /// Only these indexes; with none listed, the table is scanned.
+    Use(Vec<String>),
+    /// Any index but these.
+    Ignore(Vec<String>),
+}
+
+impl IndexHint {
+    /// The indexes the hint names.
+    pub fn indexes(&self) -> &[String] {
+        match self {
+            IndexHint::Use(names) | IndexHint::Ignore(names) => names,
+        }
+    }
+
+    /// Whether the planner may use the index `name`.
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            IndexHint::Use(names) => names.iter().any(|n| n == name),
+            IndexHint::Ignore(names) => !names.iter().any(|n| n == name),
+        }
+    }
+}
+
+impl fmt::Display for IndexHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self {
+            IndexHint::Use(_) => "USE",
+            IndexHint::Ignore(_) => "IGNORE",
+        };
+        write!(f, "{} INDEX ({})", verb, self.indexes().join(", "))
+    }
+}
+
+impl TableRef {
+    /// The name columns of this table are qualified with in the query.
+    pub fn qualifier(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Condition {
-    pub column: String,
-    pub op: Operator,
-    pub value: Value,
+/// A common table expression: `name AS (SELECT ...)` in a WITH clause.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cte {
+    pub name: String,
+    pub query: SelectQuery,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SelectQuery {
-    pub table: String,
-    pub columns: Vec<String>,
+    pub with: Vec<Cte>,
+    pub tables: Vec<TableRef>,
+    pub columns: Vec<SelectItem>,
     pub condition: Option<Condition>,
     pub order_by: Option<(String, bool)>,
     pub limit: Option<usize>,
     pub offset: Option<usize>,
 }
 
-#[derive(Debug, PartialEq)]
+/// The query as SQL that parses back to an equal query.
+impl fmt::Display for SelectQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, cte) in self.with.iter().enumerate() {
+            f.write_str(if i == 0 { "WITH " } else { ", " })?;
+            write!(f, "{} AS ({})", cte.name, cte.query)?;
+        }
+        if !self.with.is_empty() {
+            f.write_str(" ")?;
+        }
+        f.write_str("SELECT ")?;
+        if self.columns.is_empty() {
+            f.write_str("*")?;
+        }
+        for (i, item) in self.columns.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", item.expr)?;
+            if let Some(alias) = &item.alias {
+                write!(f, " AS {}", alias)?;
+            }
+        }
+        f.write_str(" FROM ")?;
+        for (i, table) in self.tables.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(&table.name)?;
+            if let Some(alias) = &table.alias {
+                write!(f, " AS {}", alias)?;
+            }
+            if let Some(hint) = &table.hint {
+                write!(f, " {}", hint)?;
+            }
+        }
+        if let Some(cond) = &self.condition {
+            write!(f, " WHERE {}", cond)?;
+        }
+        if let Some((column, ascending)) = &self.order_by {
+            let dir = if *ascending { "ASC" } else { "DESC" };
+            write!(f, " ORDER BY {} {}", column, dir)?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " OFFSET {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl SelectQuery {
+    /// Calls `f` on every table reference in the query, including those in
+    /// CTEs and subqueries.
+    pub(crate) fn visit_tables(&mut self, f: &mut dyn FnMut(&mut TableRef)) {
+        for cte in &mut self.with {
+            cte.query.visit_tables(f);
+        }
+        self.tables.iter_mut().for_each(&mut *f);
+        for item in &mut self.columns {
+            item.expr.visit_tables(f);
+        }
+        if let Some(cond) = &mut self.condition {
+            cond.visit_tables(f);
+        }
+    }
+
+    /// Calls `f` on every literal value in the query, in the order they
+    /// are written.
+    pub(crate) fn visit_literals(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        for cte in &mut self.with {
+            cte.query.visit_literals(f);
+        }
+        for item in &mut self.columns {
+            item.expr.visit_literals(f);
+        }
+        if let Some(cond) = &mut self.condition {
+            cond.visit_literals(f);
+        }
+    }
+
+    /// Names of every table the query reads.
+    pub(crate) fn table_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.clone()
+            .visit_tables(&mut |t| names.push(t.name.clone()));
+        names
+    }
+
+    /// Names of every column the query reads from the tables in its FROM
+    /// list, as written, qualified or not; CTEs and subqueries read their
+    /// own tables and are left out.
+    pub(crate) fn column_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut query = self.clone();
+        for item in &mut query.columns {
+            item.expr
+                .visit_columns(&mut |name| names.push(name.clone()));
+        }
+        if let Some(cond) = &mut query.condition {
+            cond.visit_columns(&mut |name| names.push(name.clone()));
+        }
+        names.extend(self.order_by.iter().map(|(name, _)| name.clone()));
+        names
+    }
+
+    /// Points every reference to table `from` at `to`. References without
+    /// an alias get `from` as one, so that columns qualified with the old
+    /// name still resolve.
+    pub(crate) fn rename_table(&mut self, from: &str, to: &str) {
+        self.visit_tables(&mut |table| {
+            if table.name == from {
+                table.alias.get_or_insert_with(|| from.to_string());
+                table.name = to.to_string();
+            }
+        });
+    }
+}
+
+impl Expr {
+    fn visit_literals(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        match self {
+            Expr::Literal(value) => f(value),
+            Expr::Binary { left, right, .. } => {
+                left.visit_literals(f);
+                right.visit_literals(f);
+            }
+            Expr::Aggregate { arg, .. } => {
+                if let Some(arg) = arg {
+                    arg.visit_literals(f);
+                }
+            }
+            Expr::Function { args, .. } => args.iter_mut().for_each(|a| a.visit_literals(f)),
+            Expr::Cast { expr, .. } => expr.visit_literals(f),
+            Expr::Case {
+                branches,
+                otherwise,
+            } => {
+                for (cond, expr) in branches {
+                    cond.visit_literals(f);
+                    expr.visit_literals(f);
+                }
+                if let Some(e) = otherwise {
+                    e.visit_literals(f);
+                }
+            }
+            Expr::Column(_) => {}
+        }
+    }
+
+    fn visit_columns(&mut self, f: &mut dyn FnMut(&mut String)) {
+        match self {
+            Expr::Column(name) => f(name),
+            Expr::Binary { left, right, .. } => {
+                left.visit_columns(f);
+                right.visit_columns(f);
+            }
+            Expr::Aggregate { arg, .. } => {
+                if let Some(arg) = arg {
+                    arg.visit_columns(f);
+                }
+            }
+            Expr::Function { args, .. } => args.iter_mut().for_each(|a| a.visit_columns(f)),
+            Expr::Cast { expr, .. } => expr.visit_columns(f),
+            Expr::Case {
+                branches,
+                otherwise,
+            } => {
+                for (cond, expr) in branches {
+                    cond.visit_columns(f);
+                    expr.visit_columns(f);
+                }
+                if let Some(e) = otherwise {
+                    e.visit_columns(f);
+                }
+            }
+            Expr::Literal(_) => {}
+        }
+    }
+
+    pub(crate) fn visit_tables(&mut self, f: &mut dyn FnMut(&mut TableRef)) {
+        match self {
+            Expr::Binary { left, right, .. } => {
+                left.visit_tables(f);
+                right.visit_tables(f);
+            }
+            Expr::Aggregate { arg, .. } => {
+                if let Some(arg) = arg {
+                    arg.visit_tables(f);
+                }
+            }
+            Expr::Function { args, .. } => args.iter_mut().for_each(|a| a.visit_tables(f)),
+            Expr::Cast { expr, .. } => expr.visit_tables(f),
+            Expr::Case {
+                branches,
+                otherwise,
+            } => {
+                for (cond, expr) in branches {
+                    cond.visit_tables(f);
+                    expr.visit_tables(f);
+                }
+                if let Some(e) = otherwise {
+                    e.visit_tables(f);
+                }
+            }
+            Expr::Literal(_) | Expr::Column(_) => {}
+        }
+    }
+}
+
+impl Condition {
+    fn visit_literals(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        match self {
+            Condition::Compare { left, right, .. } => {
+                left.visit_literals(f);
+                right.visit_literals(f);
+            }
+            Condition::Between { expr, low, high } => {
+                for e in [expr, low, high] {
+                    e.visit_literals(f);
+                }
+            }
+            Condition::InList { expr, values } => {
+                expr.visit_literals(f);
+                values.iter_mut().for_each(|v| v.visit_literals(f));
+            }
+            Condition::InSubquery { expr, subquery } => {
+                expr.visit_literals(f);
+                subquery.visit_literals(f);
+            }
+            Condition::Any { expr, list, .. } => {
+                expr.visit_literals(f);
+                list.visit_literals(f);
+            }
+            Condition::IsNull(expr) => expr.visit_literals(f),
+            Condition::Not(c) => c.visit_literals(f),
+            Condition::And(a, b) | Condition::Or(a, b) => {
+                a.visit_literals(f);
+                b.visit_literals(f);
+            }
+        }
+    }
+
+    /// Names of every column the condition reads, as written, leaving out
+    /// those read inside an IN subquery.
+    pub(crate) fn column_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.clone()
+            .visit_columns(&mut |name| names.push(name.clone()));
+        names
+    }
+
+    /// Calls `f` on every column the condition reads, leaving out those
+    /// read inside an IN subquery.
+    fn visit_columns(&mut self, f: &mut dyn FnMut(&mut String)) {
+        match self {
+            Condition::Compare { left, right, .. } => {
+                left.visit_columns(f);
+                right.visit_columns(f);
+            }
+            Condition::Between { expr, low, high } => {
+                for e in [expr, low, high] {
+                    e.visit_columns(f);
+                }
+            }
+            Condition::InList { expr, values } => {
+                expr.visit_columns(f);
+                values.iter_mut().for_each(|v| v.visit_columns(f));
+            }
+            Condition::InSubquery { expr, .. } => expr.visit_columns(f),
+            Condition::Any { expr, list, .. } => {
+                expr.visit_columns(f);
+                list.visit_columns(f);
+            }
+            Condition::IsNull(expr) => expr.visit_columns(f),
+            Condition::Not(c) => c.visit_columns(f),
+            Condition::And(a, b) | Condition::Or(a, b) => {
+                a.visit_columns(f);
+                b.visit_columns(f);
+            }
+        }
+    }
+
+    pub(crate) fn visit_tables(&mut self, f: &mut dyn FnMut(&mut TableRef)) {
+        match self {
+            Condition::Compare { left, right, .. } => {
+                left.visit_tables(f);
+                right.visit_tables(f);
+            }
+            Condition::Between { expr, low, high } => {
+                for e in [expr, low, high] {
+                    e.visit_tables(f);
+                }
+            }
+            Condition::InList { expr, values } => {
+                expr.visit_tables(f);
+                values.iter_mut().for_each(|v| v.visit_tables(f));
+            }
+            Condition::InSubquery { expr, subquery } => {
+                expr.visit_tables(f);
+                subquery.visit_tables(f);
+            }
+            Condition::Any { expr, list, .. } => {
+                expr.visit_tables(f);
+                list.visit_tables(f);
+            }
+            Condition::IsNull(expr) => expr.visit_tables(f),
+            Condition::Not(c) => c.visit_tables(f),
+            Condition::And(a, b) | Condition::Or(a, b) => {
+                a.visit_tables(f);
+                b.visit_tables(f);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct InsertQuery {
     pub table: String,
     pub columns: Option<Vec<String>>,
-    pub values: Vec<Value>,
+    /// Expressions over no columns, such as literals and `UUID()`, worked
+    /// out each time the statement runs.
+    pub values: Vec<Expr>,
+}
+
+/// `DELETE FROM table [WHERE condition]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteQuery {
+    pub table: String,
+    pub condition: Option<Condition>,
+}
+
+/// `UPDATE table SET col = expr, ... [WHERE condition]`; each expression
+/// sees the row as it was before the update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateQuery {
+    pub table: String,
+    pub assignments: Vec<(String, Expr)>,
+    pub condition: Option<Condition>,
+}
+
+/// `ALTER TABLE table ...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterTableQuery {
+    pub table: String,
+    pub action: AlterTableAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableAction {
+    /// `RENAME TO new_name`.
+    RenameTo(String),
+    /// `ADD [CONSTRAINT name] FOREIGN KEY (column, ...) REFERENCES ...`,
+    /// named as CREATE TABLE names it if it has no name.
+    AddForeignKey(ForeignKey),
+}
+
+/// `CREATE [OR REPLACE] TABLE [IF NOT EXISTS] name (column type
+/// [constraint ...], ..., [table constraint, ...]) [WITH (option = value,
+/// ...)] [PARTITION BY ...]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTableQuery {
+    pub name: String,
+    pub columns: Vec<(String, ValueType)>,
+    /// `PRIMARY KEY (column, ...)` among the columns, or `PRIMARY KEY`
+    /// after one.
+    pub primary_key: Option<Vec<String>>,
+    /// `[CONSTRAINT name] UNIQUE (column, ...)` among the columns, or
+    /// `UNIQUE` after one. Unnamed constraints are named after the table
+    /// and columns joined by `_`, then `_key`.
+    pub uniques: Vec<UniqueConstraint>,
+    /// `[CONSTRAINT name] FOREIGN KEY (column, ...) REFERENCES table
+    /// [(column, ...)] [ON DELETE action] [ON UPDATE action]` among the
+    /// columns, or `REFERENCES ...` after one, where an action is RESTRICT,
+    /// NO ACTION, CASCADE or SET NULL. A key that names no referenced
+    /// columns has no `ref_columns` and references the primary key.
+    /// Unnamed keys are named after the table and columns joined by `_`,
+    /// then `_fkey`.
+    pub foreign_keys: Vec<ForeignKey>,
+    /// `AUTO_INCREMENT` after a column.
+    pub auto_increment: Option<String>,
+    /// Leave an existing table of the same name alone instead of failing.
+    pub if_not_exists: bool,
+    /// Replace an existing table of the same name, dropping its rows.
+    pub or_replace: bool,
+    /// `layout = 'row' | 'column'`.
+    pub layout: Layout,
+    /// `compression = 'on' | 'off'`.
+    pub compression: bool,
+    /// `overflow = 'bytes' | 'off'`.
+    pub overflow: Option<usize>,
+    /// `ttl = 'interval'`, with `ttl_column = 'column'` to count from a
+    /// column rather than the insert time.
+    pub ttl: Option<Ttl>,
+    /// `PARTITION BY RANGE (column) (PARTITION name VALUES LESS THAN
+    /// (value | MAXVALUE), ...)` or `PARTITION BY HASH (column) PARTITIONS
+    /// n`.
+    pub partitioning: Option<Partitioning>,
+}
+
+/// `CREATE [UNIQUE] INDEX [name] ON table (column, ...) [USING BTREE |
+/// HASH | TRIGRAM]`. A B-tree index is ordered; it is the default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndexQuery {
+    pub unique: bool,
+    /// Defaults to the table and column names joined by `_`, then `_idx`.
+    pub name: Option<String>,
+    pub table: String,
+    /// The columns to index, expressions over them such as `LOWER(name)`,
+    /// or `column(n)` for the first `n` characters of a text column.
+    pub columns: Vec<Expr>,
+    pub kind: IndexKind,
+}
+
+/// `CREATE [MATERIALIZED] VIEW name AS SELECT ...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateViewQuery {
+    pub name: String,
+    pub query: SelectQuery,
+    pub materialized: bool,
+}
+
+/// `COMMENT ON TABLE table IS 'text'` or `COMMENT ON COLUMN table.column
+/// IS 'text'`; `IS NULL` removes the comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentQuery {
+    pub table: String,
+    /// The column commented on, or `None` for the table itself.
+    pub column: Option<String>,
+    pub text: Option<String>,
+}
+
+/// `PRAGMA name[(table)] [= value]`: reads an engine setting, or changes it
+/// when a value is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PragmaQuery {
+    pub name: String,
+    pub table: Option<String>,
+    /// The new setting; a bare word such as `STRICT` is read as text.
+    pub value: Option<Value>,
+}
+
+/// `COPY table FROM 'path'` or `COPY {table | (SELECT ...)} TO 'path'`,
+/// either followed by `WITH (option, ...)`: `HEADER [TRUE | FALSE]`,
+/// `DELIMITER 'c'` or `NULL 'text'`. Paths are opened by the engine's
+/// process.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyQuery {
+    From {
+        table: String,
+        path: String,
+        options: CsvOptions,
+    },
+    To {
+        /// A table or view name, or the text of a SELECT.
+        source: String,
+        path: String,
+        options: CsvOptions,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
 pub enum Query {
     Select(SelectQuery),
     Insert(InsertQuery),
+    Delete(DeleteQuery),
+    Update(UpdateQuery),
+    AlterTable(AlterTableQuery),
+    CreateTable(CreateTableQuery),
+    CreateIndex(CreateIndexQuery),
+    CreateView(CreateViewQuery),
+    /// `DROP [MATERIALIZED] VIEW name`.
+    DropView(String),
+    /// `REFRESH MATERIALIZED VIEW name`.
+    RefreshView(String),
+    Comment(CommentQuery),
+    /// `CREATE SCHEMA name`.
+    CreateSchema(String),
+    /// `DROP SCHEMA name [CASCADE]`; without CASCADE the schema must be
+    /// empty.
+    DropSchema {
+        name: String,
+        cascade: bool,
+    },
+    /// `ANALYZE [table]`; without a table, every table is analyzed.
+    Analyze(Option<String>),
+    /// `EXPLAIN SELECT ...`, describing how each table would be read
+    /// instead of running the query.
+    Explain(SelectQuery),
+    /// `REINDEX [name]`, rebuilding the indexes of a table, or the indexes
+    /// of that name; without a name, every index is rebuilt.
+    Reindex(Option<String>),
+    /// `VACUUM [table]`, reclaiming the space of a table, or of every
+    /// table; see [`crate::Engine::vacuum`]. Returns one row holding the
+    /// expired rows deleted and the bytes freed in memory and on disk.
+    Vacuum(Option<String>),
+    Pragma(PragmaQuery),
+    Copy(CopyQuery),
+    /// `BEGIN [TRANSACTION | WORK]` or `START TRANSACTION`.
+    Begin,
+    /// `COMMIT [TRANSACTION | WORK]`.
+    Commit,
+    /// `ROLLBACK [TRANSACTION | WORK]`.
+    Rollback,
+    /// `SAVEPOINT name`.
+    Savepoint(String),
+    /// `ROLLBACK [TRANSACTION | WORK] TO [SAVEPOINT] name`.
+    RollbackTo(String),
+    /// `RELEASE [SAVEPOINT] name`.
+    Release(String),
 }
 
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "ORDER", "BY", "ASC", "DESC", "LIMIT", "OFFSET", "CROSS", "JOIN",
+    "AS", "INSERT", "INTO", "VALUES", "IN", "WITH", "BETWEEN", "AND", "OR", "NOT", "LIKE",
+    "REGEXP", "CASE", "WHEN", "THEN", "ELSE", "END", "NULL", "CAST", "INTERVAL", "DISTINCT", "ANY",
+    "ARRAY",
+];
+
 fn identifier(i: &str) -> IResult<&str, &str> {
     take_while1(|c: char| c.is_alphanumeric() || c == '_')(i)
 }
 
+/// An identifier that is not a reserved keyword, so that an optional alias
+/// does not swallow the clause that follows it.
+fn alias_name(i: &str) -> IResult<&str, &str> {
+    verify(identifier, |s: &str| {
+        !KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(s))
+    })(i)
+}
+
+fn parse_alias(i: &str) -> IResult<&str, String> {
+    map(
+        preceded(
+            multispace1,
+            alt((
+                preceded(pair(tag_no_case("AS"), multispace1), identifier),
+                alias_name,
+            )),
+        ),
+        |s: &str| s.to_string(),
+    )(i)
+}
+
+/// A table name, qualified with its schema or not: `orders` or
+/// `sales.orders`.
+fn table_name(i: &str) -> IResult<&str, &str> {
+    recognize(pair(identifier, opt(pair(char('.'), identifier))))(i)
+}
+
+/// A possibly qualified column reference such as `id`, `users.id` or
+/// `app.users.id`.
+fn column_ref(i: &str) -> IResult<&str, &str> {
+    recognize(pair(table_name, opt(pair(char('.'), identifier))))(i)
+}
+
 fn parse_operator(i: &str) -> IResult<&str, Operator> {
     alt((
+        map(tag("@>"), |_| Operator::Contains),
+        map(tag("<@"), |_| Operator::ContainedBy),
         map(tag("<="), |_| Operator::Le),
         map(tag(">="), |_| Operator::Ge),
         map(tag("<>"), |_| Operator::Ne),
         map(tag("="), |_| Operator::Eq),
         map(tag("<"), |_| Operator::Lt),
         map(tag(">"), |_| Operator::Gt),
+        map(tag("!~"), |_| Operator::NotRegexp),
+        map(tag("~"), |_| Operator::Regexp),
+        map(tag_no_case("LIKE"), |_| Operator::Like),
+        map(tag_no_case("REGEXP"), |_| Operator::Regexp),
+        map(
+            tuple((tag_no_case("NOT"), multispace1, tag_no_case("LIKE"))),
+            |_| Operator::NotLike,
+        ),
+        map(
+            tuple((tag_no_case("NOT"), multispace1, tag_no_case("REGEXP"))),
+            |_| Operator::NotRegexp,
+        ),
     ))(i)
 }
 
+/// Parses an integer literal with an optional sign, `_` digit separators,
+/// and an optional `0x` hex prefix. A separator must come before a digit,
+/// so `1_` and `1__0` are a hard `Digit` failure, and literals beyond 128
+/// bits a hard `TooLarge` failure, rather than recoverable mismatches; see
+/// [`statement_error`].
+fn parse_int128(i: &str) -> IResult<&str, i128> {
+    let (rest, sign) = opt(alt((char('-'), char('+'))))(i)?;
+    let (rest, (radix, digits)) = alt((
+        map(
+            preceded(
+                tag_no_case("0x"),
+                take_while1(|c: char| c.is_ascii_hexdigit() || c == '_'),
+            ),
+            |d| (16, d),
+        ),
+        map(
+            recognize(pair(
+                digit1,
+                take_while(|c: char| c.is_ascii_digit() || c == '_'),
+            )),
+            |d| (10, d),
+        ),
+    ))(rest)?;
+    if digits.ends_with('_') || digits.contains("__") {
+        return Err(nom::Err::Failure(Error::new(i, ErrorKind::Digit)));
+    }
+    let mut signed = String::from(if sign == Some('-') { "-" } else { "" });
+    signed.extend(digits.chars().filter(|&c| c != '_'));
+    let value = i128::from_str_radix(&signed, radix)
+        .map_err(|_| nom::Err::Failure(Error::new(i, ErrorKind::TooLarge)))?;
+    Ok((rest, value))
+}
+
+/// The error for `sql`, which failed to parse with `e`: an integer literal
+/// out of range is [`EngineError::LiteralOutOfRange`], anything else
+/// cannot be parsed.
+pub(crate) fn statement_error(sql: &str, e: nom::Err<Error<&str>>) -> EngineError {
+    match e {
+        nom::Err::Failure(e) if e.code == ErrorKind::TooLarge => {
+            let sign = usize::from(e.input.starts_with(['-', '+']));
+            let end = e.input[sign..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .map_or(e.input.len(), |end| end + sign);
+            EngineError::LiteralOutOfRange(e.input[..end].to_string())
+        }
+        _ => EngineError::InvalidQuery(format!("cannot parse {}", sql)),
+    }
+}
+
+/// Like [`parse_int128`], for literals that must fit in an `i64`.
+fn parse_int(i: &str) -> IResult<&str, i64> {
+    let (rest, value) = parse_int128(i)?;
+    let value =
+        i64::try_from(value).map_err(|_| nom::Err::Failure(Error::new(i, ErrorKind::TooLarge)))?;
+    Ok((rest, value))
+}
+
+/// Parses a single-quoted string literal. A doubled quote (`''`) stands for
+/// one quote; with `backslash_escapes` (the `E'...'` form) `\n`, `\t`, `\r`,
+/// `\0`, `\\` and `\'` are recognized as well.
+fn parse_string(i: &str, backslash_escapes: bool) -> IResult<&str, String> {
+    let (mut rest, _) = char('\'')(i)?;
+    let mut out = String::new();
+    loop {
+        let mut chars = rest.chars();
+        match chars.next() {
+            None => return Err(nom::Err::Error(Error::new(rest, ErrorKind::Char))),
+            Some('\'') => {
+                if chars.as_str().starts_with('\'') {
+                    out.push('\'');
+                    rest = &chars.as_str()[1..];
+                } else {
+                    return Ok((chars.as_str(), out));
+                }
+            }
+            Some('\\') if backslash_escapes => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some(c @ ('\\' | '\'')) => c,
+                    _ => return Err(nom::Err::Error(Error::new(rest, ErrorKind::Escaped))),
+                };
+                out.push(escaped);
+                rest = chars.as_str();
+            }
+            Some(c) => {
+                out.push(c);
+                rest = chars.as_str();
+            }
+        }
+    }
+}
+
+/// Parses a floating-point literal: digits with a fractional part, an
+/// exponent, or both (`1.5`, `-2.5e-3`, `1e6`).
+fn parse_float(i: &str) -> IResult<&str, f64> {
+    fn exponent(i: &str) -> IResult<&str, &str> {
+        recognize(tuple((
+            alt((char('e'), char('E'))),
+            opt(alt((char('-'), char('+')))),
+            digit1,
+        )))(i)
+    }
+    let (rest, literal) = recognize(tuple((
+        opt(alt((char('-'), char('+')))),
+        digit1,
+        alt((
+            recognize(tuple((char('.'), digit1, opt(exponent)))),
+            exponent,
+        )),
+    )))(i)?;
+    match literal.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok((rest, value)),
+        _ => Err(nom::Err::Failure(Error::new(i, ErrorKind::TooLarge))),
+    }
+}
+
+/// Parses text holding exactly one literal, surrounding whitespace aside.
+pub(crate) fn parse_literal(i: &str) -> Option<Value> {
+    all_consuming(delimited(multispace0, parse_value, multispace0))(i)
+        .ok()
+        .map(|(_, v)| v)
+}
+
+/// Parses a typed literal such as `DATE '2024-01-01'`: a type keyword
+/// followed by a string that must spell a valid value of that type.
+fn typed_literal<'a>(
+    keyword: &'static str,
+    convert: impl Fn(&str) -> Option<Value>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Value> {
+    move |i| {
+        let (rest, text) = preceded(pair(tag_no_case(keyword), multispace0), |i| {
+            parse_string(i, false)
+        })(i)?;
+        match convert(&text) {
+            Some(value) => Ok((rest, value)),
+            None => Err(nom::Err::Failure(Error::new(i, ErrorKind::Verify))),
+        }
+    }
+}
+
 fn parse_value(i: &str) -> IResult<&str, Value> {
-    let parse_int = map_res(digit1, |s: &str| s.parse::<i64>().map(Value::Int));
+    let parse_typed = alt((
+        typed_literal("DECIMAL", |s| s.parse().ok().map(Value::Decimal)),
+        typed_literal("DATE", |s| temporal::parse_date(s).map(Value::Date)),
+        typed_literal("TIMESTAMP", |s| {
+            temporal::parse_timestamp(s).map(Value::Timestamp)
+        }),
+        typed_literal("TIME", |s| temporal::parse_time(s).map(Value::Time)),
+        typed_literal("UUID", |s| Uuid::parse_str(s).ok().map(Value::Uuid)),
+        typed_literal("JSON", |s| serde_json::from_str(s).ok().map(Value::Json)),
+        typed_literal("HUGEINT", |s| s.parse().ok().map(Value::BigInt)),
+        typed_literal("FLOAT", |s| s.parse().ok().map(Value::Float)),
+    ));
+    let parse_float = map(parse_float, Value::Float);
+    let parse_int = map(parse_int128, |n| {
+        i64::try_from(n).map_or(Value::BigInt(n), Value::Int)
+    });
     let parse_string = map(
-        delimited(char('\''), take_while1(|c| c != '\''), char('\'')),
-        |s: &str| Value::Text(s.to_string()),
+        alt(
+            (preceded(tag_no_case("E"), |i| parse_string(i, true)), |i| {
+                parse_string(i, false)
+            }),
+        ),
+        Value::Text,
     );
-    let parse_bool = alt((
-        map(tag_no_case("TRUE"), |_| Value::Bool(true)),
-        map(tag_no_case("FALSE"), |_| Value::Bool(false)),
-    ));
-    alt((parse_int, parse_string, parse_bool))(i)
+    let parse_keyword = terminated(
+        alt((
+            map(tag_no_case("TRUE"), |_| Value::Bool(true)),
+            map(tag_no_case("FALSE"), |_| Value::Bool(false)),
+            map(tag_no_case("NULL"), |_| Value::Null),
+        )),
+        not(peek(satisfy(|c: char| c.is_alphanumeric() || c == '_'))),
+    );
+    // `ARRAY[a, b]`, or `[a, b]` without the keyword.
+    let parse_list = map(
+        preceded(
+            opt(pair(tag_no_case("ARRAY"), multispace0)),
+            delimited(
+                char('['),
+                separated_list0(
+                    preceded(multispace0, char(',')),
+                    preceded(multispace0, parse_value),
+                ),
+                preceded(multispace0, char(']')),
+            ),
+        ),
+        Value::List,
+    );
+    alt((
+        parse_float,
+        parse_int,
+        parse_string,
+        parse_typed,
+        parse_interval,
+        parse_list,
+        parse_keyword,
+    ))(i)
 }
 
-fn parse_values(i: &str) -> IResult<&str, Vec<Value>> {
+fn parse_values(i: &str) -> IResult<&str, Vec<Expr>> {
     delimited(
         char('('),
         separated_list0(
             preceded(multispace0, char(',')),
-            preceded(multispace0, parse_value),
+            preceded(multispace0, parse_expr),
         ),
         char(')'),
     )(i)
@@ -101,112 +1241,1585 @@ fn parse_column_names(i: &str) -> IResult<&str, Vec<String>> {
     .map(|(i, cols)| (i, cols.into_iter().map(|s| s.to_string()).collect()))
 }
 
-fn parse_condition(i: &str) -> IResult<&str, Condition> {
+/// Parses `expr [NOT] IN (...)`; `NOT IN` is the negation of `IN`, so it is
+/// UNKNOWN wherever `IN` is.
+fn parse_in(i: &str) -> IResult<&str, Condition> {
+    let (i, expr) = parse_expr(i)?;
+    let (i, negated) = opt(preceded(multispace1, tag_no_case("NOT")))(i)?;
+    let (i, _) = preceded(multispace1, tag_no_case("IN"))(i)?;
+    let (i, _) = multispace0(i)?;
+    let negate = |c| match negated {
+        Some(_) => Condition::Not(Box::new(c)),
+        None => c,
+    };
+    if let Ok((i, subquery)) = delimited(
+        char('('),
+        delimited(multispace0, parse_select, multispace0),
+        char(')'),
+    )(i)
+    {
+        let subquery = Box::new(subquery);
+        return Ok((i, negate(Condition::InSubquery { expr, subquery })));
+    }
+    let (i, values) = delimited(
+        char('('),
+        separated_list1(
+            preceded(multispace0, char(',')),
+            preceded(multispace0, parse_expr),
+        ),
+        preceded(multispace0, char(')')),
+    )(i)?;
+    Ok((i, negate(Condition::InList { expr, values })))
+}
+
+fn parse_between(i: &str) -> IResult<&str, Condition> {
     map(
         tuple((
-            identifier,
-            preceded(multispace0, parse_operator),
-            preceded(multispace0, parse_value),
+            parse_expr,
+            preceded(multispace1, tag_no_case("BETWEEN")),
+            preceded(multispace1, parse_expr),
+            preceded(multispace1, tag_no_case("AND")),
+            preceded(multispace1, parse_expr),
         )),
-        |(col, op, val)| Condition {
-            column: col.to_string(),
-            op,
-            value: val,
-        },
+        |(expr, _, low, _, high)| Condition::Between { expr, low, high },
     )(i)
 }
 
-fn parse_columns(i: &str) -> IResult<&str, Vec<String>> {
+/// Parses `expr IS [NOT] NULL`.
+fn parse_is_null(i: &str) -> IResult<&str, Condition> {
+    let (i, expr) = parse_expr(i)?;
+    let (i, _) = tuple((multispace1, tag_no_case("IS"), multispace1))(i)?;
+    let (i, negated) = opt(terminated(tag_no_case("NOT"), multispace1))(i)?;
+    let (i, _) = terminated(
+        tag_no_case("NULL"),
+        not(peek(satisfy(|c: char| c.is_alphanumeric() || c == '_'))),
+    )(i)?;
+    let cond = Condition::IsNull(expr);
+    Ok(match negated {
+        Some(_) => (i, Condition::Not(Box::new(cond))),
+        None => (i, cond),
+    })
+}
+
+fn parse_predicate(i: &str) -> IResult<&str, Condition> {
     alt((
-        map(tag("*"), |_| Vec::new()),
+        delimited(
+            char('('),
+            delimited(multispace0, parse_condition, multispace0),
+            char(')'),
+        ),
+        parse_between,
+        parse_in,
+        parse_is_null,
         map(
-            separated_list1(
-                preceded(multispace0, char(',')),
-                preceded(multispace0, identifier),
+            tuple((
+                parse_expr,
+                preceded(multispace0, parse_operator),
+                preceded(
+                    tuple((multispace0, tag_no_case("ANY"), multispace0)),
+                    delimited(
+                        char('('),
+                        delimited(multispace0, parse_expr, multispace0),
+                        char(')'),
+                    ),
+                ),
+            )),
+            |(expr, op, list)| Condition::Any { expr, op, list },
+        ),
+        map(
+            tuple((
+                parse_expr,
+                preceded(multispace0, parse_operator),
+                preceded(multispace0, parse_expr),
+            )),
+            |(left, op, right)| Condition::Compare { left, op, right },
+        ),
+    ))(i)
+}
+
+fn parse_not(i: &str) -> IResult<&str, Condition> {
+    alt((
+        map(
+            preceded(
+                tuple((tag_no_case("NOT"), alt((multispace1, peek_paren)))),
+                preceded(multispace0, parse_not),
             ),
-            |cols: Vec<&str>| cols.into_iter().map(|s| s.to_string()).collect(),
+            |c| Condition::Not(Box::new(c)),
         ),
+        parse_predicate,
     ))(i)
 }
 
-fn parse_order_by(i: &str) -> IResult<&str, (String, bool)> {
-    let (i, _) = tag("ORDER")(i)?;
-    let (i, _) = multispace1(i)?;
-    let (i, _) = tag("BY")(i)?;
-    let (i, _) = multispace1(i)?;
-    let (i, col) = identifier(i)?;
-    let (i, dir) = opt(preceded(
-        multispace1,
-        alt((tag_no_case("ASC"), tag_no_case("DESC"))),
-    ))(i)?;
-    let asc = match dir {
-        Some(d) => d.eq_ignore_ascii_case("ASC"),
-        None => true,
-    };
-    Ok((i, (col.to_string(), asc)))
+fn peek_paren(i: &str) -> IResult<&str, &str> {
+    recognize(peek(char('(')))(i)
 }
 
-fn parse_usize(i: &str) -> IResult<&str, usize> {
-    map_res(digit1, |s: &str| s.parse::<usize>())(i)
+fn parse_and(i: &str) -> IResult<&str, Condition> {
+    let (i, first) = parse_not(i)?;
+    fold_many0(
+        preceded(
+            tuple((multispace1, tag_no_case("AND"), multispace0)),
+            parse_not,
+        ),
+        move || first.clone(),
+        |acc, next| Condition::And(Box::new(acc), Box::new(next)),
+    )(i)
 }
 
-pub fn parse_select(i: &str) -> IResult<&str, SelectQuery> {
-    let (i, _) = tag("SELECT")(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, columns) = parse_columns(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, _) = tag("FROM")(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, table) = identifier(i)?;
+/// Parses a WHERE condition; NOT binds tighter than AND, AND binds tighter
+/// than OR, and parentheses override all three.
+fn parse_condition(i: &str) -> IResult<&str, Condition> {
+    let (i, first) = parse_and(i)?;
+    fold_many0(
+        preceded(
+            tuple((multispace1, tag_no_case("OR"), multispace0)),
+            parse_and,
+        ),
+        move || first.clone(),
+        |acc, next| Condition::Or(Box::new(acc), Box::new(next)),
+    )(i)
+}
+
+fn parse_aggregate_func(i: &str) -> IResult<&str, AggregateFunc> {
+    alt((
+        map(tag_no_case("COUNT"), |_| AggregateFunc::Count),
+        map(tag_no_case("SUM"), |_| AggregateFunc::Sum),
+        map(tag_no_case("MIN"), |_| AggregateFunc::Min),
+        map(tag_no_case("MAX"), |_| AggregateFunc::Max),
+        map(tag_no_case("AVG"), |_| AggregateFunc::Avg),
+    ))(i)
+}
+
+fn parse_aggregate(i: &str) -> IResult<&str, Expr> {
+    let (i, func) = parse_aggregate_func(i)?;
     let (i, _) = multispace0(i)?;
-    let (i, condition) = opt(preceded(
-        tag("WHERE"),
-        preceded(multispace1, parse_condition),
+    let distinct_arg = preceded(
+        pair(tag_no_case("DISTINCT"), multispace1),
+        map(parse_expr, |e| (Some(e), true)),
+    );
+    let (i, (arg, distinct)) = delimited(
+        char('('),
+        delimited(
+            multispace0,
+            alt((
+                map(tag("*"), |_| (None, false)),
+                distinct_arg,
+                map(parse_expr, |e| (Some(e), false)),
+            )),
+            multispace0,
+        ),
+        char(')'),
+    )(i)?;
+    Ok((
+        i,
+        Expr::Aggregate {
+            func,
+            arg: arg.map(Box::new),
+            distinct,
+        },
+    ))
+}
+
+fn parse_case(i: &str) -> IResult<&str, Expr> {
+    let (i, _) = tag_no_case("CASE")(i)?;
+    let branch = tuple((
+        preceded(
+            tuple((multispace1, tag_no_case("WHEN"), multispace1)),
+            parse_condition,
+        ),
+        preceded(
+            tuple((multispace1, tag_no_case("THEN"), multispace1)),
+            parse_expr,
+        ),
+    ));
+    let (i, branches) = many1(branch)(i)?;
+    let (i, otherwise) = opt(preceded(
+        tuple((multispace1, tag_no_case("ELSE"), multispace1)),
+        parse_expr,
     ))(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, order_by) = opt(parse_order_by)(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, limit) = opt(preceded(tag("LIMIT"), preceded(multispace1, parse_usize)))(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, offset) = opt(preceded(tag("OFFSET"), preceded(multispace1, parse_usize)))(i)?;
+    let (i, _) = preceded(multispace1, tag_no_case("END"))(i)?;
     Ok((
         i,
-        SelectQuery {
-            table: table.to_string(),
-            columns,
-            condition,
-            order_by,
-            limit,
-            offset,
+        Expr::Case {
+            branches,
+            otherwise: otherwise.map(Box::new),
         },
     ))
 }
 
-pub fn parse_insert(i: &str) -> IResult<&str, InsertQuery> {
-    let (i, _) = tag("INSERT")(i)?;
-    let (i, _) = multispace1(i)?;
-    let (i, _) = tag("INTO")(i)?;
-    let (i, _) = multispace1(i)?;
-    let (i, table) = identifier(i)?;
-    let (i, columns) = opt(preceded(multispace0, parse_column_names))(i)?;
-    let (i, _) = multispace0(i)?;
-    let (i, _) = tag("VALUES")(i)?;
+/// Parses a column type name.
+/// Parses `DECIMAL`/`NUMERIC` with an optional `(precision[, scale])`;
+/// the defaults are the maximum precision and a scale of zero.
+fn parse_decimal_type(i: &str) -> IResult<&str, ValueType> {
+    let (i, _) = terminated(
+        alt((tag_no_case("DECIMAL"), tag_no_case("NUMERIC"))),
+        not(peek(satisfy(|c: char| c.is_alphanumeric() || c == '_'))),
+    )(i)?;
+    let digits = |i| map_res(digit1, |d: &str| d.parse::<u8>())(i);
+    let (i, spec) = opt(preceded(
+        multispace0,
+        delimited(
+            char('('),
+            pair(
+                delimited(multispace0, digits, multispace0),
+                opt(preceded(
+                    char(','),
+                    delimited(multispace0, digits, multispace0),
+                )),
+            ),
+            char(')'),
+        ),
+    ))(i)?;
+    let (precision, scale) = match spec {
+        Some((precision, scale)) => (precision, scale.unwrap_or(0)),
+        None => (MAX_PRECISION, 0),
+    };
+    if precision == 0 || precision > MAX_PRECISION || scale > precision {
+        return Err(nom::Err::Failure(Error::new(i, ErrorKind::Verify)));
+    }
+    Ok((i, ValueType::Decimal { precision, scale }))
+}
+
+/// Parses `VARCHAR(n)`; a plain `VARCHAR` is unbounded TEXT.
+fn parse_varchar_type(i: &str) -> IResult<&str, ValueType> {
+    let (i, _) = tag_no_case("VARCHAR")(i)?;
+    let (i, max) = opt(preceded(
+        multispace0,
+        delimited(
+            char('('),
+            delimited(
+                multispace0,
+                map_res(digit1, |d: &str| d.parse::<u32>()),
+                multispace0,
+            ),
+            char(')'),
+        ),
+    ))(i)?;
+    match max {
+        Some(0) => Err(nom::Err::Failure(Error::new(i, ErrorKind::Verify))),
+        Some(max) => Ok((i, ValueType::Varchar(max))),
+        None => Ok((i, ValueType::Text)),
+    }
+}
+
+/// Parses `ENUM('label', ...)`; the labels must be distinct.
+fn parse_enum_type(i: &str) -> IResult<&str, ValueType> {
+    let (i, _) = pair(tag_no_case("ENUM"), multispace0)(i)?;
+    let (i, labels) = delimited(
+        char('('),
+        separated_list1(
+            char(','),
+            delimited(multispace0, |i| parse_string(i, false), multispace0),
+        ),
+        char(')'),
+    )(i)?;
+    let distinct = labels
+        .iter()
+        .enumerate()
+        .all(|(n, label)| !labels[..n].contains(label));
+    if !distinct || labels.len() > u16::MAX as usize + 1 {
+        return Err(nom::Err::Failure(Error::new(i, ErrorKind::Verify)));
+    }
+    Ok((i, ValueType::Enum(labels.into())))
+}
+
+pub fn parse_type(i: &str) -> IResult<&str, ValueType> {
+    terminated(
+        alt((
+            parse_decimal_type,
+            parse_varchar_type,
+            parse_enum_type,
+            map(tag_no_case("INTERVAL"), |_| ValueType::Interval),
+            map(alt((tag_no_case("HUGEINT"), tag_no_case("INT128"))), |_| {
+                ValueType::BigInt
+            }),
+            map(
+                alt((
+                    tag_no_case("INTEGER"),
+                    tag_no_case("INT"),
+                    tag_no_case("BIGINT"),
+                )),
+                |_| ValueType::Int,
+            ),
+            map(
+                alt((
+                    tag_no_case("FLOAT"),
+                    tag_no_case("REAL"),
+                    tag_no_case("DOUBLE"),
+                )),
+                |_| ValueType::Float,
+            ),
+            map(tag_no_case("TEXT"), |_| ValueType::Text),
+            map(
+                alt((tag_no_case("TIMESTAMP"), tag_no_case("DATETIME"))),
+                |_| ValueType::Timestamp,
+            ),
+            map(tag_no_case("TIME"), |_| ValueType::Time),
+            map(tag_no_case("DATE"), |_| ValueType::Date),
+            map(tag_no_case("UUID"), |_| ValueType::Uuid),
+            map(tag_no_case("JSON"), |_| ValueType::Json),
+            map(alt((tag_no_case("ARRAY"), tag_no_case("LIST"))), |_| {
+                ValueType::List
+            }),
+            map(alt((tag_no_case("BOOLEAN"), tag_no_case("BOOL"))), |_| {
+                ValueType::Bool
+            }),
+        )),
+        not(peek(satisfy(|c: char| c.is_alphanumeric() || c == '_'))),
+    )(i)
+}
+
+fn parse_cast(i: &str) -> IResult<&str, Expr> {
+    let (i, _) = tag_no_case("CAST")(i)?;
     let (i, _) = multispace0(i)?;
-    let (i, values) = parse_values(i)?;
+    let (i, (expr, target)) = delimited(
+        char('('),
+        pair(
+            delimited(multispace0, parse_expr, multispace1),
+            preceded(pair(tag_no_case("AS"), multispace1), parse_type),
+        ),
+        preceded(multispace0, char(')')),
+    )(i)?;
     Ok((
         i,
-        InsertQuery {
-            table: table.to_string(),
-            columns,
-            values,
+        Expr::Cast {
+            expr: Box::new(expr),
+            target,
         },
     ))
 }
 
-pub fn parse_query(i: &str) -> IResult<&str, Query> {
-    let (i, _) = multispace0(i)?;
-    alt((
-        map(parse_select, Query::Select),
+/// Parses `INTERVAL n unit`, `INTERVAL 'n' unit`, or `INTERVAL 'text'`
+/// with the text in the form accepted by [`Interval`]'s `FromStr`, such as
+/// `INTERVAL '1 day 02:00:00'`.
+fn parse_interval(i: &str) -> IResult<&str, Value> {
+    let (i, _) = tag_no_case("INTERVAL")(i)?;
+    let (i, literal) = alt((
+        map(preceded(multispace1, parse_int), |n| n.to_string()),
+        preceded(multispace0, |i| parse_string(i, false)),
+    ))(i)?;
+    let (rest, unit) = opt(preceded(
+        multispace1,
+        verify(take_while1(|c: char| c.is_ascii_alphabetic()), |u: &str| {
+            Interval::of(1, u).is_some()
+        }),
+    ))(i)?;
+    let interval = match unit {
+        Some(unit) => literal
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|n| Interval::of(n, unit)),
+        None => literal.parse().ok(),
+    };
+    match interval {
+        Some(interval) => Ok((rest, Value::Interval(interval))),
+        None => Err(nom::Err::Failure(Error::new(i, ErrorKind::Verify))),
+    }
+}
+
+fn parse_function(i: &str) -> IResult<&str, Expr> {
+    let (i, name) = alias_name(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, args) = delimited(
+        char('('),
+        separated_list0(
+            preceded(multispace0, char(',')),
+            preceded(multispace0, parse_expr),
+        ),
+        preceded(multispace0, char(')')),
+    )(i)?;
+    Ok((
+        i,
+        Expr::Function {
+            name: name.to_string(),
+            args,
+        },
+    ))
+}
+
+fn parse_primary(i: &str) -> IResult<&str, Expr> {
+    alt((
+        delimited(
+            char('('),
+            delimited(multispace0, parse_expr, multispace0),
+            char(')'),
+        ),
+        parse_case,
+        parse_cast,
+        parse_aggregate,
+        map(parse_value, Expr::Literal),
+        parse_function,
+        map(column_ref, |s: &str| Expr::Column(s.to_string())),
+    ))(i)
+}
+
+/// Parses a primary expression followed by any number of accessors:
+/// `list[n]` is `ELEMENT_AT(list, n)` (1-based), `doc -> path` is
+/// `JSON_EXTRACT(doc, path)` and `doc ->> path` is
+/// `JSON_UNQUOTE(JSON_EXTRACT(doc, path))`.
+fn parse_access(i: &str) -> IResult<&str, Expr> {
+    let (i, first) = parse_primary(i)?;
+    let call = |name: &str, args| Expr::Function {
+        name: name.to_string(),
+        args,
+    };
+    let subscript = map(
+        delimited(
+            pair(multispace0, char('[')),
+            delimited(multispace0, parse_expr, multispace0),
+            char(']'),
+        ),
+        |idx| ("[]", idx),
+    );
+    let json = pair(
+        preceded(multispace0, alt((tag("->>"), tag("->")))),
+        preceded(multispace0, parse_primary),
+    );
+    fold_many0(
+        alt((subscript, json)),
+        move || first.clone(),
+        move |base, (op, arg)| match op {
+            "[]" => call("ELEMENT_AT", vec![base, arg]),
+            "->" => call("JSON_EXTRACT", vec![base, arg]),
+            _ => call("JSON_UNQUOTE", vec![call("JSON_EXTRACT", vec![base, arg])]),
+        },
+    )(i)
+}
+
+fn parse_term(i: &str) -> IResult<&str, Expr> {
+    let (i, first) = parse_access(i)?;
+    let op = alt((
+        map(char('*'), |_| BinaryOp::Mul),
+        map(char('/'), |_| BinaryOp::Div),
+        map(char('%'), |_| BinaryOp::Mod),
+    ));
+    fold_many0(
+        pair(
+            preceded(multispace0, op),
+            preceded(multispace0, parse_access),
+        ),
+        move || first.clone(),
+        |left, (op, right)| Expr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+    )(i)
+}
+
+/// Parses a scalar expression; `*`, `/` and `%` bind tighter than `+` and
+/// `-`, and all binary operators are left-associative.
+pub fn parse_expr(i: &str) -> IResult<&str, Expr> {
+    let (i, first) = parse_term(i)?;
+    let op = alt((
+        map(char('+'), |_| BinaryOp::Add),
+        map(char('-'), |_| BinaryOp::Sub),
+    ));
+    fold_many0(
+        pair(preceded(multispace0, op), preceded(multispace0, parse_term)),
+        move || first.clone(),
+        |left, (op, right)| Expr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+    )(i)
+}
+
+fn parse_select_item(i: &str) -> IResult<&str, SelectItem> {
+    let (i, expr) = parse_expr(i)?;
+    let (i, alias) = opt(parse_alias)(i)?;
+    Ok((i, SelectItem { expr, alias }))
+}
+
+fn parse_columns(i: &str) -> IResult<&str, Vec<SelectItem>> {
+    alt((
+        map(tag("*"), |_| Vec::new()),
+        separated_list1(
+            preceded(multispace0, char(',')),
+            preceded(multispace0, parse_select_item),
+        ),
+    ))(i)
+}
+
+fn parse_order_by(i: &str) -> IResult<&str, (String, bool)> {
+    let (i, _) = tag_no_case("ORDER")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("BY")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, col) = column_ref(i)?;
+    let (i, dir) = opt(preceded(
+        multispace1,
+        alt((tag_no_case("ASC"), tag_no_case("DESC"))),
+    ))(i)?;
+    let asc = match dir {
+        Some(d) => d.eq_ignore_ascii_case("ASC"),
+        None => true,
+    };
+    Ok((i, (col.to_string(), asc)))
+}
+
+fn parse_usize(i: &str) -> IResult<&str, usize> {
+    map_res(digit1, |s: &str| s.parse::<usize>())(i)
+}
+
+fn parse_table_ref(i: &str) -> IResult<&str, TableRef> {
+    let (i, name) = table_name(i)?;
+    // A hint straight after the name comes first, as USE or IGNORE would
+    // otherwise be read as an alias.
+    let (i, hint) = opt(parse_index_hint)(i)?;
+    let (i, alias, hint) = match hint {
+        Some(hint) => (i, None, Some(hint)),
+        None => {
+            let (i, alias) = opt(parse_alias)(i)?;
+            let (i, hint) = opt(parse_index_hint)(i)?;
+            (i, alias, hint)
+        }
+    };
+    Ok((
+        i,
+        TableRef {
+            name: name.to_string(),
+            alias,
+            hint,
+        },
+    ))
+}
+
+fn parse_index_hint(i: &str) -> IResult<&str, IndexHint> {
+    let (i, _) = multispace1(i)?;
+    let (i, verb) = alt((tag_no_case("USE"), tag_no_case("IGNORE")))(i)?;
+    let (i, _) = tuple((multispace1, tag_no_case("INDEX"), multispace0, char('(')))(i)?;
+    let (i, names) = separated_list0(
+        delimited(multispace0, char(','), multispace0),
+        preceded(multispace0, map(identifier, str::to_string)),
+    )(i)?;
+    let (i, _) = preceded(multispace0, char(')'))(i)?;
+    let hint = if verb.eq_ignore_ascii_case("USE") {
+        IndexHint::Use(names)
+    } else {
+        IndexHint::Ignore(names)
+    };
+    Ok((i, hint))
+}
+
+fn parse_from_list(i: &str) -> IResult<&str, Vec<TableRef>> {
+    let join = alt((
+        map(preceded(multispace0, char(',')), |_| ()),
+        map(
+            tuple((
+                multispace1,
+                tag_no_case("CROSS"),
+                multispace1,
+                tag_no_case("JOIN"),
+            )),
+            |_| (),
+        ),
+    ));
+    separated_list1(join, preceded(multispace0, parse_table_ref))(i)
+}
+
+fn parse_cte(i: &str) -> IResult<&str, Cte> {
+    let (i, name) = identifier(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("AS")(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, query) = delimited(
+        char('('),
+        delimited(multispace0, parse_select, multispace0),
+        char(')'),
+    )(i)?;
+    Ok((
+        i,
+        Cte {
+            name: name.to_string(),
+            query,
+        },
+    ))
+}
+
+fn parse_with(i: &str) -> IResult<&str, Vec<Cte>> {
+    let (i, _) = tag_no_case("WITH")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, ctes) = separated_list1(
+        preceded(multispace0, char(',')),
+        preceded(multispace0, parse_cte),
+    )(i)?;
+    let (i, _) = multispace0(i)?;
+    Ok((i, ctes))
+}
+
+pub fn parse_select(i: &str) -> IResult<&str, SelectQuery> {
+    let (i, with) = opt(parse_with)(i)?;
+    let (i, _) = tag_no_case("SELECT")(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, columns) = parse_columns(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, _) = tag_no_case("FROM")(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, tables) = parse_from_list(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, condition) = opt(preceded(
+        tag_no_case("WHERE"),
+        preceded(multispace1, parse_condition),
+    ))(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, order_by) = opt(parse_order_by)(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, limit) = opt(preceded(
+        tag_no_case("LIMIT"),
+        preceded(multispace1, parse_usize),
+    ))(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, offset) = opt(preceded(
+        tag_no_case("OFFSET"),
+        preceded(multispace1, parse_usize),
+    ))(i)?;
+    Ok((
+        i,
+        SelectQuery {
+            with: with.unwrap_or_default(),
+            tables,
+            columns,
+            condition,
+            order_by,
+            limit,
+            offset,
+        },
+    ))
+}
+
+pub fn parse_insert(i: &str) -> IResult<&str, InsertQuery> {
+    let (i, _) = tag_no_case("INSERT")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("INTO")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, table) = table_name(i)?;
+    let (i, columns) = opt(preceded(multispace0, parse_column_names))(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, _) = tag_no_case("VALUES")(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, values) = parse_values(i)?;
+    Ok((
+        i,
+        InsertQuery {
+            table: table.to_string(),
+            columns,
+            values,
+        },
+    ))
+}
+
+fn parse_where(i: &str) -> IResult<&str, Option<Condition>> {
+    opt(preceded(
+        tag_no_case("WHERE"),
+        preceded(multispace1, parse_condition),
+    ))(i)
+}
+
+pub fn parse_delete(i: &str) -> IResult<&str, DeleteQuery> {
+    let (i, _) = tag_no_case("DELETE")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("FROM")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, table) = table_name(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, condition) = parse_where(i)?;
+    Ok((
+        i,
+        DeleteQuery {
+            table: table.to_string(),
+            condition,
+        },
+    ))
+}
+
+fn parse_assignment(i: &str) -> IResult<&str, (String, Expr)> {
+    let (i, column) = identifier(i)?;
+    let (i, _) = delimited(multispace0, char('='), multispace0)(i)?;
+    let (i, expr) = parse_expr(i)?;
+    Ok((i, (column.to_string(), expr)))
+}
+
+pub fn parse_update(i: &str) -> IResult<&str, UpdateQuery> {
+    let (i, _) = tag_no_case("UPDATE")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, table) = table_name(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("SET")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, assignments) = separated_list1(
+        preceded(multispace0, char(',')),
+        preceded(multispace0, parse_assignment),
+    )(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, condition) = parse_where(i)?;
+    Ok((
+        i,
+        UpdateQuery {
+            table: table.to_string(),
+            assignments,
+            condition,
+        },
+    ))
+}
+
+pub fn parse_alter_table(i: &str) -> IResult<&str, AlterTableQuery> {
+    let (i, _) = tag_no_case("ALTER")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("TABLE")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, table) = table_name(i)?;
+    let (i, _) = multispace1(i)?;
+    let base = table.rsplit('.').next().unwrap_or(table);
+    let (i, action) = alt((
+        map(
+            preceded(
+                tuple((
+                    tag_no_case("RENAME"),
+                    multispace1,
+                    tag_no_case("TO"),
+                    multispace1,
+                )),
+                table_name,
+            ),
+            |name| AlterTableAction::RenameTo(name.to_string()),
+        ),
+        map_opt(
+            preceded(pair(tag_no_case("ADD"), multispace1), table_constraint),
+            |element| match element {
+                TableElement::ForeignKey(name, columns, mut fk) => {
+                    fk.name =
+                        name.unwrap_or_else(|| format!("{}_{}_fkey", base, columns.join("_")));
+                    fk.columns = columns;
+                    Some(AlterTableAction::AddForeignKey(fk))
+                }
+                _ => None,
+            },
+        ),
+    ))(i)?;
+    Ok((
+        i,
+        AlterTableQuery {
+            table: table.to_string(),
+            action,
+        },
+    ))
+}
+
+/// A column of CREATE TABLE, or a constraint over its columns.
+enum TableElement {
+    Column(String, ValueType, Vec<(Option<String>, ColumnConstraint)>),
+    PrimaryKey(Vec<String>),
+    Unique(Option<String>, Vec<String>),
+    ForeignKey(Option<String>, Vec<String>, ForeignKey),
+}
+
+enum ColumnConstraint {
+    PrimaryKey,
+    Unique,
+    AutoIncrement,
+    References(ForeignKey),
+}
+
+fn parse_column_def(i: &str) -> IResult<&str, TableElement> {
+    let (i, name) = identifier(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, col_type) = parse_type(i)?;
+    let (i, constraints) = many0(preceded(
+        multispace1,
+        pair(
+            opt(constraint_name),
+            alt((
+                map(
+                    tuple((tag_no_case("PRIMARY"), multispace1, tag_no_case("KEY"))),
+                    |_| ColumnConstraint::PrimaryKey,
+                ),
+                map(tag_no_case("UNIQUE"), |_| ColumnConstraint::Unique),
+                map(
+                    alt((tag_no_case("AUTO_INCREMENT"), tag_no_case("AUTOINCREMENT"))),
+                    |_| ColumnConstraint::AutoIncrement,
+                ),
+                map(references, ColumnConstraint::References),
+            )),
+        ),
+    ))(i)?;
+    Ok((
+        i,
+        TableElement::Column(name.to_string(), col_type, constraints),
+    ))
+}
+
+/// `CONSTRAINT name `, naming the constraint that follows.
+fn constraint_name(i: &str) -> IResult<&str, String> {
+    map(
+        delimited(
+            pair(tag_no_case("CONSTRAINT"), multispace1),
+            identifier,
+            multispace1,
+        ),
+        str::to_string,
+    )(i)
+}
+
+fn constraint_columns(i: &str) -> IResult<&str, Vec<String>> {
+    delimited(
+        pair(char('('), multispace0),
+        separated_list1(
+            delimited(multispace0, char(','), multispace0),
+            map(identifier, str::to_string),
+        ),
+        pair(multispace0, char(')')),
+    )(i)
+}
+
+fn table_constraint(i: &str) -> IResult<&str, TableElement> {
+    let (i, name) = opt(constraint_name)(i)?;
+    let key = |word| {
+        tuple((
+            tag_no_case(word),
+            multispace1,
+            tag_no_case("KEY"),
+            multispace0,
+        ))
+    };
+    if let Ok((i, columns)) = preceded(key("PRIMARY"), constraint_columns)(i) {
+        return Ok((i, TableElement::PrimaryKey(columns)));
+    }
+    if let Ok((i, columns)) =
+        preceded(pair(tag_no_case("UNIQUE"), multispace0), constraint_columns)(i)
+    {
+        return Ok((i, TableElement::Unique(name, columns)));
+    }
+    let (i, columns) = preceded(key("FOREIGN"), constraint_columns)(i)?;
+    let (i, fk) = preceded(multispace1, references)(i)?;
+    Ok((i, TableElement::ForeignKey(name, columns, fk)))
+}
+
+/// `REFERENCES table [(column, ...)] [ON DELETE action] [ON UPDATE
+/// action]`, as a foreign key with no name or columns of its own yet.
+fn references(i: &str) -> IResult<&str, ForeignKey> {
+    let (i, _) = pair(tag_no_case("REFERENCES"), multispace1)(i)?;
+    let (i, table) = table_name(i)?;
+    let (i, ref_columns) = opt(preceded(multispace0, constraint_columns))(i)?;
+    let (i, actions) = many0(preceded(
+        tuple((multispace1, tag_no_case("ON"), multispace1)),
+        pair(
+            terminated(
+                alt((tag_no_case("DELETE"), tag_no_case("UPDATE"))),
+                multispace1,
+            ),
+            referential_action,
+        ),
+    ))(i)?;
+    let mut fk = ForeignKey::new("", &[], table, &[]);
+    fk.ref_columns = ref_columns.unwrap_or_default();
+    for (event, action) in actions {
+        if event.eq_ignore_ascii_case("DELETE") {
+            fk.on_delete = action;
+        } else {
+            fk.on_update = action;
+        }
+    }
+    Ok((i, fk))
+}
+
+fn referential_action(i: &str) -> IResult<&str, ReferentialAction> {
+    alt((
+        map(tag_no_case("RESTRICT"), |_| ReferentialAction::Restrict),
+        map(
+            tuple((tag_no_case("NO"), multispace1, tag_no_case("ACTION"))),
+            |_| ReferentialAction::Restrict,
+        ),
+        map(tag_no_case("CASCADE"), |_| ReferentialAction::Cascade),
+        map(
+            tuple((tag_no_case("SET"), multispace1, tag_no_case("NULL"))),
+            |_| ReferentialAction::SetNull,
+        ),
+    ))(i)
+}
+
+pub fn parse_create_table(i: &str) -> IResult<&str, CreateTableQuery> {
+    let (i, _) = tag_no_case("CREATE")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, or_replace) = opt(tuple((
+        tag_no_case("OR"),
+        multispace1,
+        tag_no_case("REPLACE"),
+        multispace1,
+    )))(i)?;
+    let (i, _) = tag_no_case("TABLE")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, if_not_exists) = opt(tuple((
+        tag_no_case("IF"),
+        multispace1,
+        tag_no_case("NOT"),
+        multispace1,
+        tag_no_case("EXISTS"),
+        multispace1,
+    )))(i)?;
+    let (i, name) = table_name(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, elements) = delimited(
+        char('('),
+        separated_list1(
+            char(','),
+            delimited(
+                multispace0,
+                alt((table_constraint, parse_column_def)),
+                multispace0,
+            ),
+        ),
+        char(')'),
+    )(i)?;
+    let (i, options) = opt(preceded(
+        tuple((multispace1, tag_no_case("WITH"), multispace0, char('('))),
+        terminated(
+            separated_list1(
+                preceded(multispace0, char(',')),
+                preceded(multispace0, table_option),
+            ),
+            pair(multispace0, char(')')),
+        ),
+    ))(i)?;
+    let (i, partitioning) = opt(preceded(multispace1, partition_by))(i)?;
+    let mut query = CreateTableQuery {
+        name: name.to_string(),
+        columns: Vec::new(),
+        primary_key: None,
+        uniques: Vec::new(),
+        foreign_keys: Vec::new(),
+        auto_increment: None,
+        if_not_exists: if_not_exists.is_some(),
+        or_replace: or_replace.is_some(),
+        layout: Layout::Row,
+        compression: false,
+        overflow: None,
+        partitioning,
+        ttl: None,
+    };
+    let mut ttl_column = None;
+    for option in options.into_iter().flatten() {
+        match option {
+            TableOption::Layout(layout) => query.layout = layout,
+            TableOption::Compression(on) => query.compression = on,
+            TableOption::Overflow(threshold) => query.overflow = threshold,
+            TableOption::Ttl(after) => query.ttl = Some(Ttl::since_insert(after)),
+            TableOption::TtlColumn(column) => ttl_column = Some(column),
+        }
+    }
+    if let Some(column) = ttl_column {
+        let Some(ttl) = &mut query.ttl else {
+            return Err(nom::Err::Error(Error::new(i, ErrorKind::Verify)));
+        };
+        ttl.column = Some(column);
+    }
+    // Constraint names start with the table's own, without its schema.
+    let base = name.rsplit('.').next().unwrap_or(name);
+    let invalid = || nom::Err::Error(Error::new(i, ErrorKind::Verify));
+    for element in elements {
+        let TableElement::Column(column, col_type, constraints) = element else {
+            add_constraint(&mut query, base, element).ok_or_else(invalid)?;
+            continue;
+        };
+        for (name, constraint) in constraints {
+            let columns = vec![column.clone()];
+            let element = match constraint {
+                ColumnConstraint::PrimaryKey => TableElement::PrimaryKey(columns),
+                ColumnConstraint::Unique => TableElement::Unique(name, columns),
+                ColumnConstraint::References(fk) => TableElement::ForeignKey(name, columns, fk),
+                ColumnConstraint::AutoIncrement => {
+                    if query.auto_increment.is_some() {
+                        return Err(invalid());
+                    }
+                    query.auto_increment = Some(column.clone());
+                    continue;
+                }
+            };
+            add_constraint(&mut query, base, element).ok_or_else(invalid)?;
+        }
+        query.columns.push((column, col_type));
+    }
+    Ok((i, query))
+}
+
+/// Adds a constraint to `query`, naming it after the table `base` and its
+/// columns if it has no name. `None` for a second primary key.
+fn add_constraint(query: &mut CreateTableQuery, base: &str, element: TableElement) -> Option<()> {
+    let name = |name: Option<String>, columns: &[String], suffix: &str| {
+        name.unwrap_or_else(|| format!("{}_{}_{}", base, columns.join("_"), suffix))
+    };
+    match element {
+        TableElement::PrimaryKey(columns) => {
+            if query.primary_key.is_some() {
+                return None;
+            }
+            query.primary_key = Some(columns);
+        }
+        TableElement::Unique(constraint, columns) => query.uniques.push(UniqueConstraint {
+            name: name(constraint, &columns, "key"),
+            columns,
+        }),
+        TableElement::ForeignKey(constraint, columns, mut fk) => {
+            fk.name = name(constraint, &columns, "fkey");
+            fk.columns = columns;
+            query.foreign_keys.push(fk);
+        }
+        TableElement::Column(..) => {}
+    }
+    Some(())
+}
+
+enum TableOption {
+    Layout(Layout),
+    Compression(bool),
+    Overflow(Option<usize>),
+    Ttl(Interval),
+    TtlColumn(String),
+}
+
+fn table_option(i: &str) -> IResult<&str, TableOption> {
+    let (i, key) = alt((
+        tag_no_case("layout"),
+        tag_no_case("compression"),
+        tag_no_case("overflow"),
+        tag_no_case("ttl_column"),
+        tag_no_case("ttl"),
+    ))(i)?;
+    let (i, _) = delimited(multispace0, char('='), multispace0)(i)?;
+    let (i, value) = parse_string(i, false)?;
+    let option = match key.to_ascii_lowercase().as_str() {
+        "layout" => Layout::from_name(&value).map(TableOption::Layout),
+        "ttl" => value.parse().ok().map(TableOption::Ttl),
+        "ttl_column" => Some(TableOption::TtlColumn(value)),
+        "overflow" if value.eq_ignore_ascii_case("off") => Some(TableOption::Overflow(None)),
+        "overflow" => value.parse().ok().map(|n| TableOption::Overflow(Some(n))),
+        _ => match value.to_ascii_lowercase().as_str() {
+            "on" => Some(TableOption::Compression(true)),
+            "off" => Some(TableOption::Compression(false)),
+            _ => None,
+        },
+    };
+    match option {
+        Some(option) => Ok((i, option)),
+        None => Err(nom::Err::Error(Error::new(i, ErrorKind::Verify))),
+    }
+}
+
+fn partition_by(i: &str) -> IResult<&str, Partitioning> {
+    let (i, _) = tuple((
+        tag_no_case("PARTITION"),
+        multispace1,
+        tag_no_case("BY"),
+        multispace1,
+    ))(i)?;
+    let (i, kind) = alt((tag_no_case("RANGE"), tag_no_case("HASH")))(i)?;
+    let (i, column) = delimited(
+        pair(multispace0, char('(')),
+        delimited(multispace0, identifier, multispace0),
+        char(')'),
+    )(i)?;
+    if kind.eq_ignore_ascii_case("HASH") {
+        let (i, n) = preceded(
+            tuple((multispace1, tag_no_case("PARTITIONS"), multispace1)),
+            parse_usize,
+        )(i)?;
+        return Ok((i, Partitioning::hash(column, n)));
+    }
+    let bound = alt((
+        map(tag_no_case("MAXVALUE"), |_| None),
+        map(
+            delimited(
+                pair(char('('), multispace0),
+                parse_value,
+                pair(multispace0, char(')')),
+            ),
+            Some,
+        ),
+    ));
+    let partition = map(
+        tuple((
+            tag_no_case("PARTITION"),
+            multispace1,
+            identifier,
+            multispace1,
+            tag_no_case("VALUES"),
+            multispace1,
+            tag_no_case("LESS"),
+            multispace1,
+            tag_no_case("THAN"),
+            multispace0,
+            bound,
+        )),
+        |(_, _, name, _, _, _, _, _, _, _, bound)| (name.to_string(), bound),
+    );
+    let (i, bounds) = delimited(
+        pair(multispace0, char('(')),
+        separated_list1(char(','), delimited(multispace0, partition, multispace0)),
+        char(')'),
+    )(i)?;
+    Ok((i, Partitioning::range(column, bounds)))
+}
+
+pub fn parse_create_index(i: &str) -> IResult<&str, CreateIndexQuery> {
+    let (i, _) = pair(tag_no_case("CREATE"), multispace1)(i)?;
+    let (i, unique) = opt(terminated(tag_no_case("UNIQUE"), multispace1))(i)?;
+    let (i, _) = pair(tag_no_case("INDEX"), multispace1)(i)?;
+    let (i, name) = opt(terminated(
+        verify(identifier, |s: &str| !s.eq_ignore_ascii_case("ON")),
+        multispace1,
+    ))(i)?;
+    let (i, _) = pair(tag_no_case("ON"), multispace1)(i)?;
+    let (i, table) = table_name(i)?;
+    let (i, _) = multispace0(i)?;
+    let (i, columns) = delimited(
+        char('('),
+        separated_list1(
+            preceded(multispace0, char(',')),
+            preceded(multispace0, parse_expr),
+        ),
+        preceded(multispace0, char(')')),
+    )(i)?;
+    let (i, kind) = opt(preceded(
+        tuple((multispace1, tag_no_case("USING"), multispace1)),
+        alt((
+            map(tag_no_case("BTREE"), |_| IndexKind::Ordered),
+            map(tag_no_case("HASH"), |_| IndexKind::Hash),
+            map(tag_no_case("TRIGRAM"), |_| IndexKind::Trigram),
+        )),
+    ))(i)?;
+    Ok((
+        i,
+        CreateIndexQuery {
+            unique: unique.is_some(),
+            name: name.map(str::to_string),
+            table: table.to_string(),
+            columns,
+            kind: kind.unwrap_or(IndexKind::Ordered),
+        },
+    ))
+}
+
+pub fn parse_create_view(i: &str) -> IResult<&str, CreateViewQuery> {
+    let (i, _) = tag_no_case("CREATE")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, materialized) = opt(terminated(tag_no_case("MATERIALIZED"), multispace1))(i)?;
+    let (i, _) = tag_no_case("VIEW")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, name) = table_name(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = tag_no_case("AS")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, query) = parse_select(i)?;
+    Ok((
+        i,
+        CreateViewQuery {
+            name: name.to_string(),
+            query,
+            materialized: materialized.is_some(),
+        },
+    ))
+}
+
+fn parse_drop_view(i: &str) -> IResult<&str, String> {
+    let (i, _) = tag_no_case("DROP")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, _) = opt(terminated(tag_no_case("MATERIALIZED"), multispace1))(i)?;
+    let (i, _) = tag_no_case("VIEW")(i)?;
+    let (i, _) = multispace1(i)?;
+    map(table_name, str::to_string)(i)
+}
+
+fn parse_refresh_view(i: &str) -> IResult<&str, String> {
+    let (i, _) = tuple((
+        tag_no_case("REFRESH"),
+        multispace1,
+        tag_no_case("MATERIALIZED"),
+        multispace1,
+        tag_no_case("VIEW"),
+        multispace1,
+    ))(i)?;
+    map(table_name, str::to_string)(i)
+}
+
+fn parse_create_schema(i: &str) -> IResult<&str, String> {
+    let (i, _) = tuple((
+        tag_no_case("CREATE"),
+        multispace1,
+        tag_no_case("SCHEMA"),
+        multispace1,
+    ))(i)?;
+    map(identifier, str::to_string)(i)
+}
+
+fn parse_drop_schema(i: &str) -> IResult<&str, Query> {
+    let (i, _) = tuple((
+        tag_no_case("DROP"),
+        multispace1,
+        tag_no_case("SCHEMA"),
+        multispace1,
+    ))(i)?;
+    let (i, name) = identifier(i)?;
+    let (i, cascade) = opt(preceded(multispace1, tag_no_case("CASCADE")))(i)?;
+    Ok((
+        i,
+        Query::DropSchema {
+            name: name.to_string(),
+            cascade: cascade.is_some(),
+        },
+    ))
+}
+
+fn parse_analyze(i: &str) -> IResult<&str, Option<String>> {
+    let (i, _) = tag_no_case("ANALYZE")(i)?;
+    opt(map(preceded(multispace1, table_name), str::to_string))(i)
+}
+
+fn parse_explain(i: &str) -> IResult<&str, SelectQuery> {
+    preceded(pair(tag_no_case("EXPLAIN"), multispace1), parse_select)(i)
+}
+
+fn parse_reindex(i: &str) -> IResult<&str, Option<String>> {
+    let (i, _) = tag_no_case("REINDEX")(i)?;
+    opt(map(preceded(multispace1, table_name), str::to_string))(i)
+}
+
+/// `BEGIN`, `START TRANSACTION`, `COMMIT` or `ROLLBACK`, or a statement
+/// setting, going back to or releasing a savepoint.
+fn parse_transaction(i: &str) -> IResult<&str, Query> {
+    let noise = |i| {
+        opt(preceded(
+            multispace1,
+            alt((tag_no_case("TRANSACTION"), tag_no_case("WORK"))),
+        ))(i)
+    };
+    alt((
+        map(pair(tag_no_case("BEGIN"), noise), |_| Query::Begin),
+        map(
+            tuple((
+                tag_no_case("START"),
+                multispace1,
+                tag_no_case("TRANSACTION"),
+            )),
+            |_| Query::Begin,
+        ),
+        map(pair(tag_no_case("COMMIT"), noise), |_| Query::Commit),
+        map(
+            preceded(
+                tuple((
+                    tag_no_case("ROLLBACK"),
+                    noise,
+                    multispace1,
+                    tag_no_case("TO"),
+                )),
+                savepoint_name,
+            ),
+            Query::RollbackTo,
+        ),
+        map(pair(tag_no_case("ROLLBACK"), noise), |_| Query::Rollback),
+        map(
+            preceded(tag_no_case("SAVEPOINT"), preceded(multispace1, identifier)),
+            |name| Query::Savepoint(name.to_string()),
+        ),
+        map(
+            preceded(tag_no_case("RELEASE"), savepoint_name),
+            Query::Release,
+        ),
+    ))(i)
+}
+
+/// The name of a savepoint, after an optional `SAVEPOINT`.
+fn savepoint_name(i: &str) -> IResult<&str, String> {
+    let (i, name) = alt((
+        preceded(
+            tuple((multispace1, tag_no_case("SAVEPOINT"), multispace1)),
+            identifier,
+        ),
+        preceded(multispace1, identifier),
+    ))(i)?;
+    Ok((i, name.to_string()))
+}
+
+fn parse_vacuum(i: &str) -> IResult<&str, Option<String>> {
+    let (i, _) = tag_no_case("VACUUM")(i)?;
+    opt(map(preceded(multispace1, table_name), str::to_string))(i)
+}
+
+pub fn parse_comment(i: &str) -> IResult<&str, CommentQuery> {
+    let (i, _) = tuple((
+        tag_no_case("COMMENT"),
+        multispace1,
+        tag_no_case("ON"),
+        multispace1,
+    ))(i)?;
+    let (i, (table, column)) = alt((
+        map(
+            preceded(pair(tag_no_case("TABLE"), multispace1), table_name),
+            |table| (table, None),
+        ),
+        map_opt(
+            preceded(pair(tag_no_case("COLUMN"), multispace1), column_ref),
+            |name| {
+                name.rsplit_once('.')
+                    .map(|(table, column)| (table, Some(column)))
+            },
+        ),
+    ))(i)?;
+    let (i, _) = tuple((multispace1, tag_no_case("IS"), multispace1))(i)?;
+    let (i, text) = map_opt(parse_value, |v| match v {
+        Value::Text(text) => Some(Some(text)),
+        Value::Null => Some(None),
+        _ => None,
+    })(i)?;
+    Ok((
+        i,
+        CommentQuery {
+            table: table.to_string(),
+            column: column.map(str::to_string),
+            text,
+        },
+    ))
+}
+
+pub fn parse_pragma(i: &str) -> IResult<&str, PragmaQuery> {
+    let (i, _) = tag_no_case("PRAGMA")(i)?;
+    let (i, _) = multispace1(i)?;
+    let (i, name) = identifier(i)?;
+    let (i, table) = opt(preceded(
+        multispace0,
+        delimited(
+            char('('),
+            delimited(multispace0, table_name, multispace0),
+            char(')'),
+        ),
+    ))(i)?;
+    let (i, value) = opt(preceded(
+        delimited(multispace0, char('='), multispace0),
+        alt((parse_value, map(identifier, |w| Value::Text(w.to_string())))),
+    ))(i)?;
+    Ok((
+        i,
+        PragmaQuery {
+            name: name.to_string(),
+            table: table.map(|t| t.to_string()),
+            value,
+        },
+    ))
+}
+
+enum CopyOption {
+    Header(bool),
+    Delimiter(char),
+    Null(String),
+}
+
+fn copy_option(i: &str) -> IResult<&str, CopyOption> {
+    alt((
+        map(
+            preceded(
+                tag_no_case("HEADER"),
+                opt(preceded(
+                    multispace1,
+                    alt((
+                        map(tag_no_case("TRUE"), |_| true),
+                        map(tag_no_case("FALSE"), |_| false),
+                    )),
+                )),
+            ),
+            |header| CopyOption::Header(header.unwrap_or(true)),
+        ),
+        preceded(
+            pair(tag_no_case("DELIMITER"), multispace1),
+            map_opt(
+                |i| parse_string(i, false),
+                |s| {
+                    let mut chars = s.chars();
+                    let c = chars.next().filter(|_| chars.next().is_none())?;
+                    Some(CopyOption::Delimiter(c))
+                },
+            ),
+        ),
+        preceded(
+            pair(tag_no_case("NULL"), multispace1),
+            map(|i| parse_string(i, false), CopyOption::Null),
+        ),
+    ))(i)
+}
+
+fn copy_options(i: &str) -> IResult<&str, CsvOptions> {
+    let (i, set) = opt(preceded(
+        tuple((multispace1, tag_no_case("WITH"), multispace0, char('('))),
+        terminated(
+            separated_list1(
+                preceded(multispace0, char(',')),
+                preceded(multispace0, copy_option),
+            ),
+            pair(multispace0, char(')')),
+        ),
+    ))(i)?;
+    let mut options = CsvOptions::default();
+    for option in set.into_iter().flatten() {
+        match option {
+            CopyOption::Header(header) => options.header = header,
+            CopyOption::Delimiter(c) => options.delimiter = c,
+            CopyOption::Null(null) => options.null = null,
+        }
+    }
+    Ok((i, options))
+}
+
+fn parse_copy(i: &str) -> IResult<&str, CopyQuery> {
+    let (i, _) = pair(tag_no_case("COPY"), multispace1)(i)?;
+    let path = |i| preceded(multispace1, |i| parse_string(i, false))(i);
+    alt((
+        map(
+            tuple((
+                table_name,
+                preceded(multispace1, tag_no_case("FROM")),
+                path,
+                copy_options,
+            )),
+            |(table, _, path, options)| CopyQuery::From {
+                table: table.to_string(),
+                path,
+                options,
+            },
+        ),
+        map(
+            tuple((
+                alt((
+                    table_name,
+                    delimited(
+                        pair(char('('), multispace0),
+                        recognize(parse_select),
+                        pair(multispace0, char(')')),
+                    ),
+                )),
+                preceded(multispace1, tag_no_case("TO")),
+                path,
+                copy_options,
+            )),
+            |(source, _, path, options)| CopyQuery::To {
+                source: source.to_string(),
+                path,
+                options,
+            },
+        ),
+    ))(i)
+}
+
+impl Query {
+    /// Whether the statement reads or changes rows, leaving the schema
+    /// alone.
+    pub(crate) fn is_dml(&self) -> bool {
+        matches!(
+            self,
+            Query::Select(_)
+                | Query::Explain(_)
+                | Query::Insert(_)
+                | Query::Update(_)
+                | Query::Delete(_)
+        )
+    }
+
+    /// Whether the statement opens or ends a transaction.
+    pub(crate) fn is_transaction_control(&self) -> bool {
+        matches!(
+            self,
+            Query::Begin
+                | Query::Commit
+                | Query::Rollback
+                | Query::Savepoint(_)
+                | Query::RollbackTo(_)
+                | Query::Release(_)
+        )
+    }
+
+    /// Whether the statement changes anything: rows, the schema, settings
+    /// or statistics. Only SELECT, EXPLAIN, PRAGMA reading a setting and
+    /// COPY ... TO leave the engine as it was.
+    pub(crate) fn writes(&self) -> bool {
+        !matches!(
+            self,
+            Query::Select(_)
+                | Query::Explain(_)
+                | Query::Pragma(PragmaQuery { value: None, .. })
+                | Query::Copy(CopyQuery::To { .. })
+        )
+    }
+
+    /// Calls `f` on every literal value of a SELECT, EXPLAIN, INSERT,
+    /// UPDATE or DELETE, in the order they are written, returning whether
+    /// the statement is one of those.
+    pub(crate) fn visit_literals(&mut self, f: &mut dyn FnMut(&mut Value)) -> bool {
+        match self {
+            Query::Select(q) | Query::Explain(q) => q.visit_literals(f),
+            Query::Insert(q) => {
+                for value in &mut q.values {
+                    value.visit_literals(f);
+                }
+            }
+            Query::Update(q) => {
+                for (_, expr) in &mut q.assignments {
+                    expr.visit_literals(f);
+                }
+                if let Some(cond) = &mut q.condition {
+                    cond.visit_literals(f);
+                }
+            }
+            Query::Delete(q) => {
+                if let Some(cond) = &mut q.condition {
+                    cond.visit_literals(f);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+pub fn parse_query(i: &str) -> IResult<&str, Query> {
+    let (i, _) = multispace0(i)?;
+    alt((
+        map(parse_select, Query::Select),
+        map(parse_explain, Query::Explain),
         map(parse_insert, Query::Insert),
+        map(parse_delete, Query::Delete),
+        map(parse_update, Query::Update),
+        map(parse_alter_table, Query::AlterTable),
+        map(parse_create_table, Query::CreateTable),
+        map(parse_create_index, Query::CreateIndex),
+        map(parse_create_view, Query::CreateView),
+        map(parse_drop_view, Query::DropView),
+        map(parse_refresh_view, Query::RefreshView),
+        map(parse_comment, Query::Comment),
+        map(parse_create_schema, Query::CreateSchema),
+        parse_drop_schema,
+        map(parse_analyze, Query::Analyze),
+        map(parse_reindex, Query::Reindex),
+        map(parse_vacuum, Query::Vacuum),
+        map(parse_pragma, Query::Pragma),
+        map(parse_copy, Query::Copy),
+        parse_transaction,
+    ))(i)
+}
+
+/// Parses a script of statements separated by semicolons that makes up
+/// the whole input, allowing whitespace around them and a trailing
+/// semicolon.
+pub fn parse_script(i: &str) -> IResult<&str, Vec<Query>> {
+    all_consuming(terminated(
+        separated_list0(delimited(multispace0, char(';'), multispace0), parse_query),
+        tuple((multispace0, opt(char(';')), multispace0)),
+    ))(i)
+}
+
+/// Parses a statement that makes up the whole input, allowing whitespace
+/// around it and a trailing semicolon.
+pub fn parse_statement(i: &str) -> IResult<&str, Query> {
+    all_consuming(terminated(
+        parse_query,
+        tuple((multispace0, opt(char(';')), multispace0)),
     ))(i)
 }
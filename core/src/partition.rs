@@ -0,0 +1,381 @@
+//! Tables whose rows are split into partitions by the value of one column,
+//! chosen with `CREATE TABLE ... PARTITION BY RANGE (column) (PARTITION p0
+//! VALUES LESS THAN (10), ...)` or `PARTITION BY HASH (column) PARTITIONS
+//! n`.
+//!
+//! Each partition is a segment of the table's rows, kept as their
+//! positions as the rows change. A query whose WHERE condition compares
+//! the partitioning column with constants reads only the partitions that
+//! can hold matching rows; see [`crate::AccessPath::Partitions`].
+//!
+//! Partitions split the rows in memory only: a table is saved, logged and
+//! checkpointed whole, in the same files whether it is partitioned or
+//! not, and its partitions are worked out again when it is read back.
+
+use std::fmt;
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::{fnv1a, write_value};
+use crate::engine::{Engine, EngineError, Row, Table, Value};
+use crate::expr::Relation;
+use crate::index::{probe, AccessPath, IndexScan, Probe};
+use crate::parser::{Condition, Expr};
+
+/// How rows are assigned to partitions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PartitionScheme {
+    /// Named partitions in ascending order of bound, each holding the
+    /// values below its bound and not below the bound before. A bound of
+    /// `None` is MAXVALUE, above every value, and may only end the list.
+    /// NULL goes to the first partition.
+    Range(Vec<(String, Option<Value>)>),
+    /// This many partitions, named `p0`, `p1` and so on, each holding the
+    /// values that hash to it. NULL goes to the first partition.
+    Hash(usize),
+}
+
+/// The partitioning of a table; see [`Table::set_partitioning`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Partitioning {
+    pub column: String,
+    pub scheme: PartitionScheme,
+}
+
+impl Partitioning {
+    pub fn range(column: &str, bounds: Vec<(String, Option<Value>)>) -> Self {
+        Self {
+            column: column.to_string(),
+            scheme: PartitionScheme::Range(bounds),
+        }
+    }
+
+    pub fn hash(column: &str, partitions: usize) -> Self {
+        Self {
+            column: column.to_string(),
+            scheme: PartitionScheme::Hash(partitions),
+        }
+    }
+
+    /// The names of the partitions, in order.
+    pub fn names(&self) -> Vec<String> {
+        match &self.scheme {
+            PartitionScheme::Range(bounds) => bounds.iter().map(|(name, _)| name.clone()).collect(),
+            PartitionScheme::Hash(n) => (0..*n).map(|i| format!("p{}", i)).collect(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.scheme {
+            PartitionScheme::Range(bounds) => bounds.len(),
+            PartitionScheme::Hash(n) => *n,
+        }
+    }
+
+    /// The partition holding `value`, already of the column's type, or
+    /// `None` if it is above every bound.
+    fn bucket(&self, value: &Value) -> Option<usize> {
+        if value.is_null() {
+            return Some(0);
+        }
+        match &self.scheme {
+            PartitionScheme::Range(bounds) => bounds
+                .iter()
+                .position(|(_, bound)| bound.as_ref().is_none_or(|bound| value < bound)),
+            PartitionScheme::Hash(n) => Some((stable_hash(value) % *n as u64) as usize),
+        }
+    }
+
+    /// Whether partition `part` can hold a value in the range from `low`
+    /// to `high`.
+    fn overlaps(&self, part: usize, low: Bound<&Value>, high: Bound<&Value>) -> bool {
+        let PartitionScheme::Range(bounds) = &self.scheme else {
+            return true;
+        };
+        let below_upper = match (&bounds[part].1, low) {
+            (None, _) | (_, Bound::Unbounded) => true,
+            (Some(upper), Bound::Included(low) | Bound::Excluded(low)) => low < upper,
+        };
+        let lower = part.checked_sub(1).and_then(|prev| bounds[prev].1.as_ref());
+        let above_lower = match (lower, high) {
+            (None, _) | (_, Bound::Unbounded) => true,
+            (Some(lower), Bound::Included(high)) => high >= lower,
+            (Some(lower), Bound::Excluded(high)) => high > lower,
+        };
+        below_upper && above_lower
+    }
+}
+
+/// The clause that partitions a table this way, as CREATE TABLE takes it.
+impl fmt::Display for Partitioning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.scheme {
+            PartitionScheme::Range(bounds) => {
+                write!(f, "PARTITION BY RANGE ({}) (", self.column)?;
+                for (i, (name, bound)) in bounds.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match bound {
+                        Some(bound) => {
+                            write!(f, "PARTITION {} VALUES LESS THAN ({})", name, bound)?
+                        }
+                        None => write!(f, "PARTITION {} VALUES LESS THAN MAXVALUE", name)?,
+                    }
+                }
+                f.write_str(")")
+            }
+            PartitionScheme::Hash(n) => {
+                write!(f, "PARTITION BY HASH ({}) PARTITIONS {}", self.column, n)
+            }
+        }
+    }
+}
+
+impl Table {
+    /// Splits the rows into partitions by `partitioning`, or stops
+    /// partitioning them with `None`. Fails, changing nothing, if the
+    /// column does not exist, if the partitions are not well formed, or if
+    /// a row fits no partition.
+    pub fn set_partitioning(
+        &mut self,
+        partitioning: Option<Partitioning>,
+    ) -> Result<(), EngineError> {
+        let Some(mut partitioning) = partitioning else {
+            self.partitioning = None;
+            self.segments = Vec::new();
+            return Ok(());
+        };
+        let col_idx = self.column_positions(&[&partitioning.column])?[0];
+        let invalid = |why: &str| EngineError::InvalidOperation(why.to_string());
+        match &mut partitioning.scheme {
+            PartitionScheme::Hash(0) => return Err(invalid("a table needs a partition")),
+            PartitionScheme::Hash(_) => {}
+            PartitionScheme::Range(bounds) => {
+                if bounds.is_empty() {
+                    return Err(invalid("a table needs a partition"));
+                }
+                for (_, bound) in bounds.iter_mut() {
+                    if let Some(value) = bound.take() {
+                        if value.is_null() {
+                            return Err(invalid("a partition bound cannot be NULL"));
+                        }
+                        *bound = Some(self.accept(col_idx, value)?);
+                    }
+                }
+                if let Some(i) = bounds[..bounds.len() - 1]
+                    .iter()
+                    .position(|(_, bound)| bound.is_none())
+                {
+                    return Err(invalid(&format!(
+                        "MAXVALUE must bound the last partition, not {}",
+                        bounds[i].0
+                    )));
+                }
+                if bounds
+                    .windows(2)
+                    .any(|pair| matches!(pair, [(_, Some(a)), (_, Some(b))] if a >= b))
+                {
+                    return Err(invalid("partition bounds must rise"));
+                }
+                let mut names = bounds.iter().map(|(name, _)| name).collect::<Vec<_>>();
+                names.sort();
+                if names.windows(2).any(|pair| pair[0] == pair[1]) {
+                    return Err(invalid("partition names must differ"));
+                }
+            }
+        }
+        let mut segments = vec![Vec::new(); partitioning.len()];
+        for (row_idx, value) in self.column_values(col_idx).enumerate() {
+            let part = partitioning
+                .bucket(value)
+                .ok_or_else(|| no_partition(value))?;
+            segments[part].push(row_idx);
+        }
+        self.partitioning = Some(partitioning);
+        self.segments = segments;
+        Ok(())
+    }
+
+    /// Each partition's name with the number of rows in it.
+    pub fn partitions(&self) -> Vec<(String, usize)> {
+        let Some(partitioning) = &self.partitioning else {
+            return Vec::new();
+        };
+        partitioning
+            .names()
+            .into_iter()
+            .zip(self.segments.iter().map(Vec::len))
+            .collect()
+    }
+
+    fn partition_column(&self) -> Option<(&Partitioning, usize)> {
+        let partitioning = self.partitioning.as_ref()?;
+        let col_idx = self
+            .columns
+            .iter()
+            .position(|c| c.name == partitioning.column)?;
+        Some((partitioning, col_idx))
+    }
+
+    /// The partition of `row`; a row above every bound, which
+    /// [`Table::check_partition`] refuses, goes to the last.
+    fn partition_of(&self, row: &Row) -> Option<usize> {
+        let (partitioning, col_idx) = self.partition_column()?;
+        Some(
+            partitioning
+                .bucket(&row[col_idx])
+                .unwrap_or(partitioning.len() - 1),
+        )
+    }
+
+    /// Fails if `row`, about to be stored, fits no partition.
+    pub(crate) fn check_partition(&self, row: &Row) -> Result<(), EngineError> {
+        match self.partition_column() {
+            Some((partitioning, col_idx)) => partitioning
+                .bucket(&row[col_idx])
+                .map(|_| ())
+                .ok_or_else(|| no_partition(&row[col_idx])),
+            None => Ok(()),
+        }
+    }
+
+    /// Notes a row about to be appended at the end of the table.
+    pub(crate) fn segment_push(&mut self, row: &Row) {
+        if let Some(part) = self.partition_of(row) {
+            let row_idx = self.row_count();
+            self.segments[part].push(row_idx);
+        }
+    }
+
+    /// Moves row `row_idx`, just replaced, from the partition of `old`
+    /// to its own.
+    pub(crate) fn segment_replace(&mut self, row_idx: usize, old: &Row) {
+        let Some(from) = self.partition_of(old) else {
+            return;
+        };
+        let to = self.partition_of(&self.row(row_idx)).unwrap_or(from);
+        if from == to {
+            return;
+        }
+        if let Ok(at) = self.segments[from].binary_search(&row_idx) {
+            self.segments[from].remove(at);
+        }
+        let segment = &mut self.segments[to];
+        let at = segment.binary_search(&row_idx).unwrap_or_else(|at| at);
+        segment.insert(at, row_idx);
+    }
+
+    /// Assigns every row to its partition again, after the rows were
+    /// replaced.
+    pub(crate) fn rebuild_segments(&mut self) {
+        let Some((partitioning, col_idx)) = self.partition_column() else {
+            return;
+        };
+        let mut segments = vec![Vec::new(); partitioning.len()];
+        for (row_idx, value) in self.column_values(col_idx).enumerate() {
+            let part = partitioning.bucket(value).unwrap_or(partitioning.len() - 1);
+            segments[part].push(row_idx);
+        }
+        self.segments = segments;
+    }
+}
+
+/// A hash of `value` that is the same in every build, unlike the one
+/// [`std::hash::Hash`] gives, so that rows keep their partition.
+fn stable_hash(value: &Value) -> u64 {
+    let mut bytes = Vec::new();
+    match value {
+        // Zeros of either sign are equal, as are NaNs.
+        Value::Float(f) if *f == 0.0 => write_value(&mut bytes, &Value::Float(0.0)),
+        Value::Float(f) if f.is_nan() => write_value(&mut bytes, &Value::Float(f64::NAN)),
+        _ => write_value(&mut bytes, value),
+    }
+    fnv1a(&bytes)
+}
+
+fn no_partition(value: &Value) -> EngineError {
+    EngineError::InvalidOperation(format!("no partition holds the value {}", value))
+}
+
+impl Engine {
+    /// Partitions `table` from now on; see [`Table::set_partitioning`] and
+    /// [`Engine::set_table_layout`].
+    pub fn set_table_partitioning(
+        &mut self,
+        table: &str,
+        partitioning: Option<Partitioning>,
+    ) -> Result<(), EngineError> {
+        self.change_table(table, |t| t.set_partitioning(partitioning))
+    }
+}
+
+/// The rows of the partitions of `table` that can hold rows matching every
+/// one of `parts`, or `None` if every partition can.
+pub(crate) fn partition_scan(
+    table: &Table,
+    rel: &Relation,
+    parts: &[&Condition],
+) -> Option<IndexScan> {
+    let (partitioning, col_idx) = table.partition_column()?;
+    // Rows added to `Table::rows` directly are in no segment.
+    if table.segments.iter().map(Vec::len).sum::<usize>() != table.row_count() {
+        return None;
+    }
+    let mut keep = vec![true; partitioning.len()];
+    for (expr, probe) in parts.iter().filter_map(|part| probe(part)) {
+        let Expr::Column(column) = expr else {
+            continue;
+        };
+        if rel.resolve(column).ok() != Some(col_idx) {
+            continue;
+        }
+        // Constants are compared as the column stores them; one it would
+        // not store rules out nothing.
+        let stored = |value: &Value| table.accept(col_idx, value.clone()).ok();
+        match probe {
+            Probe::Keys(values) => {
+                let Some(buckets) = values
+                    .iter()
+                    .map(|v| stored(v).map(|v| partitioning.bucket(&v)))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+                for (part, keep) in keep.iter_mut().enumerate() {
+                    *keep &= buckets.contains(&Some(part));
+                }
+            }
+            Probe::Range(low, high) => {
+                let bound = |bound: Bound<&Value>| match bound {
+                    Bound::Included(v) => stored(v).map(Bound::Included),
+                    Bound::Excluded(v) => stored(v).map(Bound::Excluded),
+                    Bound::Unbounded => Some(Bound::Unbounded),
+                };
+                let (Some(low), Some(high)) = (bound(low), bound(high)) else {
+                    continue;
+                };
+                for (part, keep) in keep.iter_mut().enumerate() {
+                    *keep &= partitioning.overlaps(part, low.as_ref(), high.as_ref());
+                }
+            }
+        }
+    }
+    if keep.iter().all(|k| *k) {
+        return None;
+    }
+    let names = partitioning.names();
+    let mut rows = Vec::new();
+    let mut read = Vec::new();
+    for (part, _) in keep.iter().enumerate().filter(|(_, k)| **k) {
+        rows.extend_from_slice(&table.segments[part]);
+        read.push(names[part].clone());
+    }
+    rows.sort_unstable();
+    Some(IndexScan {
+        path: AccessPath::Partitions(read),
+        rows,
+        exact: false,
+    })
+}
@@ -0,0 +1,277 @@
+//! The plan a statement runs by, as a tree that tools built on the engine
+//! can show or test against, returned by [`Engine::plan`].
+//!
+//! Each node takes the rows of the node inside it. Planning picks indexes
+//! exactly as running the statement would, running its common table
+//! expressions and views to do so, but changes no rows.
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Scope, Table};
+use crate::expr::Relation;
+use crate::index::{check_hint, AccessPath};
+use crate::join::equi_keys;
+use crate::parser::{Condition, IndexHint, Query, SelectQuery};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Plan {
+    /// Every row of a table.
+    Scan {
+        table: String,
+    },
+    /// The rows stored under some keys of an index.
+    IndexLookup {
+        table: String,
+        index: String,
+    },
+    /// The rows in a range of keys of an ordered index.
+    IndexRange {
+        table: String,
+        index: String,
+    },
+    /// No row, as the bloom filter on `column` shows that none holds the
+    /// value compared with.
+    BloomFilter {
+        table: String,
+        column: String,
+    },
+    /// The rows of the partitions of a table that can hold matching rows.
+    Partitions {
+        table: String,
+        partitions: Vec<String>,
+    },
+    /// The rows of a common table expression or view, run first.
+    Subquery {
+        name: String,
+        plan: Box<Plan>,
+    },
+    /// The rows matching a condition.
+    Filter {
+        condition: String,
+        input: Box<Plan>,
+    },
+    /// Every pair of rows from the two sides. When `keys` names columns of
+    /// the left and right side that must be equal, a hash join pairs only
+    /// rows equal on them if the inputs are large enough.
+    Join {
+        left: Box<Plan>,
+        right: Box<Plan>,
+        keys: Vec<(String, String)>,
+    },
+    /// One row of aggregates over all rows.
+    Aggregate {
+        columns: Vec<String>,
+        input: Box<Plan>,
+    },
+    Sort {
+        column: String,
+        ascending: bool,
+        input: Box<Plan>,
+    },
+    /// The rows after the first `offset`, at most `limit` of them.
+    Limit {
+        limit: Option<usize>,
+        offset: usize,
+        input: Box<Plan>,
+    },
+    /// The select list evaluated on each row.
+    Project {
+        columns: Vec<String>,
+        input: Box<Plan>,
+    },
+    Insert {
+        table: String,
+    },
+    /// Changes the rows found by `input`.
+    Update {
+        table: String,
+        input: Box<Plan>,
+    },
+    /// Removes the rows found by `input`.
+    Delete {
+        table: String,
+        input: Box<Plan>,
+    },
+}
+
+impl Plan {
+    fn filter(condition: &Condition, input: Plan) -> Plan {
+        Plan::Filter {
+            condition: condition.to_string(),
+            input: Box::new(input),
+        }
+    }
+}
+
+impl Engine {
+    /// The plan `query` would run by. Only SELECT, EXPLAIN, INSERT, UPDATE
+    /// and DELETE have one; the plan of EXPLAIN is that of its query.
+    pub fn plan(&self, query: &Query) -> Result<Plan, EngineError> {
+        match query {
+            Query::Select(q) | Query::Explain(q) => self.plan_select(q, &Scope::new()),
+            Query::Insert(q) => {
+                self.table(&q.table)?;
+                Ok(Plan::Insert {
+                    table: q.table.clone(),
+                })
+            }
+            Query::Update(q) => Ok(Plan::Update {
+                table: q.table.clone(),
+                input: Box::new(self.plan_rows(&q.table, q.condition.as_ref())?),
+            }),
+            Query::Delete(q) => Ok(Plan::Delete {
+                table: q.table.clone(),
+                input: Box::new(self.plan_rows(&q.table, q.condition.as_ref())?),
+            }),
+            _ => Err(EngineError::InvalidOperation(
+                "only SELECT, EXPLAIN, INSERT, UPDATE and DELETE have a plan".to_string(),
+            )),
+        }
+    }
+
+    /// How UPDATE and DELETE find the rows of `name` matching `cond`.
+    fn plan_rows(&self, name: &str, cond: Option<&Condition>) -> Result<Plan, EngineError> {
+        let table = self.table(name)?;
+        let rel = Relation::from_table(name, table);
+        let scan = Plan::Scan {
+            table: name.to_string(),
+        };
+        Ok(self.plan_access(table, name, &rel, scan, cond, None))
+    }
+
+    /// How the rows of `table`, named `name`, matching `cond` are found:
+    /// through an index when one answers the condition, and otherwise by
+    /// testing every row of `source`.
+    fn plan_access(
+        &self,
+        table: &Table,
+        name: &str,
+        rel: &Relation,
+        source: Plan,
+        cond: Option<&Condition>,
+        hint: Option<&IndexHint>,
+    ) -> Plan {
+        let Some(cond) = cond else {
+            return source;
+        };
+        let Some(found) = self.index_lookup(table, rel, cond, hint) else {
+            return Plan::filter(cond, source);
+        };
+        let table = name.to_string();
+        let found_by = match found.path {
+            AccessPath::Scan => source,
+            AccessPath::IndexLookup(index) => Plan::IndexLookup { table, index },
+            AccessPath::IndexRange(index) => Plan::IndexRange { table, index },
+            AccessPath::BloomFilter(column) => Plan::BloomFilter { table, column },
+            AccessPath::Partitions(partitions) => Plan::Partitions { table, partitions },
+        };
+        if found.exact {
+            found_by
+        } else {
+            Plan::filter(cond, found_by)
+        }
+    }
+
+    fn plan_select(&self, q: &SelectQuery, outer: &Scope) -> Result<Plan, EngineError> {
+        let scoped;
+        let scope =
+            if q.with.is_empty() && !q.tables.iter().any(|t| self.views.contains_key(&t.name)) {
+                outer
+            } else {
+                scoped = self.materialize(q, outer)?;
+                &scoped
+            };
+
+        let single = q.tables.len() == 1;
+        let mut rel = Relation::default();
+        let mut plan: Option<Plan> = None;
+        for table_ref in &q.tables {
+            let table = self.get_table(scope, &table_ref.name)?;
+            let hint = table_ref.hint.as_ref();
+            check_hint(table, &table_ref.name, hint)?;
+            let table_rel = Relation::from_table(table_ref.qualifier(), table);
+            let source = if let Some(cte) = q.with.iter().find(|c| c.name == table_ref.name) {
+                Plan::Subquery {
+                    name: cte.name.clone(),
+                    plan: Box::new(self.plan_select(&cte.query, scope)?),
+                }
+            } else if let Some(view) = self.views.get(&table_ref.name) {
+                let view_scope = Scope {
+                    ctes: Default::default(),
+                    shared: scope.shared.clone(),
+                    now: scope.now,
+                };
+                Plan::Subquery {
+                    name: table_ref.name.clone(),
+                    plan: Box::new(self.plan_select(view, &view_scope)?),
+                }
+            } else {
+                Plan::Scan {
+                    table: table_ref.name.clone(),
+                }
+            };
+            plan = Some(match plan {
+                None if single => {
+                    let cond = q.condition.as_ref();
+                    self.plan_access(table, &table_ref.name, &table_rel, source, cond, hint)
+                }
+                None => source,
+                Some(left) => {
+                    let keys = q
+                        .condition
+                        .as_ref()
+                        .map_or_else(Vec::new, |cond| equi_keys(&rel, &table_rel, cond));
+                    let name = |(t, c): &(String, String)| format!("{}.{}", t, c);
+                    Plan::Join {
+                        left: Box::new(left),
+                        right: Box::new(source),
+                        keys: keys
+                            .into_iter()
+                            .map(|(l, r)| (name(&rel.columns[l]), name(&table_rel.columns[r])))
+                            .collect(),
+                    }
+                }
+            });
+            rel.columns.extend(table_rel.columns);
+        }
+        let mut plan = plan.ok_or_else(|| {
+            EngineError::InvalidQuery("a query must read at least one table".to_string())
+        })?;
+        if let (false, Some(cond)) = (single, &q.condition) {
+            plan = Plan::filter(cond, plan);
+        }
+
+        let columns = q
+            .columns
+            .iter()
+            .map(|item| item.expr.to_string())
+            .collect::<Vec<_>>();
+        let aggregate = q.columns.iter().any(|c| c.expr.contains_aggregate());
+        if aggregate {
+            plan = Plan::Aggregate {
+                columns: columns.clone(),
+                input: Box::new(plan),
+            };
+        } else if let Some((column, ascending)) = &q.order_by {
+            plan = Plan::Sort {
+                column: column.clone(),
+                ascending: *ascending,
+                input: Box::new(plan),
+            };
+        }
+        if q.limit.is_some() || q.offset.is_some() {
+            plan = Plan::Limit {
+                limit: q.limit,
+                offset: q.offset.unwrap_or(0),
+                input: Box::new(plan),
+            };
+        }
+        if !aggregate && !columns.is_empty() {
+            plan = Plan::Project {
+                columns,
+                input: Box::new(plan),
+            };
+        }
+        Ok(plan)
+    }
+}
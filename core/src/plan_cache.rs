@@ -0,0 +1,323 @@
+//! A cache of parsed statements for [`Engine::execute_sql`], so that
+//! statements of the same shape are parsed once.
+//!
+//! Statements are keyed by their text with whitespace collapsed and, where
+//! it is safe, with each string and number literal replaced by a
+//! placeholder. A statement found by its shape gets the literals of the new
+//! text put in place of the cached ones. Only SELECT, EXPLAIN, INSERT,
+//! UPDATE and DELETE are cached; any other statement empties the cache.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::engine::{Engine, EngineError, Row, Value};
+use crate::parser::{parse_literal, parse_statement, statement_error, Query};
+
+/// Number of statements cached by a new engine.
+pub const DEFAULT_PLAN_CACHE_SIZE: usize = 128;
+
+/// How the cache has been used, as returned by [`Engine::plan_cache_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    /// Number of statements cached now.
+    pub entries: usize,
+    /// Statements found in the cache.
+    pub hits: u64,
+    /// Statements that had to be parsed.
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    /// Text with placeholders for literals.
+    Shape(String),
+    /// Text with literals as written, for statements whose literals cannot
+    /// be told apart from the rest of the text.
+    Text(String),
+}
+
+#[derive(Debug)]
+struct Entry {
+    query: Query,
+    /// When the entry was last used, in lookups since the cache was made.
+    used: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct PlanCache {
+    capacity: usize,
+    entries: HashMap<Key, Entry>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for PlanCache {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_PLAN_CACHE_SIZE,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+/// A statement's text split into its shape and its literals.
+struct Shape<'a> {
+    /// The text with whitespace collapsed and literals as written.
+    text: String,
+    /// The text with each literal replaced by a placeholder, or `None`
+    /// when a number follows a sign, which the parser may read as part of
+    /// the literal.
+    shape: Option<String>,
+    literals: Vec<&'a str>,
+}
+
+/// Splits `sql` into its shape and literals, or `None` for an unterminated
+/// string or quoted name.
+fn split(sql: &str) -> Option<Shape<'_>> {
+    let bytes = sql.as_bytes();
+    let mut text = String::with_capacity(sql.len());
+    let mut shape = Some(String::with_capacity(sql.len()));
+    let mut literals = Vec::new();
+    let mut i = 0;
+    let push = |out: &mut String, shape: &mut Option<String>, s: &str| {
+        out.push_str(s);
+        if let Some(shape) = shape {
+            shape.push_str(s);
+        }
+    };
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        if c.is_ascii_whitespace() {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if !text.is_empty() && i < bytes.len() {
+                push(&mut text, &mut shape, " ");
+            }
+            continue;
+        }
+        // A string, with `E` in front when it takes backslash escapes.
+        let e_string = matches!(c, b'E' | b'e') && bytes.get(i + 1) == Some(&b'\'');
+        if c == b'\'' || e_string {
+            i += if e_string { 2 } else { 1 };
+            loop {
+                match bytes.get(i) {
+                    None => return None,
+                    Some(b'\\') if e_string => i += 2,
+                    Some(b'\'') if bytes.get(i + 1) == Some(&b'\'') => i += 2,
+                    Some(b'\'') => break,
+                    Some(_) => i += 1,
+                }
+            }
+            i += 1;
+            let literal = &sql[start..i];
+            text.push_str(literal);
+            if let Some(shape) = &mut shape {
+                shape.push_str(if e_string { "E'?'" } else { "'?'" });
+            }
+            literals.push(literal);
+        } else if c == b'"' || c == b'`' {
+            i += 1 + sql[i + 1..].find(c as char)? + 1;
+            push(&mut text, &mut shape, &sql[start..i]);
+        } else if c.is_ascii_alphabetic() || c == b'_' || !c.is_ascii() {
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || !bytes[i].is_ascii())
+            {
+                i += 1;
+            }
+            push(&mut text, &mut shape, &sql[start..i]);
+        } else if c.is_ascii_digit() {
+            i = number_end(bytes, i);
+            let literal = &sql[start..i];
+            if text.trim_end().ends_with(['-', '+']) {
+                shape = None;
+            }
+            text.push_str(literal);
+            if let Some(shape) = &mut shape {
+                shape.push('?');
+            }
+            literals.push(literal);
+        } else {
+            let len = sql[i..].chars().next().map_or(1, char::len_utf8);
+            i += len;
+            push(&mut text, &mut shape, &sql[start..i]);
+        }
+    }
+    Some(Shape {
+        text,
+        shape,
+        literals,
+    })
+}
+
+/// The end of the number starting at `i`: hex digits after `0x`, or digits
+/// with `_` separators, a fraction and an exponent.
+fn number_end(bytes: &[u8], mut i: usize) -> usize {
+    let digits = |i: &mut usize, hex: bool| {
+        while *i < bytes.len()
+            && (bytes[*i].is_ascii_digit()
+                || bytes[*i] == b'_'
+                || (hex && bytes[*i].is_ascii_hexdigit()))
+        {
+            *i += 1;
+        }
+    };
+    if bytes[i] == b'0' && matches!(bytes.get(i + 1), Some(b'x' | b'X')) {
+        i += 2;
+        digits(&mut i, true);
+        return i;
+    }
+    digits(&mut i, false);
+    if bytes.get(i) == Some(&b'.') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+        i += 1;
+        digits(&mut i, false);
+    }
+    if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(i + 1), Some(b'-' | b'+')));
+        if bytes.get(i + 1 + sign).is_some_and(u8::is_ascii_digit) {
+            i += 1 + sign;
+            digits(&mut i, false);
+        }
+    }
+    i
+}
+
+/// The literal values of a statement, in the order they are written, or
+/// `None` for a statement that is not cached.
+fn literal_values(query: &mut Query) -> Option<Vec<Value>> {
+    let mut values = Vec::new();
+    query
+        .visit_literals(&mut |v| values.push(v.clone()))
+        .then_some(values)
+}
+
+impl PlanCache {
+    fn get(&mut self, key: &Key) -> Option<&Query> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.used = self.clock;
+        Some(&entry.query)
+    }
+
+    fn insert(&mut self, key: Key, query: Query) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) {
+            self.shrink_to(self.capacity - 1);
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                query,
+                used: self.clock,
+            },
+        );
+    }
+
+    /// Drops the least recently used entries until at most `len` are left.
+    fn shrink_to(&mut self, len: usize) {
+        while self.entries.len() > len {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The cached statement `split` stands for, with its literals.
+    fn lookup(&mut self, split: &Shape) -> Option<Query> {
+        if let Some(shape) = &split.shape {
+            if let Some(cached) = self.get(&Key::Shape(shape.clone())) {
+                let values = split
+                    .literals
+                    .iter()
+                    .map(|l| parse_literal(l))
+                    .collect::<Option<Vec<_>>>()?;
+                let mut query = cached.clone();
+                let mut values = values.into_iter();
+                query.visit_literals(&mut |v| {
+                    if let Some(value) = values.next() {
+                        *v = value;
+                    }
+                });
+                return Some(query);
+            }
+        }
+        self.get(&Key::Text(split.text.clone())).cloned()
+    }
+
+    /// Caches a statement just parsed from `split`. It is cached by shape
+    /// when its literals are exactly those of the text, in order, and all
+    /// different: placing new literals by position is then sound.
+    fn store(&mut self, split: &Shape, query: &Query) {
+        let Some(values) = literal_values(&mut query.clone()) else {
+            return;
+        };
+        let matches_text = values.len() == split.literals.len()
+            && values
+                .iter()
+                .zip(&split.literals)
+                .all(|(v, l)| parse_literal(l).as_ref() == Some(v));
+        let distinct = values.iter().collect::<HashSet<_>>().len() == values.len();
+        let key = match &split.shape {
+            Some(shape) if matches_text && distinct => Key::Shape(shape.clone()),
+            _ => Key::Text(split.text.clone()),
+        };
+        self.insert(key, query.clone());
+    }
+}
+
+impl Engine {
+    /// Parses and runs one statement, reusing the parse of an earlier
+    /// statement of the same shape. Statements other than SELECT, EXPLAIN,
+    /// INSERT, UPDATE and DELETE empty the cache.
+    pub fn execute_sql(&mut self, sql: &str) -> Result<Vec<Row>, EngineError> {
+        let split = split(sql);
+        let cached = split.as_ref().and_then(|s| self.plan_cache.lookup(s));
+        let query = match cached {
+            Some(query) => {
+                self.plan_cache.hits += 1;
+                query
+            }
+            None => {
+                self.plan_cache.misses += 1;
+                let (_, query) = parse_statement(sql).map_err(|e| statement_error(sql, e))?;
+                if let Some(split) = &split {
+                    self.plan_cache.store(split, &query);
+                }
+                query
+            }
+        };
+        self.execute(query)
+    }
+
+    /// Sets how many statements [`Engine::execute_sql`] keeps parsed,
+    /// dropping the least recently used ones beyond that. Zero turns the
+    /// cache off.
+    pub fn set_plan_cache_size(&mut self, size: usize) {
+        self.plan_cache.capacity = size;
+        self.plan_cache.shrink_to(size);
+    }
+
+    pub fn plan_cache_stats(&self) -> PlanCacheStats {
+        PlanCacheStats {
+            entries: self.plan_cache.entries.len(),
+            hits: self.plan_cache.hits,
+            misses: self.plan_cache.misses,
+        }
+    }
+}
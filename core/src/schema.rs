@@ -0,0 +1,286 @@
+//! Views of the schema, for tools built on the engine that need to list
+//! tables and inspect their structure without reaching into [`Table`]
+//! internals, and the metadata that such tools can attach to it.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Column, Engine, EngineError, ForeignKey, Scope, Table, Typing};
+use crate::index::IndexStats;
+use crate::parser::SelectQuery;
+
+/// Metadata key under which COMMENT ON stores its text.
+pub const COMMENT_KEY: &str = "comment";
+
+/// Structure of one table, as returned by [`Engine::describe`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub constraints: Vec<Constraint>,
+    /// Names of the table's indexes, in order, leaving out those that
+    /// enforce a constraint.
+    pub indexes: Vec<String>,
+    pub auto_increment: Option<String>,
+    pub typing: Typing,
+    /// The table's own metadata; column metadata is on each column.
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Constraint {
+    PrimaryKey { columns: Vec<String> },
+    Unique { name: String, columns: Vec<String> },
+    ForeignKey(ForeignKey),
+}
+
+/// Size figures for one table, as returned by [`Engine::table_stats`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub rows: usize,
+}
+
+/// Memory and disk use of a table, as part of [`EngineStats`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableUsage {
+    pub name: String,
+    pub rows: usize,
+    /// Rough number of bytes the rows take up in memory.
+    pub row_memory: usize,
+    /// The table's indexes in name order, each with roughly how many bytes
+    /// its entries take up.
+    pub indexes: Vec<IndexStats>,
+    /// Bytes of the table's log on disk; 0 without logging.
+    pub disk: u64,
+    /// Whether the rows are spilled to disk to keep under
+    /// [`Engine::set_memory_limit`], taking up no memory.
+    #[serde(default)]
+    pub spilled: bool,
+}
+
+impl TableUsage {
+    /// Rough number of bytes the rows and index entries take up.
+    pub fn memory(&self) -> usize {
+        self.row_memory + self.indexes.iter().map(|i| i.memory).sum::<usize>()
+    }
+}
+
+/// Memory and disk use of the whole engine, as returned by
+/// [`Engine::stats`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineStats {
+    /// Every table, in name order.
+    pub tables: Vec<TableUsage>,
+    /// Rough number of bytes the rows and index entries of every table
+    /// take up in memory.
+    pub memory: usize,
+    /// Bytes of every file in the log directory, checkpoints included; 0
+    /// without logging.
+    pub disk: u64,
+}
+
+impl Engine {
+    /// Names of all tables, in order.
+    pub fn list_tables(&self) -> Vec<String> {
+        let mut names = self.tables.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Names of all views, plain and materialized, in order.
+    pub fn list_views(&self) -> Vec<String> {
+        let mut names = self
+            .views
+            .keys()
+            .chain(self.materialized.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// The query a view is defined by.
+    pub fn view_query(&self, name: &str) -> Option<&SelectQuery> {
+        self.views
+            .get(name)
+            .or_else(|| self.materialized.get(name).map(|view| &view.query))
+    }
+
+    /// Describes a table or view. A view has no constraints or indexes, and
+    /// its column types are taken from its current result, or from its
+    /// stored rows if it is materialized.
+    pub fn describe(&self, name: &str) -> Result<TableSchema, EngineError> {
+        let expanded;
+        let table = match (self.views.get(name), self.materialized.get(name)) {
+            (Some(view), _) => {
+                expanded = self.temporary_table(view, &Scope::new())?;
+                &expanded
+            }
+            (None, Some(view)) => &view.table,
+            (None, None) => self.table(name)?,
+        };
+        let mut constraints = Vec::new();
+        if let Some(columns) = &table.primary_key {
+            constraints.push(Constraint::PrimaryKey {
+                columns: columns.clone(),
+            });
+        }
+        for unique in &table.uniques {
+            if table.primary_key.is_none() || unique.name != "PRIMARY" {
+                constraints.push(Constraint::Unique {
+                    name: unique.name.clone(),
+                    columns: unique.columns.clone(),
+                });
+            }
+        }
+        constraints.extend(
+            table
+                .foreign_keys
+                .iter()
+                .cloned()
+                .map(Constraint::ForeignKey),
+        );
+        let mut indexes = table
+            .indices
+            .keys()
+            .filter(|name| !table.uniques.iter().any(|u| &u.name == *name))
+            .cloned()
+            .collect::<Vec<_>>();
+        indexes.sort();
+        Ok(TableSchema {
+            name: name.to_string(),
+            columns: table.columns.clone(),
+            constraints,
+            indexes,
+            auto_increment: table.auto_increment.as_ref().map(|a| a.column.clone()),
+            typing: table.typing,
+            metadata: table.metadata.clone(),
+        })
+    }
+
+    /// Sets a metadata entry of a table, or of one of its columns, removing
+    /// it when `value` is `None`. The change is kept as a change to the
+    /// table's definition is; see [`Engine::set_table_layout`].
+    pub fn set_metadata(
+        &mut self,
+        table: &str,
+        column: Option<&str>,
+        key: &str,
+        value: Option<String>,
+    ) -> Result<(), EngineError> {
+        self.change_table(table, |t| {
+            let metadata = t.metadata_mut(column)?;
+            match value {
+                Some(value) => metadata.insert(key.to_string(), value),
+                None => metadata.remove(key),
+            };
+            Ok(())
+        })
+    }
+
+    pub fn table_stats(&self, name: &str) -> Result<TableStats, EngineError> {
+        Ok(TableStats {
+            rows: self.table(name)?.row_count(),
+        })
+    }
+
+    /// How many rows each table holds and roughly how much memory and disk
+    /// it takes up, for enforcing quotas or finding what grew. Memory
+    /// figures are estimates of what the values own, not what the
+    /// allocator holds. Tables kept by attached storage are left out.
+    pub fn stats(&self) -> Result<EngineStats, EngineError> {
+        let mut names = self.tables.keys().collect::<Vec<_>>();
+        names.sort();
+        let mut tables = Vec::with_capacity(names.len());
+        for name in names {
+            let table = &self.tables[name];
+            tables.push(TableUsage {
+                name: name.clone(),
+                rows: self.spill.rows(name).unwrap_or(table.row_count()),
+                row_memory: table.row_memory(),
+                indexes: self.index_stats(name)?,
+                disk: self.log_size(name)?,
+                spilled: self.spill.holds(name),
+            });
+        }
+        Ok(EngineStats {
+            memory: tables.iter().map(TableUsage::memory).sum(),
+            disk: self.log_dir_size()?,
+            tables,
+        })
+    }
+
+    pub fn create_schema(&mut self, name: &str) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            if !engine.schemas.insert(name.to_string()) {
+                return Err(EngineError::SchemaExists(name.to_string()));
+            }
+            Ok(())
+        })
+    }
+
+    /// Drops a schema. With `cascade` its tables and views go with it;
+    /// otherwise it must be empty. Fails if a table outside the schema has
+    /// a foreign key to one inside it.
+    pub fn drop_schema(&mut self, name: &str, cascade: bool) -> Result<(), EngineError> {
+        self.change_schema(|engine| engine.drop_schema_now(name, cascade))
+    }
+
+    fn drop_schema_now(&mut self, name: &str, cascade: bool) -> Result<(), EngineError> {
+        if !self.schemas.contains(name) {
+            return Err(EngineError::SchemaNotFound(name.to_string()));
+        }
+        let inside = |n: &String| n.split_once('.').is_some_and(|(s, _)| s == name);
+        let empty = !self
+            .tables
+            .keys()
+            .chain(self.views.keys())
+            .chain(self.materialized.keys())
+            .any(inside);
+        if !empty && !cascade {
+            return Err(EngineError::InvalidQuery(format!(
+                "schema {} is not empty",
+                name
+            )));
+        }
+        for (table_name, table) in &self.tables {
+            if let Some(fk) = table
+                .foreign_keys
+                .iter()
+                .find(|fk| !inside(table_name) && inside(&fk.ref_table))
+            {
+                return Err(EngineError::InvalidQuery(format!(
+                    "foreign key {} of {} references {}",
+                    fk.name, table_name, fk.ref_table
+                )));
+            }
+        }
+        self.tables.retain(|n, _| !inside(n));
+        self.views.retain(|n, _| !inside(n));
+        self.materialized.retain(|n, _| !inside(n));
+        self.schemas.remove(name);
+        Ok(())
+    }
+
+    /// Names of all schemas, in order.
+    pub fn list_schemas(&self) -> Vec<String> {
+        self.schemas.iter().cloned().collect()
+    }
+
+    /// Checks that the schema a qualified name belongs to exists.
+    pub(crate) fn check_schema(&self, name: &str) -> Result<(), EngineError> {
+        match name.split_once('.') {
+            Some((schema, _)) if !self.schemas.contains(schema) => {
+                Err(EngineError::SchemaNotFound(schema.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn table(&self, name: &str) -> Result<&Table, EngineError> {
+        self.tables
+            .get(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))
+    }
+}
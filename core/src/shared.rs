@@ -0,0 +1,338 @@
+//! An engine shared between threads; see [`SharedEngine`].
+//!
+//! Each table has a lock of its own, and the rest of the engine, its
+//! settings, views, log, plan cache, last insert id and transaction,
+//! another. A SELECT or EXPLAIN shares the locks of the tables it reads. An
+//! INSERT, UPDATE or DELETE takes the locks of the tables it changes or
+//! reads to itself, with those of the tables linked to them by foreign
+//! keys. Both share the lock of the engine, so a write to one table waits
+//! for, and holds up, only the statements using that table.
+//!
+//! Every other statement takes the engine and all its tables to itself, as
+//! do INSERT, UPDATE and DELETE on an engine that logs its tables, keeps a
+//! memory limit or has materialized views, and those on attached tables.
+//! Locks are taken in one order, the engine's and then the tables' by name,
+//! so statements waiting on each other cannot deadlock.
+//!
+//! An open transaction belongs to the engine, not to a handle, so it would
+//! take in the writes of every thread. Transactions therefore run only
+//! through [`SharedEngine::transaction`], which holds the engine from BEGIN
+//! to COMMIT; BEGIN, COMMIT, ROLLBACK and the savepoint statements fail.
+//!
+//! A thread that panics while it holds a table to itself may leave it half
+//! changed, and later uses of the table fail with [`EngineError::Poisoned`];
+//! so does every later use once it held the whole engine.
+
+use std::collections::{BTreeSet, HashMap};
+use std::mem;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::engine::{Engine, EngineError, Row, Scope, Table};
+use crate::parser::{parse_statement, statement_error, Query};
+
+/// A handle to an engine that any number of threads can hold; see the
+/// [module docs](self). SELECT and EXPLAIN run side by side, and INSERT,
+/// UPDATE and DELETE alongside the statements using other tables; other
+/// statements run one at a time. Clones are handles to the same engine.
+///
+/// With a memory limit set, or tables spilled, reads take the engine to
+/// themselves too, since they may read spilled tables back in.
+#[derive(Clone, Default)]
+pub struct SharedEngine {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    /// The engine, which holds its tables only while a statement has it to
+    /// itself.
+    engine: RwLock<Engine>,
+    tables: RwLock<Tables>,
+}
+
+/// What a statement run on some tables of a shared engine returned, and
+/// the last insert id it left.
+type Written = (Result<Vec<Row>, EngineError>, Option<i128>);
+
+/// The tables of a shared engine, each behind a lock of its own.
+#[derive(Default)]
+struct Tables {
+    locks: HashMap<String, RwLock<Arc<Table>>>,
+    /// The tables the foreign keys of each table reference; see
+    /// [`Engine::references`].
+    references: HashMap<String, Vec<String>>,
+}
+
+impl SharedEngine {
+    pub fn new(mut engine: Engine) -> Self {
+        let tables = Tables::take(&mut engine);
+        Self {
+            shared: Arc::new(Shared {
+                engine: RwLock::new(engine),
+                tables: RwLock::new(tables),
+            }),
+        }
+    }
+
+    /// Runs a statement, alongside other threads where it can; see
+    /// [`Engine::execute`]. Fails for statements that open, end or mark a
+    /// transaction.
+    pub fn execute(&self, query: Query) -> Result<Vec<Row>, EngineError> {
+        if query.is_transaction_control() {
+            return Err(EngineError::InvalidOperation(
+                "transactions on a shared engine run through SharedEngine::transaction".to_string(),
+            ));
+        }
+        {
+            let engine = self.lock_read()?;
+            let tables = self.lock_tables()?;
+            if engine.reads_shared(&query) {
+                return tables.read(&engine, &query);
+            }
+            if let Some(used) = engine.writes_shared(&query, &tables.references) {
+                let last_insert_id = engine.last_insert_id;
+                let (rows, changed) = tables.write(&engine, &used, query)?;
+                drop((tables, engine));
+                if changed != last_insert_id {
+                    self.lock_write()?.last_insert_id = changed;
+                }
+                return rows;
+            }
+        }
+        self.exclusive(|engine| engine.execute(query))?
+    }
+
+    /// Parses and runs one statement; see [`SharedEngine::execute`].
+    pub fn execute_sql(&self, sql: &str) -> Result<Vec<Row>, EngineError> {
+        let (_, query) = parse_statement(sql).map_err(|e| statement_error(sql, e))?;
+        self.execute(query)
+    }
+
+    /// Runs `f` on the engine and all its tables once every other thread
+    /// has let go of them.
+    pub fn read<T>(&self, f: impl FnOnce(&Engine) -> T) -> Result<T, EngineError> {
+        self.exclusive(|engine| f(engine))
+    }
+
+    /// Runs `f` on the engine and all its tables once every other thread
+    /// has let go of them. A transaction `f` leaves open is rolled back,
+    /// failing the call.
+    pub fn write<T>(
+        &self,
+        f: impl FnOnce(&mut Engine) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        self.exclusive(|engine| {
+            let result = f(engine);
+            if engine.in_transaction() {
+                engine.rollback()?;
+                return Err(EngineError::InvalidOperation(
+                    "a transaction left open on a shared engine was rolled back".to_string(),
+                ));
+            }
+            result
+        })?
+    }
+
+    /// Runs `f` in a transaction of its own, holding the engine until it
+    /// commits or rolls back; see [`Engine::transaction`].
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&mut Engine) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        self.write(|engine| engine.transaction(f))
+    }
+
+    /// The engine, if this is the last handle to it.
+    pub fn into_inner(self) -> Result<Engine, EngineError> {
+        let shared = Arc::try_unwrap(self.shared).map_err(|_| {
+            EngineError::InvalidOperation("another handle holds the engine".to_string())
+        })?;
+        let mut engine = shared
+            .engine
+            .into_inner()
+            .map_err(|_| EngineError::Poisoned)?;
+        let mut tables = shared
+            .tables
+            .into_inner()
+            .map_err(|_| EngineError::Poisoned)?;
+        tables.put_back(&mut engine)?;
+        Ok(engine)
+    }
+
+    /// Runs `f` on the engine holding all its tables, once every other
+    /// thread has let go of it.
+    fn exclusive<T>(&self, f: impl FnOnce(&mut Engine) -> T) -> Result<T, EngineError> {
+        let mut engine = self.lock_write()?;
+        let mut tables = self
+            .shared
+            .tables
+            .write()
+            .map_err(|_| EngineError::Poisoned)?;
+        tables.put_back(&mut engine)?;
+        let result = f(&mut engine);
+        *tables = Tables::take(&mut engine);
+        Ok(result)
+    }
+
+    fn lock_read(&self) -> Result<RwLockReadGuard<'_, Engine>, EngineError> {
+        self.shared.engine.read().map_err(|_| EngineError::Poisoned)
+    }
+
+    fn lock_write(&self) -> Result<RwLockWriteGuard<'_, Engine>, EngineError> {
+        self.shared
+            .engine
+            .write()
+            .map_err(|_| EngineError::Poisoned)
+    }
+
+    fn lock_tables(&self) -> Result<RwLockReadGuard<'_, Tables>, EngineError> {
+        self.shared.tables.read().map_err(|_| EngineError::Poisoned)
+    }
+}
+
+impl From<Engine> for SharedEngine {
+    fn from(engine: Engine) -> Self {
+        Self::new(engine)
+    }
+}
+
+impl Tables {
+    /// Moves the tables of `engine` each behind a lock.
+    fn take(engine: &mut Engine) -> Self {
+        Self {
+            references: engine.references(),
+            locks: engine
+                .tables
+                .drain()
+                .map(|(name, table)| (name, RwLock::new(Arc::new(table))))
+                .collect(),
+        }
+    }
+
+    /// Moves the tables back into `engine`, unless a thread panicked while
+    /// it held one.
+    fn put_back(&mut self, engine: &mut Engine) -> Result<(), EngineError> {
+        if self.locks.values().any(RwLock::is_poisoned) {
+            return Err(EngineError::Poisoned);
+        }
+        for (name, lock) in self.locks.drain() {
+            let table = lock.into_inner().map_err(|_| EngineError::Poisoned)?;
+            engine.tables.insert(name, Arc::unwrap_or_clone(table));
+        }
+        self.references.clear();
+        Ok(())
+    }
+
+    /// Runs a SELECT or EXPLAIN sharing the locks of the tables it reads.
+    fn read(&self, engine: &Engine, query: &Query) -> Result<Vec<Row>, EngineError> {
+        let used = engine
+            .tables_linked(query, &HashMap::new())
+            .unwrap_or_default();
+        let mut locked = Vec::with_capacity(used.len());
+        for name in &used {
+            if let Some(lock) = self.locks.get(name) {
+                locked.push((name, lock.read().map_err(|_| EngineError::Poisoned)?));
+            }
+        }
+        let scope = Scope {
+            shared: locked
+                .iter()
+                .map(|(name, table)| ((*name).clone(), Arc::clone(table)))
+                .collect(),
+            ..Scope::new()
+        };
+        match query {
+            Query::Select(q) => engine.run_select(q, &scope).map(|result| result.rows),
+            Query::Explain(q) => engine.explain(q, &scope),
+            _ => unreachable!("only SELECT and EXPLAIN read shared"),
+        }
+    }
+
+    /// Runs `query` holding the locks of the tables `used` to itself, on an
+    /// engine of those tables and the settings of `engine`. Returns its
+    /// result and the last insert id it leaves.
+    fn write(
+        &self,
+        engine: &Engine,
+        used: &BTreeSet<String>,
+        query: Query,
+    ) -> Result<Written, EngineError> {
+        let mut locked = Vec::with_capacity(used.len());
+        for name in used {
+            if let Some(lock) = self.locks.get(name) {
+                locked.push((name, lock.write().map_err(|_| EngineError::Poisoned)?));
+            }
+        }
+        let placeholder = || Arc::new(Table::new(Vec::new()));
+        let mut scratch = engine.with_tables(
+            locked
+                .iter_mut()
+                .map(|(name, table)| {
+                    let table = mem::replace(&mut **table, placeholder());
+                    ((*name).clone(), Arc::unwrap_or_clone(table))
+                })
+                .collect(),
+        );
+        let rows = scratch.execute(query);
+        for (name, table) in &mut locked {
+            if let Some(changed) = scratch.tables.remove(*name) {
+                **table = Arc::new(changed);
+            }
+        }
+        Ok((rows, scratch.last_insert_id))
+    }
+}
+
+impl Engine {
+    /// Whether `query` can run with only shared access to the engine: a
+    /// SELECT or EXPLAIN that reads no spilled table and leaves no memory
+    /// limit to keep.
+    fn reads_shared(&self, query: &Query) -> bool {
+        matches!(query, Query::Select(_) | Query::Explain(_))
+            && self.memory_limit().is_none()
+            && self.spilled_tables().is_empty()
+    }
+
+    /// The tables an INSERT, UPDATE or DELETE may change or read, if it can
+    /// run with shared access to the engine and only those tables to
+    /// itself: if it changes no log, memory limit or materialized view of
+    /// the engine, and no attached table.
+    fn writes_shared(
+        &self,
+        query: &Query,
+        references: &HashMap<String, Vec<String>>,
+    ) -> Option<BTreeSet<String>> {
+        if !matches!(
+            query,
+            Query::Insert(_) | Query::Update(_) | Query::Delete(_)
+        ) || self.logs.is_some()
+            || self.memory_limit().is_some()
+            || !self.spilled_tables().is_empty()
+            || !self.materialized.is_empty()
+        {
+            return None;
+        }
+        let used = self.tables_linked(query, references)?;
+        if used.iter().any(|name| self.attached.contains_key(name)) {
+            return None;
+        }
+        Some(used)
+    }
+
+    /// An engine of the settings, functions and views of this one, holding
+    /// `tables`, to run a statement that uses no others.
+    fn with_tables(&self, tables: HashMap<String, Table>) -> Engine {
+        Engine {
+            tables,
+            functions: self.functions.clone(),
+            default_typing: self.default_typing,
+            bool_ints: self.bool_ints,
+            last_insert_id: self.last_insert_id,
+            views: self.views.clone(),
+            schemas: self.schemas.clone(),
+            sort_memory: self.sort_memory,
+            read_only: self.read_only,
+            ..Engine::default()
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Saving the whole engine to a file and reading it back.
+//!
+//! A snapshot starts with a 28-byte header: the bytes `MINISQL\0`, the
+//! format version as a little-endian `u32`, then the length of the body
+//! and an FNV-1a hash of it as little-endian `u64`s. The body is the
+//! tables, views, schemas, applied migrations and settings as JSON.
+//! Registered functions are not saved.
+//!
+//! A backup has the same header, starting `MSQLBAK\0` instead, with the
+//! length and hash of the body before compression, followed by the body
+//! compressed as a [`crate::compress`] block. [`Engine::backup_to`]
+//! encodes and writes the same file on a thread of its own, from a copy of
+//! the engine that shares the rows of its tables.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::fnv1a;
+use crate::compress::{compress, decompress};
+use crate::engine::{Engine, EngineError, Table, Typing};
+use crate::migrate::AppliedMigration;
+use crate::parser::SelectQuery;
+use crate::view::MaterializedView;
+
+const MAGIC: &[u8; 8] = b"MINISQL\0";
+const BACKUP_MAGIC: &[u8; 8] = b"MSQLBAK\0";
+/// Version of the format written; files of other versions are refused.
+pub const SNAPSHOT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 28;
+
+/// What [`Engine::save_with`] writes besides the schema and rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveOptions {
+    /// Whether index entries are saved. Without them the file is smaller,
+    /// and opening it rebuilds every index from the rows.
+    pub indexes: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self { indexes: true }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Body<'a> {
+    tables: Cow<'a, HashMap<String, Table>>,
+    views: Cow<'a, HashMap<String, SelectQuery>>,
+    materialized: Cow<'a, HashMap<String, MaterializedView>>,
+    schemas: Cow<'a, BTreeSet<String>>,
+    migrations: Cow<'a, [AppliedMigration]>,
+    default_typing: Typing,
+    bool_ints: bool,
+}
+
+impl Body<'_> {
+    /// The body with nothing borrowed, to move to another thread.
+    fn into_owned(self) -> Body<'static> {
+        Body {
+            tables: Cow::Owned(self.tables.into_owned()),
+            views: Cow::Owned(self.views.into_owned()),
+            materialized: Cow::Owned(self.materialized.into_owned()),
+            schemas: Cow::Owned(self.schemas.into_owned()),
+            migrations: Cow::Owned(self.migrations.into_owned()),
+            default_typing: self.default_typing,
+            bool_ints: self.bool_ints,
+        }
+    }
+
+    /// The body as JSON.
+    fn encode(&self) -> Result<Vec<u8>, EngineError> {
+        serde_json::to_vec(self)
+            .map_err(|e| EngineError::InvalidSnapshot(format!("cannot encode: {}", e)))
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> EngineError {
+    EngineError::Io(format!("{}: {}", path.display(), e))
+}
+
+fn corrupt(path: &Path) -> EngineError {
+    EngineError::Corruption {
+        location: path.display().to_string(),
+    }
+}
+
+/// Writes `bytes` beside `path` first and then renames the file over it,
+/// so a failed write leaves the old file whole.
+fn write_whole(path: &Path, bytes: &[u8]) -> Result<(), EngineError> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, bytes).map_err(|e| io_error(path, e))?;
+    fs::rename(&temp, path).map_err(|e| io_error(path, e))
+}
+
+/// The body of the snapshot at `path`, checked against its header.
+pub(crate) fn read_snapshot(path: &Path) -> Result<Vec<u8>, EngineError> {
+    let mut file = fs::read(path).map_err(|e| io_error(path, e))?;
+    let invalid = |why: &str| EngineError::InvalidSnapshot(format!("{}: {}", path.display(), why));
+    if file.len() < HEADER_LEN || &file[..8] != MAGIC {
+        return Err(invalid("not a snapshot"));
+    }
+    let version = u32::from_le_bytes(file[8..12].try_into().unwrap());
+    if version != SNAPSHOT_VERSION {
+        return Err(invalid(&format!(
+            "format version {} cannot be read, only {}",
+            version, SNAPSHOT_VERSION
+        )));
+    }
+    let len = u64::from_le_bytes(file[12..20].try_into().unwrap());
+    let hash = u64::from_le_bytes(file[20..28].try_into().unwrap());
+    let body = file.split_off(HEADER_LEN);
+    if body.len() as u64 != len || fnv1a(&body) != hash {
+        return Err(corrupt(path));
+    }
+    Ok(body)
+}
+
+/// Compresses a backup body and writes it to `path`.
+fn write_backup(path: &Path, body: &[u8]) -> Result<(), EngineError> {
+    let packed = compress(body);
+    let mut file = Vec::with_capacity(HEADER_LEN + packed.len());
+    file.extend_from_slice(BACKUP_MAGIC);
+    file.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    file.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    file.extend_from_slice(&fnv1a(body).to_le_bytes());
+    file.extend_from_slice(&packed);
+    write_whole(path, &file)
+}
+
+/// A backup being written by [`Engine::backup_to`].
+#[derive(Debug)]
+pub struct BackupJob {
+    path: PathBuf,
+    handle: JoinHandle<Result<(), EngineError>>,
+}
+
+impl BackupJob {
+    /// Where the backup is written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the backup is written, or has failed.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the backup to be written.
+    pub fn wait(self) -> Result<(), EngineError> {
+        self.handle.join().unwrap_or_else(|_| {
+            Err(EngineError::Io(format!(
+                "{}: backup thread panicked",
+                self.path.display()
+            )))
+        })
+    }
+}
+
+impl Engine {
+    /// Saves every table, view, schema and applied migration to `path`,
+    /// with index entries; see [`Engine::save_with`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        self.save_with(path, SaveOptions::default())
+    }
+
+    /// Saves the engine to `path`, replacing any file there. The snapshot
+    /// is written beside it first and then renamed over it, so a failed
+    /// save leaves the old file whole.
+    pub fn save_with(
+        &self,
+        path: impl AsRef<Path>,
+        options: SaveOptions,
+    ) -> Result<(), EngineError> {
+        let body = self.encode(options)?;
+        let mut file = Vec::with_capacity(HEADER_LEN + body.len());
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        file.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        file.extend_from_slice(&fnv1a(&body).to_le_bytes());
+        file.extend_from_slice(&body);
+        write_whole(path.as_ref(), &file)
+    }
+
+    /// Opens an engine saved by [`Engine::save`].
+    pub fn open(path: impl AsRef<Path>) -> Result<Engine, EngineError> {
+        let mut engine = Engine::new();
+        engine.load(path)?;
+        Ok(engine)
+    }
+
+    /// Replaces the engine's tables, views, schemas and migrations with
+    /// those saved in `path`, keeping its registered functions, so that
+    /// indexes over them can be rebuilt. Indexes saved without entries, or
+    /// over expressions, are rebuilt from the rows. The engine's settings
+    /// stay as they were: a logged engine checkpoints what it loaded, and
+    /// one with a memory limit spills down to it. Fails, changing
+    /// nothing, if the file is of another format version or holds an index
+    /// that does not rebuild, and with [`EngineError::Corruption`] if it
+    /// is damaged; and on an engine opened read only or inside a
+    /// transaction.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let path = path.as_ref();
+        let body = read_snapshot(path)?;
+        let invalid =
+            |why: &str| EngineError::InvalidSnapshot(format!("{}: {}", path.display(), why));
+        self.decode(&body, &invalid)
+    }
+
+    /// Backs the engine up to `path` as one compressed file, replacing any
+    /// file there. A backup holds what [`Engine::save`] does, without index
+    /// entries, so it depends on nothing but the rows and schema and can
+    /// be copied between machines; [`Engine::restore`] reads it back and
+    /// rebuilds the indexes.
+    pub fn backup(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let body = self.encode(SaveOptions { indexes: false })?;
+        write_backup(path.as_ref(), &body)
+    }
+
+    /// Backs the engine up to `path` as [`Engine::backup`] does, without
+    /// making the caller wait for it. The caller waits only for a copy of
+    /// the engine, which shares the rows of its tables until a statement
+    /// changes them, and for spilled tables to be read back; a thread of
+    /// its own encodes, compresses and writes the copy while statements go
+    /// on changing the engine. The backup holds none of their changes.
+    /// [`BackupJob::wait`] reports how it went.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<BackupJob, EngineError> {
+        let path = path.as_ref().to_path_buf();
+        let body = self.body(SaveOptions { indexes: false })?.into_owned();
+        let target = path.clone();
+        let handle = thread::Builder::new()
+            .name("minisql-backup".to_string())
+            .spawn(move || write_backup(&target, &body.encode()?))
+            .map_err(|e| io_error(&path, e))?;
+        Ok(BackupJob { path, handle })
+    }
+
+    /// Replaces the engine's tables, views, schemas and migrations with
+    /// those of the backup at `path`, as [`Engine::load`] does for a
+    /// snapshot. Fails, changing nothing, if the file is of another format
+    /// version, and with [`EngineError::Corruption`] if it is damaged.
+    pub fn restore(&mut self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let path = path.as_ref();
+        let file = fs::read(path).map_err(|e| io_error(path, e))?;
+        let invalid =
+            |why: &str| EngineError::InvalidSnapshot(format!("{}: {}", path.display(), why));
+        if file.len() < HEADER_LEN || &file[..8] != BACKUP_MAGIC {
+            return Err(invalid("not a backup"));
+        }
+        let version = u32::from_le_bytes(file[8..12].try_into().unwrap());
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(&format!(
+                "format version {} cannot be read, only {}",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+        let len = u64::from_le_bytes(file[12..20].try_into().unwrap());
+        let hash = u64::from_le_bytes(file[20..28].try_into().unwrap());
+        let body = usize::try_from(len)
+            .ok()
+            .and_then(|len| decompress(&file[HEADER_LEN..], len))
+            .filter(|body| fnv1a(body) == hash)
+            .ok_or_else(|| corrupt(path))?;
+        self.decode(&body, &invalid)
+    }
+
+    /// The snapshot body: the engine as JSON.
+    fn encode(&self, options: SaveOptions) -> Result<Vec<u8>, EngineError> {
+        self.body(options)?.encode()
+    }
+
+    /// What a snapshot of the engine holds. Tables are copied to read
+    /// spilled ones back or to leave out index entries, which shares their
+    /// rows.
+    fn body(&self, options: SaveOptions) -> Result<Body<'_>, EngineError> {
+        let tables = if options.indexes && self.spilled_tables().is_empty() {
+            Cow::Borrowed(&self.tables)
+        } else {
+            let mut tables = self.tables.clone();
+            for (name, table) in tables.iter_mut() {
+                if let Some(restored) = self.spill.restored(name, table)? {
+                    *table = restored;
+                }
+            }
+            if !options.indexes {
+                tables.values_mut().for_each(Table::drop_index_entries);
+            }
+            Cow::Owned(tables)
+        };
+        Ok(Body {
+            tables,
+            views: Cow::Borrowed(&self.views),
+            materialized: Cow::Borrowed(&self.materialized),
+            schemas: Cow::Borrowed(&self.schemas),
+            migrations: Cow::Borrowed(&self.migrations),
+            default_typing: self.default_typing,
+            bool_ints: self.bool_ints,
+        })
+    }
+
+    /// Replaces the engine's contents with those of a snapshot body,
+    /// keeping its registered functions and rebuilding stale indexes, the
+    /// partitions and the bloom filters.
+    fn decode(
+        &mut self,
+        body: &[u8],
+        invalid: &dyn Fn(&str) -> EngineError,
+    ) -> Result<(), EngineError> {
+        self.writable()?;
+        if self.in_transaction() {
+            return Err(EngineError::InvalidOperation(
+                "a snapshot cannot be loaded inside a transaction".to_string(),
+            ));
+        }
+        let body: Body = serde_json::from_slice(body).map_err(|e| invalid(&e.to_string()))?;
+        let mut restored = Engine::new();
+        restored.tables = body.tables.into_owned();
+        restored.views = body.views.into_owned();
+        restored.materialized = body.materialized.into_owned();
+        restored.schemas = body.schemas.into_owned();
+        restored.migrations = body.migrations.into_owned();
+        restored.default_typing = body.default_typing;
+        restored.bool_ints = body.bool_ints;
+        restored.functions = self.functions.clone();
+        let mut stale = restored
+            .tables
+            .iter()
+            .filter(|(_, table)| table.indices.values().any(|index| !index.is_ready()))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        stale.sort();
+        for name in &stale {
+            restored.rebuild_table_indexes(name, None)?;
+        }
+        for table in restored.tables.values_mut() {
+            table.rebuild_segments();
+            table.rebuild_bloom_filters();
+        }
+        // Spill files hold rows of the tables being replaced.
+        for name in self.spilled_tables() {
+            self.forget_spilled_table(&name);
+        }
+        self.tables = restored.tables;
+        self.views = restored.views;
+        self.materialized = restored.materialized;
+        self.schemas = restored.schemas;
+        self.migrations = restored.migrations;
+        self.default_typing = restored.default_typing;
+        self.bool_ints = restored.bool_ints;
+        self.last_insert_id = None;
+        self.plan_cache.clear();
+        self.checkpoint()?;
+        self.enforce_memory_limit()
+    }
+}
@@ -0,0 +1,172 @@
+//! Sorting for ORDER BY within a memory budget. Sort keys are gathered
+//! until they outgrow the budget, then sorted and written to a temporary
+//! file as a run; once every key is read the runs are merged, so that only
+//! one key per run is held at a time.
+//!
+//! A run is a sequence of records, each a little-endian `u32` length
+//! followed by a [`crate::codec`] row of the key and the row's position.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+use crate::codec::{decode_row, encode_row, encode_value};
+use crate::engine::{Engine, EngineError, Value};
+
+/// Bytes of sort keys an ORDER BY holds in memory before spilling, unless
+/// set otherwise with [`Engine::set_sort_memory`].
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+
+/// How sorts have spilled, as returned by [`Engine::sort_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SortStats {
+    /// Sorts that outgrew the memory budget.
+    pub spilled: u64,
+    /// Runs written to temporary files.
+    pub runs: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SortCounters {
+    spilled: AtomicU64,
+    runs: AtomicU64,
+}
+
+/// Numbers the run files of this process.
+static NEXT_RUN: AtomicU64 = AtomicU64::new(0);
+
+fn io_error(e: std::io::Error) -> EngineError {
+    EngineError::Io(e.to_string())
+}
+
+/// A sorted run in a temporary file, removed when dropped.
+struct Run {
+    path: PathBuf,
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Run {
+    /// Sorts `pairs` by key, keeping their order among equal keys, and
+    /// writes them out, leaving `pairs` empty.
+    fn write(pairs: &mut Vec<(Value, usize)>) -> Result<Run, EngineError> {
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        let run = Run {
+            path: std::env::temp_dir().join(format!(
+                "minisql-sort-{}-{}.run",
+                std::process::id(),
+                NEXT_RUN.fetch_add(1, AtomicOrdering::Relaxed)
+            )),
+        };
+        let mut out = BufWriter::new(File::create(&run.path).map_err(io_error)?);
+        for (key, pos) in pairs.drain(..) {
+            let record = encode_row(&vec![key, Value::Int(pos as i64)]);
+            out.write_all(&(record.len() as u32).to_le_bytes())
+                .and_then(|_| out.write_all(&record))
+                .map_err(io_error)?;
+        }
+        out.flush().map_err(io_error)?;
+        Ok(run)
+    }
+
+    fn reader(&self) -> Result<BufReader<File>, EngineError> {
+        File::open(&self.path).map(BufReader::new).map_err(io_error)
+    }
+}
+
+/// The next key and position of a run, or `None` at its end.
+fn next_record(reader: &mut impl Read) -> Result<Option<(Value, usize)>, EngineError> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.map_err(io_error)?,
+    }
+    let mut record = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut record).map_err(io_error)?;
+    let damaged = || EngineError::Io("a sort run is damaged".to_string());
+    match decode_row(&record).map_err(|_| damaged())?.as_slice() {
+        [key, Value::Int(pos)] => Ok(Some((key.clone(), *pos as usize))),
+        _ => Err(damaged()),
+    }
+}
+
+impl Engine {
+    /// Sets how many bytes of sort keys an ORDER BY holds in memory before
+    /// spilling sorted runs to temporary files.
+    pub fn set_sort_memory(&mut self, bytes: usize) {
+        self.sort_memory = Some(bytes);
+    }
+
+    pub fn sort_memory(&self) -> usize {
+        self.sort_memory.unwrap_or(DEFAULT_SORT_MEMORY)
+    }
+
+    pub fn sort_stats(&self) -> SortStats {
+        SortStats {
+            spilled: self.sort_counters.spilled.load(AtomicOrdering::Relaxed),
+            runs: self.sort_counters.runs.load(AtomicOrdering::Relaxed),
+        }
+    }
+
+    /// The positions of `keyed` in ascending order of their keys, equal
+    /// keys in the order they came.
+    pub(crate) fn sort_positions(
+        &self,
+        keyed: impl Iterator<Item = Result<(Value, usize), EngineError>>,
+    ) -> Result<Vec<usize>, EngineError> {
+        let budget = self.sort_memory();
+        let mut pairs = Vec::new();
+        let mut used = 0;
+        let mut runs = Vec::new();
+        for pair in keyed {
+            let pair = pair?;
+            used += std::mem::size_of_val(&pair) + encode_value(&pair.0).len();
+            pairs.push(pair);
+            if used > budget {
+                runs.push(Run::write(&mut pairs)?);
+                used = 0;
+            }
+        }
+        if runs.is_empty() {
+            pairs.sort_by(|a, b| a.0.cmp(&b.0));
+            return Ok(pairs.into_iter().map(|(_, pos)| pos).collect());
+        }
+        if !pairs.is_empty() {
+            runs.push(Run::write(&mut pairs)?);
+        }
+        self.sort_counters
+            .spilled
+            .fetch_add(1, AtomicOrdering::Relaxed);
+        self.sort_counters
+            .runs
+            .fetch_add(runs.len() as u64, AtomicOrdering::Relaxed);
+
+        // Earlier runs hold earlier rows, so equal keys are taken from the
+        // lowest run first.
+        let mut readers = runs
+            .iter()
+            .map(Run::reader)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut heads = BinaryHeap::new();
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some((key, pos)) = next_record(reader)? {
+                heads.push(Reverse((key, run, pos)));
+            }
+        }
+        let mut sorted = Vec::new();
+        while let Some(Reverse((_, run, pos))) = heads.pop() {
+            sorted.push(pos);
+            if let Some((key, pos)) = next_record(&mut readers[run])? {
+                heads.push(Reverse((key, run, pos)));
+            }
+        }
+        Ok(sorted)
+    }
+}
@@ -0,0 +1,342 @@
+//! A memory limit for the engine's tables, set with
+//! [`Engine::set_memory_limit`]. After each statement, while the rows and
+//! index entries of the tables take up more than the limit, the rows of
+//! the table used longest ago are written to a file in a directory of
+//! their own under [`std::env::temp_dir`], and the table keeps only its
+//! schema and index definitions. A statement that reads or changes a
+//! spilled table reads its rows back first and rebuilds its indexes.
+//!
+//! A spill file holds each row's [`crate::codec`] encoding behind its
+//! length as a little-endian `u32`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::codec::{decode_row, encode_row};
+use crate::engine::{Engine, EngineError, Row, Table};
+use crate::parser::Query;
+
+fn io_error(path: &Path, e: std::io::Error) -> EngineError {
+    EngineError::Io(format!("{}: {}", path.display(), e))
+}
+
+/// The rows of a table written out to make room.
+#[derive(Debug)]
+struct Spilled {
+    path: PathBuf,
+    rows: usize,
+}
+
+/// The memory limit and the tables spilled to keep under it.
+#[derive(Debug, Default)]
+pub(crate) struct Spill {
+    limit: Option<usize>,
+    /// The directory of the spill files, made the first time a table is
+    /// spilled and removed with the engine.
+    dir: Option<PathBuf>,
+    tables: HashMap<String, Spilled>,
+    /// When each table was last used by a statement, by a count of
+    /// statements.
+    used: HashMap<String, u64>,
+    clock: u64,
+    next_file: u64,
+}
+
+impl Spill {
+    /// Whether the rows of `name` are in a spill file.
+    pub(crate) fn holds(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    /// How many rows `name` has in its spill file, if it is spilled.
+    pub(crate) fn rows(&self, name: &str) -> Option<usize> {
+        self.tables.get(name).map(|spilled| spilled.rows)
+    }
+
+    /// A copy of `table`, the shell of the spilled table `name`, holding
+    /// its rows again but no index entries; `None` if it is not spilled.
+    pub(crate) fn restored(&self, name: &str, table: &Table) -> Result<Option<Table>, EngineError> {
+        let Some(spilled) = self.tables.get(name) else {
+            return Ok(None);
+        };
+        let mut table = table.clone();
+        table.set_rows(read_rows(&spilled.path)?);
+        Ok(Some(table))
+    }
+
+    fn write(&mut self, rows: &[Row]) -> Result<PathBuf, EngineError> {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => {
+                let dir = std::env::temp_dir().join(format!(
+                    "minisql-spill-{}-{}",
+                    std::process::id(),
+                    DIRS.fetch_add(1, Ordering::Relaxed)
+                ));
+                fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+                self.dir = Some(dir.clone());
+                dir
+            }
+        };
+        let path = dir.join(format!("{}.rows", self.next_file));
+        self.next_file += 1;
+        let file = File::create(&path).map_err(|e| io_error(&path, e))?;
+        let mut out = BufWriter::new(file);
+        for row in rows {
+            let bytes = encode_row(row);
+            out.write_all(&(bytes.len() as u32).to_le_bytes())
+                .and_then(|_| out.write_all(&bytes))
+                .map_err(|e| io_error(&path, e))?;
+        }
+        out.flush().map_err(|e| io_error(&path, e))?;
+        Ok(path)
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+fn read_rows(path: &Path) -> Result<Vec<Row>, EngineError> {
+    let bytes = fs::read(path).map_err(|e| io_error(path, e))?;
+    let damaged = || EngineError::Io(format!("{}: damaged spill file", path.display()));
+    let mut rows = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let len = rest
+            .get(..4)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+            .ok_or_else(damaged)?;
+        let row = rest.get(4..4 + len).ok_or_else(damaged)?;
+        rows.push(decode_row(row).map_err(|_| damaged())?);
+        rest = &rest[4 + len..];
+    }
+    Ok(rows)
+}
+
+impl Engine {
+    /// Keeps the rows and index entries of the tables, as
+    /// [`Engine::stats`] counts them, under `bytes` after each statement
+    /// run by [`Engine::execute`], by spilling the tables used longest ago
+    /// to disk; see the [module docs](self). `None`, the default, lets
+    /// them grow and reads every spilled table back.
+    ///
+    /// Statements read spilled tables back as they need them. Saving,
+    /// dumping and checkpoints read them from their files. Anything else
+    /// that reads [`Engine::tables`] directly should call
+    /// [`Engine::load_spilled`] first.
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) -> Result<(), EngineError> {
+        self.spill.limit = bytes;
+        match bytes {
+            Some(_) => self.enforce_memory_limit(),
+            None => self.load_spilled(),
+        }
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.spill.limit
+    }
+
+    /// Names of the tables whose rows are spilled to disk, in name order.
+    pub fn spilled_tables(&self) -> Vec<String> {
+        let mut names = self.spill.tables.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Reads every spilled table back into memory. They stay there until
+    /// the next statement run by [`Engine::execute`] finds the tables over
+    /// the memory limit.
+    pub fn load_spilled(&mut self) -> Result<(), EngineError> {
+        for name in self.spilled_tables() {
+            self.load_table(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the spill files without reading them back, for when the
+    /// tables are replaced.
+    pub(crate) fn forget_spilled(&mut self) {
+        for (_, spilled) in self.spill.tables.drain() {
+            let _ = fs::remove_file(spilled.path);
+        }
+    }
+
+    /// Deletes the spill file of `name` without reading it back, for when
+    /// the table is replaced.
+    pub(crate) fn forget_spilled_table(&mut self, name: &str) {
+        if let Some(spilled) = self.spill.tables.remove(name) {
+            let _ = fs::remove_file(spilled.path);
+        }
+    }
+
+    pub(crate) fn load_table(&mut self, name: &str) -> Result<(), EngineError> {
+        let Some(spilled) = self.spill.tables.get(name) else {
+            return Ok(());
+        };
+        let rows = read_rows(&spilled.path)?;
+        if let Some(spilled) = self.spill.tables.remove(name) {
+            let _ = fs::remove_file(spilled.path);
+        }
+        match self.tables.get_mut(name) {
+            Some(table) => {
+                table.set_rows(rows);
+                self.rebuild_table_indexes(name, None)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the rows of `name` to a spill file and drops them, along with
+    /// its index entries, from memory.
+    fn spill_table(&mut self, name: &str) -> Result<(), EngineError> {
+        let Some(table) = self.tables.get_mut(name) else {
+            return Ok(());
+        };
+        let rows = table.take_rows();
+        let path = match self.spill.write(&rows) {
+            Ok(path) => path,
+            Err(e) => {
+                table.set_rows(rows);
+                return Err(e);
+            }
+        };
+        table.drop_index_entries();
+        self.spill.tables.insert(
+            name.to_string(),
+            Spilled {
+                path,
+                rows: rows.len(),
+            },
+        );
+        Ok(())
+    }
+
+    /// The tables `query` may read or change, with the tables the views it
+    /// reads are built on and the tables linked to those by foreign keys;
+    /// `None` for a statement that may touch any table.
+    fn tables_used(&self, query: &Query) -> Option<BTreeSet<String>> {
+        self.tables_linked(query, &self.references())
+    }
+
+    /// The tables the foreign keys of each table reference, by table.
+    pub(crate) fn references(&self) -> HashMap<String, Vec<String>> {
+        self.tables
+            .iter()
+            .map(|(name, table)| {
+                let parents = table.foreign_keys.iter().map(|fk| fk.ref_table.clone());
+                (name.clone(), parents.collect())
+            })
+            .collect()
+    }
+
+    /// As [`Engine::tables_used`], with the foreign keys of the tables
+    /// given by `references`, as [`Engine::references`] lists them.
+    pub(crate) fn tables_linked(
+        &self,
+        query: &Query,
+        references: &HashMap<String, Vec<String>>,
+    ) -> Option<BTreeSet<String>> {
+        let mut pending = match query {
+            Query::Select(q) | Query::Explain(q) => q.table_names(),
+            Query::Insert(q) => vec![q.table.clone()],
+            Query::Update(q) => {
+                let mut names = vec![q.table.clone()];
+                let mut q = q.clone();
+                let mut add = |t: &mut crate::parser::TableRef| names.push(t.name.clone());
+                for (_, expr) in &mut q.assignments {
+                    expr.visit_tables(&mut add);
+                }
+                if let Some(cond) = &mut q.condition {
+                    cond.visit_tables(&mut add);
+                }
+                names
+            }
+            Query::Delete(q) => {
+                let mut names = vec![q.table.clone()];
+                if let Some(mut cond) = q.condition.clone() {
+                    cond.visit_tables(&mut |t| names.push(t.name.clone()));
+                }
+                names
+            }
+            _ => return None,
+        };
+        let mut used = BTreeSet::new();
+        while let Some(name) = pending.pop() {
+            if !used.insert(name.clone()) {
+                continue;
+            }
+            if let Some(view) = self.views.get(&name) {
+                pending.extend(view.table_names());
+            }
+            if let Some(parents) = references.get(&name) {
+                pending.extend(parents.iter().cloned());
+            }
+            pending.extend(
+                references
+                    .iter()
+                    .filter(|(_, parents)| parents.contains(&name))
+                    .map(|(child, _)| child.clone()),
+            );
+        }
+        Some(used)
+    }
+
+    /// Reads back the spilled tables `query` may use, and notes them as
+    /// used now.
+    pub(crate) fn load_for(&mut self, query: &Query) -> Result<(), EngineError> {
+        if self.spill.limit.is_none() && self.spill.tables.is_empty() {
+            return Ok(());
+        }
+        self.spill.clock += 1;
+        let used = match self.tables_used(query) {
+            Some(used) => used,
+            None => self.tables.keys().cloned().collect(),
+        };
+        for name in used {
+            if self.tables.contains_key(&name) {
+                self.load_table(&name)?;
+                self.spill.used.insert(name, self.spill.clock);
+            }
+        }
+        Ok(())
+    }
+
+    /// Spills the tables used longest ago until the rest fit in the memory
+    /// limit.
+    pub(crate) fn enforce_memory_limit(&mut self) -> Result<(), EngineError> {
+        let Some(limit) = self.spill.limit else {
+            return Ok(());
+        };
+        self.spill
+            .used
+            .retain(|name, _| self.tables.contains_key(name));
+        let mut resident = self
+            .tables
+            .iter()
+            .filter(|(name, _)| !self.spill.holds(name))
+            .map(|(name, table)| {
+                let used = self.spill.used.get(name).copied().unwrap_or(0);
+                (used, name.clone(), table.memory())
+            })
+            .collect::<Vec<_>>();
+        resident.sort();
+        let mut memory = self.tables.values().map(Table::memory).sum::<usize>();
+        for (_, name, table_memory) in resident {
+            if memory <= limit {
+                break;
+            }
+            self.spill_table(&name)?;
+            memory -= table_memory - self.tables[&name].memory();
+        }
+        Ok(())
+    }
+}
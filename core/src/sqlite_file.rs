@@ -0,0 +1,218 @@
+//! Reading SQLite database files, with the `sqlite` feature; see
+//! [`Engine::import_sqlite`].
+//!
+//! SQLite lets any column hold any value, so imported tables use
+//! [`Typing::Flexible`]. A column's type comes from its declared type when
+//! CREATE TABLE here would accept it, and otherwise from SQLite's own
+//! affinity rules: types naming `INT` become `INT`, those naming `CHAR`,
+//! `CLOB` or `TEXT`, and undeclared ones, become `TEXT`, and the rest,
+//! such as a bare `NUMERIC`, become `FLOAT`.
+
+use std::path::Path;
+
+use nom::combinator::all_consuming;
+use rusqlite::types::Value as SqliteValue;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::engine::{Engine, EngineError, Row, Typing, Value, ValueType};
+use crate::parser::parse_type;
+
+fn sqlite_error(path: &Path, e: impl std::fmt::Display) -> EngineError {
+    EngineError::Io(format!("{}: {}", path.display(), e))
+}
+
+/// The type of a column SQLite declared as `declared`.
+fn column_type(declared: &str) -> ValueType {
+    match all_consuming(parse_type)(declared.trim()) {
+        // A DECIMAL without a precision here has no digits after the
+        // point, while SQLite keeps whatever number it is given.
+        Ok((_, ValueType::Decimal { .. })) if !declared.contains('(') => {}
+        Ok((_, col_type)) => return col_type,
+        Err(_) => {}
+    }
+    let declared = declared.to_ascii_uppercase();
+    if declared.contains("INT") {
+        ValueType::Int
+    } else if ["CHAR", "CLOB", "TEXT", "BLOB"]
+        .iter()
+        .any(|name| declared.contains(name))
+        || declared.trim().is_empty()
+    {
+        ValueType::Text
+    } else {
+        ValueType::Float
+    }
+}
+
+/// The SQL value of a SQLite value stored in a column of type
+/// `col_type`. SQLite keeps booleans as 0 and 1, and blobs become text,
+/// failing if they are not UTF-8.
+fn to_value(value: SqliteValue, col_type: &ValueType, table: &str) -> Result<Value, EngineError> {
+    Ok(match value {
+        SqliteValue::Null => Value::Null,
+        SqliteValue::Integer(n @ (0 | 1)) if *col_type == ValueType::Bool => Value::Bool(n == 1),
+        SqliteValue::Integer(n) => Value::Int(n),
+        SqliteValue::Real(f) => Value::Float(f),
+        SqliteValue::Text(text) => Value::Text(text),
+        SqliteValue::Blob(bytes) => Value::Text(String::from_utf8(bytes).map_err(|_| {
+            EngineError::InvalidOperation(format!("table {} holds a BLOB that is not text", table))
+        })?),
+    })
+}
+
+/// A table of a SQLite file, as read by [`read_table`].
+struct SqliteTable {
+    name: String,
+    columns: Vec<(String, ValueType)>,
+    primary_key: Vec<String>,
+    /// Name, columns and uniqueness of each index over plain columns.
+    indexes: Vec<(String, Vec<String>, bool)>,
+    rows: Vec<Vec<SqliteValue>>,
+}
+
+fn read_tables(conn: &Connection) -> rusqlite::Result<Vec<SqliteTable>> {
+    conn.prepare(
+        "SELECT name FROM sqlite_master \
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY rowid",
+    )?
+    .query_map([], |row| row.get::<_, String>(0))?
+    .collect::<Result<Vec<_>, _>>()?
+    .iter()
+    .map(|name| read_table(conn, name))
+    .collect()
+}
+
+fn read_table(conn: &Connection, name: &str) -> rusqlite::Result<SqliteTable> {
+    let mut columns = Vec::new();
+    let mut primary_key = Vec::new();
+    let mut info = conn.prepare("SELECT name, type, pk FROM pragma_table_info(?1) ORDER BY cid")?;
+    let mut info_rows = info.query([name])?;
+    while let Some(row) = info_rows.next()? {
+        let column: String = row.get(0)?;
+        let declared: String = row.get(1)?;
+        let pk: i64 = row.get(2)?;
+        if pk > 0 {
+            primary_key.push((pk, column.clone()));
+        }
+        columns.push((column, column_type(&declared)));
+    }
+    primary_key.sort();
+
+    let mut indexes = Vec::new();
+    let mut list = conn.prepare(r#"SELECT name, "unique", origin FROM pragma_index_list(?1)"#)?;
+    let mut index_info = conn.prepare("SELECT name FROM pragma_index_info(?1) ORDER BY seqno")?;
+    let list = list
+        .query_map([name], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (index, unique, origin) in list {
+        if origin == "pk" {
+            continue;
+        }
+        // Expressions have no column name; such indexes are left out.
+        let index_columns = index_info
+            .query_map([&index], |row| row.get::<_, Option<String>>(0))?
+            .collect::<Result<Option<Vec<_>>, _>>()?;
+        if let Some(index_columns) = index_columns {
+            indexes.push((index, index_columns, unique));
+        }
+    }
+
+    let mut select = conn.prepare(&format!("SELECT * FROM \"{}\"", name.replace('"', "\"\"")))?;
+    let width = select.column_count();
+    let rows = select
+        .query_map([], |record| (0..width).map(|idx| record.get(idx)).collect())?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(SqliteTable {
+        name: name.to_string(),
+        columns,
+        primary_key: primary_key.into_iter().map(|(_, c)| c).collect(),
+        indexes,
+        rows,
+    })
+}
+
+impl Engine {
+    /// Recreates the tables of the SQLite database file at `path`, with
+    /// their rows, primary keys and indexes over plain columns, and returns
+    /// their names in the order the file created them. Views, triggers,
+    /// foreign keys and CHECK constraints are left out.
+    ///
+    /// BLOBs are read as text. Fails without creating anything if a
+    /// table's name is taken, if a BLOB is not UTF-8 text, or if the rows
+    /// break a primary key or UNIQUE index.
+    pub fn import_sqlite(&mut self, path: impl AsRef<Path>) -> Result<Vec<String>, EngineError> {
+        let path = path.as_ref();
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| sqlite_error(path, e))?;
+        let tables = read_tables(&conn).map_err(|e| sqlite_error(path, e))?;
+        if let Some(table) = tables.iter().find(|t| self.name_taken(&t.name)) {
+            return Err(EngineError::TableExists(table.name.clone()));
+        }
+
+        let mut created = Vec::new();
+        for table in tables {
+            match self.create_sqlite_table(table) {
+                Ok(name) => created.push(name),
+                Err(e) => {
+                    for name in &created {
+                        self.tables.remove(name);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    /// Creates `sqlite`'s table with its keys and indexes and adds its
+    /// rows, removing the table again if that fails.
+    fn create_sqlite_table(&mut self, sqlite: SqliteTable) -> Result<String, EngineError> {
+        let name = sqlite.name;
+        self.create_table_with_typing(&name, sqlite.columns, Typing::Flexible)?;
+        let result = (|| {
+            let table = self
+                .tables
+                .get_mut(&name)
+                .ok_or_else(|| EngineError::TableNotFound(name.clone()))?;
+            if !sqlite.primary_key.is_empty() {
+                let columns = sqlite.primary_key.iter().map(String::as_str);
+                table.set_primary_key(&columns.collect::<Vec<_>>())?;
+            }
+            for (index, columns, unique) in &sqlite.indexes {
+                let columns = columns.iter().map(String::as_str).collect::<Vec<_>>();
+                if *unique {
+                    table.add_unique(index, &columns)?;
+                } else {
+                    table.add_index(index, &columns, Default::default())?;
+                }
+            }
+            let rows = sqlite
+                .rows
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .enumerate()
+                        .map(|(idx, value)| {
+                            let value = to_value(value, &table.columns[idx].col_type, &name)?;
+                            table.accept(idx, value)
+                        })
+                        .collect::<Result<Row, _>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            self.insert_rows(&name, rows)
+        })();
+        match result {
+            Ok(_) => Ok(name),
+            Err(e) => {
+                self.tables.remove(&name);
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Table statistics gathered by ANALYZE, and the estimates the planner
+//! makes from them.
+//!
+//! Statistics are a snapshot: they are not updated as rows change, only
+//! when the table is analyzed again. Estimates scale them to the table's
+//! current row count.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Table, Value};
+
+/// Number of buckets in a column histogram.
+pub const HISTOGRAM_BUCKETS: usize = 16;
+
+/// What ANALYZE found in one column.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    /// Number of distinct non-NULL values.
+    pub distinct: usize,
+    pub nulls: usize,
+    /// Bounds of buckets holding about the same number of non-NULL values
+    /// each, in order: the smallest value, then the largest value of each
+    /// bucket. Empty if every value is NULL.
+    pub histogram: Vec<Value>,
+}
+
+/// What ANALYZE found in a table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Analysis {
+    /// Number of rows when the table was analyzed.
+    pub rows: usize,
+    pub columns: BTreeMap<String, ColumnStats>,
+}
+
+impl ColumnStats {
+    fn collect<'a>(values: impl Iterator<Item = &'a Value>) -> Self {
+        let mut nulls = 0;
+        let mut sorted = Vec::new();
+        for value in values {
+            if value.is_null() {
+                nulls += 1;
+            } else {
+                sorted.push(value);
+            }
+        }
+        sorted.sort();
+        let distinct = sorted.iter().collect::<HashSet<_>>().len();
+        let histogram = match sorted.len() {
+            0 => Vec::new(),
+            n => (0..=HISTOGRAM_BUCKETS)
+                .map(|b| sorted[b * (n - 1) / HISTOGRAM_BUCKETS].clone())
+                .collect(),
+        };
+        Self {
+            distinct,
+            nulls,
+            histogram,
+        }
+    }
+
+    /// Fraction of the non-NULL values equal to any of `count` distinct
+    /// values, taking every value to be equally common.
+    fn equal_fraction(&self, count: usize) -> f64 {
+        match self.distinct {
+            0 => 0.0,
+            distinct => (count as f64 / distinct as f64).min(1.0),
+        }
+    }
+
+    /// Fraction of the non-NULL values between the bounds, counted in
+    /// histogram buckets.
+    fn range_fraction(&self, low: Bound<&Value>, high: Bound<&Value>) -> f64 {
+        let buckets = self.histogram.len().saturating_sub(1);
+        if buckets == 0 {
+            // A single value, or none: it is either in range or not.
+            return match self.histogram.first() {
+                Some(v) if in_bounds(v, low, high) => 1.0,
+                _ => 0.0,
+            };
+        }
+        // A bucket partly in range counts as half in.
+        let within = self
+            .histogram
+            .windows(2)
+            .map(|bucket| {
+                match (
+                    in_bounds(&bucket[0], low, high),
+                    in_bounds(&bucket[1], low, high),
+                ) {
+                    (true, true) => 1.0,
+                    (false, false) if !straddles(&bucket[0], &bucket[1], low, high) => 0.0,
+                    _ => 0.5,
+                }
+            })
+            .sum::<f64>();
+        within / buckets as f64
+    }
+}
+
+fn in_bounds(value: &Value, low: Bound<&Value>, high: Bound<&Value>) -> bool {
+    let above = match low {
+        Bound::Included(l) => value >= l,
+        Bound::Excluded(l) => value > l,
+        Bound::Unbounded => true,
+    };
+    let below = match high {
+        Bound::Included(h) => value <= h,
+        Bound::Excluded(h) => value < h,
+        Bound::Unbounded => true,
+    };
+    above && below
+}
+
+/// Whether a range lies inside a bucket without reaching either end.
+fn straddles(first: &Value, last: &Value, low: Bound<&Value>, high: Bound<&Value>) -> bool {
+    let starts_after = match low {
+        Bound::Included(l) | Bound::Excluded(l) => l > first,
+        Bound::Unbounded => false,
+    };
+    let ends_before = match high {
+        Bound::Included(h) | Bound::Excluded(h) => h < last,
+        Bound::Unbounded => false,
+    };
+    starts_after && ends_before
+}
+
+impl Table {
+    /// Estimated number of rows whose `column` equals any of `count`
+    /// distinct non-NULL values, or `None` without statistics.
+    pub(crate) fn estimate_equal(&self, column: &str, count: usize) -> Option<f64> {
+        let (stats, non_null) = self.column_stats(column)?;
+        Some(stats.equal_fraction(count) * non_null)
+    }
+
+    /// Estimated number of rows whose `column` lies between the bounds, or
+    /// `None` without statistics.
+    pub(crate) fn estimate_range(
+        &self,
+        column: &str,
+        low: Bound<&Value>,
+        high: Bound<&Value>,
+    ) -> Option<f64> {
+        let (stats, non_null) = self.column_stats(column)?;
+        Some(stats.range_fraction(low, high) * non_null)
+    }
+
+    /// The statistics of `column`, with the number of rows now estimated
+    /// to hold a value in it.
+    fn column_stats(&self, column: &str) -> Option<(&ColumnStats, f64)> {
+        let stats = self.stats.as_ref()?;
+        let column_stats = stats.columns.get(column)?;
+        let null_fraction = match stats.rows {
+            0 => 0.0,
+            rows => column_stats.nulls as f64 / rows as f64,
+        };
+        Some((
+            column_stats,
+            self.row_count() as f64 * (1.0 - null_fraction),
+        ))
+    }
+}
+
+impl Engine {
+    /// Gathers statistics on every column of `table` for the planner,
+    /// replacing any gathered before.
+    pub fn analyze(&mut self, table: &str) -> Result<(), EngineError> {
+        self.change_table(table, |table| {
+            let columns = table
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(idx, column)| {
+                    let stats = ColumnStats::collect(table.column_values(idx));
+                    (column.name.clone(), stats)
+                })
+                .collect();
+            table.stats = Some(Analysis {
+                rows: table.row_count(),
+                columns,
+            });
+            Ok(())
+        })
+    }
+
+    /// Runs ANALYZE on one table, or on every table.
+    pub(crate) fn analyze_statement(&mut self, table: Option<&str>) -> Result<(), EngineError> {
+        match table {
+            Some(table) => self.analyze(table),
+            None => {
+                let mut names = self.tables.keys().cloned().collect::<Vec<_>>();
+                names.sort();
+                names.iter().try_for_each(|name| self.analyze(name))
+            }
+        }
+    }
+}
@@ -0,0 +1,605 @@
+//! Where the rows of a table are kept: the [`StorageEngine`] trait.
+//!
+//! A table made by [`Engine::create_table`] with the row layout keeps its
+//! rows in a [`MemoryStorage`], which it reads and changes in place rather
+//! than through the trait, since its indexes find rows by position; its
+//! indexes, constraints and log sit on top, in the [`Table`].
+//!
+//! Anything implementing [`StorageEngine`] can back a table attached with
+//! [`Engine::attach`]: a [`PagedTable`], an [`LsmStorage`], a
+//! [`MemoryStorage`], or a user's own type over sled, RocksDB or S3. Queries
+//! read an attached table by scanning it, and INSERT, UPDATE and DELETE
+//! change it through the trait.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::engine::{Column, Engine, EngineError, QueryResult, Row, Scope, Table, Value};
+use crate::expr::{Binder, Relation};
+use crate::lsm::LsmStorage;
+use crate::paged::{PagedTable, RowId};
+use crate::parser::{Condition, Expr, SelectQuery};
+
+/// Where the rows of a table are kept. Each row has an id, chosen by the
+/// storage when the row is inserted, that finds it until it is deleted.
+pub trait StorageEngine: Send {
+    fn columns(&self) -> &[Column];
+
+    /// Calls `f` on every row with its id, and stops at the first error
+    /// `f` returns.
+    fn scan(
+        &mut self,
+        f: &mut dyn FnMut(u64, Row) -> Result<(), EngineError>,
+    ) -> Result<(), EngineError>;
+
+    /// The row with `id`, or `None` if there is none.
+    fn get(&mut self, id: u64) -> Result<Option<Row>, EngineError>;
+
+    /// Stores `row`, which has a value for each column, returning its id.
+    fn insert(&mut self, row: Row) -> Result<u64, EngineError>;
+
+    /// Deletes the row with `id`, returning whether there was one.
+    fn delete(&mut self, id: u64) -> Result<bool, EngineError>;
+
+    /// Deletes the rows with `ids`, returning how many there were. Storage
+    /// that can delete many rows at once faster than one by one overrides
+    /// this default.
+    fn delete_many(&mut self, ids: &[u64]) -> Result<usize, EngineError> {
+        let mut deleted = 0;
+        for &id in ids {
+            deleted += self.delete(id)? as usize;
+        }
+        Ok(deleted)
+    }
+
+    /// Puts `row` in place of the row with `id`, returning the id it has
+    /// now, or `None` if there was no such row. This default deletes the
+    /// row and inserts `row`, which gets a new id.
+    fn update(&mut self, id: u64, row: Row) -> Result<Option<u64>, EngineError> {
+        if !self.delete(id)? {
+            return Ok(None);
+        }
+        self.insert(row).map(Some)
+    }
+
+    /// The ids of the rows in increasing order, and the rows in the same
+    /// order, for storage that keeps them in memory where they can be read
+    /// without copying. Other storage keeps this default, and is read with
+    /// `scan` and `get`.
+    fn rows_in_memory(&self) -> Option<(&[u64], &[Row])> {
+        None
+    }
+
+    /// Makes every change so far durable.
+    fn flush(&mut self) -> Result<(), EngineError>;
+
+    /// Gives back the space deleted rows left, returning how many bytes
+    /// were reclaimed; see [`Engine::vacuum`]. Ids may change. Storage that
+    /// has nothing to reclaim keeps this default, which does nothing.
+    fn vacuum(&mut self) -> Result<u64, EngineError> {
+        Ok(0)
+    }
+
+    /// Reads everything stored back and checks it against its checksums,
+    /// failing with [`EngineError::Corruption`] where it does not match;
+    /// see [`Engine::verify`]. Storage that keeps no checksums keeps this
+    /// default, which does nothing.
+    fn verify(&mut self) -> Result<(), EngineError> {
+        Ok(())
+    }
+}
+
+/// Rows kept in a vector in memory, in the order they were inserted, with
+/// ids counting up from 0. Nothing is written anywhere, so flushing does
+/// nothing. It is the storage of the engine's own tables.
+///
+/// Rows are stored as they are given; the engine checks them against the
+/// columns before they get here.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    columns: Vec<Column>,
+    ids: Vec<u64>,
+    rows: Vec<Row>,
+    next_id: u64,
+}
+
+impl MemoryStorage {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self {
+            columns,
+            ..Self::default()
+        }
+    }
+
+    /// The position of the row with `id`.
+    fn position(&self, id: u64) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
+    }
+}
+
+/// Removes the items at `doomed`, which is sorted, keeping the rest in
+/// order.
+fn remove_positions<T>(items: &mut Vec<T>, doomed: &[usize]) {
+    let mut doomed = doomed.iter().peekable();
+    let mut pos = 0;
+    items.retain(|_| {
+        let kept = doomed.next_if_eq(&&pos).is_none();
+        pos += 1;
+        kept
+    });
+}
+
+/// `row` checked against `columns` as [`Table::insert`] checks it.
+pub(crate) fn accepted(columns: &[Column], row: Row) -> Result<Row, EngineError> {
+    if row.len() != columns.len() {
+        return Err(EngineError::ValueCountMismatch);
+    }
+    columns
+        .iter()
+        .zip(row)
+        .map(|(column, value)| column.accept(value))
+        .collect()
+}
+
+impl StorageEngine for MemoryStorage {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn scan(
+        &mut self,
+        f: &mut dyn FnMut(u64, Row) -> Result<(), EngineError>,
+    ) -> Result<(), EngineError> {
+        for (&id, row) in self.ids.iter().zip(&self.rows) {
+            f(id, row.clone())?;
+        }
+        Ok(())
+    }
+
+    fn get(&mut self, id: u64) -> Result<Option<Row>, EngineError> {
+        Ok(self.position(id).map(|pos| self.rows[pos].clone()))
+    }
+
+    fn insert(&mut self, row: Row) -> Result<u64, EngineError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ids.push(id);
+        self.rows.push(row);
+        Ok(id)
+    }
+
+    fn delete(&mut self, id: u64) -> Result<bool, EngineError> {
+        let Some(pos) = self.position(id) else {
+            return Ok(false);
+        };
+        self.ids.remove(pos);
+        self.rows.remove(pos);
+        Ok(true)
+    }
+
+    fn delete_many(&mut self, ids: &[u64]) -> Result<usize, EngineError> {
+        let mut doomed = ids
+            .iter()
+            .filter_map(|&id| self.position(id))
+            .collect::<Vec<_>>();
+        doomed.sort_unstable();
+        doomed.dedup();
+        remove_positions(&mut self.rows, &doomed);
+        remove_positions(&mut self.ids, &doomed);
+        Ok(doomed.len())
+    }
+
+    fn update(&mut self, id: u64, row: Row) -> Result<Option<u64>, EngineError> {
+        Ok(self.position(id).map(|pos| {
+            self.rows[pos] = row;
+            id
+        }))
+    }
+
+    fn flush(&mut self) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    fn rows_in_memory(&self) -> Option<(&[u64], &[Row])> {
+        Some((&self.ids, &self.rows))
+    }
+}
+
+/// The rows of a table with the row layout, kept in a [`MemoryStorage`].
+/// The table's row at position `n` is the storage's `n`-th row. The table
+/// checks and describes the columns itself, so the storage is given none.
+/// Copies of the table share the rows until one of them changes.
+#[derive(Clone, Default)]
+pub(crate) struct TableStorage(Arc<MemoryStorage>);
+
+impl TableStorage {
+    pub(crate) fn from_rows(rows: Vec<Row>) -> Self {
+        let mut storage = MemoryStorage::new(Vec::new());
+        storage.ids = (0..rows.len() as u64).collect();
+        storage.next_id = rows.len() as u64;
+        storage.rows = rows;
+        Self(Arc::new(storage))
+    }
+
+    /// The rows, in table order.
+    pub(crate) fn rows(&self) -> &[Row] {
+        &self.0.rows
+    }
+
+    pub(crate) fn insert(&mut self, row: Row) {
+        let storage = Arc::make_mut(&mut self.0);
+        storage.ids.push(storage.next_id);
+        storage.next_id += 1;
+        storage.rows.push(row);
+    }
+
+    /// Puts `row` at `row_idx`, returning the row that was there.
+    pub(crate) fn replace(&mut self, row_idx: usize, row: Row) -> Row {
+        std::mem::replace(&mut Arc::make_mut(&mut self.0).rows[row_idx], row)
+    }
+
+    /// Deletes the rows at `doomed`, returning them in table order.
+    pub(crate) fn delete(&mut self, doomed: &BTreeSet<usize>) -> Vec<Row> {
+        let doomed = doomed.iter().copied().collect::<Vec<_>>();
+        let removed = doomed.iter().map(|&i| self.0.rows[i].clone()).collect();
+        let storage = Arc::make_mut(&mut self.0);
+        remove_positions(&mut storage.rows, &doomed);
+        remove_positions(&mut storage.ids, &doomed);
+        removed
+    }
+
+    /// Removes every row and returns them, copying them only if another
+    /// copy of the table shares them.
+    pub(crate) fn take(&mut self) -> Vec<Row> {
+        Arc::unwrap_or_clone(std::mem::take(&mut self.0)).rows
+    }
+}
+
+impl fmt::Debug for TableStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.rows()).finish()
+    }
+}
+
+/// Saved as the list of rows.
+impl Serialize for TableStorage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.rows())
+    }
+}
+
+impl<'de> Deserialize<'de> for TableStorage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<Row>::deserialize(deserializer).map(Self::from_rows)
+    }
+}
+
+/// A [`RowId`] as the id of a [`StorageEngine`] row: the page above the
+/// low 16 bits and the slot in them.
+fn row_id(id: u64) -> RowId {
+    RowId {
+        page: id >> 16,
+        slot: id as u16,
+    }
+}
+
+impl StorageEngine for PagedTable {
+    fn columns(&self) -> &[Column] {
+        PagedTable::columns(self)
+    }
+
+    fn scan(
+        &mut self,
+        f: &mut dyn FnMut(u64, Row) -> Result<(), EngineError>,
+    ) -> Result<(), EngineError> {
+        self.for_each(|id, row| f(id.page << 16 | u64::from(id.slot), row))
+    }
+
+    fn get(&mut self, id: u64) -> Result<Option<Row>, EngineError> {
+        PagedTable::get(self, row_id(id))
+    }
+
+    fn insert(&mut self, row: Row) -> Result<u64, EngineError> {
+        let id = PagedTable::insert(self, row)?;
+        Ok(id.page << 16 | u64::from(id.slot))
+    }
+
+    fn delete(&mut self, id: u64) -> Result<bool, EngineError> {
+        PagedTable::delete(self, row_id(id))
+    }
+
+    fn flush(&mut self) -> Result<(), EngineError> {
+        PagedTable::flush(self)
+    }
+
+    fn vacuum(&mut self) -> Result<u64, EngineError> {
+        PagedTable::vacuum(self)
+    }
+
+    fn verify(&mut self) -> Result<(), EngineError> {
+        PagedTable::verify(self)
+    }
+}
+
+impl StorageEngine for LsmStorage {
+    fn columns(&self) -> &[Column] {
+        LsmStorage::columns(self)
+    }
+
+    fn scan(
+        &mut self,
+        f: &mut dyn FnMut(u64, Row) -> Result<(), EngineError>,
+    ) -> Result<(), EngineError> {
+        self.for_each(f)
+    }
+
+    fn get(&mut self, id: u64) -> Result<Option<Row>, EngineError> {
+        LsmStorage::get(self, id)
+    }
+
+    fn insert(&mut self, row: Row) -> Result<u64, EngineError> {
+        LsmStorage::insert(self, row)
+    }
+
+    fn delete(&mut self, id: u64) -> Result<bool, EngineError> {
+        LsmStorage::delete(self, id)
+    }
+
+    fn flush(&mut self) -> Result<(), EngineError> {
+        LsmStorage::flush(self)
+    }
+
+    fn vacuum(&mut self) -> Result<u64, EngineError> {
+        LsmStorage::vacuum(self)
+    }
+
+    fn verify(&mut self) -> Result<(), EngineError> {
+        LsmStorage::verify(self)
+    }
+}
+
+/// An attached storage, locked so that queries holding only `&Engine` can
+/// scan it.
+pub(crate) type Attached = Mutex<Box<dyn StorageEngine>>;
+
+fn lock(storage: &Attached) -> MutexGuard<'_, Box<dyn StorageEngine>> {
+    // A storage whose user panicked is still used as it was left.
+    storage.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// An empty table with the columns of `storage`, to bind expressions to
+/// and to hold rows read from it.
+fn empty_table(storage: &dyn StorageEngine) -> Table {
+    let mut table = Table::new(Vec::new());
+    table.columns = storage.columns().to_vec();
+    table
+}
+
+impl Engine {
+    /// Has the storage attached as `name` reclaim the space of deleted
+    /// rows, returning how many bytes it gave back.
+    pub(crate) fn vacuum_attached(&self, name: &str) -> Result<u64, EngineError> {
+        match self.attached.get(name) {
+            Some(storage) => lock(storage).vacuum(),
+            None => Err(EngineError::TableNotFound(name.to_string())),
+        }
+    }
+
+    /// Has the storage attached as `name` check what it stores against its
+    /// checksums.
+    pub(crate) fn verify_attached(&self, name: &str) -> Result<(), EngineError> {
+        match self.attached.get(name) {
+            Some(storage) => lock(storage).verify(),
+            None => Err(EngineError::TableNotFound(name.to_string())),
+        }
+    }
+
+    /// Makes the rows of `storage` readable and writable by SQL as the
+    /// table `name`, which must not be taken by a table or view.
+    ///
+    /// Attached tables have no indexes or constraints, are not logged, and
+    /// are left out of [`Engine::save`] and [`Engine::dump`]; a statement
+    /// that fails part way may leave some of its changes in the storage.
+    pub fn attach(
+        &mut self,
+        name: &str,
+        storage: Box<dyn StorageEngine>,
+    ) -> Result<(), EngineError> {
+        if self.name_taken(name) {
+            return Err(EngineError::TableExists(name.to_string()));
+        }
+        self.check_schema(name)?;
+        self.attached.insert(name.to_string(), Mutex::new(storage));
+        Ok(())
+    }
+
+    /// Flushes the storage attached as `name` and hands it back.
+    pub fn detach(&mut self, name: &str) -> Result<Box<dyn StorageEngine>, EngineError> {
+        let storage = self
+            .attached
+            .remove(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let mut storage = storage.into_inner().unwrap_or_else(|e| e.into_inner());
+        storage.flush()?;
+        Ok(storage)
+    }
+
+    /// Flushes every attached storage, in name order.
+    pub fn flush_attached(&mut self) -> Result<(), EngineError> {
+        let mut names = self.attached.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            self.attached
+                .get_mut(&name)
+                .expect("an attached name")
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .flush()?;
+        }
+        Ok(())
+    }
+
+    /// The rows of the storage attached as `name`, as a temporary table
+    /// for `q`. When `q` reads only that table, rows are streamed through
+    /// its WHERE condition and only those matching are kept.
+    pub(crate) fn attached_table(
+        &self,
+        name: &str,
+        q: &SelectQuery,
+        scope: &Scope,
+    ) -> Result<Option<Table>, EngineError> {
+        let Some(storage) = self.attached.get(name) else {
+            return Ok(None);
+        };
+        let mut storage = lock(storage);
+        self.stored_rows(name, storage.as_mut(), q, scope).map(Some)
+    }
+
+    fn stored_rows(
+        &self,
+        name: &str,
+        storage: &mut dyn StorageEngine,
+        q: &SelectQuery,
+        scope: &Scope,
+    ) -> Result<Table, EngineError> {
+        let mut rows = empty_table(storage);
+        let filter = match (q.tables.as_slice(), &q.condition) {
+            ([table_ref], Some(cond)) if table_ref.name == name => {
+                let rel = Relation::from_table(table_ref.qualifier(), &rows);
+                Some(Binder::new(self, scope, &rel).condition(cond)?)
+            }
+            _ => None,
+        };
+        let mut matched = Vec::new();
+        storage.scan(&mut |_, row| {
+            if filter.as_ref().map_or(Ok(true), |f| f.matches(&row))? {
+                matched.push(row);
+            }
+            Ok(())
+        })?;
+        rows.set_rows(matched);
+        Ok(rows)
+    }
+
+    /// Runs `q`, which reads `storage` under the name `name`, alongside the
+    /// engine's own tables, as [`Engine::select_paged`] does for a
+    /// [`PagedTable`].
+    pub fn select_stored(
+        &self,
+        name: &str,
+        storage: &mut dyn StorageEngine,
+        q: &SelectQuery,
+    ) -> Result<QueryResult, EngineError> {
+        let mut scope = Scope::new();
+        let rows = self.stored_rows(name, storage, q, &scope)?;
+        scope.ctes.insert(name.to_string(), rows);
+        self.run_select(q, &scope)
+    }
+
+    /// The storage attached as `name`, for changing.
+    fn attached_mut(&mut self, name: &str) -> Result<&mut dyn StorageEngine, EngineError> {
+        let storage = self
+            .attached
+            .get_mut(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        Ok(storage
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut())
+    }
+
+    /// INSERT into an attached table; see [`Engine::insert_into`].
+    pub(crate) fn insert_attached(
+        &mut self,
+        name: &str,
+        values: Row,
+        columns: Option<Vec<String>>,
+    ) -> Result<(), EngineError> {
+        let storage = self.attached_mut(name)?;
+        let row = match columns {
+            Some(names) => {
+                if names.len() != values.len() {
+                    return Err(EngineError::ValueCountMismatch);
+                }
+                let table = empty_table(storage);
+                let mut row = table
+                    .columns
+                    .iter()
+                    .map(|c| Value::TypedNull(c.col_type.clone()))
+                    .collect::<Row>();
+                for (idx, value) in table.column_positions(&names)?.into_iter().zip(values) {
+                    row[idx] = value;
+                }
+                row
+            }
+            None => values,
+        };
+        storage.insert(accepted(storage.columns(), row)?)?;
+        Ok(())
+    }
+
+    /// The ids of the rows of the storage attached as `name` matching
+    /// `cond`, each with the row it becomes after `assignments`.
+    fn attached_changes(
+        &self,
+        name: &str,
+        assignments: &[(String, Expr)],
+        cond: Option<&Condition>,
+    ) -> Result<Vec<(u64, Row)>, EngineError> {
+        let storage = self
+            .attached
+            .get(name)
+            .ok_or_else(|| EngineError::TableNotFound(name.to_string()))?;
+        let mut storage = lock(storage);
+        let table = empty_table(storage.as_ref());
+        let scope = Scope::new();
+        let rel = Relation::from_table(name, &table);
+        let binder = Binder::new(self, &scope, &rel);
+        let filter = cond.map(|c| binder.condition(c)).transpose()?;
+        let assignments = assignments
+            .iter()
+            .map(|(column, expr)| Ok((rel.resolve(column)?, binder.expr(expr)?)))
+            .collect::<Result<Vec<_>, EngineError>>()?;
+        let mut changes = Vec::new();
+        storage.scan(&mut |id, row| {
+            if filter.as_ref().map_or(Ok(true), |f| f.matches(&row))? {
+                let mut new = row.clone();
+                for (col_idx, expr) in &assignments {
+                    new[*col_idx] = table.columns[*col_idx].accept(expr.eval(&row)?)?;
+                }
+                changes.push((id, new));
+            }
+            Ok(())
+        })?;
+        Ok(changes)
+    }
+
+    /// DELETE from an attached table; see [`Engine::delete`].
+    pub(crate) fn delete_attached(
+        &mut self,
+        name: &str,
+        cond: Option<&Condition>,
+    ) -> Result<usize, EngineError> {
+        let doomed = self.attached_changes(name, &[], cond)?;
+        let ids = doomed.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        self.attached_mut(name)?.delete_many(&ids)
+    }
+
+    /// UPDATE of an attached table; see [`Engine::update`].
+    pub(crate) fn update_attached(
+        &mut self,
+        name: &str,
+        assignments: &[(String, Expr)],
+        cond: Option<&Condition>,
+    ) -> Result<usize, EngineError> {
+        let changes = self.attached_changes(name, assignments, cond)?;
+        let storage = self.attached_mut(name)?;
+        for (id, row) in changes.iter().cloned() {
+            storage.update(id, row)?;
+        }
+        Ok(changes.len())
+    }
+}
@@ -0,0 +1,939 @@
+//! Keeping each table in an append-only log file, so that its rows
+//! survive the process; see [`Engine::enable_log`].
+//!
+//! A log starts with the bytes `MSQLLOG\0`, the format version as a
+//! little-endian `u32` and the checkpoint it follows as a little-endian
+//! `u64`, followed by records: a tag byte, the length of the payload as a
+//! little-endian `u32`, the payload, and an FNV-1a checksum of the three
+//! as a little-endian `u64`. The first record is the table without its
+//! rows, as JSON; each later one puts a row, or removes a row equal to it,
+//! in the row encoding of [`crate::codec`]. The records of one statement
+//! are wrapped in a batch record, so that a statement cut short by a crash
+//! is dropped whole; the records in a batch have no checksums of their
+//! own. A last record that fails its checksum was being written when the
+//! process stopped, and is dropped too, while one followed by others fails
+//! with [`EngineError::Corruption`]. A batch starts with a stamp record:
+//! the batch's log sequence number and the time it was written, as
+//! little-endian `u64` and `i64`.
+//!
+//! A checkpoint, see [`Engine::checkpoint`], saves the whole engine as a
+//! snapshot named `checkpoint-<n>.snapshot` beside the logs and starts
+//! every log afresh after it, so that a log holds only the changes made
+//! since. A log following checkpoint 0 follows none and holds every row
+//! of its table. When a log follows an earlier checkpoint than the latest,
+//! the process stopped part way through a checkpoint, which already holds
+//! the log's changes.
+//!
+//! The `history` file lists the checkpoints kept, one per line: its number,
+//! the last log sequence number it holds and the time it was taken. An
+//! engine keeping history, see [`Engine::set_history`], moves each log a
+//! checkpoint replaces to a segment `<table>.<n>.segment`, `n` being the
+//! checkpoint the log follows, so that [`Engine::open_at`] can replay an
+//! earlier checkpoint's segments up to a point.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::codec::{decode_row, encode_row, fnv1a};
+use crate::engine::{Engine, EngineError, Row, Table};
+use crate::snapshot::{read_snapshot, SaveOptions};
+
+const MAGIC: &[u8; 8] = b"MSQLLOG\0";
+const LOG_VERSION: u32 = 3;
+const EXTENSION: &str = "log";
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+const CHECKPOINT_EXTENSION: &str = "snapshot";
+
+/// Bytes logged after a checkpoint before the next is taken, unless set
+/// otherwise with [`Engine::set_checkpoint_size`].
+pub const DEFAULT_CHECKPOINT_SIZE: u64 = 16 * 1024 * 1024;
+
+const TABLE: u8 = b'T';
+const PUT: u8 = b'P';
+const TOMBSTONE: u8 = b'D';
+/// The records of one statement, read back all or none.
+const BATCH: u8 = b'B';
+/// The position of a batch.
+const STAMP: u8 = b'S';
+const HISTORY: &str = "history";
+const SEGMENT_EXTENSION: &str = "segment";
+
+/// The open logs of an engine that keeps them.
+#[derive(Debug)]
+pub(crate) struct TableLogs {
+    dir: PathBuf,
+    files: HashMap<String, File>,
+    /// The latest checkpoint, 0 before the first.
+    checkpoint: u64,
+    /// Bytes appended to the logs since that checkpoint.
+    appended: u64,
+    /// The last log sequence number given to a batch.
+    lsn: u64,
+}
+
+/// A point in the history of a logged engine; see [`Engine::open_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    /// Microseconds since the Unix epoch, as a TIMESTAMP holds.
+    Timestamp(i64),
+    /// A log sequence number, as [`Engine::last_lsn`] returns.
+    Lsn(u64),
+}
+
+/// Where a batch or checkpoint falls in the history.
+#[derive(Debug, Clone, Copy)]
+struct Stamp {
+    lsn: u64,
+    time: i64,
+}
+
+impl Stamp {
+    fn now(lsn: u64) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as i64);
+        Self { lsn, time }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.lsn.to_le_bytes().to_vec();
+        out.extend_from_slice(&self.time.to_le_bytes());
+        out
+    }
+
+    /// The stamp a batch starts with, if it has one.
+    fn of_batch(batch: &[u8]) -> Option<Self> {
+        match records(batch).next()? {
+            (STAMP, payload) if payload.len() == 16 => Some(Self {
+                lsn: u64_at(payload, 0),
+                time: u64_at(payload, 8) as i64,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl PointInTime {
+    fn includes(&self, stamp: Stamp) -> bool {
+        match *self {
+            PointInTime::Timestamp(time) => stamp.time <= time,
+            PointInTime::Lsn(lsn) => stamp.lsn <= lsn,
+        }
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> EngineError {
+    EngineError::Io(format!("{}: {}", path.display(), e))
+}
+
+/// The table and checkpoint of the segment at `path`, if it is one.
+fn segment_of(path: &Path) -> Option<(&str, u64)> {
+    if path.extension()? != SEGMENT_EXTENSION {
+        return None;
+    }
+    let (name, checkpoint) = path.file_stem()?.to_str()?.rsplit_once('.')?;
+    Some((name, checkpoint.parse().ok()?))
+}
+
+/// The number of the checkpoint at `path`, if it is one.
+fn checkpoint_number(path: &Path) -> Option<u64> {
+    if path.extension()? != CHECKPOINT_EXTENSION {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(CHECKPOINT_PREFIX)?
+        .parse()
+        .ok()
+}
+
+fn record(out: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// Adds a record to a log file, followed by its checksum.
+fn checked_record(out: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    let start = out.len();
+    record(out, tag, payload);
+    let checksum = fnv1a(&out[start..]);
+    out.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// The records that turn `old` rows into `new` ones: a tombstone for each
+/// row only in `old`, then the rows only in `new`, in their order.
+fn changes(old: &[Row], new: &[Row]) -> Vec<u8> {
+    let mut count: HashMap<&Row, isize> = HashMap::new();
+    for row in old {
+        *count.entry(row).or_default() += 1;
+    }
+    for row in new {
+        *count.entry(row).or_default() -= 1;
+    }
+    let mut out = Vec::new();
+    for row in old {
+        if let Some(n) = count.get_mut(row).filter(|n| **n > 0) {
+            *n -= 1;
+            record(&mut out, TOMBSTONE, &encode_row(row));
+        }
+    }
+    for row in new {
+        if let Some(n) = count.get_mut(row).filter(|n| **n < 0) {
+            *n += 1;
+            record(&mut out, PUT, &encode_row(row));
+        }
+    }
+    out
+}
+
+/// A record as its tag and payload.
+type Record<'a> = (u8, &'a [u8]);
+
+/// The records in `bytes`, each as its tag and payload. A record cut short
+/// was being written when the process stopped, and ends the list.
+fn records(bytes: &[u8]) -> impl Iterator<Item = Record<'_>> {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        let tag = *bytes.get(at)?;
+        let len = u32::from_le_bytes(bytes.get(at + 1..at + 5)?.try_into().unwrap()) as usize;
+        let payload = bytes.get(at + 5..at + 5 + len)?;
+        at += 5 + len;
+        Some((tag, payload))
+    })
+}
+
+/// The records of the log at `path` in `bytes`, starting at `at`, each
+/// followed by its checksum, and where the last whole one ends. A last
+/// record cut short or failing its checksum ends the list.
+fn checked_records<'a>(
+    path: &Path,
+    bytes: &'a [u8],
+    mut at: usize,
+) -> Result<(Vec<Record<'a>>, usize), EngineError> {
+    let mut found = Vec::new();
+    while let Some((tag, payload)) = records(&bytes[at..]).next() {
+        let end = at + 5 + payload.len();
+        let Some(checksum) = bytes.get(end..end + 8) else {
+            break;
+        };
+        if fnv1a(&bytes[at..end]).to_le_bytes() != checksum {
+            if end + 8 == bytes.len() {
+                break;
+            }
+            return Err(EngineError::Corruption {
+                location: format!("{}: record at byte {}", path.display(), at),
+            });
+        }
+        found.push((tag, payload));
+        at = end + 8;
+    }
+    Ok((found, at))
+}
+
+/// Rows put into a log and not yet removed.
+#[derive(Default)]
+struct Replay {
+    rows: Vec<Option<Row>>,
+    /// Positions in `rows` of the rows still there, by value.
+    live: HashMap<Row, Vec<usize>>,
+}
+
+impl Replay {
+    /// A replay starting from `rows`.
+    fn from_rows(rows: &[Row]) -> Self {
+        let mut replay = Replay::default();
+        for row in rows {
+            replay
+                .live
+                .entry(row.clone())
+                .or_default()
+                .push(replay.rows.len());
+            replay.rows.push(Some(row.clone()));
+        }
+        replay
+    }
+
+    fn apply(&mut self, table: &Table, tag: u8, payload: &[u8]) -> Result<(), &'static str> {
+        match tag {
+            BATCH => {
+                return records(payload)
+                    .try_for_each(|(tag, payload)| self.apply(table, tag, payload))
+            }
+            STAMP => return Ok(()),
+            _ => {}
+        }
+        let row = decode_row(payload)
+            .ok()
+            .filter(|row| row.len() == table.columns.len())
+            .ok_or("damaged row")?;
+        let row = row
+            .into_iter()
+            .enumerate()
+            .map(|(idx, value)| table.accept(idx, value))
+            .collect::<Result<Row, _>>()
+            .map_err(|_| "row does not fit its table")?;
+        match tag {
+            PUT => {
+                self.live
+                    .entry(row.clone())
+                    .or_default()
+                    .push(self.rows.len());
+                self.rows.push(Some(row));
+            }
+            TOMBSTONE => {
+                if let Some(row_idx) = self.live.get_mut(&row).and_then(Vec::pop) {
+                    self.rows[row_idx] = None;
+                }
+            }
+            _ => return Err("unknown record"),
+        }
+        Ok(())
+    }
+}
+
+/// A table read back from its log.
+struct Replayed {
+    /// `None` if the table was dropped before the checkpoint.
+    table: Option<Table>,
+    /// Whether the log follows the checkpoint, or none, so that changes
+    /// can be appended to it.
+    current: bool,
+    /// Where the last whole record of the log ends, if it is of the
+    /// current version; one of an older version is written afresh before
+    /// changes are appended to it.
+    whole: Option<u64>,
+    /// The last log sequence number replayed.
+    lsn: u64,
+}
+
+/// A table as its log reads back: its definition with the rows put and
+/// not removed since, in the order they were put, after the rows `saved`
+/// has for it in checkpoint `checkpoint`. Batches after `until` are left
+/// out.
+fn replay(
+    path: &Path,
+    checkpoint: u64,
+    saved: Option<&Table>,
+    until: Option<PointInTime>,
+) -> Result<Replayed, EngineError> {
+    let bytes = fs::read(path).map_err(|e| io_error(path, e))?;
+    let invalid = |why: &str| EngineError::InvalidSnapshot(format!("{}: {}", path.display(), why));
+    if bytes.len() < 12 || &bytes[..8] != MAGIC {
+        return Err(invalid("not a table log"));
+    }
+    // Logs of version 1 have no checkpoint and hold every row, and those
+    // before version 3 no checksums.
+    let (follows, records, whole) = match u32::from_le_bytes(bytes[8..12].try_into().unwrap()) {
+        1 => (0, records(&bytes[12..]).collect(), None),
+        2 if bytes.len() >= 20 => (u64_at(&bytes, 12), records(&bytes[20..]).collect(), None),
+        LOG_VERSION if bytes.len() >= 20 => {
+            let (records, end) = checked_records(path, &bytes, 20)?;
+            (u64_at(&bytes, 12), records, Some(end as u64))
+        }
+        version => {
+            return Err(invalid(&format!(
+                "format version {} cannot be read, only {}",
+                version, LOG_VERSION
+            )))
+        }
+    };
+    if follows > checkpoint {
+        return Err(invalid(&format!("checkpoint {} is missing", follows)));
+    }
+    if follows != 0 && follows < checkpoint {
+        return Ok(Replayed {
+            table: saved.cloned(),
+            current: false,
+            whole,
+            lsn: 0,
+        });
+    }
+    let mut records = records.into_iter();
+    let mut table: Table = match records.next() {
+        Some((TABLE, definition)) => {
+            serde_json::from_slice(definition).map_err(|e| invalid(&e.to_string()))?
+        }
+        _ => return Err(invalid("no table record")),
+    };
+    let mut replay = match saved {
+        Some(saved) if follows != 0 => Replay::from_rows(&saved.all_rows()),
+        _ => Replay::default(),
+    };
+    let mut lsn = 0;
+    for (tag, payload) in records {
+        if let Some(stamp) = (tag == BATCH).then(|| Stamp::of_batch(payload)).flatten() {
+            if until.is_some_and(|until| !until.includes(stamp)) {
+                break;
+            }
+            lsn = lsn.max(stamp.lsn);
+        }
+        replay.apply(&table, tag, payload).map_err(invalid)?;
+    }
+    let rows = replay.rows.into_iter().flatten().collect::<Vec<_>>();
+    for row in &rows {
+        table.note_auto_increment(row);
+    }
+    table.set_rows(rows);
+    Ok(Replayed {
+        table: Some(table),
+        current: true,
+        whole,
+        lsn,
+    })
+}
+
+/// The checkpoints listed in the history of `dir`, oldest first. A line
+/// cut short by a crash is skipped.
+fn read_history(dir: &Path) -> Result<Vec<(u64, Stamp)>, EngineError> {
+    let path = dir.join(HISTORY);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(&path, e)),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ').map(str::parse::<i64>);
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(n)), Some(Ok(lsn)), Some(Ok(time))) => Some((
+                    n as u64,
+                    Stamp {
+                        lsn: lsn as u64,
+                        time,
+                    },
+                )),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// The log of each table in `dir`, or its segment following checkpoint
+/// `segment`, by table name.
+fn log_paths(dir: &Path, segment: Option<u64>) -> Result<Vec<(String, PathBuf)>, EngineError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| io_error(dir, e))? {
+        let path = entry.map_err(|e| io_error(dir, e))?.path();
+        let name = match segment {
+            None if path.extension().is_some_and(|ext| ext == EXTENSION) => {
+                path.file_stem().and_then(|s| s.to_str())
+            }
+            Some(checkpoint) => segment_of(&path)
+                .filter(|(_, n)| *n == checkpoint)
+                .map(|(name, _)| name),
+            None => None,
+        };
+        if let Some(name) = name {
+            paths.push((name.to_string(), path.clone()));
+        }
+    }
+    Ok(paths)
+}
+
+/// An engine read back from a checkpoint and its logs by [`recover`].
+struct Recovered {
+    engine: Engine,
+    /// The tables whose logs follow the checkpoint, each with where its
+    /// last whole record ends as [`Replayed::whole`] has it.
+    current: Vec<(String, Option<u64>)>,
+    /// The last log sequence number replayed.
+    lsn: u64,
+}
+
+/// The engine saved in checkpoint `checkpoint` of `dir`, if any, with the
+/// logs at `paths` replayed over it up to `until`.
+fn recover(
+    dir: &Path,
+    checkpoint: Option<u64>,
+    paths: &[(String, PathBuf)],
+    until: Option<PointInTime>,
+) -> Result<Recovered, EngineError> {
+    let mut engine = Engine::new();
+    if let Some(checkpoint) = checkpoint {
+        engine.load(checkpoint_path(dir, checkpoint))?;
+    }
+    let saved = std::mem::take(&mut engine.tables);
+    let mut current = Vec::new();
+    let mut lsn = 0;
+    for (name, path) in paths {
+        let replayed = replay(path, checkpoint.unwrap_or(0), saved.get(name), until)?;
+        lsn = lsn.max(replayed.lsn);
+        if let Some(table) = replayed.table {
+            engine.tables.insert(name.clone(), table);
+            if replayed.current {
+                current.push((name.clone(), replayed.whole));
+            }
+        }
+    }
+    // A table whose log the checkpoint did not get to start afresh is as
+    // the checkpoint saved it.
+    for (name, table) in saved {
+        engine.tables.entry(name).or_insert(table);
+    }
+    let mut names = engine.tables.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    for name in &names {
+        engine.rebuild_table_indexes(name, None)?;
+        if let Some(table) = engine.tables.get_mut(name) {
+            table.rebuild_bloom_filters();
+        }
+    }
+    Ok(Recovered {
+        engine,
+        current,
+        lsn,
+    })
+}
+
+fn checkpoint_path(dir: &Path, checkpoint: u64) -> PathBuf {
+    dir.join(format!(
+        "{}{}.{}",
+        CHECKPOINT_PREFIX, checkpoint, CHECKPOINT_EXTENSION
+    ))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+impl TableLogs {
+    fn path(&self, table: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", table, EXTENSION))
+    }
+
+    /// Rewrites the log of `name` to hold `table` as it stands, or only its
+    /// definition when `rows` is false, to follow the latest checkpoint.
+    fn write(&mut self, name: &str, table: &Table, rows: bool) -> Result<(), EngineError> {
+        let path = self.path(name);
+        let mut header = table.clone();
+        header.set_rows(Vec::new());
+        header.drop_index_entries();
+        let definition =
+            serde_json::to_vec(&header).map_err(|e| io_error(&path, std::io::Error::other(e)))?;
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&LOG_VERSION.to_le_bytes());
+        let follows = if rows { 0 } else { self.checkpoint };
+        out.extend_from_slice(&follows.to_le_bytes());
+        checked_record(&mut out, TABLE, &definition);
+        if rows {
+            let mut puts = Vec::new();
+            for row in table.all_rows().iter() {
+                record(&mut puts, PUT, &encode_row(row));
+            }
+            checked_record(&mut out, BATCH, &self.stamped(&puts));
+        }
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, &out).map_err(|e| io_error(&path, e))?;
+        fs::rename(&temp, &path).map_err(|e| io_error(&path, e))?;
+        self.open(name)
+    }
+
+    fn open(&mut self, name: &str) -> Result<(), EngineError> {
+        let path = self.path(name);
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| io_error(&path, e))?;
+        self.files.insert(name.to_string(), file);
+        Ok(())
+    }
+
+    /// `records` behind the stamp of a new batch.
+    fn stamped(&mut self, records: &[u8]) -> Vec<u8> {
+        self.lsn += 1;
+        let mut out = Vec::with_capacity(records.len() + 21);
+        record(&mut out, STAMP, &Stamp::now(self.lsn).encode());
+        out.extend_from_slice(records);
+        out
+    }
+
+    /// Appends the records of one statement as a batch.
+    fn append(&mut self, name: &str, records: &[u8]) -> Result<(), EngineError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let path = self.path(name);
+        let records = self.stamped(records);
+        let mut batch = Vec::with_capacity(records.len() + 5);
+        checked_record(&mut batch, BATCH, &records);
+        let file = self.files.get_mut(name).expect("log is open");
+        file.write_all(&batch)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| io_error(&path, e))?;
+        self.appended += batch.len() as u64;
+        Ok(())
+    }
+}
+
+impl Engine {
+    /// Keeps every table in a log file in `dir`, creating the directory if
+    /// needed and writing each table's log afresh. From then on each
+    /// INSERT, UPDATE and DELETE appends the rows it put and removed to the
+    /// logs of the tables it changed, and a schema change made through
+    /// [`Engine::execute`] rewrites the logs. Changes made to
+    /// [`Engine::tables`] directly reach a log when it is next compacted.
+    ///
+    /// Views, schemas and settings are kept by the checkpoints, the first
+    /// of which is taken now; see [`Engine::checkpoint`]. Fails for an
+    /// engine opened with [`Engine::open_read_only`].
+    pub fn enable_log(&mut self, dir: impl AsRef<Path>) -> Result<(), EngineError> {
+        self.writable()?;
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        let checkpoint = latest_checkpoint(dir)?.unwrap_or(0);
+        let history = read_history(dir)?;
+        self.logs = Some(TableLogs {
+            dir: dir.to_path_buf(),
+            files: HashMap::new(),
+            checkpoint,
+            appended: 0,
+            lsn: history
+                .iter()
+                .map(|(_, stamp)| stamp.lsn)
+                .max()
+                .unwrap_or(0),
+        });
+        self.checkpoint()
+    }
+
+    /// Stops keeping table logs, leaving the files as they are.
+    pub fn disable_log(&mut self) {
+        self.logs = None;
+    }
+
+    /// Opens an engine with the tables logged in `dir`, and keeps logging
+    /// to it: the latest checkpoint is read, and the changes logged since
+    /// replayed over it. Rows come back in the order they were last
+    /// written, so that an updated row follows the rows that were left
+    /// alone. A record a crash cut short is cut off the log, and logs
+    /// written by an older version are written afresh by a checkpoint.
+    pub fn open_log(dir: impl AsRef<Path>) -> Result<Engine, EngineError> {
+        let dir = dir.as_ref();
+        let checkpoint = latest_checkpoint(dir)?;
+        let Recovered {
+            mut engine,
+            current,
+            lsn,
+        } = recover(dir, checkpoint, &log_paths(dir, None)?, None)?;
+        let history = read_history(dir)?;
+        let mut logs = TableLogs {
+            dir: dir.to_path_buf(),
+            files: HashMap::new(),
+            checkpoint: checkpoint.unwrap_or(0),
+            appended: 0,
+            lsn: history
+                .iter()
+                .map(|(_, stamp)| stamp.lsn)
+                .fold(lsn, u64::max),
+        };
+        let mut outdated = false;
+        for (name, whole) in &current {
+            let Some(whole) = *whole else {
+                outdated = true;
+                continue;
+            };
+            let path = logs.path(name);
+            OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|file| {
+                    if file.metadata()?.len() > whole {
+                        file.set_len(whole)?;
+                    }
+                    Ok(())
+                })
+                .map_err(|e| io_error(&path, e))?;
+            logs.open(name)?;
+        }
+        engine.logs = Some(logs);
+        if outdated {
+            engine.checkpoint()?;
+        }
+        Ok(engine)
+    }
+
+    /// Opens the engine logged in `dir` as it stood at `point`, without
+    /// logging, so that nothing in `dir` changes. The latest checkpoint
+    /// taken by then is read, and the changes logged after it replayed
+    /// up to `point`. Fails if every checkpoint that old is gone; see
+    /// [`Engine::set_history`].
+    pub fn open_at(dir: impl AsRef<Path>, point: PointInTime) -> Result<Engine, EngineError> {
+        let dir = dir.as_ref();
+        let checkpoint = read_history(dir)?
+            .into_iter()
+            .filter(|(n, stamp)| point.includes(*stamp) && checkpoint_path(dir, *n).exists())
+            .map(|(n, _)| n)
+            .max()
+            .ok_or_else(|| {
+                EngineError::InvalidOperation(format!(
+                    "no checkpoint in {} is as old as {:?}",
+                    dir.display(),
+                    point
+                ))
+            })?;
+        let segment = (Some(checkpoint) != latest_checkpoint(dir)?).then_some(checkpoint);
+        let paths = log_paths(dir, segment)?;
+        Ok(recover(dir, Some(checkpoint), &paths, Some(point))?.engine)
+    }
+
+    /// Opens the engine saved at `path`, a snapshot written by
+    /// [`Engine::save`] or a directory of table logs, for reading only:
+    /// statements that would change it fail with
+    /// [`EngineError::ReadOnly`], and nothing at `path` is written, so a
+    /// writer may keep using the files meanwhile. A log directory is read
+    /// as it stands, skipping a statement the writer has only partly
+    /// logged, and read again if the writer checkpoints meanwhile.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Engine, EngineError> {
+        let path = path.as_ref();
+        let mut engine = if path.is_dir() {
+            loop {
+                let checkpoint = latest_checkpoint(path)?;
+                match recover(path, checkpoint, &log_paths(path, None)?, None) {
+                    Ok(recovered) => break recovered.engine,
+                    // A checkpoint removes the logs it replaces.
+                    Err(_) if latest_checkpoint(path)? != checkpoint => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        } else {
+            Engine::open(path)?
+        };
+        engine.read_only = true;
+        Ok(engine)
+    }
+
+    /// Whether the engine was opened with [`Engine::open_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The log sequence number of the last change logged, for
+    /// [`Engine::open_at`], or `None` when the engine keeps no logs.
+    pub fn last_lsn(&self) -> Option<u64> {
+        self.logs.as_ref().map(|logs| logs.lsn)
+    }
+
+    /// Keeps the checkpoints and logs of the last `checkpoints` checkpoints
+    /// before the latest, so that [`Engine::open_at`] can go back to any
+    /// point since the oldest of them. None are kept unless set.
+    pub fn set_history(&mut self, checkpoints: usize) {
+        self.history = checkpoints;
+    }
+
+    /// Takes a checkpoint: saves the engine as it stands beside the logs,
+    /// then starts every table log afresh, dropping the logs of tables
+    /// that are gone and the checkpoints older than the history kept; see
+    /// [`Engine::set_history`]. Opening the logs then
+    /// reads the checkpoint and replays only what was logged after it.
+    /// Does nothing when the engine keeps no logs.
+    ///
+    /// A checkpoint is also taken once more than
+    /// [`Engine::checkpoint_size`] bytes are logged after the last one, so
+    /// that the logs to replay stay bounded.
+    pub fn checkpoint(&mut self) -> Result<(), EngineError> {
+        let Some(logs) = &self.logs else {
+            return Ok(());
+        };
+        let previous = logs.checkpoint;
+        let checkpoint = previous + 1;
+        self.save_with(
+            checkpoint_path(&logs.dir, checkpoint),
+            SaveOptions { indexes: false },
+        )?;
+
+        let Some(logs) = &mut self.logs else {
+            return Ok(());
+        };
+        // The oldest checkpoint kept.
+        let oldest = checkpoint.saturating_sub(self.history as u64);
+        let mut history = read_history(&logs.dir)?;
+        history.retain(|(n, _)| *n >= oldest);
+        history.push((checkpoint, Stamp::now(logs.lsn)));
+        let text = history
+            .iter()
+            .map(|(n, stamp)| format!("{} {} {}\n", n, stamp.lsn, stamp.time))
+            .collect::<String>();
+        let path = logs.dir.join(HISTORY);
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, text).map_err(|e| io_error(&path, e))?;
+        fs::rename(&temp, &path).map_err(|e| io_error(&path, e))?;
+
+        logs.checkpoint = checkpoint;
+        logs.appended = 0;
+        if self.history > 0 && previous > 0 {
+            logs.files.clear();
+            for (name, path) in log_paths(&logs.dir, None)? {
+                let segment = logs
+                    .dir
+                    .join(format!("{}.{}.{}", name, previous, SEGMENT_EXTENSION));
+                fs::rename(&path, &segment).map_err(|e| io_error(&path, e))?;
+            }
+        }
+        for (name, table) in &self.tables {
+            match self.spill.restored(name, table)? {
+                Some(restored) => logs.write(name, &restored, false)?,
+                None => logs.write(name, table, false)?,
+            }
+        }
+        let entries = fs::read_dir(&logs.dir).map_err(|e| io_error(&logs.dir, e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| io_error(&logs.dir, e))?.path();
+            let stale_log = path.extension().is_some_and(|ext| ext == EXTENSION)
+                && path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|name| !self.tables.contains_key(name));
+            let stale_checkpoint = checkpoint_number(&path).is_some_and(|n| n < oldest);
+            let stale_segment = segment_of(&path).is_some_and(|(_, n)| n < oldest);
+            if stale_log || stale_checkpoint || stale_segment {
+                fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
+            }
+        }
+        logs.files.retain(|name, _| self.tables.contains_key(name));
+        Ok(())
+    }
+
+    /// Rewrites the logs without removed rows or dropped tables, by taking
+    /// a checkpoint; see [`Engine::checkpoint`].
+    pub fn compact(&mut self) -> Result<(), EngineError> {
+        self.checkpoint()
+    }
+
+    /// Sets how many bytes may be logged after a checkpoint before the
+    /// next one is taken.
+    pub fn set_checkpoint_size(&mut self, bytes: u64) {
+        self.checkpoint_size = Some(bytes);
+    }
+
+    pub fn checkpoint_size(&self) -> u64 {
+        self.checkpoint_size.unwrap_or(DEFAULT_CHECKPOINT_SIZE)
+    }
+
+    /// Takes a checkpoint if more than [`Engine::checkpoint_size`] bytes
+    /// were logged since the last.
+    fn checkpoint_if_due(&mut self) -> Result<(), EngineError> {
+        match &self.logs {
+            Some(logs) if logs.appended > self.checkpoint_size() => self.checkpoint(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Bytes of the log of `name`; 0 without one.
+    pub(crate) fn log_size(&self, name: &str) -> Result<u64, EngineError> {
+        let Some(logs) = &self.logs else {
+            return Ok(0);
+        };
+        let path = logs.path(name);
+        match fs::metadata(&path) {
+            Ok(meta) => Ok(meta.len()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Bytes of every file in the log directory; 0 without logging.
+    pub(crate) fn log_dir_size(&self) -> Result<u64, EngineError> {
+        let Some(logs) = &self.logs else {
+            return Ok(0);
+        };
+        let mut size = 0;
+        for entry in fs::read_dir(&logs.dir).map_err(|e| io_error(&logs.dir, e))? {
+            let meta = entry
+                .and_then(|e| e.metadata())
+                .map_err(|e| io_error(&logs.dir, e))?;
+            if meta.is_file() {
+                size += meta.len();
+            }
+        }
+        Ok(size)
+    }
+
+    /// Reads every checkpoint, log and segment in the log directory back,
+    /// failing at the first that does not match its checksums.
+    pub(crate) fn verify_logs(&self) -> Result<(), EngineError> {
+        let Some(logs) = &self.logs else {
+            return Ok(());
+        };
+        let mut paths = fs::read_dir(&logs.dir)
+            .map_err(|e| io_error(&logs.dir, e))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| io_error(&logs.dir, e))?;
+        paths.sort();
+        for path in paths {
+            if checkpoint_number(&path).is_some() {
+                read_snapshot(&path)?;
+            } else if path.extension().is_some_and(|ext| ext == EXTENSION)
+                || segment_of(&path).is_some()
+            {
+                let bytes = fs::read(&path).map_err(|e| io_error(&path, e))?;
+                if bytes.len() >= 20 && bytes[8..12] == LOG_VERSION.to_le_bytes() {
+                    checked_records(&path, &bytes, 20)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends to the log of `name` the change from rows `old` to the rows
+    /// the table has now.
+    pub(crate) fn log_changes(&mut self, name: &str, old: &[Row]) -> Result<(), EngineError> {
+        self.log_records(name, |table| changes(old, &table.all_rows()))
+    }
+
+    /// Appends to the log of `name` the rows a statement took out of it and
+    /// those it put in, leaving out rows it put back as they were.
+    pub(crate) fn log_rows(
+        &mut self,
+        name: &str,
+        removed: &[Row],
+        added: &[Row],
+    ) -> Result<(), EngineError> {
+        self.log_records(name, |_| changes(removed, added))
+    }
+
+    /// Appends rows just added to `name` to its log.
+    pub(crate) fn log_inserts(&mut self, name: &str, rows: &[Row]) -> Result<(), EngineError> {
+        self.log_records(name, |_| {
+            let mut records = Vec::new();
+            for row in rows {
+                record(&mut records, PUT, &encode_row(row));
+            }
+            records
+        })
+    }
+
+    /// Appends to the log of `name` the records `f` makes for the table. A
+    /// table without a log yet gets a whole one instead.
+    fn log_records(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&Table) -> Vec<u8>,
+    ) -> Result<(), EngineError> {
+        let (Some(logs), Some(table)) = (&mut self.logs, self.tables.get(name)) else {
+            return Ok(());
+        };
+        if !logs.files.contains_key(name) {
+            return logs.write(name, table, true);
+        }
+        logs.append(name, &f(table))?;
+        self.checkpoint_if_due()
+    }
+}
+
+/// The number of the latest checkpoint in `dir`, if there is one.
+fn latest_checkpoint(dir: &Path) -> Result<Option<u64>, EngineError> {
+    let mut latest = None;
+    for entry in fs::read_dir(dir).map_err(|e| io_error(dir, e))? {
+        let path = entry.map_err(|e| io_error(dir, e))?.path();
+        latest = latest.max(checkpoint_number(&path));
+    }
+    Ok(latest)
+}
@@ -0,0 +1,317 @@
+//! Calendar arithmetic, parsing and formatting for the DATE, TIME,
+//! TIMESTAMP and INTERVAL types. Dates count days since 1970-01-01; times
+//! and timestamps count microseconds since midnight and since 1970-01-01
+//! 00:00:00 UTC.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// Days since the epoch for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The `(year, month, day)` of a day count since the epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Parses exactly `width` ASCII digits.
+fn fixed_digits(s: &str, width: usize) -> Option<u32> {
+    (s.len() == width && s.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| s.parse().ok())
+        .flatten()
+}
+
+/// Parses `YYYY-MM-DD` into days since the epoch.
+pub fn parse_date(s: &str) -> Option<i32> {
+    let mut parts = s.splitn(3, '-');
+    let year = fixed_digits(parts.next()?, 4)? as i64;
+    let month = fixed_digits(parts.next()?, 2)?;
+    let day = fixed_digits(parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    i32::try_from(days_from_civil(year, month, day)).ok()
+}
+
+/// Parses `HH:MM[:SS[.ffffff]]` into microseconds since midnight.
+pub fn parse_time(s: &str) -> Option<i64> {
+    let (hms, frac) = s.split_once('.').unwrap_or((s, ""));
+    let mut parts = hms.splitn(3, ':');
+    let hour = fixed_digits(parts.next()?, 2)?;
+    let minute = fixed_digits(parts.next()?, 2)?;
+    let second = parts.next().map_or(Some(0), |p| fixed_digits(p, 2))?;
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    if frac.len() > 6 || (s.contains('.') && frac.is_empty()) {
+        return None;
+    }
+    let micros = if frac.is_empty() {
+        0
+    } else {
+        fixed_digits(frac, frac.len())? * 10u32.pow(6 - frac.len() as u32)
+    };
+    Some(((hour * 60 + minute) * 60 + second) as i64 * MICROS_PER_SECOND + micros as i64)
+}
+
+/// Parses `YYYY-MM-DD[ HH:MM[:SS[.ffffff]]]` (a `T` may separate the date
+/// and time) into microseconds since the epoch.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let (date, time) = match s.split_once([' ', 'T']) {
+        Some((date, time)) => (date, parse_time(time)?),
+        None => (s, 0),
+    };
+    (parse_date(date)? as i64)
+        .checked_mul(MICROS_PER_DAY)?
+        .checked_add(time)
+}
+
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn format_time(micros: i64) -> String {
+    let secs = micros.div_euclid(MICROS_PER_SECOND);
+    let frac = micros.rem_euclid(MICROS_PER_SECOND);
+    let hms = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if frac == 0 {
+        hms
+    } else {
+        let frac = format!("{:06}", frac);
+        format!("{}.{}", hms, frac.trim_end_matches('0'))
+    }
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    let days = micros.div_euclid(MICROS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {}",
+        year,
+        month,
+        day,
+        format_time(micros.rem_euclid(MICROS_PER_DAY))
+    )
+}
+
+/// The day a timestamp falls on.
+pub fn timestamp_date(micros: i64) -> Option<i32> {
+    i32::try_from(micros.div_euclid(MICROS_PER_DAY)).ok()
+}
+
+/// Shifts a day count by whole months, clamping the day of month to the
+/// length of the target month (Jan 31 + 1 month is Feb 28 or 29).
+fn add_months(days: i64, months: i32) -> Option<i64> {
+    if months == 0 {
+        return Some(days);
+    }
+    let (year, month, day) = civil_from_days(days);
+    let total = (year * 12 + month as i64 - 1).checked_add(months as i64)?;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+    Some(days_from_civil(
+        year,
+        month,
+        day.min(days_in_month(year, month)),
+    ))
+}
+
+/// A span of time made of whole months plus an exact number of
+/// microseconds. Months are kept apart because their length varies, so
+/// adding `1 month` to a timestamp moves it to the same day of the next
+/// month. Intervals order by their length taking a month as 30 days.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Interval {
+    pub months: i32,
+    pub micros: i64,
+}
+
+impl Interval {
+    pub fn new(months: i32, micros: i64) -> Self {
+        Interval { months, micros }
+    }
+
+    /// `amount` of `unit`, where the unit is a name such as `DAY`, `hours`
+    /// or `mon` as accepted in INTERVAL literals. `None` for an unknown
+    /// unit or on overflow.
+    pub fn of(amount: i64, unit: &str) -> Option<Self> {
+        let unit = unit.to_ascii_lowercase();
+        let unit = unit.strip_suffix('s').unwrap_or(&unit);
+        let micros = |per: i64| Some(Interval::new(0, amount.checked_mul(per)?));
+        let months = |per: i64| {
+            let months = i32::try_from(amount.checked_mul(per)?).ok()?;
+            Some(Interval::new(months, 0))
+        };
+        match unit {
+            "microsecond" => micros(1),
+            "millisecond" => micros(1_000),
+            "second" | "sec" => micros(MICROS_PER_SECOND),
+            "minute" | "min" => micros(60 * MICROS_PER_SECOND),
+            "hour" => micros(3_600 * MICROS_PER_SECOND),
+            "day" => micros(MICROS_PER_DAY),
+            "week" => micros(7 * MICROS_PER_DAY),
+            "month" | "mon" => months(1),
+            "year" => months(12),
+            _ => None,
+        }
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(Interval::new(
+            self.months.checked_add(other.months)?,
+            self.micros.checked_add(other.micros)?,
+        ))
+    }
+
+    pub fn checked_neg(&self) -> Option<Self> {
+        Some(Interval::new(
+            self.months.checked_neg()?,
+            self.micros.checked_neg()?,
+        ))
+    }
+
+    pub fn checked_mul(&self, factor: i64) -> Option<Self> {
+        Some(Interval::new(
+            i32::try_from((self.months as i64).checked_mul(factor)?).ok()?,
+            self.micros.checked_mul(factor)?,
+        ))
+    }
+
+    /// Divides each part, truncating toward zero.
+    pub fn checked_div(&self, divisor: i64) -> Option<Self> {
+        Some(Interval::new(
+            i32::try_from((self.months as i64).checked_div(divisor)?).ok()?,
+            self.micros.checked_div(divisor)?,
+        ))
+    }
+
+    /// The timestamp `self` after `ts`.
+    pub fn add_to_timestamp(&self, ts: i64) -> Option<i64> {
+        let days = add_months(ts.div_euclid(MICROS_PER_DAY), self.months)?;
+        days.checked_mul(MICROS_PER_DAY)?
+            .checked_add(ts.rem_euclid(MICROS_PER_DAY))?
+            .checked_add(self.micros)
+    }
+
+    /// The date `self` after `date`, when the interval is a whole number
+    /// of days.
+    pub fn add_to_date(&self, date: i32) -> Option<i32> {
+        if self.micros % MICROS_PER_DAY != 0 {
+            return None;
+        }
+        let days = add_months(date as i64, self.months)?;
+        i32::try_from(days.checked_add(self.micros / MICROS_PER_DAY)?).ok()
+    }
+
+    /// Length in microseconds taking a month as 30 days.
+    fn approx_micros(&self) -> i128 {
+        self.months as i128 * 30 * MICROS_PER_DAY as i128 + self.micros as i128
+    }
+}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Ties in length fall back to the parts so that the order agrees
+        // with equality.
+        self.approx_micros()
+            .cmp(&other.approx_micros())
+            .then_with(|| (self.months, self.micros).cmp(&(other.months, other.micros)))
+    }
+}
+
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Formats as e.g. `1 year 2 mons 3 days 04:05:06`, leaving out zero parts;
+/// the zero interval is `00:00:00`.
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        let plural = |n: i64| if n.abs() == 1 { "" } else { "s" };
+        let (years, months) = (self.months / 12, self.months % 12);
+        let (days, time) = (self.micros / MICROS_PER_DAY, self.micros % MICROS_PER_DAY);
+        if years != 0 {
+            parts.push(format!("{} year{}", years, plural(years as i64)));
+        }
+        if months != 0 {
+            parts.push(format!("{} mon{}", months, plural(months as i64)));
+        }
+        if days != 0 {
+            parts.push(format!("{} day{}", days, plural(days)));
+        }
+        if time != 0 || parts.is_empty() {
+            let sign = if time < 0 { "-" } else { "" };
+            parts.push(format!("{}{}", sign, format_time(time.abs())));
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// Error returned when text is not a valid interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseIntervalError;
+
+impl FromStr for Interval {
+    type Err = ParseIntervalError;
+
+    /// Parses a sequence of `amount unit` pairs, optionally followed by a
+    /// `[-]HH:MM[:SS[.ffffff]]` time, as produced by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut total = Interval::default();
+        let mut words = s.split_whitespace().peekable();
+        if words.peek().is_none() {
+            return Err(ParseIntervalError);
+        }
+        while let Some(word) = words.next() {
+            let part = if word.contains(':') {
+                let (negative, time) = match word.strip_prefix('-') {
+                    Some(time) => (true, time),
+                    None => (false, word),
+                };
+                let micros = parse_time(time).ok_or(ParseIntervalError)?;
+                Interval::new(0, if negative { -micros } else { micros })
+            } else {
+                let amount = word.parse().map_err(|_| ParseIntervalError)?;
+                let unit = words.next().ok_or(ParseIntervalError)?;
+                Interval::of(amount, unit).ok_or(ParseIntervalError)?
+            };
+            total = total.checked_add(&part).ok_or(ParseIntervalError)?;
+        }
+        Ok(total)
+    }
+}
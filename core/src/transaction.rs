@@ -0,0 +1,230 @@
+//! Transactions: `BEGIN`, `COMMIT` and `ROLLBACK`, or [`Engine::begin`],
+//! [`Engine::commit`] and [`Engine::rollback`].
+//!
+//! A transaction keeps each table as it was before the transaction first
+//! changed it, with its indexes, and ROLLBACK puts those copies back.
+//! With logging on, the changes reach the table logs only at COMMIT, as
+//! the difference between each kept copy and the table.
+//!
+//! `SAVEPOINT name` marks a point in the transaction that `ROLLBACK TO
+//! name` goes back to, keeping the transaction and the savepoint open, and
+//! that `RELEASE name` forgets, along with the savepoints after it. Each
+//! savepoint keeps the tables first changed after it as they were then.
+//!
+//! Inside a transaction only SELECT, EXPLAIN, INSERT, UPDATE, DELETE and
+//! statements that change nothing run; the rest fail, as do writes to
+//! attached storage, which keeps its own changes.
+
+use std::collections::HashMap;
+
+use crate::engine::{Engine, EngineError, Table};
+use crate::parser::Query;
+
+/// The tables an open transaction changed, as they were before.
+#[derive(Debug, Default)]
+pub(crate) struct Transaction {
+    saved: HashMap<String, Table>,
+    /// Open savepoints, oldest first.
+    savepoints: Vec<Savepoint>,
+}
+
+/// The tables changed since a savepoint, as they were at it, for as long
+/// as no later savepoint is set.
+#[derive(Debug)]
+struct Savepoint {
+    name: String,
+    saved: HashMap<String, Table>,
+}
+
+impl Transaction {
+    /// Keeps `table`, as `name` was before a change, where the transaction
+    /// and its latest savepoint have no copy of it yet.
+    fn keep(&mut self, name: &str, table: Table) {
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            if !savepoint.saved.contains_key(name) {
+                savepoint.saved.insert(name.to_string(), table.clone());
+            }
+        }
+        self.saved.entry(name.to_string()).or_insert(table);
+    }
+
+    /// Whether the transaction and its latest savepoint both hold a copy
+    /// of `name`.
+    fn holds(&self, name: &str) -> bool {
+        self.saved.contains_key(name)
+            && self
+                .savepoints
+                .last()
+                .is_none_or(|savepoint| savepoint.saved.contains_key(name))
+    }
+
+    /// Position of the latest savepoint named `name`.
+    fn savepoint(&self, name: &str) -> Result<usize, EngineError> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.name == name)
+            .ok_or_else(|| EngineError::InvalidOperation(format!("no savepoint {} is open", name)))
+    }
+}
+
+impl Engine {
+    /// Opens a transaction. Fails if one is open already.
+    pub fn begin(&mut self) -> Result<(), EngineError> {
+        if self.transaction.is_some() {
+            return Err(EngineError::InvalidOperation(
+                "a transaction is already open".to_string(),
+            ));
+        }
+        self.transaction = Some(Transaction::default());
+        Ok(())
+    }
+
+    /// Ends the open transaction, keeping its changes and logging them.
+    pub fn commit(&mut self) -> Result<(), EngineError> {
+        let transaction = self.end_transaction()?;
+        let mut names = transaction.saved.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            if self.tables.contains_key(name) {
+                self.load_table(name)?;
+                self.log_changes(name, &transaction.saved[name].all_rows())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the open transaction, putting back every table it changed.
+    pub fn rollback(&mut self) -> Result<(), EngineError> {
+        let transaction = self.end_transaction()?;
+        self.put_back(transaction.saved);
+        Ok(())
+    }
+
+    /// Sets a savepoint named `name` in the open transaction. A savepoint
+    /// of the same name set before is hidden until this one is released.
+    pub fn savepoint(&mut self, name: &str) -> Result<(), EngineError> {
+        self.open_transaction()?.savepoints.push(Savepoint {
+            name: name.to_string(),
+            saved: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    /// Puts back every table changed since the savepoint `name`, dropping
+    /// the savepoints set after it but keeping it and the transaction
+    /// open.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), EngineError> {
+        let transaction = self.open_transaction()?;
+        let at = transaction.savepoint(name)?;
+        let later = transaction.savepoints.split_off(at + 1);
+        let mut saved = std::mem::take(&mut transaction.savepoints[at].saved);
+        // The oldest copy of each table is the one from the savepoint.
+        for savepoint in later {
+            for (table, copy) in savepoint.saved {
+                saved.entry(table).or_insert(copy);
+            }
+        }
+        self.put_back(saved);
+        Ok(())
+    }
+
+    /// Forgets the savepoint `name` and those set after it, keeping their
+    /// changes in the transaction.
+    pub fn release(&mut self, name: &str) -> Result<(), EngineError> {
+        let transaction = self.open_transaction()?;
+        let at = transaction.savepoint(name)?;
+        let released = transaction.savepoints.split_off(at);
+        if let Some(previous) = transaction.savepoints.last_mut() {
+            for savepoint in released {
+                for (table, copy) in savepoint.saved {
+                    previous.saved.entry(table).or_insert(copy);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the tables in `saved` back in place of the ones changed.
+    fn put_back(&mut self, saved: HashMap<String, Table>) {
+        let mut saved = saved.into_iter().collect::<Vec<_>>();
+        saved.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, table) in saved {
+            self.forget_spilled_table(&name);
+            self.tables.insert(name.clone(), table);
+            self.table_changed(&name);
+        }
+    }
+
+    /// Whether a transaction is open.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Runs `f` in a transaction of its own, committing if it succeeds and
+    /// rolling back if it fails. Fails if a transaction is open already.
+    pub fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        self.begin()?;
+        match f(self) {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    fn open_transaction(&mut self) -> Result<&mut Transaction, EngineError> {
+        self.transaction
+            .as_mut()
+            .ok_or_else(|| EngineError::InvalidOperation("no transaction is open".to_string()))
+    }
+
+    fn end_transaction(&mut self) -> Result<Transaction, EngineError> {
+        self.transaction
+            .take()
+            .ok_or_else(|| EngineError::InvalidOperation("no transaction is open".to_string()))
+    }
+
+    /// Fails for a statement that cannot run in the open transaction, if
+    /// there is one; see the [module docs](self).
+    pub(crate) fn check_transaction(&self, query: &Query) -> Result<(), EngineError> {
+        if self.transaction.is_none() {
+            return Ok(());
+        }
+        let target = match query {
+            Query::Insert(q) => Some(&q.table),
+            Query::Update(q) => Some(&q.table),
+            Query::Delete(q) => Some(&q.table),
+            _ => None,
+        };
+        if let Some(table) = target.filter(|t| self.attached.contains_key(*t)) {
+            return Err(EngineError::InvalidOperation(format!(
+                "table {} is attached storage, which cannot be changed inside a transaction",
+                table
+            )));
+        }
+        if query.writes() && !query.is_dml() && !query.is_transaction_control() {
+            return Err(EngineError::InvalidOperation(
+                "only SELECT, INSERT, UPDATE and DELETE can change anything inside a transaction"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Keeps a copy of `name` for ROLLBACK, if a transaction is open and
+    /// it or its latest savepoint has not kept one already.
+    pub(crate) fn save_for_rollback(&mut self, name: &str) {
+        if let (Some(transaction), Some(table)) = (&mut self.transaction, self.tables.get(name)) {
+            if !transaction.holds(name) {
+                transaction.keep(name, table.clone());
+            }
+        }
+    }
+}
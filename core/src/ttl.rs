@@ -0,0 +1,198 @@
+//! Tables whose rows expire, chosen with `CREATE TABLE ... WITH (ttl =
+//! '30 minutes')` to count from when each row was inserted, or `WITH (ttl =
+//! '30 days', ttl_column = 'created_at')` to count from a date or
+//! timestamp column.
+//!
+//! Queries and DML statements skip expired rows, but the rows stay in the
+//! table, holding their keys in unique indexes, until
+//! [`Engine::sweep_expired`] deletes them.
+
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Table, Value, ValueType};
+use crate::temporal::{Interval, MICROS_PER_DAY};
+
+/// How long the rows of a table live; see [`Table::set_ttl`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ttl {
+    pub after: Interval,
+    /// The date or timestamp column the time counts from, or `None` to
+    /// count from when each row was inserted. A row whose column is NULL
+    /// never expires.
+    pub column: Option<String>,
+}
+
+impl Ttl {
+    pub fn since_insert(after: Interval) -> Self {
+        Self {
+            after,
+            column: None,
+        }
+    }
+
+    pub fn since_column(after: Interval, column: &str) -> Self {
+        Self {
+            after,
+            column: Some(column.to_string()),
+        }
+    }
+}
+
+fn now_micros() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as i64)
+}
+
+impl Table {
+    /// Expires the rows `ttl.after` past their insert time or the value of
+    /// `ttl.column`, or lets them live for good with `None`. Rows already
+    /// in the table count as inserted now. Fails if the column does not
+    /// exist or holds neither dates nor timestamps, or if the interval is
+    /// not positive.
+    pub fn set_ttl(&mut self, ttl: Option<Ttl>) -> Result<(), EngineError> {
+        if let Some(ttl) = &ttl {
+            if ttl.after <= Interval::default() {
+                return Err(EngineError::InvalidOperation(
+                    "a TTL must be positive".to_string(),
+                ));
+            }
+            if let Some(column) = &ttl.column {
+                let col_idx = self.column_positions(&[column])?[0];
+                if !matches!(
+                    self.columns[col_idx].col_type,
+                    ValueType::Date | ValueType::Timestamp
+                ) {
+                    return Err(EngineError::InvalidOperation(format!(
+                        "a TTL needs a DATE or TIMESTAMP column, not {}",
+                        column
+                    )));
+                }
+            }
+        }
+        let since_insert = ttl.as_ref().is_some_and(|ttl| ttl.column.is_none());
+        if !since_insert {
+            self.inserted = Vec::new();
+        } else if self.inserted.len() != self.row_count() {
+            self.inserted = vec![now_micros(); self.row_count()];
+        }
+        self.ttl = ttl;
+        Ok(())
+    }
+
+    fn counts_from_insert(&self) -> bool {
+        self.ttl.as_ref().is_some_and(|ttl| ttl.column.is_none())
+    }
+
+    /// Whether row `row_idx` had expired at `now`, in microseconds since
+    /// the Unix epoch.
+    pub(crate) fn expired(&self, row_idx: usize, now: i64) -> bool {
+        let Some(ttl) = &self.ttl else {
+            return false;
+        };
+        let since = match &ttl.column {
+            None => self.inserted.get(row_idx).copied(),
+            Some(column) => {
+                let Some(col_idx) = self.columns.iter().position(|c| &c.name == column) else {
+                    return false;
+                };
+                match self.row(row_idx)[col_idx] {
+                    Value::Timestamp(ts) => Some(ts),
+                    Value::Date(days) => Some(days as i64 * MICROS_PER_DAY),
+                    _ => None,
+                }
+            }
+        };
+        since
+            .and_then(|since| ttl.after.add_to_timestamp(since))
+            .is_some_and(|until| until <= now)
+    }
+
+    /// Positions of the rows that had expired at `now`.
+    pub(crate) fn expired_rows(&self, now: i64) -> BTreeSet<usize> {
+        if self.ttl.is_none() {
+            return BTreeSet::new();
+        }
+        (0..self.row_count())
+            .filter(|&row_idx| self.expired(row_idx, now))
+            .collect()
+    }
+
+    /// Notes the insert time of a row about to be appended.
+    pub(crate) fn note_inserted(&mut self) {
+        if self.counts_from_insert() {
+            self.inserted.push(now_micros());
+        }
+    }
+
+    /// Forgets the insert times of the rows about to be deleted.
+    pub(crate) fn forget_inserted(&mut self, doomed: &BTreeSet<usize>) {
+        if self.counts_from_insert() {
+            let mut row_idx = 0;
+            self.inserted.retain(|_| {
+                row_idx += 1;
+                !doomed.contains(&(row_idx - 1))
+            });
+        }
+    }
+
+    /// Counts rows replaced wholesale, other than by a delete or a change
+    /// of layout, as inserted now.
+    pub(crate) fn sync_inserted(&mut self) {
+        if self.counts_from_insert() && self.inserted.len() != self.row_count() {
+            self.inserted = vec![now_micros(); self.row_count()];
+        }
+    }
+
+    /// A copy of the table without the rows that had expired at `now`, or
+    /// `None` if no row had.
+    pub(crate) fn unexpired(&self, now: i64) -> Result<Option<Table>, EngineError> {
+        let expired = self.expired_rows(now);
+        if expired.is_empty() {
+            return Ok(None);
+        }
+        let mut table = self.clone();
+        table.remove_rows(&expired);
+        table.reindex()?;
+        Ok(Some(table))
+    }
+}
+
+impl Engine {
+    /// Expires the rows of `table` from now on; see [`Table::set_ttl`] and
+    /// [`Engine::set_table_layout`].
+    pub fn set_table_ttl(&mut self, table: &str, ttl: Option<Ttl>) -> Result<(), EngineError> {
+        self.change_table(table, |t| t.set_ttl(ttl))
+    }
+
+    /// Deletes the expired rows of `table`, or of every table with a TTL
+    /// for `None`, as a DELETE would, and returns how many were deleted.
+    pub fn sweep_expired(&mut self, table: Option<&str>) -> Result<usize, EngineError> {
+        self.writable()?;
+        let now = now_micros();
+        let mut names = match table {
+            Some(name) => {
+                if !self.tables.contains_key(name) {
+                    return Err(EngineError::TableNotFound(name.to_string()));
+                }
+                vec![name.to_string()]
+            }
+            None => self
+                .tables
+                .iter()
+                .filter(|(_, t)| t.ttl.is_some())
+                .map(|(name, _)| name.clone())
+                .collect(),
+        };
+        names.sort();
+        let mut swept = 0;
+        for name in names {
+            let expired = self.tables[&name].expired_rows(now);
+            swept += self.delete_positions(&name, expired)?;
+        }
+        Ok(swept)
+    }
+}
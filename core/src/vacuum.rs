@@ -0,0 +1,98 @@
+//! `VACUUM [table]`, giving back the space that deleted and changed rows
+//! leave behind; see [`Engine::vacuum`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Table, Value};
+
+/// What a vacuum reclaimed, as returned by [`Engine::vacuum`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VacuumStats {
+    /// Expired rows deleted; see [`crate::Ttl`].
+    pub expired: usize,
+    /// Rough number of bytes of rows and index entries freed in memory,
+    /// as [`crate::TableUsage`] counts them.
+    pub memory: usize,
+    /// Bytes by which the table logs and the files of attached storage
+    /// shrank.
+    pub disk: u64,
+}
+
+impl Table {
+    /// Copies the rows into storage sized to hold just them, which drops
+    /// the texts of a compressed column, or of an overflow area, that no
+    /// row holds any more.
+    fn repack(&mut self) {
+        let mut rows = self.take_rows();
+        for row in &mut rows {
+            for value in row.iter_mut() {
+                if let Value::Text(text) = value {
+                    text.shrink_to_fit();
+                }
+            }
+            row.shrink_to_fit();
+        }
+        rows.shrink_to_fit();
+        self.set_rows(rows);
+    }
+}
+
+impl Engine {
+    /// Reclaims the space of `table`, or of every table for `None`:
+    /// deletes the rows whose TTL ran out, repacks the rows, rebuilds the
+    /// indexes without room to spare and the bloom filters without the
+    /// values of deleted rows, and has attached storage drop the
+    /// space of deleted rows. With logging on, a checkpoint then rewrites
+    /// the logs to hold only the rows that are left. Returns what was
+    /// reclaimed.
+    pub fn vacuum(&mut self, table: Option<&str>) -> Result<VacuumStats, EngineError> {
+        self.change_schema(|engine| engine.vacuum_now(table))
+    }
+
+    fn vacuum_now(&mut self, table: Option<&str>) -> Result<VacuumStats, EngineError> {
+        let names = match table {
+            Some(name) if self.tables.contains_key(name) || self.attached.contains_key(name) => {
+                vec![name.to_string()]
+            }
+            Some(name) => return Err(EngineError::TableNotFound(name.to_string())),
+            None => {
+                let mut names = self
+                    .tables
+                    .keys()
+                    .chain(self.attached.keys())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                names.sort();
+                names
+            }
+        };
+        let disk_before = self.log_dir_size()?;
+        let mut stats = VacuumStats::default();
+        for name in names {
+            if self.attached.contains_key(&name) {
+                stats.disk += self.vacuum_attached(&name)?;
+                continue;
+            }
+            if self.tables[&name].ttl.is_some() {
+                stats.expired += self.sweep_expired(Some(&name))?;
+            }
+            let before = self.tables[&name].memory();
+            if let Some(table) = self.tables.get_mut(&name) {
+                table.repack();
+                table.rebuild_bloom_filters();
+            }
+            self.rebuild_table_indexes(&name, None)?;
+            let table = self
+                .tables
+                .get_mut(&name)
+                .ok_or_else(|| EngineError::TableNotFound(name.clone()))?;
+            table.indices.values_mut().for_each(|i| i.shrink_to_fit());
+            stats.memory += before.saturating_sub(table.memory());
+        }
+        if self.logs.is_some() {
+            self.checkpoint()?;
+            stats.disk += disk_before.saturating_sub(self.log_dir_size()?);
+        }
+        Ok(stats)
+    }
+}
@@ -0,0 +1,154 @@
+//! Views, plain and materialized.
+//!
+//! A plain view is kept as its query and expanded each time it is read. A
+//! materialized view keeps the rows its query returned when it was last
+//! refreshed, and reads as an ordinary table until the next refresh.
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Engine, EngineError, Scope, Table};
+use crate::parser::SelectQuery;
+
+/// A view together with the rows it last produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct MaterializedView {
+    pub(crate) query: SelectQuery,
+    pub(crate) table: Table,
+    /// Whether the rows are recomputed whenever a table the query reads
+    /// changes, rather than only on REFRESH.
+    pub(crate) auto_refresh: bool,
+}
+
+impl Engine {
+    /// Whether `name` is used by a table or by either kind of view.
+    pub(crate) fn name_taken(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+            || self.views.contains_key(name)
+            || self.materialized.contains_key(name)
+            || self.attached.contains_key(name)
+    }
+
+    /// Defines a view that reads as the result of `query`. Fails if the
+    /// name is taken by a table or view, or if the query does not run.
+    pub fn create_view(&mut self, name: &str, query: SelectQuery) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            if engine.name_taken(name) {
+                return Err(EngineError::TableExists(name.to_string()));
+            }
+            engine.check_schema(name)?;
+            engine.run_select(&query, &Scope::new())?;
+            engine.views.insert(name.to_string(), query);
+            Ok(())
+        })
+    }
+
+    /// Defines a view that stores the rows `query` returns now. They stay
+    /// as they are until [`Engine::refresh_materialized_view`], or until a
+    /// base table changes once automatic refresh is on.
+    pub fn create_materialized_view(
+        &mut self,
+        name: &str,
+        query: SelectQuery,
+    ) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            if engine.name_taken(name) {
+                return Err(EngineError::TableExists(name.to_string()));
+            }
+            engine.check_schema(name)?;
+            let table = engine.temporary_table(&query, &Scope::new())?;
+            engine.materialized.insert(
+                name.to_string(),
+                MaterializedView {
+                    query,
+                    table,
+                    auto_refresh: false,
+                },
+            );
+            Ok(())
+        })
+    }
+
+    /// Drops a view of either kind.
+    pub fn drop_view(&mut self, name: &str) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            if engine.views.remove(name).is_some() || engine.materialized.remove(name).is_some() {
+                Ok(())
+            } else {
+                Err(EngineError::ViewNotFound(name.to_string()))
+            }
+        })
+    }
+
+    /// Reruns the query of a materialized view and replaces its rows. On
+    /// failure the view keeps the rows it had.
+    pub fn refresh_materialized_view(&mut self, name: &str) -> Result<(), EngineError> {
+        self.change_schema(|engine| engine.refresh_view(name))
+    }
+
+    /// Refreshes the materialized view `name` as part of whatever change
+    /// is under way, which may be a row change inside a transaction.
+    fn refresh_view(&mut self, name: &str) -> Result<(), EngineError> {
+        let view = self
+            .materialized
+            .get(name)
+            .ok_or_else(|| EngineError::ViewNotFound(name.to_string()))?;
+        let table = self.temporary_table(&view.query, &Scope::new())?;
+        if let Some(view) = self.materialized.get_mut(name) {
+            view.table = table;
+        }
+        self.table_changed(name);
+        Ok(())
+    }
+
+    pub fn auto_refresh(&self, name: &str) -> Result<bool, EngineError> {
+        self.materialized
+            .get(name)
+            .map(|view| view.auto_refresh)
+            .ok_or_else(|| EngineError::ViewNotFound(name.to_string()))
+    }
+
+    /// Turns automatic refresh of a materialized view on or off. Turning it
+    /// on refreshes the view, so that it starts out current.
+    pub fn set_auto_refresh(&mut self, name: &str, on: bool) -> Result<(), EngineError> {
+        self.change_schema(|engine| {
+            let view = engine
+                .materialized
+                .get_mut(name)
+                .ok_or_else(|| EngineError::ViewNotFound(name.to_string()))?;
+            view.auto_refresh = on;
+            if on {
+                engine.refresh_view(name)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Refreshes the materialized views set to refresh automatically that
+    /// read `table`, directly or through other views. A view whose query
+    /// now fails keeps its previous rows.
+    pub(crate) fn table_changed(&mut self, table: &str) {
+        let mut stale: Vec<String> = self
+            .materialized
+            .iter()
+            .filter(|(_, view)| view.auto_refresh && self.reads(&view.query, table))
+            .map(|(name, _)| name.clone())
+            .collect();
+        stale.sort();
+        for name in stale {
+            // Refreshing a view counts as a change to it, which reaches the
+            // views that read it in turn.
+            let _ = self.refresh_view(&name);
+        }
+    }
+
+    /// Whether `query` reads `table`, looking through plain views.
+    fn reads(&self, query: &SelectQuery, table: &str) -> bool {
+        query.table_names().iter().any(|name| {
+            name == table
+                || self
+                    .views
+                    .get(name)
+                    .is_some_and(|view| self.reads(view, table))
+        })
+    }
+}
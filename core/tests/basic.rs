@@ -1,15 +1,20 @@
-use sql_core::{parse_query, Engine, Value, ValueType};
+use sql_core::{
+    parse_query, parse_type, Decimal, Engine, EngineError, Query, ResultColumn, Table, Typing,
+    Value, ValueType,
+};
 
 #[test]
 fn basic_flow() {
     let mut engine = Engine::new();
-    engine.create_table(
-        "users",
-        vec![
-            ("id".into(), ValueType::Int),
-            ("name".into(), ValueType::Text),
-        ],
-    );
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
 
     let insert_q = parse_query("INSERT INTO users VALUES (1, 'Alice')")
         .unwrap()
@@ -25,13 +30,15 @@ fn basic_flow() {
 #[test]
 fn bool_flow() {
     let mut engine = Engine::new();
-    engine.create_table(
-        "flags",
-        vec![
-            ("id".into(), ValueType::Int),
-            ("active".into(), ValueType::Bool),
-        ],
-    );
+    engine
+        .create_table(
+            "flags",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("active".into(), ValueType::Bool),
+            ],
+        )
+        .unwrap();
 
     let insert_q = parse_query("INSERT INTO flags VALUES (1, TRUE)").unwrap().1;
     engine.execute(insert_q).unwrap();
@@ -47,13 +54,15 @@ fn bool_flow() {
 #[test]
 fn advanced_select() {
     let mut engine = Engine::new();
-    engine.create_table(
-        "nums",
-        vec![
-            ("id".into(), ValueType::Int),
-            ("name".into(), ValueType::Text),
-        ],
-    );
+    engine
+        .create_table(
+            "nums",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
 
     let insert1 = parse_query("INSERT INTO nums VALUES (1, 'Alice')")
         .unwrap()
@@ -77,14 +86,16 @@ fn advanced_select() {
 #[test]
 fn insert_with_columns() {
     let mut engine = Engine::new();
-    engine.create_table(
-        "users",
-        vec![
-            ("id".into(), ValueType::Int),
-            ("name".into(), ValueType::Text),
-            ("active".into(), ValueType::Bool),
-        ],
-    );
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+                ("active".into(), ValueType::Bool),
+            ],
+        )
+        .unwrap();
 
     let insert_q = parse_query("INSERT INTO users (id, active) VALUES (1, TRUE)")
         .unwrap()
@@ -96,3 +107,6851 @@ fn insert_with_columns() {
     assert_eq!(rows.len(), 1);
     assert_eq!(rows[0], vec![Value::Int(1), Value::Null, Value::Bool(true)]);
 }
+
+#[test]
+fn aggregates_without_group_by() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("active".into(), ValueType::Bool),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO users VALUES (1, TRUE)",
+        "INSERT INTO users VALUES (2, FALSE)",
+        "INSERT INTO users VALUES (3, TRUE)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let select_q = parse_query("SELECT COUNT(*), MAX(id), SUM(id) FROM users WHERE active=TRUE")
+        .unwrap()
+        .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(
+        rows,
+        vec![vec![Value::Int(2), Value::Int(3), Value::Int(4)]]
+    );
+
+    let select_q = parse_query("SELECT COUNT(id), MIN(id) FROM users WHERE id>10")
+        .unwrap()
+        .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(rows, vec![vec![Value::Int(0), Value::Null]]);
+
+    let select_q = parse_query("SELECT id, COUNT(*) FROM users").unwrap().1;
+    assert_eq!(
+        engine.execute(select_q),
+        Err(EngineError::InvalidQuery(
+            "column id must appear in an aggregate function".into()
+        ))
+    );
+}
+
+#[test]
+fn cross_join() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    engine
+        .create_table("roles", vec![("id".into(), ValueType::Int)])
+        .unwrap();
+    for sql in [
+        "INSERT INTO users VALUES (1, 'Alice')",
+        "INSERT INTO users VALUES (2, 'Bob')",
+        "INSERT INTO roles VALUES (10)",
+        "INSERT INTO roles VALUES (20)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let select_q = parse_query("SELECT * FROM users, roles").unwrap().1;
+    assert_eq!(engine.execute(select_q).unwrap().len(), 4);
+
+    let select_q =
+        parse_query("SELECT name, roles.id FROM users CROSS JOIN roles WHERE users.id=2")
+            .unwrap()
+            .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Text("Bob".into()), Value::Int(10)],
+            vec![Value::Text("Bob".into()), Value::Int(20)],
+        ]
+    );
+
+    let select_q = parse_query("SELECT id FROM users, roles").unwrap().1;
+    assert!(engine.execute(select_q).is_err());
+}
+
+#[test]
+fn column_and_table_aliases() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    engine
+        .execute(
+            parse_query("INSERT INTO users VALUES (1, 'Alice')")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+
+    let select_q = match parse_query("SELECT id AS user_id, name FROM users u WHERE u.id = 1")
+        .unwrap()
+        .1
+    {
+        Query::Select(q) => q,
+        _ => unreachable!(),
+    };
+    let result = engine.select_result(&select_q).unwrap();
+    assert_eq!(result.column_names(), vec!["user_id", "name"]);
+    assert_eq!(
+        result.rows,
+        vec![vec![Value::Int(1), Value::Text("Alice".into())]]
+    );
+}
+
+#[test]
+fn in_subquery() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    engine
+        .create_table(
+            "admins",
+            vec![
+                ("user_id".into(), ValueType::Int),
+                ("level".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO users VALUES (1, 'Alice')",
+        "INSERT INTO users VALUES (2, 'Bob')",
+        "INSERT INTO users VALUES (3, 'Carol')",
+        "INSERT INTO admins VALUES (1, 5)",
+        "INSERT INTO admins VALUES (3, 1)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let select_q = parse_query(
+        "SELECT name FROM users WHERE id IN (SELECT user_id FROM admins WHERE level>2)",
+    )
+    .unwrap()
+    .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(rows, vec![vec![Value::Text("Alice".into())]]);
+
+    let select_q = parse_query("SELECT name FROM users WHERE id IN (SELECT * FROM admins)")
+        .unwrap()
+        .1;
+    assert!(engine.execute(select_q).is_err());
+}
+
+#[test]
+fn common_table_expressions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO users VALUES (1, 'Alice')",
+        "INSERT INTO users VALUES (2, 'Bob')",
+        "INSERT INTO users VALUES (3, 'Carol')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let select_q = parse_query(
+        "WITH recent AS (SELECT id AS uid, name FROM users WHERE id>1) \
+         SELECT name FROM recent WHERE uid<3",
+    )
+    .unwrap()
+    .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(rows, vec![vec![Value::Text("Bob".into())]]);
+    assert!(!engine.tables.contains_key("recent"));
+}
+
+#[test]
+fn between_operator() {
+    let mut engine = Engine::new();
+    engine
+        .create_table("nums", vec![("n".into(), ValueType::Int)])
+        .unwrap();
+    for n in 1..=5 {
+        let sql = format!("INSERT INTO nums VALUES ({})", n);
+        engine.execute(parse_query(&sql).unwrap().1).unwrap();
+    }
+
+    let select_q = parse_query("SELECT n FROM nums WHERE n BETWEEN 2 AND 4 ORDER BY n DESC")
+        .unwrap()
+        .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Int(4)],
+            vec![Value::Int(3)],
+            vec![Value::Int(2)]
+        ]
+    );
+}
+
+#[test]
+fn and_or_conditions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "t",
+            vec![
+                ("a".into(), ValueType::Int),
+                ("b".into(), ValueType::Int),
+                ("c".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES (1, 1, 'x')",
+        "INSERT INTO t VALUES (1, 3, 'y')",
+        "INSERT INTO t VALUES (1, 1, 'z')",
+        "INSERT INTO t VALUES (2, 5, 'x')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let select_q = parse_query("SELECT c FROM t WHERE a = 1 AND (b > 2 OR c = 'x')")
+        .unwrap()
+        .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(
+        rows,
+        vec![vec![Value::Text("x".into())], vec![Value::Text("y".into())]]
+    );
+
+    let select_q = parse_query("SELECT c FROM t WHERE c = 'z' OR a = 2 AND b = 5")
+        .unwrap()
+        .1;
+    let rows = engine.execute(select_q).unwrap();
+    assert_eq!(
+        rows,
+        vec![vec![Value::Text("z".into())], vec![Value::Text("x".into())]]
+    );
+}
+
+#[test]
+fn not_and_parentheses() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "t",
+            vec![("a".into(), ValueType::Int), ("b".into(), ValueType::Int)],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES (1, 1)",
+        "INSERT INTO t VALUES (1, 2)",
+        "INSERT INTO t VALUES (2, 1)",
+        "INSERT INTO t VALUES (2, 2)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let count =
+        |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        count(
+            &mut engine,
+            "SELECT COUNT(*) FROM t WHERE NOT a = 1 AND b = 1"
+        ),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        count(
+            &mut engine,
+            "SELECT COUNT(*) FROM t WHERE NOT (a = 1 AND b = 1)"
+        ),
+        vec![vec![Value::Int(3)]]
+    );
+    assert_eq!(
+        count(
+            &mut engine,
+            "SELECT COUNT(*) FROM t WHERE NOT(a = 1 OR (b = 1))"
+        ),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        count(
+            &mut engine,
+            "SELECT COUNT(*) FROM t WHERE NOT NOT a BETWEEN 2 AND 3"
+        ),
+        vec![vec![Value::Int(2)]]
+    );
+}
+
+#[test]
+fn like_patterns() {
+    let mut engine = Engine::new();
+    engine
+        .create_table("t", vec![("name".into(), ValueType::Text)])
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES ('Alice')",
+        "INSERT INTO t VALUES ('Alan')",
+        "INSERT INTO t VALUES ('Bob')",
+        "INSERT INTO t VALUES ('Al')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM t WHERE name LIKE 'Al%'"),
+        vec![vec![Value::Int(3)]]
+    );
+    assert_eq!(
+        run("SELECT name FROM t WHERE name LIKE 'A_a%'"),
+        vec![vec![Value::Text("Alan".into())]]
+    );
+    assert_eq!(
+        run("SELECT name FROM t WHERE name LIKE '%o%'"),
+        vec![vec![Value::Text("Bob".into())]]
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM t WHERE name NOT LIKE '%l%'"),
+        vec![vec![Value::Int(1)]]
+    );
+}
+
+#[test]
+fn in_value_list() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "tasks",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("status".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO tasks VALUES (1, 'a')",
+        "INSERT INTO tasks VALUES (2, 'b')",
+        "INSERT INTO tasks VALUES (3, 'd')",
+        "INSERT INTO tasks VALUES (4, 'c')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT id FROM tasks WHERE status IN ('a', 'b', 'c')"),
+        vec![
+            vec![Value::Int(1)],
+            vec![Value::Int(2)],
+            vec![Value::Int(4)]
+        ]
+    );
+    // `id` is indexed, so this probes the index once per listed value.
+    assert_eq!(
+        run("SELECT status FROM tasks WHERE id IN (4, 2, 4, 9)"),
+        vec![vec![Value::Text("b".into())], vec![Value::Text("c".into())]]
+    );
+    assert_eq!(
+        run("SELECT id FROM tasks WHERE status NOT IN ('a', 'c')"),
+        vec![vec![Value::Int(2)], vec![Value::Int(3)]]
+    );
+    assert_eq!(
+        run("SELECT id FROM tasks WHERE id not in (SELECT id FROM tasks WHERE status < 'c')"),
+        vec![vec![Value::Int(3)], vec![Value::Int(4)]]
+    );
+    // A NULL in the list leaves rows it does not rule out UNKNOWN.
+    assert_eq!(
+        run("SELECT id FROM tasks WHERE id NOT IN (1, NULL)"),
+        Vec::<Vec<Value>>::new()
+    );
+}
+
+#[test]
+fn arithmetic_expressions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "orders",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("price".into(), ValueType::Int),
+                ("qty".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO orders VALUES (1, 10, 2)",
+        "INSERT INTO orders VALUES (2, 3, 7)",
+        "INSERT INTO orders VALUES (3, 5, 5)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT price * qty FROM orders WHERE qty + 1 > 5").unwrap(),
+        vec![vec![Value::Int(21)], vec![Value::Int(25)]]
+    );
+    assert_eq!(
+        run("SELECT id, (price + 1) * 2 - qty % 4 FROM orders WHERE id = 1").unwrap(),
+        vec![vec![Value::Int(1), Value::Int(20)]]
+    );
+    assert_eq!(
+        run("SELECT SUM(price * qty) / 2 FROM orders").unwrap(),
+        vec![vec![Value::Int(33)]]
+    );
+    assert!(run("SELECT price / 0 FROM orders").is_err());
+}
+
+#[test]
+fn case_expressions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "scores",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("score".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO scores VALUES (1, 95)",
+        "INSERT INTO scores VALUES (2, 70)",
+        "INSERT INTO scores VALUES (3, 40)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id, CASE WHEN score >= 90 THEN 'A' WHEN score >= 60 THEN 'B' ELSE 'F' END AS grade FROM scores")
+            .unwrap(),
+        vec![
+            vec![Value::Int(1), Value::Text("A".into())],
+            vec![Value::Int(2), Value::Text("B".into())],
+            vec![Value::Int(3), Value::Text("F".into())],
+        ]
+    );
+    assert_eq!(
+        run("SELECT id FROM scores WHERE CASE WHEN id = 3 THEN 0 ELSE score END > 80").unwrap(),
+        vec![vec![Value::Int(1)]]
+    );
+    // Branches are evaluated lazily, so the division is never attempted.
+    assert_eq!(
+        run("SELECT CASE WHEN id > 0 THEN 1 ELSE 1 / 0 END FROM scores WHERE id = 1").unwrap(),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        run("SELECT CASE WHEN id = 9 THEN 1 END FROM scores WHERE id = 1").unwrap(),
+        vec![vec![Value::Null]]
+    );
+}
+
+#[test]
+fn coalesce_and_ifnull() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("nick".into(), ValueType::Text),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO users (id, name) VALUES (1, 'Alice')",
+        "INSERT INTO users VALUES (2, 'bobby', 'Bob')",
+        "INSERT INTO users VALUES (3, NULL, NULL)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT COALESCE(nick, name, 'anon') FROM users").unwrap(),
+        vec![
+            vec![Value::Text("Alice".into())],
+            vec![Value::Text("bobby".into())],
+            vec![Value::Text("anon".into())],
+        ]
+    );
+    assert_eq!(
+        run("SELECT id FROM users WHERE ifnull(nick, 'none') = 'none'").unwrap(),
+        vec![vec![Value::Int(1)], vec![Value::Int(3)]]
+    );
+    assert_eq!(
+        run("SELECT COALESCE(MAX(id), 0) FROM users WHERE id > 5").unwrap(),
+        vec![vec![Value::Int(0)]]
+    );
+    assert!(run("SELECT IFNULL(nick) FROM users").is_err());
+    assert!(run("SELECT NOSUCH(nick) FROM users").is_err());
+}
+
+#[test]
+fn cast_expressions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "t",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("code".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES (1, '42')",
+        "INSERT INTO t VALUES (2, 'abc')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT CAST(code AS INT) + 1, CAST(id AS TEXT), CAST(id AS BOOLEAN) FROM t WHERE id = 1")
+            .unwrap(),
+        vec![vec![
+            Value::Int(43),
+            Value::Text("1".into()),
+            Value::Bool(true)
+        ]]
+    );
+    assert_eq!(
+        run("SELECT id FROM t WHERE CAST(id AS TEXT) = '2'").unwrap(),
+        vec![vec![Value::Int(2)]]
+    );
+    assert_eq!(
+        run("SELECT CAST(code AS INT) FROM t WHERE id = 2"),
+        Err(EngineError::InvalidCast {
+            value: Value::Text("abc".into()),
+            target: ValueType::Int,
+        })
+    );
+}
+
+#[test]
+fn column_to_column_comparisons() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "events",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("start_ts".into(), ValueType::Int),
+                ("end_ts".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO events VALUES (1, 10, 20)",
+        "INSERT INTO events VALUES (2, 30, 25)",
+        "INSERT INTO events VALUES (3, 5, 5)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT id FROM events WHERE start_ts <= end_ts"),
+        vec![vec![Value::Int(1)], vec![Value::Int(3)]]
+    );
+    assert_eq!(
+        run("SELECT id FROM events WHERE end_ts - start_ts > id * 4"),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        run("SELECT id FROM events WHERE 22 BETWEEN start_ts AND end_ts + 2"),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        run("SELECT id FROM events WHERE end_ts IN (start_ts, 20)"),
+        vec![vec![Value::Int(1)], vec![Value::Int(3)]]
+    );
+}
+
+#[test]
+fn regexp_matching() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "logs",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("line".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO logs VALUES (1, 'GET /index.html 200')",
+        "INSERT INTO logs VALUES (2, 'POST /api/users 500')",
+        "INSERT INTO logs VALUES (3, 'GET /api/users 404')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM logs WHERE line REGEXP ' [45][0-9]{2}$'").unwrap(),
+        vec![vec![Value::Int(2)], vec![Value::Int(3)]]
+    );
+    assert_eq!(
+        run("SELECT id FROM logs WHERE line ~ '^GET' AND line !~ 'api'").unwrap(),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM logs WHERE line NOT REGEXP 'users'").unwrap(),
+        vec![vec![Value::Int(1)]]
+    );
+    assert!(matches!(
+        run("SELECT id FROM logs WHERE line ~ '(unclosed'"),
+        Err(EngineError::InvalidPattern(_))
+    ));
+}
+
+#[test]
+fn numeric_literals() {
+    let mut engine = Engine::new();
+    engine
+        .create_table("t", vec![("n".into(), ValueType::Int)])
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES (-1)",
+        "INSERT INTO t VALUES (1_000_000)",
+        "INSERT INTO t VALUES (0xFF)",
+        "INSERT INTO t VALUES (-9223372036854775808)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT n FROM t WHERE n = -1"),
+        vec![vec![Value::Int(-1)]]
+    );
+    assert_eq!(
+        run("SELECT n FROM t WHERE n = +0x_ff"),
+        vec![vec![Value::Int(255)]]
+    );
+    assert_eq!(
+        run("SELECT n - -1 FROM t WHERE n > 1_000-1"),
+        vec![vec![Value::Int(1_000_001)]]
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM t WHERE n < -0x7FFF_FFFF_FFFF_FFFF"),
+        vec![vec![Value::Int(1)]]
+    );
+
+    assert!(matches!(
+        parse_query("SELECT * FROM t WHERE n = 170141183460469231731687303715884105728"),
+        Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge
+    ));
+    assert_eq!(
+        engine
+            .execute_sql("SELECT * FROM t WHERE n = -0x1_0000_0000_0000_0000_0000_0000_0000_0001"),
+        Err(EngineError::LiteralOutOfRange(
+            "-0x1_0000_0000_0000_0000_0000_0000_0000_0001".into()
+        ))
+    );
+    assert_eq!(
+        engine.execute_sql(
+            "SELECT COUNT(*) FROM t WHERE n > -170141183460469231731687303715884105728"
+        ),
+        Ok(vec![vec![Value::Int(4)]])
+    );
+
+    // Separators go between digits.
+    for sql in [
+        "SELECT n FROM t WHERE n = 1_",
+        "SELECT n FROM t WHERE n = 1__0",
+    ] {
+        assert!(!matches!(parse_query(sql), Ok(("", _))), "{}", sql);
+        assert_eq!(
+            engine.execute_sql(sql),
+            Err(EngineError::InvalidQuery(format!("cannot parse {}", sql)))
+        );
+    }
+}
+
+#[test]
+fn string_literal_escaping() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "t",
+            vec![("id".into(), ValueType::Int), ("s".into(), ValueType::Text)],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES (1, '')",
+        "INSERT INTO t VALUES (2, 'it''s')",
+        "INSERT INTO t VALUES (3, E'a\\tb\\\\c\\'d')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT id FROM t WHERE s = ''"),
+        vec![vec![Value::Int(1)]]
+    );
+    assert_eq!(
+        run("SELECT s FROM t WHERE s = 'it''s'"),
+        vec![vec![Value::Text("it's".into())]]
+    );
+    assert_eq!(
+        run("SELECT s FROM t WHERE id = 3"),
+        vec![vec![Value::Text("a\tb\\c'd".into())]]
+    );
+    // Without the E prefix a backslash is an ordinary character.
+    assert_eq!(
+        run("SELECT COUNT(*) FROM t WHERE s = 'a\\tb'"),
+        vec![vec![Value::Int(0)]]
+    );
+}
+
+#[test]
+fn date_time_functions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "events",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("ts".into(), ValueType::Timestamp),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO events VALUES (1, TIMESTAMP '2024-01-01 10:00:00')",
+        "INSERT INTO events VALUES (2, TIMESTAMP '2024-01-03 00:00:00')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT DATE(ts), CAST(ts + INTERVAL 1 DAY AS TEXT) FROM events WHERE id = 1"),
+        vec![vec![
+            Value::Date(19723),
+            Value::Text("2024-01-02 10:00:00".into())
+        ]]
+    );
+    assert_eq!(
+        run("SELECT id FROM events \
+             WHERE ts - INTERVAL '36' HOURS > TIMESTAMP '2024-01-01 10:00:00'"),
+        vec![vec![Value::Int(2)]]
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events WHERE ts < NOW() AND NOW() = NOW()"),
+        vec![vec![Value::Int(2)]]
+    );
+}
+
+#[test]
+fn temporal_types() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "shifts",
+            vec![
+                ("day".into(), ValueType::Date),
+                ("starts".into(), ValueType::Time),
+                ("logged".into(), ValueType::Timestamp),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO shifts VALUES (DATE '2024-02-29', TIME '09:30', \
+         TIMESTAMP '2024-02-29T09:31:15.250')",
+        "INSERT INTO shifts VALUES (DATE '1969-12-31', TIME '23:00:00', \
+         TIMESTAMP '1969-12-31 23:59:59')",
+        "INSERT INTO shifts VALUES (DATE '2024-03-01', TIME '07:00:00', NULL)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+    assert!(matches!(
+        parse_query("SELECT * FROM shifts WHERE day = DATE '2023-02-29'"),
+        Err(nom::Err::Failure(_))
+    ));
+    assert!(matches!(
+        engine.execute(
+            parse_query("INSERT INTO shifts VALUES ('2024-01-01', NULL, NULL)")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    // The first column is indexed, so this exercises date index keys.
+    assert_eq!(
+        run("SELECT starts FROM shifts WHERE day = DATE '2024-02-29'"),
+        vec![vec![Value::Time(34_200_000_000)]]
+    );
+    assert_eq!(
+        run("SELECT CAST(day AS TEXT) FROM shifts WHERE day < DATE '2024-03-01' ORDER BY day"),
+        vec![
+            vec![Value::Text("1969-12-31".into())],
+            vec![Value::Text("2024-02-29".into())]
+        ]
+    );
+    assert_eq!(
+        run(
+            "SELECT CAST(logged AS TEXT), CAST(starts + 3600 AS TEXT) FROM shifts \
+             WHERE logged >= day ORDER BY logged DESC"
+        ),
+        vec![
+            vec![
+                Value::Text("2024-02-29 09:31:15.25".into()),
+                Value::Text("10:30:00".into())
+            ],
+            vec![
+                Value::Text("1969-12-31 23:59:59".into()),
+                Value::Text("00:00:00".into())
+            ],
+        ]
+    );
+    assert_eq!(
+        run(
+            "SELECT DATE '2024-03-01' - day, day + 1, CAST('2024-01-05' AS DATE) FROM shifts \
+             WHERE day = DATE '2024-02-29'"
+        ),
+        vec![vec![Value::Int(1), Value::Date(19783), Value::Date(19727)]]
+    );
+}
+
+#[test]
+fn null_three_valued_logic() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "t",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("score".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO t VALUES (1, 10)",
+        "INSERT INTO t VALUES (2, NULL)",
+        "INSERT INTO t VALUES (3, 30)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut ids = |sql: &str| -> Vec<Value> {
+        engine
+            .execute(parse_query(sql).unwrap().1)
+            .unwrap()
+            .into_iter()
+            .map(|r| r[0].clone())
+            .collect()
+    };
+    // UNKNOWN rows are dropped whether or not the comparison is negated.
+    assert_eq!(
+        ids("SELECT id FROM t WHERE score < 20"),
+        vec![Value::Int(1)]
+    );
+    assert_eq!(
+        ids("SELECT id FROM t WHERE NOT score < 20"),
+        vec![Value::Int(3)]
+    );
+    assert_eq!(ids("SELECT id FROM t WHERE score = NULL"), vec![]);
+    assert_eq!(ids("SELECT id FROM t WHERE id = NULL"), vec![]);
+    // TRUE OR UNKNOWN is TRUE; FALSE AND UNKNOWN is FALSE, so NOT keeps it,
+    // while TRUE AND UNKNOWN stays UNKNOWN under NOT.
+    assert_eq!(
+        ids("SELECT id FROM t WHERE id = 2 OR score > 5"),
+        vec![Value::Int(1), Value::Int(2), Value::Int(3)]
+    );
+    assert_eq!(
+        ids("SELECT id FROM t WHERE NOT (id = 1 AND score > 100)"),
+        vec![Value::Int(1), Value::Int(2), Value::Int(3)]
+    );
+    assert_eq!(
+        ids("SELECT id FROM t WHERE NOT (id > 1 AND score > 100)"),
+        vec![Value::Int(1), Value::Int(3)]
+    );
+    // NOT (x IN list) is never TRUE when the list contains NULL.
+    assert_eq!(ids("SELECT id FROM t WHERE NOT id IN (1, NULL)"), vec![]);
+    assert_eq!(
+        ids("SELECT id FROM t WHERE id IN (1, NULL)"),
+        vec![Value::Int(1)]
+    );
+    assert_eq!(
+        ids("SELECT id FROM t WHERE NOT score IN (10, id)"),
+        vec![Value::Int(3)]
+    );
+    // IS NULL is never UNKNOWN, so it and its negation split the rows.
+    assert_eq!(
+        ids("SELECT id FROM t WHERE score IS NULL"),
+        vec![Value::Int(2)]
+    );
+    assert_eq!(
+        ids("SELECT id FROM t WHERE score is not null"),
+        vec![Value::Int(1), Value::Int(3)]
+    );
+    assert_eq!(
+        ids("SELECT id FROM t WHERE NOT score + 1 IS NULL AND id > 1"),
+        vec![Value::Int(3)]
+    );
+    assert!(!matches!(
+        parse_query("SELECT id FROM t WHERE score IS NULLS"),
+        Ok(("", _))
+    ));
+}
+
+#[test]
+fn computed_projection_metadata() {
+    let mut engine = Engine::new();
+    engine
+        .create_table("t", vec![("id".into(), ValueType::Int)])
+        .unwrap();
+    engine
+        .execute(parse_query("INSERT INTO t VALUES (3)").unwrap().1)
+        .unwrap();
+
+    let select = |sql: &str| match parse_query(sql).unwrap().1 {
+        Query::Select(q) => engine.select_result(&q).unwrap(),
+        _ => unreachable!(),
+    };
+    let result = select("SELECT id, id * 2 AS doubled, id + 1 FROM t");
+    assert_eq!(
+        result.columns,
+        vec![
+            ResultColumn {
+                name: "id".into(),
+                source: Some(("t".into(), "id".into())),
+            },
+            ResultColumn {
+                name: "doubled".into(),
+                source: None,
+            },
+            ResultColumn {
+                name: "id + 1".into(),
+                source: None,
+            },
+        ]
+    );
+    assert_eq!(
+        result.rows,
+        vec![vec![Value::Int(3), Value::Int(6), Value::Int(4)]]
+    );
+
+    let result = select("SELECT x.id AS key FROM t x");
+    assert_eq!(
+        result.columns,
+        vec![ResultColumn {
+            name: "key".into(),
+            source: Some(("x".into(), "id".into())),
+        }]
+    );
+    assert_eq!(select("SELECT * FROM t").column_names(), vec!["id"]);
+}
+
+#[test]
+fn distinct_aggregates() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "orders",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("customer".into(), ValueType::Text),
+                ("amount".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO orders VALUES (1, 'alice', 10)",
+        "INSERT INTO orders VALUES (2, 'bob', 10)",
+        "INSERT INTO orders VALUES (3, 'alice', 25)",
+        "INSERT INTO orders VALUES (4, NULL, NULL)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run(
+            "SELECT COUNT(DISTINCT customer), COUNT(customer), SUM(DISTINCT amount), \
+             SUM(amount) FROM orders"
+        ),
+        vec![vec![
+            Value::Int(2),
+            Value::Int(3),
+            Value::Int(35),
+            Value::Int(45)
+        ]]
+    );
+    assert_eq!(
+        run("SELECT count(distinct amount) FROM orders WHERE id < 3"),
+        vec![vec![Value::Int(1)]]
+    );
+}
+
+#[test]
+fn user_defined_functions() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    engine
+        .execute(
+            parse_query("INSERT INTO users VALUES (1, 'alice')")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+    engine
+        .register_function("shout", 1, |args| match &args[0] {
+            Value::Text(s) => Ok(Value::Text(format!("{}!", s))),
+            other => Ok(other.clone()),
+        })
+        .unwrap();
+    engine
+        .register_function("clamp", 3, |args| match args {
+            [Value::Int(v), Value::Int(lo), Value::Int(hi)] => Ok(Value::Int(*v.clamp(lo, hi))),
+            _ => Err(EngineError::InvalidOperation("clamp expects ints".into())),
+        })
+        .unwrap();
+    assert!(matches!(
+        engine.register_function("coalesce", 2, |_| Ok(Value::Null)),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        engine.register_function("lower", 1, |_| Ok(Value::Null)),
+        Err(EngineError::InvalidOperation(_))
+    ));
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT Shout(name), CLAMP(id + 10, 0, 5) FROM users WHERE shout(name) = 'alice!'"),
+        Ok(vec![vec![Value::Text("alice!".into()), Value::Int(5)]])
+    );
+    assert!(matches!(
+        run("SELECT clamp(id, 'a', 2) FROM users"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        run("SELECT shout(name, id) FROM users"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    assert_eq!(
+        run("SELECT whisper(name) FROM users"),
+        Err(EngineError::UnknownFunction("whisper".into()))
+    );
+    assert_eq!(
+        run("SELECT UPPER(name), lower('MiXeD'), upper(NULL) FROM users"),
+        Ok(vec![vec![
+            Value::Text("ALICE".into()),
+            Value::Text("mixed".into()),
+            Value::Null
+        ]])
+    );
+    assert!(matches!(
+        run("SELECT lower(id) FROM users"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+}
+
+#[test]
+fn case_insensitive_keywords() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("insert into users values (1, 'Alice')");
+    run("Insert Into users (id, name) Values (2, 'Bob')");
+    run("INSERT into users VALUES (3, null)");
+
+    assert_eq!(
+        run("select * from users where id = 1"),
+        vec![vec![Value::Int(1), Value::Text("Alice".into())]]
+    );
+    assert_eq!(
+        run("with named as (select id, name from users where id > 1) \
+             Select n.id From named n Cross Join users u Where u.id = 1 And n.name Like 'B%' \
+             Order By n.id Desc Limit 5 Offset 0"),
+        vec![vec![Value::Int(2)]]
+    );
+    assert_eq!(
+        run("sElEcT CoUnT(*) fRoM users wHeRe NoT id In (1, 2) oR id BeTwEeN 5 aNd 6"),
+        vec![vec![Value::Int(1)]]
+    );
+}
+
+#[test]
+fn float_values() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "readings",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("temp".into(), ValueType::Float),
+            ],
+        )
+        .unwrap();
+    engine
+        .tables
+        .get_mut("readings")
+        .unwrap()
+        .create_index("temp");
+    for sql in [
+        "INSERT INTO readings VALUES (1, 21.5)",
+        "INSERT INTO readings VALUES (2, -0.0)",
+        "INSERT INTO readings VALUES (3, 1.5e1)",
+        "INSERT INTO readings VALUES (4, 2E-1)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+    assert!(matches!(
+        engine.execute(
+            parse_query("INSERT INTO readings VALUES (5, '7')")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM readings WHERE temp > 1.0 ORDER BY temp DESC"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(3)]])
+    );
+    // The index on `temp` treats -0.0 and 0.0 as the same key.
+    assert_eq!(
+        run("SELECT id FROM readings WHERE temp = 0.0"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("SELECT temp * 2.0, temp / 4.0, CAST(temp AS INT) FROM readings WHERE id = 1"),
+        Ok(vec![vec![
+            Value::Float(43.0),
+            Value::Float(5.375),
+            Value::Int(21)
+        ]])
+    );
+    assert_eq!(
+        run("SELECT SUM(temp), AVG(temp), MIN(temp) FROM readings"),
+        Ok(vec![vec![
+            Value::Float(36.7),
+            Value::Float(9.175),
+            Value::Float(-0.0)
+        ]])
+    );
+    assert_eq!(
+        run("SELECT CAST('2.5' AS FLOAT), CAST(3 AS REAL) FROM readings WHERE id = 1"),
+        Ok(vec![vec![Value::Float(2.5), Value::Float(3.0)]])
+    );
+    assert_eq!(
+        run("SELECT temp / 0.0 FROM readings"),
+        Err(EngineError::DivisionByZero)
+    );
+    assert!(matches!(
+        parse_query("SELECT id FROM readings WHERE temp < 1e400"),
+        Err(nom::Err::Failure(_))
+    ));
+}
+
+#[test]
+fn decimal_values() {
+    let money = parse_type("DECIMAL(8, 2)").unwrap().1;
+    assert_eq!(
+        money,
+        ValueType::Decimal {
+            precision: 8,
+            scale: 2
+        }
+    );
+    assert!(parse_type("NUMERIC(40)").is_err());
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "prices",
+            vec![("id".into(), ValueType::Int), ("amount".into(), money)],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO prices VALUES (1, DECIMAL '0.10')",
+        "INSERT INTO prices VALUES (2, 0.2)",
+        "INSERT INTO prices VALUES (3, 5)",
+        "INSERT INTO prices VALUES (4, 1.005)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+    assert_eq!(
+        engine.execute(
+            parse_query("INSERT INTO prices VALUES (5, 1000000)")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::NumericOverflow)
+    );
+    assert!(matches!(
+        engine.execute(
+            parse_query("INSERT INTO prices VALUES (5, '1.00')")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+
+    let dec = |s: &str| Value::Decimal(s.parse::<Decimal>().unwrap());
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    // 0.1 + 0.2 is exactly 0.3, and 1.005 was rounded half away from zero.
+    assert_eq!(
+        run("SELECT id FROM prices WHERE amount + DECIMAL '0.2' = DECIMAL '0.3'"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    assert_eq!(
+        run("SELECT amount FROM prices WHERE id = 1 OR id = 2"),
+        Ok(vec![vec![dec("0.10")], vec![dec("0.20")]])
+    );
+    assert_eq!(
+        run("SELECT SUM(amount), AVG(amount), MAX(amount) FROM prices"),
+        Ok(vec![vec![dec("6.31"), dec("1.5775"), dec("5.00")]])
+    );
+    assert_eq!(
+        run("SELECT amount * 3, amount / 3 FROM prices WHERE id = 4 AND amount > 1"),
+        Ok(vec![vec![dec("3.03"), dec("0.33666667")]])
+    );
+    assert_eq!(
+        run(
+            "SELECT CAST(amount AS TEXT), CAST('12.345' AS NUMERIC(5, 1)) FROM prices WHERE id = 3"
+        ),
+        Ok(vec![vec![Value::Text("5.00".into()), dec("12.3")]])
+    );
+}
+
+#[test]
+fn uuid_values() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "sessions",
+            vec![
+                ("id".into(), ValueType::Uuid),
+                ("user".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("INSERT INTO sessions VALUES (UUID '67e55044-10b1-426f-9247-bb680e5fe0c8', 'alice')");
+    run("INSERT INTO sessions VALUES (UUID '00000000-0000-0000-0000-000000000001', 'bob')");
+
+    assert_eq!(
+        run("SELECT user FROM sessions WHERE id = UUID '67E55044-10B1-426F-9247-BB680E5FE0C8'"),
+        vec![vec![Value::Text("alice".into())]]
+    );
+    assert_eq!(
+        run("SELECT CAST(id AS TEXT) FROM sessions ORDER BY id"),
+        vec![
+            vec![Value::Text("00000000-0000-0000-0000-000000000001".into())],
+            vec![Value::Text("67e55044-10b1-426f-9247-bb680e5fe0c8".into())],
+        ]
+    );
+    let rows = run("SELECT UUID(), UUID() FROM sessions WHERE user = 'bob'");
+    assert!(matches!(rows[0][0], Value::Uuid(u) if u.get_version_num() == 4));
+    assert_ne!(rows[0][0], rows[0][1]);
+    assert!(matches!(
+        parse_query("SELECT * FROM sessions WHERE id = UUID 'not-a-uuid'"),
+        Err(nom::Err::Failure(_))
+    ));
+
+    // UUID() makes a new value each time an INSERT runs, cached or not, and
+    // text that reads as a UUID is stored as one.
+    for _ in 0..2 {
+        engine
+            .execute_sql("INSERT INTO sessions VALUES (UUID(), 'carol')")
+            .unwrap();
+    }
+    engine
+        .execute_sql("INSERT INTO sessions VALUES ('6ba7b810-9dad-11d1-80b4-00c04fd430c8', 'dave')")
+        .unwrap();
+    assert_eq!(
+        engine.execute_sql("SELECT COUNT(DISTINCT id) FROM sessions"),
+        Ok(vec![vec![Value::Int(5)]])
+    );
+    assert_eq!(
+        engine.execute_sql(
+            "SELECT user FROM sessions WHERE id = UUID '6ba7b810-9dad-11d1-80b4-00c04fd430c8'"
+        ),
+        Ok(vec![vec![Value::Text("dave".into())]])
+    );
+    assert!(matches!(
+        engine.execute_sql("INSERT INTO sessions VALUES ('not-a-uuid', 'erin')"),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+}
+
+#[test]
+fn json_values() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "docs",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("body".into(), ValueType::Json),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        r#"INSERT INTO docs VALUES (1, JSON '{"name": "alice", "age": 31, "tags": ["a", "b"]}')"#,
+        r#"INSERT INTO docs VALUES (2, JSON '{"name": "bob", "age": 25, "address": {"city": "Oslo"}}')"#,
+        "INSERT INTO docs VALUES (3, NULL)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT body ->> 'name', body->>'$.tags[1]', body -> 'tags' FROM docs WHERE id = 1"),
+        Ok(vec![vec![
+            Value::Text("alice".into()),
+            Value::Text("b".into()),
+            Value::Json(serde_json::json!(["a", "b"]))
+        ]])
+    );
+    assert_eq!(
+        run("SELECT id FROM docs WHERE body->>'age' > 30 OR body->'address'->>'city' = 'Oslo'"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("SELECT json_extract(body, '$.address.city'), body->>'missing' FROM docs WHERE id = 2"),
+        Ok(vec![vec![
+            Value::Json(serde_json::json!("Oslo")),
+            Value::Null
+        ]])
+    );
+    assert_eq!(
+        run(r#"SELECT id FROM docs WHERE body -> 'tags' = JSON '["a","b"]'"#),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    // JSON scalars compare with SQL values as the values they hold.
+    assert_eq!(
+        run("SELECT id FROM docs WHERE body->>'age' = 31 OR body->>'name' = 'bob'"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run(
+            "SELECT id FROM docs WHERE json_extract(body, '$.age') = 25 AND body -> 'name' = 'bob'"
+        ),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM docs WHERE 30 < body -> 'age' OR body -> 'address' = 'Oslo'"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    assert_eq!(
+        run("SELECT CAST(body -> 'address' AS TEXT) FROM docs WHERE id = 2"),
+        Ok(vec![vec![Value::Text(r#"{"city":"Oslo"}"#.into())]])
+    );
+    assert!(matches!(
+        run("SELECT body -> '$.tags[' FROM docs"),
+        Err(EngineError::InvalidPattern(_))
+    ));
+}
+
+#[test]
+fn list_values() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "posts",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("tags".into(), ValueType::List),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO posts VALUES (1, ARRAY['rust', 'sql'])",
+        "INSERT INTO posts VALUES (2, ARRAY['go'])",
+        "INSERT INTO posts VALUES (3, ARRAY[])",
+        "INSERT INTO posts VALUES (4, NULL)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM posts WHERE 'sql' = ANY (tags)"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM posts WHERE NOT 'go' = ANY(tags)"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM posts WHERE tags @> ARRAY['sql'] OR tags <@ ARRAY['go', 'c']"),
+        Ok(vec![
+            vec![Value::Int(1)],
+            vec![Value::Int(2)],
+            vec![Value::Int(3)]
+        ])
+    );
+    assert_eq!(
+        run("SELECT tags[1], tags[3], CARDINALITY(tags) FROM posts WHERE id = 1"),
+        Ok(vec![vec![
+            Value::Text("rust".into()),
+            Value::Null,
+            Value::Int(2)
+        ]])
+    );
+    assert_eq!(
+        run("SELECT tags FROM posts WHERE tags = ARRAY['go']"),
+        Ok(vec![vec![Value::List(vec![Value::Text("go".into())])]])
+    );
+    assert!(matches!(
+        run("SELECT id FROM posts WHERE 1 = ANY (id)"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+
+    // `[...]` is a list literal without the ARRAY keyword.
+    run("INSERT INTO posts VALUES (5, ['sql', 'go', 'c'])").unwrap();
+    assert_eq!(
+        run("SELECT id FROM posts WHERE tags @> ['go', 'c']"),
+        Ok(vec![vec![Value::Int(5)]])
+    );
+
+    // UNNEST gives a row per element; empty and NULL lists give none.
+    assert_eq!(
+        run("SELECT id, UNNEST(tags) AS tag FROM posts ORDER BY id"),
+        Ok(vec![
+            vec![Value::Int(1), Value::Text("rust".into())],
+            vec![Value::Int(1), Value::Text("sql".into())],
+            vec![Value::Int(2), Value::Text("go".into())],
+            vec![Value::Int(5), Value::Text("sql".into())],
+            vec![Value::Int(5), Value::Text("go".into())],
+            vec![Value::Int(5), Value::Text("c".into())],
+        ])
+    );
+    assert_eq!(
+        run("SELECT unnest(tags), unnest([1, 2]) FROM posts WHERE id = 5 LIMIT 2 OFFSET 1"),
+        Ok(vec![
+            vec![Value::Text("go".into()), Value::Int(2)],
+            vec![Value::Text("c".into()), Value::Null],
+        ])
+    );
+    assert!(matches!(
+        run("SELECT UNNEST(id) FROM posts"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        run("SELECT CARDINALITY(UNNEST(tags)) FROM posts"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn enum_columns() {
+    let status = parse_type("ENUM('new', 'active', 'done')").unwrap().1;
+    assert!(parse_type("ENUM('a', 'a')").is_err());
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "tasks",
+            vec![("id".into(), ValueType::Int), ("status".into(), status)],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO tasks VALUES (1, 'done')",
+        "INSERT INTO tasks VALUES (2, 'new')",
+        "INSERT INTO tasks VALUES (3, 'active')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+    assert_eq!(
+        engine.execute(
+            parse_query("INSERT INTO tasks VALUES (4, 'closed')")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::InvalidEnumValue {
+            column: "status".into(),
+            value: "closed".into()
+        })
+    );
+    engine
+        .tables
+        .get_mut("tasks")
+        .unwrap()
+        .create_index("status");
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    let rows = run("SELECT status FROM tasks WHERE id = 1").unwrap();
+    let Value::Enum(done) = &rows[0][0] else {
+        panic!("expected an enum value, got {:?}", rows[0][0]);
+    };
+    assert_eq!((done.label(), done.index()), ("done", 2));
+    assert_eq!(
+        run("SELECT id FROM tasks WHERE status = 'new'"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM tasks WHERE status > 'new' ORDER BY status"),
+        Ok(vec![vec![Value::Int(3)], vec![Value::Int(1)]])
+    );
+    assert_eq!(
+        run("SELECT CAST(status AS TEXT), CAST(status AS INT) FROM tasks WHERE id = 3"),
+        Ok(vec![vec![Value::Text("active".into()), Value::Int(2)]])
+    );
+}
+
+#[test]
+fn bigint_values() {
+    // The 128-bit type is HUGEINT; BIGINT is the 64-bit INT.
+    assert_eq!(parse_type("HUGEINT").unwrap().1, ValueType::BigInt);
+    assert_eq!(parse_type("int128").unwrap().1, ValueType::BigInt);
+    assert_eq!(parse_type("BIGINT").unwrap().1, ValueType::Int);
+    let mut engine = Engine::new();
+    for sql in [
+        "CREATE TABLE counters (id HUGEINT, hits HUGEINT)",
+        "INSERT INTO counters VALUES (18446744073709551615, 1)",
+        "INSERT INTO counters VALUES (2, 9223372036854775807)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT hits FROM counters WHERE id = 18446744073709551615"),
+        Ok(vec![vec![Value::BigInt(1)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM counters WHERE id = 2"),
+        Ok(vec![vec![Value::BigInt(2)]])
+    );
+    assert_eq!(
+        run("SELECT hits + 1 FROM counters WHERE id < 10"),
+        Ok(vec![vec![Value::BigInt(9_223_372_036_854_775_808)]])
+    );
+    assert_eq!(
+        run("SELECT SUM(hits) FROM counters"),
+        Ok(vec![vec![Value::BigInt(9_223_372_036_854_775_808)]])
+    );
+    assert_eq!(
+        run("SELECT CAST(id AS TEXT) FROM counters WHERE hits = 1"),
+        Ok(vec![vec![Value::Text("18446744073709551615".into())]])
+    );
+    assert_eq!(
+        run("SELECT id * id * id FROM counters WHERE hits = 1"),
+        Err(EngineError::NumericOverflow)
+    );
+}
+
+#[test]
+fn numeric_coercion() {
+    let price = parse_type("DECIMAL(6, 2)").unwrap().1;
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "items",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("weight".into(), ValueType::Float),
+                ("price".into(), price),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO items VALUES (1, 2, 3)",
+        "INSERT INTO items VALUES (2.0, 0.5, 1.25)",
+        "INSERT INTO items VALUES (3, 10, 9.99)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+    assert!(matches!(
+        engine.execute(
+            parse_query("INSERT INTO items VALUES (4.5, 1, 1)")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+    assert_eq!(
+        engine.execute(
+            parse_query("INSERT INTO items VALUES (1e30, 1, 1)")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::NumericOverflow)
+    );
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id, weight FROM items WHERE id = 2"),
+        Ok(vec![vec![Value::Int(2), Value::Float(0.5)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM items WHERE id = 2.0 OR weight = 10 OR price = 1.25"),
+        Ok(vec![vec![Value::Int(2)], vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM items WHERE weight < price AND id IN (1.0, 3.0)"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    assert_eq!(
+        run("SELECT id + 0.5, weight * 2, price * weight FROM items WHERE id = 1"),
+        Ok(vec![vec![
+            Value::Float(1.5),
+            Value::Float(4.0),
+            Value::Float(6.0)
+        ]])
+    );
+    assert_eq!(
+        run("SELECT MAX(price) FROM items WHERE price > weight"),
+        Ok(vec![vec![Value::Decimal("3.00".parse().unwrap())]])
+    );
+}
+
+#[test]
+fn typing_modes() {
+    let mut engine = Engine::new();
+    let columns = || {
+        vec![
+            ("id".into(), ValueType::Int),
+            ("label".into(), ValueType::Text),
+        ]
+    };
+    engine
+        .create_table_with_typing("loose", columns(), Typing::Flexible)
+        .unwrap();
+    engine.create_table("tight", columns()).unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+
+    run("INSERT INTO loose VALUES ('7', 42)").unwrap();
+    run("INSERT INTO loose VALUES ('n/a', TRUE)").unwrap();
+    assert!(matches!(
+        run("INSERT INTO tight VALUES ('7', 'x')"),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+    assert_eq!(
+        run("SELECT id, label FROM loose"),
+        Ok(vec![
+            vec![Value::Int(7), Value::Int(42)],
+            vec![Value::Text("n/a".into()), Value::Bool(true)]
+        ])
+    );
+    assert_eq!(
+        run("SELECT label FROM loose WHERE id = 7"),
+        Ok(vec![vec![Value::Int(42)]])
+    );
+
+    assert_eq!(
+        run("PRAGMA typing(tight)"),
+        Ok(vec![vec![Value::Text("STRICT".into())]])
+    );
+    run("PRAGMA typing(tight) = flexible").unwrap();
+    run("INSERT INTO tight VALUES ('7', 'x')").unwrap();
+    run("PRAGMA typing = 'FLEXIBLE'").unwrap();
+    assert_eq!(
+        run("PRAGMA typing"),
+        Ok(vec![vec![Value::Text("FLEXIBLE".into())]])
+    );
+    assert!(matches!(
+        run("PRAGMA typing = LOOSE"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    engine.create_table("later", columns()).unwrap();
+    assert_eq!(engine.tables["later"].typing, Typing::Flexible);
+}
+
+#[test]
+fn value_display_round_trip() {
+    let values = [
+        Value::Int(-42),
+        Value::BigInt(7),
+        Value::BigInt(i128::MAX),
+        Value::Float(2.5),
+        Value::Float(1e300),
+        Value::Float(f64::NEG_INFINITY),
+        Value::Float(f64::NAN),
+        Value::Decimal("12.50".parse().unwrap()),
+        Value::Text("it's\nhere".into()),
+        Value::Bool(false),
+        Value::Interval(sql_core::Interval::new(14, -3_600_000_000)),
+        Value::Uuid("67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap()),
+        Value::Json(serde_json::json!({"a": [1, "b"]})),
+        Value::List(vec![Value::Int(1), Value::Null, Value::Text("x".into())]),
+        Value::Null,
+    ];
+    for value in values {
+        let text = value.to_string();
+        assert_eq!(text.parse::<Value>(), Ok(value), "{}", text);
+    }
+
+    assert_eq!(Value::Text("it's".into()).to_string(), "'it''s'");
+    assert_eq!(Value::Float(f64::NAN).to_string(), "FLOAT 'NaN'");
+    let date: Value = " DATE '2024-02-29' ".parse().unwrap();
+    assert_eq!(date.to_string(), "DATE '2024-02-29'");
+    assert!("1 + 1".parse::<Value>().is_err());
+    assert!("name".parse::<Value>().is_err());
+}
+
+#[test]
+fn value_conversions() {
+    let row: Vec<Value> = vec![
+        7.into(),
+        "seven".into(),
+        true.into(),
+        None::<i64>.into(),
+        Some(2.5).into(),
+        u64::MAX.into(),
+        vec![1, 2].into(),
+    ];
+    assert_eq!(
+        row,
+        vec![
+            Value::Int(7),
+            Value::Text("seven".into()),
+            Value::Bool(true),
+            Value::Null,
+            Value::Float(2.5),
+            Value::BigInt(u64::MAX as i128),
+            Value::List(vec![Value::Int(1), Value::Int(2)])
+        ]
+    );
+
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "t",
+            vec![("n".into(), ValueType::Int), ("s".into(), ValueType::Text)],
+        )
+        .unwrap();
+    engine
+        .insert_into("t", vec![3.into(), "x".into()], None)
+        .unwrap();
+    engine
+        .insert_into("t", vec![4.into(), None::<&str>.into()], None)
+        .unwrap();
+    let rows = engine
+        .execute(parse_query("SELECT n, s FROM t").unwrap().1)
+        .unwrap();
+    let decoded: Vec<(i64, Option<String>)> = rows
+        .into_iter()
+        .map(|r| {
+            let mut r = r.into_iter();
+            let n = i64::try_from(r.next().unwrap()).unwrap();
+            let s = Option::<String>::try_from(r.next().unwrap()).unwrap();
+            (n, s)
+        })
+        .collect();
+    assert_eq!(decoded, vec![(3, Some("x".into())), (4, None)]);
+
+    assert_eq!(f64::try_from(Value::Int(2)), Ok(2.0));
+    assert_eq!(i64::try_from(Value::BigInt(1 << 70)).ok(), None);
+    assert_eq!(
+        bool::try_from(Value::Null),
+        Err(EngineError::InvalidCast {
+            value: Value::Null,
+            target: ValueType::Bool
+        })
+    );
+}
+
+#[test]
+fn interval_values() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "subscriptions",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("started".into(), ValueType::Date),
+                ("term".into(), parse_type("INTERVAL").unwrap().1),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO subscriptions VALUES (1, DATE '2024-01-31', INTERVAL 1 MONTH)",
+        "INSERT INTO subscriptions VALUES (2, DATE '2024-03-01', INTERVAL '2 weeks')",
+        "INSERT INTO subscriptions VALUES (3, DATE '2023-06-15', INTERVAL '1' YEAR)",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    let date = |s: &str| format!("DATE '{}'", s).parse::<Value>().unwrap();
+    assert_eq!(
+        run("SELECT started + term FROM subscriptions"),
+        Ok(vec![
+            vec![date("2024-02-29")],
+            vec![date("2024-03-15")],
+            vec![date("2024-06-15")]
+        ])
+    );
+    assert_eq!(
+        run(
+            "SELECT CAST(started + INTERVAL '1 day 06:00' AS TEXT) FROM subscriptions WHERE id = 2"
+        ),
+        Ok(vec![vec![Value::Text("2024-03-02 06:00:00".into())]])
+    );
+    assert_eq!(
+        run("SELECT CAST(TIMESTAMP '2024-03-31 12:00:00' - INTERVAL 1 MONTH AS TEXT) FROM subscriptions WHERE id = 1"),
+        Ok(vec![vec![Value::Text("2024-02-29 12:00:00".into())]])
+    );
+    assert_eq!(
+        run("SELECT id FROM subscriptions WHERE term > INTERVAL 20 DAY ORDER BY term"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT CAST(term * 3 + INTERVAL 90 MINUTES AS TEXT) FROM subscriptions WHERE id = 1"),
+        Ok(vec![vec![Value::Text("3 mons 01:30:00".into())]])
+    );
+    assert_eq!(
+        run("SELECT SUM(term) FROM subscriptions"),
+        Ok(vec![vec![Value::Interval(sql_core::Interval::new(
+            13,
+            14 * 86_400_000_000
+        ))]])
+    );
+    assert!(parse_query("SELECT INTERVAL '3 fortnights' FROM subscriptions").is_err());
+}
+
+#[test]
+fn varchar_columns() {
+    assert_eq!(parse_type("VARCHAR(3)").unwrap().1, ValueType::Varchar(3));
+    assert_eq!(parse_type("varchar").unwrap().1, ValueType::Text);
+    assert!(parse_type("VARCHAR(0)").is_err());
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "countries",
+            vec![
+                ("code".into(), parse_type("VARCHAR(3)").unwrap().1),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO countries VALUES ('NOR', 'Norway')").unwrap();
+    run("INSERT INTO countries VALUES ('ÅLA', 'Åland')").unwrap();
+    assert_eq!(
+        run("INSERT INTO countries VALUES ('SWED', 'Sweden')"),
+        Err(EngineError::ValueTooLong {
+            column: "code".into(),
+            max: 3,
+            actual: 4
+        })
+    );
+    assert_eq!(
+        run("SELECT code, CAST(name AS VARCHAR(2)) FROM countries WHERE code = 'ÅLA'"),
+        Ok(vec![vec![
+            Value::Text("ÅLA".into()),
+            Value::Text("Ål".into())
+        ]])
+    );
+}
+
+#[test]
+fn binary_row_encoding() {
+    use sql_core::codec::{decode_row, decode_value, encode_row, encode_value, DecodeError};
+
+    let status = parse_type("ENUM('new', 'done')").unwrap().1;
+    let mut engine = Engine::new();
+    engine
+        .create_table("t", vec![("status".into(), status)])
+        .unwrap();
+    engine.insert_into("t", vec!["done".into()], None).unwrap();
+    let enum_value = engine.tables["t"].row(0)[0].clone();
+
+    let row: Vec<Value> = vec![
+        Value::Int(-300),
+        Value::BigInt(i128::MIN),
+        Value::Float(-0.5),
+        Value::Decimal("-12.345".parse().unwrap()),
+        Value::Text("naïve".into()),
+        Value::Bool(true),
+        "DATE '1969-12-31'".parse().unwrap(),
+        "TIMESTAMP '2024-05-06 07:08:09.5'".parse().unwrap(),
+        "INTERVAL '1 mon -02:00'".parse().unwrap(),
+        Value::Uuid("67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap()),
+        Value::Json(serde_json::json!({"k": [1, null]})),
+        Value::List(vec![Value::Int(1), Value::List(vec![Value::Null])]),
+        enum_value,
+        Value::Null,
+    ];
+    let bytes = encode_row(&row);
+    assert_eq!(decode_row(&bytes), Ok(row));
+
+    // Small integers take a tag byte and a single varint byte.
+    assert_eq!(encode_value(&Value::Int(-1)), vec![1, 1, 1]);
+    assert_eq!(
+        decode_value(&encode_value(&Value::Text("hi".into()))),
+        Ok(Value::Text("hi".into()))
+    );
+    assert_eq!(decode_row(&[]), Err(DecodeError::UnexpectedEnd));
+    assert_eq!(decode_row(&[9, 0]), Err(DecodeError::UnsupportedVersion(9)));
+    assert_eq!(decode_row(&[1, 1, 99]), Err(DecodeError::InvalidTag(99)));
+    assert_eq!(
+        decode_row(&[1, 1, 5, 3, b'a']),
+        Err(DecodeError::UnexpectedEnd)
+    );
+    assert_eq!(decode_row(&[1, 0, 0]), Err(DecodeError::TrailingBytes));
+}
+
+#[test]
+fn custom_value_types() {
+    use sql_core::{codec, register_custom_type, CustomType};
+    use std::fmt;
+
+    #[derive(
+        Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+    )]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    impl fmt::Display for Point {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "({}, {})", self.x, self.y)
+        }
+    }
+
+    impl CustomType for Point {
+        const NAME: &'static str = "point";
+    }
+
+    register_custom_type::<Point>();
+    let mut engine = Engine::new();
+    engine
+        .register_function("POINT", 2, |args| match args {
+            [Value::Int(x), Value::Int(y)] => Ok(Value::custom(Point { x: *x, y: *y })),
+            _ => Err(EngineError::InvalidOperation("POINT takes two ints".into())),
+        })
+        .unwrap();
+    engine
+        .create_table(
+            "places",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("at".into(), ValueType::Custom("point".into())),
+            ],
+        )
+        .unwrap();
+    for (id, x, y) in [(1, 3, 4), (2, 0, 9), (3, 3, 1)] {
+        engine
+            .insert_into(
+                "places",
+                vec![Value::Int(id), Value::custom(Point { x, y })],
+                None,
+            )
+            .unwrap();
+    }
+    assert!(matches!(
+        engine.insert_into("places", vec![Value::Int(4), Value::Int(0)], None),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM places WHERE at = POINT(3, 4)"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM places WHERE at < POINT(3, 2) ORDER BY at"),
+        Ok(vec![vec![Value::Int(2)], vec![Value::Int(3)]])
+    );
+    let rows = run("SELECT at FROM places WHERE id = 2").unwrap();
+    assert_eq!(rows[0][0].as_custom::<Point>(), Some(&Point { x: 0, y: 9 }));
+    assert_eq!(rows[0][0].to_string(), "'(0, 9)'");
+
+    let json = serde_json::to_string(&rows[0]).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Value>>(&json).unwrap(), rows[0]);
+    assert_eq!(
+        codec::decode_row(&codec::encode_row(&rows[0])),
+        Ok(rows[0].clone())
+    );
+}
+
+#[test]
+fn total_ordering() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "samples",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("x".into(), ValueType::Float),
+            ],
+        )
+        .unwrap();
+    for sql in [
+        "INSERT INTO samples VALUES (1, FLOAT 'NaN')",
+        "INSERT INTO samples VALUES (2, 2.5)",
+        "INSERT INTO samples VALUES (3, NULL)",
+        "INSERT INTO samples VALUES (4, FLOAT '-inf')",
+    ] {
+        engine.execute(parse_query(sql).unwrap().1).unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    // NULL sorts first and NaN after every other number.
+    assert_eq!(
+        run("SELECT id FROM samples ORDER BY x"),
+        Ok(vec![
+            vec![Value::Int(3)],
+            vec![Value::Int(4)],
+            vec![Value::Int(2)],
+            vec![Value::Int(1)]
+        ])
+    );
+    let rows = run("SELECT MIN(x), MAX(x) FROM samples").unwrap();
+    assert_eq!(rows[0][0], Value::Float(f64::NEG_INFINITY));
+    assert!(matches!(rows[0][1], Value::Float(f) if f.is_nan()));
+
+    let mut mixed = vec![
+        Value::Text("a".into()),
+        Value::Float(1.0),
+        Value::Null,
+        Value::Int(1),
+        Value::Bool(true),
+        Value::Decimal("0.5".parse().unwrap()),
+    ];
+    mixed.sort();
+    assert_eq!(
+        mixed,
+        vec![
+            Value::Null,
+            Value::Bool(true),
+            Value::Decimal("0.5".parse().unwrap()),
+            Value::Int(1),
+            Value::Float(1.0),
+            Value::Text("a".into()),
+        ]
+    );
+}
+
+#[test]
+fn bool_int_coercion() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("active".into(), ValueType::Bool),
+            ],
+        )
+        .unwrap();
+    engine
+        .tables
+        .get_mut("users")
+        .unwrap()
+        .create_index("active");
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for sql in [
+        "INSERT INTO users VALUES (1, TRUE)",
+        "INSERT INTO users VALUES (2, FALSE)",
+        "INSERT INTO users VALUES (3, TRUE)",
+        "INSERT INTO users VALUES (4, NULL)",
+    ] {
+        run(sql).unwrap();
+    }
+
+    // Off by default: booleans never equal numbers.
+    assert_eq!(run("SELECT id FROM users WHERE active = 1"), Ok(vec![]));
+    assert!(run("SELECT SUM(active) FROM users").is_err());
+
+    assert_eq!(run("PRAGMA bool_ints"), Ok(vec![vec![Value::Bool(false)]]));
+    run("PRAGMA bool_ints = ON").unwrap();
+    assert_eq!(
+        run("SELECT id FROM users WHERE active = 1"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM users WHERE active IN (0)"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM users WHERE active = TRUE AND id > 1"),
+        Ok(vec![vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT SUM(active), COUNT(active) FROM users"),
+        Ok(vec![vec![Value::Int(2), Value::Int(3)]])
+    );
+    assert!(matches!(
+        run("PRAGMA bool_ints = maybe"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    run("PRAGMA bool_ints = 0").unwrap();
+    assert_eq!(run("SELECT id FROM users WHERE active = 1"), Ok(vec![]));
+}
+
+#[test]
+fn typed_nulls() {
+    use sql_core::codec;
+
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "notes",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("body".into(), ValueType::Text),
+                ("due".into(), ValueType::Date),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO notes VALUES (1, NULL, DATE '2024-05-01')").unwrap();
+    run("INSERT INTO notes (id) VALUES (2)").unwrap();
+
+    let rows = run("SELECT body, due FROM notes WHERE id = 2").unwrap();
+    assert_eq!(rows, vec![vec![Value::Null, Value::Null]]);
+    assert_eq!(rows[0][0].value_type(), ValueType::Text);
+    assert_eq!(rows[0][1].value_type(), ValueType::Date);
+    assert_eq!(rows[0][0].to_string(), "NULL");
+    assert_eq!(
+        codec::decode_row(&codec::encode_row(&rows[0])),
+        Ok(rows[0].clone())
+    );
+
+    let rows = run("SELECT CAST(NULL AS INT) FROM notes WHERE id = 1").unwrap();
+    assert_eq!(rows[0][0], Value::TypedNull(ValueType::Int));
+    assert_eq!(rows[0][0].value_type(), ValueType::Int);
+    assert_eq!(
+        run("SELECT id FROM notes WHERE due < DATE '2025-01-01'"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+}
+
+#[test]
+fn unique_constraints() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "accounts",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("email".into(), ValueType::Text),
+                ("org".into(), ValueType::Int),
+                ("handle".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    let table = engine.tables.get_mut("accounts").unwrap();
+    table.add_unique("accounts_email_key", &["email"]).unwrap();
+    table
+        .add_unique("accounts_org_handle_key", &["org", "handle"])
+        .unwrap();
+    assert_eq!(
+        table.add_unique("bad", &["missing"]),
+        Err(EngineError::ColumnNotFound("missing".into()))
+    );
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO accounts VALUES (1, 'a@x.io', 1, 'ann')").unwrap();
+    run("INSERT INTO accounts VALUES (2, 'b@x.io', 2, 'ann')").unwrap();
+    assert_eq!(
+        run("INSERT INTO accounts VALUES (3, 'a@x.io', 3, 'cy')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "accounts_email_key".into(),
+            value: Value::Text("a@x.io".into()),
+        })
+    );
+    assert_eq!(
+        run("INSERT INTO accounts VALUES (3, 'c@x.io', 1, 'ann')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "accounts_org_handle_key".into(),
+            value: Value::List(vec![Value::Int(1), Value::Text("ann".into())]),
+        })
+    );
+    // NULLs never collide.
+    run("INSERT INTO accounts (id, org) VALUES (4, 1)").unwrap();
+    run("INSERT INTO accounts (id, org) VALUES (5, 1)").unwrap();
+    assert_eq!(
+        run("SELECT id FROM accounts WHERE id > 2"),
+        Ok(vec![vec![Value::Int(4)], vec![Value::Int(5)]])
+    );
+
+    let table = engine.tables.get_mut("accounts").unwrap();
+    assert!(matches!(
+        table.add_unique("accounts_org_key", &["org"]),
+        Err(EngineError::UniqueViolation {
+            value: Value::Int(1),
+            ..
+        })
+    ));
+    assert_eq!(table.uniques.len(), 2);
+
+    // The same constraints in CREATE TABLE, named after their columns
+    // unless named.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE members (id INT, email TEXT UNIQUE, org INT, handle TEXT, CONSTRAINT member_handle UNIQUE (org, handle))").unwrap();
+    run("INSERT INTO members VALUES (1, 'a@x.io', 1, 'ann')").unwrap();
+    assert_eq!(
+        run("INSERT INTO members VALUES (2, 'a@x.io', 2, 'bo')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "members_email_key".into(),
+            value: Value::Text("a@x.io".into()),
+        })
+    );
+    assert_eq!(
+        run("INSERT INTO members VALUES (2, 'b@x.io', 1, 'ann')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "member_handle".into(),
+            value: Value::List(vec![Value::Int(1), Value::Text("ann".into())]),
+        })
+    );
+}
+
+#[test]
+fn delete_and_update() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "items",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("qty".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for id in 1..=4 {
+        run(&format!("INSERT INTO items VALUES ({}, {})", id, id * 10)).unwrap();
+    }
+    run("UPDATE items SET qty = qty + 1, id = id * 100 WHERE qty >= 30").unwrap();
+    run("DELETE FROM items WHERE id = 2").unwrap();
+    assert_eq!(
+        run("SELECT id, qty FROM items"),
+        Ok(vec![
+            vec![Value::Int(1), Value::Int(10)],
+            vec![Value::Int(300), Value::Int(31)],
+            vec![Value::Int(400), Value::Int(41)]
+        ])
+    );
+    // The index on `id` follows the changes.
+    assert_eq!(
+        run("SELECT qty FROM items WHERE id = 400"),
+        Ok(vec![vec![Value::Int(41)]])
+    );
+    assert!(matches!(
+        run("UPDATE items SET qty = 'many'"),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+    run("DELETE FROM items").unwrap();
+    assert_eq!(run("SELECT id FROM items"), Ok(vec![]));
+}
+
+#[test]
+fn foreign_keys() {
+    use sql_core::{ForeignKey, ReferentialAction};
+
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "authors",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("name".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    for child in ["books", "reviews", "quotes"] {
+        engine
+            .create_table(
+                child,
+                vec![
+                    ("id".into(), ValueType::Int),
+                    ("author".into(), ValueType::Int),
+                ],
+            )
+            .unwrap();
+    }
+    assert!(matches!(
+        engine.add_foreign_key(
+            "books",
+            ForeignKey::new("fk", &["author"], "authors", &["id"])
+        ),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    engine
+        .tables
+        .get_mut("authors")
+        .unwrap()
+        .add_unique("authors_pkey", &["id"])
+        .unwrap();
+    let fk = |name: &str| ForeignKey::new(name, &["author"], "authors", &["id"]);
+    engine
+        .add_foreign_key(
+            "books",
+            fk("books_author_fkey")
+                .on_delete(ReferentialAction::Cascade)
+                .on_update(ReferentialAction::Cascade),
+        )
+        .unwrap();
+    engine
+        .add_foreign_key(
+            "reviews",
+            fk("reviews_author_fkey").on_delete(ReferentialAction::SetNull),
+        )
+        .unwrap();
+    engine
+        .add_foreign_key("quotes", fk("quotes_author_fkey"))
+        .unwrap();
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for sql in [
+        "INSERT INTO authors VALUES (1, 'Le Guin')",
+        "INSERT INTO authors VALUES (2, 'Calvino')",
+        "INSERT INTO authors VALUES (3, 'Borges')",
+        "INSERT INTO books VALUES (10, 1)",
+        "INSERT INTO books VALUES (11, 2)",
+        "INSERT INTO reviews VALUES (20, 2)",
+        "INSERT INTO quotes VALUES (30, 3)",
+        "INSERT INTO quotes (id) VALUES (31)",
+    ] {
+        run(sql).unwrap();
+    }
+    assert_eq!(
+        run("INSERT INTO books VALUES (12, 9)"),
+        Err(EngineError::ForeignKeyViolation {
+            constraint: "books_author_fkey".into(),
+            value: Value::Int(9),
+        })
+    );
+
+    run("UPDATE authors SET id = 5 WHERE id = 1").unwrap();
+    assert_eq!(
+        run("SELECT author FROM books WHERE id = 10"),
+        Ok(vec![vec![Value::Int(5)]])
+    );
+    run("DELETE FROM authors WHERE id = 2").unwrap();
+    assert_eq!(run("SELECT id FROM books WHERE id = 11"), Ok(vec![]));
+    assert_eq!(
+        run("SELECT author FROM reviews"),
+        Ok(vec![vec![Value::Null]])
+    );
+
+    // RESTRICT fails the whole statement, including its cascades.
+    assert_eq!(
+        run("DELETE FROM authors"),
+        Err(EngineError::ForeignKeyViolation {
+            constraint: "quotes_author_fkey".into(),
+            value: Value::Int(3),
+        })
+    );
+    assert_eq!(
+        run("SELECT id FROM authors"),
+        Ok(vec![vec![Value::Int(5)], vec![Value::Int(3)]])
+    );
+    assert_eq!(run("SELECT id FROM books"), Ok(vec![vec![Value::Int(10)]]));
+    assert!(matches!(
+        run("UPDATE authors SET id = 4 WHERE id = 3"),
+        Err(EngineError::ForeignKeyViolation { .. })
+    ));
+
+    // The same keys in CREATE TABLE. One that names no columns references
+    // the primary key, and a table may reference itself.
+    run("CREATE TABLE writers (id INT PRIMARY KEY, mentor INT REFERENCES writers ON DELETE SET NULL)").unwrap();
+    run("CREATE TABLE essays (id INT, writer INT, FOREIGN KEY (writer) REFERENCES writers (id) ON DELETE CASCADE ON UPDATE RESTRICT)").unwrap();
+    assert_eq!(
+        engine.tables["essays"].foreign_keys,
+        vec![
+            ForeignKey::new("essays_writer_fkey", &["writer"], "writers", &["id"])
+                .on_delete(ReferentialAction::Cascade)
+        ]
+    );
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO writers VALUES (1, NULL)").unwrap();
+    run("INSERT INTO writers VALUES (2, 1)").unwrap();
+    run("INSERT INTO essays VALUES (100, 1)").unwrap();
+    assert_eq!(
+        run("INSERT INTO essays VALUES (101, 7)"),
+        Err(EngineError::ForeignKeyViolation {
+            constraint: "essays_writer_fkey".into(),
+            value: Value::Int(7),
+        })
+    );
+    run("DELETE FROM writers WHERE id = 1").unwrap();
+    assert_eq!(run("SELECT id FROM essays"), Ok(vec![]));
+    assert_eq!(
+        run("SELECT mentor FROM writers"),
+        Ok(vec![vec![Value::Null]])
+    );
+
+    // A key that cannot hold leaves no table behind.
+    assert!(matches!(
+        run("CREATE TABLE notes (id INT, essay INT REFERENCES essays (id))"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        run("CREATE TABLE notes (id INT, essay INT REFERENCES essays)"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        run("CREATE TABLE notes (id INT, essay INT REFERENCES missing)"),
+        Err(EngineError::TableNotFound(_))
+    ));
+    // Keys must hold the same kind of value as the columns they reference.
+    assert_eq!(
+        run("CREATE TABLE notes (id INT, writer TEXT REFERENCES writers)"),
+        Err(EngineError::TypeMismatch {
+            column: "writer".into(),
+            expected: ValueType::Int,
+            found: ValueType::Text,
+        })
+    );
+    assert!(!engine.tables.contains_key("notes"));
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE notes (id INT, writer HUGEINT)").unwrap();
+    assert_eq!(
+        run("ALTER TABLE notes ADD FOREIGN KEY (writer) REFERENCES writers (id)"),
+        Err(EngineError::TypeMismatch {
+            column: "writer".into(),
+            expected: ValueType::Int,
+            found: ValueType::BigInt,
+        })
+    );
+    assert!(engine.tables["notes"].foreign_keys.is_empty());
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE handles (name VARCHAR(20) UNIQUE)").unwrap();
+    run("CREATE TABLE mentions (handle TEXT REFERENCES handles (name))").unwrap();
+}
+
+#[test]
+fn auto_increment() {
+    let mut engine = Engine::new();
+    engine
+        .execute(
+            parse_query("CREATE TABLE orders (id INT AUTO_INCREMENT PRIMARY KEY, item TEXT)")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+    engine
+        .create_table("notes", vec![("body".into(), ValueType::Text)])
+        .unwrap();
+    assert!(matches!(
+        engine
+            .tables
+            .get_mut("notes")
+            .unwrap()
+            .set_auto_increment("body"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        engine.execute(
+            parse_query("CREATE TABLE memos (body TEXT AUTO_INCREMENT)")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    assert!(!engine.tables.contains_key("memos"));
+    assert_eq!(engine.last_insert_id(), None);
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO orders (item) VALUES ('tea')").unwrap();
+    run("INSERT INTO orders VALUES (NULL, 'jam')").unwrap();
+    run("INSERT INTO orders VALUES (10, 'oats')").unwrap();
+    run("INSERT INTO orders (item) VALUES ('figs')").unwrap();
+    assert_eq!(
+        run("SELECT id, item FROM orders"),
+        Ok(vec![
+            vec![Value::Int(1), Value::Text("tea".into())],
+            vec![Value::Int(2), Value::Text("jam".into())],
+            vec![Value::Int(10), Value::Text("oats".into())],
+            vec![Value::Int(11), Value::Text("figs".into())]
+        ])
+    );
+    assert_eq!(
+        run("SELECT LAST_INSERT_ID() FROM orders WHERE id = 1"),
+        Ok(vec![vec![Value::Int(11)]])
+    );
+    assert_eq!(engine.last_insert_id(), Some(11));
+}
+
+#[test]
+fn auto_increment_past_int_range() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE t (id HUGEINT PRIMARY KEY AUTO_INCREMENT, name TEXT)").unwrap();
+    run("INSERT INTO t VALUES (1, 'a')").unwrap();
+    run("INSERT INTO t VALUES (10000000000000000000, 'big')").unwrap();
+    run("INSERT INTO t (name) VALUES ('next')").unwrap();
+    assert_eq!(
+        run("SELECT id FROM t WHERE name = 'next'"),
+        Ok(vec![vec![Value::BigInt(10_000_000_000_000_000_001)]])
+    );
+    assert_eq!(
+        run("SELECT LAST_INSERT_ID() FROM t WHERE name = 'a'"),
+        Ok(vec![vec![Value::BigInt(10_000_000_000_000_000_001)]])
+    );
+    assert_eq!(engine.last_insert_id(), Some(10_000_000_000_000_000_001));
+
+    let mut table = Table::new(vec![("id".into(), ValueType::BigInt)]);
+    table
+        .insert(vec![Value::BigInt(i64::MAX as i128 + 5)])
+        .unwrap();
+    table.set_auto_increment("id").unwrap();
+    assert_eq!(table.auto_increment.unwrap().last, i64::MAX as i128 + 5);
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE small (id INT AUTO_INCREMENT, name TEXT)").unwrap();
+    run("INSERT INTO small VALUES (9223372036854775807, 'last')").unwrap();
+    assert_eq!(
+        run("INSERT INTO small (name) VALUES ('over')"),
+        Err(EngineError::NumericOverflow)
+    );
+}
+
+#[test]
+fn composite_primary_keys() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE enrollments (student INT, course TEXT, grade TEXT, PRIMARY KEY (student, course))")
+        .unwrap();
+    assert!(
+        parse_query("CREATE TABLE twice (a INT PRIMARY KEY, b INT, PRIMARY KEY (a, b))").is_err()
+    );
+    assert!(matches!(
+        run("CREATE TABLE bad (a INT, PRIMARY KEY (b))"),
+        Err(EngineError::ColumnNotFound(_))
+    ));
+    assert!(!engine.tables.contains_key("bad"));
+    let table = engine.tables.get_mut("enrollments").unwrap();
+    assert_eq!(
+        table.primary_key,
+        Some(vec!["student".to_string(), "course".to_string()])
+    );
+    assert!(matches!(
+        table.set_primary_key(&["student"]),
+        Err(EngineError::InvalidQuery(_))
+    ));
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO enrollments VALUES (1, 'math', 'A')").unwrap();
+    run("INSERT INTO enrollments VALUES (1, 'art', 'B')").unwrap();
+    run("INSERT INTO enrollments VALUES (2, 'math', 'C')").unwrap();
+    assert_eq!(
+        run("INSERT INTO enrollments VALUES (1, 'math', 'F')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "PRIMARY".into(),
+            value: Value::List(vec![Value::Int(1), Value::Text("math".into())]),
+        })
+    );
+    assert_eq!(
+        run("INSERT INTO enrollments (student, grade) VALUES (3, 'A')"),
+        Err(EngineError::NotNullViolation {
+            column: "course".into()
+        })
+    );
+    assert!(matches!(
+        run("UPDATE enrollments SET course = 'math' WHERE student = 1"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+    assert_eq!(
+        run("SELECT course FROM enrollments WHERE student = 1"),
+        Ok(vec![
+            vec![Value::Text("math".into())],
+            vec![Value::Text("art".into())]
+        ])
+    );
+
+    // A key of one column may follow the column.
+    run("CREATE TABLE courses (code TEXT PRIMARY KEY, title TEXT)").unwrap();
+    run("INSERT INTO courses VALUES ('math', 'Mathematics')").unwrap();
+    assert!(matches!(
+        run("INSERT INTO courses VALUES ('math', 'Again')"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+
+    // The keys survive a dump.
+    let mut script = Vec::new();
+    engine.dump(&mut script).unwrap();
+    let script = String::from_utf8(script).unwrap();
+    assert!(script.contains(
+        "CREATE TABLE enrollments (student INT, course TEXT, grade TEXT, PRIMARY KEY (student, course));"
+    ));
+    let mut restored = Engine::new();
+    restored.restore_from_sql(script.as_bytes()).unwrap();
+    assert_eq!(
+        restored.tables["enrollments"].primary_key,
+        engine.tables["enrollments"].primary_key
+    );
+    assert_eq!(
+        restored.tables["courses"].indices.len(),
+        engine.tables["courses"].indices.len()
+    );
+}
+
+#[test]
+fn rename_table() {
+    use sql_core::ForeignKey;
+
+    let mut engine = Engine::new();
+    engine
+        .create_table("people", vec![("id".into(), ValueType::Int)])
+        .unwrap();
+    engine
+        .create_table("pets", vec![("owner".into(), ValueType::Int)])
+        .unwrap();
+    engine
+        .tables
+        .get_mut("people")
+        .unwrap()
+        .add_unique("people_id_key", &["id"])
+        .unwrap();
+    engine
+        .add_foreign_key(
+            "pets",
+            ForeignKey::new("pets_owner_fkey", &["owner"], "people", &["id"]),
+        )
+        .unwrap();
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO people VALUES (1)").unwrap();
+    run("ALTER TABLE people RENAME TO owners").unwrap();
+    assert_eq!(run("SELECT id FROM owners"), Ok(vec![vec![Value::Int(1)]]));
+    assert_eq!(
+        run("SELECT id FROM people"),
+        Err(EngineError::TableNotFound("people".into()))
+    );
+    assert_eq!(
+        run("ALTER TABLE owners RENAME TO pets"),
+        Err(EngineError::TableExists("pets".into()))
+    );
+    run("INSERT INTO pets VALUES (1)").unwrap();
+    assert!(matches!(
+        run("INSERT INTO pets VALUES (2)"),
+        Err(EngineError::ForeignKeyViolation { .. })
+    ));
+    assert_eq!(engine.tables["pets"].foreign_keys[0].ref_table, "owners");
+
+    // Renaming through the method is checked and logged as the statement is.
+    engine.begin().unwrap();
+    assert!(matches!(
+        engine.rename_table("owners", "people"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    engine.rollback().unwrap();
+    let dir = std::env::temp_dir().join(format!("minisql-rename-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    engine.enable_log(&dir).unwrap();
+    engine.rename_table("owners", "people").unwrap();
+    let mut reader = Engine::open_read_only(&dir).unwrap();
+    assert!(reader.tables.contains_key("people") && !reader.tables.contains_key("owners"));
+    assert_eq!(
+        reader.rename_table("people", "owners"),
+        Err(EngineError::ReadOnly)
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn schema_introspection() {
+    use sql_core::{Column, Constraint, ForeignKey, TableStats};
+
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("email".into(), ValueType::Varchar(64)),
+            ],
+        )
+        .unwrap();
+    engine
+        .create_table("posts", vec![("author".into(), ValueType::Int)])
+        .unwrap();
+    let users = engine.tables.get_mut("users").unwrap();
+    users.set_primary_key(&["id"]).unwrap();
+    users.add_unique("users_email_key", &["email"]).unwrap();
+    users.set_auto_increment("id").unwrap();
+    users.create_index("email");
+    let fk = ForeignKey::new("posts_author_fkey", &["author"], "users", &["id"]);
+    engine.add_foreign_key("posts", fk.clone()).unwrap();
+    engine
+        .execute(
+            parse_query("INSERT INTO users (email) VALUES ('a@x.io')")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+
+    assert_eq!(engine.list_tables(), vec!["posts", "users"]);
+    let schema = engine.describe("users").unwrap();
+    assert_eq!(
+        schema.columns,
+        vec![
+            Column {
+                name: "id".into(),
+                col_type: ValueType::Int,
+                metadata: Default::default(),
+            },
+            Column {
+                name: "email".into(),
+                col_type: ValueType::Varchar(64),
+                metadata: Default::default(),
+            },
+        ]
+    );
+    assert_eq!(
+        schema.constraints,
+        vec![
+            Constraint::PrimaryKey {
+                columns: vec!["id".into()]
+            },
+            Constraint::Unique {
+                name: "users_email_key".into(),
+                columns: vec!["email".into()]
+            },
+        ]
+    );
+    assert_eq!(schema.indexes, vec!["email", "id"]);
+    assert_eq!(schema.auto_increment.as_deref(), Some("id"));
+    assert_eq!(
+        engine.describe("posts").unwrap().constraints,
+        vec![Constraint::ForeignKey(fk)]
+    );
+    assert_eq!(engine.table_stats("users"), Ok(TableStats { rows: 1 }));
+    assert_eq!(
+        engine.describe("nope"),
+        Err(EngineError::TableNotFound("nope".into()))
+    );
+}
+
+#[test]
+fn views() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "staff",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("dept".into(), ValueType::Text),
+                ("salary".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO staff VALUES (1, 'ops', 50)").unwrap();
+    run("INSERT INTO staff VALUES (2, 'dev', 70)").unwrap();
+    run("CREATE VIEW devs AS SELECT staff.id, salary FROM staff WHERE dept = 'dev'").unwrap();
+    run("CREATE VIEW rich_devs AS SELECT id FROM devs WHERE salary > 60").unwrap();
+    assert_eq!(
+        run("CREATE VIEW staff AS SELECT id FROM staff"),
+        Err(EngineError::TableExists("staff".into()))
+    );
+    assert_eq!(
+        run("CREATE VIEW broken AS SELECT id FROM nowhere"),
+        Err(EngineError::TableNotFound("nowhere".into()))
+    );
+
+    // Views are expanded on every read, so they see new rows.
+    run("INSERT INTO staff VALUES (3, 'dev', 90)").unwrap();
+    assert_eq!(
+        run("SELECT id FROM rich_devs"),
+        Ok(vec![vec![Value::Int(2)], vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT MAX(salary) FROM devs"),
+        Ok(vec![vec![Value::Int(90)]])
+    );
+
+    run("ALTER TABLE staff RENAME TO employees").unwrap();
+    assert_eq!(
+        run("SELECT id FROM devs WHERE id = 3"),
+        Ok(vec![vec![Value::Int(3)]])
+    );
+    assert_eq!(engine.list_views(), vec!["devs", "rich_devs"]);
+    assert_eq!(engine.list_tables(), vec!["employees"]);
+    let schema = engine.describe("devs").unwrap();
+    assert_eq!(schema.columns[1].col_type, ValueType::Int);
+    assert!(schema.constraints.is_empty());
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("DROP VIEW rich_devs").unwrap();
+    assert_eq!(
+        run("DROP VIEW rich_devs"),
+        Err(EngineError::ViewNotFound("rich_devs".into()))
+    );
+    assert_eq!(
+        run("SELECT id FROM rich_devs"),
+        Err(EngineError::TableNotFound("rich_devs".into()))
+    );
+}
+
+#[test]
+fn materialized_views() {
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "sales",
+            vec![
+                ("region".into(), ValueType::Text),
+                ("amount".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO sales VALUES ('north', 10)").unwrap();
+    run("CREATE MATERIALIZED VIEW totals AS SELECT SUM(amount) FROM sales").unwrap();
+    run("CREATE VIEW big AS SELECT amount FROM sales WHERE amount > 5").unwrap();
+    run("CREATE MATERIALIZED VIEW big_count AS SELECT COUNT(*) FROM big").unwrap();
+
+    // The stored rows stay as they were until a refresh.
+    run("INSERT INTO sales VALUES ('south', 20)").unwrap();
+    assert_eq!(run("SELECT * FROM totals"), Ok(vec![vec![Value::Int(10)]]));
+    run("REFRESH MATERIALIZED VIEW totals").unwrap();
+    assert_eq!(run("SELECT * FROM totals"), Ok(vec![vec![Value::Int(30)]]));
+
+    // With automatic refresh, changes to the tables read through a plain
+    // view are picked up too.
+    run("PRAGMA auto_refresh(big_count) = ON").unwrap();
+    assert_eq!(
+        run("PRAGMA auto_refresh(big_count)"),
+        Ok(vec![vec![Value::Bool(true)]])
+    );
+    run("INSERT INTO sales VALUES ('east', 7)").unwrap();
+    run("DELETE FROM sales WHERE amount = 10").unwrap();
+    assert_eq!(
+        run("SELECT * FROM big_count"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("REFRESH MATERIALIZED VIEW big"),
+        Err(EngineError::ViewNotFound("big".into()))
+    );
+
+    run("DROP MATERIALIZED VIEW totals").unwrap();
+    assert_eq!(
+        run("SELECT * FROM totals"),
+        Err(EngineError::TableNotFound("totals".into()))
+    );
+    assert_eq!(engine.list_views(), vec!["big", "big_count"]);
+}
+
+#[test]
+fn table_metadata() {
+    use sql_core::{Table, COMMENT_KEY};
+
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "users",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("email".into(), ValueType::Text),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("COMMENT ON TABLE users IS 'Registered accounts'").unwrap();
+    run("COMMENT ON COLUMN users.email IS 'Lower-cased'").unwrap();
+    assert_eq!(
+        run("COMMENT ON COLUMN users.name IS 'x'"),
+        Err(EngineError::ColumnNotFound("name".into()))
+    );
+    engine
+        .set_metadata("users", None, "owner", Some("accounts-team".into()))
+        .unwrap();
+
+    let schema = engine.describe("users").unwrap();
+    assert_eq!(
+        schema.metadata.get(COMMENT_KEY).map(String::as_str),
+        Some("Registered accounts")
+    );
+    assert_eq!(schema.metadata["owner"], "accounts-team");
+    assert_eq!(schema.columns[1].metadata[COMMENT_KEY], "Lower-cased");
+    assert!(schema.columns[0].metadata.is_empty());
+
+    // Metadata is part of the table, so it survives serialization.
+    let json = serde_json::to_string(&engine.tables["users"]).unwrap();
+    let table: Table = serde_json::from_str(&json).unwrap();
+    assert_eq!(table.metadata, schema.metadata);
+    assert_eq!(table.columns[1].metadata[COMMENT_KEY], "Lower-cased");
+
+    engine
+        .execute(
+            parse_query("COMMENT ON COLUMN users.email IS NULL")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+    assert!(engine.describe("users").unwrap().columns[1]
+        .metadata
+        .is_empty());
+
+    // Metadata set on a logged engine is there when the logs are reopened.
+    let dir = std::env::temp_dir().join(format!("minisql-metadata-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    engine.enable_log(&dir).unwrap();
+    engine
+        .set_metadata("users", Some("id"), "owner", Some("billing".into()))
+        .unwrap();
+    let reopened = Engine::open_log(&dir).unwrap();
+    assert_eq!(
+        reopened.describe("users").unwrap().columns[0].metadata["owner"],
+        "billing"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn schemas() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE SCHEMA acme").unwrap();
+    run("CREATE SCHEMA globex").unwrap();
+    assert_eq!(
+        run("CREATE SCHEMA acme"),
+        Err(EngineError::SchemaExists("acme".into()))
+    );
+    for name in ["acme.users", "globex.users"] {
+        engine
+            .create_table(
+                name,
+                vec![
+                    ("id".into(), ValueType::Int),
+                    ("name".into(), ValueType::Text),
+                ],
+            )
+            .unwrap();
+    }
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("INSERT INTO acme.users VALUES (1, 'ann')").unwrap();
+    run("INSERT INTO globex.users VALUES (1, 'bob')").unwrap();
+    run("UPDATE globex.users SET name = 'bo' WHERE id = 1").unwrap();
+    assert_eq!(
+        run("SELECT name FROM acme.users"),
+        Ok(vec![vec![Value::Text("ann".into())]])
+    );
+    // Columns can be qualified by the full name, by the table name alone
+    // when that is unambiguous, or by an alias.
+    assert_eq!(
+        run("SELECT globex.users.name FROM globex.users WHERE users.id = 1"),
+        Ok(vec![vec![Value::Text("bo".into())]])
+    );
+    assert_eq!(
+        run("SELECT a.name, g.name FROM acme.users a, globex.users g WHERE a.id = g.id"),
+        Ok(vec![vec![
+            Value::Text("ann".into()),
+            Value::Text("bo".into())
+        ]])
+    );
+    assert_eq!(
+        run("SELECT users.name FROM acme.users, globex.users"),
+        Err(EngineError::AmbiguousColumn("users.name".into()))
+    );
+    assert_eq!(
+        run("CREATE VIEW initech.names AS SELECT name FROM acme.users"),
+        Err(EngineError::SchemaNotFound("initech".into()))
+    );
+    run("CREATE VIEW acme.names AS SELECT name FROM acme.users").unwrap();
+
+    assert_eq!(
+        run("DROP SCHEMA acme"),
+        Err(EngineError::InvalidQuery("schema acme is not empty".into()))
+    );
+    run("DROP SCHEMA acme CASCADE").unwrap();
+    assert_eq!(engine.list_schemas(), vec!["globex"]);
+    assert_eq!(engine.list_tables(), vec!["globex.users"]);
+    assert!(engine.list_views().is_empty());
+}
+
+#[test]
+fn create_table_modifiers() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE users (id INT, name VARCHAR(20))").unwrap();
+    run("INSERT INTO users VALUES (1, 'ann')").unwrap();
+    assert_eq!(
+        run("CREATE TABLE users (id INT)"),
+        Err(EngineError::TableExists("users".into()))
+    );
+    assert_eq!(
+        run("CREATE TABLE crm.users (id INT)"),
+        Err(EngineError::SchemaNotFound("crm".into()))
+    );
+
+    // IF NOT EXISTS keeps the existing table and its rows.
+    run("CREATE TABLE IF NOT EXISTS users (id INT)").unwrap();
+    assert_eq!(
+        run("SELECT name FROM users"),
+        Ok(vec![vec![Value::Text("ann".into())]])
+    );
+
+    // OR REPLACE starts over with the new columns.
+    run("CREATE OR REPLACE TABLE users (id INT, email TEXT, active BOOL)").unwrap();
+    assert_eq!(run("SELECT * FROM users"), Ok(vec![]));
+    run("INSERT INTO users VALUES (2, 'b@x.io', TRUE)").unwrap();
+    assert_eq!(
+        run("CREATE OR REPLACE TABLE IF NOT EXISTS users (id INT)"),
+        Err(EngineError::InvalidQuery(
+            "OR REPLACE cannot be combined with IF NOT EXISTS".into()
+        ))
+    );
+
+    assert_eq!(
+        engine.create_table("users", vec![("id".into(), ValueType::Int)]),
+        Err(EngineError::TableExists("users".into()))
+    );
+    assert_eq!(
+        engine.create_table_if_not_exists("users", vec![("id".into(), ValueType::Int)]),
+        Ok(false)
+    );
+    assert_eq!(engine.describe("users").unwrap().columns.len(), 3);
+
+    // Inside a transaction tables are neither replaced nor created, so that
+    // ROLLBACK brings back every row the transaction started with.
+    engine.begin().unwrap();
+    engine
+        .execute_sql("INSERT INTO users VALUES (3, 'c@x.io', FALSE)")
+        .unwrap();
+    assert!(matches!(
+        engine.replace_table("users", vec![("id".into(), ValueType::Int)]),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        engine.create_table("other", vec![("id".into(), ValueType::Int)]),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    engine.rollback().unwrap();
+    assert_eq!(
+        engine.execute_sql("SELECT id, email FROM users"),
+        Ok(vec![vec![Value::Int(2), Value::Text("b@x.io".into())]])
+    );
+    assert!(!engine.tables.contains_key("other"));
+}
+
+#[test]
+fn migrations() {
+    use sql_core::Migration;
+
+    let migrations = vec![
+        Migration::new(1, "create users").step("CREATE TABLE users (id INT, name TEXT);"),
+        Migration::new(2, "seed users")
+            .step("INSERT INTO users VALUES (1, 'ann')")
+            .step("COMMENT ON TABLE users IS 'accounts'"),
+    ];
+    let mut engine = Engine::new();
+    assert_eq!(engine.schema_version(), 0);
+    assert_eq!(engine.migrate(&migrations), Ok(2));
+    assert_eq!(engine.schema_version(), 2);
+    // Applied migrations are skipped on later runs.
+    assert_eq!(engine.migrate(&migrations), Ok(0));
+
+    // A failing step undoes the whole migration it belongs to.
+    let mut next = migrations.clone();
+    next.push(
+        Migration::new(3, "add orders")
+            .step("CREATE TABLE orders (id INT)")
+            .step("INSERT INTO users VALUES (2, 'bob')")
+            .step("INSERT INTO missing VALUES (1)"),
+    );
+    assert_eq!(
+        engine.migrate(&next),
+        Err(EngineError::MigrationFailed {
+            version: 3,
+            error: Box::new(EngineError::TableNotFound("missing".into())),
+        })
+    );
+    assert_eq!(engine.schema_version(), 2);
+    assert_eq!(engine.list_tables(), vec!["users"]);
+    assert_eq!(engine.table_stats("users").unwrap().rows, 1);
+
+    next[2].steps.pop();
+    assert_eq!(engine.migrate(&next), Ok(1));
+    let applied = engine.applied_migrations();
+    assert_eq!(
+        applied.iter().map(|m| m.version).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(applied[2].name, "add orders");
+
+    next[0].name = "make users".into();
+    assert_eq!(
+        engine.migrate(&next),
+        Err(EngineError::InvalidQuery(
+            "migration 1 was applied as create users, not make users".into()
+        ))
+    );
+    next.swap(0, 1);
+    assert!(matches!(
+        engine.migrate(&next),
+        Err(EngineError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn ordered_indexes() {
+    use sql_core::IndexKind;
+
+    let mut engine = Engine::new();
+    engine
+        .create_table(
+            "events",
+            vec![
+                ("id".into(), ValueType::Int),
+                ("score".into(), ValueType::Int),
+            ],
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for (id, score) in [(1, "30"), (2, "10"), (3, "NULL"), (4, "20"), (5, "10")] {
+        run(&format!("INSERT INTO events VALUES ({}, {})", id, score)).unwrap();
+    }
+    let queries = [
+        "SELECT id FROM events WHERE score > 10",
+        "SELECT id FROM events WHERE score <= 20",
+        "SELECT id FROM events WHERE 20 > score",
+        "SELECT id FROM events WHERE score BETWEEN 10 AND 20",
+        "SELECT id FROM events WHERE score BETWEEN 20 AND 10",
+        "SELECT id FROM events WHERE score < 10.5",
+        "SELECT id FROM events WHERE score >= NULL",
+        "SELECT id FROM events WHERE score = 10",
+    ];
+    let scanned = queries.map(|q| run(q).unwrap());
+
+    engine
+        .tables
+        .get_mut("events")
+        .unwrap()
+        .create_index_with_kind("score", IndexKind::Ordered);
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for (query, expected) in queries.iter().zip(&scanned) {
+        assert_eq!(&run(query).unwrap(), expected, "{}", query);
+    }
+    let ids = |ids: &[i64]| {
+        ids.iter()
+            .map(|&id| vec![Value::Int(id)])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(scanned[0], ids(&[1, 4]));
+    assert_eq!(scanned[3], ids(&[2, 4, 5]));
+    assert!(scanned[4].is_empty());
+    assert!(scanned[6].is_empty());
+
+    // The index keeps up with new rows and survives a rebuild.
+    run("INSERT INTO events VALUES (6, 15)").unwrap();
+    run("DELETE FROM events WHERE id = 2").unwrap();
+    assert_eq!(
+        run("SELECT id FROM events WHERE score > 12 AND score < 25"),
+        Ok(ids(&[4, 6]))
+    );
+    assert_eq!(
+        run("SELECT id FROM events WHERE score BETWEEN 10 AND 15"),
+        Ok(ids(&[5, 6]))
+    );
+    assert_eq!(
+        engine.tables["events"].indices["score"].kind(),
+        IndexKind::Ordered
+    );
+}
+
+#[test]
+fn composite_indexes() {
+    use sql_core::IndexKind;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE visits (site TEXT, day INT, hits INT)").unwrap();
+    for (site, day, hits) in [
+        ("a", 1, 5),
+        ("a", 2, 7),
+        ("b", 1, 3),
+        ("a", 1, 9),
+        ("c", 2, 1),
+    ] {
+        run(&format!(
+            "INSERT INTO visits VALUES ('{}', {}, {})",
+            site, day, hits
+        ))
+        .unwrap();
+    }
+    run("INSERT INTO visits VALUES ('b', NULL, 4)").unwrap();
+    let queries = [
+        "SELECT hits FROM visits WHERE site = 'a' AND day = 1",
+        "SELECT hits FROM visits WHERE day = 2 AND site = 'a'",
+        "SELECT hits FROM visits WHERE site = 'b'",
+        "SELECT hits FROM visits WHERE site = 'a' AND hits > 6",
+        "SELECT hits FROM visits WHERE day = 1",
+        "SELECT hits FROM visits WHERE site = 'z' AND day = 1",
+    ];
+    let scanned = queries.map(|q| run(q).unwrap());
+
+    run("CREATE INDEX ON visits (site, day)").unwrap();
+    assert_eq!(
+        run("CREATE INDEX visits_site_day_idx ON visits (day)"),
+        Err(EngineError::InvalidQuery(
+            "index visits_site_day_idx already exists on visits".into()
+        ))
+    );
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for (query, expected) in queries.iter().zip(&scanned) {
+        assert_eq!(&run(query).unwrap(), expected, "{}", query);
+    }
+    assert_eq!(scanned[0], vec![vec![Value::Int(5)], vec![Value::Int(9)]]);
+    // The prefix lookup finds rows with a NULL in a later column.
+    assert_eq!(scanned[2], vec![vec![Value::Int(3)], vec![Value::Int(4)]]);
+
+    run("INSERT INTO visits VALUES ('a', 1, 2)").unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM visits WHERE site = 'a' AND day = 1"),
+        Ok(vec![vec![Value::Int(3)]])
+    );
+    let table = engine.tables.get_mut("visits").unwrap();
+    assert_eq!(
+        table.indices["visits_site_day_idx"].kind(),
+        IndexKind::Ordered
+    );
+    assert_eq!(
+        table.add_index("by_hits", &["hits", "nope"], IndexKind::Hash),
+        Err(EngineError::ColumnNotFound("nope".into()))
+    );
+}
+
+#[test]
+fn unique_indexes() {
+    use sql_core::IndexKind;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE accounts (id INT, region TEXT, login TEXT)").unwrap();
+    run("INSERT INTO accounts VALUES (1, 'eu', 'ann')").unwrap();
+    run("INSERT INTO accounts VALUES (2, 'us', 'ann')").unwrap();
+    run("INSERT INTO accounts VALUES (3, 'eu', NULL)").unwrap();
+    assert_eq!(
+        run("CREATE UNIQUE INDEX login_key ON accounts (login)"),
+        Err(EngineError::UniqueViolation {
+            constraint: "login_key".into(),
+            value: Value::Text("ann".into()),
+        })
+    );
+    run("CREATE UNIQUE INDEX login_key ON accounts (region, login) USING HASH").unwrap();
+
+    // Rows with a NULL in the key are exempt.
+    run("INSERT INTO accounts VALUES (4, 'eu', NULL)").unwrap();
+    assert_eq!(
+        run("INSERT INTO accounts VALUES (5, 'us', 'ann')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "login_key".into(),
+            value: Value::List(vec![Value::Text("us".into()), Value::Text("ann".into())]),
+        })
+    );
+    assert_eq!(
+        run("UPDATE accounts SET region = 'eu' WHERE id = 2"),
+        Err(EngineError::UniqueViolation {
+            constraint: "login_key".into(),
+            value: Value::List(vec![Value::Text("eu".into()), Value::Text("ann".into())]),
+        })
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM accounts"),
+        Ok(vec![vec![Value::Int(4)]])
+    );
+
+    // UNIQUE constraints and primary keys are enforced by unique indexes,
+    // which a plain index cannot replace.
+    let table = engine.tables.get_mut("accounts").unwrap();
+    table.set_primary_key(&["id"]).unwrap();
+    assert!(table.indices["PRIMARY"].unique);
+    assert_eq!(
+        table.add_index("PRIMARY", &["login"], IndexKind::Hash),
+        Err(EngineError::InvalidQuery(
+            "index PRIMARY enforces a UNIQUE constraint".into()
+        ))
+    );
+    assert_eq!(
+        table.insert(vec![
+            Value::Int(1),
+            Value::Text("ap".into()),
+            Value::Text("zed".into())
+        ]),
+        Err(EngineError::UniqueViolation {
+            constraint: "PRIMARY".into(),
+            value: Value::Int(1),
+        })
+    );
+    assert_eq!(
+        engine.describe("accounts").unwrap().indexes,
+        vec!["id", "login_key"]
+    );
+}
+
+#[test]
+fn expression_indexes() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE people (id INT, name TEXT, age INT)").unwrap();
+    run("INSERT INTO people VALUES (1, 'Ann', 30)").unwrap();
+    run("INSERT INTO people VALUES (2, 'BOB', 41)").unwrap();
+    run("CREATE UNIQUE INDEX ON people (lower(name))").unwrap();
+    run("CREATE INDEX doubled ON people (age * 2)").unwrap();
+    run("INSERT INTO people VALUES (3, 'Cy', NULL)").unwrap();
+
+    // Keys are computed on every write, so the constraint covers new rows
+    // and updated ones alike.
+    assert_eq!(
+        run("INSERT INTO people VALUES (4, 'ann', 5)"),
+        Err(EngineError::UniqueViolation {
+            constraint: "people_lower_idx".into(),
+            value: Value::Text("ann".into()),
+        })
+    );
+    assert_eq!(
+        run("UPDATE people SET name = 'bob' WHERE id = 3"),
+        Err(EngineError::UniqueViolation {
+            constraint: "people_lower_idx".into(),
+            value: Value::Text("bob".into()),
+        })
+    );
+    run("UPDATE people SET name = 'Dee' WHERE id = 3").unwrap();
+
+    assert_eq!(
+        run("SELECT id FROM people WHERE LOWER(people.name) = 'bob'"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM people WHERE LOWER(name) IN ('ann', 'dee')"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(3)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM people WHERE age * 2 BETWEEN 50 AND 70"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    // A constant of another type than the keys is answered by a scan.
+    assert_eq!(
+        run("SELECT id FROM people WHERE age * 2 = 82.0"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+
+    assert_eq!(
+        run("CREATE INDEX ON people (UUID())"),
+        Err(EngineError::InvalidQuery(
+            "function UUID cannot be used in an index".into()
+        ))
+    );
+    assert_eq!(
+        run("CREATE INDEX by_id ON people (lower(id))"),
+        Err(EngineError::InvalidOperation(
+            "cannot apply LOWER to Int".into()
+        ))
+    );
+    assert_eq!(
+        engine.describe("people").unwrap().indexes,
+        vec!["doubled", "id", "people_lower_idx"]
+    );
+    assert_eq!(
+        engine.tables["people"].indices["people_lower_idx"].columns,
+        vec!["LOWER(name)"]
+    );
+}
+
+#[test]
+fn analyze_and_planner() {
+    use sql_core::{AccessPath, HISTOGRAM_BUCKETS};
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE events (id INT, flag INT, n INT)").unwrap();
+    for i in 0..200 {
+        run(&format!(
+            "INSERT INTO events VALUES ({}, {}, {})",
+            i,
+            i % 2,
+            i
+        ))
+        .unwrap();
+    }
+    run("CREATE INDEX by_flag ON events (flag) USING HASH").unwrap();
+    run("CREATE INDEX by_n ON events (n)").unwrap();
+
+    let path = |engine: &Engine, condition: &str| {
+        let sql = format!("SELECT * FROM events WHERE {}", condition);
+        let Query::Select(q) = parse_query(&sql).unwrap().1 else {
+            unreachable!()
+        };
+        engine.access_path("events", q.condition.as_ref()).unwrap()
+    };
+    let lookup = |name: &str| AccessPath::IndexLookup(name.into());
+    let range = |name: &str| AccessPath::IndexRange(name.into());
+
+    // Without statistics every usable index is used.
+    assert_eq!(path(&engine, "flag = 1"), lookup("by_flag"));
+    assert_eq!(path(&engine, "n > 10"), range("by_n"));
+
+    engine
+        .execute(parse_query("ANALYZE events").unwrap().1)
+        .unwrap();
+    let stats = engine.tables["events"].stats.as_ref().unwrap();
+    assert_eq!(stats.rows, 200);
+    assert_eq!(stats.columns["flag"].distinct, 2);
+    assert_eq!(stats.columns["n"].histogram.len(), HISTOGRAM_BUCKETS + 1);
+    assert_eq!(stats.columns["n"].histogram[0], Value::Int(0));
+    assert_eq!(
+        stats.columns["n"].histogram[HISTOGRAM_BUCKETS],
+        Value::Int(199)
+    );
+
+    // Half the rows match, which a scan reads more cheaply.
+    assert_eq!(path(&engine, "flag = 1"), AccessPath::Scan);
+    assert_eq!(path(&engine, "id = 5"), lookup("id"));
+    assert_eq!(path(&engine, "n < 10"), range("by_n"));
+    assert_eq!(path(&engine, "n > 10"), AccessPath::Scan);
+    assert_eq!(
+        path(&engine, "flag = 1 AND n BETWEEN 3 AND 6"),
+        range("by_n")
+    );
+    assert_eq!(path(&engine, "n < 10 AND id IN (1, 2)"), lookup("id"));
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events WHERE flag = 1"),
+        Ok(vec![vec![Value::Int(100)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM events WHERE flag = 1 AND n BETWEEN 3 AND 6"),
+        Ok(vec![vec![Value::Int(3)], vec![Value::Int(5)]])
+    );
+    assert_eq!(
+        run("ANALYZE missing"),
+        Err(EngineError::TableNotFound("missing".into()))
+    );
+    run("ANALYZE").unwrap();
+}
+
+#[test]
+fn combined_range_scans() {
+    use sql_core::AccessPath;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE readings (id INT, n INT)").unwrap();
+    for i in 0..200 {
+        run(&format!("INSERT INTO readings VALUES ({}, {})", i, i)).unwrap();
+    }
+    run("CREATE INDEX by_n ON readings (n)").unwrap();
+    run("ANALYZE readings").unwrap();
+
+    let path = |engine: &Engine, condition: &str| {
+        let sql = format!("SELECT * FROM readings WHERE {}", condition);
+        let Query::Select(q) = parse_query(&sql).unwrap().1 else {
+            unreachable!()
+        };
+        engine
+            .access_path("readings", q.condition.as_ref())
+            .unwrap()
+    };
+    // Each bound alone admits too many rows; together they admit few.
+    assert_eq!(path(&engine, "n > 100"), AccessPath::Scan);
+    assert_eq!(path(&engine, "n < 110"), AccessPath::Scan);
+    assert_eq!(
+        path(&engine, "n > 100 AND 110 > n"),
+        AccessPath::IndexRange("by_n".into())
+    );
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    let ids = |rows: Vec<Vec<Value>>| {
+        rows.into_iter()
+            .map(|row| match row[0] {
+                Value::Int(n) => n,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        ids(run("SELECT id FROM readings WHERE n > 100 AND 110 > n").unwrap()),
+        (101..110).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        ids(run("SELECT id FROM readings WHERE n >= 5 AND n > 5 AND n <= 8").unwrap()),
+        vec![6, 7, 8]
+    );
+    assert_eq!(
+        ids(run("SELECT id FROM readings WHERE n > 8 AND n < 3").unwrap()),
+        Vec::<i64>::new()
+    );
+
+    // UPDATE and DELETE find their rows the same way.
+    run("UPDATE readings SET id = -1 WHERE n BETWEEN 10 AND 12 AND n < 12").unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM readings WHERE id = -1"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    run("DELETE FROM readings WHERE n >= 190 AND id > 194").unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM readings"),
+        Ok(vec![vec![Value::Int(195)]])
+    );
+}
+
+#[test]
+fn indexes_follow_updates_and_deletes() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE items (id INT PRIMARY KEY, code TEXT UNIQUE, n INT, note TEXT)").unwrap();
+    run("CREATE INDEX by_n ON items (n, id) USING BTREE").unwrap();
+    run("CREATE INDEX by_note ON items (note) USING TRIGRAM").unwrap();
+    run("CREATE TABLE parts (id INT, item INT REFERENCES items (id) ON DELETE CASCADE ON UPDATE CASCADE)")
+        .unwrap();
+    run("CREATE INDEX by_item ON parts (item)").unwrap();
+    for i in 0..100 {
+        run(&format!(
+            "INSERT INTO items VALUES ({}, 'c{}', {}, 'note {}')",
+            i,
+            i,
+            i % 10,
+            i
+        ))
+        .unwrap();
+        run(&format!("INSERT INTO parts VALUES ({}, {})", i, i % 50)).unwrap();
+    }
+
+    // Each statement updates the indexes for the rows it changes: rows
+    // trade keys, free them, take new ones and move up after a delete.
+    run("UPDATE items SET n = 9 - n WHERE id < 30").unwrap();
+    run("UPDATE items SET code = 'x', note = 'moved' WHERE id = 5").unwrap();
+    run("UPDATE items SET code = 'c5' WHERE id = 6").unwrap();
+    run("DELETE FROM items WHERE id IN (0, 7, 48, 99)").unwrap();
+    run("UPDATE items SET id = id + 1000 WHERE id = 42").unwrap();
+    // A statement failing part way leaves every index as it was.
+    assert!(matches!(
+        run("UPDATE items SET code = 'c1' WHERE id > 90"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+    assert!(matches!(
+        run("INSERT INTO parts VALUES (500, 7)"),
+        Err(EngineError::ForeignKeyViolation { .. })
+    ));
+
+    let explain = |engine: &mut Engine, sql: &str| {
+        engine
+            .execute(parse_query(&format!("EXPLAIN {}", sql)).unwrap().1)
+            .unwrap()[0][1]
+            .clone()
+    };
+    let checks = [
+        ("items", "n = 0", "by_n"),
+        ("items", "n = 9 AND id < 30", "by_n"),
+        ("items", "code = 'c5'", "INDEX LOOKUP"),
+        ("items", "note LIKE '%te 4%'", "by_note"),
+        ("parts", "item = 1042", "INDEX LOOKUP by_item"),
+    ];
+    for (table, condition, path) in checks {
+        let sql = format!("SELECT * FROM {} WHERE {} ORDER BY id", table, condition);
+        let Value::Text(used) = explain(&mut engine, &sql) else {
+            unreachable!()
+        };
+        assert!(used.contains(path), "{} reads {}", sql, used);
+        let scan = format!(
+            "SELECT * FROM {} USE INDEX () WHERE {} ORDER BY id",
+            table, condition
+        );
+        assert_eq!(
+            engine.execute(parse_query(&sql).unwrap().1),
+            engine.execute(parse_query(&scan).unwrap().1),
+            "{}",
+            sql
+        );
+    }
+    for stats in engine.index_stats("items").unwrap() {
+        if stats.name != "by_note" {
+            assert_eq!(stats.entries, 96, "{}", stats.name);
+        }
+    }
+}
+
+#[test]
+fn hash_joins() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE orders (id INT, customer INT, region TEXT)").unwrap();
+    run("CREATE TABLE customers (id INT, region TEXT, name TEXT)").unwrap();
+    for i in 0..40 {
+        run(&format!(
+            "INSERT INTO customers VALUES ({}, '{}', 'c{}')",
+            i,
+            if i % 2 == 0 { "eu" } else { "us" },
+            i
+        ))
+        .unwrap();
+    }
+    for i in 0..60 {
+        run(&format!(
+            "INSERT INTO orders VALUES ({}, {}, '{}')",
+            i,
+            i % 50,
+            if i % 3 == 0 { "eu" } else { "us" }
+        ))
+        .unwrap();
+    }
+    run("INSERT INTO orders VALUES (60, NULL, 'eu')").unwrap();
+
+    // Orders 0..39 and 50..59 have a customer; NULL joins with nothing.
+    assert_eq!(
+        run("SELECT COUNT(*) FROM orders, customers WHERE orders.customer = customers.id"),
+        Ok(vec![vec![Value::Int(50)]])
+    );
+    // Rows come out in the same order as without a hash join.
+    assert_eq!(
+        run("SELECT o.id, c.name FROM orders o CROSS JOIN customers c \
+             WHERE c.id = o.customer AND o.id > 48 AND o.id < 52"),
+        Ok(vec![
+            vec![Value::Int(50), Value::Text("c0".into())],
+            vec![Value::Int(51), Value::Text("c1".into())],
+        ])
+    );
+    // Two equalities make a composite key.
+    assert_eq!(
+        run("SELECT COUNT(*) FROM orders o, customers c \
+             WHERE o.customer = c.id AND o.region = c.region"),
+        Ok(vec![vec![Value::Int(24)]])
+    );
+    // Without an equality every pair is tested.
+    assert_eq!(
+        run("SELECT COUNT(*) FROM orders o, customers c WHERE o.customer < c.id AND c.id < 2"),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    // Keys of different types that compare equal still match.
+    run("CREATE TABLE refunds (customer FLOAT)").unwrap();
+    for customer in ["7.0", "7.5", "45.0"] {
+        run(&format!("INSERT INTO refunds VALUES ({})", customer)).unwrap();
+    }
+    assert_eq!(
+        run("SELECT orders.id FROM orders, refunds WHERE orders.customer = refunds.customer"),
+        Ok(vec![
+            vec![Value::Int(7)],
+            vec![Value::Int(45)],
+            vec![Value::Int(57)]
+        ])
+    );
+}
+
+#[test]
+fn bloom_filters() {
+    use sql_core::AccessPath;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE sessions (id INT, token TEXT)").unwrap();
+    engine
+        .tables
+        .get_mut("sessions")
+        .unwrap()
+        .add_bloom_filter("token")
+        .unwrap();
+    // Enough rows to grow the filter past its first size.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    for i in 0..500 {
+        run(&format!("INSERT INTO sessions VALUES ({}, 't{}')", i, i)).unwrap();
+    }
+    let filter = &engine.tables["sessions"].bloom_filters["token"];
+    assert!((0..500).all(|i| filter.may_contain(&Value::Text(format!("t{}", i)))));
+
+    let path = |engine: &Engine, condition: &str| {
+        let sql = format!("SELECT * FROM sessions WHERE {}", condition);
+        let Query::Select(q) = parse_query(&sql).unwrap().1 else {
+            unreachable!()
+        };
+        engine
+            .access_path("sessions", q.condition.as_ref())
+            .unwrap()
+    };
+    assert_eq!(
+        path(&engine, "token = 'nope'"),
+        AccessPath::BloomFilter("token".into())
+    );
+    assert_eq!(
+        path(&engine, "id > 3 AND token IN ('nope', 'nada')"),
+        AccessPath::BloomFilter("token".into())
+    );
+    assert_eq!(path(&engine, "token = 't7'"), AccessPath::Scan);
+    assert_eq!(path(&engine, "token IN ('nope', 't7')"), AccessPath::Scan);
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM sessions WHERE token = 't7'"),
+        Ok(vec![vec![Value::Int(7)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM sessions WHERE token = 'nope'"),
+        Ok(vec![])
+    );
+    assert_eq!(run("DELETE FROM sessions WHERE token = 'nope'"), Ok(vec![]));
+
+    // The bits are not saved, since their hash may differ in another
+    // build, but are rebuilt when the snapshot is read back.
+    let file = std::env::temp_dir().join(format!("minisql-bloom-{}.db", std::process::id()));
+    engine.save(&file).unwrap();
+    let saved = std::fs::read(&file).unwrap();
+    assert!(!String::from_utf8_lossy(&saved).contains("\"bits\""));
+    let mut reopened = Engine::open(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    let filter = &reopened.tables["sessions"].bloom_filters["token"];
+    assert!((0..500).all(|i| filter.may_contain(&Value::Text(format!("t{}", i)))));
+    assert!(!filter.may_contain(&Value::Text("nope".into())));
+    assert_eq!(
+        reopened.execute(
+            parse_query("SELECT id FROM sessions WHERE token = 't9'")
+                .unwrap()
+                .1
+        ),
+        Ok(vec![vec![Value::Int(9)]])
+    );
+
+    // Removed values leave the filter when VACUUM rebuilds it.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("DELETE FROM sessions WHERE id = 7").unwrap();
+    assert_eq!(path(&engine, "token = 't7'"), AccessPath::Scan);
+    engine.vacuum(Some("sessions")).unwrap();
+    assert_eq!(
+        path(&engine, "token = 't7'"),
+        AccessPath::BloomFilter("token".into())
+    );
+    let table = engine.tables.get_mut("sessions").unwrap();
+    assert!(table.drop_bloom_filter("token"));
+    assert!(!table.drop_bloom_filter("token"));
+    assert_eq!(
+        table.add_bloom_filter("missing"),
+        Err(EngineError::ColumnNotFound("missing".into()))
+    );
+}
+
+#[test]
+fn index_persistence() {
+    use sql_core::{AccessPath, Table};
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE users (id INT, email TEXT, age INT)").unwrap();
+    for i in 0..50 {
+        run(&format!(
+            "INSERT INTO users VALUES ({}, 'User{}@example.com', {})",
+            i,
+            i,
+            20 + i % 30
+        ))
+        .unwrap();
+    }
+    run("CREATE UNIQUE INDEX by_id ON users (id)").unwrap();
+    run("CREATE INDEX by_age ON users (age, id) USING BTREE").unwrap();
+    run("CREATE UNIQUE INDEX by_email ON users (lower(email))").unwrap();
+    run("CREATE INDEX by_double ON users (age * 2)").unwrap();
+
+    let path = |engine: &Engine, condition: &str| {
+        let sql = format!("SELECT * FROM users WHERE {}", condition);
+        let Query::Select(q) = parse_query(&sql).unwrap().1 else {
+            unreachable!()
+        };
+        engine.access_path("users", q.condition.as_ref()).unwrap()
+    };
+    let reload = |engine: &mut Engine, json: &str| {
+        let table: Table = serde_json::from_str(json).unwrap();
+        engine.tables.insert("users".into(), table);
+    };
+
+    // With their entries, plain indexes answer lookups straight away, and
+    // only indexed expressions wait to be bound again.
+    let full = serde_json::to_string(&engine.tables["users"]).unwrap();
+    reload(&mut engine, &full);
+    assert_eq!(
+        path(&engine, "id = 7"),
+        AccessPath::IndexLookup("by_id".into())
+    );
+    assert_eq!(path(&engine, "age * 2 = 60"), AccessPath::Scan);
+    assert!(matches!(
+        engine.execute(
+            parse_query("INSERT INTO users VALUES (50, 'x', 1)")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    engine.rebuild_indexes().unwrap();
+    assert_eq!(
+        path(&engine, "age * 2 = 60"),
+        AccessPath::IndexLookup("by_double".into())
+    );
+
+    // Without them, the file is smaller and every index is rebuilt.
+    let mut table = engine.tables["users"].clone();
+    table.drop_index_entries();
+    let lean = serde_json::to_string(&table).unwrap();
+    assert!(lean.len() < full.len());
+    reload(&mut engine, &lean);
+    assert!(engine.tables["users"]
+        .indices
+        .values()
+        .all(|i| !i.is_ready()));
+    assert_eq!(path(&engine, "id = 7"), AccessPath::Scan);
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM users WHERE age = 27"),
+        Ok(vec![vec![Value::Int(7)], vec![Value::Int(37)]])
+    );
+    engine.rebuild_indexes().unwrap();
+    assert_eq!(
+        path(&engine, "id = 7"),
+        AccessPath::IndexLookup("by_id".into())
+    );
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("INSERT INTO users VALUES (50, 'USER3@example.com', 1)"),
+        Err(EngineError::UniqueViolation {
+            constraint: "by_email".into(),
+            value: Value::Text("user3@example.com".into()),
+        })
+    );
+    assert_eq!(
+        run("SELECT id FROM users WHERE lower(email) = 'user12@example.com'"),
+        Ok(vec![vec![Value::Int(12)]])
+    );
+}
+
+#[test]
+fn reindex_and_index_stats() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE orders (id INT, customer TEXT, total INT)").unwrap();
+    for i in 0..40 {
+        run(&format!(
+            "INSERT INTO orders VALUES ({}, 'c{}', {})",
+            i,
+            i % 4,
+            i * 10
+        ))
+        .unwrap();
+    }
+    run("CREATE INDEX by_customer ON orders (customer)").unwrap();
+    run("CREATE INDEX by_total ON orders (total) USING BTREE").unwrap();
+    run("SELECT * FROM orders WHERE id = 3").unwrap();
+    run("SELECT * FROM orders WHERE id IN (4, 5)").unwrap();
+    run("UPDATE orders SET total = 0 WHERE id = 6").unwrap();
+    run("SELECT * FROM orders WHERE total > 380").unwrap();
+
+    let stats = engine.index_stats("orders").unwrap();
+    let summary = stats
+        .iter()
+        .map(|s| (s.name.as_str(), s.entries, s.keys, s.hits))
+        .collect::<Vec<_>>();
+    // Rebuilding the indexes after the UPDATE kept their hit counts.
+    assert_eq!(
+        summary,
+        vec![
+            ("by_customer", 40, 4, 0),
+            ("by_total", 40, 39, 1),
+            ("id", 40, 40, 3),
+        ]
+    );
+    assert!(stats.iter().all(|s| s.memory > 0));
+
+    let mut table = engine.tables["orders"].clone();
+    table.drop_index_entries();
+    engine.tables.insert("orders".into(), table);
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("REINDEX by_total").unwrap();
+    let ready = |engine: &Engine| {
+        let mut ready = engine.tables["orders"]
+            .indices
+            .iter()
+            .filter(|(_, i)| i.is_ready())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        ready.sort();
+        ready
+    };
+    assert_eq!(ready(&engine), vec!["by_total"]);
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("REINDEX orders").unwrap();
+    assert_eq!(ready(&engine).len(), 3);
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("REINDEX").unwrap();
+    assert!(matches!(
+        run("REINDEX nothing"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+    assert_eq!(engine.index_stats("orders").unwrap()[2].hits, 3);
+}
+
+#[test]
+fn prefix_indexes() {
+    use sql_core::AccessPath;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE pages (id INT, url TEXT)").unwrap();
+    for i in 0..40 {
+        run(&format!(
+            "INSERT INTO pages VALUES ({}, 'https://example.com/{}/{}')",
+            i,
+            ["docs", "blog"][i % 2],
+            i
+        ))
+        .unwrap();
+    }
+    run("CREATE INDEX by_url ON pages (url(24))").unwrap();
+    assert_eq!(
+        engine.tables["pages"].indices["by_url"].columns,
+        vec!["url(24)"]
+    );
+
+    let path = |engine: &Engine, condition: &str| {
+        let sql = format!("SELECT id FROM pages WHERE {}", condition);
+        let Query::Select(q) = parse_query(&sql).unwrap().1 else {
+            unreachable!()
+        };
+        engine.access_path("pages", q.condition.as_ref()).unwrap()
+    };
+    assert_eq!(
+        path(&engine, "url = 'https://example.com/blog/7'"),
+        AccessPath::IndexLookup("by_url".into())
+    );
+    assert_eq!(
+        path(&engine, "url < 'https://example.com/blog/2'"),
+        AccessPath::IndexRange("by_url".into())
+    );
+
+    // Rows sharing the prefix are tested against the whole value.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM pages WHERE url = 'https://example.com/blog/7'"),
+        Ok(vec![vec![Value::Int(7)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM pages WHERE url IN ('https://example.com/docs/4', 'https://example.com/docs/99')"),
+        Ok(vec![vec![Value::Int(4)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM pages WHERE url >= 'https://example.com/docs/8' AND url < 'https://example.com/docs/9'"),
+        Ok(vec![vec![Value::Int(8)]])
+    );
+
+    // A unique prefix index rejects values that only share the prefix.
+    run("CREATE TABLE codes (code TEXT)").unwrap();
+    run("CREATE UNIQUE INDEX short ON codes (code(3))").unwrap();
+    run("INSERT INTO codes VALUES ('abc-1')").unwrap();
+    assert_eq!(
+        run("INSERT INTO codes VALUES ('abc-2')"),
+        Err(EngineError::UniqueViolation {
+            constraint: "short".into(),
+            value: Value::Text("abc".into()),
+        })
+    );
+    assert!(matches!(
+        run("CREATE INDEX ON pages (id(2))"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn index_hints() {
+    use sql_core::IndexHint;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE events (id INT, kind TEXT, at INT)").unwrap();
+    for i in 0..20 {
+        run(&format!(
+            "INSERT INTO events VALUES ({}, 'k{}', {})",
+            i,
+            i % 3,
+            i * 5
+        ))
+        .unwrap();
+    }
+    run("CREATE INDEX by_kind ON events (kind)").unwrap();
+    run("CREATE INDEX by_at ON events (at)").unwrap();
+
+    let Query::Select(q) = parse_query("SELECT * FROM events e USE INDEX (by_at, by_kind)")
+        .unwrap()
+        .1
+    else {
+        unreachable!()
+    };
+    assert_eq!(q.tables[0].alias.as_deref(), Some("e"));
+    assert_eq!(
+        q.tables[0].hint,
+        Some(IndexHint::Use(vec!["by_at".into(), "by_kind".into()]))
+    );
+
+    let explain = |engine: &mut Engine, sql: &str| {
+        engine.execute(parse_query(&format!("EXPLAIN {}", sql)).unwrap().1)
+    };
+    let text = |s: &str| Value::Text(s.into());
+    let sql = "SELECT id FROM events WHERE kind = 'k1' AND at > 50";
+    assert_eq!(
+        explain(&mut engine, sql),
+        Ok(vec![vec![
+            text("events"),
+            text("INDEX LOOKUP by_kind"),
+            Value::Null
+        ]])
+    );
+    let sql = "SELECT id FROM events USE INDEX (by_at) WHERE kind = 'k1' AND at > 50";
+    assert_eq!(
+        explain(&mut engine, sql),
+        Ok(vec![vec![
+            text("events"),
+            text("INDEX RANGE by_at"),
+            text("USE INDEX (by_at)")
+        ]])
+    );
+    let ignored =
+        "SELECT id FROM events IGNORE INDEX (by_kind, by_at) WHERE kind = 'k1' AND at > 50";
+    assert_eq!(
+        explain(&mut engine, ignored),
+        Ok(vec![vec![
+            text("events"),
+            text("SCAN"),
+            text("IGNORE INDEX (by_kind, by_at)")
+        ]])
+    );
+
+    // Hints change how rows are found, not which.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    let expected = Ok(vec![
+        vec![Value::Int(13)],
+        vec![Value::Int(16)],
+        vec![Value::Int(19)],
+    ]);
+    assert_eq!(run(sql), expected);
+    assert_eq!(run(ignored), expected);
+    assert_eq!(
+        run("SELECT id FROM events USE INDEX () WHERE kind = 'k1' AND at > 50"),
+        expected
+    );
+    assert!(matches!(
+        run("SELECT id FROM events USE INDEX (by_day) WHERE at > 50"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn trigram_indexes() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE notes (id INT, body TEXT)").unwrap();
+    let words = ["apple", "banana", "cherry", "grape", "lemon"];
+    for i in 0..50 {
+        let body = format!("{} and {} #{}", words[i % 5], words[(i / 5) % 5], i);
+        run(&format!("INSERT INTO notes VALUES ({}, '{}')", i, body)).unwrap();
+    }
+    run("INSERT INTO notes VALUES (50, NULL)").unwrap();
+    run("CREATE INDEX body_trgm ON notes (body) USING TRIGRAM").unwrap();
+    run("INSERT INTO notes VALUES (51, 'banana split with cherry')").unwrap();
+
+    let explain = |engine: &mut Engine, condition: &str| {
+        let sql = format!("EXPLAIN SELECT id FROM notes WHERE {}", condition);
+        engine.execute(parse_query(&sql).unwrap().1).unwrap()[0][1].clone()
+    };
+    let text = |s: &str| Value::Text(s.into());
+    assert_eq!(
+        explain(&mut engine, "body LIKE '%rry and gra%'"),
+        text("INDEX LOOKUP body_trgm")
+    );
+    // Too short to hold a trigram, or not a LIKE pattern.
+    assert_eq!(explain(&mut engine, "body LIKE '%an%'"), text("SCAN"));
+    assert_eq!(explain(&mut engine, "body = 'lemon'"), text("SCAN"));
+
+    // Rows holding every trigram are tested against the whole pattern.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT id FROM notes WHERE body LIKE '%rry and gra%'"),
+        Ok(vec![vec![Value::Int(17)], vec![Value::Int(42)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM notes WHERE body LIKE 'banana%cherry'"),
+        Ok(vec![vec![Value::Int(51)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM notes WHERE body LIKE '%ban_na and app%'"),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(26)]])
+    );
+    assert_eq!(
+        run("SELECT id FROM notes WHERE body LIKE '%kiwi%'"),
+        Ok(vec![])
+    );
+    assert!(matches!(
+        run("CREATE INDEX ON notes (id) USING TRIGRAM"),
+        Err(EngineError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn plan_cache() {
+    use sql_core::PlanCacheStats;
+
+    let mut engine = Engine::new();
+    engine
+        .execute_sql("CREATE TABLE items (id INT, name TEXT, price INT)")
+        .unwrap();
+    for (id, name) in [(1, "pen"), (2, "ink"), (3, "pad")] {
+        let sql = format!("INSERT INTO items VALUES ({}, '{}', {})", id, name, id * 10);
+        engine.execute_sql(&sql).unwrap();
+    }
+    let stats = |engine: &Engine| engine.plan_cache_stats();
+    assert_eq!(
+        stats(&engine),
+        PlanCacheStats {
+            entries: 1,
+            hits: 2,
+            misses: 2
+        }
+    );
+
+    // The same shape with other literals and spacing is found again.
+    assert_eq!(
+        engine.execute_sql("SELECT name FROM items WHERE id = 2 AND price > 5"),
+        Ok(vec![vec![Value::Text("ink".into())]])
+    );
+    assert_eq!(
+        engine.execute_sql("SELECT name   FROM items WHERE id = 3 AND price > 40"),
+        Ok(vec![])
+    );
+    assert_eq!(
+        engine.execute_sql("SELECT name FROM items WHERE id = 1 AND price > 0.5"),
+        Ok(vec![vec![Value::Text("pen".into())]])
+    );
+    assert_eq!(stats(&engine).hits, 4);
+
+    // A signed number, or the same value twice, is cached by exact text.
+    let run = |engine: &mut Engine, sql: &str| engine.execute_sql(sql).unwrap().len();
+    assert_eq!(
+        run(&mut engine, "SELECT id FROM items WHERE price - 5 > -1"),
+        3
+    );
+    assert_eq!(
+        run(&mut engine, "SELECT id FROM items WHERE price - 25 > -1"),
+        1
+    );
+    assert_eq!(
+        run(
+            &mut engine,
+            "SELECT id FROM items WHERE id = 1 OR price = 1"
+        ),
+        1
+    );
+    assert_eq!(
+        run(
+            &mut engine,
+            "SELECT id FROM items WHERE id = 2 OR price = 30"
+        ),
+        2
+    );
+    assert_eq!(
+        run(&mut engine, "SELECT id FROM items WHERE price - 5 > -1"),
+        3
+    );
+    assert_eq!(stats(&engine).hits, 5);
+
+    // DDL empties the cache.
+    engine.execute_sql("CREATE INDEX ON items (name)").unwrap();
+    assert_eq!(stats(&engine).entries, 0);
+    engine
+        .execute_sql("DELETE FROM items WHERE id = 3")
+        .unwrap();
+    engine
+        .execute_sql("DELETE FROM items WHERE id = 2")
+        .unwrap();
+    assert_eq!(run(&mut engine, "SELECT id FROM items"), 1);
+    assert_eq!(stats(&engine).entries, 2);
+
+    engine.set_plan_cache_size(1);
+    assert_eq!(stats(&engine).entries, 1);
+    engine.set_plan_cache_size(0);
+    engine.execute_sql("SELECT id FROM items").unwrap();
+    engine.execute_sql("SELECT id FROM items").unwrap();
+    assert_eq!(
+        stats(&engine),
+        PlanCacheStats {
+            entries: 0,
+            hits: 6,
+            misses: 12
+        }
+    );
+}
+
+#[test]
+fn query_plans() {
+    use sql_core::Plan;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE users (id INT, name TEXT)");
+    run("CREATE TABLE orders (order_id INT, user_id INT, total INT)");
+    run("CREATE INDEX by_total ON orders (total)");
+    for i in 0..5 {
+        run(&format!("INSERT INTO users VALUES ({}, 'u{}')", i, i));
+        run(&format!(
+            "INSERT INTO orders VALUES ({}, {}, {})",
+            i,
+            i % 2,
+            i * 10
+        ));
+    }
+    let plan = |engine: &Engine, sql: &str| engine.plan(&parse_query(sql).unwrap().1).unwrap();
+    let scan = |table: &str| {
+        Box::new(Plan::Scan {
+            table: table.into(),
+        })
+    };
+
+    assert_eq!(
+        plan(
+            &engine,
+            "SELECT name FROM users WHERE id = 3 ORDER BY name DESC LIMIT 1"
+        ),
+        Plan::Project {
+            columns: vec!["name".into()],
+            input: Box::new(Plan::Limit {
+                limit: Some(1),
+                offset: 0,
+                input: Box::new(Plan::Sort {
+                    column: "name".into(),
+                    ascending: false,
+                    input: Box::new(Plan::IndexLookup {
+                        table: "users".into(),
+                        index: "id".into(),
+                    }),
+                }),
+            }),
+        }
+    );
+    assert_eq!(
+        plan(&engine, "SELECT COUNT(*) FROM orders o WHERE o.total >= 20"),
+        Plan::Aggregate {
+            columns: vec!["COUNT(*)".into()],
+            input: Box::new(Plan::Filter {
+                condition: "o.total >= 20".into(),
+                input: Box::new(Plan::IndexRange {
+                    table: "orders".into(),
+                    index: "by_total".into(),
+                }),
+            }),
+        }
+    );
+    let joined = plan(
+        &engine,
+        "SELECT * FROM users u, orders o WHERE u.id = o.user_id AND o.total > 10",
+    );
+    assert_eq!(
+        joined,
+        Plan::Filter {
+            condition: "(u.id = o.user_id AND o.total > 10)".into(),
+            input: Box::new(Plan::Join {
+                left: scan("users"),
+                right: scan("orders"),
+                keys: vec![("u.id".into(), "o.user_id".into())],
+            }),
+        }
+    );
+    let json = serde_json::to_string(&joined).unwrap();
+    assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), joined);
+
+    assert_eq!(
+        plan(
+            &engine,
+            "WITH big AS (SELECT * FROM orders WHERE total > 25) SELECT * FROM big"
+        ),
+        Plan::Subquery {
+            name: "big".into(),
+            plan: Box::new(Plan::Filter {
+                condition: "total > 25".into(),
+                input: Box::new(Plan::IndexRange {
+                    table: "orders".into(),
+                    index: "by_total".into(),
+                }),
+            }),
+        }
+    );
+    assert_eq!(
+        plan(&engine, "DELETE FROM users WHERE name = 'u1'"),
+        Plan::Delete {
+            table: "users".into(),
+            input: Box::new(Plan::Filter {
+                condition: "name = 'u1'".into(),
+                input: scan("users"),
+            }),
+        }
+    );
+    assert!(matches!(
+        engine.plan(&parse_query("CREATE INDEX ON users (name)").unwrap().1),
+        Err(EngineError::InvalidOperation(_))
+    ));
+}
+
+#[test]
+fn save_and_open() {
+    use sql_core::SaveOptions;
+
+    let dir = std::env::temp_dir().join(format!("minisql-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("engine.db");
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE items (id INT, name TEXT, price INT)");
+    run("CREATE INDEX by_price ON items (price)");
+    run("CREATE INDEX doubled ON items (price * 2)");
+    for (id, name) in [(1, "pen"), (2, "ink"), (3, "pad")] {
+        run(&format!(
+            "INSERT INTO items VALUES ({}, '{}', {})",
+            id,
+            name,
+            id * 10
+        ));
+    }
+    run("CREATE VIEW cheap AS SELECT name FROM items WHERE price < 25");
+    engine.save(&path).unwrap();
+
+    let mut opened = Engine::open(&path).unwrap();
+    let mut run = |sql: &str| opened.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT * FROM cheap"),
+        vec![
+            vec![Value::Text("pen".into())],
+            vec![Value::Text("ink".into())]
+        ]
+    );
+    assert_eq!(
+        run("EXPLAIN SELECT id FROM items WHERE price * 2 = 60")[0][1],
+        Value::Text("INDEX LOOKUP doubled".into())
+    );
+    run("INSERT INTO items VALUES (4, 'cap', 40)");
+    assert_eq!(
+        run("SELECT id FROM items WHERE price > 25"),
+        vec![vec![Value::Int(3)], vec![Value::Int(4)]]
+    );
+
+    // Without index entries the file is smaller; opening rebuilds them.
+    let bare = dir.join("bare.db");
+    engine
+        .save_with(&bare, SaveOptions { indexes: false })
+        .unwrap();
+    let size = |p: &std::path::Path| std::fs::metadata(p).unwrap().len();
+    assert!(size(&bare) < size(&path));
+    let mut opened = Engine::open(&bare).unwrap();
+    assert_eq!(
+        opened
+            .execute(
+                parse_query("EXPLAIN SELECT id FROM items WHERE price = 20")
+                    .unwrap()
+                    .1
+            )
+            .unwrap()[0][1],
+        Value::Text("INDEX LOOKUP by_price".into())
+    );
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 2;
+    bytes[last] ^= 1;
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(matches!(
+        Engine::open(&path),
+        Err(EngineError::Corruption { .. })
+    ));
+    assert!(matches!(
+        Engine::open(dir.join("missing.db")),
+        Err(EngineError::Io(_))
+    ));
+
+    // Floats JSON has no number for come back as they were.
+    let floats = dir.join("floats.db");
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE readings (id INT, v FLOAT)");
+    run("INSERT INTO readings VALUES (1, CAST('NaN' AS FLOAT))");
+    run("INSERT INTO readings VALUES (2, CAST('inf' AS FLOAT))");
+    run("INSERT INTO readings VALUES (3, CAST('-inf' AS FLOAT))");
+    run("INSERT INTO readings VALUES (4, -0.5)");
+    engine.save(&floats).unwrap();
+    let opened = Engine::open(&floats).unwrap();
+    let values = opened.tables["readings"]
+        .all_rows()
+        .iter()
+        .map(|row| match row[1] {
+            Value::Float(f) => f,
+            ref other => panic!("not a float: {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert!(values[0].is_nan());
+    assert_eq!(values[1..], [f64::INFINITY, f64::NEG_INFINITY, -0.5]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn paged_tables() {
+    use sql_core::{parse_select, Column, PagedTable, PAGE_SIZE};
+
+    let path = std::env::temp_dir().join(format!("minisql-paged-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let column = |name: &str, col_type| Column {
+        name: name.into(),
+        col_type,
+        metadata: Default::default(),
+    };
+    let columns = vec![
+        column("id", ValueType::Int),
+        column("note", ValueType::Text),
+    ];
+    let mut table = PagedTable::create(&path, columns, 4).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..2000 {
+        ids.push(
+            table
+                .insert(vec![Value::Int(i), Value::Text(format!("note {}", i))])
+                .unwrap(),
+        );
+    }
+    assert_eq!(
+        table.insert(vec![Value::Int(1)]),
+        Err(EngineError::ValueCountMismatch)
+    );
+    assert!(table.pool_stats().pages <= 4);
+    for id in &ids[..1000] {
+        assert!(table.delete(*id).unwrap());
+    }
+    assert!(!table.delete(ids[0]).unwrap());
+    assert_eq!(table.get(ids[0]).unwrap(), None);
+    assert_eq!(
+        table.get(ids[1500]).unwrap(),
+        Some(vec![Value::Int(1500), Value::Text("note 1500".into())])
+    );
+    drop(table);
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    // Pages emptied by deletes are reused rather than added.
+    let mut table = PagedTable::open(&path, 4).unwrap();
+    for i in 2000..2500 {
+        table
+            .insert(vec![Value::Int(i), Value::Text(format!("note {}", i))])
+            .unwrap();
+    }
+    table.flush().unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+    assert_eq!(size % PAGE_SIZE as u64, 0);
+
+    let engine = Engine::new();
+    let query = |sql: &str| parse_select(sql).unwrap().1;
+    let result = engine
+        .select_paged(
+            "notes",
+            &mut table,
+            &query("SELECT note FROM notes WHERE id >= 1998 AND id < 2001 ORDER BY id"),
+        )
+        .unwrap();
+    assert_eq!(
+        result.rows,
+        vec![
+            vec![Value::Text("note 1998".into())],
+            vec![Value::Text("note 1999".into())],
+            vec![Value::Text("note 2000".into())],
+        ]
+    );
+    let result = engine
+        .select_paged("notes", &mut table, &query("SELECT COUNT(*) FROM notes"))
+        .unwrap();
+    assert_eq!(result.rows, vec![vec![Value::Int(1500)]]);
+    assert!(table.pool_stats().pages <= 4);
+    drop(table);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn table_logs() {
+    let dir = std::env::temp_dir().join(format!("minisql-log-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let log = dir.join("items.log");
+    let size = || std::fs::metadata(dir.join("items.log")).unwrap().len();
+
+    let mut engine = Engine::new();
+    engine.enable_log(&dir).unwrap();
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run(
+        &mut engine,
+        "CREATE TABLE items (id INT, name TEXT, price INT)",
+    );
+    // Changes made to a table directly reach its log when compacted.
+    let items = engine.tables.get_mut("items").unwrap();
+    items.set_auto_increment("id").unwrap();
+    engine.compact().unwrap();
+    let mut run = |sql: &str| run(&mut engine, sql);
+    run("CREATE INDEX by_price ON items (price)");
+    for name in ["pen", "ink", "pad", "cap"] {
+        run(&format!(
+            "INSERT INTO items (name, price) VALUES ('{}', 10)",
+            name
+        ));
+    }
+    run("UPDATE items SET price = 20 WHERE name = 'ink'");
+    run("DELETE FROM items WHERE name = 'pad'");
+    // Rows trading values are logged as the statement changed them.
+    run("UPDATE items SET price = 30 - price WHERE price IN (10, 20)");
+    let expected = run("SELECT * FROM items");
+    let before = size();
+
+    let mut opened = Engine::open_log(&dir).unwrap();
+    let mut run = |sql: &str| opened.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(run("SELECT * FROM items ORDER BY id"), expected);
+    assert_eq!(
+        run("EXPLAIN SELECT id FROM items WHERE price = 20")[0][1],
+        Value::Text("INDEX LOOKUP by_price".into())
+    );
+    // AUTO_INCREMENT carries on from the logged rows.
+    run("INSERT INTO items (name, price) VALUES ('mug', 30)");
+    assert_eq!(
+        run("SELECT id FROM items WHERE name = 'mug'"),
+        vec![vec![Value::Int(5)]]
+    );
+
+    // A statement cut short by a crash is dropped whole, and cut off the
+    // log.
+    let mut bytes = std::fs::read(&log).unwrap();
+    bytes.truncate(bytes.len() - 3);
+    std::fs::write(&log, &bytes).unwrap();
+    let mut opened = Engine::open_log(&dir).unwrap();
+    assert_eq!(
+        opened
+            .execute(parse_query("SELECT * FROM items ORDER BY id").unwrap().1)
+            .unwrap(),
+        expected
+    );
+    assert_eq!(size(), before);
+
+    // Compaction drops removed rows and the logs of dropped tables.
+    opened.compact().unwrap();
+    assert!(size() < before);
+    opened.tables.remove("items");
+    opened.compact().unwrap();
+    assert!(!log.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn csv_import_export() {
+    use sql_core::CsvOptions;
+
+    let mut engine = Engine::new();
+    let csv = "name,price,added,ok\n\
+               pen,1.5,2024-01-02,TRUE\n\
+               \"ink, blue\",2,2024-02-03,FALSE\n\
+               \"\",,2024-03-04,\n";
+    let options = CsvOptions::default();
+    assert_eq!(engine.import_csv("items", csv.as_bytes(), &options), Ok(3));
+    let items = &engine.tables["items"];
+    let types = items
+        .columns
+        .iter()
+        .map(|c| c.col_type.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            ValueType::Text,
+            ValueType::Float,
+            ValueType::Date,
+            ValueType::Bool
+        ]
+    );
+    // A quoted empty field is text; an unquoted one is NULL.
+    assert_eq!(items.row(2)[0], Value::Text(String::new()));
+    assert!(items.row(2)[1].is_null());
+
+    // Headers map fields by name, and fields are cast to the column types.
+    let create = parse_query("CREATE TABLE stock (id INT, item TEXT, qty INT)");
+    engine.execute(create.unwrap().1).unwrap();
+    let semicolons = CsvOptions {
+        delimiter: ';',
+        ..CsvOptions::default()
+    };
+    assert_eq!(
+        engine.import_csv(
+            "stock",
+            "qty;id;item\n5;1;pen\n7;2;ink\n".as_bytes(),
+            &semicolons
+        ),
+        Ok(2)
+    );
+    // A bad row leaves the table as it was.
+    assert!(matches!(
+        engine.import_csv("stock", "id,qty\n3,4\n4,many\n".as_bytes(), &options),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+    assert_eq!(
+        engine.import_csv("stock", "id,qty\n3,\"4\n".as_bytes(), &options),
+        Err(EngineError::InvalidInput {
+            line: 2,
+            reason: "unterminated quoted field".into()
+        })
+    );
+    assert_eq!(engine.tables["stock"].row_count(), 2);
+
+    let mut out = Vec::new();
+    assert_eq!(engine.export_csv("items", &mut out, &options), Ok(3));
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "name,price,added,ok\r\n\
+         pen,1.5,2024-01-02,TRUE\r\n\
+         \"ink, blue\",2,2024-02-03,FALSE\r\n\
+         \"\",,2024-03-04,\r\n"
+    );
+
+    // COPY writes a query to a file and reads it back.
+    let path = std::env::temp_dir().join(format!("minisql-copy-{}.csv", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run(&format!(
+        "COPY (SELECT id, qty FROM stock WHERE qty > 5) TO '{}' WITH (DELIMITER '|', NULL 'NULL')",
+        path
+    ));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "id|qty\r\n2|7\r\n");
+    run(&format!(
+        "COPY stock FROM '{}' WITH (HEADER, DELIMITER '|')",
+        path
+    ));
+    let rows = run("SELECT id, item, qty FROM stock WHERE id = 2");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1][0], Value::Int(2));
+    assert!(rows[1][1].is_null());
+    assert_eq!(rows[1][2], Value::Int(7));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn json_import_export() {
+    use sql_core::JsonFormat;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE events (id INT, kind TEXT, day DATE, data JSON)");
+    let lines =
+        "{\"id\": 1, \"kind\": \"open\", \"day\": \"2024-05-06\", \"data\": {\"by\": \"ann\"}}\n\
+                 \n\
+                 {\"kind\": \"close\", \"id\": 2}\n";
+    assert_eq!(engine.import_ndjson("events", lines.as_bytes()), Ok(2));
+    let rows = engine.tables["events"].all_rows();
+    assert_eq!(rows[0][2].value_type(), ValueType::Date);
+    assert_eq!(rows[0][3].value_type(), ValueType::Json);
+    assert!(rows[1][2].is_null());
+
+    // A bad line adds nothing.
+    assert_eq!(
+        engine.import_ndjson("events", "{\"id\": 3}\n[4]\n".as_bytes()),
+        Err(EngineError::InvalidInput {
+            line: 2,
+            reason: "not a JSON object".into()
+        })
+    );
+    assert_eq!(
+        engine.import_ndjson("events", "{\"id\": 3, \"who\": 1}\n".as_bytes()),
+        Err(EngineError::ColumnNotFound("who".into()))
+    );
+    assert_eq!(engine.tables["events"].row_count(), 2);
+
+    let mut out = Vec::new();
+    let source = "SELECT id, kind, day, data FROM events ORDER BY id";
+    assert_eq!(
+        engine.export_json(source, &mut out, JsonFormat::Lines),
+        Ok(2)
+    );
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"id\":1,\"kind\":\"open\",\"day\":\"2024-05-06\",\"data\":{\"by\":\"ann\"}}\n\
+         {\"id\":2,\"kind\":\"close\",\"day\":null,\"data\":null}\n"
+    );
+    let mut out = Vec::new();
+    engine
+        .export_json("events", &mut out, JsonFormat::Array)
+        .unwrap();
+    let parsed: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(parsed[1]["kind"], "close");
+    assert_eq!(parsed.as_array().unwrap().len(), 2);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_import_export() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE sales (id INT, item TEXT, price FLOAT, day DATE, paid BOOL)");
+    run("INSERT INTO sales VALUES (1, 'pen', 1.5, DATE '2024-01-02', TRUE)");
+    run("INSERT INTO sales VALUES (2, NULL, 2.25, DATE '2024-01-03', FALSE)");
+    run("INSERT INTO sales (id) VALUES (3)");
+
+    let path = std::env::temp_dir().join(format!("minisql-{}.parquet", std::process::id()));
+    assert_eq!(engine.export_parquet("sales", &path), Ok(3));
+    assert_eq!(&std::fs::read(&path).unwrap()[..4], b"PAR1");
+
+    // Into a new table, typed from the file.
+    assert_eq!(engine.import_parquet("copy", &path), Ok(3));
+    let types = engine.tables["copy"]
+        .columns
+        .iter()
+        .map(|c| c.col_type.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            ValueType::Int,
+            ValueType::Text,
+            ValueType::Float,
+            ValueType::Date,
+            ValueType::Bool
+        ]
+    );
+    assert_eq!(
+        engine.tables["copy"].all_rows(),
+        engine.tables["sales"].all_rows()
+    );
+
+    // Into an existing table, by column name.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE totals (n INT, total FLOAT)");
+    engine
+        .export_parquet(
+            "SELECT id AS n, price AS total FROM sales WHERE price > 2",
+            &path,
+        )
+        .unwrap();
+    assert_eq!(engine.import_parquet("totals", &path), Ok(1));
+    assert_eq!(
+        engine.tables["totals"].all_rows(),
+        vec![vec![Value::Int(2), Value::Float(2.25)]]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dump_and_restore() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE SCHEMA app");
+    run("CREATE TABLE app.users (id INT, name VARCHAR(20), role ENUM('admin', 'guest'))");
+    run("CREATE TABLE orders (id INT, user_id INT, price DECIMAL(8, 2), placed DATE)");
+    run("INSERT INTO app.users VALUES (1, 'O''Brien', 'admin')");
+    run("INSERT INTO app.users VALUES (2, NULL, 'guest')");
+    run("INSERT INTO orders VALUES (10, 1, 9.5, DATE '2024-02-29')");
+    run("INSERT INTO orders VALUES (11, 2, 20, NULL)");
+    run("CREATE UNIQUE INDEX by_id ON orders (id) USING HASH");
+    run("CREATE INDEX by_total ON orders (price * 2)");
+    run("COMMENT ON COLUMN orders.price IS 'in euros'");
+    run("PRAGMA typing(orders) = flexible");
+    run(
+        "CREATE VIEW totals AS SELECT id, (price + 1) * 2 AS doubled FROM orders \
+         WHERE user_id IN (SELECT id FROM app.users WHERE role = 'admin')",
+    );
+    run(
+        "CREATE MATERIALIZED VIEW big AS SELECT id FROM totals WHERE doubled > 10 ORDER BY id DESC",
+    );
+
+    let mut script = Vec::new();
+    engine.dump(&mut script).unwrap();
+    let script = String::from_utf8(script).unwrap();
+    assert!(script.contains("INSERT INTO app.users VALUES (1, 'O''Brien', 'admin');\n"));
+    assert!(script.contains("CREATE VIEW totals AS SELECT id, (price + 1) * 2 AS doubled"));
+
+    let mut restored = Engine::new();
+    let count = restored.restore_from_sql(script.as_bytes()).unwrap();
+    assert_eq!(count, script.lines().count());
+    let mut again = Vec::new();
+    restored.dump(&mut again).unwrap();
+    assert_eq!(String::from_utf8(again).unwrap(), script);
+    let query = "SELECT * FROM totals ORDER BY id";
+    assert_eq!(
+        restored.execute(parse_query(query).unwrap().1),
+        engine.execute(parse_query(query).unwrap().1)
+    );
+
+    // A failing statement leaves the engine as it was.
+    let bad = "CREATE TABLE extra (id INT);\nINSERT INTO extra VALUES (1);\nINSERT INTO missing VALUES (1);";
+    assert_eq!(
+        restored.restore_from_sql(bad.as_bytes()),
+        Err(EngineError::TableNotFound("missing".into()))
+    );
+    assert!(!restored.tables.contains_key("extra"));
+    assert_eq!(
+        restored.restore_from_sql("CREATE TABLE t (id INT);\nSELEC 1;".as_bytes()),
+        Err(EngineError::InvalidQuery(
+            "cannot parse the statement at line 2".into()
+        ))
+    );
+}
+
+#[test]
+fn dump_foreign_keys() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE users (id INT PRIMARY KEY, manager INT REFERENCES users (id))");
+    // Dumped before the table it references.
+    run("CREATE TABLE accounts (id INT, user_id INT REFERENCES users ON DELETE CASCADE)");
+    run("INSERT INTO users VALUES (1, NULL)");
+    run("INSERT INTO users VALUES (2, 1)");
+    run("INSERT INTO accounts VALUES (10, 1)");
+    run("INSERT INTO accounts VALUES (11, 2)");
+
+    let mut script = Vec::new();
+    engine.dump(&mut script).unwrap();
+    let script = String::from_utf8(script).unwrap();
+    assert!(script.contains(
+        "ALTER TABLE accounts ADD CONSTRAINT accounts_user_id_fkey FOREIGN KEY (user_id) \
+         REFERENCES users (id) ON DELETE CASCADE ON UPDATE RESTRICT;\n"
+    ));
+
+    let mut restored = Engine::new();
+    restored.restore_from_sql(script.as_bytes()).unwrap();
+    assert_eq!(
+        restored.tables["accounts"].foreign_keys,
+        engine.tables["accounts"].foreign_keys
+    );
+    let mut run = |sql: &str| restored.execute(parse_query(sql).unwrap().1);
+    assert!(run("INSERT INTO accounts VALUES (12, 3)").is_err());
+    assert!(run("DELETE FROM users WHERE id = 1").is_err());
+    run("DELETE FROM users WHERE id = 2").unwrap();
+    assert_eq!(
+        run("SELECT id FROM accounts"),
+        Ok(vec![vec![Value::Int(10)]])
+    );
+
+    // Rows that break a key added by ALTER TABLE are refused.
+    run("CREATE TABLE audits (user_id INT)").unwrap();
+    run("INSERT INTO audits VALUES (7)").unwrap();
+    assert!(run("ALTER TABLE audits ADD FOREIGN KEY (user_id) REFERENCES users (id)").is_err());
+    run("DELETE FROM audits").unwrap();
+    run("ALTER TABLE audits ADD FOREIGN KEY (user_id) REFERENCES users (id)").unwrap();
+    assert_eq!(
+        restored.tables["audits"].foreign_keys[0].name,
+        "audits_user_id_fkey"
+    );
+}
+
+#[test]
+fn backup_and_restore() {
+    let dir = std::env::temp_dir().join(format!("minisql-backup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let archive = dir.join("engine.bak");
+    let snapshot = dir.join("engine.db");
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE events (id INT, kind TEXT, note TEXT)");
+    run("CREATE INDEX by_kind ON events (kind)");
+    for id in 0..200 {
+        run(&format!(
+            "INSERT INTO events VALUES ({}, '{}', 'nothing to report')",
+            id,
+            ["start", "stop"][id % 2]
+        ));
+    }
+    engine.backup(&archive).unwrap();
+    engine.save(&snapshot).unwrap();
+    let size = |p: &std::path::Path| std::fs::metadata(p).unwrap().len();
+    assert!(size(&archive) * 4 < size(&snapshot));
+
+    let mut restored = Engine::new();
+    restored.restore(&archive).unwrap();
+    let mut run = |sql: &str| restored.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events WHERE kind = 'stop'"),
+        vec![vec![Value::Int(100)]]
+    );
+    assert_eq!(
+        run("EXPLAIN SELECT id FROM events WHERE kind = 'stop'")[0][1],
+        Value::Text("INDEX LOOKUP by_kind".into())
+    );
+
+    // A snapshot is not a backup, and a damaged backup changes nothing.
+    assert!(matches!(
+        restored.restore(&snapshot),
+        Err(EngineError::InvalidSnapshot(_))
+    ));
+    let mut bytes = std::fs::read(&archive).unwrap();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 1;
+    std::fs::write(&archive, &bytes).unwrap();
+    let mut empty = Engine::new();
+    assert!(matches!(
+        empty.restore(&archive),
+        Err(EngineError::Corruption { .. })
+    ));
+    // A length in the header far beyond what the block holds fails before
+    // anything is set aside for it.
+    bytes[middle] ^= 1;
+    bytes[19] ^= 0x40;
+    std::fs::write(&archive, &bytes).unwrap();
+    assert!(matches!(
+        empty.restore(&archive),
+        Err(EngineError::Corruption { .. })
+    ));
+    assert!(matches!(
+        empty.execute(parse_query("SELECT * FROM events").unwrap().1),
+        Err(EngineError::TableNotFound(_))
+    ));
+
+    // NaN and the infinities survive a backup.
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE readings (v FLOAT)");
+    for v in ["NaN", "inf", "-inf"] {
+        run(&format!(
+            "INSERT INTO readings VALUES (CAST('{}' AS FLOAT))",
+            v
+        ));
+    }
+    engine.backup(&archive).unwrap();
+    let mut restored = Engine::new();
+    restored.restore(&archive).unwrap();
+    let texts = |engine: &mut Engine| {
+        let sql = "SELECT CAST(v AS TEXT) FROM readings";
+        engine.execute(parse_query(sql).unwrap().1).unwrap()
+    };
+    assert_eq!(texts(&mut restored), texts(&mut engine));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn backup_round_trips() {
+    use sql_core::{Column, PagedTable};
+
+    let dir = std::env::temp_dir().join(format!("minisql-round-trips-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let archive = dir.join("engine.bak");
+    let snapshot = dir.join("engine.db");
+    let size = |p: &std::path::Path| std::fs::metadata(p).unwrap().len();
+
+    Engine::new().backup(&archive).unwrap();
+    let mut restored = Engine::new();
+    restored.restore(&archive).unwrap();
+    assert!(restored.tables.is_empty());
+
+    // Texts of random characters leave LZ4 nothing to shrink, and the
+    // backup ends up about as large as the snapshot.
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut noise = |len: usize| {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                char::from(
+                    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"
+                        [(seed % 62) as usize],
+                )
+            })
+            .collect::<String>()
+    };
+    let mut engine = Engine::new();
+    engine
+        .execute(
+            parse_query("CREATE TABLE noise (id INT, bytes TEXT)")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+    for id in 0..50 {
+        let sql = format!("INSERT INTO noise VALUES ({}, '{}')", id, noise(400));
+        engine.execute(parse_query(&sql).unwrap().1).unwrap();
+    }
+    engine.backup(&archive).unwrap();
+    engine.save(&snapshot).unwrap();
+    assert!(size(&archive) * 10 > size(&snapshot) * 9);
+    assert!(size(&archive) <= size(&snapshot) + size(&snapshot) / 255 + 16);
+    let mut restored = Engine::new();
+    restored.restore(&archive).unwrap();
+    let all = |engine: &mut Engine| {
+        let sql = "SELECT * FROM noise ORDER BY id";
+        engine.execute(parse_query(sql).unwrap().1).unwrap()
+    };
+    assert_eq!(all(&mut restored), all(&mut engine));
+
+    // A backup cut short anywhere fails to restore.
+    let bytes = std::fs::read(&archive).unwrap();
+    for len in (0..bytes.len()).step_by(97).chain([bytes.len() - 1]) {
+        std::fs::write(&archive, &bytes[..len]).unwrap();
+        assert!(matches!(
+            Engine::new().restore(&archive),
+            Err(EngineError::Corruption { .. } | EngineError::InvalidSnapshot(_))
+        ));
+    }
+
+    // A compressed paged table stores rows that do not shrink as they are.
+    let path = dir.join("noise.db");
+    let columns = vec![Column {
+        name: "bytes".into(),
+        col_type: ValueType::Text,
+        metadata: Default::default(),
+    }];
+    let mut table = PagedTable::create(&path, columns, 4).unwrap();
+    table.set_compressed(true);
+    let rows = [noise(1000), String::new()].map(|text| vec![Value::Text(text)]);
+    let ids = rows
+        .iter()
+        .map(|row| table.insert(row.clone()).unwrap())
+        .collect::<Vec<_>>();
+    drop(table);
+    let mut table = PagedTable::open(&path, 4).unwrap();
+    for (id, row) in ids.into_iter().zip(rows) {
+        assert_eq!(table.get(id).unwrap(), Some(row));
+    }
+    drop(table);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn column_layout() {
+    use sql_core::Layout;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE wide (id INT, a INT, b TEXT, c FLOAT, d TEXT) WITH (layout = 'column')");
+    run("CREATE INDEX by_b ON wide (b)");
+    for id in 1..=6 {
+        run(&format!(
+            "INSERT INTO wide VALUES ({}, {}, '{}', {}.5, 'note')",
+            id,
+            id * 10,
+            ["x", "y"][id as usize % 2],
+            id
+        ));
+    }
+    assert_eq!(
+        run("SELECT SUM(a) FROM wide WHERE id > 3"),
+        vec![vec![Value::Int(150)]]
+    );
+    assert_eq!(
+        run("SELECT id, d FROM wide WHERE b = 'x' ORDER BY a DESC LIMIT 2"),
+        vec![
+            vec![Value::Int(6), Value::Text("note".into())],
+            vec![Value::Int(4), Value::Text("note".into())]
+        ]
+    );
+    run("UPDATE wide SET d = 'changed' WHERE a >= 50");
+    run("DELETE FROM wide WHERE id = 1");
+    assert_eq!(
+        run("SELECT id, d FROM wide WHERE b = 'y'"),
+        vec![
+            vec![Value::Int(3), Value::Text("note".into())],
+            vec![Value::Int(5), Value::Text("changed".into())]
+        ]
+    );
+    assert_eq!(
+        run("SELECT * FROM wide WHERE id = 2"),
+        vec![vec![
+            Value::Int(2),
+            Value::Int(20),
+            Value::Text("x".into()),
+            Value::Float(2.5),
+            Value::Text("note".into())
+        ]]
+    );
+
+    let table = &engine.tables["wide"];
+    assert_eq!(table.layout, Layout::Column);
+    assert_eq!(table.row_count(), 5);
+    let mut script = Vec::new();
+    engine.dump(&mut script).unwrap();
+    assert!(String::from_utf8(script).unwrap().contains(
+        "CREATE TABLE wide (id INT, a INT, b TEXT, c FLOAT, d TEXT) WITH (layout = 'column');"
+    ));
+
+    // Switching back moves the rows into place.
+    engine.set_table_layout("wide", Layout::Row).unwrap();
+    assert_eq!(engine.tables["wide"].row_count(), 5);
+    assert!(!matches!(
+        parse_query("CREATE TABLE t (id INT) WITH (layout = 'diagonal')"),
+        Ok(("", _))
+    ));
+}
+
+#[test]
+fn compressed_tables() {
+    use sql_core::{Column, PagedTable};
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE visits (id INT, country TEXT, page TEXT) WITH (layout = 'column', compression = 'on')");
+    for id in 0..30 {
+        run(&format!(
+            "INSERT INTO visits VALUES ({}, '{}', '/home')",
+            id,
+            ["fr", "de", "jp"][id % 3]
+        ));
+    }
+    run("UPDATE visits SET country = 'it' WHERE id = 0");
+    run("DELETE FROM visits WHERE country = 'jp'");
+    assert_eq!(
+        run("SELECT COUNT(*) FROM visits WHERE country = 'fr'"),
+        vec![vec![Value::Int(9)]]
+    );
+    assert_eq!(
+        run("SELECT country, page FROM visits WHERE id = 0"),
+        vec![vec![Value::Text("it".into()), Value::Text("/home".into())]]
+    );
+    assert!(engine.tables["visits"].compressed);
+    assert!(matches!(
+        engine.execute(
+            parse_query("CREATE TABLE t (id INT) WITH (compression = 'on')")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::InvalidQuery(_))
+    ));
+
+    // Reopening the engine rebuilds the dictionaries' lookups.
+    let path = std::env::temp_dir().join(format!("minisql-dict-{}.db", std::process::id()));
+    engine.save(&path).unwrap();
+    let mut engine = Engine::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("INSERT INTO visits VALUES (100, 'de', '/about')");
+    assert_eq!(
+        run("SELECT COUNT(*) FROM visits WHERE country = 'de'"),
+        vec![vec![Value::Int(11)]]
+    );
+    engine.set_table_compression("visits", false).unwrap();
+    assert!(!engine.tables["visits"].compressed);
+
+    // Paged tables compress rows as they are stored.
+    let sizes = [false, true].map(|compressed| {
+        let path = std::env::temp_dir().join(format!(
+            "minisql-packed-{}-{}.db",
+            std::process::id(),
+            compressed
+        ));
+        let _ = std::fs::remove_file(&path);
+        let columns = vec![Column {
+            name: "body".into(),
+            col_type: ValueType::Text,
+            metadata: Default::default(),
+        }];
+        let mut table = PagedTable::create(&path, columns, 4).unwrap();
+        table.set_compressed(compressed);
+        let body = "all work and no play makes a dull row ".repeat(20);
+        let id = table.insert(vec![Value::Text(body.clone())]).unwrap();
+        for _ in 0..200 {
+            table.insert(vec![Value::Text(body.clone())]).unwrap();
+        }
+        drop(table);
+        let mut table = PagedTable::open(&path, 4).unwrap();
+        assert_eq!(table.compressed(), compressed);
+        assert_eq!(table.get(id).unwrap(), Some(vec![Value::Text(body)]));
+        drop(table);
+        let size = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_file(&path).unwrap();
+        size
+    });
+    assert!(sizes[1] * 5 < sizes[0]);
+}
+
+#[test]
+fn order_by_spills_to_disk() {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE words (id INT, word TEXT)");
+    for id in 0..500 {
+        run(&format!(
+            "INSERT INTO words VALUES ({}, 'w{:03}')",
+            id,
+            (id * 37) % 100
+        ));
+    }
+    let sorted = |engine: &mut Engine, dir: &str| {
+        engine
+            .execute(
+                parse_query(&format!("SELECT id FROM words ORDER BY word {}", dir))
+                    .unwrap()
+                    .1,
+            )
+            .unwrap()
+    };
+    let asc = sorted(&mut engine, "ASC");
+    let desc = sorted(&mut engine, "DESC");
+    assert_eq!(engine.sort_stats().spilled, 0);
+
+    engine
+        .execute(parse_query("PRAGMA sort_memory = 1024").unwrap().1)
+        .unwrap();
+    assert_eq!(sorted(&mut engine, "ASC"), asc);
+    assert_eq!(sorted(&mut engine, "DESC"), desc);
+    let stats = engine.sort_stats();
+    assert_eq!(stats.spilled, 2);
+    assert!(stats.runs > 4);
+    assert_eq!(
+        engine
+            .execute(parse_query("PRAGMA sort_memory").unwrap().1)
+            .unwrap(),
+        vec![vec![Value::Int(1024)]]
+    );
+}
+
+#[test]
+fn attached_storage() {
+    use sql_core::{Column, MemoryStorage, PagedTable, StorageEngine};
+
+    let column = |name: &str, col_type| Column {
+        name: name.into(),
+        col_type,
+        metadata: Default::default(),
+    };
+    let columns = vec![
+        column("id", ValueType::Int),
+        column("name", ValueType::Text),
+    ];
+    let mut engine = Engine::new();
+    engine
+        .attach("people", Box::new(MemoryStorage::new(columns.clone())))
+        .unwrap();
+    assert!(matches!(
+        engine.attach("people", Box::new(MemoryStorage::new(columns.clone()))),
+        Err(EngineError::TableExists(_))
+    ));
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("INSERT INTO people VALUES (1, 'Ann')");
+    run("INSERT INTO people (name, id) VALUES ('Bob', 2)");
+    run("INSERT INTO people VALUES (3, 'Cy')");
+    run("CREATE TABLE pets (owner INT, pet TEXT)");
+    run("INSERT INTO pets VALUES (2, 'Rex')");
+    assert_eq!(
+        run("SELECT name FROM people WHERE id > 1 ORDER BY id"),
+        vec![
+            vec![Value::Text("Bob".into())],
+            vec![Value::Text("Cy".into())]
+        ]
+    );
+    assert_eq!(
+        run("SELECT name, pet FROM people, pets WHERE people.id = pets.owner"),
+        vec![vec![Value::Text("Bob".into()), Value::Text("Rex".into())]]
+    );
+    run("UPDATE people SET name = 'Bea' WHERE id = 2");
+    run("DELETE FROM people WHERE id = 1");
+    assert_eq!(
+        run("SELECT id, name FROM people ORDER BY id"),
+        vec![
+            vec![Value::Int(2), Value::Text("Bea".into())],
+            vec![Value::Int(3), Value::Text("Cy".into())],
+        ]
+    );
+    assert!(matches!(
+        engine.execute(
+            parse_query("INSERT INTO people VALUES ('x', 'y')")
+                .unwrap()
+                .1
+        ),
+        Err(EngineError::TypeMismatch { .. })
+    ));
+
+    let mut storage = engine.detach("people").unwrap();
+    let mut ids = Vec::new();
+    storage
+        .scan(&mut |id, _| {
+            ids.push(id);
+            Ok(())
+        })
+        .unwrap();
+    // UPDATE changed Bob's row where it was, keeping its id.
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(
+        storage.update(2, vec![Value::Int(3), Value::Text("Cyd".into())]),
+        Ok(Some(2))
+    );
+    assert_eq!(storage.delete_many(&[0, 1, 2]), Ok(2));
+    assert_eq!(storage.get(1), Ok(None));
+    assert!(matches!(
+        engine.execute(parse_query("SELECT * FROM people").unwrap().1),
+        Err(EngineError::TableNotFound(_))
+    ));
+
+    // A paged table behind the engine keeps its rows in its file.
+    let path = std::env::temp_dir().join(format!("minisql-attached-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let paged = PagedTable::create(&path, columns, 4).unwrap();
+    engine.attach("archive", Box::new(paged)).unwrap();
+    for id in 0..100 {
+        engine
+            .execute(
+                parse_query(&format!("INSERT INTO archive VALUES ({}, 'n{}')", id, id))
+                    .unwrap()
+                    .1,
+            )
+            .unwrap();
+    }
+    engine
+        .execute(parse_query("DELETE FROM archive WHERE id >= 10").unwrap().1)
+        .unwrap();
+    engine.flush_attached().unwrap();
+    drop(engine.detach("archive").unwrap());
+    let mut reopened = PagedTable::open(&path, 4).unwrap();
+    let mut count = 0;
+    reopened
+        .scan(&mut |_, _| {
+            count += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(count, 10);
+    drop(reopened);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn checkpoints_truncate_logs() {
+    let dir = std::env::temp_dir().join(format!("minisql-checkpoint-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let log = dir.join("items.log");
+    let size = || std::fs::metadata(&log).unwrap().len();
+    let checkpoints = || {
+        let mut names = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".snapshot"))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+
+    let mut engine = Engine::new();
+    engine.enable_log(&dir).unwrap();
+    run(&mut engine, "CREATE TABLE items (id INT, name TEXT)");
+    run(&mut engine, "CREATE VIEW named AS SELECT name FROM items");
+    for id in 0..20 {
+        run(
+            &mut engine,
+            &format!("INSERT INTO items VALUES ({}, 'n{}')", id, id),
+        );
+    }
+    let logged = size();
+    engine.checkpoint().unwrap();
+    assert!(size() < logged);
+    assert_eq!(checkpoints().len(), 1);
+
+    // Statements that only read take no checkpoint.
+    let taken = checkpoints();
+    for sql in [
+        "SELECT COUNT(*) FROM named",
+        "EXPLAIN SELECT id FROM items WHERE id = 3",
+        "PRAGMA checkpoint_size",
+    ] {
+        run(&mut engine, sql);
+    }
+    assert_eq!(checkpoints(), taken);
+
+    // A log left behind by a checkpoint cut short is not replayed twice.
+    run(&mut engine, "DELETE FROM items WHERE id >= 10");
+    let before = std::fs::read(&log).unwrap();
+    engine.checkpoint().unwrap();
+    std::fs::write(&log, &before).unwrap();
+    let mut opened = Engine::open_log(&dir).unwrap();
+    assert_eq!(
+        run(&mut opened, "SELECT COUNT(*) FROM named"),
+        vec![vec![Value::Int(10)]]
+    );
+
+    // Past the checkpoint size a checkpoint is taken on its own.
+    run(&mut opened, "PRAGMA checkpoint_size = 256");
+    assert_eq!(opened.checkpoint_size(), 256);
+    let first = checkpoints();
+    for id in 10..200 {
+        run(
+            &mut opened,
+            &format!("INSERT INTO items VALUES ({}, 'n{}')", id, id),
+        );
+        assert!(size() < 1024);
+    }
+    let last = checkpoints();
+    assert_eq!(last.len(), 1);
+    assert_ne!(first, last);
+    let mut reopened = Engine::open_log(&dir).unwrap();
+    assert_eq!(
+        run(&mut reopened, "SELECT COUNT(*) FROM items"),
+        vec![vec![Value::Int(200)]]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn point_in_time_recovery() {
+    use sql_core::PointInTime;
+
+    let dir = std::env::temp_dir().join(format!("minisql-pitr-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    let count = |engine: &mut Engine| run(engine, "SELECT COUNT(*) FROM items")[0][0].clone();
+
+    let mut engine = Engine::new();
+    engine.set_history(2);
+    engine.enable_log(&dir).unwrap();
+    run(&mut engine, "CREATE TABLE items (id INT)");
+    for id in 0..3 {
+        run(&mut engine, &format!("INSERT INTO items VALUES ({})", id));
+    }
+    let three = engine.last_lsn().unwrap();
+    engine.checkpoint().unwrap();
+    for id in 3..5 {
+        run(&mut engine, &format!("INSERT INTO items VALUES ({})", id));
+    }
+    let five = engine.last_lsn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let before_job = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as i64;
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    // A bad batch job.
+    run(&mut engine, "DELETE FROM items WHERE id > 0");
+    assert_eq!(count(&mut engine), Value::Int(1));
+
+    let at = |point| Engine::open_at(&dir, point).unwrap();
+    assert_eq!(count(&mut at(PointInTime::Lsn(five))), Value::Int(5));
+    assert_eq!(
+        count(&mut at(PointInTime::Timestamp(before_job))),
+        Value::Int(5)
+    );
+    // Before the last checkpoint, from the segments kept.
+    assert_eq!(count(&mut at(PointInTime::Lsn(three))), Value::Int(3));
+    assert_eq!(count(&mut at(PointInTime::Lsn(three - 1))), Value::Int(2));
+    assert!(matches!(
+        Engine::open_at(&dir, PointInTime::Timestamp(0)),
+        Err(EngineError::InvalidOperation(_))
+    ));
+
+    // The latest state is still what the logs open to.
+    let mut opened = Engine::open_log(&dir).unwrap();
+    assert_eq!(count(&mut opened), Value::Int(1));
+    assert_eq!(opened.last_lsn(), engine.last_lsn());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn partitioned_tables() {
+    use sql_core::{Partitioning, Plan};
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE orders (id INT, year INT, total INT) PARTITION BY RANGE (year) (PARTITION old VALUES LESS THAN (2020), PARTITION recent VALUES LESS THAN (2024), PARTITION current VALUES LESS THAN MAXVALUE)").unwrap();
+    run("CREATE TABLE events (id INT, user_id INT) PARTITION BY HASH (user_id) PARTITIONS 4")
+        .unwrap();
+    for id in 0..30 {
+        run(&format!(
+            "INSERT INTO orders VALUES ({}, {}, {})",
+            id,
+            2015 + id % 12,
+            id * 10
+        ))
+        .unwrap();
+        run(&format!("INSERT INTO events VALUES ({}, {})", id, id % 7)).unwrap();
+    }
+    assert!(matches!(
+        run("CREATE TABLE bad (a INT) PARTITION BY RANGE (a) (PARTITION x VALUES LESS THAN (5), PARTITION y VALUES LESS THAN (3))"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert_eq!(
+        engine.tables["orders"].partitions(),
+        vec![
+            ("old".to_string(), 15),
+            ("recent".to_string(), 9),
+            ("current".to_string(), 6)
+        ]
+    );
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("EXPLAIN SELECT id FROM orders WHERE year >= 2024").unwrap()[0][1],
+        Value::Text("PARTITIONS current".into())
+    );
+    assert_eq!(
+        run("EXPLAIN SELECT id FROM orders WHERE year BETWEEN 2019 AND 2021").unwrap()[0][1],
+        Value::Text("PARTITIONS old, recent".into())
+    );
+    assert_eq!(
+        run("EXPLAIN SELECT id FROM orders WHERE total > 5").unwrap()[0][1],
+        Value::Text("SCAN".into())
+    );
+    assert_eq!(
+        run("SELECT id FROM orders WHERE year >= 2025 AND total > 200"),
+        Ok(vec![vec![Value::Int(22)], vec![Value::Int(23)]])
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events WHERE user_id IN (3, 5)"),
+        Ok(vec![vec![Value::Int(8)]])
+    );
+    // A single key hashes to a single partition.
+    assert!(matches!(
+        &run("EXPLAIN SELECT id FROM events WHERE user_id = 3").unwrap()[0][1],
+        Value::Text(path) if path.starts_with("PARTITIONS p") && !path.contains(',')
+    ));
+
+    // Rows move between partitions as they change.
+    run("UPDATE orders SET year = 2030 WHERE id < 3").unwrap();
+    run("DELETE FROM orders WHERE year = 2022").unwrap();
+    assert_eq!(
+        run("SELECT id FROM orders WHERE year = 2030"),
+        Ok(vec![
+            vec![Value::Int(0)],
+            vec![Value::Int(1)],
+            vec![Value::Int(2)]
+        ])
+    );
+    assert_eq!(
+        engine.tables["orders"].partitions(),
+        vec![
+            ("old".to_string(), 12),
+            ("recent".to_string(), 7),
+            ("current".to_string(), 9)
+        ]
+    );
+    assert_eq!(
+        engine
+            .plan(
+                &parse_query("SELECT * FROM orders WHERE year < 2016")
+                    .unwrap()
+                    .1
+            )
+            .unwrap(),
+        Plan::Filter {
+            condition: "year < 2016".into(),
+            input: Box::new(Plan::Partitions {
+                table: "orders".into(),
+                partitions: vec!["old".into()],
+            }),
+        }
+    );
+
+    // A value above every bound fits no partition.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE readings (at INT) PARTITION BY RANGE (at) (PARTITION p0 VALUES LESS THAN (100))").unwrap();
+    run("INSERT INTO readings VALUES (5)").unwrap();
+    assert!(matches!(
+        run("INSERT INTO readings VALUES (100)"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        run("UPDATE readings SET at = 500"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert_eq!(
+        run("SELECT at FROM readings"),
+        Ok(vec![vec![Value::Int(5)]])
+    );
+
+    // The partitions survive a dump.
+    let mut script = Vec::new();
+    engine.dump(&mut script).unwrap();
+    let mut restored = Engine::new();
+    restored.restore_from_sql(script.as_slice()).unwrap();
+    assert_eq!(
+        restored.tables["orders"].partitioning,
+        engine.tables["orders"].partitioning
+    );
+    assert_eq!(
+        restored.tables["events"].partitioning,
+        Some(Partitioning::hash("user_id", 4))
+    );
+
+    // And a snapshot, which works them out again from the rows.
+    let file = std::env::temp_dir().join(format!("minisql-parts-{}.db", std::process::id()));
+    engine.save(&file).unwrap();
+    let mut reopened = Engine::open(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    for table in ["orders", "events"] {
+        assert_eq!(
+            reopened.tables[table].partitions(),
+            engine.tables[table].partitions()
+        );
+    }
+    assert_eq!(
+        reopened.execute(
+            parse_query("SELECT COUNT(*) FROM events WHERE user_id = 3")
+                .unwrap()
+                .1
+        ),
+        Ok(vec![vec![Value::Int(4)]])
+    );
+}
+
+#[test]
+fn expiring_rows() {
+    use sql_core::{Interval, Ttl};
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run("CREATE TABLE cache (key TEXT, created TIMESTAMP) WITH (ttl = '1 day', ttl_column = 'created')").unwrap();
+    run("INSERT INTO cache VALUES ('old', TIMESTAMP '2000-01-01 00:00:00')").unwrap();
+    run("INSERT INTO cache VALUES ('fresh', TIMESTAMP '2999-01-01 00:00:00')").unwrap();
+    run("INSERT INTO cache VALUES ('forever', NULL)").unwrap();
+    assert_eq!(
+        run("SELECT key FROM cache"),
+        Ok(vec![
+            vec![Value::Text("fresh".into())],
+            vec![Value::Text("forever".into())]
+        ])
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM cache WHERE key = 'old'"),
+        Ok(vec![vec![Value::Int(0)]])
+    );
+    // DML skips expired rows too.
+    assert_eq!(
+        run("UPDATE cache SET key = 'x' WHERE key = 'old'"),
+        Ok(Vec::new())
+    );
+    assert_eq!(engine.tables["cache"].row_count(), 3);
+    assert_eq!(engine.sweep_expired(None), Ok(1));
+    assert_eq!(engine.tables["cache"].row_count(), 2);
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert!(matches!(
+        run("CREATE TABLE bad (key TEXT) WITH (ttl = '1 day', ttl_column = 'key')"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(parse_query("CREATE TABLE bad (at DATE) WITH (ttl_column = 'at')").is_err());
+
+    // Counting from the insert time.
+    run("CREATE TABLE sessions (token TEXT) WITH (ttl = '50 milliseconds')").unwrap();
+    run("INSERT INTO sessions VALUES ('a')").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(80));
+    run("INSERT INTO sessions VALUES ('b')").unwrap();
+    assert_eq!(
+        run("SELECT token FROM sessions"),
+        Ok(vec![vec![Value::Text("b".into())]])
+    );
+    assert_eq!(engine.sweep_expired(Some("sessions")), Ok(1));
+    assert_eq!(engine.tables["sessions"].row_count(), 1);
+    assert_eq!(
+        engine.tables["sessions"].ttl,
+        Some(Ttl::since_insert(Interval::new(0, 50_000)))
+    );
+
+    // The TTL survives a dump.
+    let mut script = Vec::new();
+    engine.dump(&mut script).unwrap();
+    let mut restored = Engine::new();
+    restored.restore_from_sql(script.as_slice()).unwrap();
+    assert_eq!(restored.tables["cache"].ttl, engine.tables["cache"].ttl);
+    assert_eq!(
+        restored.tables["sessions"].ttl,
+        engine.tables["sessions"].ttl
+    );
+}
+
+#[test]
+fn read_only_engines() {
+    use sql_core::Layout;
+
+    let dir = std::env::temp_dir().join(format!("minisql-read-only-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1);
+
+    let mut writer = Engine::new();
+    writer.enable_log(&dir).unwrap();
+    run(&mut writer, "CREATE TABLE items (id INT)").unwrap();
+    run(&mut writer, "INSERT INTO items VALUES (1)").unwrap();
+
+    let mut reader = Engine::open_read_only(&dir).unwrap();
+    assert!(reader.is_read_only());
+    assert_eq!(
+        run(&mut reader, "SELECT id FROM items"),
+        Ok(vec![vec![Value::Int(1)]])
+    );
+    for sql in [
+        "INSERT INTO items VALUES (2)",
+        "UPDATE items SET id = 3",
+        "DELETE FROM items",
+        "CREATE TABLE other (id INT)",
+        "CREATE INDEX ON items (id)",
+        "PRAGMA typing = FLEXIBLE",
+        "ANALYZE",
+    ] {
+        assert_eq!(run(&mut reader, sql), Err(EngineError::ReadOnly), "{}", sql);
+    }
+    assert!(run(&mut reader, "PRAGMA typing").is_ok());
+    assert_eq!(reader.enable_log(&dir), Err(EngineError::ReadOnly));
+
+    // The writer carries on, and a reader opened later sees its changes.
+    run(&mut writer, "INSERT INTO items VALUES (2)").unwrap();
+    writer.checkpoint().unwrap();
+    run(&mut writer, "INSERT INTO items VALUES (3)").unwrap();
+    let mut reader = Engine::open_read_only(&dir).unwrap();
+    assert_eq!(
+        run(&mut reader, "SELECT COUNT(*) FROM items"),
+        Ok(vec![vec![Value::Int(3)]])
+    );
+
+    let snapshot = dir.join("engine.snapshot");
+    writer.save(&snapshot).unwrap();
+    let mut reader = Engine::open_read_only(&snapshot).unwrap();
+    assert_eq!(
+        run(&mut reader, "DELETE FROM items"),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(
+        run(&mut reader, "SELECT COUNT(*) FROM items"),
+        Ok(vec![vec![Value::Int(3)]])
+    );
+    assert_eq!(reader.load(&snapshot), Err(EngineError::ReadOnly));
+    assert_eq!(
+        reader.set_table_compression("items", true),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(
+        reader.set_table_ttl("items", None),
+        Err(EngineError::ReadOnly)
+    );
+    let columns = || vec![("id".to_string(), ValueType::Int)];
+    assert_eq!(
+        reader.create_table("other", columns()),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(
+        reader.create_table_with_typing("other", columns(), Typing::Flexible),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(
+        reader.create_table_if_not_exists("other", columns()),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(
+        reader.replace_table("items", columns()),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(reader.create_schema("sales"), Err(EngineError::ReadOnly));
+    assert_eq!(
+        reader.set_metadata("items", None, "owner", Some("billing".into())),
+        Err(EngineError::ReadOnly)
+    );
+    assert_eq!(reader.analyze("items"), Err(EngineError::ReadOnly));
+    assert_eq!(reader.vacuum(None), Err(EngineError::ReadOnly));
+    assert_eq!(reader.sweep_expired(None), Err(EngineError::ReadOnly));
+    assert_eq!(reader.rebuild_indexes(), Err(EngineError::ReadOnly));
+    assert!(reader.tables["items"].stats.is_none());
+
+    // A writer's table settings reach the log, as its statements do.
+    writer.set_table_layout("items", Layout::Column).unwrap();
+    let reader = Engine::open_read_only(&dir).unwrap();
+    assert_eq!(reader.tables["items"].layout, Layout::Column);
+
+    // Loading a snapshot keeps the writer logging.
+    run(&mut writer, "DELETE FROM items").unwrap();
+    writer.load(&snapshot).unwrap();
+    run(&mut writer, "INSERT INTO items VALUES (4)").unwrap();
+    let mut reader = Engine::open_read_only(&dir).unwrap();
+    assert_eq!(
+        run(&mut reader, "SELECT COUNT(*) FROM items"),
+        Ok(vec![vec![Value::Int(4)]])
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn engine_stats() {
+    let dir = std::env::temp_dir().join(format!("minisql-stats-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE small (id INT)");
+    run("CREATE TABLE notes (id INT, body TEXT)");
+    run("CREATE INDEX by_body ON notes (body)");
+    run("INSERT INTO small VALUES (1)");
+    for id in 0..100 {
+        run(&format!(
+            "INSERT INTO notes VALUES ({}, '{}')",
+            id,
+            "x".repeat(100)
+        ));
+    }
+
+    let stats = engine.stats().unwrap();
+    assert_eq!(stats.disk, 0);
+    let names = stats
+        .tables
+        .iter()
+        .map(|t| t.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["notes", "small"]);
+    let (notes, small) = (&stats.tables[0], &stats.tables[1]);
+    assert_eq!((notes.rows, small.rows), (100, 1));
+    assert!(notes.row_memory > 100 * 100);
+    assert!(notes.row_memory > small.row_memory * 50);
+    let indexes = notes
+        .indexes
+        .iter()
+        .map(|i| i.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(indexes, ["by_body", "id"]);
+    assert!(notes.indexes.iter().all(|i| i.memory > 0));
+    assert_eq!(stats.memory, notes.memory() + small.memory());
+    assert_eq!(notes.disk, 0);
+
+    // A column table counts each distinct text of a compressed column once.
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE packed (id INT, body TEXT) WITH (layout = 'column', compression = 'on')");
+    for id in 0..100 {
+        run(&format!(
+            "INSERT INTO packed VALUES ({}, '{}')",
+            id,
+            "x".repeat(100)
+        ));
+    }
+    let stats = engine.stats().unwrap();
+    assert!(stats.tables[1].row_memory * 3 < stats.tables[0].row_memory);
+
+    engine.enable_log(&dir).unwrap();
+    let stats = engine.stats().unwrap();
+    assert!(stats.tables.iter().all(|t| t.disk > 0));
+    assert!(stats.disk >= stats.tables.iter().map(|t| t.disk).sum::<u64>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn vacuum_reclaims_space() {
+    use sql_core::{Column, PagedTable, VacuumStats};
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE notes (id INT, body TEXT) WITH (layout = 'column', compression = 'on')");
+    run("CREATE INDEX by_body ON notes (body)");
+    for id in 0..100 {
+        run(&format!("INSERT INTO notes VALUES ({}, 'note {}')", id, id));
+    }
+    run("DELETE FROM notes WHERE id >= 10");
+    let reclaimed = run("VACUUM notes");
+    assert_eq!(reclaimed.len(), 1);
+    assert_eq!(reclaimed[0][0], Value::Int(0));
+    assert!(matches!(reclaimed[0][1], Value::Int(memory) if memory > 0));
+    assert_eq!(
+        run("SELECT id FROM notes WHERE body = 'note 7'"),
+        vec![vec![Value::Int(7)]]
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM notes"),
+        vec![vec![Value::Int(10)]]
+    );
+    // Nothing is left to reclaim the second time.
+    assert_eq!(engine.vacuum(None).unwrap(), VacuumStats::default());
+    assert!(matches!(
+        engine.execute(parse_query("VACUUM missing").unwrap().1),
+        Err(EngineError::TableNotFound(_))
+    ));
+
+    // An attached paged table shrinks its file and keeps its rows.
+    let path = std::env::temp_dir().join(format!("minisql-vacuum-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let columns = vec![Column {
+        name: "id".into(),
+        col_type: ValueType::Int,
+        metadata: Default::default(),
+    }];
+    engine
+        .attach(
+            "archive",
+            Box::new(PagedTable::create(&path, columns, 4).unwrap()),
+        )
+        .unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    for id in 0..2000 {
+        run(&format!("INSERT INTO archive VALUES ({})", id));
+    }
+    run("DELETE FROM archive WHERE id >= 10");
+    engine.flush_attached().unwrap();
+    let before = std::fs::metadata(&path).unwrap().len();
+    let stats = engine.vacuum(Some("archive")).unwrap();
+    let after = std::fs::metadata(&path).unwrap().len();
+    assert!(stats.disk > 0);
+    assert_eq!(before - after, stats.disk);
+    let mut count = 0;
+    engine
+        .detach("archive")
+        .unwrap()
+        .scan(&mut |_, _| {
+            count += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(count, 10);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_import() {
+    use sql_core::Typing;
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/shop.sqlite");
+    let mut engine = Engine::new();
+    assert_eq!(
+        engine.import_sqlite(path),
+        Ok(vec!["customers".to_string(), "orders".to_string()])
+    );
+    let types = |engine: &Engine, table: &str| {
+        engine.tables[table]
+            .columns
+            .iter()
+            .map(|c| c.col_type.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        types(&engine, "customers"),
+        vec![
+            ValueType::Int,
+            ValueType::Text,
+            ValueType::Varchar(40),
+            ValueType::Date
+        ]
+    );
+    // A bare NUMERIC holds any number, so it becomes FLOAT; an undeclared
+    // type is TEXT.
+    assert_eq!(
+        types(&engine, "orders"),
+        vec![
+            ValueType::Int,
+            ValueType::Int,
+            ValueType::Float,
+            ValueType::Bool,
+            ValueType::Text
+        ]
+    );
+    assert_eq!(engine.tables["orders"].typing, Typing::Flexible);
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    assert_eq!(
+        run("SELECT name FROM customers WHERE joined = DATE '2024-01-02'").unwrap(),
+        vec![vec![Value::Text("Ann".into())]]
+    );
+    assert_eq!(
+        run("SELECT id, total FROM orders WHERE paid = TRUE ORDER BY id").unwrap(),
+        vec![
+            vec![Value::Int(10), Value::Float(12.5)],
+            vec![Value::Int(12), Value::Float(7.25)],
+        ]
+    );
+    // Keys and plain indexes come along; the index on lower(note) and the
+    // view do not.
+    assert!(matches!(
+        run("INSERT INTO customers VALUES (1, 'Cy', NULL, NULL)"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+    assert!(matches!(
+        run("INSERT INTO customers VALUES (3, 'Cy', 'ann@example.com', NULL)"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+    let indexes = engine.tables["orders"]
+        .indices
+        .keys()
+        .cloned()
+        .collect::<std::collections::BTreeSet<_>>();
+    assert!(indexes.contains("orders_by_customer"));
+    assert!(!indexes.iter().any(|name| name.contains("note")));
+    assert!(matches!(
+        engine.execute(parse_query("SELECT * FROM big_orders").unwrap().1),
+        Err(EngineError::TableNotFound(_))
+    ));
+
+    assert!(matches!(
+        engine.import_sqlite(path),
+        Err(EngineError::TableExists(_))
+    ));
+    assert!(matches!(
+        engine.import_sqlite("/nonexistent/shop.sqlite"),
+        Err(EngineError::Io(_))
+    ));
+}
+
+#[test]
+fn lsm_storage() {
+    use sql_core::{Column, LsmStorage, StorageEngine};
+
+    let dir = std::env::temp_dir().join(format!("minisql-lsm-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let columns = vec![
+        Column {
+            name: "id".into(),
+            col_type: ValueType::Int,
+            metadata: Default::default(),
+        },
+        Column {
+            name: "name".into(),
+            col_type: ValueType::Text,
+            metadata: Default::default(),
+        },
+    ];
+    let mut tree = LsmStorage::create(&dir, columns.clone()).unwrap();
+    assert!(matches!(
+        LsmStorage::create(&dir, columns),
+        Err(EngineError::Io(_))
+    ));
+    tree.set_memtable_rows(10);
+    let mut engine = Engine::new();
+    engine.attach("events", Box::new(tree)).unwrap();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    for id in 0..500 {
+        run(&format!("INSERT INTO events VALUES ({}, 'e{}')", id, id));
+    }
+    run("DELETE FROM events WHERE id >= 100");
+    run("UPDATE events SET name = 'first' WHERE id = 0");
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events"),
+        vec![vec![Value::Int(100)]]
+    );
+    assert_eq!(
+        run("SELECT name FROM events WHERE id = 0"),
+        vec![vec![Value::Text("first".into())]]
+    );
+    drop(engine.detach("events").unwrap());
+
+    // Flushed runs were merged down through the levels, and reopening
+    // reads every level back.
+    let mut tree = LsmStorage::open(&dir).unwrap();
+    let levels = tree.level_sizes();
+    assert!(levels.len() > 2);
+    assert!(levels[1..].iter().any(|&entries| entries > 100));
+    let mut rows = Vec::new();
+    tree.scan(&mut |id, row| {
+        rows.push((id, row));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(rows.len(), 100);
+    assert!(rows.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    let (first, _) = rows[0].clone();
+    assert!(tree.delete(first).unwrap());
+    assert!(!tree.delete(first).unwrap());
+    assert_eq!(tree.get(first).unwrap(), None);
+    let id = tree
+        .insert(vec![Value::Int(1000), Value::Text("late".into())])
+        .unwrap();
+    assert_eq!(
+        tree.get(id).unwrap(),
+        Some(vec![Value::Int(1000), Value::Text("late".into())])
+    );
+
+    // Vacuuming drops the tombstones and leaves a single run.
+    assert!(tree.vacuum().unwrap() > 0);
+    let levels = tree.level_sizes();
+    assert_eq!(levels.iter().sum::<usize>(), 100);
+    assert_eq!(levels.iter().filter(|&&entries| entries > 0).count(), 1);
+    drop(tree);
+    let runs = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension() == Some("run".as_ref()))
+        .count();
+    assert_eq!(runs, 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn memory_limit_spills_tables() {
+    let mut engine = Engine::new();
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    for table in ["a", "b", "c"] {
+        run(
+            &mut engine,
+            &format!("CREATE TABLE {} (id INT, body TEXT)", table),
+        );
+        run(
+            &mut engine,
+            &format!("CREATE INDEX {}_body ON {} (body)", table, table),
+        );
+        for id in 0..50 {
+            run(
+                &mut engine,
+                &format!(
+                    "INSERT INTO {} VALUES ({}, '{}{}')",
+                    table,
+                    id,
+                    table,
+                    "x".repeat(200)
+                ),
+            );
+        }
+    }
+    let full = engine.stats().unwrap().memory;
+    engine.set_memory_limit(Some(full / 2)).unwrap();
+    assert_eq!(engine.memory_limit(), Some(full / 2));
+    // The tables used longest ago, a then b, go first.
+    assert_eq!(engine.spilled_tables(), vec!["a", "b"]);
+    let stats = engine.stats().unwrap();
+    assert!(stats.memory <= full / 2);
+    assert!(stats.tables[0].spilled && stats.tables[0].row_memory == 0);
+    assert_eq!(stats.tables[0].rows, 50);
+
+    // A statement reads back what it uses, indexes included, and the
+    // table used longest ago makes room.
+    let body = format!("a{}", "x".repeat(200));
+    assert_eq!(
+        run(
+            &mut engine,
+            &format!("SELECT COUNT(*) FROM a WHERE body = '{}'", body)
+        ),
+        vec![vec![Value::Int(50)]]
+    );
+    assert_eq!(engine.spilled_tables(), vec!["b", "c"]);
+    run(&mut engine, "DELETE FROM b WHERE id >= 10");
+    assert_eq!(
+        run(&mut engine, "SELECT COUNT(*) FROM b"),
+        vec![vec![Value::Int(10)]]
+    );
+
+    // Dumps and saves hold the spilled rows.
+    assert!(!engine.spilled_tables().is_empty());
+    let mut dump = Vec::new();
+    engine.dump(&mut dump).unwrap();
+    let mut copy = Engine::new();
+    copy.restore_from_sql(&dump[..]).unwrap();
+    for (table, rows) in [("a", 50), ("b", 10), ("c", 50)] {
+        assert_eq!(copy.tables[table].row_count(), rows);
+    }
+
+    engine.set_memory_limit(None).unwrap();
+    assert!(engine.spilled_tables().is_empty());
+    assert_eq!(engine.tables["c"].row_count(), 50);
+}
+
+#[test]
+fn backup_while_writing() {
+    let dir = std::env::temp_dir().join(format!("minisql-hot-backup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let archive = dir.join("engine.bak");
+
+    let mut engine = Engine::new();
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run(&mut engine, "CREATE TABLE events (id INT, note TEXT)");
+    run(&mut engine, "CREATE INDEX by_id ON events (id)");
+    for id in 0..500 {
+        run(
+            &mut engine,
+            &format!("INSERT INTO events VALUES ({}, 'before')", id),
+        );
+    }
+    let job = engine.backup_to(&archive).unwrap();
+    assert_eq!(job.path(), archive);
+    // Statements go on while the backup is written, and it holds none of
+    // their changes.
+    run(&mut engine, "DELETE FROM events WHERE id < 100");
+    run(&mut engine, "INSERT INTO events VALUES (500, 'after')");
+    run(
+        &mut engine,
+        "UPDATE events SET note = 'after' WHERE id = 200",
+    );
+    job.wait().unwrap();
+
+    let mut restored = Engine::new();
+    restored.restore(&archive).unwrap();
+    assert_eq!(
+        run(
+            &mut restored,
+            "SELECT COUNT(*), MIN(id), MAX(id) FROM events"
+        ),
+        vec![vec![Value::Int(500), Value::Int(0), Value::Int(499)]]
+    );
+    assert_eq!(
+        run(&mut restored, "SELECT note FROM events WHERE id = 200"),
+        vec![vec![Value::Text("before".into())]]
+    );
+    assert_eq!(
+        run(&mut engine, "SELECT COUNT(*) FROM events"),
+        vec![vec![Value::Int(401)]]
+    );
+    assert_eq!(
+        run(&mut engine, "SELECT note FROM events WHERE id = 200"),
+        vec![vec![Value::Text("after".into())]]
+    );
+
+    let job = engine
+        .backup_to(dir.join("missing").join("engine.bak"))
+        .unwrap();
+    assert!(matches!(job.wait(), Err(EngineError::Io(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn checksums_catch_corruption() {
+    use sql_core::{Column, LsmStorage, PagedTable};
+
+    let dir = std::env::temp_dir().join(format!("minisql-checksums-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let logs = dir.join("logs");
+    let log = logs.join("items.log");
+    let flip = |path: &std::path::Path, at: usize| {
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[at] ^= 1;
+        std::fs::write(path, &bytes).unwrap();
+    };
+    let corrupt_at = |result: Result<(), EngineError>, file: &str| match result {
+        Err(EngineError::Corruption { location }) => {
+            assert!(location.contains(file), "{}", location)
+        }
+        other => panic!("expected corruption in {}, got {:?}", file, other),
+    };
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+
+    let mut engine = Engine::new();
+    engine.enable_log(&logs).unwrap();
+    run(&mut engine, "CREATE TABLE items (id INT, name TEXT)");
+    for id in 0..5 {
+        run(
+            &mut engine,
+            &format!("INSERT INTO items VALUES ({}, 'n{}')", id, id),
+        );
+    }
+    engine.verify().unwrap();
+
+    // A record cut short at the end is dropped and cut off the log.
+    let mut bytes = std::fs::read(&log).unwrap();
+    bytes.extend_from_slice(b"B\x40\0\0\0half");
+    std::fs::write(&log, &bytes).unwrap();
+    let mut opened = Engine::open_log(&logs).unwrap();
+    run(&mut opened, "INSERT INTO items VALUES (5, 'n5')");
+    drop(opened);
+    let mut opened = Engine::open_log(&logs).unwrap();
+    assert_eq!(
+        run(&mut opened, "SELECT COUNT(*) FROM items"),
+        vec![vec![Value::Int(6)]]
+    );
+    opened.verify().unwrap();
+
+    // A damaged record followed by others is not.
+    flip(&log, 30);
+    corrupt_at(opened.verify(), "items.log");
+    corrupt_at(Engine::open_log(&logs).map(drop), "items.log");
+
+    // Pages of a paged table, checked as they are read.
+    let columns = vec![Column {
+        name: "name".into(),
+        col_type: ValueType::Text,
+        metadata: Default::default(),
+    }];
+    let path = dir.join("notes.pages");
+    let mut table = PagedTable::create(&path, columns.clone(), 4).unwrap();
+    let id = table.insert(vec![Value::Text("hello".into())]).unwrap();
+    table.verify().unwrap();
+    drop(table);
+    flip(&path, sql_core::PAGE_SIZE + 20);
+    let mut table = PagedTable::open(&path, 4).unwrap();
+    corrupt_at(table.get(id).map(drop), "notes.pages: page 1");
+    drop(table);
+
+    // Entries of an LSM run, and the storage attached to an engine.
+    let tree_dir = dir.join("events");
+    let mut tree = LsmStorage::create(&tree_dir, columns).unwrap();
+    let id = tree.insert(vec![Value::Text("hello".into())]).unwrap();
+    tree.flush().unwrap();
+    let mut engine = Engine::new();
+    engine.attach("events", Box::new(tree)).unwrap();
+    engine.verify().unwrap();
+    drop(engine.detach("events").unwrap());
+    flip(&tree_dir.join("0.run"), 25);
+    let tree = LsmStorage::open(&tree_dir).unwrap();
+    corrupt_at(tree.get(id).map(drop), "0.run: entry at byte 20");
+    engine.attach("events", Box::new(tree)).unwrap();
+    corrupt_at(engine.verify(), "0.run");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn out_of_line_texts() {
+    for layout in ["row", "column"] {
+        out_of_line_texts_in(layout);
+    }
+}
+
+fn out_of_line_texts_in(layout: &str) {
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run(&format!(
+        "CREATE TABLE docs (id INT, title TEXT, body TEXT) WITH (layout = '{}', overflow = '64')",
+        layout
+    ));
+    let long = |n: usize| format!("{}-{}", n % 2, "x".repeat(1000));
+    for id in 0..20 {
+        run(&format!(
+            "INSERT INTO docs VALUES ({}, 't{}', '{}')",
+            id,
+            id,
+            long(id)
+        ));
+    }
+    run("INSERT INTO docs VALUES (20, 't20', 'short')");
+    assert_eq!(
+        run("SELECT title, body FROM docs WHERE id = 3"),
+        vec![vec![Value::Text("t3".into()), Value::Text(long(3))]]
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM docs WHERE body = 'short' OR body LIKE '1-%'"),
+        vec![vec![Value::Int(11)]]
+    );
+    run(&format!(
+        "UPDATE docs SET body = '{}' WHERE id = 20",
+        long(0)
+    ));
+    assert_eq!(
+        run("SELECT body FROM docs WHERE id = 20"),
+        vec![vec![Value::Text(long(0))]]
+    );
+
+    // The twenty-one long texts are two distinct ones, each held once.
+    let inline = {
+        let mut engine = Engine::new();
+        let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+        run(&format!(
+            "CREATE TABLE docs (id INT, title TEXT, body TEXT) WITH (layout = '{}')",
+            layout
+        ));
+        for id in 0..21 {
+            run(&format!(
+                "INSERT INTO docs VALUES ({}, 't', '{}')",
+                id,
+                long(id)
+            ));
+        }
+        engine.tables["docs"].row_memory()
+    };
+    assert!(engine.tables["docs"].row_memory() * 4 < inline);
+
+    // An index over the column keeps only the start of each key.
+    engine
+        .execute(
+            parse_query("CREATE INDEX docs_body ON docs (body)")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+    let stats = engine.index_stats("docs").unwrap();
+    assert_eq!(stats[0].columns, vec!["body(64)".to_string()]);
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run(&format!(
+            "SELECT COUNT(*) FROM docs WHERE body = '{}'",
+            long(1)
+        )),
+        vec![vec![Value::Int(10)]]
+    );
+
+    let mut dump = Vec::new();
+    engine.dump(&mut dump).unwrap();
+    assert!(String::from_utf8_lossy(&dump).contains("overflow = '64'"));
+    let mut copy = Engine::new();
+    copy.restore_from_sql(&dump[..]).unwrap();
+    assert_eq!(copy.tables["docs"].overflow, Some(64));
+    assert_eq!(
+        copy.execute(parse_query("SELECT body FROM docs WHERE id = 7").unwrap().1)
+            .unwrap(),
+        vec![vec![Value::Text(long(7))]]
+    );
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("DELETE FROM docs WHERE id < 5");
+    assert_eq!(
+        run("SELECT id, body FROM docs WHERE id IN (5, 20)"),
+        vec![
+            vec![Value::Int(5), Value::Text(long(5))],
+            vec![Value::Int(20), Value::Text(long(0))],
+        ]
+    );
+    let file = std::env::temp_dir().join(format!(
+        "minisql-overflow-{}-{}.db",
+        layout,
+        std::process::id()
+    ));
+    engine.save(&file).unwrap();
+    let saved = Engine::open(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    assert_eq!(
+        saved.tables["docs"].all_rows(),
+        engine.tables["docs"].all_rows()
+    );
+
+    engine.set_table_overflow("docs", None).unwrap();
+    assert_eq!(engine.tables["docs"].overflow, None);
+    assert!(engine.tables["docs"].row_memory() > inline / 2);
+}
+
+#[test]
+fn bulk_load() {
+    use std::io::Write;
+
+    let mut engine = Engine::new();
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    run("CREATE TABLE events (id INT, kind TEXT, score FLOAT)");
+    run("CREATE UNIQUE INDEX events_id ON events (id)");
+    run("CREATE INDEX events_kind ON events (kind)");
+    run("INSERT INTO events VALUES (0, 'seed', 1.5)");
+
+    let mut load = engine.copy_in("events").unwrap();
+    for id in 1..=10_000 {
+        load.write_row(vec![
+            Value::Int(id),
+            Value::Text(["click", "view"][id as usize % 2].into()),
+            Value::Int(id % 7),
+        ])
+        .unwrap();
+    }
+    assert!(matches!(
+        load.write_row(vec![Value::Int(1)]),
+        Err(EngineError::ValueCountMismatch)
+    ));
+    // CSV may arrive in pieces that cut records, and quoted fields, apart.
+    for piece in [
+        "id,kind,score\n10001,\"li",
+        "ne\nbreak\",2\r\n10002,",
+        "view,3",
+    ] {
+        load.write_all(piece.as_bytes()).unwrap();
+    }
+    assert_eq!(load.finish().unwrap(), 10_002);
+
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events WHERE kind = 'view'"),
+        vec![vec![Value::Int(5_001)]]
+    );
+    assert_eq!(
+        run("SELECT kind, score FROM events WHERE id = 10001"),
+        vec![vec![Value::Text("line\nbreak".into()), Value::Float(2.0)]]
+    );
+
+    // A duplicate key is only found at the end, and takes the whole load
+    // back, leaving the indexes as they were.
+    let mut load = engine.copy_in("events").unwrap();
+    load.write_row(vec![
+        Value::Int(20_000),
+        Value::Text("x".into()),
+        Value::Null,
+    ])
+    .unwrap();
+    load.write_row(vec![Value::Int(5), Value::Text("x".into()), Value::Null])
+        .unwrap();
+    assert!(matches!(
+        load.finish(),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+    let mut run = |sql: &str| engine.execute(parse_query(sql).unwrap().1).unwrap();
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events"),
+        vec![vec![Value::Int(10_003)]]
+    );
+    assert_eq!(
+        run("SELECT kind FROM events WHERE id = 20000"),
+        Vec::<Vec<Value>>::new()
+    );
+    assert_eq!(
+        run("SELECT COUNT(*) FROM events WHERE kind = 'x'"),
+        vec![vec![Value::Int(0)]]
+    );
+
+    // Dropping a load takes its rows back too, and a bad CSV record fails
+    // it.
+    let mut load = engine.copy_in("events").unwrap();
+    load.write_row(vec![Value::Int(30_000), Value::Null, Value::Null])
+        .unwrap();
+    drop(load);
+    let mut load = engine.copy_in("events").unwrap();
+    assert!(load.write_all(b"id,kind\n1,a,b\n").is_err());
+    assert!(matches!(
+        load.finish(),
+        Err(EngineError::InvalidInput { line: 2, .. })
+    ));
+    assert_eq!(engine.tables["events"].row_count(), 10_003);
+    assert!(matches!(
+        engine.copy_in("missing"),
+        Err(EngineError::TableNotFound(_))
+    ));
+}
+
+#[test]
+fn transactions() {
+    let dir = std::env::temp_dir().join(format!("minisql-tx-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut engine = Engine::new();
+    engine.enable_log(&dir).unwrap();
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run(&mut engine, "CREATE TABLE accounts (id INT, balance INT)").unwrap();
+    run(
+        &mut engine,
+        "CREATE UNIQUE INDEX accounts_id ON accounts (id)",
+    )
+    .unwrap();
+    run(&mut engine, "INSERT INTO accounts VALUES (1, 100)").unwrap();
+    run(&mut engine, "INSERT INTO accounts VALUES (2, 50)").unwrap();
+    let balances =
+        |engine: &mut Engine| run(engine, "SELECT balance FROM accounts ORDER BY id").unwrap();
+
+    run(&mut engine, "BEGIN").unwrap();
+    assert!(engine.in_transaction());
+    run(
+        &mut engine,
+        "UPDATE accounts SET balance = balance - 30 WHERE id = 1",
+    )
+    .unwrap();
+    run(&mut engine, "INSERT INTO accounts VALUES (3, 30)").unwrap();
+    run(&mut engine, "DELETE FROM accounts WHERE id = 2").unwrap();
+    assert!(matches!(
+        run(&mut engine, "CREATE TABLE other (id INT)"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        run(&mut engine, "BEGIN"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    run(&mut engine, "ROLLBACK").unwrap();
+    assert!(!engine.in_transaction());
+    assert_eq!(
+        balances(&mut engine),
+        vec![vec![Value::Int(100)], vec![Value::Int(50)]]
+    );
+    // The index is back as it was: id 3 is free and id 2 taken.
+    run(&mut engine, "INSERT INTO accounts VALUES (3, 0)").unwrap();
+    assert!(matches!(
+        run(&mut engine, "INSERT INTO accounts VALUES (2, 0)"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+    run(&mut engine, "DELETE FROM accounts WHERE id = 3").unwrap();
+
+    // Committed changes reach the logs; those rolled back never do.
+    run(&mut engine, "BEGIN TRANSACTION").unwrap();
+    run(&mut engine, "UPDATE accounts SET balance = 0 WHERE id = 2").unwrap();
+    run(&mut engine, "COMMIT").unwrap();
+    engine.begin().unwrap();
+    run(&mut engine, "UPDATE accounts SET balance = 1").unwrap();
+    drop(engine);
+    let mut engine = Engine::open_log(&dir).unwrap();
+    assert_eq!(
+        balances(&mut engine),
+        vec![vec![Value::Int(100)], vec![Value::Int(0)]]
+    );
+
+    let failed = engine.transaction(|engine| {
+        run(engine, "UPDATE accounts SET balance = 7 WHERE id = 1")?;
+        run(engine, "INSERT INTO accounts VALUES (1, 7)")
+    });
+    assert!(matches!(failed, Err(EngineError::UniqueViolation { .. })));
+    assert_eq!(
+        balances(&mut engine),
+        vec![vec![Value::Int(100)], vec![Value::Int(0)]]
+    );
+    assert!(matches!(
+        run(&mut engine, "COMMIT"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    drop(engine);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn savepoints() {
+    let mut engine = Engine::new();
+    let run = |engine: &mut Engine, sql: &str| engine.execute(parse_query(sql).unwrap().1);
+    run(&mut engine, "CREATE TABLE jobs (id INT, state TEXT)").unwrap();
+    run(&mut engine, "CREATE TABLE runs (job INT)").unwrap();
+    let count = |engine: &mut Engine, table: &str| {
+        run(engine, &format!("SELECT COUNT(*) FROM {}", table)).unwrap()[0][0].clone()
+    };
+
+    assert!(matches!(
+        run(&mut engine, "SAVEPOINT early"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    run(&mut engine, "BEGIN").unwrap();
+    run(&mut engine, "INSERT INTO jobs VALUES (1, 'new')").unwrap();
+    run(&mut engine, "SAVEPOINT first").unwrap();
+    run(&mut engine, "INSERT INTO jobs VALUES (2, 'new')").unwrap();
+    run(&mut engine, "SAVEPOINT second").unwrap();
+    run(&mut engine, "INSERT INTO runs VALUES (2)").unwrap();
+    run(&mut engine, "UPDATE jobs SET state = 'done'").unwrap();
+
+    run(&mut engine, "ROLLBACK TO SAVEPOINT second").unwrap();
+    assert_eq!(count(&mut engine, "runs"), Value::Int(0));
+    assert_eq!(
+        run(&mut engine, "SELECT state FROM jobs ORDER BY id").unwrap(),
+        vec![
+            vec![Value::Text("new".into())],
+            vec![Value::Text("new".into())]
+        ]
+    );
+    // The savepoint stays, so a failed batch can be tried again.
+    run(&mut engine, "INSERT INTO runs VALUES (2)").unwrap();
+    run(&mut engine, "ROLLBACK TO second").unwrap();
+    assert_eq!(count(&mut engine, "runs"), Value::Int(0));
+
+    run(&mut engine, "ROLLBACK TO first").unwrap();
+    assert_eq!(count(&mut engine, "jobs"), Value::Int(1));
+    assert!(matches!(
+        run(&mut engine, "ROLLBACK TO second"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+
+    // Releasing keeps the changes, which the transaction still rolls back.
+    run(&mut engine, "INSERT INTO jobs VALUES (3, 'new')").unwrap();
+    run(&mut engine, "RELEASE SAVEPOINT first").unwrap();
+    assert!(matches!(
+        run(&mut engine, "ROLLBACK TO first"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert_eq!(count(&mut engine, "jobs"), Value::Int(2));
+    run(&mut engine, "ROLLBACK").unwrap();
+    assert_eq!(count(&mut engine, "jobs"), Value::Int(0));
+    assert!(!engine.in_transaction());
+
+    engine.begin().unwrap();
+    engine.savepoint("a").unwrap();
+    run(&mut engine, "INSERT INTO jobs VALUES (4, 'new')").unwrap();
+    engine.release("a").unwrap();
+    engine.commit().unwrap();
+    assert_eq!(count(&mut engine, "jobs"), Value::Int(1));
+}
+
+#[test]
+fn shared_engine() {
+    use sql_core::SharedEngine;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Engine>();
+
+    let shared = SharedEngine::new(Engine::new());
+    shared
+        .execute_sql("CREATE TABLE items (id INT, name TEXT)")
+        .unwrap();
+    shared
+        .execute_sql("CREATE UNIQUE INDEX item_id ON items (id)")
+        .unwrap();
+    shared
+        .execute_sql("CREATE TABLE tags (id INT, tag TEXT)")
+        .unwrap();
+    for id in 0..50 {
+        shared
+            .execute_sql(&format!("INSERT INTO items VALUES ({}, 'item{}')", id, id))
+            .unwrap();
+        shared
+            .execute_sql(&format!(
+                "INSERT INTO tags VALUES ({}, 'tag{}')",
+                id,
+                id % 5
+            ))
+            .unwrap();
+    }
+
+    // Readers run side by side, over the same table and others.
+    std::thread::scope(|s| {
+        let readers = (0..8)
+            .map(|n| {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    let table = if n % 2 == 0 { "items" } else { "tags" };
+                    shared
+                        .execute_sql(&format!("SELECT COUNT(*) FROM {} WHERE id < 10", table))
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), vec![vec![Value::Int(10)]]);
+        }
+    });
+
+    // Writers take turns with the readers.
+    std::thread::scope(|s| {
+        for n in 0..4 {
+            let shared = shared.clone();
+            s.spawn(move || {
+                shared
+                    .execute_sql(&format!("INSERT INTO items VALUES ({}, 'new')", 100 + n))
+                    .unwrap();
+                shared.execute_sql("SELECT * FROM items").unwrap();
+            });
+        }
+    });
+    assert_eq!(
+        shared.execute_sql("SELECT COUNT(*) FROM items").unwrap(),
+        vec![vec![Value::Int(54)]]
+    );
+    assert!(matches!(
+        shared.execute_sql("INSERT INTO items VALUES (1, 'again')"),
+        Err(EngineError::UniqueViolation { .. })
+    ));
+
+    // Transactions hold the engine from start to end; BEGIN cannot leave
+    // one open for other threads to write into.
+    assert!(matches!(
+        shared.execute_sql("BEGIN"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        shared.execute_sql("SAVEPOINT a"),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    let failed = shared.transaction(|engine| {
+        engine.execute(parse_query("DELETE FROM items").unwrap().1)?;
+        engine.execute(parse_query("INSERT INTO items VALUES (1, 'x')").unwrap().1)?;
+        engine.execute(parse_query("INSERT INTO items VALUES (1, 'y')").unwrap().1)
+    });
+    assert!(matches!(failed, Err(EngineError::UniqueViolation { .. })));
+    assert!(matches!(
+        shared.write(|engine| engine.begin()),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    assert!(!shared.read(|engine| engine.in_transaction()).unwrap());
+    assert_eq!(
+        shared.execute_sql("SELECT COUNT(*) FROM items").unwrap(),
+        vec![vec![Value::Int(54)]]
+    );
+
+    // The engine comes back out once no other handle holds it.
+    let other = shared.clone();
+    assert!(matches!(
+        other.clone().into_inner(),
+        Err(EngineError::InvalidOperation(_))
+    ));
+    let engine = shared.into_inner();
+    assert!(engine.is_err());
+    let engine = other.into_inner().unwrap();
+    assert_eq!(engine.tables["items"].row_count(), 54);
+
+    // A panic while writing may leave the engine half changed, so it is
+    // not used again.
+    let shared = SharedEngine::new(engine);
+    let panicking = shared.clone();
+    std::thread::spawn(move || {
+        let _ = panicking.write(|_| -> Result<(), EngineError> { panic!("half way") });
+    })
+    .join()
+    .unwrap_err();
+    assert_eq!(
+        shared.execute_sql("SELECT COUNT(*) FROM items"),
+        Err(EngineError::Poisoned)
+    );
+    assert_eq!(shared.read(|_| ()), Err(EngineError::Poisoned));
+}
+
+#[test]
+fn shared_engine_locks_tables() {
+    use sql_core::SharedEngine;
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    // WAIT(x) returns x once the test lets it, holding up the statement
+    // calling it with the locks it took.
+    let (started_tx, started) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel::<()>();
+    let started_tx = Mutex::new(started_tx);
+    let release_rx = Mutex::new(release_rx);
+    let mut engine = Engine::new();
+    engine
+        .register_function("WAIT", 1, move |args| {
+            started_tx.lock().unwrap().send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+            Ok(args[0].clone())
+        })
+        .unwrap();
+    let shared = SharedEngine::new(engine);
+    shared.execute_sql("CREATE TABLE a (id INT)").unwrap();
+    shared.execute_sql("CREATE TABLE b (id INT)").unwrap();
+    shared.execute_sql("INSERT INTO a VALUES (1)").unwrap();
+    shared.execute_sql("INSERT INTO b VALUES (2)").unwrap();
+
+    // While a writer holds table a, a reader of b finishes; a reader of a
+    // waits for the writer and sees its change.
+    let writer = {
+        let shared = shared.clone();
+        std::thread::spawn(move || shared.execute_sql("UPDATE a SET id = WAIT(id) + 10"))
+    };
+    started.recv().unwrap();
+    let (done_tx, done) = mpsc::channel();
+    {
+        let shared = shared.clone();
+        std::thread::spawn(move || done_tx.send(shared.execute_sql("SELECT id FROM b")));
+    }
+    assert_eq!(
+        done.recv_timeout(Duration::from_secs(10)).unwrap(),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+    let writer_b = {
+        let shared = shared.clone();
+        std::thread::spawn(move || shared.execute_sql("INSERT INTO b VALUES (3)"))
+    };
+    assert_eq!(writer_b.join().unwrap(), Ok(Vec::new()));
+    let reader_a = {
+        let shared = shared.clone();
+        std::thread::spawn(move || shared.execute_sql("SELECT id FROM a"))
+    };
+    release.send(()).unwrap();
+    assert_eq!(writer.join().unwrap(), Ok(Vec::new()));
+    assert_eq!(reader_a.join().unwrap(), Ok(vec![vec![Value::Int(11)]]));
+
+    // Readers of one table share its lock: one held up in WAIT does not
+    // hold up another.
+    let reader = {
+        let shared = shared.clone();
+        std::thread::spawn(move || shared.execute_sql("SELECT WAIT(id) FROM a"))
+    };
+    started.recv().unwrap();
+    assert_eq!(
+        shared.execute_sql("SELECT COUNT(*) FROM a").unwrap(),
+        vec![vec![Value::Int(1)]]
+    );
+    release.send(()).unwrap();
+    assert_eq!(reader.join().unwrap(), Ok(vec![vec![Value::Int(11)]]));
+
+    let engine = shared.into_inner().unwrap();
+    assert_eq!(engine.tables["b"].row_count(), 2);
+}